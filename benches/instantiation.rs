@@ -0,0 +1,102 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use wasmer::*;
+use wasmer_engine_jit::JIT;
+
+static INSTANTIATION_WAT: &str = r#"(module
+    (memory (export "memory") 17)
+    (table 10 funcref)
+    (global $counter (mut i32) (i32.const 0))
+    (func $increment (export "increment") (result i32)
+       (global.set $counter (i32.add (global.get $counter) (i32.const 1)))
+       (i32.store (i32.const 0) (global.get $counter))
+       (global.get $counter))
+    (elem (i32.const 0) $increment)
+    (data (i32.const 16) "Hello, World!")
+)"#;
+
+pub fn run_instantiation(store: &Store, name: &str, c: &mut Criterion) {
+    let module = Module::new(store, INSTANTIATION_WAT).unwrap();
+    let import_object = imports! {};
+
+    c.bench_function(&format!("instantiate and call {}", name), |b| {
+        b.iter(|| {
+            let instance = Instance::new(&module, &import_object).unwrap();
+            let increment: NativeFunc<(), i32> =
+                instance.exports.get_native_function("increment").unwrap();
+            assert_eq!(black_box(increment.call().unwrap()), 1);
+        })
+    });
+}
+
+/// Instantiates a module with 4MiB of data segments, mapped from the memory
+/// image of the module rather than copied.
+pub fn run_large_data_instantiation(store: &Store, name: &str, c: &mut Criterion) {
+    let segment = "a".repeat(0x1_0000);
+    let data = (0..64)
+        .map(|i| format!("(data (i32.const {}) \"{}\")", i * 0x1_0000, segment))
+        .collect::<String>();
+    let wat = format!(
+        r#"(module
+            (memory (export "memory") 65)
+            (func (export "load") (result i32) (i32.load (i32.const 0)))
+            {})"#,
+        data
+    );
+    let module = Module::new(store, wat).unwrap();
+    let import_object = imports! {};
+
+    c.bench_function(&format!("instantiate large data {}", name), |b| {
+        b.iter(|| {
+            let instance = Instance::new(&module, &import_object).unwrap();
+            let load: NativeFunc<(), i32> = instance.exports.get_native_function("load").unwrap();
+            assert_eq!(black_box(load.call().unwrap()), 0x6161_6161);
+        })
+    });
+}
+
+/// Compares the default allocation of the instances with the pooling one.
+pub fn run_default_and_pooling(
+    engine: &impl wasmer_engine::Engine,
+    compiler_name: &str,
+    c: &mut Criterion,
+) {
+    let store = Store::new(engine);
+    run_instantiation(&store, &format!("{} default", compiler_name), c);
+    run_large_data_instantiation(&store, &format!("{} default", compiler_name), c);
+
+    #[cfg(unix)]
+    {
+        let tunables = PoolingTunables::new(PoolingLimits::default()).unwrap();
+        let store = Store::new_with_tunables(engine, tunables);
+        run_instantiation(&store, &format!("{} pooling", compiler_name), c);
+        run_large_data_instantiation(&store, &format!("{} pooling", compiler_name), c);
+    }
+}
+
+fn run_instantiation_benchmarks(c: &mut Criterion) {
+    #[cfg(feature = "llvm")]
+    run_default_and_pooling(
+        &JIT::new(&wasmer_compiler_llvm::LLVM::new()).engine(),
+        "llvm",
+        c,
+    );
+
+    #[cfg(feature = "cranelift")]
+    run_default_and_pooling(
+        &JIT::new(&wasmer_compiler_cranelift::Cranelift::new()).engine(),
+        "cranelift",
+        c,
+    );
+
+    #[cfg(feature = "singlepass")]
+    run_default_and_pooling(
+        &JIT::new(&wasmer_compiler_singlepass::Singlepass::new()).engine(),
+        "singlepass",
+        c,
+    );
+}
+
+criterion_group!(benches, run_instantiation_benchmarks);
+
+criterion_main!(benches);
@@ -49,11 +49,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Then, we get the import object related to our WASI
     // and attach it to the Wasm instance.
     let import_object = wasi_env.import_object(&module)?;
+    // The `WasiEnv` gets the instance memory when it is created.
     let instance = Instance::new(&module, &import_object)?;
 
-    // WASI requires to explicitly set the memory for the `WasiEnv`
-    wasi_env.set_memory(instance.exports.get_memory("memory")?.clone());
-
     println!("Call WASI `_start` function...");
     // And we just call the `_start` function!
     let start = instance.exports.get_function("_start")?;
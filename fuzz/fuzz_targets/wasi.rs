@@ -0,0 +1,166 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+#[macro_use]
+extern crate lazy_static;
+
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use std::fmt::Write;
+use wasmer::{Export, Instance, Memory, Module, Store, Type, Val};
+use wasmer_compiler_cranelift::Cranelift;
+use wasmer_engine_jit::JIT;
+use wasmer_wasi::types::*;
+use wasmer_wasi::{
+    generate_import_object_from_env, Pipe, WasiEnv, WasiState, WasiVersion, ALL_RIGHTS,
+};
+
+/// The syscalls that are not called: `proc_exit` only stops the instance.
+const SKIPPED_SYSCALLS: &[&str] = &["proc_exit"];
+
+/// The fd of the preopened directory, after the virtual root.
+const SANDBOX_FD: __wasi_fd_t = 4;
+
+const SUBSCRIPTION_SIZE: u64 = 48;
+/// The maximum number of subscriptions of `poll_oneoff`, with the clock
+/// subscription bounding the call.
+const MAX_SUBSCRIPTIONS: u64 = 8;
+
+/// A call to a syscall, picked by its index, with up to 9 arguments.
+#[derive(Arbitrary, Debug)]
+struct Call {
+    syscall: u8,
+    args: [u64; 9],
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    /// The initial contents of the memory, where the arguments point.
+    memory: Vec<u8>,
+    calls: Vec<Call>,
+}
+
+/// Creates a WASI environment with a preopened directory and the standard
+/// streams, all in memory, so the host file system is never touched.
+fn wasi_env() -> WasiEnv {
+    let mut state = WasiState::new("fuzz")
+        .args(&["arg"])
+        .env("KEY", "VALUE")
+        .stdin(Box::new(Pipe::new()))
+        .stdout(Box::new(Pipe::new()))
+        .stderr(Box::new(Pipe::new()))
+        .preopen(|p| {
+            p.in_memory(true)
+                .alias("sandbox")
+                .read(true)
+                .write(true)
+                .create(true)
+        })
+        .unwrap()
+        .deterministic(0)
+        .build()
+        .unwrap();
+    let fs = &mut state.fs;
+    fs.create_directory(SANDBOX_FD, "dir").unwrap();
+    let fd = fs
+        .open_path(
+            SANDBOX_FD,
+            0,
+            "file.txt",
+            __WASI_O_CREAT,
+            ALL_RIGHTS,
+            ALL_RIGHTS,
+            0,
+        )
+        .unwrap();
+    fs.write_fd(fd, b"hello").unwrap();
+    fs.close_fd(fd).unwrap();
+    WasiEnv::new(state)
+}
+
+/// Appends a relative clock subscription to the subscriptions of a call to
+/// `poll_oneoff`, so the call returns after at most a second of the
+/// deterministic clock, whatever the other subscriptions are.
+fn bound_poll_oneoff(memory: &Memory, args: &mut [u64; 9]) {
+    let nsubscriptions = args[2] % MAX_SUBSCRIPTIONS + 1;
+    let len = nsubscriptions * SUBSCRIPTION_SIZE;
+    let in_ = (args[0] % (memory.size().bytes().0 as u64 - len)) & !7;
+    let mut clock = [0; SUBSCRIPTION_SIZE as usize];
+    clock[8] = __WASI_EVENTTYPE_CLOCK;
+    clock[16..20].copy_from_slice(&__WASI_CLOCK_MONOTONIC.to_le_bytes());
+    // the arguments after the 4 ones of `poll_oneoff` are free
+    clock[24..32].copy_from_slice(&(args[4] % 1_000_000_000).to_le_bytes());
+    memory.write(in_ + len - SUBSCRIPTION_SIZE, &clock).unwrap();
+    args[0] = in_;
+    args[2] = nsubscriptions;
+}
+
+lazy_static! {
+    static ref STORE: Store = Store::new(&JIT::new(&Cranelift::default()).engine());
+    /// A module exporting its memory and re-exporting all the syscalls, so
+    /// they can be called directly.
+    static ref MODULE: Module = {
+        let import_object =
+            generate_import_object_from_env(&STORE, wasi_env(), WasiVersion::Latest);
+        let mut wat = String::from("(module\n");
+        for ((namespace, name), export) in import_object {
+            if let Export::Function(function) = export {
+                let ty = function.signature;
+                let types = |kind: &str, types: &[Type]| {
+                    types
+                        .iter()
+                        .map(|ty| format!(" ({} {})", kind, ty.to_string().to_lowercase()))
+                        .collect::<String>()
+                };
+                writeln!(
+                    wat,
+                    "  (import \"{0}\" \"{1}\" (func ${1}{2}{3}))\n  (export \"{1}\" (func ${1}))",
+                    namespace,
+                    name,
+                    types("param", ty.params()),
+                    types("result", ty.results()),
+                )
+                .unwrap();
+            }
+        }
+        wat.push_str("  (memory (export \"memory\") 1))");
+        Module::new(&STORE, wat).unwrap()
+    };
+}
+
+fuzz_target!(|input: Input| {
+    let mut wasi_env = wasi_env();
+    let import_object = wasi_env.import_object(&MODULE).unwrap();
+    let instance = Instance::new(&MODULE, &import_object).unwrap();
+
+    let memory = instance.exports.get_memory("memory").unwrap();
+    let len = std::cmp::min(input.memory.len(), memory.size().bytes().0);
+    memory.write(0, &input.memory[..len]).unwrap();
+
+    // sorted, so the syscalls keep their indices between runs
+    let mut syscalls = instance
+        .exports
+        .iter()
+        .functions()
+        .filter(|(name, _)| !SKIPPED_SYSCALLS.contains(&name.as_str()))
+        .collect::<Vec<_>>();
+    syscalls.sort_by_key(|(name, _)| *name);
+    for mut call in input.calls {
+        let (name, syscall) = syscalls[call.syscall as usize % syscalls.len()];
+        if name == "poll_oneoff" {
+            bound_poll_oneoff(memory, &mut call.args);
+        }
+        let params = syscall
+            .ty()
+            .params()
+            .iter()
+            .zip(call.args.iter())
+            .map(|(ty, arg)| match ty {
+                Type::I64 => Val::I64(*arg as i64),
+                _ => Val::I32(*arg as i32),
+            })
+            .collect::<Vec<_>>();
+        // the syscalls return an error code, or trap with a `WasiError`,
+        // but they must never panic
+        let _ = syscall.call(&params);
+    }
+});
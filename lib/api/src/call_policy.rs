@@ -0,0 +1,295 @@
+//! The `CallPolicy` accounts for the calls of the functions of an
+//! [`ImportObject`], with a cost per call and quotas per import.
+
+use crate::exports::Exports;
+use crate::externals::{Extern, Function};
+use crate::import_object::ImportObject;
+use crate::instance::Instance;
+use crate::store::Store;
+use crate::types::Val;
+use crate::{RuntimeError, WasmerEnv};
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use wasmer_vm::{Export, HostEnvInitError, ImportInitializerFuncPtr, Trap, VMContext};
+
+/// The error trapping a call denied by a [`CallPolicy`].
+///
+/// It can be retrieved from the [`RuntimeError`] of the call with
+/// [`RuntimeError::downcast`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CallPolicyError {
+    /// The quota of calls to an import is exhausted.
+    #[error("the quota of {quota} calls to {module}.{name} is exhausted")]
+    QuotaExhausted {
+        /// The module name of the import.
+        module: String,
+        /// The field name of the import.
+        name: String,
+        /// The number of calls allowed.
+        quota: u64,
+    },
+    /// The fuel remaining is lower than the cost of a call.
+    #[error("calling {module}.{name} costs {cost} fuel, but only {remaining} remains")]
+    OutOfFuel {
+        /// The module name of the import.
+        module: String,
+        /// The field name of the import.
+        name: String,
+        /// The cost of the call.
+        cost: u64,
+        /// The fuel remaining.
+        remaining: u64,
+    },
+}
+
+/// The costs and quotas of the calls to the functions of an
+/// [`ImportObject`], applied with [`ImportObject::with_call_policy`].
+///
+/// Every call consumes the cost of the function from a fuel budget, and
+/// counts towards the quota of the function. A call exceeding either traps
+/// with a [`CallPolicyError`].
+///
+/// # Usage:
+/// ```
+/// # use wasmer::{imports, CallPolicy, Function, Instance, Module, Store};
+/// # fn main() -> anyhow::Result<()> {
+/// let store = Store::default();
+/// let module = Module::new(&store, r#"(module (import "env" "log" (func)))"#)?;
+/// let imports = imports! {
+///     "env" => {
+///         "log" => Function::new_native(&store, || {}),
+///     },
+/// };
+///
+/// let mut policy = CallPolicy::new();
+/// policy.fuel(1000).cost("env", "log", 10).quota("env", "log", 50);
+/// let (imports, counters) = imports.with_call_policy(&store, &policy);
+/// let instance = Instance::new(&module, &imports)?;
+/// assert_eq!(counters.calls("env", "log"), 0);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CallPolicy {
+    fuel: Option<u64>,
+    default_cost: u64,
+    costs: HashMap<(String, String), u64>,
+    quotas: HashMap<(String, String), u64>,
+}
+
+impl CallPolicy {
+    /// Creates a `CallPolicy` without fuel limit or quotas, where every
+    /// call costs 1.
+    pub fn new() -> Self {
+        Self {
+            fuel: None,
+            default_cost: 1,
+            costs: HashMap::new(),
+            quotas: HashMap::new(),
+        }
+    }
+
+    /// Limits the fuel consumed by the calls.
+    pub fn fuel(&mut self, fuel: u64) -> &mut Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    /// Sets the cost of the calls to the functions without a cost of their
+    /// own.
+    pub fn default_cost(&mut self, cost: u64) -> &mut Self {
+        self.default_cost = cost;
+        self
+    }
+
+    /// Sets the cost of the calls to `module`.`name`.
+    pub fn cost(&mut self, module: &str, name: &str, cost: u64) -> &mut Self {
+        self.costs
+            .insert((module.to_string(), name.to_string()), cost);
+        self
+    }
+
+    /// Limits the number of calls to `module`.`name`.
+    pub fn quota(&mut self, module: &str, name: &str, calls: u64) -> &mut Self {
+        self.quotas
+            .insert((module.to_string(), name.to_string()), calls);
+        self
+    }
+}
+
+impl Default for CallPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    fuel_consumed: u64,
+    calls: HashMap<(String, String), u64>,
+}
+
+/// The counters of the calls accounted by a [`CallPolicy`].
+///
+/// The counters are shared by the clones of a `CallCounters`, and by the
+/// instances of the [`ImportObject`] it was created with.
+#[derive(Debug, Clone)]
+pub struct CallCounters {
+    fuel: Option<u64>,
+    counts: Arc<Mutex<Counts>>,
+}
+
+impl CallCounters {
+    /// Returns the number of calls to `module`.`name`.
+    pub fn calls(&self, module: &str, name: &str) -> u64 {
+        let counts = self.counts.lock().unwrap();
+        counts
+            .calls
+            .get(&(module.to_string(), name.to_string()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Returns the fuel consumed by the calls.
+    pub fn fuel_consumed(&self) -> u64 {
+        self.counts.lock().unwrap().fuel_consumed
+    }
+
+    /// Returns the fuel remaining, if the fuel is limited.
+    pub fn remaining_fuel(&self) -> Option<u64> {
+        self.fuel
+            .map(|fuel| fuel.saturating_sub(self.fuel_consumed()))
+    }
+
+    /// Resets the fuel consumed and the numbers of calls, which restores
+    /// the fuel and the quotas.
+    pub fn reset(&self) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.fuel_consumed = 0;
+        counts.calls.clear();
+    }
+
+    /// Accounts for a call to `module`.`name`.
+    fn charge(
+        &self,
+        module: &str,
+        name: &str,
+        cost: u64,
+        quota: Option<u64>,
+    ) -> Result<(), CallPolicyError> {
+        let mut counts = self.counts.lock().unwrap();
+        let calls = counts
+            .calls
+            .get(&(module.to_string(), name.to_string()))
+            .copied()
+            .unwrap_or(0);
+        if let Some(quota) = quota.filter(|&quota| calls >= quota) {
+            return Err(CallPolicyError::QuotaExhausted {
+                module: module.to_string(),
+                name: name.to_string(),
+                quota,
+            });
+        }
+        if let Some(fuel) = self.fuel {
+            let remaining = fuel.saturating_sub(counts.fuel_consumed);
+            if cost > remaining {
+                return Err(CallPolicyError::OutOfFuel {
+                    module: module.to_string(),
+                    name: name.to_string(),
+                    cost,
+                    remaining,
+                });
+            }
+        }
+        counts.fuel_consumed = counts.fuel_consumed.saturating_add(cost);
+        counts
+            .calls
+            .insert((module.to_string(), name.to_string()), calls + 1);
+        Ok(())
+    }
+}
+
+/// The environment of a function wrapped by a [`CallPolicy`].
+///
+/// The environment of the wrapped function is initialized along with it.
+#[derive(Clone)]
+struct MeteredEnv {
+    module: String,
+    name: String,
+    cost: u64,
+    quota: Option<u64>,
+    counters: CallCounters,
+    function: Function,
+    host_env_init: Option<ImportInitializerFuncPtr>,
+    vmctx: *mut VMContext,
+}
+
+impl WasmerEnv for MeteredEnv {
+    fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+        match self.host_env_init {
+            Some(init) => unsafe { init(self.vmctx, instance as *const Instance as *const c_void) },
+            None => Ok(()),
+        }
+    }
+}
+
+impl ImportObject {
+    /// Returns a copy of this `ImportObject` where the calls to the
+    /// functions are accounted by `policy`, along with the counters of
+    /// the calls.
+    ///
+    /// The counters are shared by all the instances of the returned
+    /// `ImportObject`: to account for the calls of each instance, call
+    /// this method for each of them.
+    pub fn with_call_policy(&self, store: &Store, policy: &CallPolicy) -> (Self, CallCounters) {
+        let counters = CallCounters {
+            fuel: policy.fuel,
+            counts: Arc::new(Mutex::new(Counts::default())),
+        };
+        let mut namespaces: HashMap<String, Exports> = HashMap::new();
+        for ((module, name), export) in self.clone() {
+            let item = match export {
+                Export::Function(exported) => {
+                    let key = (module.clone(), name.clone());
+                    let env = MeteredEnv {
+                        cost: policy
+                            .costs
+                            .get(&key)
+                            .copied()
+                            .unwrap_or(policy.default_cost),
+                        quota: policy.quotas.get(&key).copied(),
+                        counters: counters.clone(),
+                        host_env_init: exported.host_env_init,
+                        vmctx: exported.vmctx,
+                        function: Function::from_export(store, exported.clone()),
+                        module: module.clone(),
+                        name: name.clone(),
+                    };
+                    Extern::Function(Function::new_with_env(
+                        store,
+                        &exported.signature,
+                        env,
+                        metered_call,
+                    ))
+                }
+                export => Extern::from_export(store, export),
+            };
+            namespaces.entry(module).or_default().insert(name, item);
+        }
+
+        let mut import_object = Self::new();
+        for (module, namespace) in namespaces {
+            import_object.register(module, namespace);
+        }
+        (import_object, counters)
+    }
+}
+
+fn metered_call(env: &mut MeteredEnv, args: &[Val]) -> Result<Vec<Val>, RuntimeError> {
+    env.counters
+        .charge(&env.module, &env.name, env.cost, env.quota)
+        .map_err(|error| RuntimeError::from_trap(Trap::User(Box::new(error))))?;
+    Ok(env.function.call(args)?.into_vec())
+}
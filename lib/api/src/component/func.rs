@@ -0,0 +1,185 @@
+//! Typed functions exchanging canonical ABI values.
+
+use super::types::lift_pointer;
+use super::{CanonicalAbi, ComponentError, Lift, LiftContext, Lower, LowerContext};
+use crate::externals::Function;
+use crate::store::Store;
+use crate::types::{FunctionType, Val, ValType};
+use crate::RuntimeError;
+use std::marker::PhantomData;
+
+/// The maximum number of flat values passed as parameters. Beyond that,
+/// the parameters are stored in memory and passed by pointer.
+pub const MAX_FLAT_PARAMS: usize = 16;
+
+/// The maximum number of flat values returned as results. Beyond that,
+/// the results are stored in memory and returned by pointer.
+pub const MAX_FLAT_RESULTS: usize = 1;
+
+/// The core signature of a guest export with the given high-level types.
+fn export_signature<Params: Lower, Results: Lift>() -> FunctionType {
+    let mut params = Params::flat_types();
+    if params.len() > MAX_FLAT_PARAMS {
+        params = vec![ValType::I32];
+    }
+    let mut results = Results::flat_types();
+    if results.len() > MAX_FLAT_RESULTS {
+        results = vec![ValType::I32];
+    }
+    FunctionType::new(params, results)
+}
+
+/// The core signature of a host import with the given high-level types.
+///
+/// When the results don't fit in [`MAX_FLAT_RESULTS`], the caller passes
+/// a pointer where the callee stores them as an extra parameter.
+fn import_signature<Params: Lift, Results: Lower>() -> FunctionType {
+    let mut params = Params::flat_types();
+    if params.len() > MAX_FLAT_PARAMS {
+        params = vec![ValType::I32];
+    }
+    let mut results = Results::flat_types();
+    if results.len() > MAX_FLAT_RESULTS {
+        params.push(ValType::I32);
+        results = vec![];
+    }
+    FunctionType::new(params, results)
+}
+
+/// A guest export called with high-level parameters and results.
+///
+/// `Params` is a tuple of the parameters, `Results` the returned value.
+///
+/// ```ignore
+/// let concat: TypedFunction<(&str, &str), String> =
+///     TypedFunction::new(instance.exports.get_function("concat")?, &abi)?;
+/// assert_eq!(concat.call(("foo", "bar"))?, "foobar");
+/// ```
+pub struct TypedFunction<Params, Results> {
+    func: Function,
+    abi: CanonicalAbi,
+    _phantom: PhantomData<fn(Params) -> Results>,
+}
+
+impl<Params, Results> TypedFunction<Params, Results>
+where
+    Params: Lower,
+    Results: Lift,
+{
+    /// Creates a new `TypedFunction`, checking that `func` has the lowered
+    /// signature of `Params` and `Results`.
+    pub fn new(func: &Function, abi: &CanonicalAbi) -> Result<Self, ComponentError> {
+        let expected = export_signature::<Params, Results>();
+        if func.ty() != &expected {
+            return Err(ComponentError::Signature {
+                expected,
+                found: func.ty().clone(),
+            });
+        }
+        Ok(Self {
+            func: func.clone(),
+            abi: abi.clone(),
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Returns the underlying [`Function`].
+    pub fn function(&self) -> &Function {
+        &self.func
+    }
+
+    /// Lowers `params` into the guest, calls the function and lifts its
+    /// results.
+    pub fn call(&self, params: Params) -> Result<Results, ComponentError> {
+        let bound = self.abi.bound()?;
+        let mut cx = LowerContext::new(&bound.memory, bound.realloc.as_ref());
+
+        let mut args = Vec::new();
+        if Params::flat_types().len() > MAX_FLAT_PARAMS {
+            let ptr = cx.alloc(Params::align(), Params::size())?;
+            params.store(&mut cx, ptr)?;
+            args.push(Val::I32(ptr as i32));
+        } else {
+            params.lower(&mut cx, &mut args)?;
+        }
+
+        let results = self.func.call(&args)?;
+
+        let cx = LiftContext::new(&bound.memory);
+        if Results::flat_types().len() > MAX_FLAT_RESULTS {
+            let ptr = lift_pointer(&results)?;
+            super::check_alignment(ptr, Results::align())?;
+            Results::load(&cx, ptr)
+        } else {
+            Results::lift(&cx, &mut results.iter())
+        }
+    }
+}
+
+impl<Params, Results> Clone for TypedFunction<Params, Results> {
+    fn clone(&self) -> Self {
+        Self {
+            func: self.func.clone(),
+            abi: self.abi.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl Function {
+    /// Creates a new host `Function` exchanging high-level values with
+    /// the guest through the canonical ABI.
+    ///
+    /// `Params` is a tuple of the parameters, lifted from the guest with
+    /// the memory bound to `abi`. The returned value is lowered into the
+    /// guest, using its allocator if needed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmer::{Store, Function};
+    /// # use wasmer::component::CanonicalAbi;
+    /// # let store = Store::default();
+    /// let abi = CanonicalAbi::new();
+    ///
+    /// let shout = Function::new_component(&store, &abi, |(text,): (String,)| {
+    ///     Ok(text.to_uppercase())
+    /// });
+    /// ```
+    pub fn new_component<Params, Results, F>(store: &Store, abi: &CanonicalAbi, func: F) -> Self
+    where
+        Params: Lift + 'static,
+        Results: Lower + 'static,
+        F: Fn(Params) -> Result<Results, RuntimeError> + 'static,
+    {
+        let ty = import_signature::<Params, Results>();
+        let indirect_params = Params::flat_types().len() > MAX_FLAT_PARAMS;
+        let indirect_results = Results::flat_types().len() > MAX_FLAT_RESULTS;
+
+        Self::new_with_env(store, &ty, abi.clone(), move |abi, args| {
+            let bound = abi.bound()?;
+
+            let cx = LiftContext::new(&bound.memory);
+            let params = if indirect_params {
+                let ptr = lift_pointer(args)?;
+                super::check_alignment(ptr, Params::align())?;
+                Params::load(&cx, ptr)?
+            } else {
+                Params::lift(&cx, &mut args.iter())?
+            };
+
+            let results = func(params)?;
+
+            let mut cx = LowerContext::new(&bound.memory, bound.realloc.as_ref());
+            let mut values = Vec::new();
+            if indirect_results {
+                let ptr = lift_pointer(&args[args.len() - 1..])?;
+                super::check_alignment(ptr, Results::align())?;
+                results.store(&mut cx, ptr)?;
+            } else {
+                results.lower(&mut cx, &mut values)?;
+            }
+            Ok(values)
+        })
+    }
+}
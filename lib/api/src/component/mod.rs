@@ -0,0 +1,379 @@
+//! High-level values passed across the host/guest boundary.
+//!
+//! WebAssembly functions can only exchange numbers. This module provides
+//! a binding layer, modeled after the canonical ABI of the WebAssembly
+//! component model, that lets the host exchange strings, byte buffers,
+//! lists, records and results with a guest:
+//!
+//! * values are *lowered* into the guest (written into its linear memory,
+//!   using the allocator the guest exports as `canonical_abi_realloc`),
+//! * values are *lifted* from the guest (read back from its linear memory).
+//!
+//! Types taking part in the ABI implement [`ComponentType`], [`Lift`]
+//! and [`Lower`]. Rust structs and field-less enums can derive all of
+//! them with `#[derive(ComponentType)]`.
+//!
+//! ```
+//! # use wasmer::{Store, Module, Instance, imports, Function};
+//! # use wasmer::component::{CanonicalAbi, ComponentType, TypedFunction};
+//! #[derive(ComponentType, Debug, PartialEq)]
+//! struct Greeting {
+//!     name: String,
+//!     times: u32,
+//! }
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! # let store = Store::default();
+//! # let module = Module::new(&store, r#"
+//! # (module
+//! #   (import "host" "log" (func $log (param i32 i32)))
+//! #   (memory (export "memory") 1)
+//! #   (global $next (mut i32) (i32.const 1024))
+//! #   (func (export "canonical_abi_realloc") (param i32 i32 i32 i32) (result i32)
+//! #     (local $ptr i32)
+//! #     (local.set $ptr (global.get $next))
+//! #     (global.set $next (i32.add (global.get $next) (local.get 3)))
+//! #     (local.get $ptr))
+//! #   (func (export "greet") (param i32 i32 i32) (result i32)
+//! #     (call $log (local.get 0) (local.get 1))
+//! #     (local.get 2)))
+//! # "#)?;
+//! let abi = CanonicalAbi::new();
+//! let log = Function::new_component(&store, &abi, |(message,): (String,)| {
+//!     println!("guest says: {}", message);
+//!     Ok(())
+//! });
+//! // The `CanonicalAbi` is bound to the instance exports by `Instance::new`.
+//! let instance = Instance::new(&module, &imports! { "host" => { "log" => log } })?;
+//!
+//! let greet: TypedFunction<(Greeting,), u32> =
+//!     TypedFunction::new(instance.exports.get_function("greet")?, &abi)?;
+//! let times = greet.call((Greeting { name: "wasmer".into(), times: 3 },))?;
+//! assert_eq!(times, 3);
+//! # Ok(())
+//! # }
+//! ```
+
+mod func;
+mod types;
+
+pub use self::func::{TypedFunction, MAX_FLAT_PARAMS, MAX_FLAT_RESULTS};
+pub use self::types::{ComponentType, Lift, Lower};
+pub use wasmer_derive::ComponentType;
+
+use crate::env::WasmerEnv;
+use crate::exports::{ExportError, Exports};
+use crate::externals::{Function, Memory};
+use crate::instance::Instance;
+use crate::types::{FunctionType, Val, ValType};
+use crate::RuntimeError;
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use wasmer_vm::HostEnvInitError;
+
+/// The name of the memory export used by [`CanonicalAbi::bind`].
+pub const MEMORY_EXPORT: &str = "memory";
+
+/// The name of the allocator export used by [`CanonicalAbi::bind`].
+///
+/// The allocator has the signature
+/// `(old_ptr: i32, old_size: i32, align: i32, new_size: i32) -> i32`.
+pub const REALLOC_EXPORT: &str = "canonical_abi_realloc";
+
+/// An error that happened while lifting or lowering a value.
+#[derive(Error, Debug)]
+pub enum ComponentError {
+    /// A value points outside of the guest memory.
+    #[error("out of bounds memory access at offset {offset:#x} with length {len}")]
+    OutOfBounds {
+        /// The offset of the access.
+        offset: usize,
+        /// The length of the access.
+        len: usize,
+    },
+    /// A pointer is not aligned to its type alignment.
+    #[error("pointer {ptr:#x} is not aligned to {align}")]
+    Unaligned {
+        /// The pointer.
+        ptr: usize,
+        /// The required alignment.
+        align: usize,
+    },
+    /// A string is not valid UTF-8.
+    #[error("invalid UTF-8 string")]
+    InvalidUtf8,
+    /// A `char` is not a valid Unicode scalar value.
+    #[error("invalid char value {0:#x}")]
+    InvalidChar(u32),
+    /// A `bool` is neither `0` nor `1`.
+    #[error("invalid bool value {0}")]
+    InvalidBool(u32),
+    /// A variant discriminant doesn't match any case.
+    #[error("invalid discriminant {0}")]
+    InvalidDiscriminant(u32),
+    /// A flat value has an unexpected type.
+    #[error("expected a value of type {expected}, found {found}")]
+    TypeMismatch {
+        /// The expected type.
+        expected: ValType,
+        /// The provided type.
+        found: ValType,
+    },
+    /// There are fewer flat values than the type needs.
+    #[error("missing flat value")]
+    MissingValue,
+    /// A function doesn't have the lowered signature of its typed counterpart.
+    #[error("function signature mismatch: expected {expected}, found {found}")]
+    Signature {
+        /// The signature expected by the typed function.
+        expected: FunctionType,
+        /// The signature of the function.
+        found: FunctionType,
+    },
+    /// The [`CanonicalAbi`] was used before being bound to an instance.
+    #[error("the canonical ABI is not bound to an instance")]
+    Unbound,
+    /// A value is too large to be allocated in the guest.
+    #[error("can't allocate {0} bytes in the guest")]
+    TooLarge(usize),
+    /// A value had to be allocated in the guest, but the guest doesn't
+    /// export an allocator.
+    #[error("the guest doesn't export `{}`", REALLOC_EXPORT)]
+    MissingRealloc,
+    /// A required export is missing.
+    #[error(transparent)]
+    Export(#[from] ExportError),
+    /// A call into the guest failed.
+    #[error(transparent)]
+    Runtime(#[from] RuntimeError),
+}
+
+impl From<ComponentError> for RuntimeError {
+    fn from(error: ComponentError) -> Self {
+        match error {
+            ComponentError::Runtime(error) => error,
+            error => Self::new(error.to_string()),
+        }
+    }
+}
+
+/// Rounds `offset` up to the next multiple of `align`.
+///
+/// `align` must be a power of two.
+#[inline]
+pub fn align_to(offset: usize, align: usize) -> usize {
+    debug_assert!(align.is_power_of_two());
+    (offset + align - 1) & !(align - 1)
+}
+
+#[derive(Clone)]
+struct Bound {
+    memory: Memory,
+    realloc: Option<Function>,
+}
+
+/// The guest exports the canonical ABI needs to exchange values with an
+/// instance: its memory and its allocator.
+///
+/// A `CanonicalAbi` is created before the instance (so it can be handed
+/// to the host functions the instance imports) and bound to the instance
+/// exports afterwards with [`CanonicalAbi::bind`]. Clones share the same
+/// binding.
+///
+/// When the `CanonicalAbi` is the environment of an imported host
+/// function, [`Instance::new`] binds it automatically.
+#[derive(Clone, Default)]
+pub struct CanonicalAbi {
+    bound: Arc<Mutex<Option<Bound>>>,
+}
+
+impl CanonicalAbi {
+    /// Creates a new unbound `CanonicalAbi`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Binds to the [`MEMORY_EXPORT`] and [`REALLOC_EXPORT`] exports.
+    ///
+    /// The allocator is optional: without it, values can still be lifted
+    /// from the guest but strings and lists can't be lowered into it.
+    pub fn bind(&self, exports: &Exports) -> Result<(), ComponentError> {
+        let memory = exports.get_memory(MEMORY_EXPORT)?.clone();
+        let realloc = match exports.get_function(REALLOC_EXPORT) {
+            Ok(realloc) => Some(realloc.clone()),
+            Err(ExportError::Missing(_)) => None,
+            Err(error) => return Err(error.into()),
+        };
+        self.bind_with(memory, realloc)
+    }
+
+    /// Binds to an explicit memory and allocator.
+    pub fn bind_with(
+        &self,
+        memory: Memory,
+        realloc: Option<Function>,
+    ) -> Result<(), ComponentError> {
+        if let Some(realloc) = &realloc {
+            let expected = FunctionType::new(vec![ValType::I32; 4], vec![ValType::I32]);
+            if realloc.ty() != &expected {
+                return Err(ComponentError::Signature {
+                    expected,
+                    found: realloc.ty().clone(),
+                });
+            }
+        }
+        *self.bound.lock().unwrap() = Some(Bound { memory, realloc });
+        Ok(())
+    }
+
+    /// Returns whether the `CanonicalAbi` is bound to an instance.
+    pub fn is_bound(&self) -> bool {
+        self.bound.lock().unwrap().is_some()
+    }
+
+    /// Returns the bound memory.
+    pub fn memory(&self) -> Result<Memory, ComponentError> {
+        Ok(self.bound()?.memory)
+    }
+
+    // The binding is cloned out of the lock: lowering values calls back
+    // into the guest, which may call host functions sharing this binding.
+    fn bound(&self) -> Result<Bound, ComponentError> {
+        self.bound
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or(ComponentError::Unbound)
+    }
+}
+
+/// Host functions created with [`Function::new_component`] bind their
+/// `CanonicalAbi` to the importing instance, unless it is already bound.
+impl WasmerEnv for CanonicalAbi {
+    fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+        if self.is_bound() {
+            return Ok(());
+        }
+        self.bind(&instance.exports).map_err(|error| match error {
+            ComponentError::Export(ExportError::Missing(name)) => {
+                HostEnvInitError::MissingExport(name)
+            }
+            ComponentError::Export(ExportError::IncompatibleType) => {
+                HostEnvInitError::IncompatibleExport(MEMORY_EXPORT.to_string())
+            }
+            ComponentError::Signature { .. } => {
+                HostEnvInitError::IncompatibleExport(REALLOC_EXPORT.to_string())
+            }
+            error => HostEnvInitError::Generic(error.to_string()),
+        })
+    }
+}
+
+impl fmt::Debug for CanonicalAbi {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CanonicalAbi")
+            .field("bound", &self.is_bound())
+            .finish()
+    }
+}
+
+/// The context used to lift values from a guest.
+pub struct LiftContext<'a> {
+    memory: &'a Memory,
+}
+
+impl<'a> LiftContext<'a> {
+    /// Creates a new context reading from `memory`.
+    pub fn new(memory: &'a Memory) -> Self {
+        Self { memory }
+    }
+
+    /// Copies `buf.len()` bytes at `offset` into `buf`.
+    pub fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), ComponentError> {
+        self.memory
+            .read(offset as u64, buf)
+            .map_err(|_| ComponentError::OutOfBounds {
+                offset,
+                len: buf.len(),
+            })
+    }
+
+    /// Copies `len` bytes at `offset` into a new vector.
+    pub fn read_vec(&self, offset: usize, len: usize) -> Result<Vec<u8>, ComponentError> {
+        check_bounds(self.memory, offset, len)?;
+        let mut buf = vec![0; len];
+        self.read(offset, &mut buf)?;
+        Ok(buf)
+    }
+}
+
+/// The context used to lower values into a guest.
+pub struct LowerContext<'a> {
+    memory: &'a Memory,
+    realloc: Option<&'a Function>,
+}
+
+impl<'a> LowerContext<'a> {
+    /// Creates a new context writing into `memory`, allocating with
+    /// `realloc`.
+    pub fn new(memory: &'a Memory, realloc: Option<&'a Function>) -> Self {
+        Self { memory, realloc }
+    }
+
+    /// Allocates `size` bytes aligned to `align` in the guest, and returns
+    /// their offset.
+    pub fn alloc(&mut self, align: usize, size: usize) -> Result<usize, ComponentError> {
+        let realloc = self.realloc.ok_or(ComponentError::MissingRealloc)?;
+        let guest_size = i32::try_from(size).map_err(|_| ComponentError::TooLarge(size))?;
+        let results = realloc.call(&[
+            Val::I32(0),
+            Val::I32(0),
+            Val::I32(align as i32),
+            Val::I32(guest_size),
+        ])?;
+        let ptr = match results.first() {
+            Some(Val::I32(ptr)) => *ptr as u32 as usize,
+            Some(other) => {
+                return Err(ComponentError::TypeMismatch {
+                    expected: ValType::I32,
+                    found: other.ty(),
+                })
+            }
+            None => return Err(ComponentError::MissingValue),
+        };
+        check_alignment(ptr, align)?;
+        check_bounds(self.memory, ptr, size)?;
+        Ok(ptr)
+    }
+
+    /// Copies `bytes` at `offset`.
+    pub fn write(&mut self, offset: usize, bytes: &[u8]) -> Result<(), ComponentError> {
+        self.memory
+            .write(offset as u64, bytes)
+            .map_err(|_| ComponentError::OutOfBounds {
+                offset,
+                len: bytes.len(),
+            })
+    }
+
+    /// Returns a [`LiftContext`] over the same memory.
+    pub fn as_lift(&self) -> LiftContext<'a> {
+        LiftContext::new(self.memory)
+    }
+}
+
+fn check_bounds(memory: &Memory, offset: usize, len: usize) -> Result<(), ComponentError> {
+    match offset.checked_add(len) {
+        Some(end) if end as u64 <= memory.data_size() => Ok(()),
+        _ => Err(ComponentError::OutOfBounds { offset, len }),
+    }
+}
+
+fn check_alignment(ptr: usize, align: usize) -> Result<(), ComponentError> {
+    if ptr % align != 0 {
+        return Err(ComponentError::Unaligned { ptr, align });
+    }
+    Ok(())
+}
@@ -0,0 +1,883 @@
+//! The canonical ABI representation of the Rust types.
+//!
+//! Every type has two representations:
+//!
+//! * a *flat* one, a list of core WebAssembly values, used for function
+//!   parameters and results,
+//! * a *memory* one, with a size and an alignment, used when the value
+//!   is stored in the guest linear memory (list elements, record fields
+//!   passed indirectly, ...).
+
+use super::{align_to, check_alignment, ComponentError, LiftContext, LowerContext};
+use crate::types::{Val, ValType};
+use std::cmp::max;
+use std::mem;
+use std::slice;
+
+/// A type that has a canonical ABI representation.
+pub trait ComponentType {
+    /// The size of the type when stored in memory.
+    fn size() -> usize;
+
+    /// The alignment of the type when stored in memory.
+    fn align() -> usize;
+
+    /// Appends the flat representation of the type to `out`.
+    fn flatten(out: &mut Vec<ValType>);
+
+    /// Returns the flat representation of the type.
+    fn flat_types() -> Vec<ValType> {
+        let mut out = Vec::new();
+        Self::flatten(&mut out);
+        out
+    }
+}
+
+/// A value that can be lowered into a guest.
+pub trait Lower: ComponentType {
+    /// Appends the flat representation of the value to `dst`.
+    fn lower(&self, cx: &mut LowerContext<'_>, dst: &mut Vec<Val>) -> Result<(), ComponentError>;
+
+    /// Stores the value in the guest memory at `offset`.
+    fn store(&self, cx: &mut LowerContext<'_>, offset: usize) -> Result<(), ComponentError>;
+
+    /// Stores a list of values, contiguously, at `offset`.
+    fn store_list(
+        items: &[Self],
+        cx: &mut LowerContext<'_>,
+        offset: usize,
+    ) -> Result<(), ComponentError>
+    where
+        Self: Sized,
+    {
+        for (i, item) in items.iter().enumerate() {
+            item.store(cx, offset + i * Self::size())?;
+        }
+        Ok(())
+    }
+}
+
+/// A value that can be lifted from a guest.
+pub trait Lift: ComponentType + Sized {
+    /// Reads the value from its flat representation.
+    fn lift(cx: &LiftContext<'_>, src: &mut slice::Iter<'_, Val>) -> Result<Self, ComponentError>;
+
+    /// Loads the value from the guest memory at `offset`.
+    fn load(cx: &LiftContext<'_>, offset: usize) -> Result<Self, ComponentError>;
+
+    /// Loads a list of `len` values stored contiguously at `offset`.
+    fn load_list(
+        cx: &LiftContext<'_>,
+        offset: usize,
+        len: usize,
+    ) -> Result<Vec<Self>, ComponentError> {
+        (0..len)
+            .map(|i| Self::load(cx, offset + i * Self::size()))
+            .collect()
+    }
+}
+
+fn next<'a>(src: &mut slice::Iter<'a, Val>) -> Result<&'a Val, ComponentError> {
+    src.next().ok_or(ComponentError::MissingValue)
+}
+
+fn mismatch(expected: ValType, found: &Val) -> ComponentError {
+    ComponentError::TypeMismatch {
+        expected,
+        found: found.ty(),
+    }
+}
+
+fn next_i32(src: &mut slice::Iter<'_, Val>) -> Result<i32, ComponentError> {
+    match next(src)? {
+        Val::I32(value) => Ok(*value),
+        other => Err(mismatch(ValType::I32, other)),
+    }
+}
+
+fn next_i64(src: &mut slice::Iter<'_, Val>) -> Result<i64, ComponentError> {
+    match next(src)? {
+        Val::I64(value) => Ok(*value),
+        other => Err(mismatch(ValType::I64, other)),
+    }
+}
+
+fn next_f32(src: &mut slice::Iter<'_, Val>) -> Result<f32, ComponentError> {
+    match next(src)? {
+        Val::F32(value) => Ok(*value),
+        other => Err(mismatch(ValType::F32, other)),
+    }
+}
+
+fn next_f64(src: &mut slice::Iter<'_, Val>) -> Result<f64, ComponentError> {
+    match next(src)? {
+        Val::F64(value) => Ok(*value),
+        other => Err(mismatch(ValType::F64, other)),
+    }
+}
+
+macro_rules! integers {
+    ( $( $ty:ty => $flat:ident, $lower:expr, $lift:ident ),* ) => {
+        $(
+            impl ComponentType for $ty {
+                fn size() -> usize {
+                    std::mem::size_of::<$ty>()
+                }
+
+                fn align() -> usize {
+                    std::mem::size_of::<$ty>()
+                }
+
+                fn flatten(out: &mut Vec<ValType>) {
+                    out.push(ValType::$flat);
+                }
+            }
+
+            impl Lower for $ty {
+                #[allow(clippy::redundant_closure_call)]
+                fn lower(&self, _cx: &mut LowerContext<'_>, dst: &mut Vec<Val>) -> Result<(), ComponentError> {
+                    dst.push(Val::$flat(($lower)(*self)));
+                    Ok(())
+                }
+
+                fn store(&self, cx: &mut LowerContext<'_>, offset: usize) -> Result<(), ComponentError> {
+                    cx.write(offset, &self.to_le_bytes())
+                }
+            }
+
+            impl Lift for $ty {
+                #[allow(trivial_numeric_casts)]
+                fn lift(_cx: &LiftContext<'_>, src: &mut slice::Iter<'_, Val>) -> Result<Self, ComponentError> {
+                    // Narrow integers are truncated, as the canonical ABI does.
+                    Ok($lift(src)? as $ty)
+                }
+
+                fn load(cx: &LiftContext<'_>, offset: usize) -> Result<Self, ComponentError> {
+                    let mut bytes = [0; std::mem::size_of::<$ty>()];
+                    cx.read(offset, &mut bytes)?;
+                    Ok(<$ty>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+integers!(
+    i8 => I32, |v: i8| v as i32, next_i32,
+    i16 => I32, |v: i16| v as i32, next_i32,
+    u16 => I32, |v: u16| v as i32, next_i32,
+    i32 => I32, |v: i32| v, next_i32,
+    u32 => I32, |v: u32| v as i32, next_i32,
+    i64 => I64, |v: i64| v, next_i64,
+    u64 => I64, |v: u64| v as i64, next_i64
+);
+
+// Bytes are implemented by hand so that byte lists are copied in one go
+// rather than element by element.
+impl ComponentType for u8 {
+    fn size() -> usize {
+        1
+    }
+
+    fn align() -> usize {
+        1
+    }
+
+    fn flatten(out: &mut Vec<ValType>) {
+        out.push(ValType::I32);
+    }
+}
+
+impl Lower for u8 {
+    fn lower(&self, _cx: &mut LowerContext<'_>, dst: &mut Vec<Val>) -> Result<(), ComponentError> {
+        dst.push(Val::I32(*self as i32));
+        Ok(())
+    }
+
+    fn store(&self, cx: &mut LowerContext<'_>, offset: usize) -> Result<(), ComponentError> {
+        cx.write(offset, &[*self])
+    }
+
+    fn store_list(
+        items: &[Self],
+        cx: &mut LowerContext<'_>,
+        offset: usize,
+    ) -> Result<(), ComponentError> {
+        cx.write(offset, items)
+    }
+}
+
+impl Lift for u8 {
+    fn lift(_cx: &LiftContext<'_>, src: &mut slice::Iter<'_, Val>) -> Result<Self, ComponentError> {
+        Ok(next_i32(src)? as u8)
+    }
+
+    fn load(cx: &LiftContext<'_>, offset: usize) -> Result<Self, ComponentError> {
+        let mut byte = [0];
+        cx.read(offset, &mut byte)?;
+        Ok(byte[0])
+    }
+
+    fn load_list(
+        cx: &LiftContext<'_>,
+        offset: usize,
+        len: usize,
+    ) -> Result<Vec<Self>, ComponentError> {
+        cx.read_vec(offset, len)
+    }
+}
+
+macro_rules! floats {
+    ( $( $ty:ty => $flat:ident, $bits:ty, $lift:ident ),* ) => {
+        $(
+            impl ComponentType for $ty {
+                fn size() -> usize {
+                    std::mem::size_of::<$ty>()
+                }
+
+                fn align() -> usize {
+                    std::mem::size_of::<$ty>()
+                }
+
+                fn flatten(out: &mut Vec<ValType>) {
+                    out.push(ValType::$flat);
+                }
+            }
+
+            impl Lower for $ty {
+                fn lower(&self, _cx: &mut LowerContext<'_>, dst: &mut Vec<Val>) -> Result<(), ComponentError> {
+                    dst.push(Val::$flat(*self));
+                    Ok(())
+                }
+
+                fn store(&self, cx: &mut LowerContext<'_>, offset: usize) -> Result<(), ComponentError> {
+                    cx.write(offset, &self.to_bits().to_le_bytes())
+                }
+            }
+
+            impl Lift for $ty {
+                fn lift(_cx: &LiftContext<'_>, src: &mut slice::Iter<'_, Val>) -> Result<Self, ComponentError> {
+                    $lift(src)
+                }
+
+                fn load(cx: &LiftContext<'_>, offset: usize) -> Result<Self, ComponentError> {
+                    let mut bytes = [0; std::mem::size_of::<$ty>()];
+                    cx.read(offset, &mut bytes)?;
+                    Ok(<$ty>::from_bits(<$bits>::from_le_bytes(bytes)))
+                }
+            }
+        )*
+    };
+}
+
+floats!(
+    f32 => F32, u32, next_f32,
+    f64 => F64, u64, next_f64
+);
+
+impl ComponentType for bool {
+    fn size() -> usize {
+        1
+    }
+
+    fn align() -> usize {
+        1
+    }
+
+    fn flatten(out: &mut Vec<ValType>) {
+        out.push(ValType::I32);
+    }
+}
+
+impl Lower for bool {
+    fn lower(&self, _cx: &mut LowerContext<'_>, dst: &mut Vec<Val>) -> Result<(), ComponentError> {
+        dst.push(Val::I32(*self as i32));
+        Ok(())
+    }
+
+    fn store(&self, cx: &mut LowerContext<'_>, offset: usize) -> Result<(), ComponentError> {
+        cx.write(offset, &[*self as u8])
+    }
+}
+
+impl Lift for bool {
+    fn lift(_cx: &LiftContext<'_>, src: &mut slice::Iter<'_, Val>) -> Result<Self, ComponentError> {
+        match next_i32(src)? as u32 {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(ComponentError::InvalidBool(other)),
+        }
+    }
+
+    fn load(cx: &LiftContext<'_>, offset: usize) -> Result<Self, ComponentError> {
+        match u8::load(cx, offset)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(ComponentError::InvalidBool(other.into())),
+        }
+    }
+}
+
+impl ComponentType for char {
+    fn size() -> usize {
+        4
+    }
+
+    fn align() -> usize {
+        4
+    }
+
+    fn flatten(out: &mut Vec<ValType>) {
+        out.push(ValType::I32);
+    }
+}
+
+impl Lower for char {
+    fn lower(&self, _cx: &mut LowerContext<'_>, dst: &mut Vec<Val>) -> Result<(), ComponentError> {
+        dst.push(Val::I32(*self as u32 as i32));
+        Ok(())
+    }
+
+    fn store(&self, cx: &mut LowerContext<'_>, offset: usize) -> Result<(), ComponentError> {
+        (*self as u32).store(cx, offset)
+    }
+}
+
+impl Lift for char {
+    fn lift(_cx: &LiftContext<'_>, src: &mut slice::Iter<'_, Val>) -> Result<Self, ComponentError> {
+        let value = next_i32(src)? as u32;
+        std::char::from_u32(value).ok_or(ComponentError::InvalidChar(value))
+    }
+
+    fn load(cx: &LiftContext<'_>, offset: usize) -> Result<Self, ComponentError> {
+        let value = u32::load(cx, offset)?;
+        std::char::from_u32(value).ok_or(ComponentError::InvalidChar(value))
+    }
+}
+
+/// Lowers a list as a `(pointer, length)` pair, storing its elements in
+/// a newly allocated guest buffer.
+fn lower_list<T: Lower>(
+    items: &[T],
+    cx: &mut LowerContext<'_>,
+) -> Result<(usize, usize), ComponentError> {
+    let size = items
+        .len()
+        .checked_mul(T::size())
+        .ok_or(ComponentError::OutOfBounds {
+            offset: 0,
+            len: usize::max_value(),
+        })?;
+    let ptr = cx.alloc(T::align(), size)?;
+    T::store_list(items, cx, ptr)?;
+    Ok((ptr, items.len()))
+}
+
+fn store_pointer_pair(
+    cx: &mut LowerContext<'_>,
+    offset: usize,
+    (ptr, len): (usize, usize),
+) -> Result<(), ComponentError> {
+    (ptr as u32).store(cx, offset)?;
+    (len as u32).store(cx, offset + 4)
+}
+
+fn lift_pointer_pair(src: &mut slice::Iter<'_, Val>) -> Result<(usize, usize), ComponentError> {
+    let ptr = next_i32(src)? as u32 as usize;
+    let len = next_i32(src)? as u32 as usize;
+    Ok((ptr, len))
+}
+
+fn load_pointer_pair(
+    cx: &LiftContext<'_>,
+    offset: usize,
+) -> Result<(usize, usize), ComponentError> {
+    let ptr = u32::load(cx, offset)? as usize;
+    let len = u32::load(cx, offset + 4)? as usize;
+    Ok((ptr, len))
+}
+
+fn lift_list<T: Lift>(
+    cx: &LiftContext<'_>,
+    (ptr, len): (usize, usize),
+) -> Result<Vec<T>, ComponentError> {
+    check_alignment(ptr, T::align())?;
+    if T::size() == 0 {
+        return lift_zero_size_list(cx, ptr, len);
+    }
+    let size = len
+        .checked_mul(T::size())
+        .ok_or(ComponentError::OutOfBounds { offset: ptr, len })?;
+    // Validate the whole list before allocating anything for it.
+    super::check_bounds(cx.memory, ptr, size)?;
+    T::load_list(cx, ptr, len)
+}
+
+/// Lifts a list of zero-size elements, whose guest-controlled length
+/// doesn't depend on the memory.
+fn lift_zero_size_list<T: Lift>(
+    cx: &LiftContext<'_>,
+    ptr: usize,
+    len: usize,
+) -> Result<Vec<T>, ComponentError> {
+    let item = T::load(cx, ptr)?;
+    if mem::size_of::<T>() == 0 && !mem::needs_drop::<T>() {
+        drop(item);
+        let mut list = Vec::new();
+        // SAFETY: the capacity of a vector of zero-size elements is
+        // `usize::MAX`, and its elements have no bytes to initialize nor
+        // drop glue, like the one just loaded.
+        #[allow(clippy::uninit_vec)]
+        unsafe {
+            list.set_len(len)
+        };
+        return Ok(list);
+    }
+    // The other elements are loaded one by one, as long as the list could
+    // be held by the memory with one-byte elements.
+    super::check_bounds(cx.memory, ptr, len)?;
+    T::load_list(cx, ptr, len)
+}
+
+impl<T: ComponentType> ComponentType for [T] {
+    fn size() -> usize {
+        8
+    }
+
+    fn align() -> usize {
+        4
+    }
+
+    fn flatten(out: &mut Vec<ValType>) {
+        out.push(ValType::I32);
+        out.push(ValType::I32);
+    }
+}
+
+impl<T: Lower> Lower for [T] {
+    fn lower(&self, cx: &mut LowerContext<'_>, dst: &mut Vec<Val>) -> Result<(), ComponentError> {
+        let (ptr, len) = lower_list(self, cx)?;
+        dst.push(Val::I32(ptr as i32));
+        dst.push(Val::I32(len as i32));
+        Ok(())
+    }
+
+    fn store(&self, cx: &mut LowerContext<'_>, offset: usize) -> Result<(), ComponentError> {
+        let pair = lower_list(self, cx)?;
+        store_pointer_pair(cx, offset, pair)
+    }
+}
+
+impl<T: ComponentType> ComponentType for Vec<T> {
+    fn size() -> usize {
+        8
+    }
+
+    fn align() -> usize {
+        4
+    }
+
+    fn flatten(out: &mut Vec<ValType>) {
+        out.push(ValType::I32);
+        out.push(ValType::I32);
+    }
+}
+
+impl<T: Lower> Lower for Vec<T> {
+    fn lower(&self, cx: &mut LowerContext<'_>, dst: &mut Vec<Val>) -> Result<(), ComponentError> {
+        self.as_slice().lower(cx, dst)
+    }
+
+    fn store(&self, cx: &mut LowerContext<'_>, offset: usize) -> Result<(), ComponentError> {
+        self.as_slice().store(cx, offset)
+    }
+}
+
+impl<T: Lift> Lift for Vec<T> {
+    fn lift(cx: &LiftContext<'_>, src: &mut slice::Iter<'_, Val>) -> Result<Self, ComponentError> {
+        let pair = lift_pointer_pair(src)?;
+        lift_list(cx, pair)
+    }
+
+    fn load(cx: &LiftContext<'_>, offset: usize) -> Result<Self, ComponentError> {
+        let pair = load_pointer_pair(cx, offset)?;
+        lift_list(cx, pair)
+    }
+}
+
+impl ComponentType for str {
+    fn size() -> usize {
+        8
+    }
+
+    fn align() -> usize {
+        4
+    }
+
+    fn flatten(out: &mut Vec<ValType>) {
+        out.push(ValType::I32);
+        out.push(ValType::I32);
+    }
+}
+
+impl Lower for str {
+    fn lower(&self, cx: &mut LowerContext<'_>, dst: &mut Vec<Val>) -> Result<(), ComponentError> {
+        self.as_bytes().lower(cx, dst)
+    }
+
+    fn store(&self, cx: &mut LowerContext<'_>, offset: usize) -> Result<(), ComponentError> {
+        self.as_bytes().store(cx, offset)
+    }
+}
+
+impl ComponentType for String {
+    fn size() -> usize {
+        8
+    }
+
+    fn align() -> usize {
+        4
+    }
+
+    fn flatten(out: &mut Vec<ValType>) {
+        out.push(ValType::I32);
+        out.push(ValType::I32);
+    }
+}
+
+impl Lower for String {
+    fn lower(&self, cx: &mut LowerContext<'_>, dst: &mut Vec<Val>) -> Result<(), ComponentError> {
+        self.as_str().lower(cx, dst)
+    }
+
+    fn store(&self, cx: &mut LowerContext<'_>, offset: usize) -> Result<(), ComponentError> {
+        self.as_str().store(cx, offset)
+    }
+}
+
+impl Lift for String {
+    fn lift(cx: &LiftContext<'_>, src: &mut slice::Iter<'_, Val>) -> Result<Self, ComponentError> {
+        let (ptr, len) = lift_pointer_pair(src)?;
+        String::from_utf8(cx.read_vec(ptr, len)?).map_err(|_| ComponentError::InvalidUtf8)
+    }
+
+    fn load(cx: &LiftContext<'_>, offset: usize) -> Result<Self, ComponentError> {
+        let (ptr, len) = load_pointer_pair(cx, offset)?;
+        String::from_utf8(cx.read_vec(ptr, len)?).map_err(|_| ComponentError::InvalidUtf8)
+    }
+}
+
+impl<T: ComponentType + ?Sized> ComponentType for &T {
+    fn size() -> usize {
+        T::size()
+    }
+
+    fn align() -> usize {
+        T::align()
+    }
+
+    fn flatten(out: &mut Vec<ValType>) {
+        T::flatten(out)
+    }
+}
+
+impl<T: Lower + ?Sized> Lower for &T {
+    fn lower(&self, cx: &mut LowerContext<'_>, dst: &mut Vec<Val>) -> Result<(), ComponentError> {
+        (**self).lower(cx, dst)
+    }
+
+    fn store(&self, cx: &mut LowerContext<'_>, offset: usize) -> Result<(), ComponentError> {
+        (**self).store(cx, offset)
+    }
+}
+
+/// Joins the flat representations of the cases of a variant, so that a
+/// single list of core values can hold any of them.
+fn join(a: &[ValType], b: &[ValType]) -> Vec<ValType> {
+    let join_one = |a: ValType, b: ValType| match (a, b) {
+        (a, b) if a == b => a,
+        (ValType::I32, ValType::F32) | (ValType::F32, ValType::I32) => ValType::I32,
+        _ => ValType::I64,
+    };
+    (0..max(a.len(), b.len()))
+        .map(|i| match (a.get(i), b.get(i)) {
+            (Some(&a), Some(&b)) => join_one(a, b),
+            (Some(&ty), None) | (None, Some(&ty)) => ty,
+            (None, None) => unreachable!(),
+        })
+        .collect()
+}
+
+fn zero(ty: ValType) -> Val {
+    match ty {
+        ValType::I32 => Val::I32(0),
+        ValType::I64 => Val::I64(0),
+        ValType::F32 => Val::F32(0.0),
+        ValType::F64 => Val::F64(0.0),
+        _ => unreachable!("the canonical ABI only uses numeric types"),
+    }
+}
+
+/// Converts a case value into the joined type.
+fn widen(value: &Val, to: ValType) -> Val {
+    match (value, to) {
+        (Val::I32(v), ValType::I64) => Val::I64(*v as u32 as i64),
+        (Val::F32(v), ValType::I32) => Val::I32(v.to_bits() as i32),
+        (Val::F32(v), ValType::I64) => Val::I64(v.to_bits() as i64),
+        (Val::F64(v), ValType::I64) => Val::I64(v.to_bits() as i64),
+        (value, _) => value.clone(),
+    }
+}
+
+/// Converts a joined value back into the case type.
+fn narrow(value: &Val, to: ValType) -> Result<Val, ComponentError> {
+    Ok(match (value, to) {
+        (Val::I64(v), ValType::I32) => Val::I32(*v as i32),
+        (Val::I32(v), ValType::F32) => Val::F32(f32::from_bits(*v as u32)),
+        (Val::I64(v), ValType::F32) => Val::F32(f32::from_bits(*v as u32)),
+        (Val::I64(v), ValType::F64) => Val::F64(f64::from_bits(*v as u64)),
+        (value, to) if value.ty() == to => value.clone(),
+        (value, to) => return Err(mismatch(to, value)),
+    })
+}
+
+/// Lowers the payload of a variant case into the joined representation.
+fn lower_case<T: Lower + ?Sized>(
+    discriminant: u32,
+    payload: Option<&T>,
+    joined: &[ValType],
+    cx: &mut LowerContext<'_>,
+    dst: &mut Vec<Val>,
+) -> Result<(), ComponentError> {
+    dst.push(Val::I32(discriminant as i32));
+    let mut case = Vec::new();
+    if let Some(payload) = payload {
+        payload.lower(cx, &mut case)?;
+    }
+    for (i, &ty) in joined.iter().enumerate() {
+        dst.push(
+            case.get(i)
+                .map_or_else(|| zero(ty), |value| widen(value, ty)),
+        );
+    }
+    Ok(())
+}
+
+/// Lifts the payload of a variant case from the joined representation.
+fn lift_case<T: Lift>(joined: &[Val], cx: &LiftContext<'_>) -> Result<T, ComponentError> {
+    let case = T::flat_types()
+        .into_iter()
+        .zip(joined)
+        .map(|(ty, value)| narrow(value, ty))
+        .collect::<Result<Vec<_>, _>>()?;
+    T::lift(cx, &mut case.iter())
+}
+
+fn take_joined(src: &mut slice::Iter<'_, Val>, len: usize) -> Result<Vec<Val>, ComponentError> {
+    (0..len).map(|_| next(src).map(Clone::clone)).collect()
+}
+
+/// The offset of the payload of a variant whose cases have the given
+/// alignment, relative to the variant itself.
+fn payload_offset(payload_align: usize) -> usize {
+    align_to(1, payload_align)
+}
+
+impl<T: ComponentType> ComponentType for Option<T> {
+    fn size() -> usize {
+        align_to(payload_offset(T::align()) + T::size(), Self::align())
+    }
+
+    fn align() -> usize {
+        max(1, T::align())
+    }
+
+    fn flatten(out: &mut Vec<ValType>) {
+        out.push(ValType::I32);
+        T::flatten(out);
+    }
+}
+
+impl<T: Lower> Lower for Option<T> {
+    fn lower(&self, cx: &mut LowerContext<'_>, dst: &mut Vec<Val>) -> Result<(), ComponentError> {
+        let joined = T::flat_types();
+        match self {
+            None => lower_case::<T>(0, None, &joined, cx, dst),
+            Some(value) => lower_case(1, Some(value), &joined, cx, dst),
+        }
+    }
+
+    fn store(&self, cx: &mut LowerContext<'_>, offset: usize) -> Result<(), ComponentError> {
+        match self {
+            None => 0u8.store(cx, offset),
+            Some(value) => {
+                1u8.store(cx, offset)?;
+                value.store(cx, offset + payload_offset(T::align()))
+            }
+        }
+    }
+}
+
+impl<T: Lift> Lift for Option<T> {
+    fn lift(cx: &LiftContext<'_>, src: &mut slice::Iter<'_, Val>) -> Result<Self, ComponentError> {
+        let discriminant = next_i32(src)? as u32;
+        let joined = take_joined(src, T::flat_types().len())?;
+        match discriminant {
+            0 => Ok(None),
+            1 => Ok(Some(lift_case(&joined, cx)?)),
+            other => Err(ComponentError::InvalidDiscriminant(other)),
+        }
+    }
+
+    fn load(cx: &LiftContext<'_>, offset: usize) -> Result<Self, ComponentError> {
+        match u8::load(cx, offset)? {
+            0 => Ok(None),
+            1 => Ok(Some(T::load(cx, offset + payload_offset(T::align()))?)),
+            other => Err(ComponentError::InvalidDiscriminant(other.into())),
+        }
+    }
+}
+
+impl<T: ComponentType, E: ComponentType> ComponentType for Result<T, E> {
+    fn size() -> usize {
+        let payload_size = max(T::size(), E::size());
+        align_to(
+            payload_offset(max(T::align(), E::align())) + payload_size,
+            Self::align(),
+        )
+    }
+
+    fn align() -> usize {
+        max(1, max(T::align(), E::align()))
+    }
+
+    fn flatten(out: &mut Vec<ValType>) {
+        out.push(ValType::I32);
+        out.extend(join(&T::flat_types(), &E::flat_types()));
+    }
+}
+
+impl<T: Lower, E: Lower> Lower for Result<T, E> {
+    fn lower(&self, cx: &mut LowerContext<'_>, dst: &mut Vec<Val>) -> Result<(), ComponentError> {
+        let joined = join(&T::flat_types(), &E::flat_types());
+        match self {
+            Ok(value) => lower_case(0, Some(value), &joined, cx, dst),
+            Err(error) => lower_case(1, Some(error), &joined, cx, dst),
+        }
+    }
+
+    fn store(&self, cx: &mut LowerContext<'_>, offset: usize) -> Result<(), ComponentError> {
+        let payload = offset + payload_offset(max(T::align(), E::align()));
+        match self {
+            Ok(value) => {
+                0u8.store(cx, offset)?;
+                value.store(cx, payload)
+            }
+            Err(error) => {
+                1u8.store(cx, offset)?;
+                error.store(cx, payload)
+            }
+        }
+    }
+}
+
+impl<T: Lift, E: Lift> Lift for Result<T, E> {
+    fn lift(cx: &LiftContext<'_>, src: &mut slice::Iter<'_, Val>) -> Result<Self, ComponentError> {
+        let discriminant = next_i32(src)? as u32;
+        let joined = take_joined(src, join(&T::flat_types(), &E::flat_types()).len())?;
+        match discriminant {
+            0 => Ok(Ok(lift_case(&joined, cx)?)),
+            1 => Ok(Err(lift_case(&joined, cx)?)),
+            other => Err(ComponentError::InvalidDiscriminant(other)),
+        }
+    }
+
+    fn load(cx: &LiftContext<'_>, offset: usize) -> Result<Self, ComponentError> {
+        let payload = offset + payload_offset(max(T::align(), E::align()));
+        match u8::load(cx, offset)? {
+            0 => Ok(Ok(T::load(cx, payload)?)),
+            1 => Ok(Err(E::load(cx, payload)?)),
+            other => Err(ComponentError::InvalidDiscriminant(other.into())),
+        }
+    }
+}
+
+macro_rules! tuples {
+    ( $( ( $( $t:ident $idx:tt ),* ) )* ) => {
+        $(
+            #[allow(unused_mut, unused_variables)]
+            impl<$( $t: ComponentType ),*> ComponentType for ( $( $t, )* ) {
+                fn size() -> usize {
+                    let mut size = 0;
+                    $( size = align_to(size, $t::align()) + $t::size(); )*
+                    align_to(size, Self::align())
+                }
+
+                fn align() -> usize {
+                    let mut align = 1;
+                    $( align = max(align, $t::align()); )*
+                    align
+                }
+
+                fn flatten(out: &mut Vec<ValType>) {
+                    $( $t::flatten(out); )*
+                }
+            }
+
+            #[allow(unused_mut, unused_variables, unused_assignments)]
+            impl<$( $t: Lower ),*> Lower for ( $( $t, )* ) {
+                fn lower(&self, cx: &mut LowerContext<'_>, dst: &mut Vec<Val>) -> Result<(), ComponentError> {
+                    $( self.$idx.lower(cx, dst)?; )*
+                    Ok(())
+                }
+
+                fn store(&self, cx: &mut LowerContext<'_>, offset: usize) -> Result<(), ComponentError> {
+                    let mut offset = offset;
+                    $(
+                        offset = align_to(offset, $t::align());
+                        self.$idx.store(cx, offset)?;
+                        offset += $t::size();
+                    )*
+                    Ok(())
+                }
+            }
+
+            #[allow(unused_mut, unused_variables, unused_assignments, clippy::unused_unit)]
+            impl<$( $t: Lift ),*> Lift for ( $( $t, )* ) {
+                fn lift(cx: &LiftContext<'_>, src: &mut slice::Iter<'_, Val>) -> Result<Self, ComponentError> {
+                    Ok(( $( $t::lift(cx, src)?, )* ))
+                }
+
+                fn load(cx: &LiftContext<'_>, offset: usize) -> Result<Self, ComponentError> {
+                    let mut offset = offset;
+                    Ok(( $(
+                        {
+                            offset = align_to(offset, $t::align());
+                            let value = $t::load(cx, offset)?;
+                            offset += $t::size();
+                            value
+                        },
+                    )* ))
+                }
+            }
+        )*
+    };
+}
+
+tuples! {
+    ()
+    (A1 0)
+    (A1 0, A2 1)
+    (A1 0, A2 1, A3 2)
+    (A1 0, A2 1, A3 2, A4 3)
+    (A1 0, A2 1, A3 2, A4 3, A5 4)
+    (A1 0, A2 1, A3 2, A4 3, A5 4, A6 5)
+    (A1 0, A2 1, A3 2, A4 3, A5 4, A6 5, A7 6)
+    (A1 0, A2 1, A3 2, A4 3, A5 4, A6 5, A7 6, A8 7)
+}
+
+/// Reads the `i32` the guest returned as the pointer to its results.
+pub(super) fn lift_pointer(values: &[Val]) -> Result<usize, ComponentError> {
+    let ptr = next_i32(&mut values.iter())? as u32 as usize;
+    Ok(ptr)
+}
@@ -0,0 +1,280 @@
+use crate::exports::{ExportError, Exports};
+use crate::externals::{Function, Global, Memory, Table};
+use crate::instance::Instance;
+use crate::native::NativeFunc;
+use crate::WasmTypeList;
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use wasmer_vm::HostEnvInitError;
+
+/// The environment of a host function.
+///
+/// `init_with_instance` is called by [`Instance::new`] with the instance
+/// importing the function, after the imports are linked but before the
+/// start function runs. This is where the environment gets hold of the
+/// guest exports it needs, such as its memory.
+///
+/// Rather than implementing this trait by hand, it can be derived, binding
+/// the [`LazyInit`] fields marked with `#[wasmer(export)]` to the instance
+/// exports:
+///
+/// ```
+/// # use wasmer::{LazyInit, Memory, NativeFunc, WasmerEnv};
+/// #[derive(WasmerEnv, Clone)]
+/// pub struct MyEnv {
+///     // Bound to the export named `memory`.
+///     #[wasmer(export)]
+///     memory: LazyInit<Memory>,
+///     // Bound to the export named `malloc`.
+///     #[wasmer(export(name = "malloc"))]
+///     allocate: LazyInit<NativeFunc<'static, u32, u32>>,
+///     // Left uninitialized if the export is missing.
+///     #[wasmer(export(optional = true))]
+///     free: LazyInit<NativeFunc<'static, u32>>,
+///     // Regular fields are left untouched.
+///     counter: u32,
+/// }
+/// ```
+///
+/// If a required export is missing, or has an unexpected type, the
+/// instantiation fails with an [`InstantiationError::HostEnvInitialization`].
+///
+/// [`InstantiationError::HostEnvInitialization`]: crate::InstantiationError::HostEnvInitialization
+pub trait WasmerEnv {
+    /// Initializes the environment with the instance importing the host
+    /// function.
+    ///
+    /// The same environment value may be shared by the functions of
+    /// several instances: implementations should leave already
+    /// initialized data untouched.
+    fn init_with_instance(&mut self, _instance: &Instance) -> Result<(), HostEnvInitError> {
+        Ok(())
+    }
+}
+
+macro_rules! impl_wasmer_env {
+    ( $( $ty:ty ),* ) => {
+        $( impl WasmerEnv for $ty {} )*
+    };
+}
+
+impl_wasmer_env!(
+    (),
+    bool,
+    char,
+    u8,
+    i8,
+    u16,
+    i16,
+    u32,
+    i32,
+    u64,
+    i64,
+    u128,
+    i128,
+    usize,
+    isize,
+    f32,
+    f64,
+    String,
+    std::sync::atomic::AtomicBool,
+    std::sync::atomic::AtomicI8,
+    std::sync::atomic::AtomicU8,
+    std::sync::atomic::AtomicI16,
+    std::sync::atomic::AtomicU16,
+    std::sync::atomic::AtomicI32,
+    std::sync::atomic::AtomicU32,
+    std::sync::atomic::AtomicI64,
+    std::sync::atomic::AtomicU64,
+    std::sync::atomic::AtomicIsize,
+    std::sync::atomic::AtomicUsize
+);
+
+impl<T> WasmerEnv for *mut T {}
+
+impl<T> WasmerEnv for *const T {}
+
+impl<T: WasmerEnv + ?Sized> WasmerEnv for Box<T> {
+    fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+        (**self).init_with_instance(instance)
+    }
+}
+
+impl<T: WasmerEnv + ?Sized> WasmerEnv for &'static mut T {
+    fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+        (**self).init_with_instance(instance)
+    }
+}
+
+impl<T: WasmerEnv> WasmerEnv for Arc<Mutex<T>> {
+    fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+        self.lock().unwrap().init_with_instance(instance)
+    }
+}
+
+// Atomics are commonly shared between a host function and its caller.
+macro_rules! impl_wasmer_env_for_shared_atomics {
+    ( $( $ty:ty ),* ) => {
+        $( impl WasmerEnv for Arc<$ty> {} )*
+    };
+}
+
+impl_wasmer_env_for_shared_atomics!(
+    std::sync::atomic::AtomicBool,
+    std::sync::atomic::AtomicI32,
+    std::sync::atomic::AtomicU32,
+    std::sync::atomic::AtomicI64,
+    std::sync::atomic::AtomicU64,
+    std::sync::atomic::AtomicIsize,
+    std::sync::atomic::AtomicUsize
+);
+
+/// An export that can be bound lazily by a [`LazyInit`].
+pub trait LazyExport: Sized {
+    /// Gets the export named `name`.
+    fn get_export(exports: &Exports, name: &str) -> Result<Self, ExportError>;
+}
+
+macro_rules! impl_lazy_export {
+    ( $( $ty:ty ),* ) => {
+        $(
+            impl LazyExport for $ty {
+                fn get_export(exports: &Exports, name: &str) -> Result<Self, ExportError> {
+                    exports.get::<Self>(name).map(Clone::clone)
+                }
+            }
+        )*
+    };
+}
+
+impl_lazy_export!(Function, Memory, Global, Table);
+
+impl<Args, Rets> LazyExport for NativeFunc<'static, Args, Rets>
+where
+    Args: WasmTypeList,
+    Rets: WasmTypeList,
+{
+    fn get_export(exports: &Exports, name: &str) -> Result<Self, ExportError> {
+        exports
+            .get_function(name)?
+            .native()
+            .map_err(|_| ExportError::IncompatibleType)
+    }
+}
+
+struct LazyCell<T> {
+    initialized: AtomicBool,
+    value: UnsafeCell<Option<T>>,
+    mutate_lock: Mutex<()>,
+}
+
+/// The value is only written once, under `mutate_lock`, before
+/// `initialized` is set. It is never mutated afterwards.
+unsafe impl<T: Send> Send for LazyCell<T> {}
+unsafe impl<T: Send + Sync> Sync for LazyCell<T> {}
+
+/// A value set after the environment holding it is created, typically an
+/// export of the instance importing the host function.
+///
+/// Clones share the same value: initializing one of them initializes them
+/// all, so the environment kept by the host sees the exports bound by
+/// [`Instance::new`] too.
+pub struct LazyInit<T> {
+    cell: Arc<LazyCell<T>>,
+}
+
+impl<T> LazyInit<T> {
+    /// Creates a new uninitialized `LazyInit`.
+    pub fn new() -> Self {
+        Self {
+            cell: Arc::new(LazyCell {
+                initialized: AtomicBool::new(false),
+                value: UnsafeCell::new(None),
+                mutate_lock: Mutex::new(()),
+            }),
+        }
+    }
+
+    /// Returns the value, or `None` if it hasn't been initialized yet.
+    pub fn get_ref(&self) -> Option<&T> {
+        if self.cell.initialized.load(Ordering::Acquire) {
+            unsafe { (*self.cell.value.get()).as_ref() }
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether the value has been initialized.
+    pub fn is_initialized(&self) -> bool {
+        self.cell.initialized.load(Ordering::Acquire)
+    }
+
+    /// Sets the value.
+    ///
+    /// Returns `false`, leaving the value untouched, if it was already
+    /// initialized.
+    pub fn initialize(&self, value: T) -> bool {
+        let _guard = self.cell.mutate_lock.lock().unwrap();
+        if self.cell.initialized.load(Ordering::Acquire) {
+            return false;
+        }
+        unsafe {
+            *self.cell.value.get() = Some(value);
+        }
+        self.cell.initialized.store(true, Ordering::Release);
+        true
+    }
+}
+
+impl<T: LazyExport> LazyInit<T> {
+    /// Initializes the value with the export named `name`, unless it is
+    /// already initialized.
+    pub fn bind(&self, exports: &Exports, name: &str) -> Result<(), HostEnvInitError> {
+        if self.is_initialized() {
+            return Ok(());
+        }
+        match T::get_export(exports, name) {
+            Ok(value) => {
+                self.initialize(value);
+                Ok(())
+            }
+            Err(ExportError::Missing(_)) => Err(HostEnvInitError::MissingExport(name.to_string())),
+            Err(ExportError::IncompatibleType) => {
+                Err(HostEnvInitError::IncompatibleExport(name.to_string()))
+            }
+        }
+    }
+
+    /// Like [`LazyInit::bind`], but leaves the value uninitialized if the
+    /// export is missing.
+    pub fn bind_optional(&self, exports: &Exports, name: &str) -> Result<(), HostEnvInitError> {
+        match self.bind(exports, name) {
+            Err(HostEnvInitError::MissingExport(_)) => Ok(()),
+            result => result,
+        }
+    }
+}
+
+impl<T> Clone for LazyInit<T> {
+    fn clone(&self) -> Self {
+        Self {
+            cell: self.cell.clone(),
+        }
+    }
+}
+
+impl<T> Default for LazyInit<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for LazyInit<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LazyInit")
+            .field("data", &self.get_ref())
+            .finish()
+    }
+}
@@ -1,5 +1,7 @@
+use crate::env::WasmerEnv;
 use crate::exports::{ExportError, Exportable};
 use crate::externals::Extern;
+use crate::instance::Instance;
 use crate::store::Store;
 use crate::types::Val;
 use crate::FunctionType;
@@ -8,11 +10,12 @@ use crate::RuntimeError;
 pub use inner::{FromToNativeWasmType, HostFunction, WasmTypeList, WithEnv, WithoutEnv};
 use std::cell::RefCell;
 use std::cmp::max;
+use std::ffi::c_void;
 use std::fmt;
 use wasmer_vm::{
-    raise_user_trap, resume_panic, wasmer_call_trampoline, Export, ExportFunction,
-    VMCallerCheckedAnyfunc, VMContext, VMDynamicFunctionContext, VMFunctionBody, VMFunctionKind,
-    VMTrampoline,
+    catch_traps, raise_user_trap, resume_panic, wasmer_call_trampoline, Export, ExportFunction,
+    HostEnvInitError, VMCallerCheckedAnyfunc, VMContext, VMDynamicFunctionContext, VMFunctionBody,
+    VMFunctionKind, VMTrampoline,
 };
 
 /// A function defined in the Wasm module
@@ -95,6 +98,8 @@ impl Function {
                 kind: VMFunctionKind::Dynamic,
                 vmctx,
                 signature: ty.clone(),
+                host_env_init: None,
+                instance_ref: None,
             },
         }
     }
@@ -104,9 +109,10 @@ impl Function {
     /// # Example
     ///
     /// ```
-    /// # use wasmer::{Function, FunctionType, Type, Store, Value};
+    /// # use wasmer::{Function, FunctionType, Type, Store, Value, WasmerEnv};
     /// # let store = Store::default();
     ///
+    /// #[derive(WasmerEnv)]
     /// struct Env {
     ///   multiplier: i32,
     /// };
@@ -123,7 +129,7 @@ impl Function {
     pub fn new_with_env<F, Env>(store: &Store, ty: &FunctionType, env: Env, func: F) -> Self
     where
         F: Fn(&mut Env, &[Val]) -> Result<Vec<Val>, RuntimeError> + 'static,
-        Env: Sized + 'static,
+        Env: WasmerEnv + Sized + 'static,
     {
         let dynamic_ctx = VMDynamicFunctionContext::from_context(VMDynamicFunctionWithEnv {
             env: RefCell::new(env),
@@ -144,6 +150,8 @@ impl Function {
                 kind: VMFunctionKind::Dynamic,
                 vmctx,
                 signature: ty.clone(),
+                host_env_init: Some(init_dynamic_env::<Env>),
+                instance_ref: None,
             },
         }
     }
@@ -185,6 +193,8 @@ impl Function {
                 vmctx,
                 signature,
                 kind: VMFunctionKind::Static,
+                host_env_init: None,
+                instance_ref: None,
             },
         }
     }
@@ -197,9 +207,10 @@ impl Function {
     /// # Example
     ///
     /// ```
-    /// # use wasmer::{Store, Function};
+    /// # use wasmer::{Store, Function, WasmerEnv};
     /// # let store = Store::default();
     ///
+    /// #[derive(WasmerEnv)]
     /// struct Env {
     ///   multiplier: i32,
     /// };
@@ -216,7 +227,7 @@ impl Function {
         F: HostFunction<Args, Rets, WithEnv, Env>,
         Args: WasmTypeList,
         Rets: WasmTypeList,
-        Env: Sized + 'static,
+        Env: WasmerEnv + Sized + 'static,
     {
         let function = inner::Function::<Args, Rets>::new(func);
         let address = function.address();
@@ -238,6 +249,8 @@ impl Function {
                 kind: VMFunctionKind::Static,
                 vmctx,
                 signature,
+                host_env_init: Some(init_native_env::<Env>),
+                instance_ref: None,
             },
         }
     }
@@ -297,14 +310,26 @@ impl Function {
             }
         }
 
-        // Call the trampoline.
+        // Call the trampoline. Dynamic host functions have no address
+        // until they are linked, so their wrapper is called directly.
         if let Err(error) = unsafe {
-            wasmer_call_trampoline(
-                self.exported.vmctx,
-                func.trampoline,
-                self.exported.address,
-                values_vec.as_mut_ptr() as *mut u8,
-            )
+            match self.exported.kind {
+                VMFunctionKind::Static => wasmer_call_trampoline(
+                    self.exported.vmctx,
+                    func.trampoline,
+                    self.exported.address,
+                    values_vec.as_mut_ptr() as *mut u8,
+                ),
+                VMFunctionKind::Dynamic => {
+                    let vmctx = self.exported.vmctx;
+                    // The wrapper is the first field of the
+                    // `VMDynamicFunctionContext`.
+                    let address = *(vmctx as *const *const VMFunctionBody);
+                    let func_wrapper: unsafe fn(*mut VMContext, *mut i128) =
+                        std::mem::transmute(address);
+                    catch_traps(vmctx, || func_wrapper(vmctx, values_vec.as_mut_ptr()))
+                }
+            }
         } {
             return Err(RuntimeError::from_trap(error));
         }
@@ -415,6 +440,8 @@ impl Function {
             self.exported.address,
             self.exported.vmctx,
             self.exported.kind,
+            self.exported.host_env_init,
+            self.exported.instance_ref.clone(),
             self.definition.clone(),
         ))
     }
@@ -442,6 +469,29 @@ impl fmt::Debug for Function {
     }
 }
 
+/// Initializes the environment of a host function created with
+/// [`Function::new_native_with_env`], whose `vmctx` is the boxed environment.
+unsafe fn init_native_env<Env: WasmerEnv>(
+    vmctx: *mut VMContext,
+    instance: *const c_void,
+) -> Result<(), HostEnvInitError> {
+    let env = &mut *(vmctx as *mut Env);
+    env.init_with_instance(&*(instance as *const Instance))
+}
+
+/// Initializes the environment of a host function created with
+/// [`Function::new_with_env`], whose `vmctx` is the dynamic function context.
+unsafe fn init_dynamic_env<Env: WasmerEnv + 'static>(
+    vmctx: *mut VMContext,
+    instance: *const c_void,
+) -> Result<(), HostEnvInitError> {
+    let ctx = &mut *(vmctx as *mut VMDynamicFunctionContext<VMDynamicFunctionWithEnv<Env>>);
+    ctx.ctx
+        .env
+        .get_mut()
+        .init_with_instance(&*(instance as *const Instance))
+}
+
 /// This trait is one that all dynamic functions must fulfill.
 pub(crate) trait VMDynamicFunction {
     fn call(&self, args: &[Val]) -> Result<Vec<Val>, RuntimeError>;
@@ -573,10 +623,9 @@ mod inner {
 
         /// Convert a value of kind `Self::Native` to `Self`.
         ///
-        /// # Panics
-        ///
-        /// This method panics if `native` cannot fit in the `Self`
-        /// type`.
+        /// The Wasm integers have no sign, so their bits are
+        /// reinterpreted, and truncated if `Self` is narrower. This
+        /// never panics, as `native` can come from an untrusted module.
         fn from_native(native: Self::Native) -> Self;
 
         /// Convert self to `Self::Native`.
@@ -596,14 +645,9 @@ mod inner {
                     type Native = $native_type;
 
                     #[inline]
+                    #[allow(trivial_numeric_casts)]
                     fn from_native(native: Self::Native) -> Self {
-                        native.try_into().expect(concat!(
-                            "out of range type conversion attempt (tried to convert `",
-                            stringify!($native_type),
-                            "` to `",
-                            stringify!($type),
-                            "`)",
-                        ))
+                        native as Self
                     }
 
                     #[inline]
@@ -643,6 +687,14 @@ mod inner {
             assert_eq!(7i8.to_native(), 7i32);
         }
 
+        #[test]
+        fn test_from_native() {
+            assert_eq!(u32::from_native(-1), u32::MAX);
+            assert_eq!(u64::from_native(i64::MIN), 1 << 63);
+            assert_eq!(u8::from_native(0x1ff), 0xff);
+            assert_eq!(i16::from_native(-1), -1);
+        }
+
         #[test]
         #[should_panic(
             expected = "out of range type conversion attempt (tried to convert `u32` to `i32`)"
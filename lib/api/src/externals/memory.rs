@@ -3,11 +3,33 @@ use crate::externals::Extern;
 use crate::store::Store;
 use crate::{MemoryType, MemoryView};
 use std::convert::TryInto;
+use std::mem::{self, MaybeUninit};
+use std::ptr;
 use std::slice;
 use std::sync::Arc;
+use thiserror::Error;
 use wasmer_types::{Pages, ValueType};
 use wasmer_vm::{Export, ExportMemory, Memory as RuntimeMemory, MemoryError};
 
+/// An error while accessing a [`Memory`] from the host.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryAccessError {
+    /// The access is outside of the memory.
+    #[error("out of bounds memory access at offset {offset:#x} with length {len}")]
+    HeapOutOfBounds {
+        /// The offset of the access.
+        offset: u64,
+        /// The length of the access, in bytes.
+        len: u64,
+    },
+    /// The end of the access can't be represented.
+    #[error("address calculation overflow")]
+    Overflow,
+    /// The bytes read as a string are not valid UTF-8.
+    #[error("string is not valid UTF-8")]
+    NonUtf8String,
+}
+
 /// A WebAssembly `memory` instance.
 ///
 /// A memory instance is the runtime representation of a linear memory.
@@ -102,6 +124,12 @@ impl Memory {
     ///
     /// Returns an error if memory can't be grown by the specified amount
     /// of pages.
+    ///
+    /// In deterministic mode, this still returns the failures specific to
+    /// the host, such as the allocation of the pages, rather than trapping
+    /// like `memory.grow` does: only `MemoryError::CouldNotGrow` is
+    /// deterministic, and the host must handle the other errors
+    /// deterministically, for example by aborting the execution.
     pub fn grow<IntoPages>(&self, delta: IntoPages) -> Result<Pages, MemoryError>
     where
         IntoPages: Into<Pages>,
@@ -148,6 +176,94 @@ impl Memory {
         unsafe { MemoryView::new(base as _, length as u32) }
     }
 
+    /// Copies `buf.len()` bytes at `offset` into `buf`.
+    ///
+    /// The copy is a single `memcpy`. It's sound even if the memory is
+    /// concurrently grown, though bytes concurrently written by another
+    /// thread may be read torn.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bytes are not all in the memory.
+    pub fn read(&self, offset: u64, buf: &mut [u8]) -> Result<(), MemoryAccessError> {
+        let len = buf.len();
+        let dst = buf.as_mut_ptr();
+        self.with_range(offset, len, |src| unsafe {
+            ptr::copy_nonoverlapping(src, dst, len)
+        })
+    }
+
+    /// Copies `data` at `offset`.
+    ///
+    /// The copy is a single `memcpy`. It's sound even if the memory is
+    /// concurrently grown.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bytes are not all in the memory.
+    pub fn write(&self, offset: u64, data: &[u8]) -> Result<(), MemoryAccessError> {
+        let len = data.len();
+        let src = data.as_ptr();
+        self.with_range(offset, len, |dst| unsafe {
+            ptr::copy_nonoverlapping(src, dst, len)
+        })
+    }
+
+    /// Reads a `T` at `offset`, which doesn't need to be aligned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value is not all in the memory.
+    pub fn read_obj<T: ValueType>(&self, offset: u64) -> Result<T, MemoryAccessError> {
+        let mut value = MaybeUninit::<T>::uninit();
+        let dst = value.as_mut_ptr() as *mut u8;
+        self.with_range(offset, mem::size_of::<T>(), |src| unsafe {
+            ptr::copy_nonoverlapping(src, dst, mem::size_of::<T>())
+        })?;
+        // All the bytes are initialized, and any bit pattern is a valid
+        // `ValueType`.
+        Ok(unsafe { value.assume_init() })
+    }
+
+    /// Writes `value` at `offset`, which doesn't need to be aligned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value is not all in the memory.
+    pub fn write_obj<T: ValueType>(&self, offset: u64, value: T) -> Result<(), MemoryAccessError> {
+        let src = &value as *const T as *const u8;
+        self.with_range(offset, mem::size_of::<T>(), |dst| unsafe {
+            ptr::copy_nonoverlapping(src, dst, mem::size_of::<T>())
+        })
+    }
+
+    /// Calls `f` with the pointer to the `len` bytes at `offset`, after
+    /// checking they are in the memory. The memory can't be moved by a
+    /// concurrent grow until `f` returns.
+    pub(crate) fn with_range(
+        &self,
+        offset: u64,
+        len: usize,
+        f: impl FnOnce(*mut u8),
+    ) -> Result<(), MemoryAccessError> {
+        let end = offset
+            .checked_add(len as u64)
+            .ok_or(MemoryAccessError::Overflow)?;
+        let mut f = Some(f);
+        let mut result = Ok(());
+        self.memory.with_data(&mut |base, length| {
+            if end > length as u64 {
+                result = Err(MemoryAccessError::HeapOutOfBounds {
+                    offset,
+                    len: len as u64,
+                });
+            } else if let Some(f) = f.take() {
+                f(unsafe { base.add(offset as usize) });
+            }
+        });
+        result
+    }
+
     pub(crate) fn from_export(store: &Store, wasmer_export: ExportMemory) -> Memory {
         Memory {
             store: store.clone(),
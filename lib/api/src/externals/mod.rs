@@ -7,7 +7,7 @@ pub use self::function::{
     FromToNativeWasmType, Function, HostFunction, WasmTypeList, WithEnv, WithoutEnv,
 };
 pub use self::global::Global;
-pub use self::memory::Memory;
+pub use self::memory::{Memory, MemoryAccessError};
 pub use self::table::Table;
 
 use crate::exports::{ExportError, Exportable};
@@ -5,7 +5,10 @@ use crate::types::{Val, ValFuncRef};
 use crate::RuntimeError;
 use crate::TableType;
 use std::sync::Arc;
-use wasmer_vm::{Export, ExportTable, Table as RuntimeTable, VMCallerCheckedAnyfunc};
+use wasmer_vm::{
+    collect_table_cycles, Export, ExportTable, InstanceRef, Table as RuntimeTable,
+    VMCallerCheckedAnyfunc,
+};
 
 /// A WebAssembly `table` instance.
 ///
@@ -20,6 +23,7 @@ use wasmer_vm::{Export, ExportTable, Table as RuntimeTable, VMCallerCheckedAnyfu
 pub struct Table {
     store: Store,
     table: Arc<dyn RuntimeTable>,
+    instance_ref: Option<InstanceRef>,
 }
 
 fn set_table_item(
@@ -51,10 +55,13 @@ impl Table {
             set_table_item(table.as_ref(), i, item.clone())?;
         }
 
-        Ok(Table {
+        let table = Table {
             store: store.clone(),
             table,
-        })
+            instance_ref: None,
+        };
+        table.keep_alive(&init);
+        Ok(table)
     }
 
     /// Returns the [`TableType`] of the `Table`.
@@ -70,13 +77,26 @@ impl Table {
     /// Retrieves an element of the table at the provided `index`.
     pub fn get(&self, index: u32) -> Option<Val> {
         let item = self.table.get(index)?;
-        Some(ValFuncRef::from_checked_anyfunc(item, &self.store))
+        let mut val = ValFuncRef::from_checked_anyfunc(item, &self.store);
+        // The functions of the table are kept alive by the instance
+        // exporting it, or by the instances kept alive by the table.
+        if let Val::FuncRef(f) = &mut val {
+            let vmctx = f.exported.vmctx;
+            f.exported.instance_ref = self.instance_ref.clone().or_else(|| {
+                self.table
+                    .instances()
+                    .find(|instance| instance.keeps_alive(vmctx))
+            });
+        }
+        Some(val)
     }
 
     /// Sets an element `val` in the Table at the provided `index`.
     pub fn set(&self, index: u32, val: Val) -> Result<(), RuntimeError> {
         let item = val.into_checked_anyfunc(&self.store)?;
-        set_table_item(self.table.as_ref(), index, item)
+        set_table_item(self.table.as_ref(), index, item)?;
+        self.keep_alive(&val);
+        Ok(())
     }
 
     /// Retrieves the size of the `Table` (in elements)
@@ -100,6 +120,7 @@ impl Table {
                 for i in 0..delta {
                     set_table_item(self.table.as_ref(), len + i, item.clone())?;
                 }
+                self.keep_alive(&init);
                 Ok(len)
             }
             None => Err(RuntimeError::new(format!(
@@ -136,13 +157,34 @@ impl Table {
             len,
         )
         .map_err(RuntimeError::from_trap)?;
+        // The copied functions are kept alive by the destination table, as
+        // in `Table::set`.
+        if src_table.instance_ref != dst_table.instance_ref {
+            if let Some(instance_ref) = &src_table.instance_ref {
+                dst_table.table.keep_alive(instance_ref.clone());
+            }
+        }
         Ok(())
     }
 
+    /// Keeps the instance of the function `val` alive as long as this
+    /// table once it's stored in it, unless it's the instance exporting
+    /// the table.
+    fn keep_alive(&self, val: &Val) {
+        if let Val::FuncRef(f) = val {
+            if f.exported.instance_ref != self.instance_ref {
+                if let Some(instance_ref) = &f.exported.instance_ref {
+                    self.table.keep_alive(instance_ref.clone());
+                }
+            }
+        }
+    }
+
     pub(crate) fn from_export(store: &Store, wasmer_export: ExportTable) -> Table {
         Table {
             store: store.clone(),
             table: wasmer_export.from,
+            instance_ref: wasmer_export.instance_ref,
         }
     }
 
@@ -152,10 +194,19 @@ impl Table {
     }
 }
 
+impl Drop for Table {
+    fn drop(&mut self) {
+        // The table may be left in a cycle with the instances it keeps
+        // alive.
+        collect_table_cycles(&self.table);
+    }
+}
+
 impl<'a> Exportable<'a> for Table {
     fn to_export(&self) -> Export {
         ExportTable {
             from: self.table.clone(),
+            instance_ref: self.instance_ref.clone(),
         }
         .into()
     }
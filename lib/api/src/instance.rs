@@ -3,6 +3,7 @@ use crate::externals::Extern;
 use crate::module::Module;
 use crate::store::Store;
 use crate::InstantiationError;
+use std::ffi::c_void;
 use std::fmt;
 use wasmer_engine::Resolver;
 use wasmer_vm::InstanceHandle;
@@ -70,6 +71,11 @@ impl Instance {
     /// Those are, as defined by the spec:
     ///  * Link errors that happen when plugging the imports into the instance
     ///  * Runtime errors that happen when running the module `start` function.
+    ///
+    /// It can also fail when the environment of an imported host function
+    /// can't be initialized, see [`WasmerEnv`].
+    ///
+    /// [`WasmerEnv`]: crate::WasmerEnv
     pub fn new(module: &Module, resolver: &dyn Resolver) -> Result<Instance, InstantiationError> {
         let store = module.store();
 
@@ -85,11 +91,23 @@ impl Instance {
             })
             .collect::<Exports>();
 
-        Ok(Instance {
+        let instance = Instance {
             handle,
             module: module.clone(),
             exports,
-        })
+        };
+
+        // The host function environments are initialized before the start
+        // function runs, so that it can already call into them.
+        unsafe {
+            instance
+                .handle
+                .initialize_host_envs(&instance as *const Instance as *const c_void)
+                .map_err(InstantiationError::HostEnvInitialization)?;
+        }
+        module.finish_instantiation(&instance.handle)?;
+
+        Ok(instance)
     }
 
     /// Gets the [`Module`] associated with this instance.
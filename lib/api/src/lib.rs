@@ -24,14 +24,19 @@
     )
 )]
 
+mod call_policy;
+pub mod component;
+mod env;
 mod exports;
 mod externals;
 mod import_object;
 mod instance;
+mod linker;
 mod module;
 mod native;
 mod ptr;
 mod store;
+mod stub_resolver;
 mod tunables;
 mod types;
 mod utils;
@@ -45,16 +50,23 @@ pub mod internals {
     pub use crate::externals::{WithEnv, WithoutEnv};
 }
 
+pub use crate::call_policy::{CallCounters, CallPolicy, CallPolicyError};
+pub use crate::env::{LazyExport, LazyInit, WasmerEnv};
 pub use crate::exports::{ExportError, Exportable, Exports, ExportsIterator};
 pub use crate::externals::{
-    Extern, FromToNativeWasmType, Function, Global, HostFunction, Memory, Table, WasmTypeList,
+    Extern, FromToNativeWasmType, Function, Global, HostFunction, Memory, MemoryAccessError, Table,
+    WasmTypeList,
 };
 pub use crate::import_object::{ImportObject, ImportObjectIterator, LikeNamespace};
 pub use crate::instance::Instance;
+pub use crate::linker::{Linker, LinkerError};
 pub use crate::module::Module;
 pub use crate::native::NativeFunc;
 pub use crate::ptr::{Array, Item, WasmPtr};
-pub use crate::store::{Store, StoreObject};
+pub use crate::store::{CompilationThreadPool, Store, StoreObject};
+pub use crate::stub_resolver::StubResolver;
+#[cfg(unix)]
+pub use crate::tunables::PoolingTunables;
 pub use crate::tunables::Tunables;
 pub use crate::types::{
     ExportType, ExternRef, ExternType, FunctionType, GlobalType, HostInfo, HostRef, ImportType,
@@ -69,15 +81,19 @@ pub use wasmer_compiler::{
     MiddlewareReaderState,
 };
 pub use wasmer_compiler::{CpuFeature, Features, Target};
+pub use wasmer_derive::WasmerEnv;
 pub use wasmer_engine::{
-    ChainableNamedResolver, DeserializeError, Engine, InstantiationError, LinkError, NamedResolver,
+    Artifact, ChainableNamedResolver, CompilationStats, DeserializeError, Engine, FrameInfo,
+    FrameSymbol, ImportDiagnostic, InstantiationError, LinkError, NamedResolver,
     NamedResolverChain, Resolver, RuntimeError, SerializeError,
 };
 pub use wasmer_types::{
     Atomically, Bytes, GlobalInit, LocalFunctionIndex, MemoryView, Pages, ValueType,
     WASM_MAX_PAGES, WASM_MIN_PAGES, WASM_PAGE_SIZE,
 };
-pub use wasmer_vm::{raise_user_trap, Export, MemoryError};
+pub use wasmer_vm::{raise_user_trap, Export, HostEnvInitError, MemoryError};
+#[cfg(unix)]
+pub use wasmer_vm::{PoolingAllocator, PoolingLimits};
 #[cfg(feature = "wat")]
 pub use wat::parse_bytes as wat2wasm;
 
@@ -113,6 +129,9 @@ pub use wasmer_compiler_llvm::LLVM;
 #[cfg(feature = "jit")]
 pub use wasmer_engine_jit::{JITArtifact, JITEngine, JIT};
 
+#[cfg(all(feature = "jit", feature = "compiler"))]
+pub use wasmer_engine_jit::TieringPolicy;
+
 #[cfg(feature = "native")]
 pub use wasmer_engine_native::{Native, NativeArtifact, NativeEngine};
 
@@ -0,0 +1,292 @@
+//! The `Linker` links instances and modules together by name.
+
+use crate::exports::Exportable;
+use crate::externals::Extern;
+use crate::instance::Instance;
+use crate::module::Module;
+use crate::store::{Store, StoreObject};
+use crate::InstantiationError;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use thiserror::Error;
+use wasmer_engine::{ImportDiagnostic, LinkError, NamedResolver};
+use wasmer_vm::Export;
+
+/// An error while defining items in a [`Linker`], or while instantiating a
+/// module with it.
+#[derive(Error, Debug)]
+pub enum LinkerError {
+    /// An item is defined twice, and shadowing isn't allowed.
+    #[error("{module}.{name} is already defined")]
+    Redefinition {
+        /// The module name of the item.
+        module: String,
+        /// The field name of the item.
+        name: String,
+    },
+    /// A namespace given to [`Linker::define_module`] is already in use,
+    /// and shadowing isn't allowed.
+    #[error("the namespace {0} is already defined")]
+    NamespaceRedefinition(String),
+    /// An item belongs to another store than the linker.
+    #[error("{module}.{name} belongs to another store")]
+    StoreMismatch {
+        /// The module name of the item.
+        module: String,
+        /// The field name of the item.
+        name: String,
+    },
+    /// The module defining a namespace imports from itself, directly or
+    /// not.
+    #[error("the module defining the namespace {0} depends on itself")]
+    Cycle(String),
+    /// Some imports of a module are missing or have the wrong type.
+    #[error("unresolved imports:{}", DisplayDiagnostics(.0))]
+    UnresolvedImports(Vec<ImportDiagnostic>),
+    /// The instantiation of a module failed.
+    #[error(transparent)]
+    Instantiation(#[from] InstantiationError),
+}
+
+struct DisplayDiagnostics<'a>(&'a [ImportDiagnostic]);
+
+impl fmt::Display for DisplayDiagnostics<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for diagnostic in self.0 {
+            write!(f, "\n  {}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+/// Links instances and modules together by name.
+///
+/// The items of a `Linker` are resolved in order:
+///  * the items given to [`Linker::define`], and the exports of the
+///    instances given to [`Linker::define_instance`];
+///  * the exports of the modules given to [`Linker::define_module`], which
+///    are instantiated with the linker the first time a module imports from
+///    them;
+///  * the fallback resolver given to [`Linker::define_fallback`].
+///
+/// Defining an item twice is an error, unless shadowing is allowed with
+/// [`Linker::allow_shadowing`].
+///
+/// # Usage:
+/// ```
+/// # use wasmer::{Instance, Linker, Module, Store};
+/// # fn main() -> anyhow::Result<()> {
+/// let store = Store::default();
+/// let a = Module::new(&store, r#"(module (func (export "f") (result i32) i32.const 1))"#)?;
+/// let b = Module::new(&store, r#"(module (import "a" "f" (func (result i32))))"#)?;
+///
+/// let mut linker = Linker::new(&store);
+/// linker.define_module("a", &a)?;
+/// let instance = linker.instantiate(&b)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Linker {
+    store: Store,
+    allow_shadowing: bool,
+    definitions: HashMap<(String, String), Extern>,
+    modules: HashMap<String, Module>,
+    instantiating: HashSet<String>,
+    fallback: Option<Arc<dyn NamedResolver>>,
+}
+
+impl Linker {
+    /// Creates an empty `Linker` for the modules of `store`.
+    pub fn new(store: &Store) -> Self {
+        Self {
+            store: store.clone(),
+            allow_shadowing: false,
+            definitions: HashMap::new(),
+            modules: HashMap::new(),
+            instantiating: HashSet::new(),
+            fallback: None,
+        }
+    }
+
+    /// Returns the [`Store`] of the `Linker`.
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// Configures whether defining an item again replaces the previous
+    /// definition, rather than failing.
+    ///
+    /// This is `false` by default.
+    pub fn allow_shadowing(&mut self, allow: bool) -> &mut Self {
+        self.allow_shadowing = allow;
+        self
+    }
+
+    /// Defines `item` as `module`.`name`.
+    pub fn define(
+        &mut self,
+        module: &str,
+        name: &str,
+        item: impl Into<Extern>,
+    ) -> Result<&mut Self, LinkerError> {
+        let item = item.into();
+        if !item.comes_from_same_store(&self.store) {
+            return Err(LinkerError::StoreMismatch {
+                module: module.to_string(),
+                name: name.to_string(),
+            });
+        }
+        if !self.allow_shadowing && self.is_defined(module, name) {
+            return Err(LinkerError::Redefinition {
+                module: module.to_string(),
+                name: name.to_string(),
+            });
+        }
+        self.definitions
+            .insert((module.to_string(), name.to_string()), item);
+        Ok(self)
+    }
+
+    /// Defines all the exports of `instance` in the namespace `name`.
+    ///
+    /// Nothing is defined if one of the exports is already defined.
+    pub fn define_instance(
+        &mut self,
+        name: &str,
+        instance: &Instance,
+    ) -> Result<&mut Self, LinkerError> {
+        if !self.allow_shadowing {
+            if let Some((field, _)) = instance
+                .exports
+                .iter()
+                .find(|(field, _)| self.is_defined(name, field))
+            {
+                return Err(LinkerError::Redefinition {
+                    module: name.to_string(),
+                    name: field.to_string(),
+                });
+            }
+        }
+        for (field, item) in instance.exports.iter() {
+            self.define(name, field, item.clone())?;
+        }
+        Ok(self)
+    }
+
+    /// Defines the exports of `module` in the namespace `name`.
+    ///
+    /// The module is instantiated with this linker the first time another
+    /// module imports from `name`. When shadowing is allowed, it replaces
+    /// all the previous definitions of the namespace.
+    pub fn define_module(&mut self, name: &str, module: &Module) -> Result<&mut Self, LinkerError> {
+        let in_use = self.modules.contains_key(name)
+            || self
+                .definitions
+                .keys()
+                .any(|(namespace, _)| namespace == name);
+        if in_use {
+            if !self.allow_shadowing {
+                return Err(LinkerError::NamespaceRedefinition(name.to_string()));
+            }
+            self.definitions
+                .retain(|(namespace, _), _| namespace != name);
+        }
+        self.modules.insert(name.to_string(), module.clone());
+        Ok(self)
+    }
+
+    /// Defines a resolver for the imports that are not defined otherwise,
+    /// for example an [`ImportObject`].
+    ///
+    /// [`ImportObject`]: crate::ImportObject
+    pub fn define_fallback(&mut self, resolver: impl NamedResolver + 'static) -> &mut Self {
+        self.fallback = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Returns the item defined as `module`.`name`.
+    ///
+    /// The modules given to [`Linker::define_module`] are not instantiated
+    /// by this method, so their exports are only returned once instantiated.
+    pub fn get(&self, module: &str, name: &str) -> Option<Extern> {
+        if let Some(item) = self
+            .definitions
+            .get(&(module.to_string(), name.to_string()))
+        {
+            return Some(item.clone());
+        }
+        if self.modules.contains_key(module) {
+            return None;
+        }
+        self.fallback
+            .as_ref()?
+            .resolve_by_name(module, name)
+            .map(|export| Extern::from_export(&self.store, export))
+    }
+
+    /// Instantiates `module`, with its imports resolved by the linker.
+    ///
+    /// The modules it imports from are instantiated first. All the imports
+    /// that can't be resolved are reported at once, with
+    /// [`LinkerError::UnresolvedImports`].
+    pub fn instantiate(&mut self, module: &Module) -> Result<Instance, LinkerError> {
+        self.instantiate_dependencies(module)?;
+        Instance::new(module, &*self).map_err(|error| match error {
+            InstantiationError::Link(LinkError::Imports(imports)) => {
+                LinkerError::UnresolvedImports(imports)
+            }
+            error => LinkerError::Instantiation(error),
+        })
+    }
+
+    /// Instantiates the modules given to [`Linker::define_module`] that
+    /// `module` imports from, and defines their exports.
+    fn instantiate_dependencies(&mut self, module: &Module) -> Result<(), LinkerError> {
+        let namespaces = module
+            .imports()
+            .map(|import| import.module().to_string())
+            .filter(|namespace| self.modules.contains_key(namespace))
+            .collect::<HashSet<_>>();
+        for namespace in namespaces {
+            if !self.instantiating.insert(namespace.clone()) {
+                return Err(LinkerError::Cycle(namespace));
+            }
+            let dependency = self.modules[&namespace].clone();
+            let result = self.instantiate(&dependency);
+            self.instantiating.remove(&namespace);
+            let instance = result?;
+
+            self.modules.remove(&namespace);
+            for (field, item) in instance.exports.iter() {
+                self.definitions
+                    .insert((namespace.clone(), field.to_string()), item.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn is_defined(&self, module: &str, name: &str) -> bool {
+        self.modules.contains_key(module)
+            || self
+                .definitions
+                .contains_key(&(module.to_string(), name.to_string()))
+    }
+}
+
+impl NamedResolver for Linker {
+    fn resolve_by_name(&self, module: &str, name: &str) -> Option<Export> {
+        self.get(module, name).map(|item| item.to_export())
+    }
+}
+
+impl fmt::Debug for Linker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Linker")
+            .field("allow_shadowing", &self.allow_shadowing)
+            .field("definitions", &self.definitions)
+            .field("modules", &self.modules.keys())
+            .finish()
+    }
+}
@@ -1,15 +1,17 @@
 use crate::store::Store;
 use crate::types::{ExportType, ImportType};
 use crate::InstantiationError;
+use std::borrow::Cow;
 use std::fmt;
 use std::io;
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Instant;
 use thiserror::Error;
 use wasmer_compiler::CompileError;
 #[cfg(feature = "wat")]
 use wasmer_compiler::WasmError;
-use wasmer_engine::{Artifact, DeserializeError, Resolver, SerializeError};
+use wasmer_engine::{Artifact, CompilationStats, DeserializeError, Resolver, SerializeError};
 use wasmer_vm::{ExportsIterator, ImportsIterator, InstanceHandle, ModuleInfo};
 
 #[derive(Error, Debug)]
@@ -96,17 +98,49 @@ impl Module {
     /// # Ok(())
     /// # }
     /// ```
-    #[allow(unreachable_code)]
     pub fn new(store: &Store, bytes: impl AsRef<[u8]>) -> Result<Module, CompileError> {
+        let bytes = Self::parse_wat(bytes.as_ref())?;
+        Module::from_binary(store, &bytes)
+    }
+
+    /// Creates a new WebAssembly module, like [`Module::new`], and returns
+    /// statistics about its compilation along with it.
+    ///
+    /// ```
+    /// use wasmer::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let (module, stats) = Module::new_with_stats(&store, "(module (func))")?;
+    /// assert_eq!(stats.functions, 1);
+    /// println!("compiled in {:?}", stats.total_time());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new_with_stats(
+        store: &Store,
+        bytes: impl AsRef<[u8]>,
+    ) -> Result<(Module, CompilationStats), CompileError> {
+        let bytes = Self::parse_wat(bytes.as_ref())?;
+        let mut stats = CompilationStats::default();
+        let start = Instant::now();
+        Module::validate(store, &bytes)?;
+        stats.validation_time = start.elapsed();
+        let module = Module::compile_with_stats(store, &bytes, &mut stats)?;
+        Ok((module, stats))
+    }
+
+    /// Converts `bytes` from the WebAssembly text format, if they aren't
+    /// WebAssembly-like and the "wat" feature is enabled.
+    fn parse_wat(bytes: &[u8]) -> Result<Cow<'_, [u8]>, CompileError> {
         #[cfg(feature = "wat")]
-        let bytes = wat::parse_bytes(bytes.as_ref()).map_err(|e| {
+        return wat::parse_bytes(bytes).map_err(|e| {
             CompileError::Wasm(WasmError::Generic(format!(
                 "Error when converting wat: {}",
                 e
             )))
-        })?;
-
-        Module::from_binary(store, bytes.as_ref())
+        });
+        #[cfg(not(feature = "wat"))]
+        Ok(Cow::Borrowed(bytes))
     }
 
     /// Creates a new WebAssembly module from a file path.
@@ -158,7 +192,26 @@ impl Module {
     }
 
     fn compile(store: &Store, binary: &[u8]) -> Result<Self, CompileError> {
-        let artifact = store.engine().compile(binary, store.tunables())?;
+        Self::compile_with_stats(store, binary, &mut CompilationStats::default())
+    }
+
+    fn compile_with_stats(
+        store: &Store,
+        binary: &[u8],
+        stats: &mut CompilationStats,
+    ) -> Result<Self, CompileError> {
+        let artifact = if store.lazy_compilation() {
+            let start = Instant::now();
+            let artifact = store.engine().compile_lazily(binary, store.tunables())?;
+            stats.compilation_time = start.elapsed();
+            artifact
+        } else {
+            store.install_compilation(|| {
+                store
+                    .engine()
+                    .compile_with_stats(binary, store.tunables(), stats)
+            })?
+        };
         Ok(Self::from_artifact(store, artifact))
     }
 
@@ -251,6 +304,20 @@ impl Module {
         Ok(Self::from_artifact(store, artifact))
     }
 
+    /// Deserializes the Module compiled to native code and statically
+    /// linked into the current executable by `wasmer create-exe`.
+    ///
+    /// Only the `Native` engine supports it.
+    ///
+    /// # Safety
+    ///
+    /// The Module linked into the executable must be compiled by the
+    /// engine of the `store`.
+    pub unsafe fn deserialize_from_executable(store: &Store) -> Result<Self, DeserializeError> {
+        let artifact = store.engine().deserialize_from_executable()?;
+        Ok(Self::from_artifact(store, artifact))
+    }
+
     fn from_artifact(store: &Store, artifact: Arc<dyn Artifact>) -> Self {
         Module {
             store: store.clone(),
@@ -258,26 +325,32 @@ impl Module {
         }
     }
 
+    /// Creates the instance handle, linking the imports.
+    ///
+    /// The instance is not usable until [`Module::finish_instantiation`]
+    /// is called, once the host function environments are initialized.
     pub(crate) fn instantiate(
         &self,
         resolver: &dyn Resolver,
     ) -> Result<InstanceHandle, InstantiationError> {
         unsafe {
-            let instance_handle =
-                self.artifact
-                    .instantiate(self.store.tunables(), resolver, Box::new(()))?;
-
-            // After the instance handle is created, we need to initialize
-            // the data, call the start function and so. However, if any
-            // of this steps traps, we still need to keep the instance alive
-            // as some of the Instance elements may have placed in other
-            // instance tables.
-            self.artifact.finish_instantiation(&instance_handle)?;
-
-            Ok(instance_handle)
+            self.artifact
+                .instantiate(self.store.tunables(), resolver, Box::new(()))
         }
     }
 
+    /// Initializes the data and calls the start function of an instance
+    /// created with [`Module::instantiate`].
+    pub(crate) fn finish_instantiation(
+        &self,
+        instance_handle: &InstanceHandle,
+    ) -> Result<(), InstantiationError> {
+        // If any of these steps traps, we still need to keep the instance
+        // alive as some of the Instance elements may have been placed in
+        // other instance tables.
+        unsafe { self.artifact.finish_instantiation(instance_handle) }
+    }
+
     /// Returns the name of the current module.
     ///
     /// This name is normally set in the WebAssembly bytecode by some
@@ -410,6 +483,15 @@ impl Module {
     pub fn info(&self) -> &ModuleInfo {
         &self.artifact.module_ref()
     }
+
+    /// Returns the compiled artifact backing this module, giving access
+    /// to the engine-specific data such as the compiled function bodies.
+    ///
+    /// Like [`Module::info`], its usage is highly discouraged.
+    #[doc(hidden)]
+    pub fn artifact(&self) -> &Arc<dyn Artifact> {
+        &self.artifact
+    }
 }
 
 impl fmt::Debug for Module {
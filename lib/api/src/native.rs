@@ -17,7 +17,8 @@ use crate::{FromToNativeWasmType, Function, FunctionType, RuntimeError, Store, W
 use std::panic::{catch_unwind, AssertUnwindSafe};
 use wasmer_types::NativeWasmType;
 use wasmer_vm::{
-    ExportFunction, VMContext, VMDynamicFunctionContext, VMFunctionBody, VMFunctionKind,
+    ExportFunction, ImportInitializerFuncPtr, InstanceRef, VMContext, VMDynamicFunctionContext,
+    VMFunctionBody, VMFunctionKind,
 };
 
 /// A WebAssembly function that can be called natively
@@ -28,6 +29,8 @@ pub struct NativeFunc<'a, Args = (), Rets = ()> {
     address: *const VMFunctionBody,
     vmctx: *mut VMContext,
     arg_kind: VMFunctionKind,
+    host_env_init: Option<ImportInitializerFuncPtr>,
+    instance_ref: Option<InstanceRef>,
     // exported: ExportFunction,
     _phantom: PhantomData<(&'a (), Args, Rets)>,
 }
@@ -44,6 +47,8 @@ where
         address: *const VMFunctionBody,
         vmctx: *mut VMContext,
         arg_kind: VMFunctionKind,
+        host_env_init: Option<ImportInitializerFuncPtr>,
+        instance_ref: Option<InstanceRef>,
         definition: FunctionDefinition,
     ) -> Self {
         Self {
@@ -52,6 +57,8 @@ where
             address,
             vmctx,
             arg_kind,
+            host_env_init,
+            instance_ref,
             _phantom: PhantomData,
         }
     }
@@ -69,6 +76,8 @@ where
             vmctx: other.vmctx,
             signature,
             kind: other.arg_kind,
+            host_env_init: other.host_env_init,
+            instance_ref: other.instance_ref.clone(),
         }
     }
 }
@@ -88,6 +97,8 @@ where
                 vmctx: other.vmctx,
                 signature,
                 kind: other.arg_kind,
+                host_env_init: other.host_env_init,
+                instance_ref: other.instance_ref,
             },
         }
     }
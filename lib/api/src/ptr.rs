@@ -6,8 +6,8 @@
 //! Therefore, you should use this abstraction whenever possible to avoid memory
 //! related bugs when implementing an ABI.
 
-use crate::{externals::Memory, FromToNativeWasmType};
-use std::{cell::Cell, fmt, marker::PhantomData, mem};
+use crate::{externals::Memory, FromToNativeWasmType, MemoryAccessError};
+use std::{cell::Cell, fmt, marker::PhantomData, mem, ptr};
 use wasmer_types::ValueType;
 
 /// The `Array` marker type. This type can be used like `WasmPtr<T, Array>`
@@ -109,6 +109,22 @@ impl<T: Copy + ValueType> WasmPtr<T, Item> {
         ) as *mut Cell<T>;
         Some(&mut *cell_ptr)
     }
+
+    /// Reads the value pointed to, which doesn't need to be aligned.
+    ///
+    /// Unlike [`WasmPtr::deref`], this method copies the value, so it's
+    /// sound even if the memory is concurrently grown.
+    #[inline]
+    pub fn read(self, memory: &Memory) -> Result<T, MemoryAccessError> {
+        memory.read_obj(self.offset.into())
+    }
+
+    /// Writes `value` where the `WasmPtr` points, which doesn't need to be
+    /// aligned.
+    #[inline]
+    pub fn write(self, memory: &Memory, value: T) -> Result<(), MemoryAccessError> {
+        memory.write_obj(self.offset.into(), value)
+    }
 }
 
 /// Methods for `WasmPtr`s to arrays of data that can be dereferenced, namely to
@@ -183,6 +199,79 @@ impl<T: Copy + ValueType> WasmPtr<T, Array> {
         Some(cell_ptrs)
     }
 
+    /// Copies the `buf.len()` items of the array from `index` into `buf`.
+    ///
+    /// The items don't need to be aligned. Unlike [`WasmPtr::deref`], this
+    /// method copies them with a single `memcpy`, so it's sound even if the
+    /// memory is concurrently grown.
+    pub fn read_slice(
+        self,
+        memory: &Memory,
+        index: u32,
+        buf: &mut [T],
+    ) -> Result<(), MemoryAccessError> {
+        let (offset, len) = self.byte_range(index, buf.len())?;
+        let dst = buf.as_mut_ptr() as *mut u8;
+        memory.with_range(offset, len, |src| unsafe {
+            ptr::copy_nonoverlapping(src, dst, len)
+        })
+    }
+
+    /// Copies the `length` items of the array from `index` into a new
+    /// vector.
+    pub fn read_vec(
+        self,
+        memory: &Memory,
+        index: u32,
+        length: u32,
+    ) -> Result<Vec<T>, MemoryAccessError> {
+        let (offset, len) = self.byte_range(index, length as usize)?;
+        // Fail before allocating for a length the memory can't hold.
+        if offset.saturating_add(len as u64) > memory.data_size() {
+            return Err(MemoryAccessError::HeapOutOfBounds {
+                offset,
+                len: len as u64,
+            });
+        }
+        let mut vec = Vec::<T>::with_capacity(length as usize);
+        let dst = vec.as_mut_ptr() as *mut u8;
+        memory.with_range(offset, len, |src| unsafe {
+            ptr::copy_nonoverlapping(src, dst, len)
+        })?;
+        // All the items are initialized, and any bit pattern is a valid
+        // `ValueType`.
+        unsafe { vec.set_len(length as usize) };
+        Ok(vec)
+    }
+
+    /// Copies `data` into the array, from `index`.
+    pub fn write_slice(
+        self,
+        memory: &Memory,
+        index: u32,
+        data: &[T],
+    ) -> Result<(), MemoryAccessError> {
+        let (offset, len) = self.byte_range(index, data.len())?;
+        let src = data.as_ptr() as *const u8;
+        memory.with_range(offset, len, |dst| unsafe {
+            ptr::copy_nonoverlapping(src, dst, len)
+        })
+    }
+
+    /// The offset and the length in bytes of the `length` items from
+    /// `index`.
+    fn byte_range(self, index: u32, length: usize) -> Result<(u64, usize), MemoryAccessError> {
+        let item_size = mem::size_of::<T>() as u64;
+        let offset = (index as u64)
+            .checked_mul(item_size)
+            .and_then(|start| start.checked_add(self.offset.into()))
+            .ok_or(MemoryAccessError::Overflow)?;
+        let len = (length as u64)
+            .checked_mul(item_size)
+            .ok_or(MemoryAccessError::Overflow)?;
+        Ok((offset, len as usize))
+    }
+
     /// Get a UTF-8 string from the `WasmPtr` with the given length.
     ///
     /// Note that this method returns a reference to Wasm linear memory. The
@@ -216,6 +305,20 @@ impl<T: Copy + ValueType> WasmPtr<T, Array> {
             .position(|byte| byte == 0)
             .and_then(|length| self.get_utf8_string(memory, length as u32))
     }
+
+    /// Copies a UTF-8 string of `str_len` bytes from the `WasmPtr`.
+    ///
+    /// Unlike [`WasmPtr::get_utf8_string`], the returned string doesn't
+    /// borrow the memory.
+    pub fn read_utf8_string(
+        self,
+        memory: &Memory,
+        str_len: u32,
+    ) -> Result<String, MemoryAccessError> {
+        let mut bytes = vec![0; str_len as usize];
+        memory.read(self.offset.into(), &mut bytes)?;
+        String::from_utf8(bytes).map_err(|_| MemoryAccessError::NonUtf8String)
+    }
 }
 
 unsafe impl<T: Copy, Ty> FromToNativeWasmType for WasmPtr<T, Ty> {
@@ -264,6 +367,47 @@ mod test {
     use super::*;
     use crate::{Memory, MemoryType, Store};
 
+    #[test]
+    fn wasm_ptr_copies_hold_bounds() {
+        let store = Store::default();
+        let memory = Memory::new(&store, MemoryType::new(1, Some(1), false)).unwrap();
+        let last_u32 = (memory.size().bytes().0 - 4) as u32;
+
+        let item: WasmPtr<u32> = WasmPtr::new(last_u32);
+        item.write(&memory, 42).unwrap();
+        assert_eq!(item.read(&memory), Ok(42));
+        assert!(WasmPtr::<u32>::new(last_u32 + 1).read(&memory).is_err());
+
+        // unaligned arrays are copied
+        let array: WasmPtr<u16, Array> = WasmPtr::new(1);
+        array.write_slice(&memory, 1, &[1, 2, 3]).unwrap();
+        assert_eq!(array.read_vec(&memory, 1, 3), Ok(vec![1, 2, 3]));
+        let mut buf = [0; 2];
+        array.read_slice(&memory, 2, &mut buf).unwrap();
+        assert_eq!(buf, [2, 3]);
+
+        let end_array: WasmPtr<u32, Array> = WasmPtr::new(last_u32);
+        assert_eq!(end_array.read_vec(&memory, 0, 1), Ok(vec![42]));
+        assert!(end_array.read_vec(&memory, 0, 2).is_err());
+        assert!(end_array.read_vec(&memory, 1, 0).is_ok());
+        assert!(end_array.write_slice(&memory, 1, &[0]).is_err());
+        assert_eq!(
+            end_array.read_vec(&memory, u32::MAX, u32::MAX),
+            Err(MemoryAccessError::HeapOutOfBounds {
+                offset: last_u32 as u64 + u32::MAX as u64 * 4,
+                len: u32::MAX as u64 * 4,
+            })
+        );
+
+        let string: WasmPtr<u8, Array> = WasmPtr::new(100);
+        string.write_slice(&memory, 0, b"hi\xff").unwrap();
+        assert_eq!(string.read_utf8_string(&memory, 2).unwrap(), "hi");
+        assert_eq!(
+            string.read_utf8_string(&memory, 3),
+            Err(MemoryAccessError::NonUtf8String)
+        );
+    }
+
     /// Ensure that memory accesses work on the edges of memory and that out of
     /// bounds errors are caught with both `deref` and `deref_mut`.
     #[test]
@@ -1,6 +1,7 @@
 use crate::tunables::Tunables;
 use std::fmt;
 use std::sync::Arc;
+use wasmer_compiler::CompileError;
 #[cfg(all(feature = "compiler", feature = "engine"))]
 use wasmer_compiler::CompilerConfig;
 use wasmer_engine::Engine;
@@ -15,11 +16,18 @@ use wasmer_engine::Tunables as BaseTunables;
 /// the Wasm bytes into a valid module artifact), in addition to the
 /// [`Tunables`] (that are used to create the memories, tables and globals).
 ///
+/// The functions of the modules are compiled in parallel, in a global
+/// thread pool unless a thread pool is set with
+/// [`Store::set_compilation_thread_pool`], or on their first call if
+/// [`Store::set_lazy_compilation`] is enabled.
+///
 /// Spec: https://webassembly.github.io/spec/core/exec/runtime.html#store
 #[derive(Clone)]
 pub struct Store {
     engine: Arc<dyn Engine + Send + Sync>,
     tunables: Arc<dyn BaseTunables + Send + Sync>,
+    compilation_thread_pool: Option<CompilationThreadPool>,
+    lazy_compilation: bool,
 }
 
 impl Store {
@@ -31,6 +39,8 @@ impl Store {
         Self {
             engine: engine.cloned(),
             tunables: Arc::new(Tunables::for_target(engine.target())),
+            compilation_thread_pool: None,
+            lazy_compilation: false,
         }
     }
 
@@ -45,6 +55,8 @@ impl Store {
         Self {
             engine: engine.cloned(),
             tunables: Arc::new(tunables),
+            compilation_thread_pool: None,
+            lazy_compilation: false,
         }
     }
 
@@ -58,6 +70,72 @@ impl Store {
         &self.engine
     }
 
+    /// Sets the thread pool the modules of this store are compiled in.
+    ///
+    /// This bounds the parallelism of the compilation, and keeps it from
+    /// competing with the other users of the global thread pool.
+    ///
+    /// ```
+    /// # use wasmer::{CompilationThreadPool, Module, Store};
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut store = Store::default();
+    /// let pool = CompilationThreadPool::new(2)?;
+    /// store.set_compilation_thread_pool(pool);
+    /// let module = Module::new(&store, "(module (func))")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_compilation_thread_pool(&mut self, pool: CompilationThreadPool) {
+        self.compilation_thread_pool = Some(pool);
+    }
+
+    /// Returns the thread pool the modules of this store are compiled in,
+    /// if one was set.
+    pub fn compilation_thread_pool(&self) -> Option<&CompilationThreadPool> {
+        self.compilation_thread_pool.as_ref()
+    }
+
+    /// Sets whether the functions of the modules of this store are
+    /// compiled lazily, on their first call, rather than all of them up
+    /// front.
+    ///
+    /// This reduces the time it takes for large modules to start, at the
+    /// cost of compiling the functions on the go. It depends on the
+    /// engine and the compiler: the JIT engine compiles the functions
+    /// lazily on x86-64 Unix hosts with Cranelift or Singlepass, and the
+    /// other engines compile them up front.
+    ///
+    /// ```
+    /// # use wasmer::{Module, Store};
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut store = Store::default();
+    /// store.set_lazy_compilation(true);
+    /// let module = Module::new(&store, "(module (func))")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_lazy_compilation(&mut self, lazy: bool) {
+        self.lazy_compilation = lazy;
+    }
+
+    /// Returns whether the functions of the modules of this store are
+    /// compiled lazily.
+    pub fn lazy_compilation(&self) -> bool {
+        self.lazy_compilation
+    }
+
+    /// Runs `op` in the compilation thread pool of the store, if any.
+    pub(crate) fn install_compilation<R, F>(&self, op: F) -> R
+    where
+        F: FnOnce() -> R + Send,
+        R: Send,
+    {
+        match &self.compilation_thread_pool {
+            Some(pool) => pool.pool.install(op),
+            None => op(),
+        }
+    }
+
     /// Checks whether two stores are identical. A store is considered
     /// equal to another store if both have the same engine. The
     /// tunables are excluded from the logic.
@@ -115,6 +193,8 @@ impl Default for Store {
         Store {
             engine: Arc::new(engine),
             tunables: Arc::new(tunables),
+            compilation_thread_pool: None,
+            lazy_compilation: false,
         }
     }
 }
@@ -125,6 +205,45 @@ impl fmt::Debug for Store {
     }
 }
 
+/// A thread pool to compile the modules in, set with
+/// [`Store::set_compilation_thread_pool`].
+///
+/// Cloning it is cheap: the clones share the same threads.
+#[derive(Clone)]
+pub struct CompilationThreadPool {
+    pool: Arc<rayon::ThreadPool>,
+}
+
+impl CompilationThreadPool {
+    /// Creates a new thread pool with `num_threads` threads, or as many
+    /// threads as CPUs if `num_threads` is 0.
+    pub fn new(num_threads: usize) -> Result<Self, CompileError> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(|index| format!("wasmer-compilation-{}", index))
+            .build()
+            .map_err(|e| {
+                CompileError::Resource(format!("Can't build the compilation thread pool: {}", e))
+            })?;
+        Ok(Self {
+            pool: Arc::new(pool),
+        })
+    }
+
+    /// Returns the number of threads of the pool.
+    pub fn num_threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+}
+
+impl fmt::Debug for CompilationThreadPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CompilationThreadPool")
+            .field("num_threads", &self.num_threads())
+            .finish()
+    }
+}
+
 /// A trait represinting any object that lives in the `Store`.
 pub trait StoreObject {
     /// Return true if the object `Store` is the same as the provided `Store`.
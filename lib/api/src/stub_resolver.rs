@@ -0,0 +1,115 @@
+//! The `StubResolver` fills the imports that a resolver can't resolve
+//! with placeholders.
+
+use crate::exports::Exportable;
+use crate::externals::{Extern, Function, Global, Memory, Table};
+use crate::module::Module;
+use crate::store::Store;
+use crate::types::{ExternType, GlobalType, ImportType, Mutability, Val, ValType};
+use crate::RuntimeError;
+use wasmer_engine::Resolver;
+use wasmer_vm::Export;
+
+/// A [`Resolver`] that synthesizes placeholders for the imports of a
+/// module that the wrapped resolver can't resolve.
+///
+/// This makes it possible to instantiate modules with missing imports, as
+/// long as they are never used:
+///  * the missing functions trap when called, with a message naming the
+///    import;
+///  * the missing memories, tables and globals are created from their
+///    import type, zeroed. `funcref` globals can't be created without a
+///    value, so they stay unresolved.
+///
+/// # Usage:
+/// ```
+/// # use wasmer::{imports, Instance, Module, Store, StubResolver};
+/// # fn main() -> anyhow::Result<()> {
+/// let store = Store::default();
+/// let module = Module::new(&store, r#"(module (import "env" "f" (func)))"#)?;
+///
+/// let imports = imports! {};
+/// let instance = Instance::new(&module, &StubResolver::new(&module, &imports))?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct StubResolver<R: Resolver> {
+    store: Store,
+    imports: Vec<ImportType>,
+    resolver: R,
+}
+
+impl<R: Resolver> StubResolver<R> {
+    /// Creates a `StubResolver` for the imports of `module`, that aren't
+    /// resolved by `resolver`.
+    pub fn new(module: &Module, resolver: R) -> Self {
+        Self {
+            store: module.store().clone(),
+            imports: module.imports().collect(),
+            resolver,
+        }
+    }
+
+    /// Returns the imports of the module that `resolver` can't resolve, and
+    /// are replaced by placeholders.
+    pub fn missing_imports(&self) -> impl Iterator<Item = &ImportType> + '_ {
+        self.imports
+            .iter()
+            .enumerate()
+            .filter_map(move |(index, import)| {
+                match self
+                    .resolver
+                    .resolve(index as u32, import.module(), import.name())
+                {
+                    Some(_) => None,
+                    None => Some(import),
+                }
+            })
+    }
+
+    /// Creates the placeholder for `import`.
+    fn stub(&self, import: &ImportType) -> Option<Extern> {
+        Some(match import.ty() {
+            ExternType::Function(ty) => {
+                let message = format!(
+                    "the missing import {}.{} was called",
+                    import.module(),
+                    import.name()
+                );
+                Extern::Function(Function::new(&self.store, ty, move |_| {
+                    Err(RuntimeError::new(message.clone()))
+                }))
+            }
+            ExternType::Memory(ty) => Extern::Memory(Memory::new(&self.store, *ty).ok()?),
+            ExternType::Table(ty) => Extern::Table(Table::new(&self.store, *ty, Val::null()).ok()?),
+            ExternType::Global(GlobalType { ty, mutability }) => {
+                let value = match ty {
+                    ValType::I32 => Val::I32(0),
+                    ValType::I64 => Val::I64(0),
+                    ValType::F32 => Val::F32(0.0),
+                    ValType::F64 => Val::F64(0.0),
+                    ValType::V128 => Val::V128(0),
+                    ValType::ExternRef => Val::null(),
+                    ValType::FuncRef => return None,
+                };
+                Extern::Global(match mutability {
+                    Mutability::Const => Global::new(&self.store, value),
+                    Mutability::Var => Global::new_mut(&self.store, value),
+                })
+            }
+        })
+    }
+}
+
+impl<R: Resolver> Resolver for StubResolver<R> {
+    fn resolve(&self, index: u32, module: &str, field: &str) -> Option<Export> {
+        if let Some(export) = self.resolver.resolve(index, module, field) {
+            return Some(export);
+        }
+        let import = self
+            .imports
+            .get(index as usize)
+            .filter(|import| import.module() == module && import.name() == field)?;
+        self.stub(import).map(|item| item.to_export())
+    }
+}
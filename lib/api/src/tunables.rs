@@ -6,6 +6,8 @@ use target_lexicon::{OperatingSystem, PointerWidth};
 use wasmer_compiler::Target;
 use wasmer_engine::Tunables as BaseTunables;
 use wasmer_vm::MemoryError;
+#[cfg(unix)]
+use wasmer_vm::{InstanceAllocator, PoolingAllocator, PoolingLimits};
 use wasmer_vm::{LinearMemory, LinearTable, Memory, MemoryStyle, Table, TableStyle};
 
 /// Tunable parameters for WebAssembly compilation.
@@ -98,3 +100,80 @@ impl BaseTunables for Tunables {
         Ok(Arc::new(LinearTable::new(&ty, &style)?))
     }
 }
+
+/// Tunables allocating the instances, their memories and their tables in
+/// the slots of a [`PoolingAllocator`], reserved up front and reused once
+/// the instances are dropped.
+///
+/// This avoids mapping and unmapping memory for every instantiation, at
+/// the cost of limiting the number of instances alive at once and their
+/// size.
+///
+/// ```
+/// # use wasmer::{imports, Instance, Module, PoolingLimits, PoolingTunables, Store};
+/// # fn main() -> anyhow::Result<()> {
+/// let limits = PoolingLimits {
+///     max_instances: 10,
+///     ..Default::default()
+/// };
+/// let tunables = PoolingTunables::new(limits).map_err(anyhow::Error::msg)?;
+/// let engine = Store::default().engine().clone();
+/// let store = Store::new_with_tunables(&*engine, tunables);
+/// let module = Module::new(&store, "(module (memory 1))")?;
+/// let instance = Instance::new(&module, &imports! {})?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(unix)]
+#[derive(Clone, Debug)]
+pub struct PoolingTunables {
+    allocator: PoolingAllocator,
+}
+
+#[cfg(unix)]
+impl PoolingTunables {
+    /// Creates new `PoolingTunables`, reserving the slots of their
+    /// allocator.
+    pub fn new(limits: PoolingLimits) -> Result<Self, String> {
+        Ok(Self {
+            allocator: PoolingAllocator::new(limits)?,
+        })
+    }
+
+    /// Returns the allocator of the instances.
+    pub fn allocator(&self) -> &PoolingAllocator {
+        &self.allocator
+    }
+}
+
+#[cfg(unix)]
+impl BaseTunables for PoolingTunables {
+    /// Get the dynamic `MemoryStyle` of the pooled memories.
+    fn memory_style(&self, _memory: &MemoryType) -> MemoryStyle {
+        self.allocator.memory_style()
+    }
+
+    /// Get a [`TableStyle`] for the provided [`TableType`].
+    fn table_style(&self, _table: &TableType) -> TableStyle {
+        TableStyle::CallerChecksSignature
+    }
+
+    /// Create a memory in a slot of the pool.
+    fn create_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+    ) -> Result<Arc<dyn Memory>, MemoryError> {
+        self.allocator.create_memory(ty, style)
+    }
+
+    /// Create a table in a slot of the pool.
+    fn create_table(&self, ty: &TableType, style: &TableStyle) -> Result<Arc<dyn Table>, String> {
+        self.allocator.create_table(ty, style)
+    }
+
+    /// Allocate the instances in the slots of the pool.
+    fn instance_allocator(&self) -> Option<Arc<dyn InstanceAllocator>> {
+        Some(self.allocator.instance_allocator())
+    }
+}
@@ -78,6 +78,8 @@ impl ValFuncRef for Val {
             // are converted to use the trampolines with static signatures).
             kind: wasmer_vm::VMFunctionKind::Static,
             vmctx: item.vmctx,
+            host_env_init: None,
+            instance_ref: None,
         };
         let f = Function::from_export(store, export);
         Val::FuncRef(f)
@@ -0,0 +1,155 @@
+use anyhow::Result;
+use wasmer::*;
+
+const APP: &str = r#"
+(module
+  (import "env" "log" (func $log (param i32)))
+  (import "env" "send" (func $send (param i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "log") (call $log (i32.const 1)))
+  (func (export "send") (param i32) (result i32) (call $send (local.get 0))))
+"#;
+
+#[derive(WasmerEnv, Clone)]
+struct Env {
+    #[wasmer(export)]
+    memory: LazyInit<Memory>,
+}
+
+fn imports(store: &Store) -> ImportObject {
+    let send_ty = FunctionType::new(vec![Type::I32], vec![Type::I32]);
+    imports! {
+        "env" => {
+            "log" => Function::new_native_with_env(
+                store,
+                Env { memory: LazyInit::new() },
+                |env: &mut Env, byte: i32| {
+                    let memory = env.memory.get_ref().expect("initialized memory");
+                    memory.write(0, &[byte as u8]).unwrap();
+                },
+            ),
+            "send" => Function::new(store, &send_ty, |args| Ok(vec![Value::I32(args[0].unwrap_i32() * 2)])),
+        },
+    }
+}
+
+fn call_error(result: Result<i32, RuntimeError>) -> CallPolicyError {
+    match result.unwrap_err().downcast::<CallPolicyError>() {
+        Ok(error) => error,
+        Err(error) => panic!("unexpected error: {}", error),
+    }
+}
+
+#[test]
+fn counts_calls_and_fuel() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(&store, APP)?;
+    let mut policy = CallPolicy::new();
+    policy.cost("env", "send", 10);
+    let (imports, counters) = imports(&store).with_call_policy(&store, &policy);
+    let instance = Instance::new(&module, &imports)?;
+
+    let log = instance.exports.get_native_function::<(), ()>("log")?;
+    let send = instance.exports.get_native_function::<i32, i32>("send")?;
+    log.call()?;
+    assert_eq!(send.call(21)?, 42);
+    assert_eq!(send.call(1)?, 2);
+
+    let mut byte = [0];
+    instance.exports.get_memory("memory")?.read(0, &mut byte)?;
+    assert_eq!(byte, [1]);
+    assert_eq!(counters.calls("env", "log"), 1);
+    assert_eq!(counters.calls("env", "send"), 2);
+    assert_eq!(counters.fuel_consumed(), 21);
+    assert_eq!(counters.remaining_fuel(), None);
+
+    counters.reset();
+    assert_eq!(counters.calls("env", "send"), 0);
+    assert_eq!(counters.fuel_consumed(), 0);
+    Ok(())
+}
+
+#[test]
+fn traps_when_out_of_fuel() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(&store, APP)?;
+    let mut policy = CallPolicy::new();
+    policy.fuel(25).cost("env", "send", 10);
+    let (imports, counters) = imports(&store).with_call_policy(&store, &policy);
+    let instance = Instance::new(&module, &imports)?;
+
+    let send = instance.exports.get_native_function::<i32, i32>("send")?;
+    send.call(1)?;
+    send.call(1)?;
+    assert_eq!(
+        call_error(send.call(1)),
+        CallPolicyError::OutOfFuel {
+            module: "env".to_string(),
+            name: "send".to_string(),
+            cost: 10,
+            remaining: 5,
+        }
+    );
+    assert_eq!(counters.calls("env", "send"), 2);
+    assert_eq!(counters.remaining_fuel(), Some(5));
+
+    counters.reset();
+    assert_eq!(send.call(1)?, 2);
+    Ok(())
+}
+
+#[test]
+fn traps_when_a_quota_is_exhausted() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(&store, APP)?;
+    let mut policy = CallPolicy::new();
+    policy.quota("env", "send", 1);
+    let (imports, counters) = imports(&store).with_call_policy(&store, &policy);
+    let instance = Instance::new(&module, &imports)?;
+
+    let send = instance.exports.get_native_function::<i32, i32>("send")?;
+    send.call(1)?;
+    let error = call_error(send.call(1));
+    assert_eq!(
+        error.to_string(),
+        "the quota of 1 calls to env.send is exhausted"
+    );
+    assert_eq!(counters.calls("env", "send"), 1);
+
+    // The quotas are per import.
+    instance
+        .exports
+        .get_native_function::<(), ()>("log")?
+        .call()?;
+    Ok(())
+}
+
+#[test]
+fn counts_the_calls_of_each_instance_apart() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(&store, APP)?;
+    let policy = CallPolicy::new();
+    let imports = imports(&store);
+    let (first_imports, first) = imports.with_call_policy(&store, &policy);
+    let (second_imports, second) = imports.with_call_policy(&store, &policy);
+    let first_instance = Instance::new(&module, &first_imports)?;
+    let second_instance = Instance::new(&module, &second_imports)?;
+
+    first_instance
+        .exports
+        .get_native_function::<i32, i32>("send")?
+        .call(1)?;
+    second_instance
+        .exports
+        .get_native_function::<(), ()>("log")?
+        .call()?;
+    assert_eq!(
+        (first.calls("env", "send"), first.calls("env", "log")),
+        (1, 0)
+    );
+    assert_eq!(
+        (second.calls("env", "send"), second.calls("env", "log")),
+        (0, 1)
+    );
+    Ok(())
+}
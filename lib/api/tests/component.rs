@@ -0,0 +1,177 @@
+use anyhow::Result;
+use wasmer::component::{CanonicalAbi, ComponentError, ComponentType, LowerContext, TypedFunction};
+use wasmer::*;
+
+const GUEST: &str = r#"
+(module
+  (import "host" "concat" (func $concat (param i32 i32 i32 i32 i32)))
+  (import "host" "checked_div" (func $checked_div (param i32 i32 i32)))
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+
+  ;; A bump allocator.
+  (func (export "canonical_abi_realloc") (param i32 i32 i32 i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr
+      (i32.and
+        (i32.add (global.get $next) (i32.sub (local.get 2) (i32.const 1)))
+        (i32.sub (i32.const 0) (local.get 2))))
+    (global.set $next (i32.add (local.get $ptr) (local.get 3)))
+    (local.get $ptr))
+
+  (func (export "echo_string") (param i32 i32) (result i32)
+    (i32.store (i32.const 16) (local.get 0))
+    (i32.store (i32.const 20) (local.get 1))
+    (i32.const 16))
+
+  (func (export "echo_point") (param i32 i32 i32 i32) (result i32)
+    (i32.store (i32.const 64) (local.get 0))
+    (i32.store (i32.const 68) (local.get 1))
+    (i32.store (i32.const 72) (local.get 2))
+    (i32.store (i32.const 76) (local.get 3))
+    (i32.const 64))
+
+  (func (export "sum_bytes") (param i32 i32) (result i32)
+    (local $sum i32)
+    (block $done
+      (loop $loop
+        (br_if $done (i32.eqz (local.get 1)))
+        (local.set $sum (i32.add (local.get $sum) (i32.load8_u (local.get 0))))
+        (local.set 0 (i32.add (local.get 0) (i32.const 1)))
+        (local.set 1 (i32.sub (local.get 1) (i32.const 1)))
+        (br $loop)))
+    (local.get $sum))
+
+  (func (export "concat") (param i32 i32 i32 i32) (result i32)
+    (call $concat (local.get 0) (local.get 1) (local.get 2) (local.get 3) (i32.const 32))
+    (i32.const 32))
+
+  (func (export "units") (result i32)
+    (i32.store (i32.const 80) (i32.const 0))
+    (i32.store (i32.const 84) (i32.const -1))
+    (i32.const 80))
+
+  (func (export "div") (param i32 i32) (result i32)
+    (call $checked_div (local.get 0) (local.get 1) (i32.const 48))
+    (i32.const 48)))
+"#;
+
+#[derive(ComponentType, Debug, Clone, PartialEq)]
+struct Point {
+    x: i32,
+    y: i32,
+    label: String,
+}
+
+fn instantiate() -> Result<(Instance, CanonicalAbi)> {
+    let store = Store::default();
+    let module = Module::new(&store, GUEST)?;
+    let abi = CanonicalAbi::new();
+    let concat = Function::new_component(&store, &abi, |(a, b): (String, String)| {
+        Ok(format!("{}{}", a, b))
+    });
+    let checked_div = Function::new_component(&store, &abi, |(a, b): (u32, u32)| {
+        Ok(a.checked_div(b)
+            .ok_or_else(|| "division by zero".to_string()))
+    });
+    let instance = Instance::new(
+        &module,
+        &imports! {
+            "host" => {
+                "concat" => concat,
+                "checked_div" => checked_div,
+            }
+        },
+    )?;
+    abi.bind(&instance.exports)?;
+    Ok((instance, abi))
+}
+
+#[test]
+fn component_strings_roundtrip() -> Result<()> {
+    let (instance, abi) = instantiate()?;
+    let echo: TypedFunction<(&str,), String> =
+        TypedFunction::new(instance.exports.get_function("echo_string")?, &abi)?;
+    assert_eq!(echo.call(("hello, wasmer",))?, "hello, wasmer");
+    assert_eq!(echo.call(("",))?, "");
+    Ok(())
+}
+
+#[test]
+fn component_records_roundtrip() -> Result<()> {
+    let (instance, abi) = instantiate()?;
+    let echo: TypedFunction<(Point,), Point> =
+        TypedFunction::new(instance.exports.get_function("echo_point")?, &abi)?;
+    let point = Point {
+        x: -1,
+        y: 42,
+        label: "origin".to_string(),
+    };
+    assert_eq!(echo.call((point.clone(),))?, point);
+    Ok(())
+}
+
+#[test]
+fn component_byte_lists() -> Result<()> {
+    let (instance, abi) = instantiate()?;
+    let sum: TypedFunction<(Vec<u8>,), u32> =
+        TypedFunction::new(instance.exports.get_function("sum_bytes")?, &abi)?;
+    assert_eq!(sum.call((vec![1, 2, 3, 250],))?, 256);
+    Ok(())
+}
+
+#[test]
+fn component_zero_size_lists() -> Result<()> {
+    let (instance, abi) = instantiate()?;
+    let units: TypedFunction<(), Vec<()>> =
+        TypedFunction::new(instance.exports.get_function("units")?, &abi)?;
+    assert_eq!(units.call(())?.len(), u32::MAX as usize);
+    Ok(())
+}
+
+#[test]
+fn component_allocations_too_large() -> Result<()> {
+    let (instance, _) = instantiate()?;
+    let memory = instance.exports.get_memory("memory")?;
+    let realloc = instance.exports.get_function("canonical_abi_realloc")?;
+    let mut cx = LowerContext::new(memory, Some(realloc));
+    assert!(matches!(
+        cx.alloc(1, 1 << 31),
+        Err(ComponentError::TooLarge(size)) if size == 1 << 31
+    ));
+    assert!(cx.alloc(1, 16).is_ok());
+    Ok(())
+}
+
+#[test]
+fn component_host_functions() -> Result<()> {
+    let (instance, abi) = instantiate()?;
+    let concat: TypedFunction<(&str, &str), String> =
+        TypedFunction::new(instance.exports.get_function("concat")?, &abi)?;
+    assert_eq!(concat.call(("foo", "bar"))?, "foobar");
+
+    let div: TypedFunction<(u32, u32), Result<u32, String>> =
+        TypedFunction::new(instance.exports.get_function("div")?, &abi)?;
+    assert_eq!(div.call((10, 3))?, Ok(3));
+    assert_eq!(div.call((1, 0))?, Err("division by zero".to_string()));
+    Ok(())
+}
+
+#[test]
+fn component_signature_mismatch() -> Result<()> {
+    let (instance, abi) = instantiate()?;
+    let result: Result<TypedFunction<(u32,), String>, _> =
+        TypedFunction::new(instance.exports.get_function("echo_string")?, &abi);
+    assert!(matches!(result, Err(ComponentError::Signature { .. })));
+    Ok(())
+}
+
+#[test]
+fn component_unbound_abi() -> Result<()> {
+    let (instance, _) = instantiate()?;
+    let abi = CanonicalAbi::new();
+    let echo: TypedFunction<(&str,), String> =
+        TypedFunction::new(instance.exports.get_function("echo_string")?, &abi)?;
+    assert!(matches!(echo.call(("hi",)), Err(ComponentError::Unbound)));
+    Ok(())
+}
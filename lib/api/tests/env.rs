@@ -0,0 +1,135 @@
+use anyhow::Result;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use wasmer::*;
+
+const WAT: &str = r#"
+(module
+  (import "host" "write" (func $write (param i32)))
+  (memory (export "memory") 1)
+  (func (export "double") (param i32) (result i32)
+    (i32.mul (local.get 0) (i32.const 2)))
+  (func $start
+    (i32.store8 (i32.const 16) (i32.const 42))
+    (call $write (i32.const 16)))
+  (start $start))
+"#;
+
+#[derive(WasmerEnv, Clone)]
+struct Env {
+    #[wasmer(export)]
+    memory: LazyInit<Memory>,
+    #[wasmer(export(name = "double"))]
+    double: LazyInit<NativeFunc<'static, i32, i32>>,
+    #[wasmer(export(name = "free", optional = true))]
+    free: LazyInit<Function>,
+    last_value: Arc<AtomicU32>,
+}
+
+impl Env {
+    fn new() -> Self {
+        Self {
+            memory: LazyInit::new(),
+            double: LazyInit::new(),
+            free: LazyInit::new(),
+            last_value: Arc::new(AtomicU32::new(0)),
+        }
+    }
+}
+
+fn write(env: &mut Env, ptr: i32) {
+    let memory = env.memory.get_ref().unwrap();
+    let value = unsafe { memory.data_unchecked()[ptr as usize] };
+    let doubled = env.double.get_ref().unwrap().call(value as i32).unwrap();
+    env.last_value.store(doubled as u32, Ordering::SeqCst);
+}
+
+#[test]
+fn exports_are_bound_before_start() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(&store, WAT)?;
+    let env = Env::new();
+    let imports = imports! {
+        "host" => {
+            "write" => Function::new_native_with_env(&store, env.clone(), write),
+        }
+    };
+    let instance = Instance::new(&module, &imports)?;
+
+    assert_eq!(env.last_value.load(Ordering::SeqCst), 84);
+    // The environment kept by the host shares the bound exports.
+    assert!(env
+        .memory
+        .get_ref()
+        .unwrap()
+        .same(instance.exports.get_memory("memory")?));
+    assert!(env.free.get_ref().is_none());
+    Ok(())
+}
+
+#[test]
+fn dynamic_function_env_is_initialized() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(&store, WAT)?;
+    let env = Env::new();
+    let write = Function::new_with_env(
+        &store,
+        &FunctionType::new(vec![ValType::I32], vec![]),
+        env.clone(),
+        |env, args| {
+            write(env, args[0].unwrap_i32());
+            Ok(vec![])
+        },
+    );
+    Instance::new(&module, &imports! { "host" => { "write" => write } })?;
+
+    assert_eq!(env.last_value.load(Ordering::SeqCst), 84);
+    Ok(())
+}
+
+#[test]
+fn missing_export_fails_instantiation() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        r#"(module (import "host" "write" (func (param i32))) (memory (export "memory") 1))"#,
+    )?;
+    let imports = imports! {
+        "host" => {
+            "write" => Function::new_native_with_env(&store, Env::new(), write),
+        }
+    };
+
+    match Instance::new(&module, &imports) {
+        Err(InstantiationError::HostEnvInitialization(HostEnvInitError::MissingExport(name))) => {
+            assert_eq!(name, "double");
+        }
+        result => panic!("unexpected result: {:?}", result.map(|_| ())),
+    }
+    Ok(())
+}
+
+#[test]
+fn incompatible_export_fails_instantiation() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        r#"(module
+             (import "host" "write" (func (param i32)))
+             (memory (export "memory") 1)
+             (func (export "double") (param i64) (result i64) (local.get 0)))"#,
+    )?;
+    let imports = imports! {
+        "host" => {
+            "write" => Function::new_native_with_env(&store, Env::new(), write),
+        }
+    };
+
+    match Instance::new(&module, &imports) {
+        Err(InstantiationError::HostEnvInitialization(HostEnvInitError::IncompatibleExport(
+            name,
+        ))) => assert_eq!(name, "double"),
+        result => panic!("unexpected result: {:?}", result.map(|_| ())),
+    }
+    Ok(())
+}
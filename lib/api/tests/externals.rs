@@ -1,4 +1,6 @@
 use anyhow::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use wasmer::*;
 
 #[test]
@@ -177,6 +179,66 @@ fn memory_grow() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn memory_read_write() -> Result<()> {
+    let store = Store::default();
+    let memory = Memory::new(&store, MemoryType::new(Pages(1), None, false))?;
+    let end = memory.data_size();
+
+    memory.write(end - 4, &[1, 2, 3, 4])?;
+    let mut buf = [0; 4];
+    memory.read(end - 4, &mut buf)?;
+    assert_eq!(buf, [1, 2, 3, 4]);
+
+    memory.write_obj(13, 0xdead_beef_u32)?;
+    assert_eq!(memory.read_obj::<u32>(13)?, 0xdead_beef);
+    assert_eq!(memory.read_obj::<u8>(13)?, 0xef);
+
+    assert_eq!(
+        memory.read(end - 3, &mut buf),
+        Err(MemoryAccessError::HeapOutOfBounds {
+            offset: end - 3,
+            len: 4
+        })
+    );
+    assert!(memory.write(end, &[0]).is_err());
+    assert_eq!(memory.write(end, &[]), Ok(()));
+    assert_eq!(
+        memory.read_obj::<u64>(u64::MAX - 4),
+        Err(MemoryAccessError::Overflow)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn memory_read_write_while_growing() -> Result<()> {
+    let store = Store::default();
+    let desc = MemoryType::new(Pages(1), Some(Pages(64)), false);
+    let memory = Memory::new(&store, desc)?;
+    memory.write(0, b"hello")?;
+
+    let done = Arc::new(AtomicBool::new(false));
+    let grower = {
+        let memory = memory.clone();
+        let done = done.clone();
+        std::thread::spawn(move || {
+            while memory.grow(Pages(1)).is_ok() {}
+            done.store(true, Ordering::SeqCst);
+        })
+    };
+    let mut buf = [0; 5];
+    while !done.load(Ordering::SeqCst) {
+        memory.read(0, &mut buf)?;
+        assert_eq!(&buf, b"hello");
+        memory.write(0, b"hello")?;
+    }
+    grower.join().unwrap();
+    assert_eq!(memory.size(), Pages(64));
+
+    Ok(())
+}
+
 #[test]
 fn function_new() -> Result<()> {
     let store = Store::default();
@@ -208,7 +270,7 @@ fn function_new() -> Result<()> {
 #[test]
 fn function_new_env() -> Result<()> {
     let store = Store::default();
-    #[derive(Clone)]
+    #[derive(WasmerEnv, Clone)]
     struct MyEnv {};
     let my_env = MyEnv {};
     let function = Function::new_native_with_env(&store, my_env.clone(), |_env: &mut MyEnv| {});
@@ -270,7 +332,7 @@ fn function_new_dynamic() -> Result<()> {
 #[test]
 fn function_new_dynamic_env() -> Result<()> {
     let store = Store::default();
-    #[derive(Clone)]
+    #[derive(WasmerEnv, Clone)]
     struct MyEnv {};
     let my_env = MyEnv {};
 
@@ -0,0 +1,162 @@
+use anyhow::Result;
+use wasmer::*;
+
+const LIB: &str = r#"
+(module
+  (global $counter (export "counter") (mut i32) (i32.const 0))
+  (func (export "next") (result i32)
+    (global.set $counter (i32.add (global.get $counter) (i32.const 1)))
+    (global.get $counter)))
+"#;
+
+const APP: &str = r#"
+(module
+  (import "lib" "next" (func $next (result i32)))
+  (func (export "twice") (result i32)
+    (drop (call $next))
+    (call $next)))
+"#;
+
+fn get_i32(instance: &Instance, name: &str) -> i32 {
+    match instance.exports.get_global(name).unwrap().get() {
+        Value::I32(value) => value,
+        value => panic!("unexpected value: {:?}", value),
+    }
+}
+
+#[test]
+fn links_instances() -> Result<()> {
+    let store = Store::default();
+    let lib = Instance::new(&Module::new(&store, LIB)?, &imports! {})?;
+
+    let mut linker = Linker::new(&store);
+    linker.define_instance("lib", &lib)?;
+    let app = linker.instantiate(&Module::new(&store, APP)?)?;
+
+    let twice = app.exports.get_native_function::<(), i32>("twice")?;
+    assert_eq!(twice.call()?, 2);
+    assert_eq!(get_i32(&lib, "counter"), 2);
+    Ok(())
+}
+
+#[test]
+fn instantiates_modules_once_on_first_use() -> Result<()> {
+    let store = Store::default();
+    let mut linker = Linker::new(&store);
+    linker.define_module("lib", &Module::new(&store, LIB)?)?;
+    assert!(linker.get("lib", "next").is_none());
+
+    let app = Module::new(&store, APP)?;
+    let first = linker.instantiate(&app)?;
+    let second = linker.instantiate(&app)?;
+    first.exports.get_function("twice")?.call(&[])?;
+    assert_eq!(
+        second.exports.get_function("twice")?.call(&[])?[0],
+        Value::I32(4)
+    );
+
+    match linker.get("lib", "counter") {
+        Some(Extern::Global(counter)) => assert_eq!(counter.get(), Value::I32(4)),
+        other => panic!("unexpected definition: {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn reports_all_unresolved_imports() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        r#"(module
+          (import "env" "f" (func (param i32)))
+          (import "env" "g" (global i32))
+          (import "env" "memory" (memory 1)))"#,
+    )?;
+
+    let mut linker = Linker::new(&store);
+    linker.define("env", "f", Function::new_native(&store, || {}))?;
+    let error = linker.instantiate(&module).unwrap_err();
+    let diagnostics = match &error {
+        LinkerError::UnresolvedImports(diagnostics) => diagnostics,
+        error => panic!("unexpected error: {}", error),
+    };
+    assert_eq!(diagnostics.len(), 3);
+    assert_eq!(
+        diagnostics[0].expected,
+        ExternType::Function(FunctionType::new(vec![Type::I32], vec![]))
+    );
+    assert_eq!(
+        diagnostics[0].provided,
+        Some(ExternType::Function(FunctionType::new(vec![], vec![])))
+    );
+    assert_eq!(
+        diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.name.as_str(), diagnostic.provided.is_some()))
+            .collect::<Vec<_>>(),
+        vec![("f", true), ("g", false), ("memory", false)]
+    );
+    let message = error.to_string();
+    assert!(message.contains("env.g: unknown import"), "{}", message);
+    Ok(())
+}
+
+#[test]
+fn rejects_redefinitions_unless_shadowing() -> Result<()> {
+    let store = Store::default();
+    let one = Global::new(&store, Value::I32(1));
+    let two = Global::new(&store, Value::I32(2));
+
+    let mut linker = Linker::new(&store);
+    linker.define("env", "g", one)?;
+    assert!(matches!(
+        linker.define("env", "g", two.clone()),
+        Err(LinkerError::Redefinition { .. })
+    ));
+    assert!(matches!(
+        linker.define_module("env", &Module::new(&store, LIB)?),
+        Err(LinkerError::NamespaceRedefinition(_))
+    ));
+
+    linker.allow_shadowing(true).define("env", "g", two)?;
+    match linker.get("env", "g") {
+        Some(Extern::Global(g)) => assert_eq!(g.get(), Value::I32(2)),
+        other => panic!("unexpected definition: {:?}", other),
+    }
+
+    let other_store = Store::default();
+    assert!(matches!(
+        linker.define("env", "h", Global::new(&other_store, Value::I32(3))),
+        Err(LinkerError::StoreMismatch { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn falls_back_to_default_definitions() -> Result<()> {
+    let store = Store::default();
+    let fallback = imports! {
+        "lib" => {
+            "next" => Function::new_native(&store, || 7),
+        },
+    };
+
+    let mut linker = Linker::new(&store);
+    linker.define_fallback(fallback);
+    let app = linker.instantiate(&Module::new(&store, APP)?)?;
+    let twice = app.exports.get_native_function::<(), i32>("twice")?;
+    assert_eq!(twice.call()?, 7);
+    Ok(())
+}
+
+#[test]
+fn detects_cycles() -> Result<()> {
+    let store = Store::default();
+    let a = Module::new(&store, r#"(module (import "b" "g" (global i32)))"#)?;
+    let b = Module::new(&store, r#"(module (import "a" "g" (global i32)))"#)?;
+
+    let mut linker = Linker::new(&store);
+    linker.define_module("a", &a)?.define_module("b", &b)?;
+    assert!(matches!(linker.instantiate(&a), Err(LinkerError::Cycle(_))));
+    Ok(())
+}
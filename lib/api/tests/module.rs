@@ -156,3 +156,147 @@ fn exports() -> Result<()> {
     );
     Ok(())
 }
+
+#[test]
+fn module_new_with_stats() -> Result<()> {
+    let store = Store::default();
+    let wat = r#"(module
+    (import "host" "func" (func))
+    (func (export "one") (result i32) (i32.const 1))
+    (func (export "two") (result i32) (i32.const 2))
+)"#;
+    let (module, stats) = Module::new_with_stats(&store, wat)?;
+    assert_eq!(module.exports().functions().count(), 2);
+    assert_eq!(stats.functions, 2);
+    assert!(stats.code_size > 0);
+    assert!(stats.total_time() >= stats.compilation_time);
+
+    let (_, stats) = Module::new_with_stats(&store, "(module)")?;
+    assert_eq!(stats.functions, 0);
+    Ok(())
+}
+
+#[test]
+fn module_compile_in_thread_pool() -> Result<()> {
+    let mut store = Store::default();
+    store.set_compilation_thread_pool(CompilationThreadPool::new(1)?);
+    assert_eq!(
+        store
+            .compilation_thread_pool()
+            .map(CompilationThreadPool::num_threads),
+        Some(1)
+    );
+
+    let wat = r#"(module
+    (func $double (export "double") (param i32) (result i32)
+        (i32.add (local.get 0) (local.get 0)))
+    (func (export "quadruple") (param i32) (result i32)
+        (call $double (call $double (local.get 0))))
+)"#;
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let quadruple = instance
+        .exports
+        .get_native_function::<i32, i32>("quadruple")?;
+    assert_eq!(quadruple.call(3)?, 12);
+
+    let (_, stats) = Module::new_with_stats(&store, wat)?;
+    assert_eq!(stats.functions, 2);
+    Ok(())
+}
+
+#[test]
+fn instantiation_reports_all_unresolved_imports() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        r#"(module
+        (import "env" "f" (func (param i32)))
+        (import "env" "memory" (memory 1))
+        (import "env" "g" (global i32)))"#,
+    )?;
+    let imports = imports! {
+        "env" => {
+            "g" => Global::new(&store, Value::I64(0)),
+            "memory" => Memory::new(&store, MemoryType::new(1, None, false))?,
+        },
+    };
+
+    let error = match Instance::new(&module, &imports) {
+        Err(InstantiationError::Link(error)) => error,
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    };
+    let message = error.to_string();
+    let unresolved = match error {
+        LinkError::Imports(unresolved) => unresolved,
+        error => panic!("unexpected error: {}", error),
+    };
+    assert_eq!(
+        unresolved
+            .iter()
+            .map(|import| (import.name.as_str(), import.provided.clone()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("f", None),
+            (
+                "g",
+                Some(ExternType::Global(GlobalType::new(
+                    Type::I64,
+                    Mutability::Const
+                )))
+            ),
+        ]
+    );
+    assert!(message.contains("env.f: unknown import"), "{}", message);
+    assert!(
+        message.contains("env.g: incompatible import type"),
+        "{}",
+        message
+    );
+    Ok(())
+}
+
+#[test]
+fn instantiation_stubs_missing_imports() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        r#"(module
+        (import "env" "f" (func (param i32) (result i32)))
+        (import "env" "g" (global (mut i64)))
+        (import "env" "memory" (memory 1))
+        (import "env" "table" (table 2 funcref))
+        (import "env" "h" (func (result i32)))
+        (func (export "call_f") (result i32) (call 0 (i32.const 1)))
+        (func (export "call_h") (result i32) (call 1))
+        (func (export "g") (result i64) (global.get 0))
+        (func (export "size") (result i32) (memory.size)))"#,
+    )?;
+    let imports = imports! {
+        "env" => {
+            "h" => Function::new_native(&store, || 42),
+        },
+    };
+
+    let resolver = StubResolver::new(&module, &imports);
+    assert_eq!(
+        resolver
+            .missing_imports()
+            .map(|import| import.name())
+            .collect::<Vec<_>>(),
+        vec!["f", "g", "memory", "table"]
+    );
+    let instance = Instance::new(&module, &resolver)?;
+
+    let call_h = instance.exports.get_native_function::<(), i32>("call_h")?;
+    assert_eq!(call_h.call()?, 42);
+    let g = instance.exports.get_native_function::<(), i64>("g")?;
+    assert_eq!(g.call()?, 0);
+    let size = instance.exports.get_native_function::<(), i32>("size")?;
+    assert_eq!(size.call()?, 1);
+
+    let call_f = instance.exports.get_native_function::<(), i32>("call_f")?;
+    let error = call_f.call().unwrap_err();
+    assert_eq!(error.message(), "the missing import env.f was called");
+    Ok(())
+}
@@ -20,7 +20,7 @@ use std::collections::HashMap;
 use wasmer::{
     ChainableNamedResolver, Exports, Extern, Function, FunctionType, Global, ImportObject,
     ImportObjectIterator, ImportType, Memory, Module, NamedResolver, RuntimeError, Table, Val,
-    ValType,
+    ValType, WasmerEnv,
 };
 
 #[repr(C)]
@@ -638,6 +638,9 @@ pub(crate) struct LegacyEnv {
     pub(crate) instance_ptr: Option<NonNull<CAPIInstance>>,
 }
 
+// The instance pointer is back-patched once the instance is created.
+impl WasmerEnv for LegacyEnv {}
+
 impl LegacyEnv {
     pub(crate) fn ctx_ptr(&self) -> *mut CAPIInstance {
         self.instance_ptr
@@ -23,15 +23,38 @@ use wasmer::{
 };
 #[cfg(feature = "jit")]
 use wasmer_engine_jit::JIT;
+#[cfg(feature = "native")]
+use wasmer_engine_native::Native;
 
-/// this can be a wasmer-specific type with wasmer-specific functions for manipulating it
+/// The engines a `wasm_engine_t` can be created with, by
+/// `wasm_engine_new_with_config`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+#[allow(non_camel_case_types)]
+pub enum wasmer_engine_t {
+    JIT = 0,
+    NATIVE = 1,
+}
+
+/// The configuration of a `wasm_engine_t`.
 #[repr(C)]
-pub struct wasm_config_t {}
+pub struct wasm_config_t {
+    engine: wasmer_engine_t,
+}
+
+#[no_mangle]
+pub extern "C" fn wasm_config_new() -> Box<wasm_config_t> {
+    let engine = if cfg!(feature = "jit") {
+        wasmer_engine_t::JIT
+    } else {
+        wasmer_engine_t::NATIVE
+    };
+    Box::new(wasm_config_t { engine })
+}
 
 #[no_mangle]
-pub extern "C" fn wasm_config_new() -> *mut wasm_config_t {
-    todo!("wasm_config_new")
-    //ptr::null_mut()
+pub extern "C" fn wasm_config_set_engine(config: &mut wasm_config_t, engine: wasmer_engine_t) {
+    config.engine = engine;
 }
 
 #[repr(C)]
@@ -40,8 +63,7 @@ pub struct wasm_engine_t {
 }
 
 cfg_if! {
-    if #[cfg(all(feature = "jit", feature = "compiler"))] {
-        // Compiler JIT
+    if #[cfg(feature = "compiler")] {
         use wasmer_compiler::CompilerConfig;
         fn get_default_compiler_config() -> Box<dyn CompilerConfig> {
             cfg_if! {
@@ -56,38 +78,72 @@ cfg_if! {
                 }
             }
         }
+    }
+}
 
-        #[no_mangle]
-        pub extern "C" fn wasm_engine_new() -> Box<wasm_engine_t> {
+cfg_if! {
+    if #[cfg(all(feature = "jit", feature = "compiler"))] {
+        // Compiler JIT
+        fn new_jit_engine() -> Option<Arc<dyn Engine + Send + Sync>> {
             let compiler_config: Box<dyn CompilerConfig> = get_default_compiler_config();
-            let engine: Arc<dyn Engine + Send + Sync> = Arc::new(JIT::new(&*compiler_config).engine());
-            Box::new(wasm_engine_t { inner: engine })
+            Some(Arc::new(JIT::new(&*compiler_config).engine()))
         }
     }
     else if #[cfg(feature = "jit")] {
         // Headless JIT
-        #[no_mangle]
-        pub extern "C" fn wasm_engine_new() -> Box<wasm_engine_t> {
-            let engine: Arc<dyn Engine + Send + Sync> = Arc::new(JIT::headless().engine());
-            Box::new(wasm_engine_t { inner: engine })
+        fn new_jit_engine() -> Option<Arc<dyn Engine + Send + Sync>> {
+            Some(Arc::new(JIT::headless().engine()))
+        }
+    }
+    else {
+        fn new_jit_engine() -> Option<Arc<dyn Engine + Send + Sync>> {
+            None
+        }
+    }
+}
+
+cfg_if! {
+    if #[cfg(all(feature = "native", feature = "compiler"))] {
+        // Compiler Native
+        fn new_native_engine() -> Option<Arc<dyn Engine + Send + Sync>> {
+            let mut compiler_config: Box<dyn CompilerConfig> = get_default_compiler_config();
+            Some(Arc::new(Native::new(&mut *compiler_config).engine()))
+        }
+    }
+    else if #[cfg(feature = "native")] {
+        // Headless Native
+        fn new_native_engine() -> Option<Arc<dyn Engine + Send + Sync>> {
+            Some(Arc::new(Native::headless().engine()))
         }
     }
     else {
-        #[no_mangle]
-        pub extern "C" fn wasm_engine_new() -> Box<wasm_engine_t> {
-            unimplemented!("The JITEngine is not attached");
+        fn new_native_engine() -> Option<Arc<dyn Engine + Send + Sync>> {
+            None
         }
     }
 }
 
+#[no_mangle]
+pub extern "C" fn wasm_engine_new() -> Box<wasm_engine_t> {
+    wasm_engine_new_with_config(Some(wasm_config_new()))
+        .unwrap_or_else(|| unimplemented!("No engine is attached"))
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn wasm_engine_delete(_wasm_engine_address: Option<Box<wasm_engine_t>>) {}
 
+/// Takes ownership of the `wasm_config_t`.
+///
+/// Returns null if the engine of the configuration isn't enabled.
 #[no_mangle]
 pub extern "C" fn wasm_engine_new_with_config(
-    _config_ptr: *mut wasm_config_t,
-) -> Box<wasm_engine_t> {
-    wasm_engine_new()
+    config: Option<Box<wasm_config_t>>,
+) -> Option<Box<wasm_engine_t>> {
+    let engine = match config?.engine {
+        wasmer_engine_t::JIT => new_jit_engine(),
+        wasmer_engine_t::NATIVE => new_native_engine(),
+    };
+    Some(Box::new(wasm_engine_t { inner: engine? }))
 }
 
 #[repr(C)]
@@ -274,6 +330,25 @@ pub unsafe extern "C" fn wasm_module_deserialize(
     ))))
 }
 
+/// Loads the module compiled to native code and statically linked into
+/// the current executable by `wasmer create-exe`.
+///
+/// The engine of the store must be the native one.
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_module_deserialize_from_executable(
+    store_ptr: Option<NonNull<wasm_store_t>>,
+) -> Option<NonNull<wasm_module_t>> {
+    let store_ptr: NonNull<Store> = store_ptr?.cast::<Store>();
+    let store = store_ptr.as_ref();
+    let module = c_try!(Module::deserialize_from_executable(store));
+
+    Some(NonNull::new_unchecked(Box::into_raw(Box::new(
+        wasm_module_t {
+            inner: Arc::new(module),
+        },
+    ))))
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn wasm_module_serialize(
     module: &wasm_module_t,
@@ -0,0 +1,6 @@
+;; Imports a function, a global and a memory, to test the errors reported
+;; when they can't be resolved.
+(module
+  (import "env" "f" (func (param i32)))
+  (import "env" "g" (global i64))
+  (import "env" "m" (memory 1)))
@@ -0,0 +1,80 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "wasm.h"
+#include "wasmer_wasm.h"
+
+// All the imports which can't be resolved are reported at once.
+static const char* EXPECTED_ERRORS[] = {
+  "3 unresolved import(s)",
+  "env.f: incompatible import type",
+  "env.g: unknown import",
+  "env.m: unknown import",
+};
+
+int main(int argc, const char* argv[]) {
+  wasm_engine_t* engine = wasm_engine_new();
+  wasm_store_t* store = wasm_store_new(engine);
+
+  // Load binary.
+  printf("Loading binary...\n");
+  FILE* file = fopen("assets/import_errors.wasm", "rb");
+  if (!file) {
+    printf("> Error loading module!\n");
+    return 1;
+  }
+  fseek(file, 0L, SEEK_END);
+  size_t file_size = ftell(file);
+  fseek(file, 0L, SEEK_SET);
+  wasm_byte_vec_t binary;
+  wasm_byte_vec_new_uninitialized(&binary, file_size);
+  if (fread(binary.data, file_size, 1, file) != 1) {
+    printf("> Error loading module!\n");
+    return 1;
+  }
+  fclose(file);
+
+  wasm_module_t* module = wasm_module_new(store, &binary);
+  if (!module) {
+    printf("> Error compiling module!\n");
+    return 1;
+  }
+  wasm_byte_vec_delete(&binary);
+
+  // A global is given for the function, and nothing for the global and the
+  // memory.
+  printf("Instantiating module...\n");
+  wasm_globaltype_t* global_type =
+    wasm_globaltype_new(wasm_valtype_new_i32(), WASM_CONST);
+  wasm_val_t value = { .kind = WASM_I32, .of = { .i32 = 1 } };
+  wasm_global_t* global = wasm_global_new(store, global_type, &value);
+  wasm_globaltype_delete(global_type);
+  const wasm_extern_t* imports[] = { wasm_global_as_extern(global), NULL };
+
+  wasm_instance_t* instance = wasm_instance_new(store, module, imports, NULL);
+  if (instance) {
+    printf("> The module was instantiated despite its unresolved imports!\n");
+    return 1;
+  }
+
+  int error_len = wasmer_last_error_length();
+  char* error_str = malloc(error_len);
+  wasmer_last_error_message(error_str, error_len);
+  printf("Error str: `%s`\n", error_str);
+  for (size_t i = 0; i < sizeof(EXPECTED_ERRORS) / sizeof(EXPECTED_ERRORS[0]); ++i) {
+    if (!strstr(error_str, EXPECTED_ERRORS[i])) {
+      printf("> The error doesn't report `%s`!\n", EXPECTED_ERRORS[i]);
+      return 1;
+    }
+  }
+  free(error_str);
+
+  wasm_global_delete(global);
+  wasm_module_delete(module);
+  wasm_store_delete(store);
+  wasm_engine_delete(engine);
+
+  printf("Done.\n");
+  return 0;
+}
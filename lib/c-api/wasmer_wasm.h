@@ -99,6 +99,24 @@ own wasm_func_t* wasi_get_start_function(wasm_instance_t*);
 // Delete a `wasm_extern_t` allocated by the API.
 void wasm_extern_delete(own wasm_extern_t*);
 
+// The engines a `wasm_engine_t` can be created with.
+typedef uint32_t wasmer_engine_t;
+
+enum {
+  WASMER_ENGINE_JIT = 0,
+  WASMER_ENGINE_NATIVE = 1,
+};
+
+// Select the engine created by `wasm_engine_new_with_config`, which
+// returns null if it isn't enabled in the library.
+void wasm_config_set_engine(wasm_config_t*, wasmer_engine_t);
+
+// Load the module compiled to native code and statically linked into the
+// current executable by `wasmer create-exe`.
+//
+// The engine of the store must be the native one.
+own wasm_module_t* wasmer_module_deserialize_from_executable(wasm_store_t*);
+
 // TODO: figure out if we can do less duplication.
 /**
  * Gets the length in bytes of the last error if any.
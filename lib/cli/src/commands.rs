@@ -2,6 +2,8 @@
 mod cache;
 mod compile;
 mod config;
+#[cfg(all(feature = "native", feature = "compiler"))]
+mod create_exe;
 mod inspect;
 mod run;
 mod self_update;
@@ -9,6 +11,8 @@ mod validate;
 #[cfg(feature = "wast")]
 mod wast;
 
+#[cfg(all(feature = "native", feature = "compiler"))]
+pub use create_exe::*;
 #[cfg(feature = "wast")]
 pub use wast::*;
 pub use {cache::*, compile::*, config::*, inspect::*, run::*, self_update::*, validate::*};
@@ -0,0 +1,170 @@
+//! Create a standalone native executable for a given Wasm file.
+//!
+//! The module is compiled to native code by the native engine, into an
+//! object file holding its functions and metadata. It's linked with a C
+//! entry point and the static `libwasmer`, and loaded from the symbols the
+//! executable exports when it starts.
+
+use crate::store::StoreOptions;
+use anyhow::{bail, Context, Result};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use structopt::StructOpt;
+use wasmer::*;
+use wasmer_engine::Engine;
+use wasmer_engine_native::NativeArtifact;
+
+/// The entry point of the generated executables, running the module with
+/// WASI.
+const WASMER_MAIN_C_SOURCE: &[u8] = include_bytes!("wasmer_create_exe_main.c");
+
+#[derive(Debug, StructOpt)]
+/// The options for the `wasmer create-exe` subcommand
+///
+/// The executable bundles the module compiled to native code with the
+/// Wasmer runtime from the static `libwasmer`. It runs the module with WASI.
+pub struct CreateExe {
+    /// Input file
+    #[structopt(name = "FILE", parse(from_os_str))]
+    path: PathBuf,
+
+    /// Output file
+    #[structopt(name = "OUTPUT PATH", short = "o", parse(from_os_str))]
+    output: PathBuf,
+
+    #[structopt(flatten)]
+    store: StoreOptions,
+
+    /// Additional libraries to link against.
+    /// This is useful for fixing linker errors that may occur on some systems.
+    #[structopt(short = "l", multiple = true, number_of_values = 1)]
+    libraries: Vec<String>,
+
+    /// Print the engine, the compiler and the target used.
+    #[structopt(short = "v", long = "verbose")]
+    verbose: bool,
+}
+
+impl CreateExe {
+    /// Runs logic for the `create-exe` subcommand
+    pub fn execute(&self) -> Result<()> {
+        self.inner_execute().context(format!(
+            "failed to create an executable from `{}`",
+            self.path.display()
+        ))
+    }
+
+    fn inner_execute(&self) -> Result<()> {
+        if cfg!(windows) {
+            bail!("creating executables isn't supported on Windows yet");
+        }
+        let wasmer_dir = get_wasmer_dir()?;
+        let libwasmer_path = wasmer_dir.join("lib").join("libwasmer.a");
+        if !libwasmer_path.exists() {
+            bail!(
+                "the static library `{}` is missing, it's required to link the executable",
+                libwasmer_path.display()
+            );
+        }
+
+        let target = Target::default();
+        let (engine, compiler_type) = self.store.get_native_engine_for_target(target.clone())?;
+        if self.verbose {
+            eprintln!("Engine: native");
+            eprintln!("Compiler: {}", compiler_type.to_string());
+            eprintln!("Target: {}", target.triple());
+        }
+
+        let wasm_bytes = fs::read(&self.path)?;
+        #[cfg(feature = "wat")]
+        let wasm_bytes = wat2wasm(&wasm_bytes)?;
+        engine.validate(&wasm_bytes)?;
+        let object =
+            NativeArtifact::generate_object(&engine, &wasm_bytes, &Tunables::for_target(&target))
+                .context("failed to compile the module")?;
+
+        let working_dir = tempfile::tempdir()?;
+        let object_path = working_dir.path().join("wasm.o");
+        let main_path = working_dir.path().join("wasmer_main.c");
+        fs::write(&object_path, object)?;
+        fs::write(&main_path, WASMER_MAIN_C_SOURCE)?;
+
+        link(
+            &main_path,
+            &object_path,
+            &libwasmer_path,
+            &wasmer_dir.join("include"),
+            &self.libraries,
+            &self.output,
+        )?;
+
+        eprintln!(
+            "✔ Native executable compiled successfully to `{}`.",
+            self.output.display(),
+        );
+        Ok(())
+    }
+}
+
+/// Returns the directory Wasmer is installed in, holding the static
+/// library in `lib` and the headers in `include`.
+fn get_wasmer_dir() -> Result<PathBuf> {
+    let key = "WASMER_DIR";
+    Ok(PathBuf::from(env::var(key).context(format!(
+        "failed to retrieve the {} environment variable",
+        key
+    ))?))
+}
+
+/// Compiles the entry point, and links it with the module and the static
+/// `libwasmer` into the executable at `output`.
+///
+/// The symbols of the executable are exported, so that the native engine
+/// finds the functions of the module.
+fn link(
+    main_path: &Path,
+    object_path: &Path,
+    libwasmer_path: &Path,
+    include_dir: &Path,
+    libraries: &[String],
+    output: &Path,
+) -> Result<()> {
+    // The system libraries the Rust standard library depends on.
+    let system_libraries: &[&str] = if cfg!(target_os = "linux") {
+        &["pthread", "dl", "m", "rt"]
+    } else {
+        &["pthread", "dl", "m"]
+    };
+
+    let linker = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let output_status = Command::new(&linker)
+        .arg("-O2")
+        .arg("-rdynamic")
+        .arg("-I")
+        .arg(include_dir)
+        .arg(main_path)
+        .arg(object_path)
+        .arg(libwasmer_path)
+        .args(
+            system_libraries
+                .iter()
+                .map(|lib| lib.to_string())
+                .chain(libraries.iter().cloned())
+                .map(|lib| format!("-l{}", lib)),
+        )
+        .arg("-o")
+        .arg(output)
+        .output()
+        .context(format!("failed to run the `{}` linker", linker))?;
+
+    if !output_status.status.success() {
+        bail!(
+            "linking failed with: stdout: {}\n\nstderr: {}",
+            String::from_utf8_lossy(&output_status.stdout),
+            String::from_utf8_lossy(&output_status.stderr),
+        );
+    }
+    Ok(())
+}
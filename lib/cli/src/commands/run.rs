@@ -13,9 +13,12 @@ use wasmer_cache::{Cache, FileSystemCache, Hash};
 
 use structopt::StructOpt;
 
+mod profile;
 #[cfg(feature = "wasi")]
 mod wasi;
 
+use profile::Profile;
+
 #[cfg(feature = "wasi")]
 use wasi::Wasi;
 
@@ -49,11 +52,27 @@ pub struct Run {
     #[structopt(flatten)]
     store: StoreOptions,
 
-    // TODO: refactor WASI structure to allow shared options with Emscripten
+    /// Profile the execution, measuring the time spent in each function
+    /// and host import. A perf map of the compiled code is written too
+    #[structopt(long = "profile")]
+    profile: bool,
+
+    /// Where to write the profile, in the folded stacks format of
+    /// flamegraph tools. Defaults to `<FILE>.folded`
+    #[structopt(long = "profile-output", parse(from_os_str), requires = "profile")]
+    profile_output: Option<PathBuf>,
+
+    // The WASI options also set up the file system of Emscripten modules
     #[cfg(feature = "wasi")]
     #[structopt(flatten)]
     wasi: Wasi,
 
+    /// Instantiate the module even if some of its imports are missing.
+    /// The missing functions trap when called, and the other missing
+    /// items are created with default values
+    #[structopt(long = "allow-missing-imports")]
+    allow_missing_imports: bool,
+
     /// Enable non-standard experimental IO devices
     #[cfg(feature = "io-devices")]
     #[structopt(long = "enable-io-devices")]
@@ -94,11 +113,36 @@ impl Run {
     }
 
     fn inner_execute(&self) -> Result<()> {
-        let module = self.get_module()?;
+        let profile = if self.profile {
+            let output = self.profile_output.clone().unwrap_or_else(|| {
+                let mut output = PathBuf::from(self.path.file_name().unwrap_or_default());
+                output.set_extension("folded");
+                output
+            });
+            Some(Profile::new(output))
+        } else {
+            None
+        };
+        let module = self.get_module(profile.as_ref())?;
+        let result = self.execute_module(&module, profile.as_ref());
+        if let Some(profile) = &profile {
+            profile.finish()?;
+        }
+        result
+    }
+
+    fn execute_module(&self, module: &Module, profile: Option<&Profile>) -> Result<()> {
+        let imports = || {
+            let mut imports = imports! {};
+            if let Some(profile) = profile {
+                profile.register_imports(module.store(), &mut imports);
+            }
+            imports
+        };
         // Do we want to invoke a function?
         if let Some(ref invoke) = self.invoke {
-            let imports = imports! {};
-            let instance = Instance::new(&module, &imports)?;
+            let imports = imports();
+            let instance = instantiate(module, &imports, self.allow_missing_imports)?;
             let result = self.invoke_function(&instance, &invoke, &self.args)?;
             println!(
                 "{}",
@@ -117,13 +161,16 @@ impl Run {
                 EmscriptenGlobals,
             };
             // TODO: refactor this
-            if is_emscripten_module(&module) {
-                let mut emscripten_globals = EmscriptenGlobals::new(module.store(), &module)
-                    .map_err(|e| anyhow!("{}", e))?;
-                let mut em_env = EmEnv::new();
-                let import_object =
+            if is_emscripten_module(module) {
+                let mut emscripten_globals =
+                    EmscriptenGlobals::new(module.store(), module).map_err(|e| anyhow!("{}", e))?;
+                let mut em_env = EmEnv::new_with_fs(self.wasi.emscripten_fs()?);
+                let mut import_object =
                     generate_emscripten_env(module.store(), &mut emscripten_globals, &mut em_env);
-                let mut instance = Instance::new(&module, &import_object)
+                if let Some(profile) = profile {
+                    profile.register_imports(module.store(), &mut import_object);
+                }
+                let mut instance = instantiate(module, &import_object, self.allow_missing_imports)
                     .with_context(|| "Can't instantiate emscripten module")?;
 
                 run_emscripten_instance(
@@ -136,8 +183,7 @@ impl Run {
                         self.path.to_str().unwrap()
                     },
                     self.args.iter().map(|arg| arg.as_str()).collect(),
-                    None,   //run.em_entrypoint.clone(),
-                    vec![], //mapped_dirs,
+                    None, //run.em_entrypoint.clone(),
                 )?;
                 return Ok(());
             }
@@ -146,7 +192,14 @@ impl Run {
         // If WASI is enabled, try to execute it with it
         #[cfg(feature = "wasi")]
         {
-            let wasi_version = Wasi::get_version(&module);
+            // The profiler hooks live in their own namespace, and the
+            // missing imports can be in any namespace, so the detection
+            // can't be strict then.
+            let wasi_version = if profile.is_some() || self.allow_missing_imports {
+                wasmer_wasi::get_wasi_version(module, false)
+            } else {
+                Wasi::get_version(module)
+            };
             if wasi_version.is_some() {
                 let program_name = self
                     .command_name
@@ -159,25 +212,34 @@ impl Run {
                     .unwrap_or_default();
                 return self
                     .wasi
-                    .execute(module, program_name, self.args.clone())
+                    .execute(
+                        module,
+                        program_name,
+                        self.args.clone(),
+                        profile,
+                        self.allow_missing_imports,
+                    )
                     .with_context(|| "WASI execution failed");
             }
         }
 
         // Try to instantiate the wasm file, with no provided imports
-        let imports = imports! {};
-        let instance = Instance::new(&module, &imports)?;
+        let imports = imports();
+        let instance = instantiate(module, &imports, self.allow_missing_imports)?;
         let start: Function = self.try_find_function(&instance, "_start", &[])?;
         start.call(&[])?;
 
         Ok(())
     }
 
-    fn get_module(&self) -> Result<Module> {
+    fn get_module(&self, profile: Option<&Profile>) -> Result<Module> {
         let contents = std::fs::read(self.path.clone())?;
         #[cfg(feature = "native")]
         {
             if wasmer_engine_native::NativeArtifact::is_deserializable(&contents) {
+                if profile.is_some() {
+                    bail!("precompiled modules can't be profiled");
+                }
                 let engine = wasmer_engine_native::Native::headless().engine();
                 let store = Store::new(&engine);
                 let module = unsafe { Module::deserialize_from_file(&store, &self.path)? };
@@ -187,6 +249,9 @@ impl Run {
         #[cfg(feature = "jit")]
         {
             if wasmer_engine_jit::JITArtifact::is_deserializable(&contents) {
+                if profile.is_some() {
+                    bail!("precompiled modules can't be profiled");
+                }
                 let engine = wasmer_engine_jit::JIT::headless().engine();
                 let store = Store::new(&engine);
                 let module = unsafe { Module::deserialize_from_file(&store, &self.path)? };
@@ -195,13 +260,20 @@ impl Run {
         }
         let (store, engine_type, compiler_type) = self.store.get_store()?;
         #[cfg(feature = "cache")]
-        let module_result: Result<Module> = if !self.disable_cache && contents.len() > 0x1000 {
+        let module_result: Result<Module> = if let Some(profile) = profile {
+            // Instrumented modules are not cached.
+            profile.compile(&store, &contents)
+        } else if !self.disable_cache && contents.len() > 0x1000 {
             self.get_module_from_cache(&store, &contents, &engine_type, &compiler_type)
         } else {
             Module::new(&store, &contents).map_err(|e| e.into())
         };
         #[cfg(not(feature = "cache"))]
-        let module_result = Module::new(&store, &contents);
+        let module_result: Result<Module> = if let Some(profile) = profile {
+            profile.compile(&store, &contents)
+        } else {
+            Module::new(&store, &contents).map_err(|e| e.into())
+        };
 
         let mut module = module_result.with_context(|| {
             format!(
@@ -212,6 +284,9 @@ impl Run {
         })?;
         // We set the name outside the cache, to make sure we dont cache the name
         module.set_name(&self.path.file_name().unwrap_or_default().to_string_lossy());
+        if let Some(profile) = profile {
+            profile.write_perf_map(&module)?;
+        }
 
         Ok(module)
     }
@@ -383,3 +458,24 @@ impl Run {
         Ok(func.call(&invoke_args)?)
     }
 }
+
+/// Instantiates `module` with `imports`. With `--allow-missing-imports`, the
+/// imports that are missing are replaced by placeholders.
+fn instantiate(
+    module: &Module,
+    imports: &ImportObject,
+    allow_missing_imports: bool,
+) -> Result<Instance> {
+    if !allow_missing_imports {
+        return Ok(Instance::new(module, imports)?);
+    }
+    let resolver = StubResolver::new(module, imports);
+    for import in resolver.missing_imports() {
+        warning!(
+            "the import `{}.{}` is missing, a placeholder is used instead",
+            import.module(),
+            import.name()
+        );
+    }
+    Ok(Instance::new(module, &resolver)?)
+}
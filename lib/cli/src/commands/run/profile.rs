@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::path::PathBuf;
+use wasmer::{ImportObject, Module, Store};
+use wasmer_profiler::{write_perf_map, FunctionKind, Profiler};
+
+/// The number of functions shown in the profile summary.
+const SUMMARY_FUNCTIONS: usize = 10;
+
+/// The profiling of a `wasmer run` execution.
+pub struct Profile {
+    profiler: Profiler,
+    /// Where the folded stacks are written.
+    output: PathBuf,
+}
+
+impl Profile {
+    pub fn new(output: PathBuf) -> Self {
+        Self {
+            profiler: Profiler::new(),
+            output,
+        }
+    }
+
+    /// Instruments and compiles `wasm`.
+    pub fn compile(&self, store: &Store, wasm: &[u8]) -> Result<Module> {
+        #[cfg(feature = "wat")]
+        let wasm = wasmer::wat2wasm(wasm)?;
+        Ok(self.profiler.compile(store, &wasm)?)
+    }
+
+    /// Writes the perf map of `module`, so `perf` can symbolize its code.
+    pub fn write_perf_map(&self, module: &Module) -> Result<()> {
+        let perf_map = write_perf_map(module).context("failed to write the perf map")?;
+        eprintln!("Perf map written to `{}`", perf_map.display());
+        Ok(())
+    }
+
+    /// Adds the profiler hooks to `import_object`.
+    pub fn register_imports(&self, store: &Store, import_object: &mut ImportObject) {
+        self.profiler.register_imports(store, import_object);
+    }
+
+    /// Writes the folded stacks, and prints a summary of the most expensive
+    /// functions.
+    pub fn finish(&self) -> Result<()> {
+        let output = File::create(&self.output)
+            .with_context(|| format!("failed to create the profile `{}`", self.output.display()))?;
+        self.profiler.write_folded(output)?;
+
+        eprintln!(
+            "{:>12} {:>12} {:>10}  function",
+            "self (ms)", "total (ms)", "calls"
+        );
+        for function in self.profiler.functions().iter().take(SUMMARY_FUNCTIONS) {
+            eprintln!(
+                "{:>12.3} {:>12.3} {:>10}  {}{}",
+                function.self_time.as_secs_f64() * 1000.0,
+                function.total_time.as_secs_f64() * 1000.0,
+                function.calls,
+                function.name,
+                if function.kind == FunctionKind::Import {
+                    " (import)"
+                } else {
+                    ""
+                }
+            );
+        }
+        eprintln!(
+            "Profile written to `{}`, in the folded stacks format of flamegraph tools",
+            self.output.display()
+        );
+        Ok(())
+    }
+}
@@ -1,7 +1,8 @@
+use super::Profile;
 use crate::utils::{parse_envvar, parse_mapdir};
 use anyhow::{Context, Result};
 use std::path::PathBuf;
-use wasmer::{Instance, Module};
+use wasmer::Module;
 use wasmer_wasi::{get_wasi_version, WasiError, WasiState, WasiVersion};
 
 use structopt::StructOpt;
@@ -35,8 +36,26 @@ impl Wasi {
         get_wasi_version(&module, true)
     }
 
+    /// Builds the file system of an Emscripten module, with the
+    /// directories given by `--dir` and `--mapdir`.
+    #[cfg(feature = "emscripten")]
+    pub fn emscripten_fs(&self) -> Result<wasmer_wasi::WasiFs> {
+        let wasi_state = WasiState::new("")
+            .preopen_dirs(self.pre_opened_directories.clone())?
+            .map_dirs(self.mapped_dirs.clone())?
+            .build()?;
+        Ok(wasi_state.fs)
+    }
+
     /// Helper function for executing Wasi from the `Run` command.
-    pub fn execute(&self, module: Module, program_name: String, args: Vec<String>) -> Result<()> {
+    pub fn execute(
+        &self,
+        module: &Module,
+        program_name: String,
+        args: Vec<String>,
+        profile: Option<&Profile>,
+        allow_missing_imports: bool,
+    ) -> Result<()> {
         let args = args.iter().cloned().map(|arg| arg.into_bytes());
 
         let mut wasi_state_builder = WasiState::new(program_name);
@@ -55,10 +74,11 @@ impl Wasi {
         }
 
         let mut wasi_env = wasi_state_builder.finalize()?;
-        let import_object = wasi_env.import_object(&module)?;
-        let instance = Instance::new(&module, &import_object)?;
-
-        wasi_env.set_memory(instance.exports.get_memory("memory")?.clone());
+        let mut import_object = wasi_env.import_object(module)?;
+        if let Some(profile) = profile {
+            profile.register_imports(module.store(), &mut import_object);
+        }
+        let instance = super::instantiate(module, &import_object, allow_missing_imports)?;
 
         let start = instance.exports.get_function("_start")?;
         let result = start.call(&[]);
@@ -68,6 +88,9 @@ impl Wasi {
             Err(err) => {
                 let err: anyhow::Error = match err.downcast::<WasiError>() {
                     Ok(WasiError::Exit(exit_code)) => {
+                        if let Some(profile) = profile {
+                            profile.finish()?;
+                        }
                         // We should exit with the provided exit code
                         std::process::exit(exit_code as _);
                     }
@@ -0,0 +1,109 @@
+// The entry point of the executables generated by `wasmer create-exe`.
+//
+// The module, compiled to native code by `wasmer create-exe`, is linked in
+// along with its metadata, and loaded by the native engine from the symbols
+// exported by the executable. It's instantiated with WASI, and its `_start`
+// function is called with the arguments of the executable.
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "wasmer_wasm.h"
+
+static const char WASI_EXIT_PREFIX[] = "WASI exited with code: ";
+
+static void print_wasmer_error() {
+  int error_len = wasmer_last_error_length();
+  if (error_len > 0) {
+    char *error_str = malloc(error_len);
+    wasmer_last_error_message(error_str, error_len);
+    fprintf(stderr, "Error: %s\n", error_str);
+    free(error_str);
+  }
+}
+
+int main(int argc, char *argv[]) {
+  wasm_config_t *engine_config = wasm_config_new();
+  wasm_config_set_engine(engine_config, WASMER_ENGINE_NATIVE);
+  wasm_engine_t *engine = wasm_engine_new_with_config(engine_config);
+  if (!engine) {
+    fprintf(stderr, "The native engine isn't enabled in the library.\n");
+    return 1;
+  }
+  wasm_store_t *store = wasm_store_new(engine);
+
+  wasm_module_t *module = wasmer_module_deserialize_from_executable(store);
+  if (!module) {
+    fprintf(stderr, "Failed to load the module.\n");
+    print_wasmer_error();
+    return 1;
+  }
+
+  wasi_config_t *config = wasi_config_new(argv[0]);
+  for (int i = 1; i < argc; i++) {
+    wasi_config_arg(config, argv[i]);
+  }
+  wasi_env_t *wasi_env = wasi_env_new(config);
+  if (!wasi_env) {
+    fprintf(stderr, "Failed to create the WASI environment.\n");
+    print_wasmer_error();
+    return 1;
+  }
+
+  wasm_importtype_vec_t import_types;
+  wasm_module_imports(module, &import_types);
+  wasm_extern_t **imports =
+      malloc(sizeof(wasm_extern_t *) * (import_types.size ? import_types.size : 1));
+  wasm_importtype_vec_delete(&import_types);
+  if (!wasi_get_imports(store, module, wasi_env, imports)) {
+    fprintf(stderr, "Failed to resolve the WASI imports.\n");
+    print_wasmer_error();
+    return 1;
+  }
+
+  wasm_instance_t *instance =
+      wasm_instance_new(store, module, (const wasm_extern_t *const *)imports, NULL);
+  if (!instance) {
+    fprintf(stderr, "Failed to instantiate the module.\n");
+    print_wasmer_error();
+    return 1;
+  }
+  if (!wasi_env_set_instance(wasi_env, instance)) {
+    fprintf(stderr, "Failed to bind the WASI environment to the instance.\n");
+    return 1;
+  }
+
+  wasm_func_t *start = wasi_get_start_function(instance);
+  if (!start) {
+    fprintf(stderr, "The module doesn't export a `_start` function.\n");
+    print_wasmer_error();
+    return 1;
+  }
+
+  int exit_code = 0;
+  wasm_trap_t *trap = wasm_func_call(start, NULL, NULL);
+  if (trap) {
+    wasm_byte_vec_t message;
+    wasm_trap_message(trap, &message);
+    // `proc_exit` stops the program with a trap holding its exit code.
+    const char *exit = strstr(message.data, WASI_EXIT_PREFIX);
+    if (exit) {
+      exit_code = atoi(exit + strlen(WASI_EXIT_PREFIX));
+    } else {
+      fprintf(stderr, "Error: %s\n", message.data);
+      exit_code = 1;
+    }
+    wasm_byte_vec_delete(&message);
+    wasm_trap_delete(trap);
+  }
+
+  wasm_func_delete(start);
+  wasm_instance_delete(instance);
+  free(imports);
+  wasi_env_delete(wasi_env);
+  wasm_module_delete(module);
+  wasm_store_delete(store);
+  wasm_engine_delete(engine);
+  return exit_code;
+}
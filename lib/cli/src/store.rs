@@ -326,6 +326,25 @@ impl StoreOptions {
         Ok((store, engine_type, compiler_type))
     }
 
+    /// Gets the native engine for a given target, with the compiler name
+    /// selected, for the commands emitting native code.
+    #[cfg(feature = "native")]
+    pub fn get_native_engine_for_target(
+        &self,
+        target: Target,
+    ) -> Result<(wasmer_engine_native::NativeEngine, CompilerType)> {
+        if self.jit {
+            bail!("The `jit` engine can't emit native code, use the `native` one.");
+        }
+        let (mut compiler_config, compiler_type) = self.get_compiler_config()?;
+        let features = self.get_features(compiler_config.default_features_for_target(&target))?;
+        let engine = wasmer_engine_native::Native::new(&mut *compiler_config)
+            .target(target)
+            .features(features)
+            .engine();
+        Ok((engine, compiler_type))
+    }
+
     fn get_engine_with_compiler(
         &self,
         target: Target,
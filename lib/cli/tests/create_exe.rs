@@ -0,0 +1,126 @@
+//! Testing `wasmer create-exe`, by building standalone executables from
+//! WASI modules and running them.
+//!
+//! The executables are linked against the static library and the headers
+//! of the Wasmer package, in `WASMER_DIR` or built by `make package-capi`.
+//! The tests are skipped when they aren't packaged.
+
+#![cfg(all(feature = "native", feature = "compiler"))]
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+/// Returns the Wasmer package the executables are linked with, if it holds
+/// the static library and the headers.
+fn wasmer_package() -> Option<PathBuf> {
+    let package = env::var_os("WASMER_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("../../package"));
+    let artifacts = ["lib/libwasmer.a", "include/wasmer_wasm.h", "include/wasm.h"];
+    if artifacts
+        .iter()
+        .all(|artifact| package.join(artifact).exists())
+    {
+        Some(package)
+    } else {
+        eprintln!(
+            "skipped: `{}` doesn't hold the static library and the headers, run `make package-capi`",
+            package.display()
+        );
+        None
+    }
+}
+
+/// Creates the executable `name` from the Wasm or WAT file `wasm`, in `dir`.
+fn create_exe(package: &Path, dir: &TempDir, wasm: &Path, name: &str) -> PathBuf {
+    let executable = dir.path().join(name);
+    let output = Command::new(env!("CARGO_BIN_EXE_wasmer"))
+        .env("WASMER_DIR", package)
+        .arg("create-exe")
+        .arg(wasm)
+        .arg("-o")
+        .arg(&executable)
+        .output()
+        .expect("failed to run `wasmer create-exe`");
+    assert!(
+        output.status.success(),
+        "`wasmer create-exe` failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    executable
+}
+
+fn run(executable: &Path, args: &[&str]) -> Output {
+    // The executable must not depend on the `wasmer` CLI or on the package.
+    Command::new(executable)
+        .args(args)
+        .env_remove("WASMER_DIR")
+        .output()
+        .expect("failed to run the executable")
+}
+
+#[test]
+fn create_exe_runs_a_wasi_hello_world() {
+    let package = match wasmer_package() {
+        Some(package) => package,
+        None => return,
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let wasm = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../tests/wasi-wast/wasi/snapshot1/hello.wasm");
+    let executable = create_exe(&package, &dir, &wasm, "hello");
+
+    let output = run(&executable, &[]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Hello, world!\n");
+}
+
+#[test]
+fn create_exe_passes_the_arguments_and_the_exit_code() {
+    let package = match wasmer_package() {
+        Some(package) => package,
+        None => return,
+    };
+    let dir = tempfile::tempdir().unwrap();
+    // Prints its arguments after the program name, one per line, then exits
+    // with code 42.
+    let wat = r#"(module
+      (import "wasi_snapshot_preview1" "args_sizes_get"
+        (func $args_sizes_get (param i32 i32) (result i32)))
+      (import "wasi_snapshot_preview1" "args_get"
+        (func $args_get (param i32 i32) (result i32)))
+      (import "wasi_snapshot_preview1" "fd_write"
+        (func $fd_write (param i32 i32 i32 i32) (result i32)))
+      (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+      (memory (export "memory") 1)
+      (func (export "_start")
+        (local $start i32) (local $end i32) (local $i i32)
+        (drop (call $args_sizes_get (i32.const 0) (i32.const 4)))
+        (drop (call $args_get (i32.const 16) (i32.const 1024)))
+        ;; The arguments after the program name, and the end of the buffer.
+        (local.set $start (i32.load (i32.const 20)))
+        (local.set $end (i32.add (i32.const 1024) (i32.load (i32.const 4))))
+        ;; Their null terminators are turned into new lines.
+        (local.set $i (local.get $start))
+        (block $done
+          (loop $next
+            (br_if $done (i32.ge_u (local.get $i) (local.get $end)))
+            (if (i32.eqz (i32.load8_u (local.get $i)))
+              (then (i32.store8 (local.get $i) (i32.const 10))))
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            (br $next)))
+        (i32.store (i32.const 8) (local.get $start))
+        (i32.store (i32.const 12) (i32.sub (local.get $end) (local.get $start)))
+        (drop (call $fd_write (i32.const 1) (i32.const 8) (i32.const 1) (i32.const 0)))
+        (call $proc_exit (i32.const 42))))"#;
+    let wat_path = dir.path().join("args.wat");
+    fs::write(&wat_path, wat).unwrap();
+    let executable = create_exe(&package, &dir, &wat_path, "args");
+
+    let output = run(&executable, &["first", "second"]);
+    assert_eq!(output.status.code(), Some(42));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "first\nsecond\n");
+}
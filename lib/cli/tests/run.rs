@@ -0,0 +1,37 @@
+//! Testing `wasmer run`.
+
+#![cfg(all(feature = "wat", feature = "compiler"))]
+
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn run_reports_all_the_unresolved_imports() {
+    let dir = tempfile::tempdir().unwrap();
+    let wat_path = dir.path().join("imports.wat");
+    fs::write(
+        &wat_path,
+        r#"(module
+          (import "env" "f" (func (param i32)))
+          (import "env" "g" (global i64))
+          (import "host" "memory" (memory 1))
+          (func (export "_start")))"#,
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_wasmer"))
+        .arg("run")
+        .arg(&wat_path)
+        .output()
+        .expect("failed to run `wasmer run`");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    for expected in &[
+        "3 unresolved import(s)",
+        "env.f: unknown import, expected Function",
+        "env.g: unknown import, expected Global",
+        "host.memory: unknown import, expected Memory",
+    ] {
+        assert!(stderr.contains(expected), "{}", stderr);
+    }
+}
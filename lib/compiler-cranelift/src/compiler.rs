@@ -14,16 +14,18 @@ use crate::translator::{
     CraneliftUnwindInfo, FuncTranslator,
 };
 use cranelift_codegen::ir;
+use cranelift_codegen::isa::TargetIsa;
 use cranelift_codegen::print_errors::pretty_error;
 use cranelift_codegen::{binemit, Context};
 #[cfg(feature = "unwind")]
-use gimli::write::{Address, EhFrame, FrameTable};
+use gimli::write::{Address, CieId, EhFrame, FrameTable};
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use wasmer_compiler::CompileError;
 use wasmer_compiler::{CallingConvention, ModuleTranslationState, Target};
 use wasmer_compiler::{
     Compilation, CompileModuleInfo, CompiledFunction, CompiledFunctionFrameInfo,
-    CompiledFunctionUnwindInfo, Compiler, Dwarf, FunctionBody, FunctionBodyData, SectionIndex,
+    CompiledFunctionUnwindInfo, Compiler, CustomSection, Dwarf, FunctionBody, FunctionBodyData,
+    SectionIndex,
 };
 use wasmer_types::entity::{EntityRef, PrimaryMap};
 use wasmer_types::{FunctionIndex, LocalFunctionIndex, SignatureIndex};
@@ -46,6 +48,95 @@ impl CraneliftCompiler {
     pub fn config(&self) -> &Cranelift {
         &self.config
     }
+
+    /// Creates the ISA to compile the module for the given target.
+    fn isa(&self, target: &Target, compile_info: &CompileModuleInfo) -> Box<dyn TargetIsa> {
+        // Deterministic modules always canonicalize their NaNs.
+        if compile_info.features.deterministic {
+            let mut config = self.config().clone();
+            config.canonicalize_nans(true);
+            config.isa(target)
+        } else {
+            self.config().isa(target)
+        }
+    }
+
+    /// Compiles a function body, returning its unwind information apart,
+    /// since it depends on how the whole module is compiled.
+    #[allow(clippy::too_many_arguments)]
+    fn compile_function_body(
+        &self,
+        isa: &dyn TargetIsa,
+        compile_info: &CompileModuleInfo,
+        signatures: &PrimaryMap<SignatureIndex, ir::Signature>,
+        module_translation: &ModuleTranslationState,
+        func_translator: &mut FuncTranslator,
+        i: LocalFunctionIndex,
+        input: &FunctionBodyData<'_>,
+    ) -> Result<(CompiledFunction, CraneliftUnwindInfo), CompileError> {
+        let module = &compile_info.module;
+        let func_index = module.func_index(i);
+        let mut context = Context::new();
+        let mut func_env = FuncEnvironment::new(
+            isa.frontend_config(),
+            module,
+            signatures,
+            &compile_info.memory_styles,
+            &compile_info.table_styles,
+        );
+        context.func.name = get_function_name(func_index);
+        context.func.signature = signatures[module.functions[func_index]].clone();
+        // if generate_debug_info {
+        //     context.func.collect_debug_info();
+        // }
+
+        func_translator.translate(
+            module_translation,
+            input.data,
+            input.module_offset,
+            &mut context.func,
+            &mut func_env,
+            i,
+            &self.config,
+        )?;
+
+        let mut code_buf: Vec<u8> = Vec::new();
+        let mut reloc_sink = RelocSink::new(module, func_index);
+        let mut trap_sink = TrapSink::new();
+        let mut stackmap_sink = binemit::NullStackmapSink {};
+        context
+            .compile_and_emit(
+                isa,
+                &mut code_buf,
+                &mut reloc_sink,
+                &mut trap_sink,
+                &mut stackmap_sink,
+            )
+            .map_err(|error| {
+                CompileError::Codegen(pretty_error(&context.func, Some(isa), error))
+            })?;
+
+        let unwind_info = compiled_function_unwind_info(isa, &context)?;
+
+        let address_map = get_function_address_map(&context, input, code_buf.len(), isa);
+
+        // We transform the Cranelift JumpTable's into compiler JumpTables
+        let func_jt_offsets = transform_jump_table(context.func.jt_offsets);
+
+        let function = CompiledFunction {
+            body: FunctionBody {
+                body: code_buf,
+                unwind_info: None,
+            },
+            jt_offsets: func_jt_offsets,
+            relocations: reloc_sink.func_relocs,
+            frame_info: CompiledFunctionFrameInfo {
+                address_map,
+                traps: trap_sink.traps,
+            },
+        };
+        Ok((function, unwind_info))
+    }
 }
 
 impl Compiler for CraneliftCompiler {
@@ -58,10 +149,8 @@ impl Compiler for CraneliftCompiler {
         module_translation: &ModuleTranslationState,
         function_body_inputs: PrimaryMap<LocalFunctionIndex, FunctionBodyData<'_>>,
     ) -> Result<Compilation, CompileError> {
-        let isa = self.config().isa(target);
+        let isa = self.isa(target, compile_info);
         let frontend_config = isa.frontend_config();
-        let memory_styles = &compile_info.memory_styles;
-        let table_styles = &compile_info.table_styles;
         let module = &compile_info.module;
         let signatures = module
             .signatures
@@ -78,20 +167,8 @@ impl Compiler for CraneliftCompiler {
             None
         } else {
             use std::sync::{Arc, Mutex};
-            match target.triple().default_calling_convention() {
-                Ok(CallingConvention::SystemV) => {
-                    match isa.create_systemv_cie() {
-                        Some(cie) => {
-                            let mut dwarf_frametable = FrameTable::default();
-                            let cie_id = dwarf_frametable.add_cie(cie);
-                            Some((Arc::new(Mutex::new(dwarf_frametable)), cie_id))
-                        }
-                        // Even though we are in a SystemV system, Cranelift doesn't support it
-                        None => None,
-                    }
-                }
-                _ => None,
-            }
+            frame_table(&*isa, target)
+                .map(|(dwarf_frametable, cie_id)| (Arc::new(Mutex::new(dwarf_frametable)), cie_id))
         };
 
         let functions = function_body_inputs
@@ -99,48 +176,16 @@ impl Compiler for CraneliftCompiler {
             .collect::<Vec<(LocalFunctionIndex, &FunctionBodyData<'_>)>>()
             .par_iter()
             .map_init(FuncTranslator::new, |func_translator, (i, input)| {
-                let func_index = module.func_index(*i);
-                let mut context = Context::new();
-                let mut func_env = FuncEnvironment::new(
-                    isa.frontend_config(),
-                    module,
+                let (mut function, unwind_info) = self.compile_function_body(
+                    &*isa,
+                    compile_info,
                     &signatures,
-                    &memory_styles,
-                    &table_styles,
-                );
-                context.func.name = get_function_name(func_index);
-                context.func.signature = signatures[module.functions[func_index]].clone();
-                // if generate_debug_info {
-                //     context.func.collect_debug_info();
-                // }
-
-                func_translator.translate(
                     module_translation,
-                    input.data,
-                    input.module_offset,
-                    &mut context.func,
-                    &mut func_env,
+                    func_translator,
                     *i,
-                    &self.config,
+                    input,
                 )?;
-
-                let mut code_buf: Vec<u8> = Vec::new();
-                let mut reloc_sink = RelocSink::new(module, func_index);
-                let mut trap_sink = TrapSink::new();
-                let mut stackmap_sink = binemit::NullStackmapSink {};
-                context
-                    .compile_and_emit(
-                        &*isa,
-                        &mut code_buf,
-                        &mut reloc_sink,
-                        &mut trap_sink,
-                        &mut stackmap_sink,
-                    )
-                    .map_err(|error| {
-                        CompileError::Codegen(pretty_error(&context.func, Some(&*isa), error))
-                    })?;
-
-                let unwind_info = match compiled_function_unwind_info(&*isa, &context)? {
+                function.body.unwind_info = match unwind_info {
                     #[cfg(feature = "unwind")]
                     CraneliftUnwindInfo::FDE(fde) => {
                         if let Some((dwarf_frametable, cie_id)) = &dwarf_frametable {
@@ -166,24 +211,7 @@ impl Compiler for CraneliftCompiler {
                     }
                     other => other.maybe_into_to_windows_unwind(),
                 };
-
-                let address_map = get_function_address_map(&context, input, code_buf.len(), &*isa);
-
-                // We transform the Cranelift JumpTable's into compiler JumpTables
-                let func_jt_offsets = transform_jump_table(context.func.jt_offsets);
-
-                Ok(CompiledFunction {
-                    body: FunctionBody {
-                        body: code_buf,
-                        unwind_info,
-                    },
-                    jt_offsets: func_jt_offsets,
-                    relocations: reloc_sink.func_relocs,
-                    frame_info: CompiledFunctionFrameInfo {
-                        address_map,
-                        traps: trap_sink.traps,
-                    },
-                })
+                Ok(function)
             })
             .collect::<Result<Vec<_>, CompileError>>()?
             .into_iter()
@@ -246,4 +274,73 @@ impl Compiler for CraneliftCompiler {
             dwarf,
         ))
     }
+
+    /// Compile a single function using Cranelift, along with the `.eh_frame`
+    /// section holding its DWARF unwind information, if any.
+    fn compile_function(
+        &self,
+        target: &Target,
+        compile_info: &CompileModuleInfo,
+        module_translation: &ModuleTranslationState,
+        index: LocalFunctionIndex,
+        input: &FunctionBodyData<'_>,
+    ) -> Result<(CompiledFunction, Option<CustomSection>), CompileError> {
+        let isa = self.isa(target, compile_info);
+        let frontend_config = isa.frontend_config();
+        let signatures = compile_info
+            .module
+            .signatures
+            .values()
+            .map(|func_type| signature_to_cranelift_ir(func_type, frontend_config))
+            .collect::<PrimaryMap<SignatureIndex, ir::Signature>>();
+        let (mut function, unwind_info) = self.compile_function_body(
+            &*isa,
+            compile_info,
+            &signatures,
+            module_translation,
+            &mut FuncTranslator::new(),
+            index,
+            input,
+        )?;
+        let eh_frame = match unwind_info {
+            #[cfg(feature = "unwind")]
+            CraneliftUnwindInfo::FDE(fde) => {
+                frame_table(&*isa, target).map(|(mut dwarf_frametable, cie_id)| {
+                    dwarf_frametable.add_fde(
+                        cie_id,
+                        fde.to_fde(Address::Symbol {
+                            symbol: WriterRelocate::FUNCTION_SYMBOL,
+                            addend: index.index() as _,
+                        }),
+                    );
+                    let mut eh_frame =
+                        EhFrame(WriterRelocate::new(target.triple().endianness().ok()));
+                    dwarf_frametable.write_eh_frame(&mut eh_frame).unwrap();
+                    function.body.unwind_info = Some(CompiledFunctionUnwindInfo::Dwarf);
+                    eh_frame.0.into_section()
+                })
+            }
+            other => {
+                function.body.unwind_info = other.maybe_into_to_windows_unwind();
+                None
+            }
+        };
+        Ok((function, eh_frame))
+    }
+}
+
+/// Creates the DWARF frame table, with its CIE, for the functions compiled
+/// for the given target, if it uses the SystemV calling convention.
+#[cfg(feature = "unwind")]
+fn frame_table(isa: &dyn TargetIsa, target: &Target) -> Option<(FrameTable, CieId)> {
+    match target.triple().default_calling_convention() {
+        Ok(CallingConvention::SystemV) => {
+            // Even though we are in a SystemV system, Cranelift doesn't support it
+            let cie = isa.create_systemv_cie()?;
+            let mut dwarf_frametable = FrameTable::default();
+            let cie_id = dwarf_frametable.add_cie(cie);
+            Some((dwarf_frametable, cie_id))
+        }
+        _ => None,
+    }
 }
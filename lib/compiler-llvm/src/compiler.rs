@@ -9,15 +9,32 @@ use inkwell::targets::FileType;
 use inkwell::DLLStorageClass;
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use wasmer_compiler::{
-    Compilation, CompileError, CompileModuleInfo, Compiler, CustomSection, CustomSectionProtection,
-    Dwarf, FunctionBodyData, ModuleTranslationState, RelocationTarget, SectionBody, SectionIndex,
-    Symbol, SymbolRegistry, Target,
+    Compilation, CompileError, CompileModuleInfo, CompiledFunction, CompiledFunctionUnwindInfo,
+    Compiler, CustomSection, CustomSectionProtection, Dwarf, FunctionBodyData, JumpTable,
+    ModuleTranslationState, RelocationTarget, SectionBody, SectionIndex, Symbol, SymbolRegistry,
+    Target,
 };
 use wasmer_types::entity::{EntityRef, PrimaryMap};
 use wasmer_types::{FunctionIndex, LocalFunctionIndex, SignatureIndex};
 
 //use std::sync::{Arc, Mutex};
 
+/// The zero-length CIE terminating the `.eh_frame` sections.
+const EH_FRAME_TERMINATOR: [u8; 16] = [
+    0x00, 0x00, 0x00, 0x00, // Length
+    0x00, 0x00, 0x00, 0x00, // CIE ID
+    0x10, // Version (must be 1)
+    0x00, // Augmentation data
+    0x00, // Code alignment factor
+    0x00, // Data alignment factor
+    0x00, // Return address register
+    0x00, 0x00, 0x00, // Padding to a multiple of 4 bytes
+];
+
+/// The alignment of the read-only sections appended to the functions
+/// compiled apart from their module.
+const APPENDED_SECTION_ALIGNMENT: usize = 16;
+
 /// A compiler that compiles a WebAssembly module with LLVM, translating the Wasm to LLVM IR,
 /// optimizing it and then translating to assembly.
 pub struct LLVMCompiler {
@@ -36,6 +53,16 @@ impl LLVMCompiler {
     fn config(&self) -> &LLVM {
         &self.config
     }
+
+    /// Gets the config to translate the functions of the module with.
+    fn function_config(&self, compile_info: &CompileModuleInfo) -> LLVM {
+        let mut config = self.config.clone();
+        // Deterministic modules always canonicalize their NaNs.
+        if compile_info.features.deterministic {
+            config.canonicalize_nans(true);
+        }
+        config
+    }
 }
 
 struct ShortNames {}
@@ -84,6 +111,7 @@ impl LLVMCompiler {
         wasmer_metadata: &[u8],
     ) -> Result<Vec<u8>, CompileError> {
         let target_machine = self.config().target_machine(target);
+        let function_config = self.function_config(compile_info);
         let ctx = Context::create();
         let merged_module = ctx.create_module("");
 
@@ -105,7 +133,7 @@ impl LLVMCompiler {
                         module_translation,
                         i,
                         input,
-                        self.config(),
+                        &function_config,
                         &compile_info.memory_styles,
                         &compile_info.table_styles,
                         symbol_registry,
@@ -241,6 +269,7 @@ impl Compiler for LLVMCompiler {
         let memory_styles = &compile_info.memory_styles;
         let table_styles = &compile_info.table_styles;
         let module = &compile_info.module;
+        let function_config = self.function_config(compile_info);
 
         // TODO: merge constants in sections.
 
@@ -264,7 +293,7 @@ impl Compiler for LLVMCompiler {
                         module_translation,
                         i,
                         input,
-                        self.config(),
+                        &function_config,
                         memory_styles,
                         &table_styles,
                         &mut ShortNames {},
@@ -320,17 +349,7 @@ impl Compiler for LLVMCompiler {
             let dwarf = Some(Dwarf::new(SectionIndex::from_u32(
                 module_custom_sections.len() as u32,
             )));
-            // Terminating zero-length CIE.
-            frame_section_bytes.extend(vec![
-                0x00, 0x00, 0x00, 0x00, // Length
-                0x00, 0x00, 0x00, 0x00, // CIE ID
-                0x10, // Version (must be 1)
-                0x00, // Augmentation data
-                0x00, // Code alignment factor
-                0x00, // Data alignment factor
-                0x00, // Return address register
-                0x00, 0x00, 0x00, // Padding to a multiple of 4 bytes
-            ]);
+            frame_section_bytes.extend_from_slice(&EH_FRAME_TERMINATOR);
             module_custom_sections.push(CustomSection {
                 protection: CustomSectionProtection::Read,
                 bytes: SectionBody::new_with_vec(frame_section_bytes),
@@ -382,4 +401,117 @@ impl Compiler for LLVMCompiler {
             dwarf,
         ))
     }
+
+    /// Compile a single function using LLVM, along with its `.eh_frame`
+    /// section, if any.
+    ///
+    /// The other sections of the function, holding its constants and jump
+    /// tables, are appended to its body, and referred to as its jump tables.
+    fn compile_function(
+        &self,
+        target: &Target,
+        compile_info: &CompileModuleInfo,
+        module_translation: &ModuleTranslationState,
+        index: LocalFunctionIndex,
+        input: &FunctionBodyData<'_>,
+    ) -> Result<(CompiledFunction, Option<CustomSection>), CompileError> {
+        let function_config = self.function_config(compile_info);
+        let func_translator = FuncTranslator::new(self.config().target_machine(target));
+        let compiled = func_translator.translate(
+            &compile_info.module,
+            module_translation,
+            &index,
+            input,
+            &function_config,
+            &compile_info.memory_styles,
+            &compile_info.table_styles,
+            &mut ShortNames {},
+        )?;
+        append_function_sections(index, compiled)
+    }
+}
+
+/// Appends the sections of a function compiled apart from its module,
+/// holding its constants and jump tables, to its body, and returns it with
+/// its `.eh_frame` section, if any.
+///
+/// The appended sections are referred to as the jump tables of the
+/// function, as is the function itself by their relocations, which must
+/// not go through its stub.
+fn append_function_sections(
+    index: LocalFunctionIndex,
+    compiled: crate::object_file::CompiledFunction,
+) -> Result<(CompiledFunction, Option<CustomSection>), CompileError> {
+    let mut function = compiled.compiled_function;
+    let function_table = JumpTable::new(function.jt_offsets.values().len());
+    function.jt_offsets[function_table] = 0;
+    let mut section_tables = PrimaryMap::<SectionIndex, Option<JumpTable>>::new();
+    for (section_index, section) in compiled.custom_sections.iter() {
+        if compiled.eh_frame_section_indices.contains(&section_index) {
+            section_tables.push(None);
+            continue;
+        }
+        let body = &mut function.body.body;
+        let offset =
+            (body.len() + APPENDED_SECTION_ALIGNMENT - 1) & !(APPENDED_SECTION_ALIGNMENT - 1);
+        body.resize(offset, 0);
+        body.extend_from_slice(section.bytes.as_slice());
+        let table = JumpTable::new(function.jt_offsets.values().len());
+        function.jt_offsets[table] = offset as u32;
+        section_tables.push(Some(table));
+    }
+    let into_function_target = |target: RelocationTarget| match target {
+        RelocationTarget::CustomSection(section_index) => match section_tables[section_index] {
+            Some(table) => Ok(RelocationTarget::JumpTable(index, table)),
+            None => Err(CompileError::Codegen(
+                "The function refers to its .eh_frame section".to_string(),
+            )),
+        },
+        target => Ok(target),
+    };
+    for relocation in &mut function.relocations {
+        relocation.reloc_target = into_function_target(relocation.reloc_target)?;
+    }
+
+    let mut eh_frame_bytes = vec![];
+    let mut eh_frame_relocations = vec![];
+    for (section_index, section) in compiled.custom_sections.iter() {
+        let section_offset = match section_tables[section_index] {
+            Some(table) => function.jt_offsets[table],
+            None => {
+                let offset = eh_frame_bytes.len() as u32;
+                eh_frame_bytes.extend_from_slice(section.bytes.as_slice());
+                for relocation in &section.relocations {
+                    let mut relocation = relocation.clone();
+                    relocation.offset += offset;
+                    eh_frame_relocations.push(relocation);
+                }
+                continue;
+            }
+        };
+        for relocation in &section.relocations {
+            let mut relocation = relocation.clone();
+            relocation.offset += section_offset;
+            relocation.reloc_target = match relocation.reloc_target {
+                RelocationTarget::LocalFunc(callee) if callee == index => {
+                    RelocationTarget::JumpTable(index, function_table)
+                }
+                target => into_function_target(target)?,
+            };
+            function.relocations.push(relocation);
+        }
+    }
+
+    let eh_frame = if eh_frame_bytes.is_empty() {
+        None
+    } else {
+        eh_frame_bytes.extend_from_slice(&EH_FRAME_TERMINATOR);
+        function.body.unwind_info = Some(CompiledFunctionUnwindInfo::Dwarf);
+        Some(CustomSection {
+            protection: CustomSectionProtection::Read,
+            bytes: SectionBody::new_with_vec(eh_frame_bytes),
+            relocations: eh_frame_relocations,
+        })
+    };
+    Ok((function, eh_frame))
 }
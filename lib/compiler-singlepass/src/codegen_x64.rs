@@ -41,6 +41,9 @@ pub struct FuncGen<'a> {
     /// Function signature.
     signature: FunctionType,
 
+    /// Index of the function in the module.
+    local_func_index: LocalFunctionIndex,
+
     // Working storage.
     /// The assembler.
     ///
@@ -1772,6 +1775,24 @@ impl<'a> FuncGen<'a> {
         Ok(())
     }
 
+    /// Increments the execution counter of the function, relocated by the
+    /// JIT linker.
+    fn emit_execution_counter_increment(&mut self) {
+        let tmp = self.machine.acquire_temp_gpr().unwrap();
+        let reloc_at = self.assembler.get_offset().0 + self.assembler.arch_mov64_imm_offset();
+        self.relocations.push(Relocation {
+            kind: RelocationKind::Abs8,
+            reloc_target: RelocationTarget::ExecutionCounter(self.local_func_index),
+            offset: reloc_at as u32,
+            addend: 0,
+        });
+        self.assembler
+            .emit_mov(Size::S64, Location::Imm64(u64::MAX), Location::GPR(tmp));
+        self.assembler
+            .emit_add(Size::S64, Location::Imm32(1), Location::Memory(tmp, 0));
+        self.machine.release_temp_gpr(tmp);
+    }
+
     pub fn new(
         module: &'a ModuleInfo,
         config: &'a Singlepass,
@@ -1817,6 +1838,7 @@ impl<'a> FuncGen<'a> {
             memory_styles,
             // table_styles,
             signature,
+            local_func_index,
             assembler,
             locals: vec![], // initialization deferred to emit_head
             local_types,
@@ -5611,6 +5633,9 @@ impl<'a> FuncGen<'a> {
                     state_diff_id,
                 });
                 self.assembler.emit_label(label);
+                if self.config.enable_execution_counters {
+                    self.emit_execution_counter_increment();
+                }
 
                 // TODO: Re-enable interrupt signal check without branching
             }
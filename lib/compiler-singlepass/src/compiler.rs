@@ -11,7 +11,9 @@ use rayon::prelude::{IntoParallelIterator, IntoParallelRefIterator, ParallelIter
 use std::sync::Arc;
 use wasmer_compiler::wasmparser::BinaryReaderError;
 use wasmer_compiler::TrapInformation;
-use wasmer_compiler::{Compilation, CompileError, CompiledFunction, Compiler, SectionIndex};
+use wasmer_compiler::{
+    Compilation, CompileError, CompiledFunction, Compiler, CustomSection, SectionIndex,
+};
 use wasmer_compiler::{
     CompileModuleInfo, CompilerConfig, GenerateMiddlewareChain, MiddlewareBinaryReader,
     ModuleTranslationState, Target,
@@ -54,9 +56,13 @@ impl Compiler for SinglepassCompiler {
         if compile_info.features.multi_value {
             return Err(CompileError::UnsupportedFeature("multivalue".to_string()));
         }
+        // Deterministic modules always canonicalize their NaNs.
+        let mut config = self.config.clone();
+        if compile_info.features.deterministic {
+            config.canonicalize_nans(true);
+        }
+        config.enable_execution_counters = compile_info.execution_counters;
         let vmoffsets = VMOffsets::new(8, &compile_info.module);
-        let memory_styles = &compile_info.memory_styles;
-        let table_styles = &compile_info.table_styles;
         let module = &compile_info.module;
         let import_trampolines: PrimaryMap<SectionIndex, _> = (0..module.num_imported_functions)
             .map(FunctionIndex::new)
@@ -72,43 +78,7 @@ impl Compiler for SinglepassCompiler {
             .into_iter()
             .collect::<Vec<(LocalFunctionIndex, &FunctionBodyData<'_>)>>()
             .par_iter()
-            .map(|(i, input)| {
-                let middleware_chain = self.config.middlewares.generate_middleware_chain(*i);
-                let mut reader =
-                    MiddlewareBinaryReader::new_with_offset(input.data, input.module_offset);
-                reader.set_middleware_chain(middleware_chain);
-
-                // This local list excludes arguments.
-                let mut locals = vec![];
-                let num_locals = reader.read_local_count().map_err(to_compile_error)?;
-                for _ in 0..num_locals {
-                    let mut counter = 0;
-                    let (count, ty) = reader
-                        .read_local_decl(&mut counter)
-                        .map_err(to_compile_error)?;
-                    for _ in 0..count {
-                        locals.push(ty);
-                    }
-                }
-
-                let mut generator = FuncGen::new(
-                    module,
-                    &self.config,
-                    &vmoffsets,
-                    &memory_styles,
-                    &table_styles,
-                    *i,
-                    &locals,
-                )
-                .map_err(to_compile_error)?;
-
-                while generator.has_control_frames() {
-                    let op = reader.read_operator().map_err(to_compile_error)?;
-                    generator.feed_operator(op).map_err(to_compile_error)?;
-                }
-
-                Ok(generator.finalize())
-            })
+            .map(|(i, input)| compile_function_body(&config, compile_info, &vmoffsets, *i, input))
             .collect::<Result<Vec<CompiledFunction>, CompileError>>()?
             .into_iter()
             .collect::<PrimaryMap<LocalFunctionIndex, CompiledFunction>>();
@@ -141,6 +111,73 @@ impl Compiler for SinglepassCompiler {
             None,
         ))
     }
+
+    /// Compile a single function using Singlepass, calling the imported
+    /// functions through the import trampolines of the module.
+    fn compile_function(
+        &self,
+        _target: &Target,
+        compile_info: &CompileModuleInfo,
+        _module_translation: &ModuleTranslationState,
+        index: LocalFunctionIndex,
+        input: &FunctionBodyData<'_>,
+    ) -> Result<(CompiledFunction, Option<CustomSection>), CompileError> {
+        if compile_info.features.multi_value {
+            return Err(CompileError::UnsupportedFeature("multivalue".to_string()));
+        }
+        let mut config = self.config.clone();
+        if compile_info.features.deterministic {
+            config.canonicalize_nans(true);
+        }
+        config.enable_execution_counters = compile_info.execution_counters;
+        let vmoffsets = VMOffsets::new(8, &compile_info.module);
+        let function = compile_function_body(&config, compile_info, &vmoffsets, index, input)?;
+        Ok((function, None))
+    }
+}
+
+/// Compiles a function body in a single pass.
+fn compile_function_body(
+    config: &Singlepass,
+    compile_info: &CompileModuleInfo,
+    vmoffsets: &VMOffsets,
+    i: LocalFunctionIndex,
+    input: &FunctionBodyData<'_>,
+) -> Result<CompiledFunction, CompileError> {
+    let middleware_chain = config.middlewares.generate_middleware_chain(i);
+    let mut reader = MiddlewareBinaryReader::new_with_offset(input.data, input.module_offset);
+    reader.set_middleware_chain(middleware_chain);
+
+    // This local list excludes arguments.
+    let mut locals = vec![];
+    let num_locals = reader.read_local_count().map_err(to_compile_error)?;
+    for _ in 0..num_locals {
+        let mut counter = 0;
+        let (count, ty) = reader
+            .read_local_decl(&mut counter)
+            .map_err(to_compile_error)?;
+        for _ in 0..count {
+            locals.push(ty);
+        }
+    }
+
+    let mut generator = FuncGen::new(
+        &compile_info.module,
+        config,
+        vmoffsets,
+        &compile_info.memory_styles,
+        &compile_info.table_styles,
+        i,
+        &locals,
+    )
+    .map_err(to_compile_error)?;
+
+    while generator.has_control_frames() {
+        let op = reader.read_operator().map_err(to_compile_error)?;
+        generator.feed_operator(op).map_err(to_compile_error)?;
+    }
+
+    Ok(generator.finalize())
 }
 
 trait ToCompileError {
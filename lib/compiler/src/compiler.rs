@@ -2,12 +2,13 @@
 //! compilers will need to implement.
 
 use crate::error::CompileError;
-use crate::function::Compilation;
+use crate::function::{Compilation, CompiledFunction};
 use crate::lib::std::boxed::Box;
 use crate::lib::std::sync::Arc;
 use crate::module::CompileModuleInfo;
 use crate::target::Target;
 use crate::translator::FunctionMiddlewareGenerator;
+use crate::CustomSection;
 use crate::FunctionBodyData;
 use crate::ModuleTranslationState;
 use crate::SectionIndex;
@@ -58,9 +59,9 @@ pub trait Compiler {
         features: &Features,
         data: &'data [u8],
     ) -> Result<(), CompileError> {
-        let config = ValidatingParserConfig {
+        let config = |enable_threads| ValidatingParserConfig {
             operator_config: OperatorValidatorConfig {
-                enable_threads: features.threads,
+                enable_threads,
                 enable_reference_types: features.reference_types,
                 enable_bulk_memory: features.bulk_memory,
                 enable_tail_call: false,
@@ -68,7 +69,18 @@ pub trait Compiler {
                 enable_multi_value: features.multi_value,
             },
         };
-        validate(data, Some(config)).map_err(|e| CompileError::Validate(format!("{}", e)))
+        // The threads proposal can't be executed deterministically.
+        let enable_threads = features.threads && !features.deterministic;
+        validate(data, Some(config(enable_threads))).map_err(|e| {
+            if features.threads
+                && !enable_threads
+                && validate(data, Some(config(true))).is_ok()
+            {
+                CompileError::UnsupportedFeature("threads in deterministic mode".to_string())
+            } else {
+                CompileError::Validate(format!("{}", e))
+            }
+        })
     }
 
     /// Compiles a parsed module.
@@ -83,6 +95,29 @@ pub trait Compiler {
         function_body_inputs: PrimaryMap<LocalFunctionIndex, FunctionBodyData<'data>>,
     ) -> Result<Compilation, CompileError>;
 
+    /// Compiles a single function of a parsed module, for the engines
+    /// compiling the functions lazily, on their first call.
+    ///
+    /// The function can refer to the custom sections of the
+    /// [`Compilation`] returned by [`Compiler::compile_module`] for the
+    /// same module without any function body.
+    ///
+    /// It returns the [`CompiledFunction`], along with the `.eh_frame`
+    /// section holding its DWARF unwind information if any, or a
+    /// [`CompileError`], and by default [`CompileError::UnsupportedFeature`].
+    fn compile_function<'data, 'module>(
+        &self,
+        _target: &Target,
+        _module: &'module CompileModuleInfo,
+        _module_translation: &ModuleTranslationState,
+        _index: LocalFunctionIndex,
+        _input: &FunctionBodyData<'data>,
+    ) -> Result<(CompiledFunction, Option<CustomSection>), CompileError> {
+        Err(CompileError::UnsupportedFeature(
+            "lazy compilation".to_string(),
+        ))
+    }
+
     /// Compiles a module into a native object file.
     ///
     /// It returns the bytes as a `&[u8]` or a [`CompileError`].
@@ -24,4 +24,13 @@ pub struct CompileModuleInfo {
     pub memory_styles: PrimaryMap<MemoryIndex, MemoryStyle>,
     /// The table plans used for compiling.
     pub table_styles: PrimaryMap<TableIndex, TableStyle>,
+    /// Whether the functions count their executions, for an engine
+    /// compiling their hot functions again with an optimizing compiler.
+    ///
+    /// The compilers capable of doing so make the functions increment
+    /// their execution counter on every iteration of their loops; the
+    /// engine counts their calls. The counters are linked by the engine,
+    /// so only the engine sets this.
+    #[cfg_attr(feature = "enable-serde", serde(skip))]
+    pub execution_counters: bool,
 }
@@ -103,6 +103,9 @@ pub enum RelocationTarget {
     JumpTable(LocalFunctionIndex, JumpTable),
     /// Custom sections generated by the compiler
     CustomSection(SectionIndex),
+    /// The execution counter of a function defined locally in the wasm,
+    /// incremented on the iterations of its loops.
+    ExecutionCounter(LocalFunctionIndex),
 }
 
 impl Relocation {
@@ -456,6 +456,12 @@ impl<'data> ModuleEnvironment<'data> {
         Ok(())
     }
 
+    /// Provides the offset of the code section contents in the wasm file.
+    pub(crate) fn declare_code_section_offset(&mut self, offset: usize) -> WasmResult<()> {
+        self.result.module.code_section_offset = offset;
+        Ok(())
+    }
+
     /// Indicates that a custom section has been found in the wasm file
     pub(crate) fn custom_section(&mut self, name: &'data str, data: &'data [u8]) -> WasmResult<()> {
         let custom_section = CustomSectionIndex::from_u32(
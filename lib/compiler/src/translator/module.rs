@@ -63,6 +63,7 @@ pub fn translate_module<'data>(
             }
 
             SectionContent::Code(code) => {
+                environ.declare_code_section_offset(section.range().start)?;
                 parse_code_section(code, &module_translation_state, environ)?;
             }
 
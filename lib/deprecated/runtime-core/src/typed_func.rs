@@ -236,6 +236,9 @@ pub(crate) struct DynamicCtx {
     pub(crate) vmctx: Rc<RefCell<vm::Ctx>>,
 }
 
+// The `vm::Ctx` is patched by `module::Module::instantiate` instead.
+impl new::wasmer::WasmerEnv for DynamicCtx {}
+
 impl DynamicFunc {
     /// Create a new `DynamicFunc`.
     pub fn new<F>(signature: &FuncSig, func: F) -> Self
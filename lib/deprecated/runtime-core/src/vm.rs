@@ -38,6 +38,9 @@ pub struct Ctx {
     pub data_finalizer: Option<fn(data: *mut c_void)>,
 }
 
+// The `vm::Ctx` is patched by `module::Module::instantiate` instead.
+impl crate::new::wasmer::WasmerEnv for Ctx {}
+
 impl Ctx {
     pub(crate) unsafe fn new_uninit() -> Self {
         Self {
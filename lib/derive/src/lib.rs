@@ -0,0 +1,394 @@
+//! Derive macros for the `wasmer` crate.
+//!
+//! Please use the macros through the re-exports in `wasmer`, the
+//! generated code refers to items with the `::wasmer` path.
+#![deny(missing_docs, unused_extern_crates)]
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    parse_macro_input, parse_quote, spanned::Spanned, Data, DataEnum, DataStruct, DeriveInput,
+    Fields, Index, Lit, Member, Meta, NestedMeta, Type,
+};
+
+/// Derives `ComponentType`, `Lift` and `Lower` for a struct or a
+/// field-less enum.
+///
+/// Structs (with named or unnamed fields) are laid out as canonical ABI
+/// records: fields are placed in declaration order, each one aligned to
+/// its own alignment. Field-less enums are laid out as canonical ABI
+/// enums, whose discriminant is the index of the variant.
+#[proc_macro_derive(ComponentType)]
+pub fn derive_component_type(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let expanded = match &input.data {
+        Data::Struct(data) => derive_record(&input, data),
+        Data::Enum(data) => derive_enum(&input, data),
+        Data::Union(_) => Err(syn::Error::new(
+            input.ident.span(),
+            "`ComponentType` cannot be derived for unions",
+        )),
+    };
+    expanded
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
+}
+
+fn derive_record(input: &DeriveInput, data: &DataStruct) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let types: Vec<&Type> = data.fields.iter().map(|field| &field.ty).collect();
+    let members: Vec<Member> = data
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index {
+                index: i as u32,
+                span: field.span(),
+            }),
+        })
+        .collect();
+    let locals: Vec<syn::Ident> = (0..members.len())
+        .map(|i| syn::Ident::new(&format!("__field{}", i), Span::call_site()))
+        .collect();
+    let construct = match &data.fields {
+        Fields::Named(_) => quote! { #name { #( #members: #locals ),* } },
+        Fields::Unnamed(_) => quote! { #name ( #( #locals ),* ) },
+        Fields::Unit => quote! { #name },
+    };
+
+    let bounded = |bound: TokenStream2| {
+        let mut generics = input.generics.clone();
+        let where_clause = generics.make_where_clause();
+        for ty in types.iter() {
+            where_clause.predicates.push(parse_quote!(#ty: #bound));
+        }
+        generics
+    };
+
+    let component_generics = bounded(quote!(::wasmer::component::ComponentType));
+    let (impl_generics, ty_generics, where_clause) = component_generics.split_for_impl();
+    let component_impl = quote! {
+        impl #impl_generics ::wasmer::component::ComponentType for #name #ty_generics #where_clause {
+            fn size() -> usize {
+                #[allow(unused_mut)]
+                let mut size = 0;
+                #(
+                    size = ::wasmer::component::align_to(
+                        size,
+                        <#types as ::wasmer::component::ComponentType>::align(),
+                    );
+                    size += <#types as ::wasmer::component::ComponentType>::size();
+                )*
+                ::wasmer::component::align_to(size, Self::align())
+            }
+
+            fn align() -> usize {
+                #[allow(unused_mut)]
+                let mut align = 1;
+                #(
+                    align = ::std::cmp::max(
+                        align,
+                        <#types as ::wasmer::component::ComponentType>::align(),
+                    );
+                )*
+                align
+            }
+
+            fn flatten(out: &mut ::std::vec::Vec<::wasmer::ValType>) {
+                #( <#types as ::wasmer::component::ComponentType>::flatten(out); )*
+            }
+        }
+    };
+
+    let lower_generics = bounded(quote!(::wasmer::component::Lower));
+    let (impl_generics, ty_generics, where_clause) = lower_generics.split_for_impl();
+    let lower_impl = quote! {
+        impl #impl_generics ::wasmer::component::Lower for #name #ty_generics #where_clause {
+            fn lower(
+                &self,
+                cx: &mut ::wasmer::component::LowerContext<'_>,
+                dst: &mut ::std::vec::Vec<::wasmer::Val>,
+            ) -> ::std::result::Result<(), ::wasmer::component::ComponentError> {
+                #( ::wasmer::component::Lower::lower(&self.#members, cx, dst)?; )*
+                Ok(())
+            }
+
+            fn store(
+                &self,
+                cx: &mut ::wasmer::component::LowerContext<'_>,
+                offset: usize,
+            ) -> ::std::result::Result<(), ::wasmer::component::ComponentError> {
+                #[allow(unused_mut)]
+                let mut offset = offset;
+                #(
+                    offset = ::wasmer::component::align_to(
+                        offset,
+                        <#types as ::wasmer::component::ComponentType>::align(),
+                    );
+                    ::wasmer::component::Lower::store(&self.#members, cx, offset)?;
+                    offset += <#types as ::wasmer::component::ComponentType>::size();
+                )*
+                let _ = offset;
+                Ok(())
+            }
+        }
+    };
+
+    let lift_generics = bounded(quote!(::wasmer::component::Lift));
+    let (impl_generics, ty_generics, where_clause) = lift_generics.split_for_impl();
+    let lift_impl = quote! {
+        impl #impl_generics ::wasmer::component::Lift for #name #ty_generics #where_clause {
+            fn lift(
+                cx: &::wasmer::component::LiftContext<'_>,
+                src: &mut ::std::slice::Iter<'_, ::wasmer::Val>,
+            ) -> ::std::result::Result<Self, ::wasmer::component::ComponentError> {
+                #( let #locals = <#types as ::wasmer::component::Lift>::lift(cx, src)?; )*
+                Ok(#construct)
+            }
+
+            fn load(
+                cx: &::wasmer::component::LiftContext<'_>,
+                offset: usize,
+            ) -> ::std::result::Result<Self, ::wasmer::component::ComponentError> {
+                #[allow(unused_mut)]
+                let mut offset = offset;
+                #(
+                    offset = ::wasmer::component::align_to(
+                        offset,
+                        <#types as ::wasmer::component::ComponentType>::align(),
+                    );
+                    let #locals = <#types as ::wasmer::component::Lift>::load(cx, offset)?;
+                    offset += <#types as ::wasmer::component::ComponentType>::size();
+                )*
+                let _ = offset;
+                Ok(#construct)
+            }
+        }
+    };
+
+    Ok(quote! {
+        #component_impl
+        #lower_impl
+        #lift_impl
+    })
+}
+
+fn derive_enum(input: &DeriveInput, data: &DataEnum) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            input.generics.span(),
+            "`ComponentType` cannot be derived for generic enums",
+        ));
+    }
+    if data.variants.is_empty() {
+        return Err(syn::Error::new(
+            name.span(),
+            "`ComponentType` cannot be derived for enums without variants",
+        ));
+    }
+    for variant in data.variants.iter() {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(syn::Error::new(
+                variant.span(),
+                "`ComponentType` can only be derived for enums whose variants have no fields",
+            ));
+        }
+    }
+    let variants: Vec<&syn::Ident> = data.variants.iter().map(|v| &v.ident).collect();
+    let discriminants: Vec<u32> = (0..variants.len() as u32).collect();
+    let repr = match variants.len() {
+        n if n <= 1 << 8 => quote!(u8),
+        n if n <= 1 << 16 => quote!(u16),
+        _ => quote!(u32),
+    };
+
+    Ok(quote! {
+        impl ::wasmer::component::ComponentType for #name {
+            fn size() -> usize {
+                <#repr as ::wasmer::component::ComponentType>::size()
+            }
+
+            fn align() -> usize {
+                <#repr as ::wasmer::component::ComponentType>::align()
+            }
+
+            fn flatten(out: &mut ::std::vec::Vec<::wasmer::ValType>) {
+                out.push(::wasmer::ValType::I32);
+            }
+        }
+
+        impl ::wasmer::component::Lower for #name {
+            fn lower(
+                &self,
+                _cx: &mut ::wasmer::component::LowerContext<'_>,
+                dst: &mut ::std::vec::Vec<::wasmer::Val>,
+            ) -> ::std::result::Result<(), ::wasmer::component::ComponentError> {
+                let discriminant: u32 = match self {
+                    #( #name::#variants => #discriminants, )*
+                };
+                dst.push(::wasmer::Val::I32(discriminant as i32));
+                Ok(())
+            }
+
+            fn store(
+                &self,
+                cx: &mut ::wasmer::component::LowerContext<'_>,
+                offset: usize,
+            ) -> ::std::result::Result<(), ::wasmer::component::ComponentError> {
+                let discriminant: u32 = match self {
+                    #( #name::#variants => #discriminants, )*
+                };
+                ::wasmer::component::Lower::store(&(discriminant as #repr), cx, offset)
+            }
+        }
+
+        impl ::wasmer::component::Lift for #name {
+            fn lift(
+                cx: &::wasmer::component::LiftContext<'_>,
+                src: &mut ::std::slice::Iter<'_, ::wasmer::Val>,
+            ) -> ::std::result::Result<Self, ::wasmer::component::ComponentError> {
+                match <u32 as ::wasmer::component::Lift>::lift(cx, src)? {
+                    #( #discriminants => Ok(#name::#variants), )*
+                    discriminant => Err(::wasmer::component::ComponentError::InvalidDiscriminant(discriminant)),
+                }
+            }
+
+            fn load(
+                cx: &::wasmer::component::LiftContext<'_>,
+                offset: usize,
+            ) -> ::std::result::Result<Self, ::wasmer::component::ComponentError> {
+                match <#repr as ::wasmer::component::Lift>::load(cx, offset)? as u32 {
+                    #( #discriminants => Ok(#name::#variants), )*
+                    discriminant => Err(::wasmer::component::ComponentError::InvalidDiscriminant(discriminant)),
+                }
+            }
+        }
+    })
+}
+
+/// Derives `WasmerEnv` for a struct.
+///
+/// Fields of type `LazyInit<T>` marked with `#[wasmer(export)]` are bound
+/// to the export of the same name of the instance importing the host
+/// function. The export name can be overridden with
+/// `#[wasmer(export(name = "..."))]`, and a missing export can be allowed
+/// with `#[wasmer(export(optional = true))]`. Other fields are left
+/// untouched.
+#[proc_macro_derive(WasmerEnv, attributes(wasmer))]
+pub fn derive_wasmer_env(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let expanded = match &input.data {
+        Data::Struct(data) => derive_env_struct(&input, data),
+        _ => Err(syn::Error::new(
+            input.ident.span(),
+            "`WasmerEnv` can only be derived for structs",
+        )),
+    };
+    expanded
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
+}
+
+/// An export a `LazyInit` field is bound to.
+struct ExportAttr {
+    name: Option<String>,
+    optional: bool,
+}
+
+fn derive_env_struct(input: &DeriveInput, data: &DataStruct) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let mut bindings = Vec::new();
+    for (i, field) in data.fields.iter().enumerate() {
+        let member = match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index {
+                index: i as u32,
+                span: field.span(),
+            }),
+        };
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path.is_ident("wasmer"))
+        {
+            let export = parse_export_attr(&attr.parse_meta()?)?;
+            let export_name = match (export.name, &field.ident) {
+                (Some(name), _) => name,
+                (None, Some(ident)) => ident.to_string(),
+                (None, None) => {
+                    return Err(syn::Error::new(
+                        field.span(),
+                        "exports of tuple struct fields must be named with `#[wasmer(export(name = \"...\"))]`",
+                    ))
+                }
+            };
+            let bind = if export.optional {
+                quote!(bind_optional)
+            } else {
+                quote!(bind)
+            };
+            bindings.push(quote! {
+                ::wasmer::LazyInit::#bind(&self.#member, &instance.exports, #export_name)?;
+            });
+        }
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::wasmer::WasmerEnv for #name #ty_generics #where_clause {
+            fn init_with_instance(
+                &mut self,
+                instance: &::wasmer::Instance,
+            ) -> ::std::result::Result<(), ::wasmer::HostEnvInitError> {
+                let _ = instance;
+                #( #bindings )*
+                Ok(())
+            }
+        }
+    })
+}
+
+fn parse_export_attr(meta: &Meta) -> syn::Result<ExportAttr> {
+    let invalid = |span| {
+        syn::Error::new(
+            span,
+            "expected `#[wasmer(export)]` or `#[wasmer(export(name = \"...\", optional = true))]`",
+        )
+    };
+    let list = match meta {
+        Meta::List(list) if list.nested.len() == 1 => list,
+        _ => return Err(invalid(meta.span())),
+    };
+    let mut export = ExportAttr {
+        name: None,
+        optional: false,
+    };
+    match &list.nested[0] {
+        NestedMeta::Meta(Meta::Path(path)) if path.is_ident("export") => {}
+        NestedMeta::Meta(Meta::List(args)) if args.path.is_ident("export") => {
+            for arg in args.nested.iter() {
+                match arg {
+                    NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("name") => {
+                        match &nv.lit {
+                            Lit::Str(name) => export.name = Some(name.value()),
+                            lit => return Err(invalid(lit.span())),
+                        }
+                    }
+                    NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("optional") => {
+                        match &nv.lit {
+                            Lit::Bool(optional) => export.optional = optional.value,
+                            lit => return Err(invalid(lit.span())),
+                        }
+                    }
+                    arg => return Err(invalid(arg.span())),
+                }
+            }
+        }
+        nested => return Err(invalid(nested.span())),
+    }
+    Ok(export)
+}
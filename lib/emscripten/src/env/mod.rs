@@ -32,6 +32,15 @@ pub fn call_malloc(ctx: &mut EmEnv, size: u32) -> u32 {
         .unwrap()
 }
 
+pub fn call_free(ctx: &mut EmEnv, pointer: u32) {
+    get_emscripten_data(ctx)
+        .free
+        .as_ref()
+        .unwrap()
+        .call(pointer)
+        .unwrap()
+}
+
 #[warn(dead_code)]
 pub fn call_malloc_with_cast<T: Copy, Ty>(ctx: &mut EmEnv, size: u32) -> WasmPtr<T, Ty> {
     WasmPtr::new(call_malloc(ctx, size))
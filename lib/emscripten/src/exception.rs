@@ -1,6 +1,107 @@
-use super::env;
-use super::process::_abort;
+//! C++ exceptions.
+//!
+//! Like the JS glue of Emscripten, `___cxa_throw` records the thrown
+//! exception and unwinds with a [`CxxException`] error up to the closest
+//! `invoke_*` frame, which returns normally after `setThrew`. The landing pad
+//! of the calling function then picks the catch clause with
+//! `___cxa_find_matching_catch_*`, or resumes unwinding with
+//! `___resumeException`.
+
+#![allow(non_snake_case)]
+
+use super::env::{self, get_emscripten_data};
 use crate::EmEnv;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use wasmer::RuntimeError;
+
+/// The error unwinding the stack of the module, up to the closest `invoke_*`
+/// frame, when a C++ exception is thrown.
+///
+/// It holds the pointer to the thrown exception.
+#[derive(Copy, Clone, Debug)]
+pub struct CxxException(pub u32);
+
+impl fmt::Display for CxxException {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "uncaught C++ exception at {:#x}", self.0)
+    }
+}
+
+impl Error for CxxException {}
+
+/// The exceptions in flight, as tracked by `EXCEPTIONS` in the JS glue.
+#[derive(Debug, Default)]
+pub struct Exceptions {
+    infos: HashMap<u32, ExceptionInfo>,
+    /// The last thrown exception.
+    last: u32,
+    /// The stack of the exceptions being caught.
+    caught: Vec<u32>,
+    uncaught: u32,
+    /// A word of the memory of the module, to adjust the thrown pointer
+    /// with `___cxa_can_catch`.
+    adjust_buffer: u32,
+}
+
+#[derive(Debug)]
+struct ExceptionInfo {
+    /// The pointers to the exception handed to the catch clauses.
+    adjusted: Vec<u32>,
+    ty: u32,
+    destructor: u32,
+    refcount: u32,
+    caught: bool,
+    rethrown: bool,
+}
+
+impl Exceptions {
+    /// The exception `ptr` was adjusted from.
+    fn de_adjust(&self, ptr: u32) -> u32 {
+        if self.infos.contains_key(&ptr) {
+            return ptr;
+        }
+        self.infos
+            .iter()
+            .find(|(_, info)| info.adjusted.contains(&ptr))
+            .map_or(ptr, |(thrown, _)| *thrown)
+    }
+
+    fn add_ref(&mut self, ptr: u32) {
+        if let Some(info) = self.infos.get_mut(&ptr) {
+            info.refcount += 1;
+        }
+    }
+}
+
+fn exceptions(ctx: &mut EmEnv) -> &mut Exceptions {
+    &mut get_emscripten_data(ctx).exceptions
+}
+
+/// Drops a reference to the exception `ptr`, and destroys it if it was the
+/// last one.
+fn dec_ref(ctx: &mut EmEnv, ptr: u32) {
+    let destroyed = match exceptions(ctx).infos.get_mut(&ptr) {
+        Some(info) => {
+            info.refcount = info.refcount.saturating_sub(1);
+            info.refcount == 0 && !info.rethrown
+        }
+        None => false,
+    };
+    if destroyed {
+        let info = exceptions(ctx).infos.remove(&ptr).unwrap();
+        if info.destructor != 0 {
+            get_emscripten_data(ctx)
+                .dyn_call_vi
+                .as_ref()
+                .expect("dyn_call_vi is None")
+                .call(info.destructor as i32, ptr as i32)
+                .unwrap_or_else(|e| RuntimeError::raise(Box::new(e)));
+        }
+        ___cxa_free_exception(ctx, ptr);
+    }
+}
 
 /// emscripten: ___cxa_allocate_exception
 pub fn ___cxa_allocate_exception(ctx: &mut EmEnv, size: u32) -> u32 {
@@ -8,46 +109,181 @@ pub fn ___cxa_allocate_exception(ctx: &mut EmEnv, size: u32) -> u32 {
     env::call_malloc(ctx, size as _)
 }
 
-pub fn ___cxa_current_primary_exception(_ctx: &mut EmEnv) -> u32 {
+/// emscripten: ___cxa_free_exception
+pub fn ___cxa_free_exception(ctx: &mut EmEnv, ptr: u32) {
+    debug!("emscripten::___cxa_free_exception");
+    env::call_free(ctx, ptr);
+}
+
+pub fn ___cxa_current_primary_exception(ctx: &mut EmEnv) -> u32 {
     debug!("emscripten::___cxa_current_primary_exception");
-    unimplemented!("emscripten::___cxa_current_primary_exception")
+    let exceptions = exceptions(ctx);
+    let ptr = exceptions.caught.last().copied().unwrap_or(0);
+    if ptr != 0 {
+        let thrown = exceptions.de_adjust(ptr);
+        exceptions.add_ref(thrown);
+    }
+    ptr
 }
 
-pub fn ___cxa_decrement_exception_refcount(_ctx: &mut EmEnv, _a: u32) {
-    debug!("emscripten::___cxa_decrement_exception_refcount({})", _a);
-    unimplemented!("emscripten::___cxa_decrement_exception_refcount({})", _a)
+pub fn ___cxa_decrement_exception_refcount(ctx: &mut EmEnv, ptr: u32) {
+    debug!("emscripten::___cxa_decrement_exception_refcount({})", ptr);
+    let thrown = exceptions(ctx).de_adjust(ptr);
+    dec_ref(ctx, thrown);
 }
 
-pub fn ___cxa_increment_exception_refcount(_ctx: &mut EmEnv, _a: u32) {
-    debug!("emscripten::___cxa_increment_exception_refcount({})", _a);
-    unimplemented!("emscripten::___cxa_increment_exception_refcount({})", _a)
+pub fn ___cxa_increment_exception_refcount(ctx: &mut EmEnv, ptr: u32) {
+    debug!("emscripten::___cxa_increment_exception_refcount({})", ptr);
+    let exceptions = exceptions(ctx);
+    let thrown = exceptions.de_adjust(ptr);
+    exceptions.add_ref(thrown);
 }
 
-pub fn ___cxa_rethrow_primary_exception(_ctx: &mut EmEnv, _a: u32) {
-    debug!("emscripten::___cxa_rethrow_primary_exception({})", _a);
-    unimplemented!("emscripten::___cxa_rethrow_primary_exception({})", _a)
+pub fn ___cxa_rethrow_primary_exception(ctx: &mut EmEnv, ptr: u32) {
+    debug!("emscripten::___cxa_rethrow_primary_exception({})", ptr);
+    if ptr == 0 {
+        return;
+    }
+    let exceptions = exceptions(ctx);
+    let thrown = exceptions.de_adjust(ptr);
+    exceptions.caught.push(thrown);
+    if let Some(info) = exceptions.infos.get_mut(&thrown) {
+        info.rethrown = true;
+    }
+    ___cxa_rethrow(ctx);
 }
 
 /// emscripten: ___cxa_throw
-/// TODO: We don't have support for exceptions yet
-pub fn ___cxa_throw(ctx: &mut EmEnv, _ptr: u32, _ty: u32, _destructor: u32) {
-    debug!("emscripten::___cxa_throw");
-    eprintln!("Throwing exceptions not yet implemented: aborting!");
-    _abort(ctx);
+pub fn ___cxa_throw(ctx: &mut EmEnv, ptr: u32, ty: u32, destructor: u32) {
+    debug!("emscripten::___cxa_throw {} {} {}", ptr, ty, destructor);
+    let exceptions = exceptions(ctx);
+    exceptions.infos.insert(
+        ptr,
+        ExceptionInfo {
+            adjusted: vec![ptr],
+            ty,
+            destructor,
+            refcount: 0,
+            caught: false,
+            rethrown: false,
+        },
+    );
+    exceptions.last = ptr;
+    exceptions.uncaught += 1;
+    RuntimeError::raise(Box::new(CxxException(ptr)));
+}
+
+/// emscripten: ___cxa_rethrow
+pub fn ___cxa_rethrow(ctx: &mut EmEnv) {
+    debug!("emscripten::___cxa_rethrow");
+    let exceptions = exceptions(ctx);
+    let ptr = exceptions.caught.pop().unwrap_or(0);
+    let thrown = exceptions.de_adjust(ptr);
+    if let Some(info) = exceptions.infos.get_mut(&thrown) {
+        if !info.rethrown {
+            info.rethrown = true;
+            exceptions.caught.push(thrown);
+        }
+    }
+    exceptions.last = thrown;
+    RuntimeError::raise(Box::new(CxxException(thrown)));
+}
+
+/// emscripten: ___resumeException
+pub fn ___resumeException(ctx: &mut EmEnv, ptr: u32) {
+    debug!("emscripten::___resumeException");
+    let exceptions = exceptions(ctx);
+    if exceptions.last == 0 {
+        exceptions.last = ptr;
+    }
+    RuntimeError::raise(Box::new(CxxException(ptr)));
+}
+
+/// emscripten: ___cxa_find_matching_catch_*
+///
+/// Returns the pointer to the last thrown exception, adjusted for the first
+/// of the `catch_types` it matches, and the matched type in `tempRet0`. If
+/// none matches, the thrown type is in `tempRet0`.
+pub fn find_matching_catch(ctx: &mut EmEnv, catch_types: &[u32]) -> u32 {
+    let thrown = exceptions(ctx).last;
+    let thrown_type = match exceptions(ctx).infos.get(&thrown) {
+        Some(info) if thrown != 0 => info.ty,
+        _ => {
+            get_emscripten_data(ctx).temp_ret_0 = 0;
+            return thrown;
+        }
+    };
+
+    if exceptions(ctx).adjust_buffer == 0 {
+        exceptions(ctx).adjust_buffer = env::call_malloc(ctx, 4);
+    }
+    let buffer = exceptions(ctx).adjust_buffer;
+    ctx.memory(0).view::<u32>()[buffer as usize / 4].set(thrown);
+
+    for &catch_type in catch_types {
+        if catch_type != 0 && can_catch(ctx, catch_type, thrown_type, buffer) {
+            let adjusted = ctx.memory(0).view::<u32>()[buffer as usize / 4].get();
+            if let Some(info) = exceptions(ctx).infos.get_mut(&thrown) {
+                info.adjusted.push(adjusted);
+            }
+            get_emscripten_data(ctx).temp_ret_0 = catch_type as i32;
+            return adjusted;
+        }
+    }
+    get_emscripten_data(ctx).temp_ret_0 = thrown_type as i32;
+    thrown
+}
+
+/// Whether a catch clause of `catch_type` catches a `thrown_type`, adjusting
+/// the pointer to the exception at `adjusted_ptr` if it does.
+fn can_catch(ctx: &mut EmEnv, catch_type: u32, thrown_type: u32, adjusted_ptr: u32) -> bool {
+    match get_emscripten_data(ctx).cxa_can_catch.as_ref() {
+        Some(cxa_can_catch) => {
+            cxa_can_catch
+                .call(catch_type as i32, thrown_type as i32, adjusted_ptr as i32)
+                .unwrap_or_else(|e| RuntimeError::raise(Box::new(e)))
+                != 0
+        }
+        // Without the type information of libcxxabi, only the exact type
+        // is caught.
+        None => catch_type == thrown_type,
+    }
 }
 
-pub fn ___cxa_begin_catch(_ctx: &mut EmEnv, _exception_object_ptr: u32) -> i32 {
+pub fn ___cxa_begin_catch(ctx: &mut EmEnv, ptr: u32) -> u32 {
     debug!("emscripten::___cxa_begin_catch");
-    -1
+    let exceptions = exceptions(ctx);
+    if let Some(info) = exceptions.infos.get_mut(&ptr) {
+        if !info.caught {
+            info.caught = true;
+            exceptions.uncaught = exceptions.uncaught.saturating_sub(1);
+        }
+        info.rethrown = false;
+    }
+    exceptions.caught.push(ptr);
+    let thrown = exceptions.de_adjust(ptr);
+    exceptions.add_ref(thrown);
+    ptr
 }
 
-pub fn ___cxa_end_catch(_ctx: &mut EmEnv) {
+pub fn ___cxa_end_catch(ctx: &mut EmEnv) {
     debug!("emscripten::___cxa_end_catch");
+    get_emscripten_data(ctx)
+        .set_threw
+        .as_ref()
+        .expect("set_threw is None")
+        .call(0, 0)
+        .unwrap_or_else(|e| RuntimeError::raise(Box::new(e)));
+    if let Some(ptr) = exceptions(ctx).caught.pop() {
+        let thrown = exceptions(ctx).de_adjust(ptr);
+        dec_ref(ctx, thrown);
+        exceptions(ctx).last = 0;
+    }
 }
 
-pub fn ___cxa_uncaught_exception(_ctx: &mut EmEnv) -> i32 {
+pub fn ___cxa_uncaught_exception(ctx: &mut EmEnv) -> i32 {
     debug!("emscripten::___cxa_uncaught_exception");
-    -1
+    (exceptions(ctx).uncaught > 0) as i32
 }
 
 pub fn ___cxa_pure_virtual(_ctx: &mut EmEnv) {
@@ -0,0 +1,707 @@
+//! The file system of Emscripten modules.
+//!
+//! Emscripten modules see a POSIX file system backed by a [`WasiFs`]: only
+//! the directories preopened in it are reachable. A directory preopened as
+//! `name` is mounted at `/name`, and one preopened as `.` at `/`. Paths are
+//! resolved against the current directory of the module before picking
+//! the mount, so `..` can't escape the sandbox.
+//!
+//! Sockets and pipes are host file descriptors; the module sees them as
+//! regular file descriptors, allocated in the same table as the files.
+
+use std::collections::HashMap;
+use std::os::raw::c_int;
+use wasmer_wasi::types::*;
+use wasmer_wasi::{Fd, WasiFs, ALL_RIGHTS};
+
+// The Linux errno values, as expected by the Emscripten libc.
+pub const EPERM: i32 = 1;
+pub const ENOENT: i32 = 2;
+pub const EIO: i32 = 5;
+pub const EBADF: i32 = 9;
+pub const EACCES: i32 = 13;
+pub const EFAULT: i32 = 14;
+pub const EEXIST: i32 = 17;
+pub const ENOTDIR: i32 = 20;
+pub const EISDIR: i32 = 21;
+pub const EINVAL: i32 = 22;
+pub const ENOTTY: i32 = 25;
+pub const ESPIPE: i32 = 29;
+pub const ERANGE: i32 = 34;
+pub const ENOSYS: i32 = 38;
+pub const ENOTEMPTY: i32 = 39;
+pub const ENOTSOCK: i32 = 88;
+pub const EOPNOTSUPP: i32 = 95;
+
+// The flags of `open`, as defined by the Emscripten libc.
+pub const O_ACCMODE: i32 = 0o3;
+pub const O_RDONLY: i32 = 0o0;
+pub const O_WRONLY: i32 = 0o1;
+pub const O_RDWR: i32 = 0o2;
+pub const O_CREAT: i32 = 0o100;
+pub const O_EXCL: i32 = 0o200;
+pub const O_TRUNC: i32 = 0o1000;
+pub const O_APPEND: i32 = 0o2000;
+pub const O_DIRECTORY: i32 = 0o200000;
+pub const O_NOFOLLOW: i32 = 0o400000;
+
+/// A result whose error is a Linux errno.
+pub type EmFsResult<T> = Result<T, i32>;
+
+/// Converts a WASI errno to the Linux errno the Emscripten libc expects.
+pub fn wasi_errno_to_errno(errno: __wasi_errno_t) -> i32 {
+    match errno {
+        __WASI_E2BIG => 7,
+        __WASI_EACCES => EACCES,
+        __WASI_EADDRINUSE => 98,
+        __WASI_EADDRNOTAVAIL => 99,
+        __WASI_EAFNOSUPPORT => 97,
+        __WASI_EAGAIN => 11,
+        __WASI_EALREADY => 114,
+        __WASI_EBADF => EBADF,
+        __WASI_EBADMSG => 74,
+        __WASI_EBUSY => 16,
+        __WASI_ECANCELED => 125,
+        __WASI_ECHILD => 10,
+        __WASI_ECONNABORTED => 103,
+        __WASI_ECONNREFUSED => 111,
+        __WASI_ECONNRESET => 104,
+        __WASI_EDEADLK => 35,
+        __WASI_EDESTADDRREQ => 89,
+        __WASI_EDOM => 33,
+        __WASI_EDQUOT => 122,
+        __WASI_EEXIST => EEXIST,
+        __WASI_EFAULT => EFAULT,
+        __WASI_EFBIG => 27,
+        __WASI_EHOSTUNREACH => 113,
+        __WASI_EIDRM => 43,
+        __WASI_EILSEQ => 84,
+        __WASI_EINPROGRESS => 115,
+        __WASI_EINTR => 4,
+        __WASI_EINVAL => EINVAL,
+        __WASI_EIO => EIO,
+        __WASI_EISCONN => 106,
+        __WASI_EISDIR => EISDIR,
+        __WASI_ELOOP => 40,
+        __WASI_EMFILE => 24,
+        __WASI_EMLINK => 31,
+        __WASI_EMSGSIZE => 90,
+        __WASI_ENAMETOOLONG => 36,
+        __WASI_ENETDOWN => 100,
+        __WASI_ENETRESET => 102,
+        __WASI_ENETUNREACH => 101,
+        __WASI_ENFILE => 23,
+        __WASI_ENOBUFS => 105,
+        __WASI_ENODEV => 19,
+        __WASI_ENOENT => ENOENT,
+        __WASI_ENOEXEC => 8,
+        __WASI_ENOLCK => 37,
+        __WASI_ENOMEM => 12,
+        __WASI_ENOMSG => 42,
+        __WASI_ENOPROTOOPT => 92,
+        __WASI_ENOSPC => 28,
+        __WASI_ENOSYS => ENOSYS,
+        __WASI_ENOTCONN => 107,
+        __WASI_ENOTDIR => ENOTDIR,
+        __WASI_ENOTEMPTY => ENOTEMPTY,
+        __WASI_ENOTSOCK => ENOTSOCK,
+        __WASI_ENOTSUP => 95,
+        __WASI_ENOTTY => ENOTTY,
+        __WASI_ENXIO => 6,
+        __WASI_EOVERFLOW => 75,
+        __WASI_EPERM => EPERM,
+        __WASI_EPIPE => 32,
+        __WASI_EPROTO => 71,
+        __WASI_EPROTONOSUPPORT => 93,
+        __WASI_EPROTOTYPE => 91,
+        __WASI_ERANGE => ERANGE,
+        __WASI_EROFS => 30,
+        __WASI_ESPIPE => ESPIPE,
+        __WASI_ESRCH => 3,
+        __WASI_ETIMEDOUT => 110,
+        __WASI_ETXTBSY => 26,
+        __WASI_EXDEV => 18,
+        // Capabilities are how the sandbox is enforced.
+        __WASI_ENOTCAPABLE => EACCES,
+        _ => EIO,
+    }
+}
+
+/// A file descriptor that isn't backed by the [`WasiFs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialFd {
+    /// A host file descriptor, for sockets and pipes.
+    Host(c_int),
+    /// `/dev/null`.
+    Null,
+    /// `/dev/random` and `/dev/urandom`.
+    Random,
+}
+
+impl SpecialFd {
+    fn for_path(path: &str) -> Option<Self> {
+        match path {
+            "/dev/null" => Some(SpecialFd::Null),
+            "/dev/random" | "/dev/urandom" => Some(SpecialFd::Random),
+            _ => None,
+        }
+    }
+}
+
+/// The sandboxed file system of an Emscripten module.
+#[derive(Debug)]
+pub struct EmFs {
+    wasi_fs: WasiFs,
+    /// The current directory, an absolute and normalized guest path.
+    cwd: String,
+    /// The guest path where each preopened directory is mounted.
+    mounts: Vec<(String, __wasi_fd_t)>,
+    special_fds: HashMap<i32, SpecialFd>,
+}
+
+impl EmFs {
+    /// Creates the file system of an Emscripten module from the
+    /// directories preopened in `wasi_fs`.
+    pub fn new(wasi_fs: WasiFs) -> Self {
+        let mounts = wasi_fs
+            .preopen_fds
+            .iter()
+            .filter_map(|fd| {
+                let inode = wasi_fs.fd_map.get(fd)?.inode;
+                let name = &wasi_fs.inodes[inode].name;
+                let name = name.trim_start_matches("./").trim_matches('/');
+                let mount_point = if name.is_empty() || name == "." {
+                    "/".to_string()
+                } else {
+                    format!("/{}", name)
+                };
+                Some((mount_point, *fd))
+            })
+            .collect();
+        Self {
+            wasi_fs,
+            cwd: "/".to_string(),
+            mounts,
+            special_fds: HashMap::new(),
+        }
+    }
+
+    /// The underlying [`WasiFs`].
+    pub fn wasi_fs(&self) -> &WasiFs {
+        &self.wasi_fs
+    }
+
+    /// The underlying [`WasiFs`], mutably.
+    pub fn wasi_fs_mut(&mut self) -> &mut WasiFs {
+        &mut self.wasi_fs
+    }
+
+    /// The current directory.
+    pub fn cwd(&self) -> &str {
+        &self.cwd
+    }
+
+    /// Makes `path` absolute and normalizes it, resolving `.` and `..`
+    /// lexically.
+    fn absolute_path(&self, path: &str) -> EmFsResult<String> {
+        if path.is_empty() {
+            return Err(ENOENT);
+        }
+        let joined = if path.starts_with('/') {
+            path.to_string()
+        } else {
+            format!("{}/{}", self.cwd, path)
+        };
+        let mut components = Vec::new();
+        for component in joined.split('/') {
+            match component {
+                "" | "." => {}
+                ".." => {
+                    components.pop();
+                }
+                component => components.push(component),
+            }
+        }
+        Ok(format!("/{}", components.join("/")))
+    }
+
+    /// Resolves `path` to a preopened directory and a path relative to it.
+    fn resolve(&self, path: &str) -> EmFsResult<(__wasi_fd_t, String)> {
+        let path = self.absolute_path(path)?;
+        let (mount_point, fd) = self
+            .mounts
+            .iter()
+            .filter(|(mount_point, _)| {
+                mount_point == "/"
+                    || path == *mount_point
+                    || path.starts_with(&format!("{}/", mount_point))
+            })
+            .max_by_key(|(mount_point, _)| mount_point.len())
+            .ok_or(ENOENT)?;
+        let relative_path = path[mount_point.len()..].trim_start_matches('/');
+        let relative_path = if relative_path.is_empty() {
+            "."
+        } else {
+            relative_path
+        };
+        Ok((*fd, relative_path.to_string()))
+    }
+
+    /// Registers a host file descriptor, and returns the file descriptor
+    /// the module sees.
+    pub fn add_host_fd(&mut self, host_fd: c_int) -> i32 {
+        self.add_special_fd(SpecialFd::Host(host_fd))
+    }
+
+    fn add_special_fd(&mut self, special_fd: SpecialFd) -> i32 {
+        let fd = self.wasi_fs.next_fd.get();
+        self.wasi_fs.next_fd.set(fd + 1);
+        self.special_fds.insert(fd as i32, special_fd);
+        fd as i32
+    }
+
+    /// The host file descriptor behind `fd`, if `fd` is a socket or a pipe.
+    pub fn host_fd(&self, fd: i32) -> Option<c_int> {
+        match self.special_fds.get(&fd) {
+            Some(SpecialFd::Host(host_fd)) => Some(*host_fd),
+            _ => None,
+        }
+    }
+
+    /// The file descriptor of the module behind the host file descriptor
+    /// `host_fd`.
+    pub fn guest_fd(&self, host_fd: c_int) -> Option<i32> {
+        self.special_fds
+            .iter()
+            .find(|(_, special_fd)| **special_fd == SpecialFd::Host(host_fd))
+            .map(|(fd, _)| *fd)
+    }
+
+    /// Whether `fd` is open.
+    pub fn is_open(&self, fd: i32) -> bool {
+        self.special_fds.contains_key(&fd) || self.wasi_fs.fd_map.contains_key(&(fd as u32))
+    }
+
+    /// Opens the file at `path`, with the flags of `open`.
+    pub fn open(&mut self, path: &str, flags: i32) -> EmFsResult<i32> {
+        if let Some(special_fd) = self
+            .absolute_path(path)
+            .ok()
+            .and_then(|path| SpecialFd::for_path(&path))
+        {
+            return Ok(self.add_special_fd(special_fd));
+        }
+        let (dir_fd, relative_path) = self.resolve(path)?;
+
+        let mut o_flags = 0;
+        if flags & O_CREAT != 0 {
+            o_flags |= __WASI_O_CREAT;
+        }
+        if flags & O_EXCL != 0 {
+            o_flags |= __WASI_O_EXCL;
+        }
+        if flags & O_TRUNC != 0 {
+            o_flags |= __WASI_O_TRUNC;
+        }
+        if flags & O_DIRECTORY != 0 {
+            o_flags |= __WASI_O_DIRECTORY;
+        }
+        let fs_flags = if flags & O_APPEND != 0 {
+            __WASI_FDFLAG_APPEND
+        } else {
+            0
+        };
+        let dirflags = if flags & O_NOFOLLOW != 0 {
+            0
+        } else {
+            __WASI_LOOKUP_SYMLINK_FOLLOW
+        };
+
+        let next_fd = self.wasi_fs.next_fd.get();
+        let fd = self
+            .wasi_fs
+            .open_path(
+                dir_fd,
+                dirflags,
+                &relative_path,
+                o_flags,
+                ALL_RIGHTS,
+                ALL_RIGHTS,
+                fs_flags,
+            )
+            .map_err(wasi_errno_to_errno)?;
+
+        // Restrict the new file descriptor to its access mode; the special
+        // files, like the standard streams, are shared.
+        if fd >= next_fd {
+            if let Some(fd_entry) = self.wasi_fs.fd_map.get_mut(&fd) {
+                match flags & O_ACCMODE {
+                    O_RDONLY => fd_entry.rights &= !__WASI_RIGHT_FD_WRITE,
+                    O_WRONLY => fd_entry.rights &= !__WASI_RIGHT_FD_READ,
+                    _ => {}
+                }
+            }
+        }
+        Ok(fd as i32)
+    }
+
+    /// Closes `fd`.
+    pub fn close(&mut self, fd: i32) -> EmFsResult<()> {
+        if let Some(special_fd) = self.special_fds.remove(&fd) {
+            if let SpecialFd::Host(host_fd) = special_fd {
+                if unsafe { libc::close(host_fd) } == -1 {
+                    return Err(last_os_errno());
+                }
+            }
+            return Ok(());
+        }
+        self.wasi_fs
+            .close_fd(fd as u32)
+            .map_err(wasi_errno_to_errno)
+    }
+
+    /// Reads from `fd` into `buf`, and returns the number of bytes read.
+    pub fn read(&mut self, fd: i32, buf: &mut [u8]) -> EmFsResult<usize> {
+        match self.special_fds.get(&fd) {
+            Some(SpecialFd::Host(host_fd)) => {
+                let ret = unsafe { libc::read(*host_fd, buf.as_mut_ptr() as _, buf.len() as _) };
+                if ret < 0 {
+                    Err(last_os_errno())
+                } else {
+                    Ok(ret as usize)
+                }
+            }
+            Some(SpecialFd::Null) => Ok(0),
+            Some(SpecialFd::Random) => {
+                getrandom::getrandom(buf).map_err(|_| EIO)?;
+                Ok(buf.len())
+            }
+            None => self
+                .wasi_fs
+                .read_fd(fd as u32, buf)
+                .map_err(wasi_errno_to_errno),
+        }
+    }
+
+    /// Writes `buf` to `fd`, and returns the number of bytes written.
+    pub fn write(&mut self, fd: i32, buf: &[u8]) -> EmFsResult<usize> {
+        match self.special_fds.get(&fd) {
+            Some(SpecialFd::Host(host_fd)) => {
+                let ret = unsafe { libc::write(*host_fd, buf.as_ptr() as _, buf.len() as _) };
+                if ret < 0 {
+                    Err(last_os_errno())
+                } else {
+                    Ok(ret as usize)
+                }
+            }
+            Some(SpecialFd::Null) | Some(SpecialFd::Random) => Ok(buf.len()),
+            None => self
+                .wasi_fs
+                .write_fd(fd as u32, buf)
+                .map_err(wasi_errno_to_errno),
+        }
+    }
+
+    /// Reads from `fd` at `offset`, without moving the offset of `fd`.
+    pub fn pread(&mut self, fd: i32, buf: &mut [u8], offset: u64) -> EmFsResult<usize> {
+        if self.special_fds.contains_key(&fd) {
+            return Err(ESPIPE);
+        }
+        let previous_offset = self.swap_offset(fd, offset)?;
+        let result = self.read(fd, buf);
+        self.swap_offset(fd, previous_offset)?;
+        result
+    }
+
+    /// Writes to `fd` at `offset`, without moving the offset of `fd`.
+    pub fn pwrite(&mut self, fd: i32, buf: &[u8], offset: u64) -> EmFsResult<usize> {
+        if self.special_fds.contains_key(&fd) {
+            return Err(ESPIPE);
+        }
+        let previous_offset = self.swap_offset(fd, offset)?;
+        let result = self.write(fd, buf);
+        self.swap_offset(fd, previous_offset)?;
+        result
+    }
+
+    fn swap_offset(&mut self, fd: i32, offset: u64) -> EmFsResult<u64> {
+        let fd_entry = self.wasi_fs.fd_map.get_mut(&(fd as u32)).ok_or(EBADF)?;
+        Ok(std::mem::replace(&mut fd_entry.offset, offset))
+    }
+
+    /// Moves the offset of `fd`, with the `whence` of `lseek`, and returns
+    /// the new offset.
+    pub fn seek(&mut self, fd: i32, offset: i64, whence: i32) -> EmFsResult<u64> {
+        match self.special_fds.get(&fd) {
+            Some(SpecialFd::Host(_)) => Err(ESPIPE),
+            Some(_) => Ok(0),
+            None => {
+                let whence = match whence {
+                    0 => __WASI_WHENCE_SET,
+                    1 => __WASI_WHENCE_CUR,
+                    2 => __WASI_WHENCE_END,
+                    _ => return Err(EINVAL),
+                };
+                self.wasi_fs
+                    .seek_fd(fd as u32, offset, whence)
+                    .map_err(wasi_errno_to_errno)
+            }
+        }
+    }
+
+    /// Returns the metadata of the file at `path`, following the symbolic
+    /// links if `follow_symlinks` is set.
+    pub fn stat(&mut self, path: &str, follow_symlinks: bool) -> EmFsResult<__wasi_filestat_t> {
+        if self
+            .absolute_path(path)
+            .ok()
+            .and_then(|path| SpecialFd::for_path(&path))
+            .is_some()
+        {
+            return Ok(character_device_filestat());
+        }
+        let (dir_fd, relative_path) = self.resolve(path)?;
+        let flags = if follow_symlinks {
+            __WASI_LOOKUP_SYMLINK_FOLLOW
+        } else {
+            0
+        };
+        self.wasi_fs
+            .path_filestat(dir_fd, flags, &relative_path)
+            .map_err(wasi_errno_to_errno)
+    }
+
+    /// Returns the metadata of the file `fd`.
+    pub fn fstat(&self, fd: i32) -> EmFsResult<__wasi_filestat_t> {
+        match self.special_fds.get(&fd) {
+            Some(SpecialFd::Host(_)) => Ok(__wasi_filestat_t {
+                st_filetype: __WASI_FILETYPE_SOCKET_STREAM,
+                st_nlink: 1,
+                ..__wasi_filestat_t::default()
+            }),
+            Some(_) => Ok(character_device_filestat()),
+            None => {
+                let fd_entry = self
+                    .wasi_fs
+                    .get_fd(fd as u32)
+                    .map_err(wasi_errno_to_errno)?;
+                let inode = &self.wasi_fs.inodes[fd_entry.inode];
+                if inode.is_preopened {
+                    Ok(inode.stat)
+                } else {
+                    Ok(self
+                        .wasi_fs
+                        .get_stat_for_kind(&inode.kind)
+                        .unwrap_or(inode.stat))
+                }
+            }
+        }
+    }
+
+    /// Checks that the file at `path` exists.
+    pub fn access(&mut self, path: &str) -> EmFsResult<()> {
+        self.stat(path, true).map(|_| ())
+    }
+
+    /// Changes the current directory to `path`.
+    pub fn chdir(&mut self, path: &str) -> EmFsResult<()> {
+        let absolute_path = self.absolute_path(path)?;
+        // The root is always a valid directory, even if nothing is mounted
+        // at it, so the mounts can be reached from it.
+        if absolute_path != "/"
+            && self.stat(&absolute_path, true)?.st_filetype != __WASI_FILETYPE_DIRECTORY
+        {
+            return Err(ENOTDIR);
+        }
+        self.cwd = absolute_path;
+        Ok(())
+    }
+
+    /// Creates the directory `path`.
+    pub fn mkdir(&mut self, path: &str) -> EmFsResult<()> {
+        if self.stat(path, false).is_ok() {
+            return Err(EEXIST);
+        }
+        // Unlike `WasiFs::create_directory`, `mkdir` doesn't create the
+        // missing parents.
+        let absolute_path = self.absolute_path(path)?;
+        let parent = match absolute_path.rfind('/') {
+            Some(0) | None => "/",
+            Some(index) => &absolute_path[..index],
+        };
+        if parent != "/" && self.stat(parent, true)?.st_filetype != __WASI_FILETYPE_DIRECTORY {
+            return Err(ENOTDIR);
+        }
+        let (dir_fd, relative_path) = self.resolve(path)?;
+        self.wasi_fs
+            .create_directory(dir_fd, &relative_path)
+            .map_err(wasi_errno_to_errno)
+    }
+
+    /// Removes the empty directory `path`.
+    pub fn rmdir(&mut self, path: &str) -> EmFsResult<()> {
+        let (dir_fd, relative_path) = self.resolve(path)?;
+        if relative_path == "." {
+            // Mount points can't be removed.
+            return Err(EACCES);
+        }
+        self.wasi_fs
+            .remove_directory(dir_fd, &relative_path)
+            .map_err(wasi_errno_to_errno)
+    }
+
+    /// Removes the file `path`.
+    pub fn unlink(&mut self, path: &str) -> EmFsResult<()> {
+        let (dir_fd, relative_path) = self.resolve(path)?;
+        self.wasi_fs
+            .unlink_file(dir_fd, &relative_path)
+            .map_err(wasi_errno_to_errno)
+    }
+
+    /// Renames the file `old_path` to `new_path`.
+    pub fn rename(&mut self, old_path: &str, new_path: &str) -> EmFsResult<()> {
+        let (old_dir_fd, old_relative_path) = self.resolve(old_path)?;
+        let (new_dir_fd, new_relative_path) = self.resolve(new_path)?;
+        self.wasi_fs
+            .rename(
+                old_dir_fd,
+                &old_relative_path,
+                new_dir_fd,
+                &new_relative_path,
+            )
+            .map_err(wasi_errno_to_errno)
+    }
+
+    /// Returns up to `count` entries of the directory `fd`, as their name,
+    /// file type and inode number, and moves past them.
+    pub fn read_dir(
+        &mut self,
+        fd: i32,
+        count: usize,
+    ) -> EmFsResult<Vec<(String, __wasi_filetype_t, __wasi_inode_t)>> {
+        let fd_entry = self
+            .wasi_fs
+            .get_fd(fd as u32)
+            .map_err(wasi_errno_to_errno)?;
+        let position = fd_entry.offset as usize;
+        let dir_ino = self.wasi_fs.inodes[fd_entry.inode].stat.st_ino;
+
+        let mut entries = vec![
+            (".".to_string(), __WASI_FILETYPE_DIRECTORY, dir_ino),
+            ("..".to_string(), __WASI_FILETYPE_DIRECTORY, 0),
+        ];
+        entries.extend(
+            self.wasi_fs
+                .read_dir(fd as u32)
+                .map_err(wasi_errno_to_errno)?,
+        );
+        let entries = entries
+            .into_iter()
+            .skip(position)
+            .take(count)
+            .collect::<Vec<_>>();
+
+        self.swap_offset(fd, (position + entries.len()) as u64)?;
+        Ok(entries)
+    }
+
+    /// Truncates or extends the file `fd` to `length` bytes.
+    pub fn truncate(&mut self, fd: i32, length: u64) -> EmFsResult<()> {
+        if self.special_fds.contains_key(&fd) {
+            return Err(EINVAL);
+        }
+        self.wasi_fs
+            .set_size(fd as u32, length)
+            .map_err(wasi_errno_to_errno)
+    }
+
+    /// Synchronizes the file `fd` to disk.
+    pub fn sync(&mut self, fd: i32) -> EmFsResult<()> {
+        match self.special_fds.get(&fd) {
+            Some(SpecialFd::Host(host_fd)) => {
+                if unsafe { libc::fsync(*host_fd) } == -1 {
+                    Err(last_os_errno())
+                } else {
+                    Ok(())
+                }
+            }
+            Some(_) => Err(EINVAL),
+            None => self.wasi_fs.sync_fd(fd as u32).map_err(wasi_errno_to_errno),
+        }
+    }
+
+    /// Duplicates `fd` to the lowest free file descriptor at or above
+    /// `min_fd`.
+    pub fn dup(&mut self, fd: i32, min_fd: i32) -> EmFsResult<i32> {
+        if !self.is_open(fd) {
+            return Err(EBADF);
+        }
+        let mut new_fd = std::cmp::max(min_fd, 0);
+        while self.is_open(new_fd) {
+            new_fd += 1;
+        }
+        self.dup2(fd, new_fd)
+    }
+
+    /// Duplicates `fd` to `new_fd`, closing `new_fd` first if it's open.
+    pub fn dup2(&mut self, fd: i32, new_fd: i32) -> EmFsResult<i32> {
+        if !self.is_open(fd) || new_fd < 0 {
+            return Err(EBADF);
+        }
+        if fd == new_fd {
+            return Ok(new_fd);
+        }
+        if self.is_open(new_fd) {
+            self.close(new_fd)?;
+        }
+
+        if let Some(special_fd) = self.special_fds.get(&fd).copied() {
+            let special_fd = match special_fd {
+                SpecialFd::Host(host_fd) => {
+                    let new_host_fd = unsafe { libc::dup(host_fd) };
+                    if new_host_fd == -1 {
+                        return Err(last_os_errno());
+                    }
+                    SpecialFd::Host(new_host_fd)
+                }
+                special_fd => special_fd,
+            };
+            self.special_fds.insert(new_fd, special_fd);
+        } else {
+            let fd_entry = self
+                .wasi_fs
+                .get_fd(fd as u32)
+                .map_err(wasi_errno_to_errno)?;
+            let new_fd_entry = Fd {
+                rights: fd_entry.rights,
+                rights_inheriting: fd_entry.rights_inheriting,
+                flags: fd_entry.flags,
+                offset: fd_entry.offset,
+                open_flags: fd_entry.open_flags,
+                inode: fd_entry.inode,
+            };
+            self.wasi_fs.fd_map.insert(new_fd as u32, new_fd_entry);
+        }
+
+        // Keep the file descriptors allocated later from colliding.
+        if new_fd as u32 >= self.wasi_fs.next_fd.get() {
+            self.wasi_fs.next_fd.set(new_fd as u32 + 1);
+        }
+        Ok(new_fd)
+    }
+}
+
+/// The errno of the last failed call to the host libc.
+fn last_os_errno() -> i32 {
+    std::io::Error::last_os_error()
+        .raw_os_error()
+        .unwrap_or(EIO)
+}
+
+fn character_device_filestat() -> __wasi_filestat_t {
+    __wasi_filestat_t {
+        st_filetype: __WASI_FILETYPE_CHARACTER_DEVICE,
+        st_nlink: 1,
+        ..__wasi_filestat_t::default()
+    }
+}
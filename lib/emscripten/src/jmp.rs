@@ -1,50 +1,42 @@
-use super::env::get_emscripten_data;
-use super::process::abort_with_message;
-use libc::c_int;
-// use std::cell::UnsafeCell;
+//! `setjmp` and `longjmp`.
+//!
+//! Emscripten lowers a function calling `setjmp` so that it records the
+//! jump buffer with `_saveSetjmp`, and calls everything that may `longjmp`
+//! through the `invoke_*` imports. `longjmp` stores the jump buffer with
+//! `setThrew` and unwinds with a [`LongJumpRet`] error up to the closest
+//! `invoke_*` frame, which returns normally. The lowered function then
+//! finds the `setjmp` the buffer belongs to with `_testSetjmp`, and jumps
+//! back to it.
+
+#![allow(non_snake_case)]
+
+use super::env::{call_free, call_malloc, get_emscripten_data};
 use crate::EmEnv;
+use libc::c_int;
 use std::error::Error;
 use std::fmt;
 
 use wasmer::RuntimeError;
 
 /// setjmp
-pub fn __setjmp(ctx: &mut EmEnv, _env_addr: u32) -> c_int {
+///
+/// Only reached when `setjmp` wasn't lowered by the compiler, e.g. when it's
+/// called through a function pointer: there is no caller to jump back to.
+pub fn __setjmp(ctx: &mut EmEnv, env_addr: u32) -> c_int {
     debug!("emscripten::__setjmp (setjmp)");
-    abort_with_message(ctx, "missing function: _setjmp");
-    unreachable!()
-    // unsafe {
-    //     // Rather than using the env as the holder of the jump buffer pointer,
-    //     // we use the environment address to store the index relative to jumps
-    //     // so the address of the jump it's outside the wasm memory itself.
-    //     let jump_index = emscripten_memory_pointer!(ctx.memory(0), env_addr) as *mut i8;
-    //     // We create the jump buffer outside of the wasm memory
-    //     let jump_buf: UnsafeCell<[u32; 27]> = UnsafeCell::new([0; 27]);
-    //     let jumps = &mut get_emscripten_data(ctx).jumps;
-    //     let result = setjmp(jump_buf.get() as _);
-    //     // We set the jump index to be the last 3value of jumps
-    //     *jump_index = jumps.len() as _;
-    //     // We hold the reference of the jump buffer
-    //     jumps.push(jump_buf);
-    //     result
-    // }
+    let setjmp_id = next_setjmp_id(ctx);
+    set_u32(ctx, env_addr, setjmp_id);
+    0
 }
 
 /// longjmp
-#[allow(unreachable_code)]
-pub fn __longjmp(ctx: &mut EmEnv, _env_addr: u32, _val: c_int) {
+pub fn __longjmp(ctx: &mut EmEnv, env_addr: u32, val: c_int) {
     debug!("emscripten::__longjmp (longmp)");
-    abort_with_message(ctx, "missing function: _longjmp");
-    // unsafe {
-    //     // We retrieve the jump index from the env address
-    //     let jump_index = emscripten_memory_pointer!(ctx.memory(0), env_addr) as *mut i8;
-    //     let jumps = &mut get_emscripten_data(ctx).jumps;
-    //     // We get the real jump buffer from the jumps vector, using the retrieved index
-    //     let jump_buf = &jumps[*jump_index as usize];
-    //     longjmp(jump_buf.get() as _, val)
-    // };
+    _longjmp(ctx, env_addr as i32, val)
 }
 
+/// The error unwinding the stack of the module, up to the closest `invoke_*`
+/// frame, on a `longjmp`.
 #[derive(Copy, Clone, Debug)]
 pub struct LongJumpRet;
 
@@ -57,9 +49,8 @@ impl fmt::Display for LongJumpRet {
 impl Error for LongJumpRet {}
 
 /// _longjmp
-// This function differs from the js implementation, it should return Result<(), &'static str>
-#[allow(unreachable_code)]
 pub fn _longjmp(ctx: &mut EmEnv, env_addr: i32, val: c_int) {
+    debug!("emscripten::_longjmp {} {}", env_addr, val);
     let val = if val == 0 { 1 } else { val };
     get_emscripten_data(ctx)
         .set_threw
@@ -67,12 +58,74 @@ pub fn _longjmp(ctx: &mut EmEnv, env_addr: i32, val: c_int) {
         .expect("set_threw is None")
         .call(env_addr, val)
         .expect("set_threw failed to call");
-    // TODO: return Err("longjmp")
     RuntimeError::raise(Box::new(LongJumpRet));
-    unreachable!();
 }
 
-// extern "C" {
-//     fn setjmp(env: *mut c_void) -> c_int;
-//     fn longjmp(env: *mut c_void, val: c_int) -> !;
-// }
+/// _saveSetjmp
+///
+/// Records the `setjmp` of `env_addr`, to jump to `label` of the calling
+/// function, in its `table` of `size` entries. Returns the table, which is
+/// reallocated when it's full, and its size in `tempRet0`.
+pub fn _saveSetjmp(ctx: &mut EmEnv, env_addr: u32, label: u32, table: u32, size: u32) -> u32 {
+    debug!("emscripten::_saveSetjmp {} {}", env_addr, label);
+    let setjmp_id = next_setjmp_id(ctx);
+    set_u32(ctx, env_addr, setjmp_id);
+
+    let mut table = table;
+    let mut size = size;
+    let mut index = 0;
+    while index < size && get_u32(ctx, table + 8 * index) != 0 {
+        index += 1;
+    }
+    if index == size {
+        // The table is full, it's grown to twice its size. It has an extra
+        // entry, for the zero id terminating it.
+        let new_size = std::cmp::max(size, 1) * 2;
+        let new_table = call_malloc(ctx, 8 * (new_size + 1));
+        for offset in (0..8 * size).step_by(4) {
+            let value = get_u32(ctx, table + offset);
+            set_u32(ctx, new_table + offset, value);
+        }
+        call_free(ctx, table);
+        table = new_table;
+        size = new_size;
+    }
+    let entry = table + 8 * index;
+    set_u32(ctx, entry, setjmp_id);
+    set_u32(ctx, entry + 4, label);
+    set_u32(ctx, entry + 8, 0);
+
+    get_emscripten_data(ctx).temp_ret_0 = size as i32;
+    table
+}
+
+/// _testSetjmp
+///
+/// Returns the label of the `setjmp` with the id `id` in `table`, or 0 if it
+/// belongs to another function.
+pub fn _testSetjmp(ctx: &mut EmEnv, id: u32, table: u32, size: u32) -> u32 {
+    debug!("emscripten::_testSetjmp {}", id);
+    for index in 0..size {
+        let entry = table + 8 * index;
+        match get_u32(ctx, entry) {
+            0 => break,
+            entry_id if entry_id == id => return get_u32(ctx, entry + 4),
+            _ => {}
+        }
+    }
+    0
+}
+
+fn next_setjmp_id(ctx: &mut EmEnv) -> u32 {
+    let data = get_emscripten_data(ctx);
+    data.setjmp_id += 1;
+    data.setjmp_id
+}
+
+fn get_u32(ctx: &EmEnv, offset: u32) -> u32 {
+    ctx.memory(0).view::<u32>()[offset as usize / 4].get()
+}
+
+fn set_u32(ctx: &EmEnv, offset: u32, value: u32) {
+    ctx.memory(0).view::<u32>()[offset as usize / 4].set(value)
+}
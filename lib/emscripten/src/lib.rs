@@ -14,23 +14,14 @@
 extern crate log;
 
 use lazy_static::lazy_static;
-use std::cell::UnsafeCell;
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::{f64, ffi::c_void};
 use wasmer::{
     imports, namespace, Exports, ExternRef, Function, FunctionType, Global, ImportObject, Instance,
-    Memory, MemoryType, Module, NativeFunc, Pages, RuntimeError, Store, Table, TableType, Val,
-    ValType,
+    LazyInit, Memory, MemoryType, Module, NativeFunc, Pages, RuntimeError, Store, Table, TableType,
+    Val, ValType, WasmerEnv,
 };
-
-#[cfg(unix)]
-use ::libc::DIR as LibcDir;
-
-// We use a placeholder for windows
-#[cfg(not(unix))]
-type LibcDir = u8;
+use wasmer_wasi::{WasiFs, WasiState};
 
 #[macro_use]
 mod macros;
@@ -43,6 +34,7 @@ mod errno;
 mod exception;
 mod exec;
 mod exit;
+pub mod fs;
 mod inet;
 mod io;
 mod jmp;
@@ -63,6 +55,10 @@ mod unistd;
 mod utils;
 mod varargs;
 
+pub use self::exception::CxxException;
+use self::exception::Exceptions;
+pub use self::fs::EmFs;
+pub use self::jmp::LongJumpRet;
 pub use self::storage::{align_memory, static_alloc};
 pub use self::utils::{
     allocate_cstr_on_stack, allocate_on_stack, get_emscripten_memory_size, get_emscripten_metadata,
@@ -72,24 +68,40 @@ pub use self::utils::{
 #[derive(Clone)]
 /// The environment provided to the Emscripten imports.
 pub struct EmEnv {
-    memory: Arc<Option<Memory>>,
+    memory: LazyInit<Memory>,
     data: *mut *mut EmscriptenData<'static>,
+    fs: Arc<Mutex<EmFs>>,
 }
 
+// The memory is imported by Emscripten modules, there's nothing to bind
+// from the instance: it is set by `run_emscripten_instance`.
+impl WasmerEnv for EmEnv {}
+
 impl EmEnv {
+    /// Creates an environment whose file system only has the standard
+    /// streams.
     pub fn new() -> Self {
+        let wasi_state = WasiState::new("")
+            .build()
+            .expect("an empty WASI file system can always be built");
+        Self::new_with_fs(wasi_state.fs)
+    }
+
+    /// Creates an environment whose file system is sandboxed to the
+    /// directories preopened in `wasi_fs`.
+    ///
+    /// See the [`fs`] module for how they are mounted.
+    pub fn new_with_fs(wasi_fs: WasiFs) -> Self {
         Self {
-            memory: Arc::new(None),
+            memory: LazyInit::new(),
             // TODO: clean this up
             data: Box::into_raw(Box::new(std::ptr::null_mut())),
+            fs: Arc::new(Mutex::new(EmFs::new(wasi_fs))),
         }
     }
 
     pub fn set_memory(&mut self, memory: Memory) {
-        let ptr = Arc::as_ptr(&self.memory) as *mut _;
-        unsafe {
-            *ptr = Some(memory);
-        }
+        self.memory.initialize(memory);
     }
 
     pub fn set_data(&mut self, data: *mut c_void) {
@@ -98,7 +110,12 @@ impl EmEnv {
 
     /// Get a reference to the memory
     pub fn memory(&self, _mem_idx: u32) -> &Memory {
-        (*self.memory).as_ref().unwrap()
+        self.memory.get_ref().unwrap()
+    }
+
+    /// Get the file system of the module
+    pub fn fs(&self) -> MutexGuard<'_, EmFs> {
+        self.fs.lock().unwrap()
     }
 }
 
@@ -127,8 +144,9 @@ pub struct EmscriptenData<'a> {
     pub memalign: Option<NativeFunc<'a, (u32, u32), u32>>,
     pub memset: Option<NativeFunc<'a, (u32, u32, u32), u32>>,
     pub stack_alloc: Option<NativeFunc<'a, u32, u32>>,
-    pub jumps: Vec<UnsafeCell<[u32; 27]>>,
-    pub opened_dirs: HashMap<i32, Box<*mut LibcDir>>,
+    pub setjmp_id: u32,
+    pub exceptions: Exceptions,
+    pub cxa_can_catch: Option<NativeFunc<'a, (i32, i32, i32), i32>>,
 
     pub dyn_call_i: Option<NativeFunc<'a, i32, i32>>,
     pub dyn_call_ii: Option<NativeFunc<'a, (i32, i32), i32>>,
@@ -197,14 +215,12 @@ pub struct EmscriptenData<'a> {
     pub stack_save: Option<NativeFunc<'a, (), i32>>,
     pub stack_restore: Option<NativeFunc<'a, i32>>,
     pub set_threw: Option<NativeFunc<'a, (i32, i32)>>,
-    pub mapped_dirs: HashMap<String, PathBuf>,
 }
 
 impl<'a> EmscriptenData<'a> {
     pub fn new(
         instance: &'a mut Instance,
         globals: &'a EmscriptenGlobalsData,
-        mapped_dirs: HashMap<String, PathBuf>,
     ) -> EmscriptenData<'a> {
         let malloc = instance
             .exports
@@ -316,6 +332,12 @@ impl<'a> EmscriptenData<'a> {
             .get_native_function("dynCall_viidddddddd")
             .ok();
 
+        let cxa_can_catch = instance
+            .exports
+            .get_native_function("___cxa_can_catch")
+            .or(instance.exports.get_native_function("__cxa_can_catch"))
+            .ok();
+
         let stack_save = instance.exports.get_native_function("stackSave").ok();
         let stack_restore = instance.exports.get_native_function("stackRestore").ok();
         let set_threw = instance
@@ -332,8 +354,9 @@ impl<'a> EmscriptenData<'a> {
             memalign,
             memset,
             stack_alloc,
-            jumps: Vec::new(),
-            opened_dirs: HashMap::new(),
+            setjmp_id: 0,
+            exceptions: Exceptions::default(),
+            cxa_can_catch,
 
             dyn_call_i,
             dyn_call_ii,
@@ -394,7 +417,6 @@ impl<'a> EmscriptenData<'a> {
             stack_save,
             stack_restore,
             set_threw,
-            mapped_dirs,
         }
     }
 }
@@ -476,9 +498,8 @@ pub fn run_emscripten_instance(
     path: &str,
     args: Vec<&str>,
     entrypoint: Option<String>,
-    mapped_dirs: Vec<(String, PathBuf)>,
 ) -> Result<(), RuntimeError> {
-    let mut data = EmscriptenData::new(instance, &globals.data, mapped_dirs.into_iter().collect());
+    let mut data = EmscriptenData::new(instance, &globals.data);
     env.set_memory(globals.memory.clone());
     env.set_data(&mut data as *mut _ as *mut c_void);
     set_up_emscripten(instance)?;
@@ -910,6 +931,7 @@ pub fn generate_emscripten_env(
         "___cxa_increment_exception_refcount" => Function::new_native_with_env(store, env.clone(), crate::exception::___cxa_increment_exception_refcount),
         "___cxa_rethrow_primary_exception" => Function::new_native_with_env(store, env.clone(), crate::exception::___cxa_rethrow_primary_exception),
         "___cxa_throw" => Function::new_native_with_env(store, env.clone(), crate::exception::___cxa_throw),
+        "___cxa_rethrow" => Function::new_native_with_env(store, env.clone(), crate::exception::___cxa_rethrow),
         "___cxa_begin_catch" => Function::new_native_with_env(store, env.clone(), crate::exception::___cxa_begin_catch),
         "___cxa_end_catch" => Function::new_native_with_env(store, env.clone(), crate::exception::___cxa_end_catch),
         "___cxa_uncaught_exception" => Function::new_native_with_env(store, env.clone(), crate::exception::___cxa_uncaught_exception),
@@ -961,6 +983,8 @@ pub fn generate_emscripten_env(
         "__longjmp" => Function::new_native_with_env(store, env.clone(), crate::jmp::__longjmp),
         "_longjmp" => Function::new_native_with_env(store, env.clone(), crate::jmp::_longjmp),
         "_emscripten_longjmp" => Function::new_native_with_env(store, env.clone(), crate::jmp::_longjmp),
+        "_saveSetjmp" => Function::new_native_with_env(store, env.clone(), crate::jmp::_saveSetjmp),
+        "_testSetjmp" => Function::new_native_with_env(store, env.clone(), crate::jmp::_testSetjmp),
 
         // Bitwise
         "_llvm_bswap_i64" => Function::new_native_with_env(store, env.clone(), crate::bitwise::_llvm_bswap_i64),
@@ -1009,8 +1033,9 @@ pub fn generate_emscripten_env(
         "__Unwind_GetIPInfo" => Function::new_native_with_env(store, env.clone(), crate::emscripten_target::__Unwind_GetIPInfo),
         "___cxa_find_matching_catch_2" => Function::new_native_with_env(store, env.clone(), crate::emscripten_target::___cxa_find_matching_catch_2),
         "___cxa_find_matching_catch_3" => Function::new_native_with_env(store, env.clone(), crate::emscripten_target::___cxa_find_matching_catch_3),
-        "___cxa_free_exception" => Function::new_native_with_env(store, env.clone(), crate::emscripten_target::___cxa_free_exception),
-        "___resumeException" => Function::new_native_with_env(store, env.clone(), crate::emscripten_target::___resumeException),
+        "___cxa_find_matching_catch_4" => Function::new_native_with_env(store, env.clone(), crate::emscripten_target::___cxa_find_matching_catch_4),
+        "___cxa_free_exception" => Function::new_native_with_env(store, env.clone(), crate::exception::___cxa_free_exception),
+        "___resumeException" => Function::new_native_with_env(store, env.clone(), crate::exception::___resumeException),
         "_dladdr" => Function::new_native_with_env(store, env.clone(), crate::emscripten_target::_dladdr),
         "_pthread_attr_destroy" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_attr_destroy),
         "_pthread_attr_getstack" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_attr_getstack),
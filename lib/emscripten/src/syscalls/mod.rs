@@ -11,8 +11,9 @@ pub use self::unix::*;
 pub use self::windows::*;
 
 use crate::{
-    ptr::{Array, WasmPtr},
-    utils::{copy_stat_into_wasm, get_cstr_path, get_current_directory},
+    fs::{self, EmFsResult},
+    ptr::WasmPtr,
+    utils::copy_filestat_into_wasm,
     EmEnv,
 };
 
@@ -20,37 +21,82 @@ use super::varargs::VarArgs;
 use byteorder::{ByteOrder, LittleEndian};
 /// NOTE: TODO: These syscalls only support wasm_32 for now because they assume offsets are u32
 /// Syscall list: https://www.cs.utexas.edu/~bismith/test/syscalls/syscalls32.html
-use libc::{
-    c_int,
-    c_void,
-    chdir,
-    // setsockopt, getppid
-    close,
-    dup2,
-    exit,
-    fstat,
-    getpid,
-    // readlink,
-    // iovec,
-    lseek,
-    //    open,
-    read,
-    rename,
-    // sockaddr_in,
-    // readv,
-    rmdir,
-    // writev,
-    stat,
-    write,
-    // ENOTTY,
-};
+use libc::{c_char, c_int, exit, getpid};
+use wasmer_wasi::types::*;
 
 use super::env;
 use std::cell::Cell;
+use std::ffi::CStr;
 #[allow(unused_imports)]
 use std::io::Error;
 use std::slice;
 
+/// Reads `path`, a nul-terminated string in the memory of the module.
+fn read_path(path: *const c_char) -> EmFsResult<String> {
+    unsafe { CStr::from_ptr(path) }
+        .to_str()
+        .map(ToString::to_string)
+        .map_err(|_| fs::EINVAL)
+}
+
+/// Converts the result of a file system operation to the value returned by
+/// a syscall: the negated errno on failure.
+fn errno_result(result: EmFsResult<i32>) -> i32 {
+    match result {
+        Ok(ret) => ret,
+        Err(errno) => -errno,
+    }
+}
+
+/// Reads `len` bytes at `offset` in the memory of the module.
+fn read_memory(ctx: &EmEnv, offset: u32, len: u32) -> EmFsResult<Vec<u8>> {
+    let view = ctx.memory(0).view::<u8>();
+    let cells = view
+        .get(offset as usize..offset as usize + len as usize)
+        .ok_or(fs::EFAULT)?;
+    Ok(cells.iter().map(Cell::get).collect())
+}
+
+/// Writes `bytes` at `offset` in the memory of the module.
+fn write_memory(ctx: &EmEnv, offset: u32, bytes: &[u8]) -> EmFsResult<()> {
+    let view = ctx.memory(0).view::<u8>();
+    let cells = view
+        .get(offset as usize..offset as usize + bytes.len())
+        .ok_or(fs::EFAULT)?;
+    for (cell, byte) in cells.iter().zip(bytes) {
+        cell.set(*byte);
+    }
+    Ok(())
+}
+
+/// Reads the `iovcnt` buffers, as their base and length, of the array of
+/// `iovec` at `iov`.
+fn read_iovecs(ctx: &EmEnv, iov: u32, iovcnt: u32) -> EmFsResult<Vec<(u32, u32)>> {
+    let bytes = read_memory(ctx, iov, iovcnt.checked_mul(8).ok_or(fs::EINVAL)?)?;
+    Ok(bytes
+        .chunks(8)
+        .map(|iovec| {
+            (
+                LittleEndian::read_u32(&iovec[0..4]),
+                LittleEndian::read_u32(&iovec[4..8]),
+            )
+        })
+        .collect())
+}
+
+/// Converts a WASI file type to the `d_type` of a `dirent`.
+fn wasi_file_type_to_dirent_type(file_type: __wasi_filetype_t) -> u8 {
+    match file_type {
+        __WASI_FILETYPE_CHARACTER_DEVICE => 2,
+        __WASI_FILETYPE_DIRECTORY => 4,
+        __WASI_FILETYPE_BLOCK_DEVICE => 6,
+        __WASI_FILETYPE_REGULAR_FILE => 8,
+        __WASI_FILETYPE_SYMBOLIC_LINK => 10,
+        __WASI_FILETYPE_SOCKET_DGRAM | __WASI_FILETYPE_SOCKET_STREAM => 12,
+        _ => 0,
+    }
+}
+
 /// exit
 pub fn ___syscall1(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) {
     debug!("emscripten::___syscall1 (exit) {}", _which);
@@ -66,23 +112,29 @@ pub fn ___syscall3(ctx: &mut EmEnv, _which: i32, mut varargs: VarArgs) -> i32 {
     debug!("emscripten::___syscall3 (read) {}", _which);
     let fd: i32 = varargs.get(ctx);
     let buf: u32 = varargs.get(ctx);
-    let count: i32 = varargs.get(ctx);
+    let count: u32 = varargs.get(ctx);
     debug!("=> fd: {}, buf_offset: {}, count: {}", fd, buf, count);
-    let buf_addr = emscripten_memory_pointer!(ctx.memory(0), buf) as *mut c_void;
-    let ret = unsafe { read(fd, buf_addr, count as _) };
+    let mut bytes = vec![0; count as usize];
+    let ret = errno_result(ctx.fs().read(fd, &mut bytes).and_then(|read| {
+        write_memory(ctx, buf, &bytes[..read])?;
+        Ok(read as i32)
+    }));
     debug!("=> ret: {}", ret);
-    ret as _
+    ret
 }
 
 /// write
 pub fn ___syscall4(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall4 (write) {}", _which);
     let fd: i32 = varargs.get(ctx);
-    let buf: i32 = varargs.get(ctx);
-    let count: i32 = varargs.get(ctx);
+    let buf: u32 = varargs.get(ctx);
+    let count: u32 = varargs.get(ctx);
     debug!("=> fd: {}, buf: {}, count: {}", fd, buf, count);
-    let buf_addr = emscripten_memory_pointer!(ctx.memory(0), buf) as *const c_void;
-    unsafe { write(fd, buf_addr, count as _) as i32 }
+    errno_result(
+        read_memory(ctx, buf, count)
+            .and_then(|bytes| ctx.fs().write(fd, &bytes))
+            .map(|written| written as i32),
+    )
 }
 
 /// close
@@ -90,31 +142,23 @@ pub fn ___syscall6(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_in
     debug!("emscripten::___syscall6 (close) {}", _which);
     let fd: i32 = varargs.get(ctx);
     debug!("fd: {}", fd);
-    unsafe { close(fd) }
+    errno_result(ctx.fs().close(fd).map(|()| 0))
 }
 
 // chdir
 pub fn ___syscall12(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall12 (chdir) {}", _which);
-    let path_ptr = varargs.get_str(ctx);
-    let real_path_owned = get_cstr_path(ctx, path_ptr as *const _);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        path_ptr
-    };
-    let ret = unsafe { chdir(real_path) };
-    debug!(
-        "=> path: {:?}, ret: {}",
-        unsafe { std::ffi::CStr::from_ptr(real_path) },
-        ret
-    );
+    let path = read_path(varargs.get_str(ctx));
+    let ret = errno_result(path.and_then(|path| ctx.fs().chdir(&path)).map(|()| 0));
+    debug!("=> cwd: {}, ret: {}", ctx.fs().cwd(), ret);
     ret
 }
 
-pub fn ___syscall10(_ctx: &mut EmEnv, _one: i32, _two: i32) -> i32 {
-    debug!("emscripten::___syscall10");
-    -1
+// unlink
+pub fn ___syscall10(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall10 (unlink) {}", _which);
+    let path = read_path(varargs.get_str(ctx));
+    errno_result(path.and_then(|path| ctx.fs().unlink(&path)).map(|()| 0))
 }
 
 pub fn ___syscall14(_ctx: &mut EmEnv, _one: i32, _two: i32) -> i32 {
@@ -153,11 +197,6 @@ pub fn ___syscall32(_ctx: &mut EmEnv, _one: i32, _two: i32) -> i32 {
     -1
 }
 
-pub fn ___syscall33(_ctx: &mut EmEnv, _one: i32, _two: i32) -> i32 {
-    debug!("emscripten::___syscall33");
-    -1
-}
-
 pub fn ___syscall36(_ctx: &mut EmEnv, _one: i32, _two: i32) -> i32 {
     debug!("emscripten::___syscall36");
     -1
@@ -166,41 +205,21 @@ pub fn ___syscall36(_ctx: &mut EmEnv, _one: i32, _two: i32) -> i32 {
 // rename
 pub fn ___syscall38(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> i32 {
     debug!("emscripten::___syscall38 (rename)");
-    let old_path = varargs.get_str(ctx);
-    let new_path = varargs.get_str(ctx);
-    let real_old_path_owned = get_cstr_path(ctx, old_path as *const _);
-    let real_old_path = if let Some(ref rp) = real_old_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        old_path
-    };
-    let real_new_path_owned = get_cstr_path(ctx, new_path as *const _);
-    let real_new_path = if let Some(ref rp) = real_new_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        new_path
-    };
-    let result = unsafe { rename(real_old_path, real_new_path) };
-    debug!(
-        "=> old_path: {}, new_path: {}, result: {}",
-        unsafe { std::ffi::CStr::from_ptr(real_old_path).to_str().unwrap() },
-        unsafe { std::ffi::CStr::from_ptr(real_new_path).to_str().unwrap() },
-        result
-    );
-    result
+    let old_path = read_path(varargs.get_str(ctx));
+    let new_path = read_path(varargs.get_str(ctx));
+    let result = old_path.and_then(|old_path| {
+        let new_path = new_path?;
+        debug!("=> old_path: {}, new_path: {}", old_path, new_path);
+        ctx.fs().rename(&old_path, &new_path)
+    });
+    errno_result(result.map(|()| 0))
 }
 
 // rmdir
 pub fn ___syscall40(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall40 (rmdir)");
-    let pathname_addr = varargs.get_str(ctx);
-    let real_path_owned = get_cstr_path(ctx, pathname_addr as *const _);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        pathname_addr
-    };
-    unsafe { rmdir(real_path) }
+    let path = read_path(varargs.get_str(ctx));
+    errno_result(path.and_then(|path| ctx.fs().rmdir(&path)).map(|()| 0))
 }
 
 // pipe
@@ -209,27 +228,26 @@ pub fn ___syscall42(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_i
     // offset to a file descriptor, which contains a read end and write end, 2 integers
     let fd_offset: u32 = varargs.get(ctx);
 
-    let emscripten_memory = ctx.memory(0);
-
-    // convert the file descriptor into a vec with two slots
-    let mut fd_vec: Vec<c_int> = emscripten_memory.view()[((fd_offset / 4) as usize)..]
-        .iter()
-        .map(|pipe_end: &Cell<c_int>| pipe_end.get())
-        .take(2)
-        .collect();
-
-    // get it as a mutable pointer
-    let fd_ptr = fd_vec.as_mut_ptr();
+    let mut host_fds: [c_int; 2] = [0; 2];
 
     // call pipe and store the pointers in this array
     #[cfg(target_os = "windows")]
-    let result: c_int = unsafe { libc::pipe(fd_ptr, 2048, 0) };
+    let result: c_int = unsafe { libc::pipe(host_fds.as_mut_ptr(), 2048, 0) };
     #[cfg(not(target_os = "windows"))]
-    let result: c_int = unsafe { libc::pipe(fd_ptr) };
+    let result: c_int = unsafe { libc::pipe(host_fds.as_mut_ptr()) };
     if result == -1 {
         debug!("=> os error: {}", Error::last_os_error());
+        return -Error::last_os_error().raw_os_error().unwrap_or(fs::EIO);
     }
-    result
+
+    // the pipe ends are host file descriptors, the module sees them
+    // through its own file descriptors
+    let mut fs = ctx.fs();
+    let fds = [fs.add_host_fd(host_fds[0]), fs.add_host_fd(host_fds[1])];
+    drop(fs);
+    let mut bytes = [0; 8];
+    LittleEndian::write_i32_into(&fds, &mut bytes);
+    errno_result(write_memory(ctx, fd_offset, &bytes).map(|()| 0))
 }
 
 pub fn ___syscall51(_ctx: &mut EmEnv, _one: i32, _two: i32) -> i32 {
@@ -259,7 +277,7 @@ pub fn ___syscall63(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_i
     let src: i32 = varargs.get(ctx);
     let dst: i32 = varargs.get(ctx);
 
-    unsafe { dup2(src, dst) }
+    errno_result(ctx.fs().dup2(src, dst))
 }
 
 // getppid
@@ -351,18 +369,14 @@ pub fn ___syscall163(_ctx: &mut EmEnv, _one: i32, _two: i32) -> i32 {
 // getcwd
 pub fn ___syscall183(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> i32 {
     debug!("emscripten::___syscall183");
-    let buf_offset: WasmPtr<libc::c_char, Array> = varargs.get(ctx);
-    let _size: c_int = varargs.get(ctx);
-    let path = get_current_directory(ctx);
-    let path_string = path.unwrap().display().to_string();
-    let len = path_string.len();
-
-    let buf_writer = buf_offset.deref(ctx.memory(0), 0, len as u32 + 1).unwrap();
-    for (i, byte) in path_string.bytes().enumerate() {
-        buf_writer[i].set(byte as _);
+    let buf_offset: u32 = varargs.get(ctx);
+    let size: u32 = varargs.get(ctx);
+    let mut path = ctx.fs().cwd().as_bytes().to_vec();
+    path.push(0);
+    if path.len() > size as usize {
+        return -fs::ERANGE;
     }
-    buf_writer[len].set(0);
-    buf_offset.offset() as i32
+    errno_result(write_memory(ctx, buf_offset, &path).map(|()| buf_offset as i32))
 }
 
 // mmap2
@@ -405,106 +419,73 @@ pub fn ___syscall140(ctx: &mut EmEnv, _which: i32, mut varargs: VarArgs) -> i32
     // -> c_int
     debug!("emscripten::___syscall140 (lseek) {}", _which);
     let fd: i32 = varargs.get(ctx);
-    let _offset_high: u32 = varargs.get(ctx); // We don't use the offset high as emscripten skips it
+    let offset_high: u32 = varargs.get(ctx);
     let offset_low: u32 = varargs.get(ctx);
     let result_ptr_value: WasmPtr<i64> = varargs.get(ctx);
     let whence: i32 = varargs.get(ctx);
-    let offset = offset_low;
-    let ret = unsafe { lseek(fd, offset as _, whence) as i64 };
-
-    let result_ptr = result_ptr_value.deref(ctx.memory(0)).unwrap();
-    result_ptr.set(ret);
+    let offset = ((offset_high as u64) << 32 | offset_low as u64) as i64;
 
+    let ret = ctx.fs().seek(fd, offset, whence);
     debug!(
-        "=> fd: {}, offset: {}, result: {}, whence: {} = {}\nlast os error: {}",
-        fd,
-        offset,
-        ret,
-        whence,
-        0,
-        Error::last_os_error(),
+        "=> fd: {}, offset: {}, whence: {} = {:?}",
+        fd, offset, whence, ret
     );
-    0
+    errno_result(ret.and_then(|new_offset| {
+        let result_ptr = result_ptr_value.deref(ctx.memory(0)).ok_or(fs::EFAULT)?;
+        result_ptr.set(new_offset as i64);
+        Ok(0)
+    }))
 }
 
 /// readv
-#[allow(clippy::cast_ptr_alignment)]
 pub fn ___syscall145(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> i32 {
     // -> ssize_t
     debug!("emscripten::___syscall145 (readv) {}", _which);
 
     let fd: i32 = varargs.get(ctx);
-    let iov: i32 = varargs.get(ctx);
-    let iovcnt: i32 = varargs.get(ctx);
-
-    #[repr(C)]
-    struct GuestIovec {
-        iov_base: i32,
-        iov_len: i32,
-    }
+    let iov: u32 = varargs.get(ctx);
+    let iovcnt: u32 = varargs.get(ctx);
 
     debug!("=> fd: {}, iov: {}, iovcnt = {}", fd, iov, iovcnt);
-    let mut ret = 0;
-    unsafe {
-        for i in 0..iovcnt {
-            let guest_iov_addr =
-                emscripten_memory_pointer!(ctx.memory(0), (iov + i * 8)) as *mut GuestIovec;
-            let iov_base = emscripten_memory_pointer!(ctx.memory(0), (*guest_iov_addr).iov_base)
-                as *mut c_void;
-            let iov_len = (*guest_iov_addr).iov_len as _;
-            // debug!("=> iov_addr: {:?}, {:?}", iov_base, iov_len);
-            let curr = read(fd, iov_base, iov_len);
-            if curr < 0 {
-                return -1;
+    let result = read_iovecs(ctx, iov, iovcnt).and_then(|iovecs| {
+        let mut ret = 0;
+        for (iov_base, iov_len) in iovecs {
+            let mut bytes = vec![0; iov_len as usize];
+            let read = ctx.fs().read(fd, &mut bytes)?;
+            write_memory(ctx, iov_base, &bytes[..read])?;
+            ret += read;
+            if read < bytes.len() {
+                break;
             }
-            ret += curr;
         }
-        // debug!(" => ret: {}", ret);
-        ret as _
-    }
+        Ok(ret as i32)
+    });
+    errno_result(result)
 }
 
 // writev
-#[allow(clippy::cast_ptr_alignment)]
 pub fn ___syscall146(ctx: &mut EmEnv, _which: i32, mut varargs: VarArgs) -> i32 {
     // -> ssize_t
     debug!("emscripten::___syscall146 (writev) {}", _which);
     let fd: i32 = varargs.get(ctx);
-    let iov: i32 = varargs.get(ctx);
-    let iovcnt: i32 = varargs.get(ctx);
-
-    #[repr(C)]
-    struct GuestIovec {
-        iov_base: i32,
-        iov_len: i32,
-    }
+    let iov: u32 = varargs.get(ctx);
+    let iovcnt: u32 = varargs.get(ctx);
 
     debug!("=> fd: {}, iov: {}, iovcnt = {}", fd, iov, iovcnt);
-    let mut ret = 0;
-    for i in 0..iovcnt {
-        unsafe {
-            let guest_iov_addr =
-                emscripten_memory_pointer!(ctx.memory(0), (iov + i * 8)) as *mut GuestIovec;
-            let iov_base = emscripten_memory_pointer!(ctx.memory(0), (*guest_iov_addr).iov_base)
-                as *const c_void;
-            let iov_len = (*guest_iov_addr).iov_len as _;
-            // debug!("=> iov_addr: {:?}, {:?}", iov_base, iov_len);
-            let curr = write(fd, iov_base, iov_len);
-            debug!(
-                "=> iov_base: {}, iov_len: {}, curr = {}",
-                (*guest_iov_addr).iov_base,
-                iov_len,
-                curr
-            );
-            if curr < 0 {
-                debug!("=> os error: {}", Error::last_os_error());
-                return -1;
+    let result = read_iovecs(ctx, iov, iovcnt).and_then(|iovecs| {
+        let mut ret = 0;
+        for (iov_base, iov_len) in iovecs {
+            let bytes = read_memory(ctx, iov_base, iov_len)?;
+            let written = ctx.fs().write(fd, &bytes)?;
+            ret += written;
+            if written < bytes.len() {
+                break;
             }
-            ret += curr;
         }
-    }
-    debug!(" => ret: {}", ret);
-    ret as _
+        Ok(ret as i32)
+    });
+    debug!(" => ret: {:?}", result);
+    errno_result(result)
 }
 
 pub fn ___syscall191(ctx: &mut EmEnv, _which: i32, mut varargs: VarArgs) -> i32 {
@@ -532,32 +513,17 @@ pub fn ___syscall193(_ctx: &mut EmEnv, _one: i32, _two: i32) -> i32 {
 // stat64
 pub fn ___syscall195(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall195 (stat64) {}", _which);
-    let pathname_addr = varargs.get_str(ctx);
+    let path = read_path(varargs.get_str(ctx));
     let buf: u32 = varargs.get(ctx);
 
-    let real_path_owned = get_cstr_path(ctx, pathname_addr as *const _);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        pathname_addr
-    };
-
-    unsafe {
-        let mut _stat: stat = std::mem::zeroed();
-        let ret = stat(real_path, &mut _stat);
-        debug!(
-            "=> pathname: {}, buf: {} = {}",
-            std::ffi::CStr::from_ptr(real_path).to_str().unwrap(),
-            buf,
-            ret
-        );
-        if ret != 0 {
-            debug!("=> os error: {}", Error::last_os_error());
-            return ret;
-        }
-        copy_stat_into_wasm(ctx, buf, &_stat);
-    }
-    0
+    let stat = path.and_then(|path| {
+        debug!("=> pathname: {}, buf: {}", path, buf);
+        ctx.fs().stat(&path, true)
+    });
+    errno_result(stat.map(|stat| {
+        unsafe { copy_filestat_into_wasm(ctx, buf, &stat) };
+        0
+    }))
 }
 
 // fstat64
@@ -566,18 +532,270 @@ pub fn ___syscall197(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_
 
     let fd: c_int = varargs.get(ctx);
     let buf: u32 = varargs.get(ctx);
+    debug!("=> fd: {}, buf: {}", fd, buf);
+
+    let stat = ctx.fs().fstat(fd);
+    errno_result(stat.map(|stat| {
+        unsafe { copy_filestat_into_wasm(ctx, buf, &stat) };
+        0
+    }))
+}
+
+/// open
+pub fn ___syscall5(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall5 (open) {}", _which);
+    let path = read_path(varargs.get_str(ctx));
+    let flags: i32 = varargs.get(ctx);
+    let _mode: u32 = varargs.get(ctx);
+    let fd = path.and_then(|path| {
+        debug!("=> path: {}, flags: {}, mode: {}", path, flags, _mode);
+        ctx.fs().open(&path, flags)
+    });
+    debug!("=> fd: {:?}", fd);
+    errno_result(fd)
+}
+
+/// link
+pub fn ___syscall9(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall9 (link) {}", _which);
+    let _oldname = varargs.get_str(ctx);
+    let _newname = varargs.get_str(ctx);
+    // Hard links aren't supported by the sandboxed file system.
+    -fs::EPERM
+}
+
+/// access
+pub fn ___syscall33(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall33 (access) {}", _which);
+    let path = read_path(varargs.get_str(ctx));
+    let _amode: c_int = varargs.get(ctx);
+    errno_result(path.and_then(|path| ctx.fs().access(&path)).map(|()| 0))
+}
+
+// mkdir
+pub fn ___syscall39(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall39 (mkdir) {}", _which);
+    let path = read_path(varargs.get_str(ctx));
+    let _mode: u32 = varargs.get(ctx);
+    errno_result(path.and_then(|path| ctx.fs().mkdir(&path)).map(|()| 0))
+}
+
+/// dup
+pub fn ___syscall41(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall41 (dup) {}", _which);
+    let fd: c_int = varargs.get(ctx);
+    errno_result(ctx.fs().dup(fd, 0))
+}
+
+/// symlink
+pub fn ___syscall83(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall83 (symlink) {}", _which);
+    let _path1 = varargs.get_str(ctx);
+    let _path2 = varargs.get_str(ctx);
+    // Symbolic links could point outside of the sandbox.
+    -fs::EPERM
+}
+
+/// readlink
+pub fn ___syscall85(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> i32 {
+    debug!("emscripten::___syscall85 (readlink)");
+    let path = read_path(varargs.get_str(ctx));
+    let _buf: u32 = varargs.get(ctx);
+    let _buf_size: i32 = varargs.get(ctx);
+    // The sandboxed file system has no symbolic links to read.
+    errno_result(
+        path.and_then(|path| ctx.fs().stat(&path, false))
+            .and(Err(fs::EINVAL)),
+    )
+}
+
+/// fchmod
+pub fn ___syscall94(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall94 (fchmod) {}", _which);
+    let fd: c_int = varargs.get(ctx);
+    let _mode: u32 = varargs.get(ctx);
+    // The permissions are those of the host, they can't be changed.
+    errno_result(ctx.fs().fstat(fd).map(|_| 0))
+}
+
+/// fsync
+pub fn ___syscall118(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall118 (fsync) {}", _which);
+    let fd: c_int = varargs.get(ctx);
+    errno_result(ctx.fs().sync(fd).map(|()| 0))
+}
+
+/// fdatasync
+pub fn ___syscall148(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall148 (fdatasync) {}", _which);
+    let fd: c_int = varargs.get(ctx);
+    errno_result(ctx.fs().sync(fd).map(|()| 0))
+}
+
+// pread
+pub fn ___syscall180(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall180 (pread) {}", _which);
+    let fd: i32 = varargs.get(ctx);
+    let buf: u32 = varargs.get(ctx);
+    let count: u32 = varargs.get(ctx);
+    let _padding: u32 = varargs.get(ctx);
+    let offset: i64 = varargs.get(ctx);
 
-    unsafe {
-        let mut stat = std::mem::zeroed();
-        let ret = fstat(fd, &mut stat);
-        debug!("=> fd: {}, buf: {} = {}", fd, buf, ret);
-        if ret != 0 {
-            debug!("=> os error: {}", Error::last_os_error());
-            return ret;
+    let mut bytes = vec![0; count as usize];
+    let read = ctx.fs().pread(fd, &mut bytes, offset as u64);
+    errno_result(read.and_then(|read| {
+        write_memory(ctx, buf, &bytes[..read])?;
+        Ok(read as i32)
+    }))
+}
+
+// pwrite
+pub fn ___syscall181(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall181 (pwrite) {}", _which);
+    let fd: i32 = varargs.get(ctx);
+    let buf: u32 = varargs.get(ctx);
+    let count: u32 = varargs.get(ctx);
+    let _padding: u32 = varargs.get(ctx);
+    let offset: i64 = varargs.get(ctx);
+
+    let status = read_memory(ctx, buf, count)
+        .and_then(|bytes| ctx.fs().pwrite(fd, &bytes, offset as u64))
+        .map(|written| written as i32);
+    debug!(
+        "=> fd: {}, buf: {}, count: {}, offset: {} = status:{:?}",
+        fd, buf, count, offset, status
+    );
+    errno_result(status)
+}
+
+/// ftruncate64
+pub fn ___syscall194(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall194 (ftruncate64) {}", _which);
+    let fd: c_int = varargs.get(ctx);
+    let length: i64 = varargs.get(ctx);
+    if length < 0 {
+        return -fs::EINVAL;
+    }
+    errno_result(ctx.fs().truncate(fd, length as u64).map(|()| 0))
+}
+
+/// lstat64
+pub fn ___syscall196(ctx: &mut EmEnv, _which: i32, mut varargs: VarArgs) -> i32 {
+    debug!("emscripten::___syscall196 (lstat64) {}", _which);
+    let path = read_path(varargs.get_str(ctx));
+    let buf: u32 = varargs.get(ctx);
+
+    let stat = path.and_then(|path| ctx.fs().stat(&path, false));
+    errno_result(stat.map(|stat| {
+        unsafe { copy_filestat_into_wasm(ctx, buf, &stat) };
+        0
+    }))
+}
+
+/// lchown
+pub fn ___syscall198(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall198 (lchown) {}", _which);
+    let path = read_path(varargs.get_str(ctx));
+    let _uid: u32 = varargs.get(ctx);
+    let _gid: u32 = varargs.get(ctx);
+    // The owners are those of the host, they can't be changed.
+    errno_result(path.and_then(|path| ctx.fs().stat(&path, false)).map(|_| 0))
+}
+
+/// fchown
+pub fn ___syscall207(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall207 (fchown) {}", _which);
+    let fd: c_int = varargs.get(ctx);
+    let _owner: u32 = varargs.get(ctx);
+    let _group: u32 = varargs.get(ctx);
+    errno_result(ctx.fs().fstat(fd).map(|_| 0))
+}
+
+// chown
+pub fn ___syscall212(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall212 (chown) {}", _which);
+    let path = read_path(varargs.get_str(ctx));
+    let _owner: u32 = varargs.get(ctx);
+    let _group: u32 = varargs.get(ctx);
+    errno_result(path.and_then(|path| ctx.fs().stat(&path, true)).map(|_| 0))
+}
+
+// getdents
+// dirent structure is
+// u32, u32, u16 (268), u8, [u8; 256]
+pub fn ___syscall220(ctx: &mut EmEnv, _which: i32, mut varargs: VarArgs) -> i32 {
+    const DIRENT_SIZE: usize = 256 + 12;
+
+    let fd: i32 = varargs.get(ctx);
+    let dirp_addr: u32 = varargs.get(ctx);
+    let count: u32 = varargs.get(ctx);
+    debug!(
+        "emscripten::___syscall220 (getdents) {} {} {}",
+        fd, dirp_addr, count
+    );
+    if (count as usize) < DIRENT_SIZE {
+        return -fs::EINVAL;
+    }
+
+    let entries = ctx.fs().read_dir(fd, count as usize / DIRENT_SIZE);
+    errno_result(entries.and_then(|entries| {
+        let mut dirents = vec![0; entries.len() * DIRENT_SIZE];
+        for (index, (name, file_type, ino)) in entries.iter().enumerate() {
+            debug!("  => file {}", name);
+            let dirent = &mut dirents[index * DIRENT_SIZE..(index + 1) * DIRENT_SIZE];
+            LittleEndian::write_u32(&mut dirent[0..], *ino as u32);
+            LittleEndian::write_u32(&mut dirent[4..], (index * DIRENT_SIZE) as u32);
+            LittleEndian::write_u16(&mut dirent[8..], DIRENT_SIZE as u16);
+            dirent[10] = wasi_file_type_to_dirent_type(*file_type);
+            // the name is truncated to 255 bytes, and nul-terminated
+            let name = &name.as_bytes()[..std::cmp::min(name.len(), 255)];
+            dirent[11..11 + name.len()].copy_from_slice(name);
+        }
+        write_memory(ctx, dirp_addr, &dirents)?;
+        Ok(dirents.len() as i32)
+    }))
+}
+
+/// fallocate
+pub fn ___syscall324(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall324 (fallocate) {}", _which);
+    let fd: c_int = varargs.get(ctx);
+    let mode: c_int = varargs.get(ctx);
+    let offset: i64 = varargs.get(ctx);
+    let len: i64 = varargs.get(ctx);
+    if mode != 0 {
+        return -fs::EOPNOTSUPP;
+    }
+    if offset < 0 || len <= 0 {
+        return -fs::EINVAL;
+    }
+    let mut fs = ctx.fs();
+    let result = fs.fstat(fd).and_then(|stat| {
+        let new_size = (offset + len) as u64;
+        if new_size > stat.st_size {
+            fs.truncate(fd, new_size)?;
         }
-        copy_stat_into_wasm(ctx, buf, &stat);
+        Ok(0)
+    });
+    errno_result(result)
+}
+
+/// dup3
+pub fn ___syscall330(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    // Implementation based on description at https://linux.die.net/man/2/dup3
+    debug!("emscripten::___syscall330 (dup3)");
+    let oldfd: c_int = varargs.get(ctx);
+    let newfd: c_int = varargs.get(ctx);
+    // The only flag is `O_CLOEXEC`, and the module can't `exec`.
+    let _flags: c_int = varargs.get(ctx);
+
+    if oldfd == newfd {
+        return -fs::EINVAL;
     }
-    0
+
+    let res = errno_result(ctx.fs().dup2(oldfd, newfd));
+    debug!("=> oldfd: {}, newfd: {} = {}", oldfd, newfd, res);
+    res
 }
 
 pub fn ___syscall209(_ctx: &mut EmEnv, _one: i32, _two: i32) -> i32 {
@@ -5,22 +5,13 @@ use libc::size_t;
 /// Syscall list: https://www.cs.utexas.edu/~bismith/test/syscalls/syscalls32.html
 use libc::{
     accept,
-    access,
     bind,
-    c_char,
     c_int,
     c_ulong,
     c_void,
-    chown,
-    // fcntl, setsockopt, getppid
+    // setsockopt, getppid
     connect,
-    dup,
-    dup2,
-    fchmod,
-    fchown,
     fcntl,
-    // ENOTTY,
-    fsync,
     getegid,
     geteuid,
     getgid,
@@ -35,24 +26,14 @@ use libc::{
     in_addr_t,
     in_port_t,
     ioctl,
-    lchown,
-    link,
     // iovec,
     listen,
-    mkdir,
-    mode_t,
     msghdr,
     nice,
-    off_t,
-    open,
     pid_t,
-    pread,
-    pwrite,
-    readdir,
     // readv,
     recvfrom,
     recvmsg,
-    // ENOTTY,
     rusage,
     sa_family_t,
     // writev,
@@ -64,17 +45,11 @@ use libc::{
     sockaddr,
     socket,
     socklen_t,
-    stat,
-    symlink,
-    uid_t,
     uname,
     utsname,
-    EINVAL,
     // sockaddr_in,
     FIOCLEX,
     FIONBIO,
-    F_GETFD,
-    F_SETFD,
     SOL_SOCKET,
     TIOCGWINSZ,
     TIOCSPGRP,
@@ -110,11 +85,10 @@ fn translate_ioctl(wasm_ioctl: u32) -> c_ulong {
     }
 }
 
-#[allow(unused_imports)]
-use std::ffi::CStr;
-
+use super::errno_result;
 use crate::env::EmSockAddr;
-use crate::utils::{self, get_cstr_path};
+use crate::fs;
+use crate::ptr::Array;
 use crate::EmEnv;
 #[allow(unused_imports)]
 use std::io::Error;
@@ -126,8 +100,6 @@ use std::mem;
 extern "C" {
     pub fn wait4(pid: pid_t, status: *mut c_int, options: c_int, rusage: *mut rusage) -> pid_t;
     pub fn madvise(addr: *mut c_void, len: size_t, advice: c_int) -> c_int;
-    pub fn fdatasync(fd: c_int) -> c_int;
-    pub fn lstat64(path: *const libc::c_char, buf: *mut c_void) -> c_int;
 }
 
 // Linking to functions that are not provided by rust libc
@@ -135,17 +107,12 @@ extern "C" {
 #[link(name = "c")]
 extern "C" {
     pub fn wait4(pid: pid_t, status: *mut c_int, options: c_int, rusage: *mut rusage) -> pid_t;
-    pub fn fdatasync(fd: c_int) -> c_int;
-    pub fn ftruncate(fd: c_int, length: i64) -> c_int;
-    pub fn lstat(path: *const libc::c_char, buf: *mut stat) -> c_int;
 }
 
-#[cfg(not(any(target_os = "freebsd", target_os = "macos", target_os = "android")))]
-use libc::fallocate;
 #[cfg(target_os = "freebsd")]
 use libc::madvise;
 #[cfg(not(any(target_os = "freebsd", target_os = "macos")))]
-use libc::{fdatasync, ftruncate64, lstat, madvise, wait4};
+use libc::{madvise, wait4};
 
 // Another conditional constant for name resolution: Macos et iOS use
 // SO_NOSIGPIPE as a setsockopt flag to disable SIGPIPE emission on socket.
@@ -155,46 +122,6 @@ use libc::SO_NOSIGPIPE;
 #[cfg(not(target_os = "macos"))]
 const SO_NOSIGPIPE: c_int = 0;
 
-/// open
-pub fn ___syscall5(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
-    debug!("emscripten::___syscall5 (open) {}", _which);
-    let pathname_addr = varargs.get_str(ctx);
-    let flags: i32 = varargs.get(ctx);
-    let mode: u32 = varargs.get(ctx);
-    let real_path_owned = utils::get_cstr_path(ctx, pathname_addr as *const _);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        pathname_addr
-    };
-    let _path_str = unsafe { std::ffi::CStr::from_ptr(real_path).to_str().unwrap() };
-    let fd = unsafe { open(real_path, flags, mode) };
-    debug!(
-        "=> path: {}, flags: {}, mode: {} = fd: {}",
-        _path_str, flags, mode, fd,
-    );
-    if fd == -1 {
-        debug!("=> last os error: {}", Error::last_os_error(),);
-    }
-    fd
-}
-
-/// link
-pub fn ___syscall9(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
-    debug!("emscripten::___syscall9 (link) {}", _which);
-
-    let oldname_ptr = varargs.get_str(ctx);
-    let newname_ptr = varargs.get_str(ctx);
-    let result = unsafe { link(oldname_ptr, newname_ptr) };
-    debug!(
-        "=> oldname: {}, newname: {}, result: {}",
-        unsafe { std::ffi::CStr::from_ptr(oldname_ptr).to_str().unwrap() },
-        unsafe { std::ffi::CStr::from_ptr(newname_ptr).to_str().unwrap() },
-        result,
-    );
-    result
-}
-
 /// getrusage
 pub fn ___syscall77(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall77 (getrusage) {}", _which);
@@ -207,103 +134,6 @@ pub fn ___syscall77(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_i
     unsafe { getrusage(resource, rusage) }
 }
 
-/// symlink
-pub fn ___syscall83(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
-    debug!("emscripten::___syscall83 (symlink) {}", _which);
-
-    let path1 = varargs.get_str(ctx);
-    let path2 = varargs.get_str(ctx);
-    let real_path1_owned = utils::get_cstr_path(ctx, path1 as *const _);
-    let real_path1 = if let Some(ref rp) = real_path1_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        path1
-    };
-    let real_path2_owned = utils::get_cstr_path(ctx, path2 as *const _);
-    let real_path2 = if let Some(ref rp) = real_path2_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        path2
-    };
-    let result = unsafe { symlink(real_path1, real_path2) };
-    debug!(
-        "=> path1: {}, path2: {}, result: {}",
-        unsafe { std::ffi::CStr::from_ptr(real_path1).to_str().unwrap() },
-        unsafe { std::ffi::CStr::from_ptr(real_path2).to_str().unwrap() },
-        result,
-    );
-    result
-}
-
-/// readlink
-pub fn ___syscall85(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> i32 {
-    debug!("emscripten::___syscall85 (readlink)");
-    let pathname_addr = varargs.get_str(ctx);
-    let buf = varargs.get_str(ctx);
-    // let buf_addr: i32 = varargs.get(ctx);
-    let buf_size: i32 = varargs.get(ctx);
-    let real_path_owned = get_cstr_path(ctx, pathname_addr as *const _);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        pathname_addr
-    };
-
-    let ret = unsafe { libc::readlink(real_path, buf as _, buf_size as _) as i32 };
-    if ret == -1 {
-        debug!("readlink failed");
-        return ret;
-    }
-    debug!(
-        "=> path: {}, buf: {}, buf_size: {}, return: {} ",
-        unsafe { std::ffi::CStr::from_ptr(real_path).to_str().unwrap() },
-        unsafe { std::ffi::CStr::from_ptr(buf as _).to_str().unwrap() },
-        buf_size,
-        ret
-    );
-    ret
-}
-
-/// ftruncate64
-pub fn ___syscall194(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
-    debug!("emscripten::___syscall194 (ftruncate64) {}", _which);
-    let _fd: c_int = varargs.get(ctx);
-    let _length: i64 = varargs.get(ctx);
-    #[cfg(not(any(target_os = "freebsd", target_os = "macos")))]
-    unsafe {
-        ftruncate64(_fd, _length)
-    }
-    #[cfg(target_os = "freebsd")]
-    unsafe {
-        ftruncate(_fd, _length)
-    }
-    #[cfg(target_os = "macos")]
-    unimplemented!("emscripten::___syscall194 (ftruncate64) {}", _which)
-}
-
-/// lchown
-pub fn ___syscall198(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
-    debug!("emscripten::___syscall198 (lchown) {}", _which);
-    let path_ptr = varargs.get_str(ctx);
-    let real_path_owned = utils::get_cstr_path(ctx, path_ptr as *const _);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        path_ptr
-    };
-    let uid: uid_t = varargs.get(ctx);
-    let gid: gid_t = varargs.get(ctx);
-    let result = unsafe { lchown(real_path, uid, gid) };
-    debug!(
-        "=> path: {}, uid: {}, gid: {}, result: {}",
-        unsafe { std::ffi::CStr::from_ptr(real_path).to_str().unwrap() },
-        uid,
-        gid,
-        result,
-    );
-    result
-}
-
 /// getgroups
 pub fn ___syscall205(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall205 (getgroups) {}", _which);
@@ -321,23 +151,6 @@ pub fn ___syscall205(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_
     result
 }
 
-// chown
-pub fn ___syscall212(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
-    debug!("emscripten::___syscall212 (chown) {}", _which);
-
-    let pathname_addr = varargs.get_str(ctx);
-    let real_path_owned = utils::get_cstr_path(ctx, pathname_addr as *const _);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        pathname_addr
-    };
-    let owner: u32 = varargs.get(ctx);
-    let group: u32 = varargs.get(ctx);
-
-    unsafe { chown(real_path, owner, group) }
-}
-
 /// madvise
 pub fn ___syscall219(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall212 (chown) {}", _which);
@@ -351,27 +164,6 @@ pub fn ___syscall219(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_
     unsafe { madvise(addr, len, advice) }
 }
 
-/// access
-pub fn ___syscall33(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
-    debug!("emscripten::___syscall33 (access) {}", _which);
-    let path = varargs.get_str(ctx);
-    let real_path_owned = utils::get_cstr_path(ctx, path as *const _);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        path
-    };
-    let amode: c_int = varargs.get(ctx);
-    let result = unsafe { access(real_path, amode) };
-    debug!(
-        "=> path: {}, amode: {}, result: {}",
-        unsafe { std::ffi::CStr::from_ptr(real_path).to_str().unwrap() },
-        amode,
-        result
-    );
-    result
-}
-
 /// nice
 pub fn ___syscall34(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall34 (nice) {}", _which);
@@ -379,27 +171,6 @@ pub fn ___syscall34(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_i
     unsafe { nice(inc_r) }
 }
 
-// mkdir
-pub fn ___syscall39(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
-    debug!("emscripten::___syscall39 (mkdir) {}", _which);
-    let pathname_addr = varargs.get_str(ctx);
-    let real_path_owned = utils::get_cstr_path(ctx, pathname_addr as *const _);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        pathname_addr
-    };
-    let mode: u32 = varargs.get(ctx);
-    unsafe { mkdir(real_path, mode as _) }
-}
-
-/// dup
-pub fn ___syscall41(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
-    debug!("emscripten::___syscall41 (dup) {}", _which);
-    let fd: c_int = varargs.get(ctx);
-    unsafe { dup(fd) }
-}
-
 /// getgid32
 pub fn ___syscall200(_ctx: &mut EmEnv, _one: i32, _two: i32) -> i32 {
     debug!("emscripten::___syscall200 (getgid32)");
@@ -425,49 +196,6 @@ pub fn ___syscall202(_ctx: &mut EmEnv, _one: i32, _two: i32) -> i32 {
     }
 }
 
-/// fchown
-pub fn ___syscall207(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
-    debug!("emscripten::___syscall207 (fchown) {}", _which);
-    let fd: c_int = varargs.get(ctx);
-    let owner: uid_t = varargs.get(ctx);
-    let group: gid_t = varargs.get(ctx);
-    unsafe { fchown(fd, owner, group) }
-}
-
-/// dup3
-pub fn ___syscall330(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> pid_t {
-    // Implementation based on description at https://linux.die.net/man/2/dup3
-    debug!("emscripten::___syscall330 (dup3)");
-    let oldfd: c_int = varargs.get(ctx);
-    let newfd: c_int = varargs.get(ctx);
-    let flags: c_int = varargs.get(ctx);
-
-    if oldfd == newfd {
-        return EINVAL;
-    }
-
-    let res = unsafe { dup2(oldfd, newfd) };
-
-    // Set flags on newfd (https://www.gnu.org/software/libc/manual/html_node/Descriptor-Flags.html)
-    let mut old_flags = unsafe { fcntl(newfd, F_GETFD, 0) };
-
-    if old_flags > 0 {
-        old_flags |= flags;
-    } else if old_flags == 0 {
-        old_flags &= !flags;
-    }
-
-    unsafe {
-        fcntl(newfd, F_SETFD, old_flags);
-    }
-
-    debug!(
-        "=> oldfd: {}, newfd: {}, flags: {} = pid: {}",
-        oldfd, newfd, flags, res
-    );
-    res
-}
-
 /// ioctl
 pub fn ___syscall54(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall54 (ioctl) {}", _which);
@@ -476,6 +204,16 @@ pub fn ___syscall54(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_i
     let request: u32 = varargs.get(ctx);
     debug!("=> fd: {}, op: {}", fd, request);
 
+    // Only the standard streams, sockets and pipes are backed by a host
+    // file descriptor, files of the sandbox aren't terminals.
+    let host_fd = ctx.fs().host_fd(fd);
+    let host_fd = match host_fd {
+        Some(host_fd) => host_fd,
+        None if fd <= 2 && ctx.fs().is_open(fd) => fd,
+        None if ctx.fs().is_open(fd) => return -fs::ENOTTY,
+        None => return -fs::EBADF,
+    };
+
     // Got the equivalents here: https://code.woboq.org/linux/linux/include/uapi/asm-generic/ioctls.h.html
     match request {
         WASM_FIOCLEX | WASM_FIONBIO | WASM_TIOCGWINSZ | WASM_TIOCSPGRP | WASM_TCGETS
@@ -483,7 +221,7 @@ pub fn ___syscall54(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_i
             let argp: u32 = varargs.get(ctx);
             let argp_ptr = emscripten_memory_pointer!(ctx.memory(0), argp) as *mut c_void;
             let translated_request = translate_ioctl(request);
-            let ret = unsafe { ioctl(fd, translated_request as _, argp_ptr) };
+            let ret = unsafe { ioctl(host_fd, translated_request as _, argp_ptr) };
             debug!(
                 " => request: {}, translated: {}, return: {}",
                 request, translated_request, ret
@@ -506,6 +244,19 @@ pub fn ___syscall54(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_i
     }
 }
 
+/// The host file descriptor behind the socket `$fd` of the module, returns
+/// `-ENOTSOCK` or `-EBADF` from the syscall if there is none.
+macro_rules! host_socket {
+    ($ctx:expr, $fd:expr) => {{
+        let host_fd = $ctx.fs().host_fd($fd);
+        match host_fd {
+            Some(host_fd) => host_fd,
+            None if $ctx.fs().is_open($fd) => return -fs::ENOTSOCK,
+            None => return -fs::EBADF,
+        }
+    }};
+}
+
 const SOCK_NON_BLOCK: i32 = 2048;
 const SOCK_CLOEXC: i32 = 0x80000;
 
@@ -570,13 +321,18 @@ pub fn ___syscall102(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_
                 "=> domain: {}, type: {}, protocol: {} = fd: {}",
                 domain, ty, protocol, fd
             );
-            fd as _
+            if fd == -1 {
+                return fd;
+            }
+            ctx.fs().add_host_fd(fd)
         }
         2 => {
             debug!("socket: bind");
             // bind (socket: c_int, address: *const sockaddr, address_len: socklen_t) -> c_int
             // TODO: Emscripten has a different signature.
-            let socket = socket_varargs.get(ctx);
+            let socket: i32 = socket_varargs.get(ctx);
+            let socket = host_socket!(ctx, socket);
+            let socket = host_socket!(ctx, socket);
             let address: u32 = socket_varargs.get(ctx);
             let address_len = socket_varargs.get(ctx);
             let address = emscripten_memory_pointer!(ctx.memory(0), address) as *mut sockaddr;
@@ -601,7 +357,9 @@ pub fn ___syscall102(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_
             debug!("socket: connect");
             // connect (socket: c_int, address: *const sockaddr, len: socklen_t) -> c_int
             // TODO: Emscripten has a different signature.
-            let socket = socket_varargs.get(ctx);
+            let socket: i32 = socket_varargs.get(ctx);
+            let socket = host_socket!(ctx, socket);
+            let socket = host_socket!(ctx, socket);
             let address: u32 = socket_varargs.get(ctx);
             let address_len = socket_varargs.get(ctx);
             let address = emscripten_memory_pointer!(ctx.memory(0), address) as *mut sockaddr;
@@ -610,7 +368,9 @@ pub fn ___syscall102(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_
         4 => {
             debug!("socket: listen");
             // listen (socket: c_int, backlog: c_int) -> c_int
-            let socket = socket_varargs.get(ctx);
+            let socket: i32 = socket_varargs.get(ctx);
+            let socket = host_socket!(ctx, socket);
+            let socket = host_socket!(ctx, socket);
             let backlog: i32 = socket_varargs.get(ctx);
             let status = unsafe { listen(socket, backlog) };
             debug!(
@@ -623,6 +383,7 @@ pub fn ___syscall102(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_
             debug!("socket: accept");
             // accept (socket: c_int, address: *mut sockaddr, address_len: *mut socklen_t) -> c_int
             let socket: i32 = socket_varargs.get(ctx);
+            let socket = host_socket!(ctx, socket);
             let address: WasmPtr<EmSockAddr> = socket_varargs.get(ctx);
             let address_len: WasmPtr<u32> = socket_varargs.get(ctx);
 
@@ -658,13 +419,16 @@ pub fn ___syscall102(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_
                 "address: {:?}, len: {}, result fd = {}",
                 address_addr, address_len_addr, fd
             );
-
-            fd as _
+            if fd == -1 {
+                return fd;
+            }
+            ctx.fs().add_host_fd(fd)
         }
         6 => {
             debug!("socket: getsockname");
             // getsockname (socket: c_int, address: *mut sockaddr, address_len: *mut socklen_t) -> c_int
             let socket: i32 = socket_varargs.get(ctx);
+            let socket = host_socket!(ctx, socket);
             let address: WasmPtr<EmSockAddr> = socket_varargs.get(ctx);
             let address_len: WasmPtr<u32> = socket_varargs.get(ctx);
             let address_len_addr =
@@ -698,7 +462,9 @@ pub fn ___syscall102(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_
         7 => {
             debug!("socket: getpeername");
             // getpeername (socket: c_int, address: *mut sockaddr, address_len: *mut socklen_t) -> c_int
-            let socket = socket_varargs.get(ctx);
+            let socket: i32 = socket_varargs.get(ctx);
+            let socket = host_socket!(ctx, socket);
+            let socket = host_socket!(ctx, socket);
             let address: u32 = socket_varargs.get(ctx);
             let address_len: u32 = socket_varargs.get(ctx);
             let address = emscripten_memory_pointer!(ctx.memory(0), address) as *mut sockaddr;
@@ -709,7 +475,9 @@ pub fn ___syscall102(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_
         11 => {
             debug!("socket: sendto");
             // sendto (socket: c_int, buf: *const c_void, len: size_t, flags: c_int, addr: *const sockaddr, addrlen: socklen_t) -> ssize_t
-            let socket = socket_varargs.get(ctx);
+            let socket: i32 = socket_varargs.get(ctx);
+            let socket = host_socket!(ctx, socket);
+            let socket = host_socket!(ctx, socket);
             let buf: u32 = socket_varargs.get(ctx);
             let flags = socket_varargs.get(ctx);
             let len: i32 = socket_varargs.get(ctx);
@@ -722,7 +490,9 @@ pub fn ___syscall102(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_
         12 => {
             debug!("socket: recvfrom");
             // recvfrom (socket: c_int, buf: *const c_void, len: size_t, flags: c_int, addr: *const sockaddr, addrlen: socklen_t) -> ssize_t
-            let socket = socket_varargs.get(ctx);
+            let socket: i32 = socket_varargs.get(ctx);
+            let socket = host_socket!(ctx, socket);
+            let socket = host_socket!(ctx, socket);
             let buf: u32 = socket_varargs.get(ctx);
             let len: i32 = socket_varargs.get(ctx);
             let flags: i32 = socket_varargs.get(ctx);
@@ -749,7 +519,9 @@ pub fn ___syscall102(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_
             //      https://github.com/openbsd/src/blob/master/sys/sys/socket.h#L156
             // setsockopt (socket: c_int, level: c_int, name: c_int, value: *const c_void, option_len: socklen_t) -> c_int
 
-            let socket = socket_varargs.get(ctx);
+            let socket: i32 = socket_varargs.get(ctx);
+            let socket = host_socket!(ctx, socket);
+            let socket = host_socket!(ctx, socket);
             let level: i32 = socket_varargs.get(ctx);
             let level = if level == 1 { SOL_SOCKET } else { level };
             let untranslated_name: i32 = socket_varargs.get(ctx);
@@ -767,7 +539,9 @@ pub fn ___syscall102(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_
         15 => {
             debug!("socket: getsockopt");
             // getsockopt (sockfd: c_int, level: c_int, optname: c_int, optval: *mut c_void, optlen: *mut socklen_t) -> c_int
-            let socket = socket_varargs.get(ctx);
+            let socket: i32 = socket_varargs.get(ctx);
+            let socket = host_socket!(ctx, socket);
+            let socket = host_socket!(ctx, socket);
             let level: i32 = socket_varargs.get(ctx);
             let level = if level == 1 { SOL_SOCKET } else { level };
             let untranslated_name: i32 = socket_varargs.get(ctx);
@@ -783,6 +557,7 @@ pub fn ___syscall102(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_
             debug!("socket: sendmsg");
             // sendmsg (fd: c_int, msg: *const msghdr, flags: c_int) -> ssize_t
             let socket: i32 = socket_varargs.get(ctx);
+            let socket = host_socket!(ctx, socket);
             let msg: u32 = socket_varargs.get(ctx);
             let flags: i32 = socket_varargs.get(ctx);
             let msg_addr = emscripten_memory_pointer!(ctx.memory(0), msg) as *const msghdr;
@@ -792,6 +567,7 @@ pub fn ___syscall102(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_
             debug!("socket: recvmsg");
             // recvmsg (fd: c_int, msg: *mut msghdr, flags: c_int) -> ssize_t
             let socket: i32 = socket_varargs.get(ctx);
+            let socket = host_socket!(ctx, socket);
             let msg: u32 = socket_varargs.get(ctx);
             let flags: i32 = socket_varargs.get(ctx);
             let msg_addr = emscripten_memory_pointer!(ctx.memory(0), msg) as *mut msghdr;
@@ -855,67 +631,87 @@ unsafe impl wasmer::ValueType for EmPollFd {}
 /// poll
 pub fn ___syscall168(ctx: &mut EmEnv, _which: i32, mut varargs: VarArgs) -> i32 {
     debug!("emscripten::___syscall168(poll)");
-    let fds: WasmPtr<EmPollFd> = varargs.get(ctx);
+    let fds: WasmPtr<EmPollFd, Array> = varargs.get(ctx);
     let nfds: u32 = varargs.get(ctx);
     let timeout: i32 = varargs.get(ctx);
 
-    let fds_mut = unsafe { fds.deref_mut(ctx.memory(0)).unwrap().get_mut() };
-
-    let ret = unsafe {
-        libc::poll(
-            fds_mut as *mut EmPollFd as *mut libc::pollfd,
-            nfds as _,
-            timeout,
-        )
+    let guest_fds = match fds.deref(ctx.memory(0), 0, nfds) {
+        Some(guest_fds) => guest_fds,
+        None => return -fs::EFAULT,
     };
 
-    ret
-}
-
-// pread
-pub fn ___syscall180(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
-    debug!("emscripten::___syscall180 (pread) {}", _which);
-    let fd: i32 = varargs.get(ctx);
-    let buf: u32 = varargs.get(ctx);
-    let count: u32 = varargs.get(ctx);
-    {
-        let zero: u32 = varargs.get(ctx);
-        assert_eq!(zero, 0);
+    // The files of the sandbox are always ready, only the host file
+    // descriptors are actually polled.
+    let mut host_fds = Vec::new();
+    let mut ready = 0;
+    for (index, cell) in guest_fds.iter().enumerate() {
+        let mut poll_fd = cell.get();
+        poll_fd.revents = 0;
+        match poll_target(ctx, poll_fd.fd) {
+            PollTarget::Host(host_fd) => host_fds.push((
+                index,
+                libc::pollfd {
+                    fd: host_fd,
+                    events: poll_fd.events,
+                    revents: 0,
+                },
+            )),
+            PollTarget::Ready => poll_fd.revents = poll_fd.events & (POLLIN | POLLOUT),
+            PollTarget::Closed => poll_fd.revents = POLLNVAL,
+            PollTarget::Ignored => {}
+        }
+        if poll_fd.revents != 0 {
+            ready += 1;
+        }
+        cell.set(poll_fd);
     }
-    let offset: i64 = varargs.get(ctx);
 
-    let buf_ptr = emscripten_memory_pointer!(ctx.memory(0), buf) as _;
-
-    unsafe { pread(fd, buf_ptr, count as _, offset) as _ }
-}
-
-// pwrite
-pub fn ___syscall181(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
-    debug!("emscripten::___syscall181 (pwrite) {}", _which);
-    let fd: i32 = varargs.get(ctx);
-    let buf: u32 = varargs.get(ctx);
-    let count: u32 = varargs.get(ctx);
-    {
-        let zero: u32 = varargs.get(ctx);
-        assert_eq!(zero, 0);
+    if !host_fds.is_empty() {
+        let mut pollfds: Vec<libc::pollfd> = host_fds.iter().map(|(_, pollfd)| *pollfd).collect();
+        let timeout = if ready > 0 { 0 } else { timeout };
+        let ret = unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as _, timeout) };
+        if ret == -1 {
+            return -Error::last_os_error().raw_os_error().unwrap_or(fs::EIO);
+        }
+        for ((index, _), pollfd) in host_fds.iter().zip(pollfds) {
+            let mut poll_fd = guest_fds[*index].get();
+            poll_fd.revents = pollfd.revents;
+            guest_fds[*index].set(poll_fd);
+        }
+        ready += ret;
+    }
+    ready
+}
+
+const POLLIN: i16 = 0x1;
+const POLLOUT: i16 = 0x4;
+const POLLNVAL: i16 = 0x20;
+
+/// What polling a file descriptor of the module waits for.
+enum PollTarget {
+    /// A host file descriptor: a standard stream, a socket or a pipe.
+    Host(c_int),
+    /// A file of the sandbox, which is always ready.
+    Ready,
+    /// A file descriptor that isn't open.
+    Closed,
+    /// A negative file descriptor, which is skipped.
+    Ignored,
+}
+
+fn poll_target(ctx: &EmEnv, fd: i32) -> PollTarget {
+    let fs = ctx.fs();
+    if fd < 0 {
+        PollTarget::Ignored
+    } else if let Some(host_fd) = fs.host_fd(fd) {
+        PollTarget::Host(host_fd)
+    } else if !fs.is_open(fd) {
+        PollTarget::Closed
+    } else if fd <= 2 {
+        PollTarget::Host(fd)
+    } else {
+        PollTarget::Ready
     }
-    let offset: i64 = varargs.get(ctx);
-
-    let buf_ptr = emscripten_memory_pointer!(ctx.memory(0), buf) as _;
-    let status = unsafe { pwrite(fd, buf_ptr, count as _, offset) as _ };
-    debug!(
-        "=> fd: {}, buf: {}, count: {}, offset: {} = status:{}",
-        fd, buf, count, offset, status
-    );
-    status
-}
-
-/// fchmod
-pub fn ___syscall94(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
-    debug!("emscripten::___syscall118 (fchmod) {}", _which);
-    let fd: c_int = varargs.get(ctx);
-    let mode: mode_t = varargs.get(ctx);
-    unsafe { fchmod(fd, mode) }
 }
 
 /// wait4
@@ -937,15 +733,7 @@ pub fn ___syscall114(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> pi
     res
 }
 
-/// fsync
-pub fn ___syscall118(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
-    debug!("emscripten::___syscall118 (fsync) {}", _which);
-    let fd: c_int = varargs.get(ctx);
-    unsafe { fsync(fd) }
-}
-
 // select
-#[allow(clippy::cast_ptr_alignment)]
 pub fn ___syscall142(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall142 (newselect) {}", _which);
 
@@ -955,25 +743,108 @@ pub fn ___syscall142(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_
     let exceptfds: u32 = varargs.get(ctx);
     let _timeout: i32 = varargs.get(ctx);
 
-    if nfds > 1024 {
-        // EINVAL
-        return -22;
+    if !(0..=1024).contains(&nfds) {
+        return -fs::EINVAL;
     }
     assert!(exceptfds == 0, "`exceptfds` is not supporrted");
 
-    let readfds_ptr = emscripten_memory_pointer!(ctx.memory(0), readfds) as _;
-    let writefds_ptr = emscripten_memory_pointer!(ctx.memory(0), writefds) as _;
-
-    unsafe { select(nfds, readfds_ptr, writefds_ptr, 0 as _, 0 as _) }
-}
+    let mut guest_sets = [
+        read_fd_set(ctx, readfds, nfds),
+        read_fd_set(ctx, writefds, nfds),
+    ];
+    // The files of the sandbox are always ready, only the host file
+    // descriptors are actually selected.
+    let mut ready_sets = [vec![], vec![]];
+    let mut host_sets: [libc::fd_set; 2] = unsafe { mem::zeroed() };
+    let mut host_fds = Vec::new();
+    let mut host_nfds = 0;
+    for (set, guest_set) in guest_sets.iter_mut().enumerate() {
+        let guest_set = match guest_set {
+            Ok(guest_set) => guest_set,
+            Err(errno) => return -*errno,
+        };
+        for &fd in guest_set.iter() {
+            match poll_target(ctx, fd) {
+                PollTarget::Host(host_fd) => {
+                    unsafe { libc::FD_SET(host_fd, &mut host_sets[set]) };
+                    host_fds.push((set, fd, host_fd));
+                    host_nfds = std::cmp::max(host_nfds, host_fd + 1);
+                }
+                PollTarget::Ready => ready_sets[set].push(fd),
+                PollTarget::Closed | PollTarget::Ignored => return -fs::EBADF,
+            }
+        }
+    }
 
-/// fdatasync
-pub fn ___syscall148(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
-    debug!("emscripten::___syscall148 (fdatasync) {}", _which);
+    if !host_fds.is_empty() {
+        let mut no_wait = libc::timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        };
+        let timeout = if ready_sets.iter().any(|set| !set.is_empty()) {
+            &mut no_wait as *mut _
+        } else {
+            std::ptr::null_mut()
+        };
+        let [read_set, write_set] = &mut host_sets;
+        let ret = unsafe {
+            select(
+                host_nfds,
+                read_set,
+                write_set,
+                std::ptr::null_mut(),
+                timeout,
+            )
+        };
+        if ret == -1 {
+            return -Error::last_os_error().raw_os_error().unwrap_or(fs::EIO);
+        }
+        for (set, fd, host_fd) in host_fds {
+            if unsafe { libc::FD_ISSET(host_fd, &mut host_sets[set]) } {
+                ready_sets[set].push(fd);
+            }
+        }
+    }
 
-    let fd: i32 = varargs.get(ctx);
+    for (offset, ready_set) in [readfds, writefds].iter().zip(&ready_sets) {
+        if let Err(errno) = write_fd_set(ctx, *offset, nfds, ready_set) {
+            return -errno;
+        }
+    }
+    ready_sets.iter().map(|set| set.len() as c_int).sum()
+}
 
-    unsafe { fdatasync(fd) }
+/// Reads the file descriptors in the `fd_set` at `offset`, below `nfds`.
+fn read_fd_set(ctx: &EmEnv, offset: u32, nfds: i32) -> Result<Vec<i32>, i32> {
+    if offset == 0 {
+        return Ok(vec![]);
+    }
+    let view = ctx.memory(0).view::<u8>();
+    let bytes = view
+        .get(offset as usize..offset as usize + (nfds as usize + 7) / 8)
+        .ok_or(fs::EFAULT)?;
+    Ok((0..nfds)
+        .filter(|fd| bytes[*fd as usize / 8].get() & (1 << (fd % 8)) != 0)
+        .collect())
+}
+
+/// Writes `fds` as the `fd_set` at `offset`, below `nfds`.
+fn write_fd_set(ctx: &EmEnv, offset: u32, nfds: i32, fds: &[i32]) -> Result<(), i32> {
+    if offset == 0 {
+        return Ok(());
+    }
+    let view = ctx.memory(0).view::<u8>();
+    let bytes = view
+        .get(offset as usize..offset as usize + (nfds as usize + 7) / 8)
+        .ok_or(fs::EFAULT)?;
+    for byte in bytes {
+        byte.set(0);
+    }
+    for fd in fds {
+        let byte = &bytes[*fd as usize / 8];
+        byte.set(byte.get() | (1 << (fd % 8)));
+    }
+    Ok(())
 }
 
 // setpgid
@@ -1001,39 +872,6 @@ pub fn ___syscall122(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_
     unsafe { uname(buf_addr) }
 }
 
-/// lstat64
-pub fn ___syscall196(ctx: &mut EmEnv, _which: i32, mut varargs: VarArgs) -> i32 {
-    debug!("emscripten::___syscall196 (lstat64) {}", _which);
-    let path = varargs.get_str(ctx);
-    let real_path_owned = utils::get_cstr_path(ctx, path as *const _);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        path
-    };
-    let buf_ptr: u32 = varargs.get(ctx);
-    unsafe {
-        let mut stat: stat = std::mem::zeroed();
-
-        #[cfg(target_os = "macos")]
-        let stat_ptr = &mut stat as *mut stat as *mut c_void;
-        #[cfg(not(target_os = "macos"))]
-        let stat_ptr = &mut stat as *mut stat;
-
-        #[cfg(target_os = "macos")]
-        let ret = lstat64(real_path, stat_ptr);
-        #[cfg(not(target_os = "macos"))]
-        let ret = lstat(real_path, stat_ptr);
-
-        debug!("ret: {}", ret);
-        if ret != 0 {
-            return ret;
-        }
-        utils::copy_stat_into_wasm(ctx, buf_ptr, &stat);
-    }
-    0
-}
-
 // getuid
 pub fn ___syscall199(_ctx: &mut EmEnv, _one: i32, _two: i32) -> i32 {
     debug!("emscripten::___syscall199 (getuid)");
@@ -1042,72 +880,6 @@ pub fn ___syscall199(_ctx: &mut EmEnv, _one: i32, _two: i32) -> i32 {
     uid
 }
 
-// getdents
-// dirent structure is
-// i64, i64, u16 (280), i8, [i8; 256]
-pub fn ___syscall220(ctx: &mut EmEnv, _which: i32, mut varargs: VarArgs) -> i32 {
-    use super::super::env::get_emscripten_data;
-
-    let fd: i32 = varargs.get(ctx);
-    let dirp_addr: i32 = varargs.get(ctx);
-    let count: u32 = varargs.get(ctx);
-    debug!(
-        "emscripten::___syscall220 (getdents) {} {} {}",
-        fd, dirp_addr, count
-    );
-
-    let dirp = emscripten_memory_pointer!(ctx.memory(0), dirp_addr) as *mut u8;
-
-    let opened_dirs = &mut get_emscripten_data(ctx).opened_dirs;
-
-    // need to persist stream across calls?
-    // let dir: *mut libc::DIR = unsafe { libc::fdopendir(fd) };
-    let dir = &*opened_dirs
-        .entry(fd)
-        .or_insert_with(|| unsafe { Box::new(libc::fdopendir(fd)) });
-
-    let mut pos = 0;
-    let offset = 256 + 12;
-    while pos + offset <= count as usize {
-        let dirent = unsafe { readdir(**dir) };
-        if dirent.is_null() {
-            break;
-        }
-        #[allow(clippy::cast_ptr_alignment)]
-        #[cfg(not(target_os = "freebsd"))]
-        unsafe {
-            *(dirp.add(pos) as *mut u32) = (*dirent).d_ino as u32;
-        }
-        #[allow(clippy::cast_ptr_alignment)]
-        #[cfg(target_os = "freebsd")]
-        unsafe {
-            *(dirp.add(pos) as *mut u32) = (*dirent).d_fileno as u32;
-        }
-        #[allow(clippy::cast_ptr_alignment)]
-        unsafe {
-            *(dirp.add(pos + 4) as *mut u32) = pos as u32;
-            *(dirp.add(pos + 8) as *mut u16) = offset as u16;
-            *(dirp.add(pos + 10) as *mut u8) = (*dirent).d_type;
-            let upper_bound = std::cmp::min((*dirent).d_reclen, 255) as usize;
-            let mut i = 0;
-            while i < upper_bound {
-                *(dirp.add(pos + 11 + i) as *mut c_char) = (*dirent).d_name[i] as c_char;
-                i += 1;
-            }
-            // We set the termination string char
-            *(dirp.add(pos + 11 + i) as *mut c_char) = 0 as c_char;
-            debug!(
-                "  => file {}",
-                CStr::from_ptr(dirp.add(pos + 11) as *const c_char)
-                    .to_str()
-                    .unwrap()
-            );
-        }
-        pos += offset;
-    }
-    pos as i32
-}
-
 // fcntl64
 pub fn ___syscall221(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall221 (fcntl64) {}", _which);
@@ -1118,27 +890,37 @@ pub fn ___syscall221(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_
     // |FASYNC    - 0x40
     // |FFSYNC    - 0x80
     // |FNONBLOCK - 0x04
-    let ret = unsafe { fcntl(fd, cmd, arg) };
-    debug!("=> fd: {}, cmd: {} = {}", fd, cmd, ret);
-    if ret == -1 {
-        debug!("=> last os error: {}", Error::last_os_error(),);
+    let host_fd = ctx.fs().host_fd(fd);
+    if let Some(host_fd) = host_fd {
+        if cmd == F_DUPFD || cmd == F_DUPFD_CLOEXEC {
+            return errno_result(ctx.fs().dup(fd, arg));
+        }
+        let ret = unsafe { fcntl(host_fd, cmd, arg) };
+        debug!("=> fd: {}, cmd: {} = {}", fd, cmd, ret);
+        if ret == -1 {
+            debug!("=> last os error: {}", Error::last_os_error(),);
+            return -Error::last_os_error().raw_os_error().unwrap_or(fs::EIO);
+        }
+        return ret;
+    }
+    if !ctx.fs().is_open(fd) {
+        return -fs::EBADF;
     }
+    // The flags of the files of the sandbox are fixed when they're opened.
+    let ret = match cmd {
+        F_DUPFD | F_DUPFD_CLOEXEC => errno_result(ctx.fs().dup(fd, arg)),
+        F_GETFD | F_SETFD | F_SETFL => 0,
+        F_GETFL => fs::O_RDWR,
+        _ => -fs::EINVAL,
+    };
+    debug!("=> fd: {}, cmd: {} = {}", fd, cmd, ret);
     ret
 }
 
-/// fallocate
-pub fn ___syscall324(ctx: &mut EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
-    debug!("emscripten::___syscall324 (fallocate) {}", _which);
-    let _fd: c_int = varargs.get(ctx);
-    let _mode: c_int = varargs.get(ctx);
-    let _offset: off_t = varargs.get(ctx);
-    let _len: off_t = varargs.get(ctx);
-    #[cfg(not(any(target_os = "freebsd", target_os = "macos", target_os = "android")))]
-    unsafe {
-        fallocate(_fd, _mode, _offset, _len)
-    }
-    #[cfg(any(target_os = "freebsd", target_os = "macos", target_os = "android"))]
-    {
-        unimplemented!("emscripten::___syscall324 (fallocate) {}", _which)
-    }
-}
+// The `fcntl` commands, with the values of Emscripten.
+const F_DUPFD: c_int = 0;
+const F_GETFD: c_int = 1;
+const F_SETFD: c_int = 2;
+const F_GETFL: c_int = 3;
+const F_SETFL: c_int = 4;
+const F_DUPFD_CLOEXEC: c_int = 1030;
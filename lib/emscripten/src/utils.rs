@@ -2,13 +2,12 @@ use super::env;
 use super::env::get_emscripten_data;
 use crate::storage::align_memory;
 use crate::EmEnv;
-use libc::stat;
 use std::ffi::CStr;
 use std::mem::size_of;
 use std::os::raw::c_char;
-use std::path::PathBuf;
 use std::slice;
 use wasmer::{GlobalInit, Memory, Module, Pages};
+use wasmer_wasi::types::*;
 
 /// We check if a provided module is an Emscripten generated one
 pub fn is_emscripten_module(module: &Module) -> bool {
@@ -185,31 +184,36 @@ pub struct GuestStat {
     st_ino: u32,
 }
 
+/// Writes a WASI `stat` as the Emscripten `stat` at `buf`.
+///
+/// The sandboxed file system doesn't expose the owner or the permissions of
+/// the host files, so a mode is derived from the file type.
 #[allow(clippy::cast_ptr_alignment)]
-pub unsafe fn copy_stat_into_wasm(ctx: &mut EmEnv, buf: u32, stat: &stat) {
+pub unsafe fn copy_filestat_into_wasm(ctx: &EmEnv, buf: u32, stat: &__wasi_filestat_t) {
+    let mode = match stat.st_filetype {
+        __WASI_FILETYPE_DIRECTORY => 0o040_000 | 0o755,
+        __WASI_FILETYPE_SYMBOLIC_LINK => 0o120_000 | 0o777,
+        __WASI_FILETYPE_CHARACTER_DEVICE => 0o020_000 | 0o666,
+        __WASI_FILETYPE_BLOCK_DEVICE => 0o060_000 | 0o660,
+        __WASI_FILETYPE_SOCKET_DGRAM | __WASI_FILETYPE_SOCKET_STREAM => 0o140_000 | 0o666,
+        _ => 0o100_000 | 0o644,
+    };
     let stat_ptr = emscripten_memory_pointer!(ctx.memory(0), buf) as *mut GuestStat;
     (*stat_ptr).st_dev = stat.st_dev as _;
     (*stat_ptr).__st_dev_padding = 0;
     (*stat_ptr).__st_ino_truncated = stat.st_ino as _;
-    (*stat_ptr).st_mode = stat.st_mode as _;
+    (*stat_ptr).st_mode = mode;
     (*stat_ptr).st_nlink = stat.st_nlink as _;
-    (*stat_ptr).st_uid = stat.st_uid as _;
-    (*stat_ptr).st_gid = stat.st_gid as _;
-    (*stat_ptr).st_rdev = stat.st_rdev as _;
+    (*stat_ptr).st_uid = 0;
+    (*stat_ptr).st_gid = 0;
+    (*stat_ptr).st_rdev = 0;
     (*stat_ptr).__st_rdev_padding = 0;
     (*stat_ptr).st_size = stat.st_size as _;
     (*stat_ptr).st_blksize = 4096;
-    #[cfg(not(target_os = "windows"))]
-    {
-        (*stat_ptr).st_blocks = stat.st_blocks as _;
-    }
-    #[cfg(target_os = "windows")]
-    {
-        (*stat_ptr).st_blocks = 0;
-    }
-    (*stat_ptr).st_atime = stat.st_atime as _;
-    (*stat_ptr).st_mtime = stat.st_mtime as _;
-    (*stat_ptr).st_ctime = stat.st_ctime as _;
+    (*stat_ptr).st_blocks = ((stat.st_size + 511) / 512) as _;
+    (*stat_ptr).st_atime = stat.st_atim / 1_000_000_000;
+    (*stat_ptr).st_mtime = stat.st_mtim / 1_000_000_000;
+    (*stat_ptr).st_ctime = stat.st_ctim / 1_000_000_000;
     (*stat_ptr).st_ino = stat.st_ino as _;
 }
 
@@ -222,59 +226,3 @@ pub fn read_string_from_wasm(memory: &Memory, offset: u32) -> String {
         .collect();
     String::from_utf8_lossy(&v).to_owned().to_string()
 }
-
-/// This function trys to find an entry in mapdir
-/// translating paths into their correct value
-pub fn get_cstr_path(ctx: &mut EmEnv, path: *const i8) -> Option<std::ffi::CString> {
-    use std::collections::VecDeque;
-
-    let path_str =
-        unsafe { std::ffi::CStr::from_ptr(path as *const _).to_str().unwrap() }.to_string();
-    let data = get_emscripten_data(ctx);
-    let path = PathBuf::from(path_str);
-    let mut prefix_added = false;
-    let mut components = path.components().collect::<VecDeque<_>>();
-    // TODO(mark): handle absolute/non-canonical/non-relative paths too (this
-    // functionality should be shared among the abis)
-    if components.len() == 1 {
-        components.push_front(std::path::Component::CurDir);
-        prefix_added = true;
-    }
-    let mut cumulative_path = PathBuf::new();
-    for c in components.into_iter() {
-        cumulative_path.push(c);
-        if let Some(val) = data
-            .mapped_dirs
-            .get(&cumulative_path.to_string_lossy().to_string())
-        {
-            let rest_of_path = if !prefix_added {
-                path.strip_prefix(cumulative_path).ok()?
-            } else {
-                &path
-            };
-            let rebased_path = val.join(rest_of_path);
-            return std::ffi::CString::new(rebased_path.to_string_lossy().as_bytes()).ok();
-        }
-    }
-    None
-}
-
-/// gets the current directory
-/// handles mapdir logic
-pub fn get_current_directory(ctx: &mut EmEnv) -> Option<PathBuf> {
-    if let Some(val) = get_emscripten_data(ctx).mapped_dirs.get(".") {
-        return Some(val.clone());
-    }
-    std::env::current_dir()
-        .map(|cwd| {
-            if let Some(val) = get_emscripten_data(ctx)
-                .mapped_dirs
-                .get(&cwd.to_string_lossy().to_string())
-            {
-                val.clone()
-            } else {
-                cwd
-            }
-        })
-        .ok()
-}
@@ -0,0 +1,98 @@
+;; C++ exceptions as lowered by Emscripten (fastcomp), for:
+;;
+;;   struct E { int v; ~E() { destroyed = 1; } };
+;;   struct F {};
+;;   int mode, destroyed, result;
+;;
+;;   static void thrower(int v) { throw E{v}; }
+;;
+;;   int main() {
+;;     if (mode == 0) {
+;;       try { thrower(7); } catch (E &e) { result = e.v; }
+;;     } else {
+;;       try { thrower(7); } catch (F &f) { result = -1; }  // not caught
+;;     }
+;;     return 0;
+;;   }
+;;
+;; `mode` is set by the embedder before calling `main`. The typeinfo of `E`
+;; is at 3000 and the one of `F` at 3008; without `___cxa_can_catch`, a catch
+;; clause only matches its exact type.
+(module
+  (import "env" "memory" (memory 256 256))
+  (import "env" "table" (table 4 4 funcref))
+  (import "env" "invoke_vi" (func $invoke_vi (param i32 i32)))
+  (import "env" "___cxa_allocate_exception" (func $allocate_exception (param i32) (result i32)))
+  (import "env" "___cxa_throw" (func $throw (param i32 i32 i32)))
+  (import "env" "___cxa_find_matching_catch_3" (func $find_matching_catch_3 (param i32) (result i32)))
+  (import "env" "___cxa_begin_catch" (func $begin_catch (param i32) (result i32)))
+  (import "env" "___cxa_end_catch" (func $end_catch))
+  (import "env" "___resumeException" (func $resume_exception (param i32)))
+  (import "env" "getTempRet0" (func $getTempRet0 (result i32)))
+
+  (global $threw (mut i32) (i32.const 0))
+  (global $threwValue (mut i32) (i32.const 0))
+  (global $sp (mut i32) (i32.const 65536))
+  (global $heap (mut i32) (i32.const 8388608))
+  (global $mode (export "mode") (mut i32) (i32.const 0))
+  (global $destroyed (export "destroyed") (mut i32) (i32.const 0))
+  (global $result (export "result") (mut i32) (i32.const 0))
+  ;; the metadata of `-s EMIT_EMSCRIPTEN_METADATA=1`
+  (global $DYNAMICTOP_PTR i32 (i32.const 4096))
+  (global $DYNAMIC_BASE i32 (i32.const 8388608))
+
+  (elem (i32.const 1) $thrower $E_destructor)
+
+  (func $thrower (param $v i32)
+    (local $e i32)
+    (local.set $e (call $allocate_exception (i32.const 4)))
+    (i32.store (local.get $e) (local.get $v))
+    (call $throw (local.get $e) (i32.const 3000) (i32.const 2)))
+
+  (func $E_destructor (param $this i32)
+    (global.set $destroyed (i32.const 1)))
+
+  (func $main (export "_main") (result i32)
+    (local $catch_type i32) (local $t i32) (local $ptr i32)
+    (local.set $catch_type
+      (select (i32.const 3000) (i32.const 3008) (i32.eqz (global.get $mode))))
+    (global.set $threw (i32.const 0))
+    (call $invoke_vi (i32.const 1) (i32.const 7))
+    (local.set $t (global.get $threw))
+    (global.set $threw (i32.const 0))
+    (if (i32.eq (local.get $t) (i32.const 1))
+      (then
+        ;; the landing pad
+        (local.set $ptr (call $find_matching_catch_3 (local.get $catch_type)))
+        (if (i32.eq (call $getTempRet0) (local.get $catch_type))
+          (then
+            (global.set $result (i32.load (call $begin_catch (local.get $ptr))))
+            (call $end_catch))
+          (else
+            (call $resume_exception (local.get $ptr))))))
+    (i32.const 0))
+
+  (func $malloc (export "_malloc") (param $size i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (global.get $heap))
+    (global.set $heap
+      (i32.and (i32.add (i32.add (local.get $ptr) (local.get $size)) (i32.const 7))
+               (i32.const -8)))
+    (local.get $ptr))
+
+  (func $free (export "_free") (param $ptr i32))
+
+  (func (export "setThrew") (param $threw i32) (param $value i32)
+    (if (i32.eqz (global.get $threw))
+      (then
+        (global.set $threw (local.get $threw))
+        (global.set $threwValue (local.get $value)))))
+
+  (func (export "stackSave") (result i32)
+    (global.get $sp))
+
+  (func (export "stackRestore") (param $sp i32)
+    (global.set $sp (local.get $sp)))
+
+  (func (export "dynCall_vi") (param $index i32) (param $a1 i32)
+    (call_indirect (param i32) (local.get $a1) (local.get $index))))
@@ -0,0 +1,113 @@
+;; `setjmp` and `longjmp` as lowered by Emscripten (fastcomp), for:
+;;
+;;   static jmp_buf buf;
+;;   int mode, depth, result;
+;;
+;;   static void dive(int n) {
+;;     depth = n;
+;;     if (n == 3) longjmp(buf, 42);
+;;     dive(n + 1);
+;;   }
+;;
+;;   static void crash(int n) { __builtin_trap(); }
+;;
+;;   int main() {
+;;     int r = setjmp(buf);
+;;     if (r == 0) {
+;;       if (mode == 0) dive(0);  // jumps back with 42 at depth 3
+;;       else crash(0);           // the trap isn't caught by `setjmp`
+;;     }
+;;     result = r * 100 + depth;
+;;     return 0;
+;;   }
+;;
+;; `mode` is set by the embedder before calling `main`.
+(module
+  (import "env" "memory" (memory 256 256))
+  (import "env" "table" (table 4 4 funcref))
+  (import "env" "invoke_vi" (func $invoke_vi (param i32 i32)))
+  (import "env" "_longjmp" (func $longjmp (param i32 i32)))
+  (import "env" "_saveSetjmp" (func $saveSetjmp (param i32 i32 i32 i32) (result i32)))
+  (import "env" "_testSetjmp" (func $testSetjmp (param i32 i32 i32) (result i32)))
+  (import "env" "getTempRet0" (func $getTempRet0 (result i32)))
+  (import "env" "setTempRet0" (func $setTempRet0 (param i32)))
+
+  (global $threw (mut i32) (i32.const 0))
+  (global $threwValue (mut i32) (i32.const 0))
+  (global $sp (mut i32) (i32.const 65536))
+  (global $heap (mut i32) (i32.const 8388608))
+  (global $mode (export "mode") (mut i32) (i32.const 0))
+  (global $depth (export "depth") (mut i32) (i32.const 0))
+  (global $result (export "result") (mut i32) (i32.const 0))
+  ;; the metadata of `-s EMIT_EMSCRIPTEN_METADATA=1`
+  (global $DYNAMICTOP_PTR i32 (i32.const 4096))
+  (global $DYNAMIC_BASE i32 (i32.const 8388608))
+
+  ;; `buf` is at 2048.
+  (elem (i32.const 1) $dive $crash)
+
+  (func $dive (param $n i32)
+    (global.set $depth (local.get $n))
+    (if (i32.eq (local.get $n) (i32.const 3))
+      (then (call $longjmp (i32.const 2048) (i32.const 42))))
+    (call $dive (i32.add (local.get $n) (i32.const 1))))
+
+  (func $crash (param $n i32)
+    unreachable)
+
+  (func $main (export "_main") (result i32)
+    (local $table i32) (local $size i32) (local $r i32) (local $t i32) (local $label i32)
+    ;; setjmp(buf), with the label 1
+    (local.set $table (call $malloc (i32.const 40)))
+    (i32.store (local.get $table) (i32.const 0))
+    (local.set $table
+      (call $saveSetjmp (i32.const 2048) (i32.const 1) (local.get $table) (i32.const 4)))
+    (local.set $size (call $getTempRet0))
+    (local.set $r (i32.const 0))
+    (block $done
+      (loop $label1
+        (br_if $done (local.get $r))
+        (global.set $threw (i32.const 0))
+        (call $invoke_vi (i32.add (i32.const 1) (global.get $mode)) (i32.const 0))
+        (local.set $t (global.get $threw))
+        (global.set $threw (i32.const 0))
+        (br_if $done
+          (i32.or (i32.eqz (local.get $t)) (i32.eqz (global.get $threwValue))))
+        ;; a longjmp, to one of the setjmps of this function?
+        (local.set $label
+          (call $testSetjmp (i32.load (local.get $t)) (local.get $table) (local.get $size)))
+        (if (i32.eqz (local.get $label))
+          (then (call $longjmp (local.get $t) (global.get $threwValue))))
+        (call $setTempRet0 (global.get $threwValue))
+        ;; back to label 1, where setjmp returns tempRet0
+        (local.set $r (call $getTempRet0))
+        (br $label1)))
+    (global.set $result
+      (i32.add (i32.mul (local.get $r) (i32.const 100)) (global.get $depth)))
+    (call $free (local.get $table))
+    (i32.const 0))
+
+  (func $malloc (export "_malloc") (param $size i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (global.get $heap))
+    (global.set $heap
+      (i32.and (i32.add (i32.add (local.get $ptr) (local.get $size)) (i32.const 7))
+               (i32.const -8)))
+    (local.get $ptr))
+
+  (func $free (export "_free") (param $ptr i32))
+
+  (func (export "setThrew") (param $threw i32) (param $value i32)
+    (if (i32.eqz (global.get $threw))
+      (then
+        (global.set $threw (local.get $threw))
+        (global.set $threwValue (local.get $value)))))
+
+  (func (export "stackSave") (result i32)
+    (global.get $sp))
+
+  (func (export "stackRestore") (param $sp i32)
+    (global.set $sp (local.get $sp)))
+
+  (func (export "dynCall_vi") (param $index i32) (param $a1 i32)
+    (call_indirect (param i32) (local.get $a1) (local.get $index))))
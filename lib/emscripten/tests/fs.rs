@@ -0,0 +1,135 @@
+use std::fs;
+use std::path::Path;
+use wasmer::{Instance, Module, Store};
+use wasmer_emscripten::fs::{EmFs, EACCES, EBADF, ENOENT, O_CREAT, O_RDONLY, O_RDWR, O_WRONLY};
+use wasmer_emscripten::{
+    generate_emscripten_env, run_emscripten_instance, EmEnv, EmscriptenGlobals,
+};
+use wasmer_wasi::WasiState;
+
+fn mapped_fs(host_dir: &Path) -> EmFs {
+    let wasi_state = WasiState::new("")
+        .map_dir("data", host_dir)
+        .unwrap()
+        .build()
+        .unwrap();
+    EmFs::new(wasi_state.fs)
+}
+
+#[test]
+fn runs_with_a_sandboxed_fs() {
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        &include_bytes!("../../c-api/tests/assets/emscripten_hello_world.wasm")[..],
+    )
+    .unwrap();
+    let host_dir = tempfile::tempdir().unwrap();
+    let wasi_state = WasiState::new("")
+        .map_dir("data", host_dir.path())
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let mut globals = EmscriptenGlobals::new(&store, &module).unwrap();
+    let mut env = EmEnv::new_with_fs(wasi_state.fs);
+    let import_object = generate_emscripten_env(&store, &mut globals, &mut env);
+    let mut instance = Instance::new(&module, &import_object).unwrap();
+    run_emscripten_instance(
+        &mut instance,
+        &mut env,
+        &mut globals,
+        "emscripten_hello_world",
+        vec!["1", "2"],
+        None,
+    )
+    .unwrap();
+}
+
+#[test]
+fn reads_and_writes_mapped_files() {
+    let host_dir = tempfile::tempdir().unwrap();
+    let mut em_fs = mapped_fs(host_dir.path());
+
+    let fd = em_fs.open("/data/hello.txt", O_CREAT | O_WRONLY).unwrap();
+    assert_eq!(em_fs.write(fd, b"hello").unwrap(), 5);
+    em_fs.close(fd).unwrap();
+    assert_eq!(
+        fs::read(host_dir.path().join("hello.txt")).unwrap(),
+        b"hello"
+    );
+
+    em_fs.chdir("/data").unwrap();
+    let fd = em_fs.open("hello.txt", O_RDONLY).unwrap();
+    let mut buf = [0; 16];
+    assert_eq!(em_fs.read(fd, &mut buf).unwrap(), 5);
+    assert_eq!(&buf[..5], b"hello");
+    assert_eq!(em_fs.fstat(fd).unwrap().st_size, 5);
+    assert_eq!(em_fs.write(fd, b"bye"), Err(EACCES));
+    em_fs.close(fd).unwrap();
+    assert_eq!(em_fs.read(fd, &mut buf), Err(EBADF));
+
+    let fd = em_fs.open("hello.txt", O_RDWR).unwrap();
+    assert_eq!(em_fs.pwrite(fd, b"J", 0).unwrap(), 1);
+    em_fs.truncate(fd, 3).unwrap();
+    em_fs.close(fd).unwrap();
+    assert_eq!(fs::read(host_dir.path().join("hello.txt")).unwrap(), b"Jel");
+}
+
+#[test]
+fn manages_mapped_directories() {
+    let host_dir = tempfile::tempdir().unwrap();
+    let mut em_fs = mapped_fs(host_dir.path());
+
+    em_fs.mkdir("/data/dir").unwrap();
+    assert!(host_dir.path().join("dir").is_dir());
+    let fd = em_fs.open("/data/dir/a", O_CREAT | O_WRONLY).unwrap();
+    em_fs.close(fd).unwrap();
+
+    em_fs.rename("/data/dir/a", "/data/b").unwrap();
+    assert!(host_dir.path().join("b").is_file());
+    assert_eq!(em_fs.stat("/data/dir/a", true), Err(ENOENT));
+
+    let fd = em_fs.open("/data", O_RDONLY).unwrap();
+    let names = em_fs
+        .read_dir(fd, 16)
+        .unwrap()
+        .into_iter()
+        .map(|(name, _, _)| name)
+        .collect::<Vec<_>>();
+    assert_eq!(names.len(), 4);
+    assert!(names.contains(&"b".to_string()));
+    assert!(names.contains(&"dir".to_string()));
+    em_fs.close(fd).unwrap();
+
+    em_fs.unlink("/data/b").unwrap();
+    em_fs.rmdir("/data/dir").unwrap();
+    assert_eq!(fs::read_dir(host_dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn stays_in_the_sandbox() {
+    let host_dir = tempfile::tempdir().unwrap();
+    let mut em_fs = mapped_fs(host_dir.path());
+
+    let outside = host_dir
+        .path()
+        .parent()
+        .unwrap()
+        .join("outside.txt")
+        .to_string_lossy()
+        .to_string();
+    assert!(em_fs.open(&outside, O_CREAT | O_WRONLY).is_err());
+    assert!(em_fs
+        .open("/data/../../outside.txt", O_CREAT | O_WRONLY)
+        .is_err());
+    assert!(em_fs.stat("/etc/passwd", true).is_err());
+    assert!(em_fs.chdir("/etc").is_err());
+    assert_eq!(em_fs.cwd(), "/");
+    assert!(!host_dir
+        .path()
+        .parent()
+        .unwrap()
+        .join("outside.txt")
+        .exists());
+}
@@ -0,0 +1,66 @@
+//! The modules in `assets` are lowered the way Emscripten lowers the C and
+//! C++ programs in their comments.
+
+use wasmer::{Instance, Module, RuntimeError, Store, Val};
+use wasmer_emscripten::{
+    generate_emscripten_env, run_emscripten_instance, CxxException, EmEnv, EmscriptenGlobals,
+};
+
+/// Runs `main` with the global `mode` set to `mode`, and returns the
+/// instance.
+fn run(wasm: &[u8], mode: i32) -> (Instance, Result<(), RuntimeError>) {
+    let store = Store::default();
+    let module = Module::new(&store, wasm).unwrap();
+    let mut globals = EmscriptenGlobals::new(&store, &module).unwrap();
+    let mut env = EmEnv::new();
+    let import_object = generate_emscripten_env(&store, &mut globals, &mut env);
+    let mut instance = Instance::new(&module, &import_object).unwrap();
+    instance
+        .exports
+        .get_global("mode")
+        .unwrap()
+        .set(Val::I32(mode))
+        .unwrap();
+    let result =
+        run_emscripten_instance(&mut instance, &mut env, &mut globals, "test", vec![], None);
+    (instance, result)
+}
+
+fn get_i32(instance: &Instance, name: &str) -> i32 {
+    match instance.exports.get_global(name).unwrap().get() {
+        Val::I32(value) => value,
+        value => panic!("unexpected value: {:?}", value),
+    }
+}
+
+#[test]
+fn longjmp_returns_to_setjmp() {
+    let (instance, result) = run(include_bytes!("assets/setjmp.wasm"), 0);
+    result.unwrap();
+    assert_eq!(get_i32(&instance, "result"), 42 * 100 + 3);
+}
+
+#[test]
+fn invoke_propagates_traps() {
+    let (instance, result) = run(include_bytes!("assets/setjmp.wasm"), 1);
+    let error = result.unwrap_err();
+    assert_eq!(error.message(), "unreachable");
+    assert_eq!(get_i32(&instance, "result"), 0);
+}
+
+#[test]
+fn catches_exceptions() {
+    let (instance, result) = run(include_bytes!("assets/exceptions.wasm"), 0);
+    result.unwrap();
+    assert_eq!(get_i32(&instance, "result"), 7);
+    assert_eq!(get_i32(&instance, "destroyed"), 1);
+}
+
+#[test]
+fn uncaught_exceptions_unwind_out_of_main() {
+    let (instance, result) = run(include_bytes!("assets/exceptions.wasm"), 1);
+    let error = result.unwrap_err();
+    assert!(error.downcast::<CxxException>().is_ok());
+    assert_eq!(get_i32(&instance, "result"), 0);
+    assert_eq!(get_i32(&instance, "destroyed"), 0);
+}
@@ -2,21 +2,32 @@
 //! done as separate steps.
 
 use crate::engine::{JITEngine, JITEngineInner};
-use crate::link::link_module;
+#[cfg(feature = "compiler")]
+use crate::lazy::{LazyFunctions, LazyModule};
+use crate::link::link_module_with;
 #[cfg(feature = "compiler")]
 use crate::serialize::SerializableCompilation;
 use crate::serialize::SerializableModule;
 use crate::unwind::UnwindRegistry;
+#[cfg(feature = "compiler")]
+use std::any::Any;
+#[cfg(feature = "compiler")]
+use std::mem;
 use std::sync::{Arc, Mutex};
-use wasmer_compiler::{CompileError, Features, Triple};
 #[cfg(feature = "compiler")]
-use wasmer_compiler::{CompileModuleInfo, ModuleEnvironment};
+use std::time::Instant;
+#[cfg(feature = "compiler")]
+use wasmer_compiler::{Compilation, CompileModuleInfo, ModuleEnvironment, ModuleInfoTranslation};
+use wasmer_compiler::{CompileError, Features, Triple};
 use wasmer_engine::{
-    register_frame_info, Artifact, DeserializeError, GlobalFrameInfoRegistration, SerializeError,
+    register_frame_info, Artifact, DeserializeError, GlobalFrameInfoRegistration, MemoryImages,
+    SerializeError,
 };
 #[cfg(feature = "compiler")]
-use wasmer_engine::{Engine, SerializableFunctionFrameInfo, Tunables};
+use wasmer_engine::{CompilationStats, Engine, SerializableFunctionFrameInfo, Tunables};
 use wasmer_types::entity::{BoxedSlice, PrimaryMap};
+#[cfg(feature = "compiler")]
+use wasmer_types::DataInitializer;
 use wasmer_types::{
     FunctionIndex, LocalFunctionIndex, MemoryIndex, OwnedDataInitializer, SignatureIndex,
     TableIndex,
@@ -31,11 +42,22 @@ pub struct JITArtifact {
     finished_dynamic_function_trampolines: BoxedSlice<FunctionIndex, FunctionBodyPtr>,
     signatures: BoxedSlice<SignatureIndex, VMSharedSignatureIndex>,
     frame_info_registration: Mutex<Option<GlobalFrameInfoRegistration>>,
+    memory_images: MemoryImages,
+    #[cfg(feature = "compiler")]
+    lazy_functions: Option<Arc<LazyFunctions>>,
 }
 
 impl JITArtifact {
     const MAGIC_HEADER: &'static [u8] = b"\0wasmer-jit";
 
+    /// The version of the serialization format, written right after the
+    /// magic header. It must be bumped whenever the layout of
+    /// `SerializableModule` changes.
+    ///
+    /// The artifacts serialized before the format was versioned have
+    /// the bincode encoding of the module right after the header.
+    const FORMAT_VERSION: &'static [u8] = b"-v2";
+
     /// Check if the provided bytes look like a serialized `JITArtifact`.
     pub fn is_deserializable(bytes: &[u8]) -> bool {
         bytes.starts_with(Self::MAGIC_HEADER)
@@ -48,11 +70,127 @@ impl JITArtifact {
         data: &[u8],
         tunables: &dyn Tunables,
     ) -> Result<Self, CompileError> {
-        let environ = ModuleEnvironment::new();
+        Self::new_with_stats(jit, data, tunables, &mut CompilationStats::default())
+    }
+
+    /// Compile a data buffer into a `JITArtifact`, recording statistics
+    /// about the compilation in `stats`.
+    ///
+    /// With a tiered engine, the hot functions are compiled again later,
+    /// and the artifact can't be serialized.
+    #[cfg(feature = "compiler")]
+    pub fn new_with_stats(
+        jit: &JITEngine,
+        data: &[u8],
+        tunables: &dyn Tunables,
+        stats: &mut CompilationStats,
+    ) -> Result<Self, CompileError> {
+        if jit.is_tiered() && !LazyFunctions::is_supported(jit.target()) {
+            return Err(CompileError::UnsupportedFeature(format!(
+                "tiered compilation for {}",
+                jit.target().triple()
+            )));
+        }
         let mut inner_jit = jit.inner_mut();
-        let features = inner_jit.features();
+        // The time spent waiting for the engine isn't accounted.
+        let start = Instant::now();
+        let (translation, mut compile_info) =
+            Self::translate(inner_jit.features(), data, tunables)?;
+        let compiler = inner_jit.compiler()?;
+        stats.translation_time = start.elapsed();
+
+        // Keep what the hot functions need to be compiled again
+        let tiered_module = if jit.is_tiered() {
+            compile_info.execution_counters = true;
+            Some(LazyModule::new(
+                jit,
+                false,
+                translation.module_translation.clone().unwrap(),
+                &translation.function_body_inputs,
+            ))
+        } else {
+            None
+        };
 
-        let translation = environ.translate(data).map_err(CompileError::Wasm)?;
+        // Compile the Module
+        let start = Instant::now();
+        stats.functions = translation.function_body_inputs.len();
+        let compilation = compiler.compile_module(
+            &jit.target(),
+            &compile_info,
+            translation.module_translation.as_ref().unwrap(),
+            translation.function_body_inputs,
+        )?;
+        stats.compilation_time = start.elapsed();
+
+        let serializable =
+            Self::serializable(compilation, compile_info, &translation.data_initializers);
+        let compilation = &serializable.compilation;
+        stats.code_size = compilation
+            .function_bodies
+            .values()
+            .chain(compilation.function_call_trampolines.values())
+            .chain(compilation.dynamic_function_trampolines.values())
+            .map(|function| function.body.len())
+            .sum();
+        let start = Instant::now();
+        let artifact = Self::from_parts_with(&mut inner_jit, serializable, tiered_module)?;
+        stats.linking_time = start.elapsed();
+        if jit.is_tiered() {
+            inner_jit.spawn_tiering(jit.downgrade_inner())?;
+        }
+        Ok(artifact)
+    }
+
+    /// Compile a data buffer into a `JITArtifact` lazily: only the
+    /// trampolines are compiled up front, and each function is compiled
+    /// on its first call.
+    ///
+    /// The artifact can't be serialized.
+    #[cfg(feature = "compiler")]
+    pub fn new_lazily(
+        jit: &JITEngine,
+        data: &[u8],
+        tunables: &dyn Tunables,
+    ) -> Result<Self, CompileError> {
+        if !LazyFunctions::is_supported(jit.target()) {
+            return Err(CompileError::UnsupportedFeature(format!(
+                "lazy compilation for {}",
+                jit.target().triple()
+            )));
+        }
+        let mut inner_jit = jit.inner_mut();
+        let (translation, compile_info) = Self::translate(inner_jit.features(), data, tunables)?;
+        let module_translation = translation.module_translation.unwrap();
+
+        // Compile the Module, without any function
+        let compilation = inner_jit.compiler()?.compile_module(
+            jit.target(),
+            &compile_info,
+            &module_translation,
+            PrimaryMap::new(),
+        )?;
+
+        let lazy_module = LazyModule::new(
+            jit,
+            true,
+            module_translation,
+            &translation.function_body_inputs,
+        );
+        let serializable =
+            Self::serializable(compilation, compile_info, &translation.data_initializers);
+        Self::from_parts_with(&mut inner_jit, serializable, Some(lazy_module))
+    }
+
+    /// Translate a data buffer, along with the info to compile it.
+    #[cfg(feature = "compiler")]
+    fn translate<'data>(
+        features: &Features,
+        data: &'data [u8],
+        tunables: &dyn Tunables,
+    ) -> Result<(ModuleInfoTranslation<'data>, CompileModuleInfo), CompileError> {
+        let environ = ModuleEnvironment::new();
+        let mut translation = environ.translate(data).map_err(CompileError::Wasm)?;
 
         let memory_styles: PrimaryMap<MemoryIndex, MemoryStyle> = translation
             .module
@@ -68,26 +206,23 @@ impl JITArtifact {
             .collect();
 
         let compile_info = CompileModuleInfo {
-            module: Arc::new(translation.module),
+            module: Arc::new(mem::replace(&mut translation.module, ModuleInfo::new())),
             features: features.clone(),
             memory_styles,
             table_styles,
+            execution_counters: false,
         };
+        Ok((translation, compile_info))
+    }
 
-        let compiler = inner_jit.compiler()?;
-
-        // Compile the Module
-        let compilation = compiler.compile_module(
-            &jit.target(),
-            &compile_info,
-            translation.module_translation.as_ref().unwrap(),
-            translation.function_body_inputs,
-        )?;
-        let function_call_trampolines = compilation.get_function_call_trampolines();
-        let dynamic_function_trampolines = compilation.get_dynamic_function_trampolines();
-
-        let data_initializers = translation
-            .data_initializers
+    /// Put a compilation along with its module into serializable parts.
+    #[cfg(feature = "compiler")]
+    fn serializable(
+        compilation: Compilation,
+        compile_info: CompileModuleInfo,
+        data_initializers: &[DataInitializer<'_>],
+    ) -> SerializableModule {
+        let data_initializers = data_initializers
             .iter()
             .map(OwnedDataInitializer::new)
             .collect::<Vec<_>>()
@@ -104,18 +239,17 @@ impl JITArtifact {
             function_relocations: compilation.get_relocations(),
             function_jt_offsets: compilation.get_jt_offsets(),
             function_frame_info: frame_infos,
-            function_call_trampolines,
-            dynamic_function_trampolines,
+            function_call_trampolines: compilation.get_function_call_trampolines(),
+            dynamic_function_trampolines: compilation.get_dynamic_function_trampolines(),
             custom_sections: compilation.get_custom_sections(),
             custom_section_relocations: compilation.get_custom_section_relocations(),
             debug: compilation.get_debug(),
         };
-        let serializable = SerializableModule {
+        SerializableModule {
             compilation: serializable_compilation,
             compile_info,
             data_initializers,
-        };
-        Self::from_parts(&mut inner_jit, serializable)
+        }
     }
 
     /// Compile a data buffer into a `JITArtifact`, which may then be instantiated.
@@ -135,6 +269,13 @@ impl JITArtifact {
         }
 
         let inner_bytes = &bytes[Self::MAGIC_HEADER.len()..];
+        if !inner_bytes.starts_with(Self::FORMAT_VERSION) {
+            return Err(DeserializeError::Incompatible(
+                "The provided bytes were serialized by an incompatible version of wasmer-jit"
+                    .to_string(),
+            ));
+        }
+        let inner_bytes = &inner_bytes[Self::FORMAT_VERSION.len()..];
 
         // let r = flexbuffers::Reader::get_root(bytes).map_err(|e| DeserializeError::CorruptedBinary(format!("{:?}", e)))?;
         // let serializable = SerializableModule::deserialize(r).map_err(|e| DeserializeError::CorruptedBinary(format!("{:?}", e)))?;
@@ -149,6 +290,22 @@ impl JITArtifact {
     pub fn from_parts(
         inner_jit: &mut JITEngineInner,
         serializable: SerializableModule,
+    ) -> Result<Self, CompileError> {
+        Self::from_parts_with(
+            inner_jit,
+            serializable,
+            #[cfg(feature = "compiler")]
+            None,
+        )
+    }
+
+    /// Construct a `JITArtifact` from component parts, with the stubs of
+    /// the functions of `lazy_module`, compiled lazily or in tiers, rather
+    /// than the compiled functions.
+    fn from_parts_with(
+        inner_jit: &mut JITEngineInner,
+        serializable: SerializableModule,
+        #[cfg(feature = "compiler")] lazy_module: Option<LazyModule>,
     ) -> Result<Self, CompileError> {
         let mut unwind_registry = UnwindRegistry::new();
         let (
@@ -165,14 +322,48 @@ impl JITArtifact {
         let custom_sections =
             inner_jit.allocate_custom_sections(&serializable.compilation.custom_sections)?;
 
-        link_module(
+        #[cfg(feature = "compiler")]
+        let lazy_functions = match lazy_module {
+            Some(lazy_module) => {
+                let (lazy_functions, stubs) = LazyFunctions::allocate(
+                    inner_jit,
+                    &mut unwind_registry,
+                    lazy_module,
+                    &serializable.compile_info,
+                    &custom_sections,
+                    &finished_functions,
+                )?;
+                inner_jit.publish_lazy_functions(&lazy_functions);
+                Some((lazy_functions, stubs))
+            }
+            None => None,
+        };
+
+        // The functions compiled in tiers call each other through their
+        // stubs, counting their calls.
+        #[cfg(feature = "compiler")]
+        let (callees, execution_counters) = match &lazy_functions {
+            Some((lazy_functions, stubs)) => (stubs, lazy_functions.execution_counters()),
+            None => (&finished_functions, &[][..]),
+        };
+        #[cfg(not(feature = "compiler"))]
+        let (callees, execution_counters) = (&finished_functions, &[][..]);
+        link_module_with(
             &serializable.compile_info.module,
             &finished_functions,
+            callees,
             &serializable.compilation.function_jt_offsets,
             serializable.compilation.function_relocations.clone(),
             &custom_sections,
             &serializable.compilation.custom_section_relocations,
-        );
+            execution_counters,
+        )?;
+
+        #[cfg(feature = "compiler")]
+        let (finished_functions, lazy_functions) = match lazy_functions {
+            Some((lazy_functions, stubs)) => (stubs, Some(lazy_functions)),
+            None => (finished_functions, None),
+        };
 
         // Compute indices into the shared signature table.
         let signatures = {
@@ -223,6 +414,9 @@ impl JITArtifact {
             finished_dynamic_function_trampolines,
             signatures,
             frame_info_registration: Mutex::new(None),
+            memory_images: MemoryImages::default(),
+            #[cfg(feature = "compiler")]
+            lazy_functions,
         })
     }
 
@@ -247,6 +441,18 @@ impl Artifact for JITArtifact {
     }
 
     fn register_frame_info(&self) {
+        let finished_functions = &self.finished_functions;
+        // The functions compiled lazily, or again by the optimizing compiler,
+        // are registered once compiled.
+        #[cfg(feature = "compiler")]
+        let finished_functions = match &self.lazy_functions {
+            Some(lazy_functions) => {
+                lazy_functions.set_module(self.module());
+                lazy_functions.baseline_functions()
+            }
+            None => finished_functions,
+        };
+
         let mut info = self.frame_info_registration.lock().unwrap();
 
         if info.is_some() {
@@ -254,7 +460,6 @@ impl Artifact for JITArtifact {
         }
 
         let frame_infos = &self.serializable.compilation.function_frame_info;
-        let finished_functions = &self.finished_functions;
         *info = register_frame_info(
             self.serializable.compile_info.module.clone(),
             finished_functions,
@@ -270,6 +475,10 @@ impl Artifact for JITArtifact {
         &*self.serializable.data_initializers
     }
 
+    fn memory_images(&self) -> Option<&MemoryImages> {
+        Some(&self.memory_images)
+    }
+
     fn memory_styles(&self) -> &PrimaryMap<MemoryIndex, MemoryStyle> {
         &self.serializable.compile_info.memory_styles
     }
@@ -291,15 +500,29 @@ impl Artifact for JITArtifact {
         &self.signatures
     }
 
+    #[cfg(feature = "compiler")]
+    fn code_state(&self) -> Option<Arc<dyn Any + Send + Sync>> {
+        let lazy_functions = self.lazy_functions.clone()?;
+        Some(lazy_functions)
+    }
+
     fn serialize(&self) -> Result<Vec<u8>, SerializeError> {
+        #[cfg(feature = "compiler")]
+        if self.lazy_functions.is_some() {
+            return Err(SerializeError::Generic(
+                "The functions of the module are compiled lazily or in tiers".to_string(),
+            ));
+        }
+
         // let mut s = flexbuffers::FlexbufferSerializer::new();
         // self.serializable.serialize(&mut s).map_err(|e| SerializeError::Generic(format!("{:?}", e)));
         // Ok(s.take_buffer())
         let bytes = bincode::serialize(&self.serializable)
             .map_err(|e| SerializeError::Generic(format!("{:?}", e)))?;
 
-        // Prepend the header.
+        // Prepend the header and the format version.
         let mut serialized = Self::MAGIC_HEADER.to_vec();
+        serialized.extend(Self::FORMAT_VERSION);
         serialized.extend(bytes);
         Ok(serialized)
     }
@@ -1,12 +1,18 @@
 use crate::JITEngine;
+#[cfg(feature = "compiler")]
+use crate::TieringPolicy;
 use wasmer_compiler::{CompilerConfig, Features, Target};
 
 /// The JIT builder
 pub struct JIT<'a> {
     #[allow(dead_code)]
     compiler_config: Option<&'a dyn CompilerConfig>,
+    #[allow(dead_code)]
+    optimizing_compiler_config: Option<&'a dyn CompilerConfig>,
     target: Option<Target>,
     features: Option<Features>,
+    #[cfg(feature = "compiler")]
+    tiering_policy: TieringPolicy,
 }
 
 impl<'a> JIT<'a> {
@@ -14,8 +20,28 @@ impl<'a> JIT<'a> {
     pub fn new(compiler_config: &'a dyn CompilerConfig) -> Self {
         Self {
             compiler_config: Some(compiler_config),
+            optimizing_compiler_config: None,
             target: None,
             features: None,
+            #[cfg(feature = "compiler")]
+            tiering_policy: TieringPolicy::default(),
+        }
+    }
+
+    /// Create a new tiered JIT, compiling the modules with the
+    /// `baseline_compiler_config` first, and then their hot functions
+    /// with the `optimizing_compiler_config`.
+    #[cfg(feature = "compiler")]
+    pub fn tiered(
+        baseline_compiler_config: &'a dyn CompilerConfig,
+        optimizing_compiler_config: &'a dyn CompilerConfig,
+    ) -> Self {
+        Self {
+            compiler_config: Some(baseline_compiler_config),
+            optimizing_compiler_config: Some(optimizing_compiler_config),
+            target: None,
+            features: None,
+            tiering_policy: TieringPolicy::default(),
         }
     }
 
@@ -23,8 +49,11 @@ impl<'a> JIT<'a> {
     pub fn headless() -> Self {
         Self {
             compiler_config: None,
+            optimizing_compiler_config: None,
             target: None,
             features: None,
+            #[cfg(feature = "compiler")]
+            tiering_policy: TieringPolicy::default(),
         }
     }
 
@@ -40,6 +69,13 @@ impl<'a> JIT<'a> {
         self
     }
 
+    /// Set the policy of the tiered compilation
+    #[cfg(feature = "compiler")]
+    pub fn tiering_policy(mut self, tiering_policy: TieringPolicy) -> Self {
+        self.tiering_policy = tiering_policy;
+        self
+    }
+
     /// Build the `JITEngine` for this configuration
     #[cfg(feature = "compiler")]
     pub fn engine(self) -> JITEngine {
@@ -49,7 +85,16 @@ impl<'a> JIT<'a> {
                 .features
                 .unwrap_or_else(|| compiler_config.default_features_for_target(&target));
             let compiler = compiler_config.compiler();
-            JITEngine::new(compiler, target, features)
+            match self.optimizing_compiler_config {
+                Some(optimizing_compiler_config) => JITEngine::new_tiered(
+                    compiler,
+                    optimizing_compiler_config.compiler(),
+                    self.tiering_policy,
+                    target,
+                    features,
+                ),
+                None => JITEngine::new(compiler, target, features),
+            }
         } else {
             JITEngine::headless()
         }
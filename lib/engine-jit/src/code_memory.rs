@@ -128,6 +128,31 @@ impl CodeMemory {
         Ok(vmfunc)
     }
 
+    /// Allocate a memory block for a single compiled function, on pages of
+    /// its own.
+    ///
+    /// This is used for the functions compiled lazily, which are published
+    /// one at a time, rather than sharing a block of at least 64KB.
+    pub fn allocate_for_lazy_function(
+        &mut self,
+        registry: &mut UnwindRegistry,
+        func: &FunctionBody,
+    ) -> Result<&mut [VMFunctionBody], String> {
+        let size = Self::function_allocation_size(func);
+
+        let previous = mem::replace(&mut self.current, CodeMemoryEntry::with_capacity(size)?);
+        if !previous.mmap.is_empty() {
+            self.entries.push(previous);
+        }
+        self.position = size;
+
+        let buf = &mut self.current.mmap.as_mut_slice()[..size];
+        let base_address = buf.as_ptr() as usize;
+        let (_, _, vmfunc) = Self::copy_function(registry, base_address, func, 0, buf);
+
+        Ok(vmfunc)
+    }
+
     /// Allocate a continuous memory block for an executable custom section.
     pub fn allocate_for_executable_custom_section(
         &mut self,
@@ -1,14 +1,24 @@
 //! JIT compilation.
 
+#[cfg(feature = "compiler")]
+use crate::lazy::LazyFunctions;
+#[cfg(feature = "compiler")]
+use crate::tiering::{self, TieringPolicy};
 use crate::unwind::UnwindRegistry;
 use crate::{CodeMemory, JITArtifact};
 use std::collections::HashMap;
+#[cfg(feature = "compiler")]
+use std::sync::Weak;
 use std::sync::{Arc, Mutex};
 #[cfg(feature = "compiler")]
+use std::thread::Thread;
+#[cfg(feature = "compiler")]
 use wasmer_compiler::Compiler;
 use wasmer_compiler::{
     CompileError, CustomSection, CustomSectionProtection, FunctionBody, SectionIndex, Target,
 };
+#[cfg(feature = "compiler")]
+use wasmer_engine::CompilationStats;
 use wasmer_engine::{Artifact, DeserializeError, Engine, EngineId, Tunables};
 use wasmer_types::entity::PrimaryMap;
 use wasmer_types::Features;
@@ -25,6 +35,10 @@ pub struct JITEngine {
     /// The target for the compiler
     target: Arc<Target>,
     engine_id: EngineId,
+    /// Whether the hot functions are compiled again by an optimizing
+    /// compiler.
+    #[cfg(feature = "compiler")]
+    tiered: bool,
 }
 
 impl JITEngine {
@@ -38,12 +52,49 @@ impl JITEngine {
                 code_memory: CodeMemory::new(),
                 signatures: SignatureRegistry::new(),
                 features,
+                lazy_functions: Vec::new(),
+                optimizing_compiler: None,
+                optimized_functions: 0,
+                tiering_policy: None,
+                tiering_thread: None,
             })),
             target: Arc::new(target),
             engine_id: EngineId::default(),
+            tiered: false,
         }
     }
 
+    /// Create a new `JITEngine` compiling the modules with the `baseline`
+    /// compiler, and their hot functions again in the background with the
+    /// `optimizing` compiler, as decided by the tiering `policy`.
+    ///
+    /// The optimized functions are swapped in on their next call. The
+    /// thread compiling them is spawned with the first module.
+    #[cfg(feature = "compiler")]
+    pub fn new_tiered(
+        baseline: Box<dyn Compiler + Send>,
+        optimizing: Box<dyn Compiler + Send>,
+        policy: TieringPolicy,
+        target: Target,
+        features: Features,
+    ) -> Self {
+        let mut engine = Self::new(baseline, target, features);
+        {
+            let mut inner = engine.inner_mut();
+            inner.optimizing_compiler = Some(optimizing);
+            inner.tiering_policy = Some(policy);
+        }
+        engine.tiered = true;
+        engine
+    }
+
+    /// The number of hot functions compiled again by the optimizing
+    /// compiler so far.
+    #[cfg(feature = "compiler")]
+    pub fn optimized_functions(&self) -> usize {
+        self.inner().optimized_functions
+    }
+
     /// Create a headless `JITEngine`
     ///
     /// A headless engine is an engine without any compiler attached.
@@ -66,9 +117,21 @@ impl JITEngine {
                 code_memory: CodeMemory::new(),
                 signatures: SignatureRegistry::new(),
                 features: Features::default(),
+                #[cfg(feature = "compiler")]
+                lazy_functions: Vec::new(),
+                #[cfg(feature = "compiler")]
+                optimizing_compiler: None,
+                #[cfg(feature = "compiler")]
+                optimized_functions: 0,
+                #[cfg(feature = "compiler")]
+                tiering_policy: None,
+                #[cfg(feature = "compiler")]
+                tiering_thread: None,
             })),
             target: Arc::new(Target::default()),
             engine_id: EngineId::default(),
+            #[cfg(feature = "compiler")]
+            tiered: false,
         }
     }
 
@@ -79,6 +142,18 @@ impl JITEngine {
     pub(crate) fn inner_mut(&self) -> std::sync::MutexGuard<'_, JITEngineInner> {
         self.inner.lock().unwrap()
     }
+
+    /// Checks if the hot functions are compiled again by an optimizing
+    /// compiler.
+    #[cfg(feature = "compiler")]
+    pub(crate) fn is_tiered(&self) -> bool {
+        self.tiered
+    }
+
+    #[cfg(feature = "compiler")]
+    pub(crate) fn downgrade_inner(&self) -> Weak<Mutex<JITEngineInner>> {
+        Arc::downgrade(&self.inner)
+    }
 }
 
 impl Engine for JITEngine {
@@ -119,6 +194,30 @@ impl Engine for JITEngine {
         Ok(Arc::new(JITArtifact::new(&self, binary, tunables)?))
     }
 
+    /// Compile a WebAssembly binary, recording statistics about the
+    /// compilation
+    #[cfg(feature = "compiler")]
+    fn compile_with_stats(
+        &self,
+        binary: &[u8],
+        tunables: &dyn Tunables,
+        stats: &mut CompilationStats,
+    ) -> Result<Arc<dyn Artifact>, CompileError> {
+        Ok(Arc::new(JITArtifact::new_with_stats(
+            &self, binary, tunables, stats,
+        )?))
+    }
+
+    /// Compile a WebAssembly binary lazily
+    #[cfg(feature = "compiler")]
+    fn compile_lazily(
+        &self,
+        binary: &[u8],
+        tunables: &dyn Tunables,
+    ) -> Result<Arc<dyn Artifact>, CompileError> {
+        Ok(Arc::new(JITArtifact::new_lazily(self, binary, tunables)?))
+    }
+
     /// Compile a WebAssembly binary
     #[cfg(not(feature = "compiler"))]
     fn compile(
@@ -161,6 +260,22 @@ pub struct JITEngineInner {
     /// The signature registry is used mainly to operate with trampolines
     /// performantly.
     signatures: SignatureRegistry,
+    /// The state of the modules compiled lazily or in tiers, kept alive by
+    /// their artifacts and their instances.
+    #[cfg(feature = "compiler")]
+    lazy_functions: Vec<Weak<LazyFunctions>>,
+    /// The compiler of the hot functions, with tiered compilation.
+    #[cfg(feature = "compiler")]
+    optimizing_compiler: Option<Box<dyn Compiler + Send>>,
+    /// The number of functions compiled by the optimizing compiler.
+    #[cfg(feature = "compiler")]
+    optimized_functions: usize,
+    /// The policy of the tiered compilation, until its thread is spawned.
+    #[cfg(feature = "compiler")]
+    tiering_policy: Option<TieringPolicy>,
+    /// The thread compiling the hot functions, once spawned.
+    #[cfg(feature = "compiler")]
+    tiering_thread: Option<Thread>,
 }
 
 impl JITEngineInner {
@@ -173,6 +288,33 @@ impl JITEngineInner {
         Ok(&**self.compiler.as_ref().unwrap())
     }
 
+    /// Spawns the thread compiling the hot functions of the modules of
+    /// `engine` again, or wakes it up if it is already running, for a new
+    /// module.
+    #[cfg(feature = "compiler")]
+    pub(crate) fn spawn_tiering(
+        &mut self,
+        engine: Weak<Mutex<JITEngineInner>>,
+    ) -> Result<(), CompileError> {
+        if let Some(policy) = self.tiering_policy.take() {
+            self.tiering_thread = Some(tiering::spawn(engine, policy)?);
+        } else if let Some(thread) = &self.tiering_thread {
+            thread.unpark();
+        }
+        Ok(())
+    }
+
+    /// Gets the optimizing compiler of the tiered compilation.
+    #[cfg(feature = "compiler")]
+    pub(crate) fn optimizing_compiler(&self) -> Result<&dyn Compiler, CompileError> {
+        match &self.optimizing_compiler {
+            Some(compiler) => Ok(&**compiler),
+            None => Err(CompileError::Codegen(
+                "The JITEngine doesn't compile the modules in tiers".to_string(),
+            )),
+        }
+    }
+
     /// Validate the module
     #[cfg(feature = "compiler")]
     pub fn validate<'data>(&self, data: &'data [u8]) -> Result<(), CompileError> {
@@ -309,6 +451,70 @@ impl JITEngineInner {
         ))
     }
 
+    /// Allocate the code used to compile the functions lazily, the stubs
+    /// and the resolvers, into memory
+    #[cfg(feature = "compiler")]
+    pub(crate) fn allocate_lazy_code(
+        &mut self,
+        registry: &mut UnwindRegistry,
+        code: &FunctionBody,
+    ) -> Result<FunctionBodyPtr, CompileError> {
+        let ptr = self
+            .code_memory
+            .allocate_for_function(registry, code)
+            .map_err(|message| {
+                CompileError::Resource(format!(
+                    "failed to allocate memory for lazy compilation: {}",
+                    message
+                ))
+            })?;
+        Ok(FunctionBodyPtr(ptr))
+    }
+
+    /// Allocate a function compiled lazily into memory
+    #[cfg(feature = "compiler")]
+    pub(crate) fn allocate_lazy_function(
+        &mut self,
+        registry: &mut UnwindRegistry,
+        function: &FunctionBody,
+    ) -> Result<FunctionBodyPtr, CompileError> {
+        let ptr = self
+            .code_memory
+            .allocate_for_lazy_function(registry, function)
+            .map_err(|message| {
+                CompileError::Resource(format!(
+                    "failed to allocate memory for function: {}",
+                    message
+                ))
+            })?;
+        Ok(FunctionBodyPtr(ptr))
+    }
+
+    /// Publish the state of a module compiled lazily or in tiers, as long
+    /// as it is alive.
+    #[cfg(feature = "compiler")]
+    pub(crate) fn publish_lazy_functions(&mut self, lazy_functions: &Arc<LazyFunctions>) {
+        self.lazy_functions.push(Arc::downgrade(lazy_functions));
+    }
+
+    /// The state of the modules compiled lazily or in tiers still alive,
+    /// forgetting the dropped ones.
+    #[cfg(feature = "compiler")]
+    pub(crate) fn lazy_functions(&mut self) -> Vec<Arc<LazyFunctions>> {
+        self.lazy_functions
+            .retain(|lazy_functions| lazy_functions.strong_count() > 0);
+        self.lazy_functions
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect()
+    }
+
+    /// Count a function compiled by the optimizing compiler.
+    #[cfg(feature = "compiler")]
+    pub(crate) fn count_optimized_function(&mut self) {
+        self.optimized_functions += 1;
+    }
+
     /// Make memory containing compiled code executable.
     pub(crate) fn publish_compiled_code(&mut self) {
         self.code_memory.publish();
@@ -329,3 +535,13 @@ impl JITEngineInner {
         self.function_call_trampolines.get(&sig).cloned()
     }
 }
+
+#[cfg(feature = "compiler")]
+impl Drop for JITEngineInner {
+    fn drop(&mut self) {
+        // Wakes the tiering thread up, so it finds out the engine is gone.
+        if let Some(thread) = &self.tiering_thread {
+            thread.unpark();
+        }
+    }
+}
@@ -0,0 +1,506 @@
+//! Lazy compilation of the functions of a module, on their first call,
+//! and tiered compilation of the hot functions.
+//!
+//! Every local function gets a stub, used as its body by the instances,
+//! which jumps to the address held by the slot of the function. The slots
+//! initially point to the resolver of the module: it compiles the function,
+//! patches the slot with the compiled function, and then jumps to it, as if
+//! it had been called directly.
+//!
+//! With tiered compilation, the stubs also increment the execution counter
+//! of their function, and the slots point to the functions compiled by the
+//! baseline compiler of the engine (or to the resolver, if compiled
+//! lazily). The hot functions are compiled again by the optimizing compiler
+//! of the engine, and their slots patched, so the next calls enter the
+//! optimized code.
+//!
+//! The functions compiled afterwards call the functions in their final tier
+//! directly, and the stubs of the other functions.
+
+use crate::engine::{JITEngine, JITEngineInner};
+use crate::link::{link_function, link_function_eh_frame};
+use crate::unwind::UnwindRegistry;
+use std::mem;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::slice;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed, Ordering::SeqCst};
+use std::sync::{Arc, Mutex, Weak};
+use wasmer_compiler::{
+    Architecture, CompileError, CompileModuleInfo, FunctionBody, FunctionBodyData,
+    ModuleTranslationState, SectionIndex, Target,
+};
+use wasmer_engine::Engine;
+use wasmer_engine::{
+    register_function_frame_info, GlobalFrameInfoRegistration, SerializableFunctionFrameInfo,
+};
+use wasmer_types::entity::{BoxedSlice, EntityRef, PrimaryMap};
+use wasmer_types::{Features, LocalFunctionIndex, MemoryIndex, TableIndex};
+use wasmer_vm::{
+    raise_user_trap, resume_panic, FunctionBodyPtr, MemoryStyle, ModuleInfo, TableStyle,
+    VMFunctionBody,
+};
+
+/// The function bodies of a module, copied out of its binary.
+struct LazyFunctionBodies {
+    data: Vec<u8>,
+    /// The range of each function body in `data`, and its offset in the
+    /// binary of the module.
+    functions: PrimaryMap<LocalFunctionIndex, (Range<usize>, usize)>,
+}
+
+impl LazyFunctionBodies {
+    /// Copies the function bodies of a module.
+    fn new(inputs: &PrimaryMap<LocalFunctionIndex, FunctionBodyData<'_>>) -> Self {
+        let mut data = Vec::with_capacity(inputs.values().map(|input| input.data.len()).sum());
+        let functions = inputs
+            .values()
+            .map(|input| {
+                let start = data.len();
+                data.extend_from_slice(input.data);
+                (start..data.len(), input.module_offset)
+            })
+            .collect();
+        Self { data, functions }
+    }
+
+    fn get(&self, index: LocalFunctionIndex) -> FunctionBodyData<'_> {
+        let (range, module_offset) = &self.functions[index];
+        FunctionBodyData {
+            data: &self.data[range.clone()],
+            module_offset: *module_offset,
+        }
+    }
+}
+
+/// What the functions of a module need to be compiled lazily, or in
+/// tiers.
+pub(crate) struct LazyModule {
+    engine: Weak<Mutex<JITEngineInner>>,
+    target: Target,
+    /// Whether the functions are compiled on their first call, rather than
+    /// up front.
+    lazy: bool,
+    tiered: bool,
+    module_translation: ModuleTranslationState,
+    bodies: LazyFunctionBodies,
+}
+
+impl LazyModule {
+    /// Creates a lazy module, copying its function bodies.
+    pub(crate) fn new(
+        jit: &JITEngine,
+        lazy: bool,
+        module_translation: ModuleTranslationState,
+        inputs: &PrimaryMap<LocalFunctionIndex, FunctionBodyData<'_>>,
+    ) -> Self {
+        Self {
+            engine: jit.downgrade_inner(),
+            target: jit.target().clone(),
+            lazy,
+            tiered: jit.is_tiered(),
+            module_translation,
+            bodies: LazyFunctionBodies::new(inputs),
+        }
+    }
+}
+
+/// The tier a function is compiled in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tier {
+    /// Compiled by the compiler of the engine.
+    Baseline,
+    /// Compiled again by the optimizing compiler of the engine.
+    Optimized,
+    /// Compiled by the compiler of the engine, and failed to be compiled
+    /// by the optimizing compiler.
+    Unoptimizable,
+}
+
+/// The state of the functions of a module compiled lazily, or in tiers.
+///
+/// It is kept alive by the artifact of the module and its instances, since
+/// their functions are called through the stubs, and published to the
+/// engine, which compiles the hot ones again.
+pub(crate) struct LazyFunctions {
+    /// The addresses the stubs jump to, by function.
+    slots: Box<[AtomicUsize]>,
+    /// The execution counters of the functions, if compiled in tiers.
+    counters: Box<[AtomicU64]>,
+    /// The functions compiled up front by the baseline compiler.
+    baseline_functions: BoxedSlice<LocalFunctionIndex, FunctionBodyPtr>,
+    engine: Weak<Mutex<JITEngineInner>>,
+    target: Target,
+    features: Features,
+    memory_styles: PrimaryMap<MemoryIndex, MemoryStyle>,
+    table_styles: PrimaryMap<TableIndex, TableStyle>,
+    module_translation: ModuleTranslationState,
+    bodies: LazyFunctionBodies,
+    /// The addresses of the allocated custom sections.
+    custom_sections: PrimaryMap<SectionIndex, usize>,
+    tiered: bool,
+    /// Serializes the compilation of the functions.
+    state: Mutex<LazyState>,
+}
+
+struct LazyState {
+    /// The module info is handed over on instantiation, once the module
+    /// can't be renamed anymore.
+    module: Option<Arc<ModuleInfo>>,
+    stubs: PrimaryMap<LocalFunctionIndex, FunctionBodyPtr>,
+    /// The tier of each function, if compiled.
+    tiers: PrimaryMap<LocalFunctionIndex, Option<Tier>>,
+    frame_info_registrations: Vec<GlobalFrameInfoRegistration>,
+}
+
+impl LazyFunctions {
+    /// The size of a stub: `movabs r11, slot; jmp [r11]`.
+    const STUB_SIZE: usize = 13;
+
+    /// The size of the code incrementing the execution counter in the
+    /// stubs: `movabs r11, counter; add qword [r11], 1`.
+    const COUNTER_INCREMENT_SIZE: usize = 14;
+
+    /// Checks if the functions can be compiled lazily, or in tiers, for
+    /// `target`.
+    ///
+    /// The stubs and the resolver are only written for x86-64, and the
+    /// resolver saves the argument registers of the System V calling
+    /// convention.
+    pub(crate) fn is_supported(target: &Target) -> bool {
+        cfg!(all(target_arch = "x86_64", unix))
+            && target.triple().architecture == Architecture::X86_64
+    }
+
+    /// Allocates the stubs of the functions of a module, returning them,
+    /// and its resolver if the functions are compiled lazily rather than
+    /// being the `finished_functions`.
+    ///
+    /// The code isn't published, nor linked.
+    pub(crate) fn allocate(
+        inner_jit: &mut JITEngineInner,
+        registry: &mut UnwindRegistry,
+        lazy_module: LazyModule,
+        compile_info: &CompileModuleInfo,
+        custom_sections: &PrimaryMap<SectionIndex, *const u8>,
+        finished_functions: &PrimaryMap<LocalFunctionIndex, FunctionBodyPtr>,
+    ) -> Result<(Arc<Self>, PrimaryMap<LocalFunctionIndex, FunctionBodyPtr>), CompileError> {
+        let compiled = if lazy_module.lazy {
+            None
+        } else {
+            Some(finished_functions)
+        };
+        let num_functions = lazy_module.bodies.functions.len();
+        let slots = (0..num_functions).map(|_| AtomicUsize::new(0)).collect();
+        let num_counters = if lazy_module.tiered { num_functions } else { 0 };
+        let counters = (0..num_counters).map(|_| AtomicU64::new(0)).collect();
+        let functions = Arc::new(Self {
+            slots,
+            counters,
+            baseline_functions: compiled
+                .cloned()
+                .unwrap_or_else(PrimaryMap::new)
+                .into_boxed_slice(),
+            engine: lazy_module.engine,
+            target: lazy_module.target,
+            features: compile_info.features.clone(),
+            memory_styles: compile_info.memory_styles.clone(),
+            table_styles: compile_info.table_styles.clone(),
+            module_translation: lazy_module.module_translation,
+            bodies: lazy_module.bodies,
+            custom_sections: custom_sections
+                .values()
+                .map(|section| *section as usize)
+                .collect(),
+            tiered: lazy_module.tiered,
+            state: Mutex::new(LazyState {
+                module: None,
+                stubs: PrimaryMap::new(),
+                tiers: PrimaryMap::new(),
+                frame_info_registrations: Vec::new(),
+            }),
+        });
+
+        let (targets, tiers) = match compiled {
+            Some(compiled) => (
+                compiled
+                    .values()
+                    .map(|body| **body as *const VMFunctionBody as usize)
+                    .collect(),
+                compiled.keys().map(|_| Some(Tier::Baseline)).collect(),
+            ),
+            None => {
+                let resolver = FunctionBody {
+                    body: functions.resolver_body(),
+                    unwind_info: None,
+                };
+                let resolver = inner_jit.allocate_lazy_code(registry, &resolver)?;
+                let resolver = *resolver as *const VMFunctionBody as usize;
+                (
+                    vec![resolver; num_functions],
+                    (0..num_functions).map(|_| None).collect(),
+                )
+            }
+        };
+        let stubs = functions
+            .slots
+            .iter()
+            .zip(targets)
+            .enumerate()
+            .map(|(index, (slot, target))| {
+                slot.store(target, SeqCst);
+                FunctionBody {
+                    body: functions.stub_body(index),
+                    unwind_info: None,
+                }
+            })
+            .collect::<PrimaryMap<LocalFunctionIndex, _>>();
+        let stubs = stubs
+            .values()
+            .map(|stub| inner_jit.allocate_lazy_code(registry, stub))
+            .collect::<Result<PrimaryMap<LocalFunctionIndex, _>, _>>()?;
+
+        let mut state = functions.state.lock().unwrap();
+        state.stubs = stubs.clone();
+        state.tiers = tiers;
+        drop(state);
+        Ok((functions, stubs))
+    }
+
+    /// The execution counters of the functions, empty unless they are
+    /// compiled in tiers.
+    pub(crate) fn execution_counters(&self) -> &[AtomicU64] {
+        &self.counters
+    }
+
+    /// The functions compiled up front by the baseline compiler, if any.
+    pub(crate) fn baseline_functions(&self) -> &BoxedSlice<LocalFunctionIndex, FunctionBodyPtr> {
+        &self.baseline_functions
+    }
+
+    /// Hands over the module info, needed to compile the functions.
+    pub(crate) fn set_module(&self, module: Arc<ModuleInfo>) {
+        self.state.lock().unwrap().module = Some(module);
+    }
+
+    /// The code of the stub of the function at `index`, jumping through
+    /// its slot, after incrementing its execution counter if any.
+    fn stub_body(&self, index: usize) -> Vec<u8> {
+        let mut body = Vec::with_capacity(Self::COUNTER_INCREMENT_SIZE + Self::STUB_SIZE);
+        if let Some(counter) = self.counters.get(index) {
+            // movabs r11, counter
+            body.extend_from_slice(&[0x49, 0xbb]);
+            body.extend_from_slice(&(counter as *const AtomicU64 as u64).to_le_bytes());
+            // add qword ptr [r11], 1
+            body.extend_from_slice(&[0x49, 0x83, 0x03, 0x01]);
+            debug_assert_eq!(body.len(), Self::COUNTER_INCREMENT_SIZE);
+        }
+        // movabs r11, slot
+        body.extend_from_slice(&[0x49, 0xbb]);
+        body.extend_from_slice(&(&self.slots[index] as *const AtomicUsize as u64).to_le_bytes());
+        // jmp qword ptr [r11]
+        body.extend_from_slice(&[0x41, 0xff, 0x23]);
+        body
+    }
+
+    /// The code of the resolver, called by the stubs with the slot in `r11`.
+    ///
+    /// It saves the argument registers, calls [`resolve`] with the
+    /// functions and the slot, restores the registers, and jumps to the
+    /// compiled function.
+    fn resolver_body(self: &Arc<Self>) -> Vec<u8> {
+        let mut body = Vec::new();
+        // push rdi; push rsi; push rdx; push rcx; push r8; push r9
+        body.extend_from_slice(&[0x57, 0x56, 0x52, 0x51, 0x41, 0x50, 0x41, 0x51]);
+        // sub rsp, 0x88, keeping the stack aligned to 16 bytes
+        body.extend_from_slice(&[0x48, 0x81, 0xec, 0x88, 0x00, 0x00, 0x00]);
+        // movdqu [rsp + 16 * n], xmm<n>
+        for n in 0..8 {
+            body.extend_from_slice(&[0xf3, 0x0f, 0x7f, 0x44 | n << 3, 0x24, n * 16]);
+        }
+        // movabs rdi, functions
+        body.extend_from_slice(&[0x48, 0xbf]);
+        body.extend_from_slice(&(Arc::as_ptr(self) as u64).to_le_bytes());
+        // mov rsi, r11
+        body.extend_from_slice(&[0x4c, 0x89, 0xde]);
+        // movabs rax, resolve; call rax
+        body.extend_from_slice(&[0x48, 0xb8]);
+        body.extend_from_slice(&(resolve as ResolveFn as usize as u64).to_le_bytes());
+        body.extend_from_slice(&[0xff, 0xd0]);
+        // movdqu xmm<n>, [rsp + 16 * n]
+        for n in 0..8 {
+            body.extend_from_slice(&[0xf3, 0x0f, 0x6f, 0x44 | n << 3, 0x24, n * 16]);
+        }
+        // add rsp, 0x88
+        body.extend_from_slice(&[0x48, 0x81, 0xc4, 0x88, 0x00, 0x00, 0x00]);
+        // pop r9; pop r8; pop rcx; pop rdx; pop rsi; pop rdi
+        body.extend_from_slice(&[0x41, 0x59, 0x41, 0x58, 0x59, 0x5a, 0x5e, 0x5f]);
+        // jmp rax
+        body.extend_from_slice(&[0xff, 0xe0]);
+        body
+    }
+
+    /// Checks if the functions in `tier` won't be compiled again, so they
+    /// can be called directly rather than through their stub.
+    fn is_final(&self, tier: Option<Tier>) -> bool {
+        match tier {
+            Some(Tier::Baseline) => !self.tiered,
+            Some(Tier::Optimized) | Some(Tier::Unoptimizable) => true,
+            None => false,
+        }
+    }
+
+    /// Checks if some functions may still be compiled again with the
+    /// optimizing compiler.
+    pub(crate) fn may_optimize(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        !self.counters.is_empty() && state.tiers.values().any(|tier| !self.is_final(*tier))
+    }
+
+    /// Compiles the hot functions again with the optimizing compiler,
+    /// returning how many were compiled.
+    ///
+    /// The functions are hot once their execution counter reaches
+    /// `threshold`. The functions the optimizing compiler fails to compile
+    /// keep running their baseline code.
+    pub(crate) fn optimize_hot_functions(&self, threshold: u64) -> usize {
+        let mut optimized = 0;
+        for (index, counter) in self.counters.iter().enumerate() {
+            let index = LocalFunctionIndex::new(index);
+            if counter.load(Relaxed) < threshold
+                || self.state.lock().unwrap().tiers[index] != Some(Tier::Baseline)
+            {
+                continue;
+            }
+            match self.compile(index, Tier::Optimized) {
+                Ok(_) => optimized += 1,
+                Err(_) => self.state.lock().unwrap().tiers[index] = Some(Tier::Unoptimizable),
+            }
+        }
+        optimized
+    }
+
+    /// Compiles the function at `index` in `tier`, unless it is already
+    /// compiled in that tier, and returns its body.
+    fn compile(&self, index: LocalFunctionIndex, tier: Tier) -> Result<usize, CompileError> {
+        let mut state = self.state.lock().unwrap();
+        let slot = &self.slots[index.index()];
+        match (tier, state.tiers[index]) {
+            (_, None) | (Tier::Optimized, Some(Tier::Baseline)) => {}
+            _ => return Ok(slot.load(SeqCst)),
+        }
+        let module = state.module.clone().ok_or_else(|| {
+            CompileError::Codegen("The module of the function isn't instantiated".to_string())
+        })?;
+        let engine = self.engine.upgrade().ok_or_else(|| {
+            CompileError::Codegen("The engine of the function was dropped".to_string())
+        })?;
+        let mut inner_jit = engine.lock().unwrap();
+
+        let compile_info = CompileModuleInfo {
+            features: self.features.clone(),
+            module: module.clone(),
+            memory_styles: self.memory_styles.clone(),
+            table_styles: self.table_styles.clone(),
+            // The baseline code of the functions compiled in tiers counts
+            // their executions.
+            execution_counters: tier == Tier::Baseline && !self.counters.is_empty(),
+        };
+        let compiler = match tier {
+            Tier::Optimized => inner_jit.optimizing_compiler()?,
+            _ => inner_jit.compiler()?,
+        };
+        let (function, eh_frame) = compiler.compile_function(
+            &self.target,
+            &compile_info,
+            &self.module_translation,
+            index,
+            &self.bodies.get(index),
+        )?;
+
+        let mut registry = UnwindRegistry::new();
+        let body = inner_jit.allocate_lazy_function(&mut registry, &function.body)?;
+        let custom_sections = self
+            .custom_sections
+            .values()
+            .map(|section| *section as *const u8)
+            .collect();
+        let tiers = &state.tiers;
+        link_function(
+            body,
+            &function.jt_offsets,
+            &function.relocations,
+            |callee| {
+                let callee_tier = if callee == index {
+                    Some(tier)
+                } else {
+                    tiers[callee]
+                };
+                if !self.is_final(callee_tier) {
+                    *state.stubs[callee] as *const VMFunctionBody as usize
+                } else if callee == index {
+                    *body as *const VMFunctionBody as usize
+                } else {
+                    self.slots[callee.index()].load(SeqCst)
+                }
+            },
+            &custom_sections,
+            &self.counters,
+        )?;
+        let eh_frame = match eh_frame {
+            Some(section) => {
+                let mut sections = PrimaryMap::new();
+                let section_index = sections.push(section);
+                let pointer = inner_jit.allocate_custom_sections(&sections)?[section_index];
+                let section = &sections[section_index];
+                link_function_eh_frame(pointer, &section.relocations, body);
+                Some(unsafe { slice::from_raw_parts(pointer, section.bytes.len()) })
+            }
+            None => None,
+        };
+        inner_jit.publish_compiled_code();
+        registry.publish(eh_frame).map_err(|e| {
+            CompileError::Resource(format!("Error while publishing the unwind code: {}", e))
+        })?;
+        inner_jit.publish_unwind_registry(Arc::new(registry));
+        if tier == Tier::Optimized {
+            inner_jit.count_optimized_function();
+        }
+        drop(inner_jit);
+
+        state
+            .frame_info_registrations
+            .extend(register_function_frame_info(
+                module,
+                index,
+                &body,
+                SerializableFunctionFrameInfo::Processed(function.frame_info),
+            ));
+        state.tiers[index] = Some(tier);
+        let address = *body as *const VMFunctionBody as usize;
+        slot.store(address, SeqCst);
+        Ok(address)
+    }
+}
+
+type ResolveFn =
+    unsafe extern "C" fn(*const LazyFunctions, *const AtomicUsize) -> *const VMFunctionBody;
+
+/// Compiles the function of `slot`, and returns its body, for the
+/// resolver.
+///
+/// The compilation errors are raised as traps.
+unsafe extern "C" fn resolve(
+    functions: *const LazyFunctions,
+    slot: *const AtomicUsize,
+) -> *const VMFunctionBody {
+    let functions = &*functions;
+    let index = (slot as usize - functions.slots.as_ptr() as usize) / mem::size_of::<AtomicUsize>();
+    match panic::catch_unwind(AssertUnwindSafe(|| {
+        functions.compile(LocalFunctionIndex::new(index), Tier::Baseline)
+    })) {
+        Ok(Ok(address)) => address as *const VMFunctionBody,
+        Ok(Err(error)) => raise_user_trap(Box::new(error)),
+        Err(panic) => resume_panic(panic),
+    }
+}
@@ -28,8 +28,12 @@ mod artifact;
 mod builder;
 mod code_memory;
 mod engine;
+#[cfg(feature = "compiler")]
+mod lazy;
 mod link;
 mod serialize;
+#[cfg(feature = "compiler")]
+mod tiering;
 mod unwind;
 
 pub use crate::artifact::JITArtifact;
@@ -37,6 +41,8 @@ pub use crate::builder::JIT;
 pub use crate::code_memory::CodeMemory;
 pub use crate::engine::JITEngine;
 pub use crate::link::link_module;
+#[cfg(feature = "compiler")]
+pub use crate::tiering::TieringPolicy;
 
 /// Version number of this crate.
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -1,9 +1,10 @@
 //! Linking for JIT-compiled code.
 
 use std::ptr::write_unaligned;
+use std::sync::atomic::AtomicU64;
 use wasmer_compiler::{
-    JumpTable, JumpTableOffsets, Relocation, RelocationKind, RelocationTarget, Relocations,
-    SectionIndex,
+    CompileError, JumpTable, JumpTableOffsets, Relocation, RelocationKind, RelocationTarget,
+    Relocations, SectionIndex,
 };
 use wasmer_types::entity::{EntityRef, PrimaryMap};
 use wasmer_types::LocalFunctionIndex;
@@ -14,28 +15,57 @@ fn apply_relocation(
     body: usize,
     r: &Relocation,
     allocated_functions: &PrimaryMap<LocalFunctionIndex, FunctionBodyPtr>,
+    callees: &PrimaryMap<LocalFunctionIndex, FunctionBodyPtr>,
     jt_offsets: &PrimaryMap<LocalFunctionIndex, JumpTableOffsets>,
     allocated_sections: &PrimaryMap<SectionIndex, *const u8>,
-) {
+    execution_counters: &[AtomicU64],
+) -> Result<(), CompileError> {
     let target_func_address: usize = match r.reloc_target {
-        RelocationTarget::LocalFunc(index) => {
-            let fatptr: *const [VMFunctionBody] = allocated_functions[index].0;
-            fatptr as *const VMFunctionBody as usize
-        }
+        RelocationTarget::LocalFunc(index) => function_address(callees[index]),
         RelocationTarget::LibCall(libcall) => libcall.function_pointer(),
         RelocationTarget::CustomSection(custom_section) => {
             allocated_sections[custom_section] as usize
         }
         RelocationTarget::JumpTable(func_index, jt) => {
-            let offset = *jt_offsets
-                .get(func_index)
-                .and_then(|ofs| ofs.get(JumpTable::new(jt.index())))
-                .expect("func jump table");
-            let fatptr: *const [VMFunctionBody] = allocated_functions[func_index].0;
-            fatptr as *const VMFunctionBody as usize + offset as usize
+            jump_table_address(allocated_functions[func_index], &jt_offsets[func_index], jt)
+        }
+        RelocationTarget::ExecutionCounter(index) => {
+            execution_counter_address(execution_counters, index)?
         }
     };
+    patch_relocation(body, r, target_func_address);
+    Ok(())
+}
 
+fn execution_counter_address(
+    execution_counters: &[AtomicU64],
+    index: LocalFunctionIndex,
+) -> Result<usize, CompileError> {
+    let counter = execution_counters.get(index.index()).ok_or_else(|| {
+        CompileError::Codegen(
+            "Execution counters are only linked for tiered compilation".to_string(),
+        )
+    })?;
+    Ok(counter as *const AtomicU64 as usize)
+}
+
+fn function_address(function: FunctionBodyPtr) -> usize {
+    let fatptr: *const [VMFunctionBody] = function.0;
+    fatptr as *const VMFunctionBody as usize
+}
+
+fn jump_table_address(
+    function: FunctionBodyPtr,
+    jt_offsets: &JumpTableOffsets,
+    jt: JumpTable,
+) -> usize {
+    let offset = *jt_offsets
+        .get(JumpTable::new(jt.index()))
+        .expect("func jump table");
+    function_address(function) + offset as usize
+}
+
+fn patch_relocation(body: usize, r: &Relocation, target_func_address: usize) {
     match r.kind {
         #[cfg(target_pointer_width = "64")]
         RelocationKind::Abs8 => unsafe {
@@ -68,24 +98,114 @@ fn apply_relocation(
 /// Links a module, patching the allocated functions with the
 /// required relocations and jump tables.
 pub fn link_module(
+    module: &ModuleInfo,
+    allocated_functions: &PrimaryMap<LocalFunctionIndex, FunctionBodyPtr>,
+    jt_offsets: &PrimaryMap<LocalFunctionIndex, JumpTableOffsets>,
+    function_relocations: Relocations,
+    allocated_sections: &PrimaryMap<SectionIndex, *const u8>,
+    section_relocations: &PrimaryMap<SectionIndex, Vec<Relocation>>,
+) -> Result<(), CompileError> {
+    link_module_with(
+        module,
+        allocated_functions,
+        allocated_functions,
+        jt_offsets,
+        function_relocations,
+        allocated_sections,
+        section_relocations,
+        &[],
+    )
+}
+
+/// Links a module like [`link_module`], calling the functions of the
+/// module through `callees`, and with the `execution_counters` of its
+/// functions.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn link_module_with(
     _module: &ModuleInfo,
     allocated_functions: &PrimaryMap<LocalFunctionIndex, FunctionBodyPtr>,
+    callees: &PrimaryMap<LocalFunctionIndex, FunctionBodyPtr>,
     jt_offsets: &PrimaryMap<LocalFunctionIndex, JumpTableOffsets>,
     function_relocations: Relocations,
     allocated_sections: &PrimaryMap<SectionIndex, *const u8>,
     section_relocations: &PrimaryMap<SectionIndex, Vec<Relocation>>,
-) {
+    execution_counters: &[AtomicU64],
+) -> Result<(), CompileError> {
     for (i, section_relocs) in section_relocations.iter() {
         let body = allocated_sections[i] as usize;
         for r in section_relocs {
-            apply_relocation(body, r, allocated_functions, jt_offsets, allocated_sections);
+            apply_relocation(
+                body,
+                r,
+                allocated_functions,
+                callees,
+                jt_offsets,
+                allocated_sections,
+                execution_counters,
+            )?;
         }
     }
     for (i, function_relocs) in function_relocations.into_iter() {
-        let fatptr: *const [VMFunctionBody] = allocated_functions[i].0;
-        let body = fatptr as *const VMFunctionBody as usize;
+        let body = function_address(allocated_functions[i]);
         for r in function_relocs {
-            apply_relocation(body, r, allocated_functions, jt_offsets, allocated_sections);
+            apply_relocation(
+                body,
+                r,
+                allocated_functions,
+                callees,
+                jt_offsets,
+                allocated_sections,
+                execution_counters,
+            )?;
         }
     }
+    Ok(())
+}
+
+/// Links a single function, compiled apart from the rest of its module,
+/// patching it with the required relocations and jump tables.
+///
+/// The other functions of the module are called at the address given by
+/// `function_address`.
+#[cfg(feature = "compiler")]
+pub(crate) fn link_function(
+    function: FunctionBodyPtr,
+    jt_offsets: &JumpTableOffsets,
+    relocations: &[Relocation],
+    function_address: impl Fn(LocalFunctionIndex) -> usize,
+    allocated_sections: &PrimaryMap<SectionIndex, *const u8>,
+    execution_counters: &[AtomicU64],
+) -> Result<(), CompileError> {
+    let body = self::function_address(function);
+    for r in relocations {
+        let target_func_address = match r.reloc_target {
+            RelocationTarget::LocalFunc(index) => function_address(index),
+            RelocationTarget::LibCall(libcall) => libcall.function_pointer(),
+            RelocationTarget::CustomSection(custom_section) => {
+                allocated_sections[custom_section] as usize
+            }
+            // The jump tables of a function are only used by the function itself.
+            RelocationTarget::JumpTable(_, jt) => jump_table_address(function, jt_offsets, jt),
+            RelocationTarget::ExecutionCounter(index) => {
+                execution_counter_address(execution_counters, index)?
+            }
+        };
+        patch_relocation(body, r, target_func_address);
+    }
+    Ok(())
+}
+
+/// Links the `.eh_frame` section of a single function, compiled apart
+/// from the rest of its module, whose relocations only refer to the
+/// function itself.
+#[cfg(feature = "compiler")]
+pub(crate) fn link_function_eh_frame(
+    eh_frame: *const u8,
+    relocations: &[Relocation],
+    function: FunctionBodyPtr,
+) {
+    for r in relocations {
+        debug_assert!(matches!(r.reloc_target, RelocationTarget::LocalFunc(_)));
+        patch_relocation(eh_frame as usize, r, function_address(function));
+    }
 }
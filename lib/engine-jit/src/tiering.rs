@@ -0,0 +1,84 @@
+//! Tiered compilation: the modules are compiled by the baseline compiler
+//! of the engine, and their hot functions compiled again in the background
+//! by its optimizing compiler.
+
+use crate::engine::JITEngineInner;
+use std::sync::{Mutex, Weak};
+use std::thread::{self, Thread};
+use std::time::Duration;
+use wasmer_compiler::CompileError;
+
+/// The policy of the tiered compilation, deciding when the functions are
+/// hot enough to be compiled again by the optimizing compiler.
+///
+/// The functions count their calls, and the iterations of their loops if
+/// the baseline compiler supports it.
+#[derive(Clone, Debug)]
+pub struct TieringPolicy {
+    threshold: u64,
+    interval: Duration,
+}
+
+impl TieringPolicy {
+    /// Creates a new policy with the default thresholds.
+    pub fn new() -> Self {
+        Self {
+            threshold: 10_000,
+            interval: Duration::from_millis(10),
+        }
+    }
+
+    /// Sets the number of calls and loop iterations after which a
+    /// function is hot.
+    pub fn threshold(&mut self, threshold: u64) -> &mut Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Sets the interval between the checks for hot functions.
+    pub fn interval(&mut self, interval: Duration) -> &mut Self {
+        self.interval = interval;
+        self
+    }
+}
+
+impl Default for TieringPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Spawns the thread compiling the hot functions of the modules of
+/// `engine` again, until the engine is dropped, and returns it.
+///
+/// The thread parks while no module has functions left to optimize, and
+/// is unparked when a module is compiled or the engine dropped.
+pub(crate) fn spawn(
+    engine: Weak<Mutex<JITEngineInner>>,
+    policy: TieringPolicy,
+) -> Result<Thread, CompileError> {
+    let handle = thread::Builder::new()
+        .name("wasmer-tiering".to_string())
+        .spawn(move || loop {
+            thread::sleep(policy.interval);
+            let modules = match engine.upgrade() {
+                Some(engine) => engine.lock().unwrap().lazy_functions(),
+                None => return,
+            };
+            if !modules.iter().any(|functions| functions.may_optimize()) {
+                drop(modules);
+                thread::park();
+                continue;
+            }
+            for functions in modules {
+                functions.optimize_hot_functions(policy.threshold);
+            }
+        })
+        .map_err(|error| {
+            CompileError::Resource(format!(
+                "Can't spawn the tiered compilation thread: {}",
+                error
+            ))
+        })?;
+    Ok(handle.thread().clone())
+}
@@ -11,19 +11,23 @@ use std::path::{Path, PathBuf};
 #[cfg(feature = "compiler")]
 use std::process::Command;
 use std::sync::Arc;
+#[cfg(feature = "compiler")]
+use std::time::Instant;
+#[cfg(not(target_os = "linux"))]
 use tempfile::NamedTempFile;
 #[cfg(feature = "compiler")]
 use tracing::trace;
 use wasmer_compiler::{CompileError, Features, OperatingSystem, Symbol, SymbolRegistry, Triple};
 #[cfg(feature = "compiler")]
 use wasmer_compiler::{
-    CompileModuleInfo, FunctionBodyData, ModuleEnvironment, ModuleTranslationState,
+    CompileModuleInfo, FunctionBodyData, ModuleEnvironment, ModuleTranslationState, Target,
 };
 use wasmer_engine::{
-    Artifact, DeserializeError, InstantiationError, LinkError, RuntimeError, SerializeError,
+    Artifact, DeserializeError, InstantiationError, LinkError, MemoryImages, RuntimeError,
+    SerializeError,
 };
 #[cfg(feature = "compiler")]
-use wasmer_engine::{Engine, Tunables};
+use wasmer_engine::{CompilationStats, Engine, Tunables};
 #[cfg(feature = "compiler")]
 use wasmer_object::{emit_compilation, emit_data, get_object_for_target};
 use wasmer_types::entity::{BoxedSlice, PrimaryMap};
@@ -40,21 +44,47 @@ use wasmer_vm::{
 
 /// A compiled wasm module, ready to be instantiated.
 pub struct NativeArtifact {
-    sharedobject_path: PathBuf,
+    /// The shared object the module was loaded from, unless it's linked
+    /// into the executable.
+    sharedobject_path: Option<PathBuf>,
     metadata: ModuleMetadata,
     #[allow(dead_code)]
     library: Option<Library>,
+    /// The file the library was loaded from, when deserialized from
+    /// bytes. It's dropped after the library is unloaded.
+    #[allow(dead_code)]
+    in_memory_file: Option<InMemoryFile>,
     finished_functions: BoxedSlice<LocalFunctionIndex, FunctionBodyPtr>,
     finished_dynamic_function_trampolines: BoxedSlice<FunctionIndex, FunctionBodyPtr>,
     signatures: BoxedSlice<SignatureIndex, VMSharedSignatureIndex>,
+    memory_images: MemoryImages,
 }
 
+/// An anonymous file, alive as long as the handle is, on Linux.
+#[cfg(target_os = "linux")]
+type InMemoryFile = File;
+/// A temporary file, removed when the path is dropped, elsewhere.
+#[cfg(not(target_os = "linux"))]
+type InMemoryFile = tempfile::TempPath;
+
 fn to_compile_error(err: impl Error) -> CompileError {
     CompileError::Codegen(format!("{}", err))
 }
 
 const WASMER_METADATA_SYMBOL: &[u8] = b"WASMER_METADATA";
 
+/// The length of the header of the metadata: the LEB128 length of the
+/// serialized metadata, followed by the format version.
+const METADATA_HEADER_LEN: usize = 10;
+
+/// The version of the serialization format of the metadata, stored in
+/// the last byte of its header. It must be bumped whenever the layout of
+/// `ModuleMetadata` changes.
+///
+/// The length never takes the whole header, so that byte is always zero
+/// in the objects generated before the format was versioned.
+const METADATA_VERSION: u8 = 1;
+
 impl NativeArtifact {
     // Mach-O header in Mac
     #[allow(dead_code)]
@@ -131,6 +161,7 @@ impl NativeArtifact {
             features: features.clone(),
             memory_styles,
             table_styles,
+            execution_counters: false,
         };
         Ok((
             compile_info,
@@ -146,101 +177,38 @@ impl NativeArtifact {
         engine: &NativeEngine,
         data: &[u8],
         tunables: &dyn Tunables,
+    ) -> Result<Self, CompileError> {
+        Self::new_with_stats(engine, data, tunables, &mut CompilationStats::default())
+    }
+
+    /// Compile a data buffer into a `NativeArtifact`, recording statistics
+    /// about the compilation in `stats`.
+    ///
+    /// The shared object generation is accounted as `linking_time`.
+    #[cfg(feature = "compiler")]
+    pub fn new_with_stats(
+        engine: &NativeEngine,
+        data: &[u8],
+        tunables: &dyn Tunables,
+        stats: &mut CompilationStats,
     ) -> Result<Self, CompileError> {
         let mut engine_inner = engine.inner_mut();
         let target = engine.target();
-        let compiler = engine_inner.compiler()?;
-        let (compile_info, function_body_inputs, data_initializers, module_translation) =
-            Self::generate_metadata(data, engine_inner.features(), tunables)?;
-
-        let data_initializers = data_initializers
-            .iter()
-            .map(OwnedDataInitializer::new)
-            .collect::<Vec<_>>()
-            .into_boxed_slice();
-
         let target_triple = target.triple();
+        let (metadata, obj_bytes) =
+            Self::generate_object_with_stats(&mut engine_inner, target, data, tunables, stats)?;
+        let start = Instant::now();
+        let filepath = {
+            let file = tempfile::Builder::new()
+                .prefix("wasmer_native")
+                .suffix(".o")
+                .tempfile()
+                .map_err(to_compile_error)?;
 
-        /*
-        // We construct the function body lengths
-        let function_body_lengths = compilation
-            .get_function_bodies()
-            .values()
-            .map(|function_body| function_body.body.len() as u64)
-            .map(|_function_body| 0u64)
-            .collect::<PrimaryMap<LocalFunctionIndex, u64>>();
-         */
-
-        // TODO: we currently supply all-zero function body lengths.
-        // We don't know the lengths until they're compiled, yet we have to
-        // supply the metadata as an input to the compile.
-        let function_body_lengths = function_body_inputs
-            .keys()
-            .map(|_function_body| 0u64)
-            .collect::<PrimaryMap<LocalFunctionIndex, u64>>();
-
-        let metadata = ModuleMetadata {
-            compile_info,
-            prefix: engine_inner.get_prefix(&data),
-            data_initializers,
-            function_body_lengths,
-        };
-
-        let serialized_data = bincode::serialize(&metadata).map_err(to_compile_error)?;
-        let mut metadata_binary = vec![0; 10];
-        let mut writable = &mut metadata_binary[..];
-        leb128::write::unsigned(&mut writable, serialized_data.len() as u64)
-            .expect("Should write number");
-        metadata_binary.extend(serialized_data);
-
-        let maybe_obj_bytes = compiler.experimental_native_compile_module(
-            &target,
-            &metadata.compile_info,
-            module_translation.as_ref().unwrap(),
-            &function_body_inputs,
-            &metadata,
-            &metadata_binary,
-        );
-
-        let filepath = match maybe_obj_bytes {
-            Some(obj_bytes) => {
-                let obj_bytes = obj_bytes?;
-                let file = tempfile::Builder::new()
-                    .prefix("wasmer_native")
-                    .suffix(".o")
-                    .tempfile()
-                    .map_err(to_compile_error)?;
-
-                // Re-open it.
-                let (mut file, filepath) = file.keep().map_err(to_compile_error)?;
-                file.write(&obj_bytes).map_err(to_compile_error)?;
-                filepath
-            }
-            None => {
-                let compilation = compiler.compile_module(
-                    &target,
-                    &metadata.compile_info,
-                    module_translation.as_ref().unwrap(),
-                    function_body_inputs,
-                )?;
-                let mut obj = get_object_for_target(&target_triple).map_err(to_compile_error)?;
-                emit_data(&mut obj, WASMER_METADATA_SYMBOL, &metadata_binary)
-                    .map_err(to_compile_error)?;
-                emit_compilation(&mut obj, compilation, &metadata, &target_triple)
-                    .map_err(to_compile_error)?;
-                let file = tempfile::Builder::new()
-                    .prefix("wasmer_native")
-                    .suffix(".o")
-                    .tempfile()
-                    .map_err(to_compile_error)?;
-
-                // Re-open it.
-                let (mut file, filepath) = file.keep().map_err(to_compile_error)?;
-                let obj_bytes = obj.write().map_err(to_compile_error)?;
-
-                file.write(&obj_bytes).map_err(to_compile_error)?;
-                filepath
-            }
+            // Re-open it.
+            let (mut file, filepath) = file.keep().map_err(to_compile_error)?;
+            file.write(&obj_bytes).map_err(to_compile_error)?;
+            filepath
         };
 
         let shared_filepath = {
@@ -305,12 +273,147 @@ impl NativeArtifact {
             )));
         }
         trace!("gcc command result {:?}", output);
-        if is_cross_compiling {
+        let artifact = if is_cross_compiling {
             Self::from_parts_crosscompiled(metadata, shared_filepath)
         } else {
             let lib = Library::new(&shared_filepath).map_err(to_compile_error)?;
             Self::from_parts(&mut engine_inner, metadata, shared_filepath, lib)
-        }
+        };
+        stats.linking_time = start.elapsed();
+        artifact
+    }
+
+    /// Compile a data buffer into an object file holding the code of the
+    /// module and its metadata.
+    ///
+    /// It can be linked into an executable exporting its symbols, and
+    /// loaded with [`NativeArtifact::deserialize_from_executable`].
+    #[cfg(feature = "compiler")]
+    pub fn generate_object(
+        engine: &NativeEngine,
+        data: &[u8],
+        tunables: &dyn Tunables,
+    ) -> Result<Vec<u8>, CompileError> {
+        let mut engine_inner = engine.inner_mut();
+        let (_, obj_bytes) = Self::generate_object_with_stats(
+            &mut engine_inner,
+            engine.target(),
+            data,
+            tunables,
+            &mut CompilationStats::default(),
+        )?;
+        Ok(obj_bytes)
+    }
+
+    /// Compile a data buffer into an object file, returning it with the
+    /// metadata of the module.
+    #[cfg(feature = "compiler")]
+    fn generate_object_with_stats(
+        engine_inner: &mut NativeEngineInner,
+        target: &Target,
+        data: &[u8],
+        tunables: &dyn Tunables,
+        stats: &mut CompilationStats,
+    ) -> Result<(ModuleMetadata, Vec<u8>), CompileError> {
+        // The time spent waiting for the engine isn't accounted.
+        let start = Instant::now();
+        let compiler = engine_inner.compiler()?;
+        let (compile_info, function_body_inputs, data_initializers, module_translation) =
+            Self::generate_metadata(data, engine_inner.features(), tunables)?;
+
+        let data_initializers = data_initializers
+            .iter()
+            .map(OwnedDataInitializer::new)
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+
+        let target_triple = target.triple();
+
+        /*
+        // We construct the function body lengths
+        let function_body_lengths = compilation
+            .get_function_bodies()
+            .values()
+            .map(|function_body| function_body.body.len() as u64)
+            .map(|_function_body| 0u64)
+            .collect::<PrimaryMap<LocalFunctionIndex, u64>>();
+         */
+
+        // TODO: we currently supply all-zero function body lengths.
+        // We don't know the lengths until they're compiled, yet we have to
+        // supply the metadata as an input to the compile.
+        let function_body_lengths = function_body_inputs
+            .keys()
+            .map(|_function_body| 0u64)
+            .collect::<PrimaryMap<LocalFunctionIndex, u64>>();
+
+        let metadata = ModuleMetadata {
+            compile_info,
+            prefix: engine_inner.get_prefix(&data),
+            data_initializers,
+            function_body_lengths,
+        };
+
+        let serialized_data = bincode::serialize(&metadata).map_err(to_compile_error)?;
+        let mut metadata_binary = vec![0; METADATA_HEADER_LEN];
+        let mut writable = &mut metadata_binary[..METADATA_HEADER_LEN - 1];
+        leb128::write::unsigned(&mut writable, serialized_data.len() as u64)
+            .expect("Should write number");
+        metadata_binary[METADATA_HEADER_LEN - 1] = METADATA_VERSION;
+        metadata_binary.extend(serialized_data);
+        stats.translation_time = start.elapsed();
+
+        let start = Instant::now();
+        stats.functions = function_body_inputs.len();
+        let maybe_obj_bytes = compiler.experimental_native_compile_module(
+            &target,
+            &metadata.compile_info,
+            module_translation.as_ref().unwrap(),
+            &function_body_inputs,
+            &metadata,
+            &metadata_binary,
+        );
+
+        let obj_bytes = match maybe_obj_bytes {
+            Some(obj_bytes) => {
+                let obj_bytes = obj_bytes?;
+                stats.code_size = obj_bytes.len();
+                obj_bytes
+            }
+            None => {
+                let compilation = compiler.compile_module(
+                    &target,
+                    &metadata.compile_info,
+                    module_translation.as_ref().unwrap(),
+                    function_body_inputs,
+                )?;
+                stats.code_size = compilation
+                    .get_function_bodies()
+                    .values()
+                    .map(|function| function.body.len())
+                    .chain(
+                        compilation
+                            .get_function_call_trampolines()
+                            .values()
+                            .map(|trampoline| trampoline.body.len()),
+                    )
+                    .chain(
+                        compilation
+                            .get_dynamic_function_trampolines()
+                            .values()
+                            .map(|trampoline| trampoline.body.len()),
+                    )
+                    .sum();
+                let mut obj = get_object_for_target(&target_triple).map_err(to_compile_error)?;
+                emit_data(&mut obj, WASMER_METADATA_SYMBOL, &metadata_binary)
+                    .map_err(to_compile_error)?;
+                emit_compilation(&mut obj, compilation, &metadata, &target_triple)
+                    .map_err(to_compile_error)?;
+                obj.write().map_err(to_compile_error)?
+            }
+        };
+        stats.compilation_time = start.elapsed();
+        Ok((metadata, obj_bytes))
     }
 
     /// Get the default extension when serializing this artifact
@@ -334,13 +437,15 @@ impl NativeArtifact {
             PrimaryMap::new();
         let signatures: PrimaryMap<SignatureIndex, VMSharedSignatureIndex> = PrimaryMap::new();
         Ok(Self {
-            sharedobject_path,
+            sharedobject_path: Some(sharedobject_path),
             metadata,
             library: None,
+            in_memory_file: None,
             finished_functions: finished_functions.into_boxed_slice(),
             finished_dynamic_function_trampolines: finished_dynamic_function_trampolines
                 .into_boxed_slice(),
             signatures: signatures.into_boxed_slice(),
+            memory_images: MemoryImages::default(),
         })
     }
 
@@ -435,13 +540,15 @@ impl NativeArtifact {
         };
 
         Ok(Self {
-            sharedobject_path,
+            sharedobject_path: Some(sharedobject_path),
             metadata,
             library: Some(lib),
+            in_memory_file: None,
             finished_functions: finished_functions.into_boxed_slice(),
             finished_dynamic_function_trampolines: finished_dynamic_function_trampolines
                 .into_boxed_slice(),
             signatures: signatures.into_boxed_slice(),
+            memory_images: MemoryImages::default(),
         })
     }
 
@@ -468,12 +575,47 @@ impl NativeArtifact {
             ));
         }
         // Dump the bytes into a file, so we can read it with our `dlopen`
-        let named_file = NamedTempFile::new()?;
-        let (mut file, path) = named_file.keep().map_err(|e| e.error)?;
-        file.write_all(&bytes)?;
+        let (file, path) = Self::write_in_memory_file(bytes)?;
         // We already checked for the header, so we don't need
         // to check again.
-        Self::deserialize_from_file_unchecked(&engine, &path)
+        let mut artifact = Self::deserialize_from_file_unchecked(engine, &path)?;
+        artifact.in_memory_file = Some(file);
+        Ok(artifact)
+    }
+
+    /// Writes `bytes` into an anonymous file created with `memfd_create`,
+    /// which is opened through its `/proc/self/fd` link, so that nothing
+    /// is written to the filesystem.
+    #[cfg(target_os = "linux")]
+    fn write_in_memory_file(bytes: &[u8]) -> Result<(InMemoryFile, PathBuf), DeserializeError> {
+        use std::os::unix::io::{AsRawFd, FromRawFd};
+
+        let name = b"wasmer_native\0";
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_memfd_create,
+                name.as_ptr() as *const libc::c_char,
+                libc::MFD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let mut file = unsafe { File::from_raw_fd(fd as libc::c_int) };
+        file.write_all(bytes)?;
+        let path = PathBuf::from(format!("/proc/self/fd/{}", file.as_raw_fd()));
+        Ok((file, path))
+    }
+
+    /// Writes `bytes` into a temporary file, removed once the artifact is
+    /// dropped.
+    #[cfg(not(target_os = "linux"))]
+    fn write_in_memory_file(bytes: &[u8]) -> Result<(InMemoryFile, PathBuf), DeserializeError> {
+        let mut named_file = NamedTempFile::new()?;
+        named_file.write_all(bytes)?;
+        let path = named_file.into_temp_path();
+        let path_buf = path.to_path_buf();
+        Ok((path, path_buf))
     }
 
     /// Deserialize a `NativeArtifact` from a file path.
@@ -506,15 +648,51 @@ impl NativeArtifact {
         engine: &NativeEngine,
         path: &Path,
     ) -> Result<Self, DeserializeError> {
-        let lib = Library::new(&path).map_err(|e| {
+        // The library is mapped from the file, without being copied.
+        // `dlopen` looks up the paths without a slash in the library search
+        // paths, so the relative paths are made absolute first.
+        let shared_path: PathBuf = if path.is_relative() {
+            std::env::current_dir()?.join(path)
+        } else {
+            PathBuf::from(path)
+        };
+        let lib = Library::new(&shared_path).map_err(|e| {
             DeserializeError::CorruptedBinary(format!("Library loading failed: {}", e))
         })?;
-        let shared_path: PathBuf = PathBuf::from(path);
-        // We use 10 + 1, as the length of the module will take 10 bytes
-        // (we construct it like that in `metadata_length`) and we also want
-        // to take the first element of the data to construct the slice from
-        // it.
-        let symbol: LibrarySymbol<*mut [u8; 10 + 1]> =
+        Self::deserialize_from_library(engine, lib, shared_path)
+    }
+
+    /// Deserialize the `NativeArtifact` statically linked into the current
+    /// executable, by `wasmer create-exe`.
+    ///
+    /// Its symbols are looked up like the ones of a library, so the
+    /// executable must export them.
+    ///
+    /// # Safety
+    ///
+    /// The executable must be linked with an object file generated by this
+    /// engine.
+    #[cfg(unix)]
+    pub unsafe fn deserialize_from_executable(
+        engine: &NativeEngine,
+    ) -> Result<Self, DeserializeError> {
+        let lib = libloading::os::unix::Library::this().into();
+        let mut artifact = Self::deserialize_from_library(engine, lib, std::env::current_exe()?)?;
+        artifact.sharedobject_path = None;
+        Ok(artifact)
+    }
+
+    /// Deserialize a `NativeArtifact` from the metadata and the functions
+    /// of a loaded library.
+    unsafe fn deserialize_from_library(
+        engine: &NativeEngine,
+        lib: Library,
+        shared_path: PathBuf,
+    ) -> Result<Self, DeserializeError> {
+        // We use METADATA_HEADER_LEN + 1, as the length of the module and the
+        // format version take the whole header and we also want to take
+        // the first element of the data to construct the slice from it.
+        let symbol: LibrarySymbol<*mut [u8; METADATA_HEADER_LEN + 1]> =
             lib.get(WASMER_METADATA_SYMBOL).map_err(|e| {
                 DeserializeError::CorruptedBinary(format!(
                     "The provided object file doesn't seem to be generated by Wasmer: {}",
@@ -525,12 +703,20 @@ impl NativeArtifact {
         use std::slice;
 
         let size = &mut **symbol.deref();
-        let mut readable = &size[..];
+        if size[METADATA_HEADER_LEN - 1] != METADATA_VERSION {
+            return Err(DeserializeError::Incompatible(
+                "The provided object file was generated by an incompatible version of wasmer-engine-native"
+                    .to_string(),
+            ));
+        }
+        let mut readable = &size[..METADATA_HEADER_LEN - 1];
         let metadata_len = leb128::read::unsigned(&mut readable).map_err(|_e| {
             DeserializeError::CorruptedBinary("Can't read metadata size".to_string())
         })?;
-        let metadata_slice: &'static [u8] =
-            slice::from_raw_parts(&size[10] as *const u8, metadata_len as usize);
+        let metadata_slice: &'static [u8] = slice::from_raw_parts(
+            &size[METADATA_HEADER_LEN] as *const u8,
+            metadata_len as usize,
+        );
         let metadata: ModuleMetadata = bincode::deserialize(metadata_slice)
             .map_err(|e| DeserializeError::CorruptedBinary(format!("{:?}", e)))?;
         let mut engine_inner = engine.inner_mut();
@@ -565,6 +751,10 @@ impl Artifact for NativeArtifact {
         &*self.metadata.data_initializers
     }
 
+    fn memory_images(&self) -> Option<&MemoryImages> {
+        Some(&self.memory_images)
+    }
+
     fn memory_styles(&self) -> &PrimaryMap<MemoryIndex, MemoryStyle> {
         &self.metadata.compile_info.memory_styles
     }
@@ -596,6 +786,11 @@ impl Artifact for NativeArtifact {
 
     /// Serialize a NativeArtifact
     fn serialize(&self) -> Result<Vec<u8>, SerializeError> {
-        Ok(std::fs::read(&self.sharedobject_path)?)
+        match &self.sharedobject_path {
+            Some(path) => Ok(std::fs::read(path)?),
+            None => Err(SerializeError::Generic(
+                "The module linked into the executable can't be serialized".to_string(),
+            )),
+        }
     }
 }
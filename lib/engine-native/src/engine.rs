@@ -8,6 +8,8 @@ use std::sync::Mutex;
 #[cfg(feature = "compiler")]
 use wasmer_compiler::Compiler;
 use wasmer_compiler::{CompileError, Target};
+#[cfg(feature = "compiler")]
+use wasmer_engine::CompilationStats;
 use wasmer_engine::{Artifact, DeserializeError, Engine, EngineId, Tunables};
 #[cfg(feature = "compiler")]
 use wasmer_types::Features;
@@ -134,6 +136,20 @@ impl Engine for NativeEngine {
         Ok(Arc::new(NativeArtifact::new(&self, binary, tunables)?))
     }
 
+    /// Compile a WebAssembly binary, recording statistics about the
+    /// compilation
+    #[cfg(feature = "compiler")]
+    fn compile_with_stats(
+        &self,
+        binary: &[u8],
+        tunables: &dyn Tunables,
+        stats: &mut CompilationStats,
+    ) -> Result<Arc<dyn Artifact>, CompileError> {
+        Ok(Arc::new(NativeArtifact::new_with_stats(
+            &self, binary, tunables, stats,
+        )?))
+    }
+
     /// Compile a WebAssembly binary (it will fail because the `compiler` flag is disabled).
     #[cfg(not(feature = "compiler"))]
     fn compile(
@@ -163,6 +179,13 @@ impl Engine for NativeEngine {
         )?))
     }
 
+    /// Deserializes the WebAssembly module linked into the current
+    /// executable, from the object file generated by this engine.
+    #[cfg(unix)]
+    unsafe fn deserialize_from_executable(&self) -> Result<Arc<dyn Artifact>, DeserializeError> {
+        Ok(Arc::new(NativeArtifact::deserialize_from_executable(self)?))
+    }
+
     fn id(&self) -> &EngineId {
         &self.engine_id
     }
@@ -1,5 +1,6 @@
 use crate::{
-    resolve_imports, InstantiationError, Resolver, RuntimeError, SerializeError, Tunables,
+    resolve_imports, InstantiationError, LinkError, MemoryImages, Resolver, RuntimeError,
+    SerializeError, Tunables,
 };
 use std::any::Any;
 use std::fs;
@@ -12,7 +13,8 @@ use wasmer_types::{
     SignatureIndex, TableIndex,
 };
 use wasmer_vm::{
-    FunctionBodyPtr, InstanceHandle, MemoryStyle, ModuleInfo, TableStyle, VMSharedSignatureIndex,
+    FunctionBodyPtr, InstanceHandle, InstanceStorage, MemoryStyle, ModuleInfo, TableStyle,
+    VMSharedSignatureIndex,
 };
 
 /// An `Artifact` is the product that the `Engine`
@@ -50,6 +52,12 @@ pub trait Artifact: Send + Sync {
     /// Returns data initializers to pass to `InstanceHandle::initialize`
     fn data_initializers(&self) -> &[OwnedDataInitializer];
 
+    /// Returns the copy-on-write images of the memories of this `Artifact`,
+    /// if it keeps them to speed up the next instantiations.
+    fn memory_images(&self) -> Option<&MemoryImages> {
+        None
+    }
+
     /// Returns the functions allocated in memory or this `Artifact`
     /// ready to be run.
     fn finished_functions(&self) -> &BoxedSlice<LocalFunctionIndex, FunctionBodyPtr>;
@@ -61,6 +69,12 @@ pub trait Artifact: Send + Sync {
     /// Returns the associated VM signatures for this `Artifact`.
     fn signatures(&self) -> &BoxedSlice<SignatureIndex, VMSharedSignatureIndex>;
 
+    /// Returns the state the code of this `Artifact` needs at runtime, if
+    /// any, kept alive by its instances.
+    fn code_state(&self) -> Option<Arc<dyn Any + Send + Sync>> {
+        None
+    }
+
     /// Serializes an artifact into bytes
     fn serialize(&self) -> Result<Vec<u8>, SerializeError>;
 
@@ -98,6 +112,13 @@ pub trait Artifact: Send + Sync {
             self.table_styles(),
         )
         .map_err(InstantiationError::Link)?;
+        let storage =
+            InstanceStorage::allocate(&module, tunables.instance_allocator()).map_err(|e| {
+                InstantiationError::Link(LinkError::Resource(format!(
+                    "Failed to allocate the instance: {}",
+                    e
+                )))
+            })?;
         let finished_memories = tunables
             .create_memories(&module, self.memory_styles())
             .map_err(InstantiationError::Link)?
@@ -114,6 +135,7 @@ pub trait Artifact: Send + Sync {
         self.register_frame_info();
 
         InstanceHandle::new(
+            storage,
             module,
             self.finished_functions().clone(),
             finished_memories,
@@ -122,6 +144,8 @@ pub trait Artifact: Send + Sync {
             imports,
             self.signatures().clone(),
             host_state,
+            self.code_state(),
+            self.features().deterministic,
         )
         .map_err(|trap| InstantiationError::Start(RuntimeError::from_trap(trap)))
     }
@@ -144,8 +168,11 @@ pub trait Artifact: Send + Sync {
                 data: &*init.data,
             })
             .collect::<Vec<_>>();
+        let memory_images = self
+            .memory_images()
+            .map(|images| images.get(self.module_ref(), self.data_initializers()));
         handle
-            .finish_instantiation(is_bulk_memory, &data_initializers)
+            .finish_instantiation(is_bulk_memory, &data_initializers, memory_images.as_deref())
             .map_err(|trap| InstantiationError::Start(RuntimeError::from_trap(trap)))
     }
 }
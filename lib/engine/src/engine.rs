@@ -1,10 +1,11 @@
 //! JIT compilation.
 
 use crate::tunables::Tunables;
-use crate::{Artifact, DeserializeError};
+use crate::{Artifact, CompilationStats, DeserializeError};
 use std::path::Path;
 use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
 use std::sync::Arc;
+use std::time::Instant;
 use wasmer_compiler::{CompileError, Target};
 use wasmer_types::FunctionType;
 use wasmer_vm::{VMSharedSignatureIndex, VMTrampoline};
@@ -38,6 +39,39 @@ pub trait Engine {
         tunables: &dyn Tunables,
     ) -> Result<Arc<dyn Artifact>, CompileError>;
 
+    /// Compile a WebAssembly binary, recording statistics about the
+    /// compilation in `stats`.
+    ///
+    /// Engines not implementing this method only report the total time
+    /// of the compilation, as `compilation_time`.
+    fn compile_with_stats(
+        &self,
+        binary: &[u8],
+        tunables: &dyn Tunables,
+        stats: &mut CompilationStats,
+    ) -> Result<Arc<dyn Artifact>, CompileError> {
+        let start = Instant::now();
+        let artifact = self.compile(binary, tunables)?;
+        stats.compilation_time = start.elapsed();
+        let functions = artifact.finished_functions();
+        stats.functions = functions.len();
+        stats.code_size = functions.values().map(|body| body.0.len()).sum();
+        Ok(artifact)
+    }
+
+    /// Compile a WebAssembly binary lazily: its functions are only
+    /// compiled on their first call.
+    ///
+    /// Engines not implementing this method compile all the functions
+    /// up front, like [`Engine::compile`].
+    fn compile_lazily(
+        &self,
+        binary: &[u8],
+        tunables: &dyn Tunables,
+    ) -> Result<Arc<dyn Artifact>, CompileError> {
+        self.compile(binary, tunables)
+    }
+
     /// Deserializes a WebAssembly module
     ///
     /// # Safety
@@ -58,6 +92,20 @@ pub trait Engine {
         self.deserialize(&bytes)
     }
 
+    /// Deserializes the WebAssembly module compiled to native code and
+    /// statically linked into the current executable, as done by
+    /// `wasmer create-exe`.
+    ///
+    /// # Safety
+    ///
+    /// The module linked into the executable must be compiled by this
+    /// engine.
+    unsafe fn deserialize_from_executable(&self) -> Result<Arc<dyn Artifact>, DeserializeError> {
+        Err(DeserializeError::Generic(
+            "The engine can't load a module linked into the executable".to_string(),
+        ))
+    }
+
     /// A unique identifier for this object.
     ///
     /// This exists to allow us to compare two Engines for equality. Otherwise,
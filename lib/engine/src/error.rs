@@ -1,9 +1,11 @@
 //! The WebAssembly possible errors
 use crate::trap::RuntimeError;
+use std::fmt;
 use std::io;
 use thiserror::Error;
 use wasmer_compiler::CompileError;
 use wasmer_types::ExternType;
+use wasmer_vm::HostEnvInitError;
 
 /// The Serialize error can occur when serializing a
 /// compiled Module into a binary.
@@ -39,23 +41,100 @@ pub enum DeserializeError {
     Compiler(CompileError),
 }
 
+/// An import that couldn't be resolved.
+///
+/// Note: this error is not standard to WebAssembly, but it's
+/// useful to determine the import issue on the API side.
+#[derive(Debug, Clone)]
+pub struct ImportDiagnostic {
+    /// The module name of the import.
+    pub module: String,
+    /// The field name of the import.
+    pub name: String,
+    /// The type the importing module expects.
+    pub expected: ExternType,
+    /// The type of the definition provided for the import, if any.
+    pub provided: Option<ExternType>,
+}
+
+impl fmt::Display for ImportDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.provided {
+            Some(provided) => write!(
+                f,
+                "{}.{}: incompatible import type, expected {:?} but found {:?}",
+                self.module, self.name, self.expected, provided
+            ),
+            None => write!(
+                f,
+                "{}.{}: unknown import, expected {:?}",
+                self.module, self.name, self.expected
+            ),
+        }
+    }
+}
+
+impl ImportDiagnostic {
+    /// Returns the error of the import, as it used to be reported by
+    /// `LinkError::Import`.
+    #[deprecated(note = "Please use the `expected` and `provided` fields instead.")]
+    #[allow(deprecated)]
+    pub fn error(&self) -> ImportError {
+        match &self.provided {
+            Some(provided) => {
+                ImportError::IncompatibleType(self.expected.clone(), provided.clone())
+            }
+            None => ImportError::UnknownImport(self.expected.clone()),
+        }
+    }
+}
+
 /// An ImportError.
 ///
 /// Note: this error is not standard to WebAssembly, but it's
 /// useful to determine the import issue on the API side.
-#[derive(Error, Debug)]
+#[deprecated(note = "`LinkError::Imports` reports the unresolved imports as `ImportDiagnostic`s.")]
+#[derive(Debug)]
 pub enum ImportError {
     /// Incompatible Import Type.
     /// This error occurs when the import types mismatch.
-    #[error("incompatible import type. Expected {0:?} but received {1:?}")]
     IncompatibleType(ExternType, ExternType),
 
     /// Unknown Import.
     /// This error occurs when an import was expected but not provided.
-    #[error("unknown import. Expected {0:?}")]
     UnknownImport(ExternType),
 }
 
+#[allow(deprecated)]
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::IncompatibleType(expected, provided) => write!(
+                f,
+                "incompatible import type. Expected {:?} but received {:?}",
+                expected, provided
+            ),
+            Self::UnknownImport(expected) => write!(f, "unknown import. Expected {:?}", expected),
+        }
+    }
+}
+
+#[allow(deprecated)]
+impl std::error::Error for ImportError {}
+
+/// Displays the unresolved imports of a [`LinkError::Imports`], one per
+/// line.
+struct DisplayImports<'a>(&'a [ImportDiagnostic]);
+
+impl fmt::Display for DisplayImports<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for import in self.0 {
+            write!(f, "\n  {}", import)?;
+        }
+        Ok(())
+    }
+}
+
 /// The WebAssembly.LinkError object indicates an error during
 /// module instantiation (besides traps from the start function).
 ///
@@ -65,9 +144,10 @@ pub enum ImportError {
 #[derive(Error, Debug)]
 #[error("Link error: {0}")]
 pub enum LinkError {
-    /// An error occurred when checking the import types.
-    #[error("Error while importing {0:?}.{1:?}: {2}")]
-    Import(String, String, ImportError),
+    /// Some imports are missing or have an incompatible type. All of them
+    /// are listed.
+    #[error("{} unresolved import(s):{}", .0.len(), DisplayImports(.0))]
+    Imports(Vec<ImportDiagnostic>),
 
     /// A trap ocurred during linking.
     #[error("RuntimeError occurred during linking: {0}")]
@@ -94,4 +174,10 @@ pub enum InstantiationError {
     /// A runtime error occured while invoking the start function
     #[error(transparent)]
     Start(RuntimeError),
+
+    /// The environment of an imported host function could not be
+    /// initialized with the instance, usually because an export it
+    /// binds is missing.
+    #[error(transparent)]
+    HostEnvInitialization(HostEnvInitError),
 }
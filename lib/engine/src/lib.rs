@@ -23,21 +23,27 @@
 mod artifact;
 mod engine;
 mod error;
+mod memory_image;
 mod resolver;
 mod serialize;
+mod stats;
 mod trap;
 mod tunables;
 
 pub use crate::artifact::Artifact;
 pub use crate::engine::{Engine, EngineId};
+#[allow(deprecated)]
+pub use crate::error::ImportError;
 pub use crate::error::{
-    DeserializeError, ImportError, InstantiationError, LinkError, SerializeError,
+    DeserializeError, ImportDiagnostic, InstantiationError, LinkError, SerializeError,
 };
+pub use crate::memory_image::MemoryImages;
 pub use crate::resolver::{
     resolve_imports, ChainableNamedResolver, NamedResolver, NamedResolverChain, NullResolver,
     Resolver,
 };
 pub use crate::serialize::SerializableFunctionFrameInfo;
+pub use crate::stats::CompilationStats;
 pub use crate::trap::*;
 pub use crate::tunables::Tunables;
 
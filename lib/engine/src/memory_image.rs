@@ -0,0 +1,70 @@
+//! The copy-on-write images of the memories of a module.
+
+use std::sync::{Arc, Mutex};
+use wasmer_types::entity::{EntityRef, PrimaryMap};
+use wasmer_types::{LocalMemoryIndex, OwnedDataInitializer};
+use wasmer_vm::{MemoryImage, ModuleInfo};
+
+/// The image of each local memory, if it has one.
+type Images = PrimaryMap<LocalMemoryIndex, Option<MemoryImage>>;
+
+/// The images of the initial contents of the local memories of a module,
+/// built on its first instantiation and shared by the next ones.
+///
+/// A memory has an image only if all its data initializers have constant
+/// offsets and fit in its minimum size, so that applying them can't trap.
+/// The others are initialized by copying their data initializers.
+#[derive(Debug, Default)]
+pub struct MemoryImages {
+    images: Mutex<Option<Arc<Images>>>,
+}
+
+impl MemoryImages {
+    /// Returns the images of the memories of `module`, built from its
+    /// `data_initializers` the first time.
+    pub fn get(
+        &self,
+        module: &ModuleInfo,
+        data_initializers: &[OwnedDataInitializer],
+    ) -> Arc<Images> {
+        let mut images = self.images.lock().unwrap();
+        images
+            .get_or_insert_with(|| Arc::new(Self::build(module, data_initializers)))
+            .clone()
+    }
+
+    fn build(module: &ModuleInfo, data_initializers: &[OwnedDataInitializer]) -> Images {
+        (module.num_imported_memories..module.memories.len())
+            .map(|index| {
+                let memory_index = module
+                    .memory_index(LocalMemoryIndex::new(index - module.num_imported_memories));
+                let minimum = module.memories[memory_index].minimum.bytes().0;
+                let segments = data_initializers
+                    .iter()
+                    .filter(|init| init.location.memory_index == memory_index)
+                    .collect::<Vec<_>>();
+                let mut len = 0;
+                for init in &segments {
+                    if init.location.base.is_some() {
+                        return None;
+                    }
+                    let end = init.location.offset.checked_add(init.data.len())?;
+                    if end > minimum {
+                        return None;
+                    }
+                    len = len.max(end);
+                }
+                if len == 0 {
+                    return None;
+                }
+                MemoryImage::new(
+                    len,
+                    segments
+                        .iter()
+                        .map(|init| (init.location.offset, &*init.data)),
+                )
+                .ok()
+            })
+            .collect()
+    }
+}
@@ -1,14 +1,15 @@
 //! Define the `Resolver` trait, allowing custom resolution for external
 //! references.
 
-use crate::{ImportError, LinkError};
+use crate::{ImportDiagnostic, LinkError};
 use more_asserts::assert_ge;
 use wasmer_types::entity::{BoxedSlice, EntityRef, PrimaryMap};
 use wasmer_types::{ExternType, FunctionIndex, ImportIndex, MemoryIndex, TableIndex};
 
 use wasmer_vm::{
-    Export, FunctionBodyPtr, Imports, MemoryStyle, ModuleInfo, TableStyle, VMFunctionBody,
-    VMFunctionImport, VMFunctionKind, VMGlobalImport, VMMemoryImport, VMTableImport,
+    Export, FunctionBodyPtr, ImportFunctionEnvInitializer, Imports, MemoryStyle, ModuleInfo,
+    TableStyle, VMFunctionBody, VMFunctionImport, VMFunctionKind, VMGlobalImport, VMMemoryImport,
+    VMTableImport,
 };
 
 /// Import resolver connects imports with available exported values.
@@ -128,27 +129,40 @@ pub fn resolve_imports(
     let mut table_imports = PrimaryMap::with_capacity(module.num_imported_tables);
     let mut memory_imports = PrimaryMap::with_capacity(module.num_imported_memories);
     let mut global_imports = PrimaryMap::with_capacity(module.num_imported_globals);
+    let mut host_env_initializers = Vec::new();
+    let mut instances = Vec::new();
+    // The imports that can't be resolved. They are all collected, to be
+    // reported at once.
+    let mut unresolved = Vec::new();
 
     for ((module_name, field, import_idx), import_index) in module.imports.iter() {
         let resolved = resolver.resolve(*import_idx, module_name, field);
         let import_extern = get_extern_from_import(module, import_index);
         let resolved = match resolved {
             None => {
-                return Err(LinkError::Import(
-                    module_name.to_string(),
-                    field.to_string(),
-                    ImportError::UnknownImport(import_extern),
-                ));
+                unresolved.push(ImportDiagnostic {
+                    module: module_name.to_string(),
+                    name: field.to_string(),
+                    expected: import_extern,
+                    provided: None,
+                });
+                continue;
             }
             Some(r) => r,
         };
         let export_extern = get_extern_from_export(module, &resolved);
         if !export_extern.is_compatible_with(&import_extern) {
-            return Err(LinkError::Import(
-                module_name.to_string(),
-                field.to_string(),
-                ImportError::IncompatibleType(import_extern, export_extern),
-            ));
+            unresolved.push(ImportDiagnostic {
+                module: module_name.to_string(),
+                name: field.to_string(),
+                expected: import_extern,
+                provided: Some(export_extern),
+            });
+            continue;
+        }
+        if !unresolved.is_empty() {
+            // The imports are not going to be used.
+            continue;
         }
         match resolved {
             Export::Function(ref f) => {
@@ -169,12 +183,20 @@ pub fn resolve_imports(
                     body: address,
                     vmctx: f.vmctx,
                 });
+                if let Some(init) = f.host_env_init {
+                    host_env_initializers.push(ImportFunctionEnvInitializer {
+                        init,
+                        vmctx: f.vmctx,
+                    });
+                }
+                instances.extend(f.instance_ref.clone());
             }
             Export::Table(ref t) => {
                 table_imports.push(VMTableImport {
                     definition: t.from.vmtable(),
                     from: t.from.clone(),
                 });
+                instances.extend(t.instance_ref.clone());
             }
             Export::Memory(ref m) => {
                 match import_index {
@@ -220,11 +242,17 @@ pub fn resolve_imports(
         }
     }
 
+    if !unresolved.is_empty() {
+        return Err(LinkError::Imports(unresolved));
+    }
+
     Ok(Imports::new(
         function_imports,
         table_imports,
         memory_imports,
         global_imports,
+        host_env_initializers,
+        instances,
     ))
 }
 
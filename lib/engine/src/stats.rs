@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+/// Statistics about the compilation of a module.
+///
+/// They are collected by [`Engine::compile_with_stats`].
+///
+/// [`Engine::compile_with_stats`]: crate::Engine::compile_with_stats
+#[derive(Debug, Clone, Default)]
+pub struct CompilationStats {
+    /// The number of functions compiled, excluding the imported ones.
+    pub functions: usize,
+    /// The size of the generated machine code for the functions and
+    /// trampolines, in bytes.
+    pub code_size: usize,
+    /// The time spent validating the module.
+    pub validation_time: Duration,
+    /// The time spent parsing and translating the module.
+    pub translation_time: Duration,
+    /// The time spent compiling the functions and trampolines.
+    pub compilation_time: Duration,
+    /// The time spent turning the compiled code into an artifact ready to
+    /// be instantiated: linking and publishing the code for the JIT
+    /// engine, emitting and loading a shared object for the native one.
+    pub linking_time: Duration,
+}
+
+impl CompilationStats {
+    /// Returns the total time of the compilation, all phases included.
+    pub fn total_time(&self) -> Duration {
+        self.validation_time + self.translation_time + self.compilation_time + self.linking_time
+    }
+}
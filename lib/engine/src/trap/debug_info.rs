@@ -0,0 +1,150 @@
+//! Symbolication of WebAssembly frames with the DWARF debug information
+//! embedded in the custom sections of a module.
+//!
+//! DWARF addresses of a wasm module are offsets relative to the start of
+//! its code section contents. The debug information is only parsed the
+//! first time a frame of the module is symbolized, which only happens
+//! when the symbols of a frame are requested.
+use addr2line::Context;
+use gimli::{EndianArcSlice, LittleEndian};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use wasmer_vm::ModuleInfo;
+
+type DwarfReader = EndianArcSlice<LittleEndian>;
+
+/// The DWARF debug information of a module.
+pub(crate) struct ModuleDebugInfo {
+    module: Arc<ModuleInfo>,
+    state: Mutex<DebugInfoState>,
+}
+
+enum DebugInfoState {
+    /// The custom sections haven't been looked at yet.
+    Unparsed,
+    /// The debug information was parsed successfully.
+    Parsed(Box<Context<DwarfReader>>),
+    /// The module has no (valid) debug information.
+    Unavailable,
+}
+
+impl ModuleDebugInfo {
+    pub(crate) fn new(module: Arc<ModuleInfo>) -> Self {
+        Self {
+            module,
+            state: Mutex::new(DebugInfoState::Unparsed),
+        }
+    }
+
+    /// Returns the symbols of the instruction at `module_offset`, from
+    /// the innermost inlined function to the function containing it.
+    ///
+    /// The result is empty when the module has no debug information for
+    /// this instruction.
+    pub(crate) fn symbolize(&self, module_offset: usize) -> Vec<FrameSymbol> {
+        let module = &self.module;
+        let mut state = self.state.lock().unwrap();
+        if let DebugInfoState::Unparsed = *state {
+            *state = match parse_dwarf(module) {
+                Some(context) => DebugInfoState::Parsed(Box::new(context)),
+                None => DebugInfoState::Unavailable,
+            };
+        }
+        let context = match &*state {
+            DebugInfoState::Parsed(context) => context,
+            _ => return Vec::new(),
+        };
+        let address = match module_offset.checked_sub(module.code_section_offset) {
+            Some(address) => address as u64,
+            None => return Vec::new(),
+        };
+        let mut frames = match context.find_frames(address) {
+            Ok(frames) => frames,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut symbols = Vec::new();
+        while let Ok(Some(frame)) = frames.next() {
+            let name = frame
+                .function
+                .and_then(|function| function.demangle().ok().map(|name| name.into_owned()));
+            let (file, line, column) = match frame.location {
+                Some(location) => (
+                    location.file.map(String::from),
+                    location.line,
+                    location.column,
+                ),
+                None => (None, None, None),
+            };
+            symbols.push(FrameSymbol {
+                name,
+                file,
+                line,
+                column,
+            });
+        }
+        symbols
+    }
+}
+
+impl fmt::Debug for ModuleDebugInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ModuleDebugInfo")
+            .field("module", &self.module.name())
+            .finish()
+    }
+}
+
+/// Loads the DWARF sections of `module`, or returns `None` if it has no
+/// debug information.
+fn parse_dwarf(module: &ModuleInfo) -> Option<Context<DwarfReader>> {
+    module.custom_sections(".debug_info").next()?;
+    let empty: Arc<[u8]> = Arc::from(&[][..]);
+    let dwarf = gimli::Dwarf::load::<_, _, gimli::Error>(
+        |id| {
+            let data = module
+                .custom_sections(id.name())
+                .next()
+                .unwrap_or_else(|| empty.clone());
+            Ok(EndianArcSlice::new(data, LittleEndian))
+        },
+        |_| Ok(EndianArcSlice::new(empty.clone(), LittleEndian)),
+    )
+    .ok()?;
+    Context::from_dwarf(dwarf).ok()
+}
+
+/// Debug information of a frame, read from the DWARF sections of the
+/// module.
+///
+/// A single WebAssembly frame may have several symbols when functions
+/// were inlined into it.
+#[derive(Debug, Clone)]
+pub struct FrameSymbol {
+    name: Option<String>,
+    file: Option<String>,
+    line: Option<u32>,
+    column: Option<u32>,
+}
+
+impl FrameSymbol {
+    /// Returns the (demangled) name of the function, if known.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the source file, if known.
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    /// Returns the line in the source file, if known.
+    pub fn line(&self) -> Option<u32> {
+        self.line
+    }
+
+    /// Returns the column in the source line, if known.
+    pub fn column(&self) -> Option<u32> {
+        self.column
+    }
+}
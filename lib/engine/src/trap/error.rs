@@ -226,37 +226,82 @@ impl fmt::Debug for RuntimeError {
     }
 }
 
+/// Prints the message followed by the WebAssembly backtrace, formatted
+/// like a Rust backtrace:
+///
+/// ```text
+/// RuntimeError: unreachable
+///    0: inner
+///              at src/lib.rs:3:5
+///       outer
+///              at src/lib.rs:8:5
+///    1: <unnamed>
+///              at module[1]:0x27
+/// ```
+///
+/// Frames are symbolized with the DWARF debug information of the module
+/// when available, listing inlined functions first. Otherwise, the
+/// function name comes from the `name` section, and the location is the
+/// offset of the instruction in the module.
 impl fmt::Display for RuntimeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "RuntimeError: {}", self.message())?;
-        let trace = self.trace();
-        if trace.is_empty() {
-            return Ok(());
-        }
-        for frame in self.trace().iter() {
-            let name = frame.module_name();
-            let func_index = frame.func_index();
+        for (i, frame) in self.trace().iter().enumerate() {
             writeln!(f)?;
-            write!(f, "    at ")?;
-            match frame.function_name() {
-                Some(name) => match rustc_demangle::try_demangle(name) {
-                    Ok(name) => write!(f, "{}", name)?,
-                    Err(_) => write!(f, "{}", name)?,
-                },
-                None => write!(f, "<unnamed>")?,
+            write!(f, "{:4}: ", i)?;
+            if frame.symbols().is_empty() {
+                write_function_name(f, frame.function_name())?;
+                write!(
+                    f,
+                    "\n             at {}[{}]:0x{:x}",
+                    frame.module_name(),
+                    frame.func_index(),
+                    frame.module_offset()
+                )?;
+                continue;
+            }
+            let outermost = frame.symbols().len() - 1;
+            for (j, symbol) in frame.symbols().iter().enumerate() {
+                if j > 0 {
+                    write!(f, "\n      ")?;
+                }
+                // The name section is a better fallback for the function
+                // containing the instruction than for inlined ones.
+                let name = match symbol.name() {
+                    Some(name) => Some(name),
+                    None if j == outermost => frame.function_name(),
+                    None => None,
+                };
+                write_function_name(f, name)?;
+                write!(f, "\n             at ")?;
+                match symbol.file() {
+                    Some(file) => write!(f, "{}", file)?,
+                    None => write!(f, "{}[{}]", frame.module_name(), frame.func_index())?,
+                }
+                match (symbol.line(), symbol.column()) {
+                    (Some(line), Some(column)) if column > 0 => write!(f, ":{}:{}", line, column)?,
+                    (Some(line), _) => write!(f, ":{}", line)?,
+                    (None, _) if symbol.file().is_none() => {
+                        write!(f, ":0x{:x}", frame.module_offset())?
+                    }
+                    (None, _) => {}
+                }
             }
-            write!(
-                f,
-                " ({}[{}]:0x{:x})",
-                name,
-                func_index,
-                frame.module_offset()
-            )?;
         }
         Ok(())
     }
 }
 
+fn write_function_name(f: &mut fmt::Formatter<'_>, name: Option<&str>) -> fmt::Result {
+    match name {
+        Some(name) => match rustc_demangle::try_demangle(name) {
+            Ok(name) => write!(f, "{}", name),
+            Err(_) => write!(f, "{}", name),
+        },
+        None => write!(f, "<unnamed>"),
+    }
+}
+
 impl std::error::Error for RuntimeError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match &self.inner.source {
@@ -10,9 +10,12 @@
 //! let module: ModuleInfo = ...;
 //! FRAME_INFO.register(module, compiled_functions);
 //! ```
+use super::debug_info::{FrameSymbol, ModuleDebugInfo};
 use crate::serialize::SerializableFunctionFrameInfo;
+use once_cell::sync::OnceCell;
 use std::cmp;
 use std::collections::BTreeMap;
+use std::iter;
 use std::sync::{Arc, RwLock};
 use wasmer_compiler::{CompiledFunctionFrameInfo, SourceLoc, TrapInformation};
 use wasmer_types::entity::{BoxedSlice, EntityRef, PrimaryMap};
@@ -54,19 +57,30 @@ struct ModuleInfoFrameInfo {
     start: usize,
     functions: BTreeMap<usize, FunctionInfo>,
     module: Arc<ModuleInfo>,
+    /// The frame information of the functions, starting with the
+    /// function at `first_function`.
     frame_infos: PrimaryMap<LocalFunctionIndex, SerializableFunctionFrameInfo>,
+    first_function: usize,
+    /// The DWARF debug information, parsed lazily the first time the
+    /// symbols of a frame of this module are requested.
+    debug_info: Arc<ModuleDebugInfo>,
 }
 
 impl ModuleInfoFrameInfo {
+    fn frame_info_index(&self, local_index: LocalFunctionIndex) -> LocalFunctionIndex {
+        LocalFunctionIndex::new(local_index.index() - self.first_function)
+    }
+
     fn function_debug_info(
         &self,
         local_index: LocalFunctionIndex,
     ) -> &SerializableFunctionFrameInfo {
-        &self.frame_infos.get(local_index).unwrap()
+        &self.frame_infos[self.frame_info_index(local_index)]
     }
 
     fn process_function_debug_info(&mut self, local_index: LocalFunctionIndex) {
-        let func = self.frame_infos.get_mut(local_index).unwrap();
+        let index = self.frame_info_index(local_index);
+        let func = self.frame_infos.get_mut(index).unwrap();
         let processed: CompiledFunctionFrameInfo = match func {
             SerializableFunctionFrameInfo::Processed(_) => {
                 // This should be a no-op on processed info
@@ -155,12 +169,21 @@ impl GlobalFrameInfo {
             None => instr_map.start_srcloc,
         };
         let func_index = module.module.func_index(func.local_index);
+        // The frame is only symbolized when its symbols are requested,
+        // so that capturing a backtrace doesn't parse the DWARF sections.
+        let debug_info = if instr.is_default() {
+            None
+        } else {
+            Some(module.debug_info.clone())
+        };
         Some(FrameInfo {
             module_name: module.module.name(),
             func_index: func_index.index() as u32,
             function_name: module.module.function_names.get(&func_index).cloned(),
             instr,
             func_start: instr_map.start_srcloc,
+            debug_info,
+            symbols: OnceCell::new(),
         })
     }
 
@@ -229,11 +252,41 @@ pub fn register(
     module: Arc<ModuleInfo>,
     finished_functions: &BoxedSlice<LocalFunctionIndex, FunctionBodyPtr>,
     frame_infos: PrimaryMap<LocalFunctionIndex, SerializableFunctionFrameInfo>,
+) -> Option<GlobalFrameInfoRegistration> {
+    register_functions(module, finished_functions.iter(), frame_infos, 0)
+}
+
+/// Registers the frame information of a single function of a module,
+/// compiled apart from the other functions.
+///
+/// The returned object, when dropped, will be used to unregister the
+/// information of the function.
+pub fn register_function(
+    module: Arc<ModuleInfo>,
+    local_index: LocalFunctionIndex,
+    function: &FunctionBodyPtr,
+    frame_info: SerializableFunctionFrameInfo,
+) -> Option<GlobalFrameInfoRegistration> {
+    let mut frame_infos = PrimaryMap::with_capacity(1);
+    frame_infos.push(frame_info);
+    register_functions(
+        module,
+        iter::once((local_index, function)),
+        frame_infos,
+        local_index.index(),
+    )
+}
+
+fn register_functions<'a>(
+    module: Arc<ModuleInfo>,
+    finished_functions: impl Iterator<Item = (LocalFunctionIndex, &'a FunctionBodyPtr)>,
+    frame_infos: PrimaryMap<LocalFunctionIndex, SerializableFunctionFrameInfo>,
+    first_function: usize,
 ) -> Option<GlobalFrameInfoRegistration> {
     let mut min = usize::max_value();
     let mut max = 0;
     let mut functions = BTreeMap::new();
-    for (i, allocated) in finished_functions.iter() {
+    for (i, allocated) in finished_functions {
         let (start, end) = unsafe {
             let ptr = (***allocated).as_ptr();
             let len = (***allocated).len();
@@ -267,8 +320,10 @@ pub fn register(
         ModuleInfoFrameInfo {
             start: min,
             functions,
+            debug_info: Arc::new(ModuleDebugInfo::new(module.clone())),
             module,
             frame_infos,
+            first_function,
         },
     );
     assert!(prev.is_none());
@@ -289,6 +344,8 @@ pub struct FrameInfo {
     function_name: Option<String>,
     func_start: SourceLoc,
     instr: SourceLoc,
+    debug_info: Option<Arc<ModuleDebugInfo>>,
+    symbols: OnceCell<Vec<FrameSymbol>>,
 }
 
 impl FrameInfo {
@@ -349,4 +406,20 @@ impl FrameInfo {
     pub fn func_offset(&self) -> usize {
         (self.instr.bits() - self.func_start.bits()) as usize
     }
+
+    /// Returns the source-level symbols of this frame, read from the DWARF
+    /// debug information of the module.
+    ///
+    /// Symbols are ordered from the innermost inlined function to the
+    /// function containing the instruction. The returned slice is empty
+    /// when the module has no debug information for this frame.
+    ///
+    /// The frame is symbolized the first time this is called, and the
+    /// result is cached.
+    pub fn symbols(&self) -> &[FrameSymbol] {
+        self.symbols.get_or_init(|| match &self.debug_info {
+            Some(debug_info) => debug_info.symbolize(self.module_offset()),
+            None => Vec::new(),
+        })
+    }
 }
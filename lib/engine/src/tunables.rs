@@ -6,7 +6,7 @@ use wasmer_types::{
     TableIndex, TableType,
 };
 use wasmer_vm::MemoryError;
-use wasmer_vm::{Global, Memory, ModuleInfo, Table};
+use wasmer_vm::{Global, InstanceAllocator, Memory, ModuleInfo, Table};
 use wasmer_vm::{MemoryStyle, TableStyle};
 
 /// An engine delegates the creation of memories, tables, and globals
@@ -28,6 +28,12 @@ pub trait Tunables {
     /// Create a memory given a memory type
     fn create_table(&self, ty: &TableType, style: &TableStyle) -> Result<Arc<dyn Table>, String>;
 
+    /// Returns the allocator of the storage of the instances, or `None` to
+    /// allocate them on the heap.
+    fn instance_allocator(&self) -> Option<Arc<dyn InstanceAllocator>> {
+        None
+    }
+
     /// Create a global with an unset value.
     fn create_global(&self, ty: GlobalType) -> Result<Arc<Global>, String> {
         Ok(Arc::new(Global::new(ty)))
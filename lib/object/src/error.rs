@@ -11,6 +11,9 @@ pub enum ObjectError {
     /// The object was provided a not-supported architecture
     #[error("Architecture {0} not supported")]
     UnsupportedArchitecture(String),
+    /// The object was provided a relocation it can't write
+    #[error("Relocation {0} not supported")]
+    UnsupportedRelocation(String),
     /// The object was provided an unknown endianness
     #[error("Unknown Endianness")]
     UnknownEndianness,
@@ -302,6 +302,11 @@ pub fn emit_compilation(
                 RelocationTarget::JumpTable(_func_index, _jt) => {
                     // do nothing
                 }
+                RelocationTarget::ExecutionCounter(_func_index) => {
+                    return Err(ObjectError::UnsupportedRelocation(
+                        "to an execution counter".to_string(),
+                    ));
+                }
             };
         }
     }
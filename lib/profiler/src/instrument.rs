@@ -0,0 +1,559 @@
+//! The instrumentation pass, rewriting a WebAssembly module so every call
+//! to one of its functions, including the imported ones, goes through a
+//! wrapper notifying the profiler.
+//!
+//! For a function `f` with index `k`, the wrapper is:
+//!
+//! ```wat
+//! (func $f.profiled (type $f_type)
+//!   (call $__wasmer_profiler.enter (i32.const k))
+//!   (call $f (local.get 0) ... (local.get n))
+//!   (call $__wasmer_profiler.exit (i32.const k)))
+//! ```
+//!
+//! Every reference to `f` (calls, exports, table elements, `ref.func`
+//! and the start function) is redirected to its wrapper. The instrumented
+//! module has two more imports than the original one, shifting the index
+//! of its local functions by two; the `name` section is updated to match.
+//! The DWARF sections are dropped, since they no longer describe the code.
+use std::ops::Range;
+use thiserror::Error;
+use wasmparser::{
+    BinaryReader, BinaryReaderError, ElementItem, ExternalKind, ImportSectionEntryType,
+    ModuleReader, Operator, Section, SectionCode,
+};
+
+/// The namespace of the imports added by the instrumentation.
+pub const PROFILER_NAMESPACE: &str = "__wasmer_profiler";
+
+/// The name of the import called when a function is entered.
+pub const ENTER_FUNCTION: &str = "enter";
+
+/// The name of the import called when a function returns.
+pub const EXIT_FUNCTION: &str = "exit";
+
+/// An error while instrumenting a module.
+#[derive(Error, Debug)]
+pub enum InstrumentationError {
+    /// The module couldn't be parsed.
+    #[error("invalid module: {0}")]
+    Invalid(#[from] BinaryReaderError),
+}
+
+type Result<T> = std::result::Result<T, InstrumentationError>;
+
+/// An instrumented module.
+pub struct Instrumented {
+    /// The bytes of the instrumented module.
+    pub wasm: Vec<u8>,
+    /// The number of functions imported by the original module.
+    pub num_imported_functions: u32,
+    /// The number of functions of the original module, imported ones
+    /// included. The profiler ids of its functions are
+    /// `first_id..first_id + num_functions`.
+    pub num_functions: u32,
+}
+
+/// Instruments `wasm`, reporting the function with index `k` to the
+/// profiler with the id `first_id + k`.
+pub fn instrument(wasm: &[u8], first_id: u32) -> Result<Instrumented> {
+    let module = ModuleLayout::parse(wasm)?;
+    let instrumented = Instrumenter {
+        wasm,
+        module: &module,
+        first_id,
+    }
+    .emit()?;
+    Ok(Instrumented {
+        wasm: instrumented,
+        num_imported_functions: module.num_imported_functions(),
+        num_functions: module.function_types.len() as u32,
+    })
+}
+
+/// The sections of a module, and the types of its functions.
+struct ModuleLayout<'a> {
+    sections: Vec<Section<'a>>,
+    /// The number of parameters of each type.
+    type_params: Vec<u32>,
+    /// The type index of each function, imported ones first.
+    function_types: Vec<u32>,
+    imported_functions: u32,
+}
+
+impl<'a> ModuleLayout<'a> {
+    fn parse(wasm: &'a [u8]) -> Result<Self> {
+        let mut reader = ModuleReader::new(wasm)?;
+        let mut layout = Self {
+            sections: Vec::new(),
+            type_params: Vec::new(),
+            function_types: Vec::new(),
+            imported_functions: 0,
+        };
+        while !reader.eof() {
+            let section = reader.read()?;
+            match section.code {
+                SectionCode::Type => {
+                    let mut types = section.get_type_section_reader()?;
+                    for _ in 0..types.get_count() {
+                        layout.type_params.push(types.read()?.params.len() as u32);
+                    }
+                }
+                SectionCode::Import => {
+                    let mut imports = section.get_import_section_reader()?;
+                    for _ in 0..imports.get_count() {
+                        if let ImportSectionEntryType::Function(ty) = imports.read()?.ty {
+                            layout.function_types.push(ty);
+                            layout.imported_functions += 1;
+                        }
+                    }
+                }
+                SectionCode::Function => {
+                    let mut functions = section.get_function_section_reader()?;
+                    for _ in 0..functions.get_count() {
+                        layout.function_types.push(functions.read()?);
+                    }
+                }
+                _ => {}
+            }
+            layout.sections.push(section);
+        }
+        Ok(layout)
+    }
+
+    fn num_imported_functions(&self) -> u32 {
+        self.imported_functions
+    }
+
+    fn num_local_functions(&self) -> u32 {
+        self.function_types.len() as u32 - self.imported_functions
+    }
+
+    fn section(&self, code: SectionCode) -> Option<&Section<'a>> {
+        self.sections.iter().find(|section| section.code == code)
+    }
+}
+
+struct Instrumenter<'a> {
+    wasm: &'a [u8],
+    module: &'a ModuleLayout<'a>,
+    first_id: u32,
+}
+
+/// The sections the instrumentation appends entries to, created when
+/// missing from the original module.
+const EXTENDED_SECTIONS: [SectionCode<'static>; 4] = [
+    SectionCode::Type,
+    SectionCode::Import,
+    SectionCode::Function,
+    SectionCode::Code,
+];
+
+impl<'a> Instrumenter<'a> {
+    /// The index of the function `index` of the original module in the
+    /// instrumented one.
+    fn function_index(&self, index: u32) -> u32 {
+        if index < self.module.num_imported_functions() {
+            index
+        } else {
+            index + 2
+        }
+    }
+
+    /// The index of the wrapper of the function `index` of the original
+    /// module.
+    fn wrapper_index(&self, index: u32) -> u32 {
+        self.module.num_imported_functions() + 2 + self.module.num_local_functions() + index
+    }
+
+    fn enter_index(&self) -> u32 {
+        self.module.num_imported_functions()
+    }
+
+    fn exit_index(&self) -> u32 {
+        self.module.num_imported_functions() + 1
+    }
+
+    /// The index of the `(func (param i32))` type of the hooks.
+    fn hook_type_index(&self) -> u32 {
+        self.module.type_params.len() as u32
+    }
+
+    fn emit(&self) -> Result<Vec<u8>> {
+        let mut out = self.wasm[..8].to_vec();
+        let mut extended = EXTENDED_SECTIONS
+            .iter()
+            .filter(|code| self.module.section(**code).is_none())
+            .peekable();
+        for section in &self.module.sections {
+            let position = section_order(&section.code);
+            if let Some(position) = position {
+                // Create the missing sections preceding this one.
+                while let Some(code) = extended.next_if(|code| section_order(code) < Some(position))
+                {
+                    self.emit_section(&mut out, code, None)?;
+                }
+            }
+            self.emit_section(&mut out, &section.code, Some(section))?;
+        }
+        for code in extended {
+            self.emit_section(&mut out, code, None)?;
+        }
+        Ok(out)
+    }
+
+    /// Emits `section`, or the empty section of the given kind if `None`.
+    fn emit_section(
+        &self,
+        out: &mut Vec<u8>,
+        code: &SectionCode,
+        section: Option<&Section>,
+    ) -> Result<()> {
+        let content = match code {
+            SectionCode::Type => self.type_section(section)?,
+            SectionCode::Import => self.import_section(section)?,
+            SectionCode::Function => self.function_section(section)?,
+            SectionCode::Code => self.code_section(section)?,
+            SectionCode::Export => self.export_section(section.unwrap())?,
+            SectionCode::Start => {
+                let start = section.unwrap().get_start_section_content()?;
+                encode_u32(self.wrapper_index(start))
+            }
+            SectionCode::Element => self.element_section(section.unwrap())?,
+            SectionCode::Global => self.global_section(section.unwrap())?,
+            SectionCode::Custom { name, .. } if name.starts_with(".debug_") => return Ok(()),
+            // The payload of custom sections starts after their name.
+            SectionCode::Custom { name, .. } => {
+                let mut content = encode_str(name);
+                if *name == "name" {
+                    content.extend(self.name_section(section.unwrap())?);
+                } else {
+                    content.extend(&self.wasm[range(section.unwrap())]);
+                }
+                content
+            }
+            _ => self.wasm[range(section.unwrap())].to_vec(),
+        };
+        out.push(section_id(code));
+        out.extend(encode_u32(content.len() as u32));
+        out.extend(content);
+        Ok(())
+    }
+
+    fn type_section(&self, section: Option<&Section>) -> Result<Vec<u8>> {
+        let mut content = Vec::new();
+        let mut entries = self.entries(section, |section| {
+            let reader = section.get_type_section_reader()?;
+            Ok((reader.get_count(), reader.original_position()))
+        })?;
+        content.extend(encode_u32(entries.0 + 1));
+        content.append(&mut entries.1);
+        // The type of the hooks: `(func (param i32))`.
+        content.extend(&[0x60, 0x01, 0x7f, 0x00]);
+        Ok(content)
+    }
+
+    fn import_section(&self, section: Option<&Section>) -> Result<Vec<u8>> {
+        let mut content = Vec::new();
+        let mut entries = self.entries(section, |section| {
+            let reader = section.get_import_section_reader()?;
+            Ok((reader.get_count(), reader.original_position()))
+        })?;
+        content.extend(encode_u32(entries.0 + 2));
+        content.append(&mut entries.1);
+        for name in &[ENTER_FUNCTION, EXIT_FUNCTION] {
+            content.extend(encode_str(PROFILER_NAMESPACE));
+            content.extend(encode_str(name));
+            content.push(0x00);
+            content.extend(encode_u32(self.hook_type_index()));
+        }
+        Ok(content)
+    }
+
+    fn function_section(&self, section: Option<&Section>) -> Result<Vec<u8>> {
+        let mut content = Vec::new();
+        let mut entries = self.entries(section, |section| {
+            let reader = section.get_function_section_reader()?;
+            Ok((reader.get_count(), reader.original_position()))
+        })?;
+        let wrappers = &self.module.function_types;
+        content.extend(encode_u32(entries.0 + wrappers.len() as u32));
+        content.append(&mut entries.1);
+        for ty in wrappers {
+            content.extend(encode_u32(*ty));
+        }
+        Ok(content)
+    }
+
+    /// Returns the entry count of `section`, and the bytes of its entries.
+    fn entries(
+        &self,
+        section: Option<&Section>,
+        reader: impl Fn(&Section) -> Result<(u32, usize)>,
+    ) -> Result<(u32, Vec<u8>)> {
+        match section {
+            Some(section) => {
+                let (count, start) = reader(section)?;
+                Ok((count, self.wasm[start..range(section).end].to_vec()))
+            }
+            None => Ok((0, Vec::new())),
+        }
+    }
+
+    fn code_section(&self, section: Option<&Section>) -> Result<Vec<u8>> {
+        let mut bodies = Vec::new();
+        if let Some(section) = section {
+            let mut reader = section.get_code_section_reader()?;
+            for _ in 0..reader.get_count() {
+                let body = reader.read()?;
+                let mut operators = body.get_operators_reader()?;
+                let mut patches = Patches::default();
+                while !operators.eof() {
+                    let (operator, offset) = operators.read_with_offset()?;
+                    match operator {
+                        Operator::Call { function_index }
+                        | Operator::ReturnCall { function_index }
+                        | Operator::RefFunc { function_index } => {
+                            // The index follows the one-byte opcode.
+                            patches.push(
+                                offset + 1..operators.original_position(),
+                                encode_u32(self.wrapper_index(function_index)),
+                            );
+                        }
+                        _ => {}
+                    }
+                }
+                bodies.push(patches.apply(self.wasm, byte_range(body.range())));
+            }
+        }
+        for (index, ty) in self.module.function_types.iter().enumerate() {
+            bodies.push(self.wrapper_body(index as u32, *ty));
+        }
+
+        let mut content = encode_u32(bodies.len() as u32);
+        for body in bodies {
+            content.extend(encode_u32(body.len() as u32));
+            content.extend(body);
+        }
+        Ok(content)
+    }
+
+    fn wrapper_body(&self, index: u32, ty: u32) -> Vec<u8> {
+        let id = self.first_id + index;
+        // No locals.
+        let mut body = vec![0x00];
+        let hook = |body: &mut Vec<u8>, hook_index| {
+            body.push(0x41);
+            body.extend(encode_i32(id as i32));
+            body.push(0x10);
+            body.extend(encode_u32(hook_index));
+        };
+        hook(&mut body, self.enter_index());
+        for param in 0..self.module.type_params[ty as usize] {
+            body.push(0x20);
+            body.extend(encode_u32(param));
+        }
+        body.push(0x10);
+        body.extend(encode_u32(self.function_index(index)));
+        hook(&mut body, self.exit_index());
+        body.push(0x0b);
+        body
+    }
+
+    fn export_section(&self, section: &Section) -> Result<Vec<u8>> {
+        let mut reader = section.get_export_section_reader()?;
+        let mut content = encode_u32(reader.get_count());
+        for _ in 0..reader.get_count() {
+            let export = reader.read()?;
+            let (kind, index) = match export.kind {
+                ExternalKind::Function => (0x00, self.wrapper_index(export.index)),
+                ExternalKind::Table => (0x01, export.index),
+                ExternalKind::Memory => (0x02, export.index),
+                ExternalKind::Global => (0x03, export.index),
+            };
+            content.extend(encode_str(export.field));
+            content.push(kind as u8);
+            content.extend(encode_u32(index));
+        }
+        Ok(content)
+    }
+
+    fn element_section(&self, section: &Section) -> Result<Vec<u8>> {
+        let mut reader = section.get_element_section_reader()?;
+        let mut patches = Patches::default();
+        for _ in 0..reader.get_count() {
+            let element = reader.read()?;
+            let mut items = element.items.get_items_reader()?;
+            let exprs = items.uses_exprs();
+            for _ in 0..items.get_count() {
+                let start = items.original_position();
+                if let ElementItem::Func(index) = items.read()? {
+                    let index = encode_u32(self.wrapper_index(index));
+                    if exprs {
+                        // `ref.func $index end`
+                        patches.push(start + 1..items.original_position() - 1, index);
+                    } else {
+                        patches.push(start..items.original_position(), index);
+                    }
+                }
+            }
+        }
+        Ok(patches.apply(self.wasm, range(section)))
+    }
+
+    fn global_section(&self, section: &Section) -> Result<Vec<u8>> {
+        let mut reader = section.get_global_section_reader()?;
+        let mut patches = Patches::default();
+        for _ in 0..reader.get_count() {
+            let global = reader.read()?;
+            let mut operators = global.init_expr.get_operators_reader();
+            while !operators.eof() {
+                let (operator, offset) = operators.read_with_offset()?;
+                if let Operator::RefFunc { function_index } = operator {
+                    patches.push(
+                        offset + 1..operators.original_position(),
+                        encode_u32(self.wrapper_index(function_index)),
+                    );
+                }
+            }
+        }
+        Ok(patches.apply(self.wasm, range(section)))
+    }
+
+    /// Rewrites the `name` section with the new function indices. The
+    /// local names are dropped.
+    fn name_section(&self, section: &Section) -> Result<Vec<u8>> {
+        let mut reader = section.get_binary_reader();
+        let mut content = Vec::new();
+        while !reader.eof() {
+            let kind = reader.read_u8()?;
+            let size = reader.read_var_u32()?;
+            let start = reader.original_position();
+            let subsection = reader.read_bytes(size as usize)?;
+            let subsection = match kind {
+                // Module name
+                0 => subsection.to_vec(),
+                // Function names
+                1 => {
+                    let mut names = BinaryReader::new_with_offset(subsection, start);
+                    let count = names.read_var_u32()?;
+                    let mut map = encode_u32(count);
+                    for _ in 0..count {
+                        let index = names.read_var_u32()?;
+                        map.extend(encode_u32(self.function_index(index)));
+                        map.extend(encode_str(names.read_string()?));
+                    }
+                    map
+                }
+                _ => continue,
+            };
+            content.push(kind as u8);
+            content.extend(encode_u32(subsection.len() as u32));
+            content.extend(subsection);
+        }
+        Ok(content)
+    }
+}
+
+/// Replacements of byte ranges of the original module.
+#[derive(Default)]
+struct Patches {
+    patches: Vec<(Range<usize>, Vec<u8>)>,
+}
+
+impl Patches {
+    fn push(&mut self, range: Range<usize>, bytes: Vec<u8>) {
+        self.patches.push((range, bytes));
+    }
+
+    /// Returns the bytes of `wasm[range]`, patched. The patches must be
+    /// sorted and within `range`.
+    fn apply(self, wasm: &[u8], range: Range<usize>) -> Vec<u8> {
+        let mut out = Vec::with_capacity(range.len());
+        let mut position = range.start;
+        for (patch, bytes) in self.patches {
+            out.extend(&wasm[position..patch.start]);
+            out.extend(bytes);
+            position = patch.end;
+        }
+        out.extend(&wasm[position..range.end]);
+        out
+    }
+}
+
+fn range(section: &Section) -> Range<usize> {
+    byte_range(section.range())
+}
+
+fn byte_range(range: wasmparser::Range) -> Range<usize> {
+    range.start..range.end
+}
+
+/// The position of the non-custom sections in a module.
+fn section_order(code: &SectionCode) -> Option<u8> {
+    Some(match code {
+        SectionCode::Custom { .. } => return None,
+        SectionCode::Type => 1,
+        SectionCode::Import => 2,
+        SectionCode::Function => 3,
+        SectionCode::Table => 4,
+        SectionCode::Memory => 5,
+        SectionCode::Global => 6,
+        SectionCode::Export => 7,
+        SectionCode::Start => 8,
+        SectionCode::Element => 9,
+        SectionCode::DataCount => 10,
+        SectionCode::Code => 11,
+        SectionCode::Data => 12,
+    })
+}
+
+fn section_id(code: &SectionCode) -> u8 {
+    match code {
+        SectionCode::Custom { .. } => 0,
+        SectionCode::Type => 1,
+        SectionCode::Import => 2,
+        SectionCode::Function => 3,
+        SectionCode::Table => 4,
+        SectionCode::Memory => 5,
+        SectionCode::Global => 6,
+        SectionCode::Export => 7,
+        SectionCode::Start => 8,
+        SectionCode::Element => 9,
+        SectionCode::Code => 10,
+        SectionCode::Data => 11,
+        SectionCode::DataCount => 12,
+    }
+}
+
+fn encode_u32(mut value: u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn encode_i32(mut value: i32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn encode_str(value: &str) -> Vec<u8> {
+    let mut bytes = encode_u32(value.len() as u32);
+    bytes.extend(value.as_bytes());
+    bytes
+}
@@ -0,0 +1,37 @@
+//! The `wasmer-profiler` crate measures where the time of a WebAssembly
+//! program goes, per function and per host import.
+//!
+//! The modules are instrumented before being compiled, wrapping each of
+//! their functions with calls to the profiler: this works with every
+//! compiler and engine. The profile can be exported in the folded format
+//! consumed by flamegraph tools, and the code regions of a module can be
+//! written to a perf map so `perf` can symbolize the JIT-compiled code.
+
+#![deny(missing_docs, trivial_numeric_casts, unused_extern_crates)]
+#![warn(unused_import_braces)]
+#![cfg_attr(feature = "std", deny(unstable_features))]
+#![cfg_attr(feature = "cargo-clippy", allow(clippy::new_without_default))]
+#![cfg_attr(
+    feature = "cargo-clippy",
+    warn(
+        clippy::float_arithmetic,
+        clippy::mut_mut,
+        clippy::nonminimal_bool,
+        clippy::option_map_unwrap_or,
+        clippy::option_map_unwrap_or_else,
+        clippy::print_stdout,
+        clippy::unicode_not_nfc,
+        clippy::use_self
+    )
+)]
+
+mod instrument;
+mod perf_map;
+mod profiler;
+
+pub use crate::instrument::{
+    instrument, InstrumentationError, Instrumented, ENTER_FUNCTION, EXIT_FUNCTION,
+    PROFILER_NAMESPACE,
+};
+pub use crate::perf_map::write_perf_map;
+pub use crate::profiler::{FunctionKind, FunctionProfile, Profiler, ProfilerError};
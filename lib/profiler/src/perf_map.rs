@@ -0,0 +1,39 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use wasmer::Module;
+
+/// Appends the code regions of the functions of `module` to the perf map
+/// of the current process, `/tmp/perf-<pid>.map`, so `perf` can symbolize
+/// the code compiled by the JIT engine.
+///
+/// Each line of the map is `<start> <size> <name>`, the addresses being in
+/// hexadecimal. The functions are named `<module name>::<function name>`,
+/// after the `name` section of the module.
+///
+/// Returns the path of the perf map.
+pub fn write_perf_map(module: &Module) -> io::Result<PathBuf> {
+    let path = PathBuf::from(format!("/tmp/perf-{}.map", std::process::id()));
+    let mut map = OpenOptions::new().create(true).append(true).open(&path)?;
+
+    let info = module.info();
+    let module_name = module.name().unwrap_or("<module>");
+    let mut lines = String::new();
+    for (local_index, body) in module.artifact().finished_functions().iter() {
+        let body = **body;
+        let index = info.func_index(local_index);
+        let name = match info.function_names.get(&index) {
+            Some(name) => name.clone(),
+            None => format!("wasm-function[{}]", index.as_u32()),
+        };
+        lines.push_str(&format!(
+            "{:x} {:x} {}::{}\n",
+            body as *const u8 as usize,
+            body.len(),
+            module_name,
+            name
+        ));
+    }
+    map.write_all(lines.as_bytes())?;
+    Ok(path)
+}
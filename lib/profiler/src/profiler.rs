@@ -0,0 +1,283 @@
+use crate::instrument::{
+    instrument, InstrumentationError, ENTER_FUNCTION, EXIT_FUNCTION, PROFILER_NAMESPACE,
+};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use wasmer::{Exports, Function, ImportObject, Module, Store, WasmerEnv};
+use wasmer_compiler::CompileError;
+use wasmer_types::FunctionIndex;
+
+/// An error while compiling a module for profiling.
+#[derive(Error, Debug)]
+pub enum ProfilerError {
+    /// The module couldn't be instrumented.
+    #[error(transparent)]
+    Instrumentation(#[from] InstrumentationError),
+    /// The instrumented module couldn't be compiled.
+    #[error(transparent)]
+    Compile(#[from] CompileError),
+}
+
+/// Whether a profiled function is defined by the module or imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FunctionKind {
+    /// A function defined by the module.
+    Local,
+    /// A function imported by the module, usually a host function.
+    Import,
+}
+
+/// The profile of a function.
+#[derive(Debug, Clone)]
+pub struct FunctionProfile {
+    /// The name of the function.
+    ///
+    /// Local functions are named after the `name` section of the module,
+    /// as in the [`FrameInfo`] of traps, and imported ones after their
+    /// import: `module.field`.
+    ///
+    /// [`FrameInfo`]: wasmer::FrameInfo
+    pub name: String,
+    /// Whether the function is defined by the module or imported.
+    pub kind: FunctionKind,
+    /// The number of calls to the function.
+    pub calls: u64,
+    /// The time spent in the function itself, excluding its callees.
+    pub self_time: Duration,
+    /// The time spent in the function, including its callees.
+    ///
+    /// The time of recursive calls is only counted once.
+    pub total_time: Duration,
+}
+
+/// A profiler of WebAssembly modules, measuring the time spent in each
+/// function and in each host import.
+///
+/// The modules are instrumented before being compiled (see
+/// [`Profiler::compile`]), calling the profiler on every function entry
+/// and exit. The hooks must then be added to the imports of the module
+/// with [`Profiler::register_imports`].
+///
+/// ```
+/// # use wasmer::{imports, Instance, Store};
+/// # use wasmer_profiler::Profiler;
+/// # fn main() -> anyhow::Result<()> {
+/// let store = Store::default();
+/// let profiler = Profiler::new();
+/// let wasm = wat::parse_str(r#"
+///     (module
+///       (func $fib (export "fib") (param i32) (result i32)
+///         (if (result i32) (i32.lt_u (local.get 0) (i32.const 2))
+///           (then (local.get 0))
+///           (else
+///             (i32.add
+///               (call $fib (i32.sub (local.get 0) (i32.const 1)))
+///               (call $fib (i32.sub (local.get 0) (i32.const 2))))))))
+/// "#)?;
+/// let module = profiler.compile(&store, &wasm)?;
+///
+/// let mut import_object = imports! {};
+/// profiler.register_imports(&store, &mut import_object);
+/// let instance = Instance::new(&module, &import_object)?;
+/// let fib = instance.exports.get_native_function::<i32, i32>("fib")?;
+/// assert_eq!(fib.call(10)?, 55);
+///
+/// let profile = profiler.functions();
+/// assert_eq!(profile[0].name, "fib");
+/// assert_eq!(profile[0].calls, 177);
+///
+/// // Write the stacks for `flamegraph.pl`.
+/// let mut folded = Vec::new();
+/// profiler.write_folded(&mut folded)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Traps
+///
+/// A trap unwinds the guest frames without notifying the profiler: the
+/// frames are closed when a function entered before them returns. The
+/// time of the frames of a trapping call made by the host is discarded.
+#[derive(Clone, Default)]
+pub struct Profiler {
+    state: Arc<Mutex<ProfilerState>>,
+}
+
+#[derive(Default)]
+struct ProfilerState {
+    functions: Vec<FunctionState>,
+    stack: Vec<Frame>,
+    /// The self time of each call stack, as profiler ids, outermost first.
+    stacks: HashMap<Vec<u32>, Duration>,
+}
+
+struct FunctionState {
+    name: String,
+    kind: FunctionKind,
+    calls: u64,
+    self_time: Duration,
+    total_time: Duration,
+}
+
+struct Frame {
+    id: u32,
+    start: Instant,
+    /// The time spent in the callees of the frame.
+    callees_time: Duration,
+}
+
+impl Profiler {
+    /// Creates a new profiler.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Instruments and compiles the module `wasm`.
+    ///
+    /// Several modules can be profiled by the same profiler.
+    pub fn compile(&self, store: &Store, wasm: &[u8]) -> Result<Module, ProfilerError> {
+        let mut state = self.state.lock().unwrap();
+        let first_id = state.functions.len() as u32;
+        let instrumented = instrument(wasm, first_id)?;
+        let module = Module::new(store, &instrumented.wasm)?;
+
+        let info = module.info();
+        let imports = module
+            .imports()
+            .functions()
+            .map(|import| format!("{}.{}", import.module(), import.name()))
+            .collect::<Vec<_>>();
+        for index in 0..instrumented.num_functions {
+            let (name, kind) = if index < instrumented.num_imported_functions {
+                (imports[index as usize].clone(), FunctionKind::Import)
+            } else {
+                // Local functions are shifted by the two imported hooks.
+                let name = info
+                    .function_names
+                    .get(&FunctionIndex::from_u32(index + 2))
+                    .cloned()
+                    .unwrap_or_else(|| format!("wasm-function[{}]", index));
+                (name, FunctionKind::Local)
+            };
+            state.functions.push(FunctionState {
+                name,
+                kind,
+                calls: 0,
+                self_time: Duration::default(),
+                total_time: Duration::default(),
+            });
+        }
+        Ok(module)
+    }
+
+    /// Registers the profiler hooks called by the instrumented modules in
+    /// `import_object`.
+    pub fn register_imports(&self, store: &Store, import_object: &mut ImportObject) {
+        let env = HookEnv {
+            state: self.state.clone(),
+        };
+        let mut namespace = Exports::new();
+        namespace.insert(
+            ENTER_FUNCTION,
+            Function::new_native_with_env(store, env.clone(), enter),
+        );
+        namespace.insert(
+            EXIT_FUNCTION,
+            Function::new_native_with_env(store, env, exit),
+        );
+        import_object.register(PROFILER_NAMESPACE, namespace);
+    }
+
+    /// Returns the profile of the functions called at least once, sorted
+    /// by decreasing self time.
+    pub fn functions(&self) -> Vec<FunctionProfile> {
+        let state = self.state.lock().unwrap();
+        let mut functions = state
+            .functions
+            .iter()
+            .filter(|function| function.calls > 0)
+            .map(|function| FunctionProfile {
+                name: function.name.clone(),
+                kind: function.kind,
+                calls: function.calls,
+                self_time: function.self_time,
+                total_time: function.total_time,
+            })
+            .collect::<Vec<_>>();
+        functions.sort_by_key(|function| Reverse(function.self_time));
+        functions
+    }
+
+    /// Writes the call stacks in the folded format used by flamegraph
+    /// tools, such as `flamegraph.pl` or `inferno`: one line per call
+    /// stack, with the names of its frames separated by `;` followed by
+    /// its self time in nanoseconds.
+    pub fn write_folded<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let state = self.state.lock().unwrap();
+        let mut lines = state
+            .stacks
+            .iter()
+            .map(|(stack, time)| {
+                let names = stack
+                    .iter()
+                    .map(|id| state.functions[*id as usize].name.replace(';', ":"))
+                    .collect::<Vec<_>>();
+                (names.join(";"), time.as_nanos())
+            })
+            .filter(|(_, time)| *time > 0)
+            .collect::<Vec<_>>();
+        lines.sort();
+        for (stack, time) in lines {
+            writeln!(out, "{} {}", stack, time)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(WasmerEnv, Clone)]
+struct HookEnv {
+    state: Arc<Mutex<ProfilerState>>,
+}
+
+fn enter(env: &mut HookEnv, id: u32) {
+    let mut state = env.state.lock().unwrap();
+    if let Some(function) = state.functions.get_mut(id as usize) {
+        function.calls += 1;
+    }
+    state.stack.push(Frame {
+        id,
+        start: Instant::now(),
+        callees_time: Duration::default(),
+    });
+}
+
+fn exit(env: &mut HookEnv, id: u32) {
+    let now = Instant::now();
+    let mut state = env.state.lock().unwrap();
+    // Frames above the exiting one were unwound by a trap.
+    let depth = match state.stack.iter().rposition(|frame| frame.id == id) {
+        Some(depth) => depth,
+        None => return,
+    };
+    state.stack.truncate(depth + 1);
+    let stack = state.stack.iter().map(|frame| frame.id).collect::<Vec<_>>();
+    let frame = state.stack.pop().unwrap();
+    let time = now - frame.start;
+    let self_time = time.checked_sub(frame.callees_time).unwrap_or_default();
+    let recursive = state.stack.iter().any(|frame| frame.id == id);
+
+    if let Some(caller) = state.stack.last_mut() {
+        caller.callees_time += time;
+    }
+    *state.stacks.entry(stack).or_default() += self_time;
+    if let Some(function) = state.functions.get_mut(id as usize) {
+        function.self_time += self_time;
+        if !recursive {
+            function.total_time += time;
+        }
+    }
+}
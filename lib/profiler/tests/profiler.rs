@@ -0,0 +1,176 @@
+use anyhow::Result;
+use wasmer::*;
+use wasmer_profiler::{write_perf_map, FunctionKind, Profiler};
+
+fn fold(profiler: &Profiler) -> Result<String> {
+    let mut folded = Vec::new();
+    profiler.write_folded(&mut folded)?;
+    Ok(String::from_utf8(folded)?)
+}
+
+#[test]
+fn profile_local_and_imported_functions() -> Result<()> {
+    let store = Store::default();
+    let profiler = Profiler::new();
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (import "env" "tick" (func $tick (param i32)))
+          (func $work (param i32)
+            (loop $loop
+              (call $tick (local.get 0))
+              (local.set 0 (i32.sub (local.get 0) (i32.const 1)))
+              (br_if $loop (local.get 0))))
+          (func (export "run")
+            (call $work (i32.const 3))
+            (call $work (i32.const 2))))
+        "#,
+    )?;
+    let module = profiler.compile(&store, &wasm)?;
+
+    let mut import_object = imports! {
+        "env" => {
+            "tick" => Function::new_native(&store, |_: i32| {}),
+        },
+    };
+    profiler.register_imports(&store, &mut import_object);
+    let instance = Instance::new(&module, &import_object)?;
+    let run = instance.exports.get_function("run")?;
+    run.call(&[])?;
+
+    let mut functions = profiler.functions();
+    functions.sort_by(|a, b| a.name.cmp(&b.name));
+    let calls = functions
+        .iter()
+        .map(|function| (function.name.as_str(), function.kind, function.calls))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        calls,
+        vec![
+            ("env.tick", FunctionKind::Import, 5),
+            ("wasm-function[2]", FunctionKind::Local, 1),
+            ("work", FunctionKind::Local, 2),
+        ]
+    );
+    for function in &functions {
+        assert!(function.self_time <= function.total_time);
+    }
+
+    let folded = fold(&profiler)?;
+    let stacks = folded
+        .lines()
+        .map(|line| line.rsplitn(2, ' ').nth(1).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        stacks,
+        vec![
+            "wasm-function[2]",
+            "wasm-function[2];work",
+            "wasm-function[2];work;env.tick",
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn profile_indirect_calls_and_recursion() -> Result<()> {
+    let store = Store::default();
+    let profiler = Profiler::new();
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (type $t (func (param i32) (result i32)))
+          (table 1 funcref)
+          (elem (i32.const 0) $fib)
+          (func $fib (type $t)
+            (if (result i32) (i32.lt_u (local.get 0) (i32.const 2))
+              (then (local.get 0))
+              (else
+                (i32.add
+                  (call $fib (i32.sub (local.get 0) (i32.const 1)))
+                  (call_indirect (type $t)
+                    (i32.sub (local.get 0) (i32.const 2))
+                    (i32.const 0))))))
+          (func (export "run") (param i32) (result i32)
+            (call_indirect (type $t) (local.get 0) (i32.const 0))))
+        "#,
+    )?;
+    let module = profiler.compile(&store, &wasm)?;
+
+    let mut import_object = imports! {};
+    profiler.register_imports(&store, &mut import_object);
+    let instance = Instance::new(&module, &import_object)?;
+    let run = instance.exports.get_native_function::<i32, i32>("run")?;
+    assert_eq!(run.call(10)?, 55);
+
+    let functions = profiler.functions();
+    let fib = functions
+        .iter()
+        .find(|function| function.name == "fib")
+        .unwrap();
+    assert_eq!(fib.calls, 177);
+    let run = functions
+        .iter()
+        .find(|function| function.name == "wasm-function[1]")
+        .unwrap();
+    assert_eq!(run.calls, 1);
+    // The recursive calls are only counted once in the total time.
+    assert!(fib.total_time <= run.total_time);
+    assert!(fold(&profiler)?.contains("wasm-function[1];fib;fib;fib "));
+    Ok(())
+}
+
+#[test]
+fn profiled_traps_keep_function_names() -> Result<()> {
+    let store = Store::default();
+    let profiler = Profiler::new();
+    let wasm = wat::parse_str(
+        r#"
+        (module $m
+          (func $die unreachable)
+          (func $start (call $die))
+          (start $start))
+        "#,
+    )?;
+    let module = profiler.compile(&store, &wasm)?;
+
+    let mut import_object = imports! {};
+    profiler.register_imports(&store, &mut import_object);
+    let err = match Instance::new(&module, &import_object) {
+        Err(InstantiationError::Start(err)) => err,
+        _ => panic!("expected a start trap"),
+    };
+    let names = err
+        .trace()
+        .iter()
+        .filter_map(|frame| frame.function_name())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["die", "start"]);
+    assert_eq!(profiler.functions().len(), 2);
+    Ok(())
+}
+
+#[test]
+fn perf_map_lists_compiled_functions() -> Result<()> {
+    let store = Store::default();
+    let wasm = wat::parse_str(
+        r#"
+        (module $perf_map_test
+          (func $first)
+          (func $second))
+        "#,
+    )?;
+    let module = Module::new(&store, &wasm)?;
+    let path = write_perf_map(&module)?;
+    let map = std::fs::read_to_string(&path)?;
+    for name in &["perf_map_test::first", "perf_map_test::second"] {
+        let line = map
+            .lines()
+            .find(|line| line.ends_with(name))
+            .expect("function missing from the perf map");
+        let fields = line.split(' ').collect::<Vec<_>>();
+        assert!(usize::from_str_radix(fields[0], 16)? > 0);
+        assert!(usize::from_str_radix(fields[1], 16)? > 0);
+    }
+    Ok(())
+}
@@ -0,0 +1,223 @@
+//! Collection of the reference cycles between instances and tables.
+//!
+//! An instance holds its tables, the tables it imports and the instances
+//! it imports from, and a table keeps alive the instances whose functions
+//! are stored in it. An instance storing its functions in a table it
+//! imports, as every Emscripten module does with `env.table`, is part of
+//! a cycle that reference counting alone never frees.
+//!
+//! Every such cycle goes through a table keeping an instance alive. When a
+//! reference to an instance or a table is released, the references
+//! between the instances and the tables reachable from it are subtracted
+//! from their reference counts. The nodes left with references from
+//! elsewhere are alive, with everything they reach. The tables of the
+//! other ones release the instances they keep alive, which frees them.
+//!
+//! The references held by the instances and the tables are only cloned
+//! under the read lock of `EDGES`, so that the counts can't grow behind
+//! the collector, which holds the write lock.
+
+use crate::instance::{Instance, InstanceHandle, InstanceRef};
+use crate::table::Table;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+lazy_static! {
+    static ref EDGES: RwLock<()> = RwLock::new(());
+}
+
+/// The number of instances kept alive by tables, without which there
+/// can't be any cycle.
+static KEPT_INSTANCES: AtomicUsize = AtomicUsize::new(0);
+
+/// Clones a reference held by an instance or a table with `clone`.
+pub(crate) fn follow_edges<T>(clone: impl FnOnce() -> T) -> T {
+    let _edges = EDGES.read().unwrap();
+    clone()
+}
+
+/// The instances kept alive by a table, as their functions are stored in
+/// it.
+#[derive(Debug, Default)]
+pub struct TableInstances {
+    handles: Mutex<Vec<InstanceRef>>,
+}
+
+impl TableInstances {
+    /// Creates an empty set of instances.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps `instance` alive as long as the table.
+    pub fn keep_alive(&self, instance: InstanceRef) {
+        let mut handles = self.handles.lock().unwrap();
+        if !handles.contains(&instance) {
+            handles.push(instance);
+            KEPT_INSTANCES.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Returns a handle to the first instance satisfying `predicate`.
+    pub fn find(&self, mut predicate: impl FnMut(&InstanceRef) -> bool) -> Option<InstanceRef> {
+        follow_edges(|| {
+            let handles = self.handles.lock().unwrap();
+            handles.iter().find(|handle| predicate(handle)).cloned()
+        })
+    }
+
+    /// Returns handles to all the instances.
+    pub fn to_vec(&self) -> Vec<InstanceRef> {
+        follow_edges(|| self.handles.lock().unwrap().clone())
+    }
+
+    /// Returns whether no instance is kept alive.
+    pub fn is_empty(&self) -> bool {
+        self.handles.lock().unwrap().is_empty()
+    }
+
+    fn take(&self) -> Vec<InstanceRef> {
+        let handles = mem::take(&mut *self.handles.lock().unwrap());
+        KEPT_INSTANCES.fetch_sub(handles.len(), Ordering::SeqCst);
+        handles
+    }
+}
+
+impl Drop for TableInstances {
+    fn drop(&mut self) {
+        let len = self.handles.get_mut().unwrap().len();
+        KEPT_INSTANCES.fetch_sub(len, Ordering::SeqCst);
+    }
+}
+
+/// Frees the cycles that `table` is the last reference from elsewhere to,
+/// before it's released.
+pub fn collect_table_cycles(table: &Arc<dyn Table>) {
+    if KEPT_INSTANCES.load(Ordering::SeqCst) > 0 {
+        collect(Node::Table(table));
+    }
+}
+
+/// Frees the cycles that `handle` is the last reference from elsewhere
+/// to, before it's released.
+pub(crate) fn collect_instance_cycles(handle: &InstanceHandle) {
+    if KEPT_INSTANCES.load(Ordering::SeqCst) > 0 && handle.instance().has_edges() {
+        collect(Node::Instance(handle.instance()));
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Node {
+    Instance(*const Instance),
+    Table(*const Arc<dyn Table>),
+}
+
+impl Node {
+    /// Identifies the node, the clones of an `Arc` sharing their table.
+    fn key(self) -> usize {
+        match self {
+            Self::Instance(instance) => instance as usize,
+            Self::Table(table) => unsafe { Arc::as_ptr(&*table) as *const u8 as usize },
+        }
+    }
+}
+
+#[derive(Default)]
+struct Graph {
+    nodes: Vec<Node>,
+    indices: HashMap<usize, usize>,
+    edges: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    fn add(&mut self, node: Node) -> usize {
+        let nodes = &mut self.nodes;
+        let edges = &mut self.edges;
+        *self.indices.entry(node.key()).or_insert_with(|| {
+            nodes.push(node);
+            edges.push(Vec::new());
+            nodes.len() - 1
+        })
+    }
+}
+
+/// Frees the cycles reachable from `root`, whose reference held by the
+/// caller isn't counted as one from elsewhere.
+fn collect(root: Node) {
+    let released = {
+        let _edges = EDGES.write().unwrap();
+        // The nodes reachable from the root are kept alive by the
+        // references followed here, which are only released by the
+        // collector or once their holder is freed.
+        unsafe { find_garbage(root) }
+    };
+    // The instances are freed once the lock is released, as they may
+    // release more references.
+    drop(released);
+}
+
+unsafe fn find_garbage(root: Node) -> Vec<InstanceRef> {
+    let mut graph = Graph::default();
+    graph.add(root);
+    let mut next = 0;
+    while next < graph.nodes.len() {
+        let mut targets = Vec::new();
+        match graph.nodes[next] {
+            Node::Instance(instance) => {
+                let instance = &*instance;
+                for table in instance.held_tables() {
+                    targets.push(Node::Table(table));
+                }
+                for handle in instance.imported_instances() {
+                    targets.push(Node::Instance(handle.instance()));
+                }
+            }
+            Node::Table(table) => {
+                for handle in (*table).instances().handles.lock().unwrap().iter() {
+                    targets.push(Node::Instance(handle.instance()));
+                }
+            }
+        }
+        let targets = targets.into_iter().map(|node| graph.add(node)).collect();
+        graph.edges[next] = targets;
+        next += 1;
+    }
+
+    // The references from elsewhere.
+    let mut external = graph
+        .nodes
+        .iter()
+        .map(|node| match *node {
+            Node::Instance(instance) => (*instance).ref_count(),
+            Node::Table(table) => Arc::strong_count(&*table),
+        })
+        .collect::<Vec<_>>();
+    external[0] = external[0].saturating_sub(1);
+    for targets in &graph.edges {
+        for &target in targets {
+            external[target] = external[target].saturating_sub(1);
+        }
+    }
+
+    let mut alive = vec![false; graph.nodes.len()];
+    let mut stack = (0..graph.nodes.len())
+        .filter(|&index| external[index] > 0)
+        .collect::<Vec<_>>();
+    while let Some(index) = stack.pop() {
+        if !alive[index] {
+            alive[index] = true;
+            stack.extend(graph.edges[index].iter().filter(|&&target| !alive[target]));
+        }
+    }
+
+    let mut released = Vec::new();
+    for (node, alive) in graph.nodes.iter().zip(alive) {
+        if let (Node::Table(table), false) = (*node, alive) {
+            released.append(&mut (*table).instances().take());
+        }
+    }
+    released
+}
@@ -2,6 +2,8 @@
 // Attributions: https://github.com/wasmerio/wasmer/blob/master/ATTRIBUTIONS.md
 
 use crate::global::Global;
+use crate::imports::ImportInitializerFuncPtr;
+use crate::instance::InstanceRef;
 use crate::memory::{Memory, MemoryStyle};
 use crate::table::{Table, TableStyle};
 use crate::vmcontext::{VMContext, VMFunctionBody, VMFunctionKind};
@@ -35,6 +37,12 @@ pub struct ExportFunction {
     pub signature: FunctionType,
     /// The function kind (it defines how it's the signature that provided `address` have)
     pub kind: VMFunctionKind,
+    /// The initializer of the host function environment, called with the
+    /// instance importing the function before its start function runs.
+    pub host_env_init: Option<ImportInitializerFuncPtr>,
+    /// A reference to the instance exporting the function, if any, which is
+    /// kept alive by it.
+    pub instance_ref: Option<InstanceRef>,
 }
 
 /// # Safety
@@ -55,6 +63,9 @@ impl From<ExportFunction> for Export {
 pub struct ExportTable {
     /// Pointer to the containing `Table`.
     pub from: Arc<dyn Table>,
+    /// A reference to the instance exporting the table, if any, which is kept
+    /// alive by it, as the table can hold its functions.
+    pub instance_ref: Option<InstanceRef>,
 }
 
 /// # Safety
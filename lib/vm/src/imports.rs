@@ -1,10 +1,46 @@
 // This file contains code from external sources.
 // Attributions: https://github.com/wasmerio/wasmer/blob/master/ATTRIBUTIONS.md
 
-use crate::vmcontext::{VMFunctionImport, VMGlobalImport, VMMemoryImport, VMTableImport};
+use crate::instance::InstanceRef;
+use crate::vmcontext::{
+    VMContext, VMFunctionImport, VMGlobalImport, VMMemoryImport, VMTableImport,
+};
+use std::ffi::c_void;
+use thiserror::Error;
 use wasmer_types::entity::{BoxedSlice, PrimaryMap};
 use wasmer_types::{FunctionIndex, GlobalIndex, MemoryIndex, TableIndex};
 
+/// An error while initializing the environment of an imported host function.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum HostEnvInitError {
+    /// An export required by the environment is missing.
+    #[error("missing export `{0}` required by a host function environment")]
+    MissingExport(String),
+    /// An export required by the environment has an unexpected type.
+    #[error("export `{0}` required by a host function environment has an incompatible type")]
+    IncompatibleExport(String),
+    /// A user defined error value, used for error cases not listed above.
+    #[error("{0}")]
+    Generic(String),
+}
+
+/// A function initializing the environment of an imported host function,
+/// once the importing instance exists.
+///
+/// It receives the `vmctx` of the host function and an opaque pointer to
+/// the instance, whose type is defined by the embedder.
+pub type ImportInitializerFuncPtr =
+    unsafe fn(*mut VMContext, *const c_void) -> Result<(), HostEnvInitError>;
+
+/// The initializer of an imported host function environment.
+#[derive(Clone, Copy, Debug)]
+pub struct ImportFunctionEnvInitializer {
+    /// The function initializing the environment.
+    pub init: ImportInitializerFuncPtr,
+    /// The `vmctx` of the imported function, holding the environment.
+    pub vmctx: *mut VMContext,
+}
+
 /// Resolved import pointers.
 #[derive(Clone)]
 pub struct Imports {
@@ -19,6 +55,13 @@ pub struct Imports {
 
     /// Resolved addresses for imported globals.
     pub globals: BoxedSlice<GlobalIndex, VMGlobalImport>,
+
+    /// Initializers of the imported host function environments.
+    pub host_env_initializers: Vec<ImportFunctionEnvInitializer>,
+
+    /// References to the instances exporting the imported functions and
+    /// tables, kept alive by the importing instance.
+    pub instances: Vec<InstanceRef>,
 }
 
 impl Imports {
@@ -28,12 +71,16 @@ impl Imports {
         table_imports: PrimaryMap<TableIndex, VMTableImport>,
         memory_imports: PrimaryMap<MemoryIndex, VMMemoryImport>,
         global_imports: PrimaryMap<GlobalIndex, VMGlobalImport>,
+        host_env_initializers: Vec<ImportFunctionEnvInitializer>,
+        instances: Vec<InstanceRef>,
     ) -> Self {
         Self {
             functions: function_imports.into_boxed_slice(),
             tables: table_imports.into_boxed_slice(),
             memories: memory_imports.into_boxed_slice(),
             globals: global_imports.into_boxed_slice(),
+            host_env_initializers,
+            instances,
         }
     }
 
@@ -44,6 +91,8 @@ impl Imports {
             tables: PrimaryMap::new().into_boxed_slice(),
             memories: PrimaryMap::new().into_boxed_slice(),
             globals: PrimaryMap::new().into_boxed_slice(),
+            host_env_initializers: Vec::new(),
+            instances: Vec::new(),
         }
     }
 }
@@ -4,12 +4,14 @@
 //! An `Instance` contains all the runtime state used by execution of a
 //! wasm module (except its callstack and register state). An
 //! `InstanceHandle` is a reference-counting handle for an `Instance`.
+use crate::cycles;
 use crate::export::Export;
 use crate::global::Global;
-use crate::imports::Imports;
+use crate::imports::{HostEnvInitError, Imports};
 use crate::memory::{Memory, MemoryError};
+use crate::memory_image::MemoryImage;
 use crate::table::Table;
-use crate::trap::{catch_traps, init_traps, Trap, TrapCode};
+use crate::trap::{catch_traps, init_traps, raise_user_trap, Trap, TrapCode};
 use crate::vmcontext::{
     VMBuiltinFunctionsArray, VMCallerCheckedAnyfunc, VMContext, VMFunctionBody, VMFunctionImport,
     VMFunctionKind, VMGlobalDefinition, VMGlobalImport, VMMemoryDefinition, VMMemoryImport,
@@ -24,9 +26,11 @@ use std::any::Any;
 use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
+use std::ffi::c_void;
 use std::ptr::NonNull;
-use std::sync::Arc;
-use std::{mem, ptr, slice};
+use std::sync::atomic::{self, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::{fmt, mem, ptr, slice};
 use wasmer_types::entity::{packed_option::ReservedValue, BoxedSlice, EntityRef, PrimaryMap};
 use wasmer_types::{
     DataIndex, DataInitializer, ElemIndex, ExportIndex, FunctionIndex, GlobalIndex, GlobalInit,
@@ -97,9 +101,29 @@ pub(crate) struct Instance {
     /// Hosts can store arbitrary per-instance information here.
     host_state: Box<dyn Any>,
 
+    /// The state the code of the module needs at runtime, if any, which
+    /// lives as long as the instance.
+    code_state: Option<Arc<dyn Any + Send + Sync>>,
+
+    /// The resolved imports. The `vmctx` only holds copies of them, so they
+    /// are owned here, and live as long as the instance.
+    imports: Imports,
+
+    /// Whether the instance runs in deterministic mode, where the failures
+    /// of `memory.grow` specific to the host trap.
+    deterministic: bool,
+
     /// Handler run when `SIGBUS`, `SIGFPE`, `SIGILL`, or `SIGSEGV` are caught by the instance thread.
     pub(crate) signal_handler: Cell<Option<Box<SignalHandler>>>,
 
+    /// The number of `InstanceHandle`s to this instance, which is
+    /// deallocated when the last one is dropped.
+    ref_count: AtomicUsize,
+
+    /// The allocator of the storage of this instance, if it's not
+    /// allocated on the heap.
+    allocator: Option<Arc<dyn InstanceAllocator>>,
+
     /// Additional context used by compiled wasm code. This field is last, and
     /// represents a dynamically-sized array that extends beyond the nominal
     /// end of the struct (similar to a flexible array member).
@@ -276,16 +300,6 @@ impl Instance {
         self.vmctx() as *const VMContext as *mut VMContext
     }
 
-    /// Lookup an export with the given name.
-    pub fn lookup(&self, field: &str) -> Option<Export> {
-        let export = if let Some(export) = self.module.exports.get(field) {
-            export.clone()
-        } else {
-            return None;
-        };
-        Some(self.lookup_by_declaration(&export))
-    }
-
     /// Lookup an export with the given export declaration.
     pub fn lookup_by_declaration(&self, export: &ExportIndex) -> Export {
         match export {
@@ -308,17 +322,27 @@ impl Instance {
                     kind: VMFunctionKind::Static,
                     signature,
                     vmctx,
+                    // Imported functions were already initialized by
+                    // the instance that imported them.
+                    host_env_init: None,
+                    instance_ref: None,
                 }
                 .into()
             }
             ExportIndex::Table(index) => {
-                let from = if let Some(def_index) = self.module.local_table_index(*index) {
-                    self.tables[def_index].clone()
-                } else {
-                    let import = self.imported_table(*index);
-                    import.from.clone()
-                };
-                ExportTable { from }.into()
+                let from = cycles::follow_edges(|| {
+                    if let Some(def_index) = self.module.local_table_index(*index) {
+                        self.tables[def_index].clone()
+                    } else {
+                        let import = self.imported_table(*index);
+                        import.from.clone()
+                    }
+                });
+                ExportTable {
+                    from,
+                    instance_ref: None,
+                }
+                .into()
             }
             ExportIndex::Memory(index) => {
                 let from = if let Some(def_index) = self.module.local_memory_index(*index) {
@@ -476,6 +500,18 @@ impl Instance {
         from.grow(delta.into())
     }
 
+    /// Returns the result of a `memory.grow` that failed with `error`.
+    ///
+    /// Growing a memory beyond its limits returns -1. In deterministic mode,
+    /// the other failures, which depend on the host, trap instead.
+    pub(crate) fn memory_grow_failure(&self, error: MemoryError) -> u32 {
+        match error {
+            MemoryError::CouldNotGrow { .. } => u32::max_value(),
+            error if self.deterministic => unsafe { raise_user_trap(Box::new(error)) },
+            _ => u32::max_value(),
+        }
+    }
+
     /// Returns the number of allocated wasm pages.
     pub(crate) fn memory_size(&self, memory_index: LocalMemoryIndex) -> Pages {
         self.memories
@@ -539,10 +575,14 @@ impl Instance {
     }
 
     fn alloc_layout(&self) -> Layout {
-        let size = mem::size_of_val(self)
-            .checked_add(usize::try_from(self.offsets.size_of_vmctx()).unwrap())
+        Self::alloc_layout_for(&self.offsets)
+    }
+
+    fn alloc_layout_for(offsets: &VMOffsets) -> Layout {
+        let size = mem::size_of::<Self>()
+            .checked_add(usize::try_from(offsets.size_of_vmctx()).unwrap())
             .unwrap();
-        let align = mem::align_of_val(self);
+        let align = mem::align_of::<Self>();
         Layout::from_size_align(size, align).unwrap()
     }
 
@@ -758,9 +798,114 @@ impl Instance {
         let import = self.imported_table(index);
         &*import.from
     }
+
+    /// Returns the tables held by this instance, local and imported.
+    pub(crate) fn held_tables(&self) -> impl Iterator<Item = &Arc<dyn Table>> {
+        self.tables
+            .values()
+            .chain(self.imports.tables.values().map(|import| &import.from))
+    }
+
+    /// Returns references to the instances this instance imports from.
+    pub(crate) fn imported_instances(&self) -> &[InstanceRef] {
+        &self.imports.instances
+    }
+
+    /// Returns whether this instance holds tables or other instances.
+    pub(crate) fn has_edges(&self) -> bool {
+        !self.tables.is_empty()
+            || !self.imports.tables.is_empty()
+            || !self.imports.instances.is_empty()
+    }
+
+    /// Returns the number of handles to this instance.
+    pub(crate) fn ref_count(&self) -> usize {
+        self.ref_count.load(Ordering::SeqCst)
+    }
+}
+
+/// Allocates the storage of the instances, holding their `VMContext`.
+///
+/// The instances are allocated on the heap unless the `Tunables` of the
+/// engine provide an allocator.
+pub trait InstanceAllocator: Send + Sync {
+    /// Allocates the storage of an instance with the given layout.
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, String>;
+
+    /// Deallocates the storage of an instance.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been allocated by this allocator with `layout`, and
+    /// must not be used anymore.
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout);
+}
+
+/// The storage of an instance, allocated before it's created by
+/// [`InstanceHandle::new`].
+pub struct InstanceStorage {
+    ptr: NonNull<u8>,
+    layout: Layout,
+    allocator: Option<Arc<dyn InstanceAllocator>>,
+}
+
+impl InstanceStorage {
+    /// Allocates the storage of an instance of `module`, with `allocator`,
+    /// or on the heap if it's `None`.
+    pub fn allocate(
+        module: &ModuleInfo,
+        allocator: Option<Arc<dyn InstanceAllocator>>,
+    ) -> Result<Self, String> {
+        let offsets = VMOffsets::new(mem::size_of::<*const u8>() as u8, module);
+        let layout = Instance::alloc_layout_for(&offsets);
+        let ptr = match &allocator {
+            Some(allocator) => allocator.allocate(layout)?,
+            None => {
+                let ptr = unsafe { alloc::alloc(layout) };
+                match NonNull::new(ptr) {
+                    Some(ptr) => ptr,
+                    None => alloc::handle_alloc_error(layout),
+                }
+            }
+        };
+        Ok(Self {
+            ptr,
+            layout,
+            allocator,
+        })
+    }
+
+    unsafe fn deallocate(
+        ptr: NonNull<u8>,
+        layout: Layout,
+        allocator: Option<Arc<dyn InstanceAllocator>>,
+    ) {
+        match allocator {
+            Some(allocator) => allocator.deallocate(ptr, layout),
+            None => alloc::dealloc(ptr.as_ptr(), layout),
+        }
+    }
+}
+
+impl Drop for InstanceStorage {
+    fn drop(&mut self) {
+        unsafe { Self::deallocate(self.ptr, self.layout, self.allocator.take()) }
+    }
 }
 
-/// A handle holding an `Instance` of a WebAssembly module.
+impl fmt::Debug for InstanceStorage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("InstanceStorage")
+            .field("ptr", &self.ptr)
+            .field("layout", &self.layout)
+            .finish()
+    }
+}
+
+/// A reference-counting handle holding an `Instance` of a WebAssembly
+/// module.
+///
+/// The instance is deallocated when the last handle is dropped.
 #[derive(Hash, PartialEq, Eq)]
 pub struct InstanceHandle {
     instance: *mut Instance,
@@ -789,6 +934,7 @@ impl InstanceHandle {
     /// safety.
     #[allow(clippy::too_many_arguments)]
     pub unsafe fn new(
+        storage: InstanceStorage,
         module: Arc<ModuleInfo>,
         finished_functions: BoxedSlice<LocalFunctionIndex, FunctionBodyPtr>,
         finished_memories: BoxedSlice<LocalMemoryIndex, Arc<dyn Memory>>,
@@ -797,6 +943,8 @@ impl InstanceHandle {
         imports: Imports,
         vmshared_signatures: BoxedSlice<SignatureIndex, VMSharedSignatureIndex>,
         host_state: Box<dyn Any>,
+        code_state: Option<Arc<dyn Any + Send + Sync>>,
+        deterministic: bool,
     ) -> Result<Self, Trap> {
         // TODO: investigate `vmctx_tables` and `vmctx_memories`: both of these
         // appear to be dropped in this function which may cause memory problems
@@ -840,16 +988,20 @@ impl InstanceHandle {
                 passive_elements: Default::default(),
                 passive_data,
                 host_state,
+                code_state,
+                imports,
+                deterministic,
                 signal_handler: Cell::new(None),
+                ref_count: AtomicUsize::new(1),
+                allocator: None,
                 vmctx: VMContext {},
             };
-            let layout = instance.alloc_layout();
+            let mut storage = mem::ManuallyDrop::new(storage);
+            assert_eq!(storage.layout, instance.alloc_layout());
             #[allow(clippy::cast_ptr_alignment)]
-            let instance_ptr = alloc::alloc(layout) as *mut Instance;
-            if instance_ptr.is_null() {
-                alloc::handle_alloc_error(layout);
-            }
+            let instance_ptr = storage.ptr.as_ptr() as *mut Instance;
             ptr::write(instance_ptr, instance);
+            (*instance_ptr).allocator = storage.allocator.take();
             Self {
                 instance: instance_ptr,
             }
@@ -862,24 +1014,24 @@ impl InstanceHandle {
             vmshared_signatures.len(),
         );
         ptr::copy(
-            imports.functions.values().as_slice().as_ptr(),
+            instance.imports.functions.values().as_slice().as_ptr(),
             instance.imported_functions_ptr() as *mut VMFunctionImport,
-            imports.functions.len(),
+            instance.imports.functions.len(),
         );
         ptr::copy(
-            imports.tables.values().as_slice().as_ptr(),
+            instance.imports.tables.values().as_slice().as_ptr(),
             instance.imported_tables_ptr() as *mut VMTableImport,
-            imports.tables.len(),
+            instance.imports.tables.len(),
         );
         ptr::copy(
-            imports.memories.values().as_slice().as_ptr(),
+            instance.imports.memories.values().as_slice().as_ptr(),
             instance.imported_memories_ptr() as *mut VMMemoryImport,
-            imports.memories.len(),
+            instance.imports.memories.len(),
         );
         ptr::copy(
-            imports.globals.values().as_slice().as_ptr(),
+            instance.imports.globals.values().as_slice().as_ptr(),
             instance.imported_globals_ptr() as *mut VMGlobalImport,
-            imports.globals.len(),
+            instance.imports.globals.len(),
         );
         ptr::copy(
             vmctx_tables.values().as_slice().as_ptr(),
@@ -909,18 +1061,49 @@ impl InstanceHandle {
         initialize_passive_elements(instance);
         initialize_globals(instance);
 
+        // The functions of an instance importing a table can be stored in
+        // it, and called after the instance is dropped.
+        for import in instance.imports.tables.values() {
+            import.from.keep_alive(handle.clone().into());
+        }
+
         Ok(handle)
     }
 
+    /// Initializes the environments of the imported host functions.
+    ///
+    /// `instance_ptr` is an opaque pointer to the embedder's instance,
+    /// handed over to each initializer.
+    ///
+    /// # Safety
+    ///
+    /// Only safe to call after instantiation and before
+    /// `finish_instantiation`, with the pointer type expected by the
+    /// initializers.
+    pub unsafe fn initialize_host_envs(
+        &self,
+        instance_ptr: *const c_void,
+    ) -> Result<(), HostEnvInitError> {
+        for initializer in self.instance().imports.host_env_initializers.iter() {
+            (initializer.init)(initializer.vmctx, instance_ptr)?;
+        }
+        Ok(())
+    }
+
     /// Finishes the instantiation process started by `Instance::new`.
     ///
+    /// The local memories with an image in `memory_images` are initialized
+    /// by mapping it, and their data initializers are skipped.
+    ///
     /// # Safety
     ///
-    /// Only safe to call immediately after instantiation.
+    /// Only safe to call immediately after instantiation, with the images
+    /// built from `data_initializers`.
     pub unsafe fn finish_instantiation(
         &self,
         is_bulk_memory: bool,
         data_initializers: &[DataInitializer<'_>],
+        memory_images: Option<&PrimaryMap<LocalMemoryIndex, Option<MemoryImage>>>,
     ) -> Result<(), Trap> {
         // Check initializer bounds before initializing anything. Only do this
         // when bulk memory is disabled, since the bulk memory proposal changes
@@ -933,7 +1116,7 @@ impl InstanceHandle {
 
         // Apply the initializers.
         initialize_tables(self.instance())?;
-        initialize_memories(self.instance(), data_initializers)?;
+        initialize_memories(self.instance(), data_initializers, memory_images)?;
 
         // The WebAssembly spec specifies that the start function is
         // invoked automatically at instantiation time.
@@ -948,11 +1131,21 @@ impl InstanceHandle {
     /// This is unsafe because it doesn't work on just any `VMContext`, it must
     /// be a `VMContext` allocated as part of an `Instance`.
     pub unsafe fn from_vmctx(vmctx: *mut VMContext) -> Self {
+        let handle = Self::from_vmctx_uncounted(vmctx);
+        (*handle).clone()
+    }
+
+    /// Create a new `InstanceHandle` pointing at the instance pointed
+    /// to by the given `VMContext` pointer, without counting it.
+    ///
+    /// # Safety
+    /// See `from_vmctx`.
+    pub(crate) unsafe fn from_vmctx_uncounted(vmctx: *mut VMContext) -> mem::ManuallyDrop<Self> {
         let instance = (&*vmctx).instance();
 
-        Self {
+        mem::ManuallyDrop::new(Self {
             instance: instance as *const Instance as *mut Instance,
-        }
+        })
     }
 
     /// Return a reference to the vmctx used by compiled wasm code.
@@ -977,12 +1170,22 @@ impl InstanceHandle {
 
     /// Lookup an export with the given name.
     pub fn lookup(&self, field: &str) -> Option<Export> {
-        self.instance().lookup(field)
+        let export = self.module_ref().exports.get(field)?;
+        Some(self.lookup_by_declaration(export))
     }
 
     /// Lookup an export with the given export declaration.
+    ///
+    /// The exported functions and tables hold a handle to the instance,
+    /// keeping it alive.
     pub fn lookup_by_declaration(&self, export: &ExportIndex) -> Export {
-        self.instance().lookup_by_declaration(export)
+        let mut export = self.instance().lookup_by_declaration(export);
+        match &mut export {
+            Export::Function(function) => function.instance_ref = Some(self.clone().into()),
+            Export::Table(table) => table.instance_ref = Some(self.clone().into()),
+            Export::Memory(_) | Export::Global(_) => {}
+        }
+        export
     }
 
     /// Return an iterator over the exports of this instance.
@@ -1067,35 +1270,105 @@ impl InstanceHandle {
 
     /// Deallocates memory associated with this instance.
     ///
+    /// It's called when the last handle to the instance is dropped.
+    ///
     /// # Safety
     ///
     /// This is unsafe because there might be other handles to this
     /// `InstanceHandle` elsewhere, and there's nothing preventing
     /// usage of this handle after this function is called.
     pub unsafe fn dealloc(&self) {
-        let instance = self.instance();
+        let instance = &mut *self.instance;
         let layout = instance.alloc_layout();
+        let allocator = instance.allocator.take();
+        // The tables released here may be left in cycles.
+        let tables = instance
+            .held_tables()
+            .filter(|table| !table.instances().is_empty())
+            .map(Arc::downgrade)
+            .collect::<Vec<_>>();
         ptr::drop_in_place(self.instance);
-        alloc::dealloc(self.instance.cast(), layout);
+        InstanceStorage::deallocate(
+            NonNull::new_unchecked(self.instance.cast()),
+            layout,
+            allocator,
+        );
+        for table in tables.iter().filter_map(Weak::upgrade) {
+            cycles::collect_table_cycles(&table);
+        }
     }
 }
 
 impl Clone for InstanceHandle {
     fn clone(&self) -> Self {
+        self.instance().ref_count.fetch_add(1, Ordering::Relaxed);
         Self {
             instance: self.instance,
         }
     }
 }
 
-// TODO: uncomment this, as we need to store the handles
-// in the store, and once the store is dropped, then the instances
-// will too.
-// impl Drop for InstanceHandle {
-//     fn drop(&mut self) {
-//         unsafe { self.dealloc() }
-//     }
-// }
+impl Drop for InstanceHandle {
+    fn drop(&mut self) {
+        // Unless it's the last one, the released handle may leave the
+        // instance in a cycle.
+        if self.instance().ref_count() > 1 {
+            cycles::collect_instance_cycles(self);
+        }
+        if self.instance().ref_count.fetch_sub(1, Ordering::Release) != 1 {
+            return;
+        }
+        atomic::fence(Ordering::Acquire);
+        unsafe { self.dealloc() }
+    }
+}
+
+impl fmt::Debug for InstanceHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("InstanceHandle")
+            .field("instance", &self.instance)
+            .finish()
+    }
+}
+
+/// A reference keeping an instance alive, held by its exports, by the
+/// instances importing from it and by the tables storing its functions.
+///
+/// Unlike an `InstanceHandle`, it gives no access to the instance.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct InstanceRef(InstanceHandle);
+
+/// # Safety
+/// This is safe because an `InstanceRef` only reads the address of the
+/// `VMContext` of the instance and the references to the instances it
+/// imports from, which aren't modified after the instantiation, and
+/// updates the reference count of the instance atomically.
+unsafe impl Sync for InstanceRef {}
+
+impl InstanceRef {
+    /// Returns whether this reference keeps alive the instance of `vmctx`,
+    /// being that instance or importing from it.
+    pub fn keeps_alive(&self, vmctx: *mut VMContext) -> bool {
+        self.0.vmctx_ptr() == vmctx
+            || self
+                .instance()
+                .imports
+                .instances
+                .iter()
+                .any(|instance| instance.keeps_alive(vmctx))
+    }
+
+    /// Return a reference to the contained `Instance`.
+    pub(crate) fn instance(&self) -> &Instance {
+        self.0.instance()
+    }
+}
+
+impl From<InstanceHandle> for InstanceRef {
+    fn from(handle: InstanceHandle) -> Self {
+        Self(handle)
+    }
+}
 
 fn check_table_init_bounds(instance: &Instance) -> Result<(), Trap> {
     let module = Arc::clone(&instance.module);
@@ -1237,11 +1510,39 @@ fn initialize_passive_elements(instance: &Instance) {
 }
 
 /// Initialize the table memory from the provided initializers.
+///
+/// The image of a local memory is mapped before its first initializer is
+/// applied, so that a trap leaves the memories initialized in order.
 fn initialize_memories(
     instance: &Instance,
     data_initializers: &[DataInitializer<'_>],
+    memory_images: Option<&PrimaryMap<LocalMemoryIndex, Option<MemoryImage>>>,
 ) -> Result<(), Trap> {
+    // Whether each local memory has been initialized from its image, once
+    // it has been tried.
+    let mut mapped: PrimaryMap<LocalMemoryIndex, Option<bool>> =
+        instance.memories.iter().map(|_| None).collect();
     for init in data_initializers {
+        let image = instance
+            .module
+            .local_memory_index(init.location.memory_index)
+            .and_then(|index| Some((index, memory_images?[index].as_ref()?)));
+        if let Some((index, image)) = image {
+            let is_mapped = match mapped[index] {
+                Some(is_mapped) => is_mapped,
+                None => {
+                    let is_mapped = instance.memories[index]
+                        .map_image(image)
+                        .map_err(|e| Trap::User(Box::new(e)))?;
+                    mapped[index] = Some(is_mapped);
+                    is_mapped
+                }
+            };
+            if is_mapped {
+                continue;
+            }
+        }
+
         let memory = instance.get_memory(init.location.memory_index);
 
         let start = get_memory_init_start(init, instance);
@@ -20,13 +20,17 @@
     )
 )]
 
+mod cycles;
 mod export;
 mod global;
 mod imports;
 mod instance;
 mod memory;
+mod memory_image;
 mod mmap;
 mod module;
+#[cfg(unix)]
+mod pooling;
 mod probestack;
 mod sig_registry;
 mod table;
@@ -36,13 +40,19 @@ mod vmoffsets;
 
 pub mod libcalls;
 
+pub use crate::cycles::{collect_table_cycles, TableInstances};
 pub use crate::export::*;
 pub use crate::global::*;
-pub use crate::imports::Imports;
-pub use crate::instance::InstanceHandle;
+pub use crate::imports::{
+    HostEnvInitError, ImportFunctionEnvInitializer, ImportInitializerFuncPtr, Imports,
+};
+pub use crate::instance::{InstanceAllocator, InstanceHandle, InstanceRef, InstanceStorage};
 pub use crate::memory::{LinearMemory, Memory, MemoryError, MemoryStyle};
+pub use crate::memory_image::MemoryImage;
 pub use crate::mmap::Mmap;
 pub use crate::module::{ExportsIterator, ImportsIterator, ModuleInfo};
+#[cfg(unix)]
+pub use crate::pooling::{PoolingAllocator, PoolingLimits};
 pub use crate::probestack::PROBESTACK;
 pub use crate::sig_registry::SignatureRegistry;
 pub use crate::table::{LinearTable, Table, TableStyle};
@@ -148,7 +148,7 @@ pub unsafe extern "C" fn wasmer_memory32_grow(
     instance
         .memory_grow(memory_index, delta)
         .map(|pages| pages.0)
-        .unwrap_or(u32::max_value())
+        .unwrap_or_else(|error| instance.memory_grow_failure(error))
 }
 
 /// Implementation of memory.grow for imported 32-bit memories.
@@ -167,7 +167,7 @@ pub unsafe extern "C" fn wasmer_imported_memory32_grow(
     instance
         .imported_memory_grow(memory_index, delta)
         .map(|pages| pages.0)
-        .unwrap_or(u32::max_value())
+        .unwrap_or_else(|error| instance.memory_grow_failure(error))
 }
 
 /// Implementation of memory.size for locally-defined 32-bit memories.
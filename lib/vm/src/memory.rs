@@ -5,6 +5,7 @@
 //!
 //! `LinearMemory` is to WebAssembly linear memories what `Table` is to WebAssembly tables.
 
+use crate::memory_image::MemoryImage;
 use crate::mmap::Mmap;
 use crate::vmcontext::VMMemoryDefinition;
 use more_asserts::{assert_ge, assert_le};
@@ -97,6 +98,25 @@ pub trait Memory: fmt::Debug + Send + Sync {
     ///
     /// The pointer returned in [`VMMemoryDefinition`] must be valid for the lifetime of this memory.
     fn vmmemory(&self) -> NonNull<VMMemoryDefinition>;
+
+    /// Calls `f` with the base pointer and the current length in bytes of
+    /// the memory.
+    ///
+    /// The memory isn't moved by a concurrent `grow` before `f` returns, so
+    /// `f` can access the `length` bytes at `base`.
+    fn with_data(&self, f: &mut dyn FnMut(*mut u8, usize)) {
+        let definition = unsafe { self.vmmemory().as_ref() };
+        f(definition.base, definition.current_length as usize)
+    }
+
+    /// Maps `image` copy-on-write as the initial contents of the memory,
+    /// which must still be zero-filled.
+    ///
+    /// Returns `false` if the memory doesn't support images, in which case
+    /// it's left untouched and the data segments must be copied into it.
+    fn map_image(&self, _image: &MemoryImage) -> Result<bool, MemoryError> {
+        Ok(false)
+    }
 }
 
 /// A linear memory instance.
@@ -307,4 +327,28 @@ impl Memory for LinearMemory {
             as *const VMMemoryDefinition as *mut VMMemoryDefinition;
         unsafe { NonNull::new_unchecked(ptr) }
     }
+
+    /// Calls `f` with the base pointer and the current length in bytes of
+    /// the memory, while holding the lock `grow` takes.
+    fn with_data(&self, f: &mut dyn FnMut(*mut u8, usize)) {
+        let _mmap_guard = self.mmap.lock().unwrap();
+        let definition = unsafe { &*self.vm_memory_definition.get() };
+        f(definition.base, definition.current_length as usize)
+    }
+
+    /// Maps `image` at the start of the memory, if it's a static memory,
+    /// which is never moved, and the image fits in its accessible pages.
+    fn map_image(&self, image: &MemoryImage) -> Result<bool, MemoryError> {
+        if let MemoryStyle::Dynamic { .. } = self.style {
+            return Ok(false);
+        }
+        let mut mmap_guard = self.mmap.lock().unwrap();
+        let mmap = mmap_guard.borrow_mut();
+        if image.len() > mmap.size.bytes().0 {
+            return Ok(false);
+        }
+        unsafe { image.map_at(mmap.alloc.as_mut_ptr()) }
+            .map_err(|e| MemoryError::Region(e.to_string()))?;
+        Ok(true)
+    }
 }
@@ -0,0 +1,143 @@
+//! Copy-on-write images of the initial contents of linear memories.
+//!
+//! A `MemoryImage` holds the data segments of a memory in a `memfd`, so
+//! that they are mapped with `MAP_PRIVATE` at the start of the memory
+//! instead of being copied into it on each instantiation. The pages are
+//! only copied when they are written to.
+
+use std::fmt;
+use std::io;
+
+/// The initial contents of a linear memory, shared by its instances.
+pub struct MemoryImage {
+    #[cfg(target_os = "linux")]
+    file: std::fs::File,
+    len: usize,
+}
+
+impl MemoryImage {
+    /// Creates the image of the `len` first bytes of a memory, zero-filled
+    /// except for the `segments`, given as pairs of offset and data.
+    ///
+    /// `len` must hold all the segments, and is rounded up to the page size.
+    /// The images are only supported on Linux.
+    pub fn new<'data>(
+        len: usize,
+        segments: impl IntoIterator<Item = (usize, &'data [u8])>,
+    ) -> io::Result<Self> {
+        let page_size = region::page::size();
+        let len = (len + (page_size - 1)) & !(page_size - 1);
+        cfg_if::cfg_if! {
+            if #[cfg(target_os = "linux")] {
+                use std::os::unix::fs::FileExt;
+                use std::os::unix::io::FromRawFd;
+
+                let name = b"wasm-memory-image\0";
+                let fd = unsafe {
+                    libc::syscall(
+                        libc::SYS_memfd_create,
+                        name.as_ptr() as *const libc::c_char,
+                        libc::MFD_CLOEXEC,
+                    )
+                };
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let file = unsafe { std::fs::File::from_raw_fd(fd as libc::c_int) };
+                // The file is sparse, and reads as zeros out of the segments.
+                file.set_len(len as u64)?;
+                for (offset, data) in segments {
+                    assert!(offset + data.len() <= len);
+                    file.write_all_at(data, offset as u64)?;
+                }
+                Ok(Self { file, len })
+            } else {
+                let _ = segments;
+                Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "memory images are only supported on Linux",
+                ))
+            }
+        }
+    }
+
+    /// Returns the length in bytes of the image, a multiple of the page
+    /// size.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the image is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Maps the image copy-on-write over the `self.len()` bytes at `ptr`,
+    /// replacing the pages mapped there.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be page-aligned, and the `self.len()` bytes at `ptr` must
+    /// be a mapping owned by the caller.
+    pub(crate) unsafe fn map_at(&self, ptr: *mut u8) -> io::Result<()> {
+        if self.len == 0 {
+            return Ok(());
+        }
+        cfg_if::cfg_if! {
+            if #[cfg(target_os = "linux")] {
+                use std::os::unix::io::AsRawFd;
+
+                let mapped = libc::mmap(
+                    ptr as *mut libc::c_void,
+                    self.len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_FIXED,
+                    self.file.as_raw_fd(),
+                    0,
+                );
+                if mapped == libc::MAP_FAILED {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            } else {
+                let _ = ptr;
+                unreachable!("memory images can't be created on this platform")
+            }
+        }
+    }
+}
+
+impl fmt::Debug for MemoryImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryImage")
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::mmap::Mmap;
+
+    #[test]
+    fn the_mapped_pages_are_private_copies() {
+        let page_size = region::page::size();
+        let image =
+            MemoryImage::new(2 * page_size, vec![(1, &b"abc"[..]), (page_size, b"d")]).unwrap();
+
+        let mut first = Mmap::accessible_reserved(4 * page_size, 4 * page_size).unwrap();
+        let mut second = Mmap::accessible_reserved(4 * page_size, 4 * page_size).unwrap();
+        unsafe {
+            image.map_at(first.as_mut_ptr()).unwrap();
+            image.map_at(second.as_mut_ptr()).unwrap();
+        }
+        first.as_mut_slice()[1] = b'x';
+        first.as_mut_slice()[3 * page_size] = b'y';
+
+        assert_eq!(&first.as_slice()[..4], b"\0xbc");
+        assert_eq!(&second.as_slice()[..4], b"\0abc");
+        assert_eq!(second.as_slice()[page_size], b'd');
+        assert_eq!(second.as_slice()[3 * page_size], 0);
+    }
+}
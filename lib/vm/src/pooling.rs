@@ -0,0 +1,695 @@
+//! A pooling allocator of instances.
+//!
+//! The `PoolingAllocator` reserves up front slots for the storage of the
+//! instances, for their linear memories and for their tables, so that
+//! instantiating a module doesn't map and unmap memory. The slots of an
+//! instance are given back to the pool when it's dropped, once their
+//! pages are reset with `madvise(MADV_DONTNEED)`. A slot failing to reset
+//! is quarantined: it's never handed out again.
+
+use crate::cycles::TableInstances;
+use crate::instance::InstanceAllocator;
+use crate::memory::{Memory, MemoryError, MemoryStyle};
+use crate::mmap::Mmap;
+use crate::table::{Table, TableStyle};
+use crate::trap::{Trap, TrapCode};
+use crate::vmcontext::{VMCallerCheckedAnyfunc, VMMemoryDefinition, VMTableDefinition};
+use std::alloc::Layout;
+use std::cell::UnsafeCell;
+use std::convert::TryInto;
+use std::fmt;
+use std::io;
+use std::mem;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use wasmer_types::{MemoryType, Pages, TableType, Type as ValType};
+
+/// Round `size` up to the nearest multiple of the page size.
+fn round_up_to_page_size(size: usize) -> usize {
+    let page_size = region::page::size();
+    (size + (page_size - 1)) & !(page_size - 1)
+}
+
+/// Resets the `len` bytes at `ptr` to zero-filled pages, and releases the
+/// memory backing them.
+///
+/// The pages are left accessible, unless `accessible` is `false`.
+unsafe fn reset(ptr: *mut u8, len: usize, accessible: bool) -> Result<(), String> {
+    if len == 0 {
+        return Ok(());
+    }
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "linux")] {
+            // The private anonymous pages are zero-filled on their next
+            // access.
+            if libc::madvise(ptr as *mut libc::c_void, len, libc::MADV_DONTNEED) != 0 {
+                return Err(io::Error::last_os_error().to_string());
+            }
+            if !accessible {
+                region::protect(ptr, len, region::Protection::NONE).map_err(|e| e.to_string())?;
+            }
+        } else {
+            // `MADV_DONTNEED` doesn't zero the pages on the other systems,
+            // so they are mapped again.
+            let protection = if accessible {
+                libc::PROT_READ | libc::PROT_WRITE
+            } else {
+                libc::PROT_NONE
+            };
+            let mapped = libc::mmap(
+                ptr as *mut libc::c_void,
+                len,
+                protection,
+                libc::MAP_PRIVATE | libc::MAP_ANON | libc::MAP_FIXED,
+                -1,
+                0,
+            );
+            if mapped as isize == -1_isize {
+                return Err(io::Error::last_os_error().to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The limits of a [`PoolingAllocator`], deciding how many slots it
+/// reserves and their size.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PoolingLimits {
+    /// The maximum number of instances alive at once.
+    pub max_instances: u32,
+
+    /// The size in bytes of the storage of an instance, holding its
+    /// `VMContext`.
+    pub instance_size: usize,
+
+    /// The maximum number of linear memories of an instance.
+    pub max_memories_per_instance: u32,
+
+    /// The maximum size in wasm pages of a linear memory.
+    pub memory_pages: Pages,
+
+    /// The size in bytes of the offset guard after each linear memory.
+    pub memory_offset_guard_size: u64,
+
+    /// The maximum number of tables of an instance.
+    pub max_tables_per_instance: u32,
+
+    /// The maximum number of elements of a table.
+    pub table_elements: u32,
+}
+
+impl Default for PoolingLimits {
+    fn default() -> Self {
+        Self {
+            max_instances: 1000,
+            instance_size: 0x10_0000,
+            max_memories_per_instance: 1,
+            memory_pages: 160.into(),
+            memory_offset_guard_size: 0x1_0000,
+            max_tables_per_instance: 1,
+            table_elements: 10_000,
+        }
+    }
+}
+
+/// Slots of the same size reserved in a single mapping.
+struct Slots {
+    mmap: Mmap,
+    slot_size: usize,
+    free: Mutex<Vec<usize>>,
+    quarantined: AtomicUsize,
+}
+
+impl Slots {
+    fn new(count: usize, slot_size: usize, accessible: bool) -> Result<Self, String> {
+        let slot_size = round_up_to_page_size(slot_size);
+        let len = count
+            .checked_mul(slot_size)
+            .ok_or_else(|| "the pool is too large".to_string())?;
+        let mmap = if accessible {
+            Mmap::accessible_reserved(len, len)?
+        } else {
+            Mmap::accessible_reserved(0, len)?
+        };
+        Ok(Self {
+            mmap,
+            slot_size,
+            // The lowest slots are handed out first.
+            free: Mutex::new((0..count).rev().collect()),
+            quarantined: AtomicUsize::new(0),
+        })
+    }
+
+    fn take(&self) -> Option<usize> {
+        self.free.lock().unwrap().pop()
+    }
+
+    fn give_back(&self, slot: usize) {
+        self.free.lock().unwrap().push(slot);
+    }
+
+    /// Gives back the slot at `ptr` once the `len` bytes at its start are
+    /// reset, as in `reset`. A slot failing to reset is quarantined
+    /// instead, as it could still hold the data of its previous owner.
+    unsafe fn release(&self, ptr: *mut u8, len: usize, accessible: bool) {
+        match reset(ptr, len, accessible) {
+            Ok(()) => self.give_back(self.slot(ptr)),
+            Err(_) => {
+                self.quarantined.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn ptr(&self, slot: usize) -> *mut u8 {
+        unsafe { (self.mmap.as_ptr() as *mut u8).add(slot * self.slot_size) }
+    }
+
+    fn slot(&self, ptr: *mut u8) -> usize {
+        (ptr as usize - self.mmap.as_ptr() as usize) / self.slot_size
+    }
+
+    fn available(&self) -> usize {
+        self.free.lock().unwrap().len()
+    }
+
+    fn quarantined(&self) -> usize {
+        self.quarantined.load(Ordering::Relaxed)
+    }
+}
+
+struct Pool {
+    limits: PoolingLimits,
+    instances: Slots,
+    memories: Slots,
+    tables: Slots,
+}
+
+impl InstanceAllocator for Pool {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, String> {
+        if layout.size() > self.limits.instance_size {
+            return Err(format!(
+                "the instance needs {} bytes, more than the {} bytes of the pool slots",
+                layout.size(),
+                self.limits.instance_size
+            ));
+        }
+        assert!(layout.align() <= region::page::size());
+        let slot = self.instances.take().ok_or_else(|| {
+            format!(
+                "the maximum number of instances ({}) is reached",
+                self.limits.max_instances
+            )
+        })?;
+        Ok(NonNull::new(self.instances.ptr(slot)).unwrap())
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.instances
+            .release(ptr.as_ptr(), round_up_to_page_size(layout.size()), true);
+    }
+}
+
+/// A pooling allocator of instances, with slots for their storage, their
+/// linear memories and their tables, reserved up front and reused.
+///
+/// The allocator is plugged in the engine through `Tunables`. The pooled
+/// linear memories use the dynamic style, so that the bounds checks don't
+/// depend on the size of the slots, but never move.
+#[derive(Clone)]
+pub struct PoolingAllocator {
+    pool: Arc<Pool>,
+}
+
+impl PoolingAllocator {
+    /// Creates a new pooling allocator, reserving all its slots.
+    pub fn new(limits: PoolingLimits) -> Result<Self, String> {
+        let max_instances = limits.max_instances as usize;
+        let memory_bytes = limits.memory_pages.bytes().0;
+        let memory_slot_size = memory_bytes
+            .checked_add(limits.memory_offset_guard_size as usize)
+            .ok_or_else(|| "the memory slots are too large".to_string())?;
+        let table_slot_size = (limits.table_elements as usize)
+            .checked_mul(mem::size_of::<VMCallerCheckedAnyfunc>())
+            .ok_or_else(|| "the table slots are too large".to_string())?;
+        let pool = Pool {
+            instances: Slots::new(max_instances, limits.instance_size, true)?,
+            memories: Slots::new(
+                max_instances * limits.max_memories_per_instance as usize,
+                memory_slot_size,
+                false,
+            )?,
+            tables: Slots::new(
+                max_instances * limits.max_tables_per_instance as usize,
+                table_slot_size,
+                true,
+            )?,
+            limits,
+        };
+        Ok(Self {
+            pool: Arc::new(pool),
+        })
+    }
+
+    /// Returns the limits of this allocator.
+    pub fn limits(&self) -> &PoolingLimits {
+        &self.pool.limits
+    }
+
+    /// Returns the number of instances that can still be allocated.
+    pub fn available_instances(&self) -> usize {
+        self.pool.instances.available()
+    }
+
+    /// Returns the number of slots that failed to reset when given back,
+    /// and that are never handed out again.
+    pub fn quarantined_slots(&self) -> usize {
+        self.pool.instances.quarantined()
+            + self.pool.memories.quarantined()
+            + self.pool.tables.quarantined()
+    }
+
+    /// Returns the allocator of the storage of the instances.
+    pub fn instance_allocator(&self) -> Arc<dyn InstanceAllocator> {
+        self.pool.clone()
+    }
+
+    /// Returns the style of the pooled linear memories.
+    pub fn memory_style(&self) -> MemoryStyle {
+        MemoryStyle::Dynamic {
+            offset_guard_size: self.pool.limits.memory_offset_guard_size,
+        }
+    }
+
+    /// Creates a linear memory in a slot of the pool.
+    pub fn create_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+    ) -> Result<Arc<dyn Memory>, MemoryError> {
+        Ok(Arc::new(PooledMemory::new(self.pool.clone(), ty, style)?))
+    }
+
+    /// Creates a table in a slot of the pool.
+    pub fn create_table(
+        &self,
+        ty: &TableType,
+        style: &TableStyle,
+    ) -> Result<Arc<dyn Table>, String> {
+        Ok(Arc::new(PooledTable::new(self.pool.clone(), ty, style)?))
+    }
+}
+
+impl fmt::Debug for PoolingAllocator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PoolingAllocator")
+            .field("limits", &self.pool.limits)
+            .finish()
+    }
+}
+
+/// A linear memory in a slot of a pool.
+struct PooledMemory {
+    pool: Arc<Pool>,
+    base: *mut u8,
+    // The current size in wasm pages, locked while growing.
+    size: Mutex<Pages>,
+    maximum: Pages,
+    memory: MemoryType,
+    style: MemoryStyle,
+    vm_memory_definition: Box<UnsafeCell<VMMemoryDefinition>>,
+}
+
+/// This is correct because the memory is only mutated while holding the
+/// lock on its size.
+unsafe impl Send for PooledMemory {}
+/// This is correct because all internal mutability is protected by a mutex.
+unsafe impl Sync for PooledMemory {}
+
+impl PooledMemory {
+    fn new(pool: Arc<Pool>, memory: &MemoryType, style: &MemoryStyle) -> Result<Self, MemoryError> {
+        let limits = &pool.limits;
+        if memory.minimum > limits.memory_pages {
+            return Err(MemoryError::InvalidMemory {
+                reason: format!(
+                    "the minimum ({} pages) is more than the {} pages of the pool slots",
+                    memory.minimum.0, limits.memory_pages.0
+                ),
+            });
+        }
+        if let Some(maximum) = memory.maximum {
+            if maximum < memory.minimum {
+                return Err(MemoryError::InvalidMemory {
+                    reason: format!(
+                        "the maximum ({} pages) is less than the minimum ({} pages)",
+                        maximum.0, memory.minimum.0
+                    ),
+                });
+            }
+        }
+        // The compiled code of the static memories doesn't check the
+        // accesses below their bound.
+        let fits = match style {
+            MemoryStyle::Dynamic { offset_guard_size } => {
+                *offset_guard_size <= limits.memory_offset_guard_size
+            }
+            MemoryStyle::Static {
+                bound,
+                offset_guard_size,
+            } => {
+                *bound <= limits.memory_pages
+                    && *offset_guard_size <= limits.memory_offset_guard_size
+            }
+        };
+        if !fits {
+            return Err(MemoryError::InvalidMemory {
+                reason: format!("the style {:?} doesn't fit in the pool slots", style),
+            });
+        }
+
+        let slot = pool.memories.take().ok_or_else(|| {
+            MemoryError::Region(format!(
+                "the maximum number of memories ({}) is reached",
+                limits.max_instances * limits.max_memories_per_instance
+            ))
+        })?;
+        let base = pool.memories.ptr(slot);
+        let minimum_bytes = memory.minimum.bytes().0;
+        if let Err(e) =
+            unsafe { region::protect(base, minimum_bytes, region::Protection::READ_WRITE) }
+        {
+            pool.memories.give_back(slot);
+            return Err(MemoryError::Region(e.to_string()));
+        }
+
+        let maximum = memory.maximum.map_or(limits.memory_pages, |maximum| {
+            maximum.min(limits.memory_pages)
+        });
+        Ok(Self {
+            base,
+            size: Mutex::new(memory.minimum),
+            maximum,
+            memory: *memory,
+            style: style.clone(),
+            vm_memory_definition: Box::new(UnsafeCell::new(VMMemoryDefinition {
+                base,
+                current_length: minimum_bytes.try_into().unwrap(),
+            })),
+            pool,
+        })
+    }
+}
+
+impl fmt::Debug for PooledMemory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PooledMemory")
+            .field("base", &self.base)
+            .field("size", &self.size)
+            .field("memory", &self.memory)
+            .field("style", &self.style)
+            .finish()
+    }
+}
+
+impl Memory for PooledMemory {
+    fn ty(&self) -> &MemoryType {
+        &self.memory
+    }
+
+    fn style(&self) -> &MemoryStyle {
+        &self.style
+    }
+
+    fn size(&self) -> Pages {
+        *self.size.lock().unwrap()
+    }
+
+    fn grow(&self, delta: Pages) -> Result<Pages, MemoryError> {
+        let mut size = self.size.lock().unwrap();
+        let prev_pages = *size;
+        let new_pages = prev_pages
+            .checked_add(delta)
+            .filter(|new_pages| *new_pages <= self.maximum)
+            .ok_or(MemoryError::CouldNotGrow {
+                current: prev_pages,
+                attempted_delta: delta,
+            })?;
+        if delta.0 == 0 {
+            return Ok(prev_pages);
+        }
+
+        let prev_bytes = prev_pages.bytes().0;
+        unsafe {
+            region::protect(
+                self.base.add(prev_bytes),
+                delta.bytes().0,
+                region::Protection::READ_WRITE,
+            )
+        }
+        .map_err(|e| MemoryError::Region(e.to_string()))?;
+        *size = new_pages;
+        unsafe {
+            let md = &mut *self.vm_memory_definition.get();
+            md.current_length = new_pages.bytes().0.try_into().unwrap();
+        }
+        Ok(prev_pages)
+    }
+
+    fn vmmemory(&self) -> NonNull<VMMemoryDefinition> {
+        let _size_guard = self.size.lock().unwrap();
+        let ptr = self.vm_memory_definition.as_ref() as *const UnsafeCell<VMMemoryDefinition>
+            as *const VMMemoryDefinition as *mut VMMemoryDefinition;
+        unsafe { NonNull::new_unchecked(ptr) }
+    }
+
+    fn with_data(&self, f: &mut dyn FnMut(*mut u8, usize)) {
+        let size = self.size.lock().unwrap();
+        f(self.base, size.bytes().0)
+    }
+}
+
+impl Drop for PooledMemory {
+    fn drop(&mut self) {
+        let size = self.size.get_mut().unwrap().bytes().0;
+        unsafe { self.pool.memories.release(self.base, size, false) };
+    }
+}
+
+/// A table in a slot of a pool.
+struct PooledTable {
+    pool: Arc<Pool>,
+    base: *mut VMCallerCheckedAnyfunc,
+    // The current number of elements, locked while accessing them.
+    size: Mutex<u32>,
+    maximum: u32,
+    table: TableType,
+    style: TableStyle,
+    vm_table_definition: Box<UnsafeCell<VMTableDefinition>>,
+    // The instances whose functions are stored in this table.
+    instances: TableInstances,
+}
+
+/// This is correct because there is no thread-specific data tied to this type.
+unsafe impl Send for PooledTable {}
+/// This is correct because all internal mutability is protected by a mutex.
+unsafe impl Sync for PooledTable {}
+
+impl PooledTable {
+    fn new(pool: Arc<Pool>, table: &TableType, style: &TableStyle) -> Result<Self, String> {
+        match table.ty {
+            ValType::FuncRef => (),
+            ty => return Err(format!("tables of types other than anyfunc ({})", ty)),
+        };
+        let table_elements = pool.limits.table_elements;
+        if table.minimum > table_elements {
+            return Err(format!(
+                "Table minimum ({}) is larger than the {} elements of the pool slots",
+                table.minimum, table_elements
+            ));
+        }
+        if let Some(max) = table.maximum {
+            if max < table.minimum {
+                return Err(format!(
+                    "Table minimum ({}) is larger than maximum ({})!",
+                    table.minimum, max
+                ));
+            }
+        }
+        let slot = pool.tables.take().ok_or_else(|| {
+            format!(
+                "the maximum number of tables ({}) is reached",
+                pool.limits.max_instances * pool.limits.max_tables_per_instance
+            )
+        })?;
+        let base = pool.tables.ptr(slot) as *mut VMCallerCheckedAnyfunc;
+        // The reset pages are zero-filled, which isn't a null element.
+        for index in 0..table.minimum as usize {
+            unsafe { ptr::write(base.add(index), VMCallerCheckedAnyfunc::default()) };
+        }
+        Ok(Self {
+            base,
+            size: Mutex::new(table.minimum),
+            maximum: table
+                .maximum
+                .map_or(table_elements, |maximum| maximum.min(table_elements)),
+            table: *table,
+            style: style.clone(),
+            vm_table_definition: Box::new(UnsafeCell::new(VMTableDefinition {
+                base: base as _,
+                current_elements: table.minimum,
+            })),
+            instances: TableInstances::new(),
+            pool,
+        })
+    }
+}
+
+impl fmt::Debug for PooledTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PooledTable")
+            .field("base", &self.base)
+            .field("size", &self.size)
+            .field("table", &self.table)
+            .field("style", &self.style)
+            .finish()
+    }
+}
+
+impl Table for PooledTable {
+    fn style(&self) -> &TableStyle {
+        &self.style
+    }
+
+    fn ty(&self) -> &TableType {
+        &self.table
+    }
+
+    fn size(&self) -> u32 {
+        *self.size.lock().unwrap()
+    }
+
+    fn grow(&self, delta: u32) -> Option<u32> {
+        let mut size = self.size.lock().unwrap();
+        let prev_size = *size;
+        let new_size = prev_size
+            .checked_add(delta)
+            .filter(|new_size| *new_size <= self.maximum)?;
+        for index in prev_size as usize..new_size as usize {
+            unsafe { ptr::write(self.base.add(index), VMCallerCheckedAnyfunc::default()) };
+        }
+        *size = new_size;
+        unsafe {
+            let td = &mut *self.vm_table_definition.get();
+            td.current_elements = new_size;
+        }
+        Some(prev_size)
+    }
+
+    fn get(&self, index: u32) -> Option<VMCallerCheckedAnyfunc> {
+        let size = self.size.lock().unwrap();
+        if index >= *size {
+            return None;
+        }
+        Some(unsafe { (*self.base.add(index as usize)).clone() })
+    }
+
+    fn set(&self, index: u32, func: VMCallerCheckedAnyfunc) -> Result<(), Trap> {
+        let size = self.size.lock().unwrap();
+        if index >= *size {
+            return Err(Trap::new_from_runtime(TrapCode::TableAccessOutOfBounds));
+        }
+        unsafe { *self.base.add(index as usize) = func };
+        Ok(())
+    }
+
+    fn vmtable(&self) -> NonNull<VMTableDefinition> {
+        let _size_guard = self.size.lock().unwrap();
+        let ptr = self.vm_table_definition.as_ref() as *const UnsafeCell<VMTableDefinition>
+            as *const VMTableDefinition as *mut VMTableDefinition;
+        unsafe { NonNull::new_unchecked(ptr) }
+    }
+
+    fn instances(&self) -> &TableInstances {
+        &self.instances
+    }
+}
+
+impl Drop for PooledTable {
+    fn drop(&mut self) {
+        let size = *self.size.get_mut().unwrap() as usize;
+        let len = round_up_to_page_size(size * mem::size_of::<VMCallerCheckedAnyfunc>());
+        unsafe { self.pool.tables.release(self.base as *mut u8, len, true) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> PoolingLimits {
+        PoolingLimits {
+            max_instances: 2,
+            memory_pages: 2.into(),
+            table_elements: 10,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn memory_slots_are_reset_and_reused() {
+        let allocator = PoolingAllocator::new(limits()).unwrap();
+        let ty = MemoryType::new(1, None, false);
+        let style = allocator.memory_style();
+        let memory = allocator.create_memory(&ty, &style).unwrap();
+        let base = unsafe { memory.vmmemory().as_ref().base };
+        unsafe { *base = 42 };
+        assert_eq!(memory.grow(1.into()), Ok(1.into()));
+        assert!(memory.grow(1.into()).is_err());
+        let _other = allocator.create_memory(&ty, &style).unwrap();
+        assert!(allocator.create_memory(&ty, &style).is_err());
+
+        drop(memory);
+        let memory = allocator.create_memory(&ty, &style).unwrap();
+        assert_eq!(unsafe { memory.vmmemory().as_ref().base }, base);
+        assert_eq!(unsafe { *base }, 0);
+        assert_eq!(memory.size(), 1.into());
+    }
+
+    #[test]
+    fn table_slots_are_reset_and_reused() {
+        let allocator = PoolingAllocator::new(limits()).unwrap();
+        let ty = TableType::new(ValType::FuncRef, 1, None);
+        let style = TableStyle::CallerChecksSignature;
+        let table = allocator.create_table(&ty, &style).unwrap();
+        let func = VMCallerCheckedAnyfunc {
+            type_index: Default::default(),
+            func_ptr: 0x1000 as *const _,
+            vmctx: ptr::null_mut(),
+        };
+        table.set(0, func).unwrap();
+        assert!(table.set(1, Default::default()).is_err());
+        assert_eq!(table.grow(9), Some(1));
+        assert_eq!(table.grow(1), None);
+
+        drop(table);
+        let table = allocator.create_table(&ty, &style).unwrap();
+        assert_eq!(table.size(), 1);
+        assert!(table.get(0).unwrap().func_ptr.is_null());
+    }
+
+    #[test]
+    fn the_number_of_instances_is_limited() {
+        let allocator = PoolingAllocator::new(limits()).unwrap();
+        let instances = allocator.instance_allocator();
+        let layout = Layout::from_size_align(64, 16).unwrap();
+        let first = instances.allocate(layout).unwrap();
+        let _second = instances.allocate(layout).unwrap();
+        assert!(instances.allocate(layout).is_err());
+        assert_eq!(allocator.available_instances(), 0);
+
+        unsafe { instances.deallocate(first, layout) };
+        assert_eq!(allocator.available_instances(), 1);
+        assert_eq!(instances.allocate(layout).unwrap(), first);
+    }
+}
@@ -5,6 +5,8 @@
 //!
 //! `Table` is to WebAssembly tables what `LinearMemory` is to WebAssembly linear memories.
 
+use crate::cycles::TableInstances;
+use crate::instance::InstanceRef;
 use crate::trap::{Trap, TrapCode};
 use crate::vmcontext::{VMCallerCheckedAnyfunc, VMTableDefinition};
 use serde::{Deserialize, Serialize};
@@ -55,6 +57,16 @@ pub trait Table: fmt::Debug + Send + Sync {
     /// Return a `VMTableDefinition` for exposing the table to compiled wasm code.
     fn vmtable(&self) -> NonNull<VMTableDefinition>;
 
+    /// Returns the instances kept alive by this table, as their functions
+    /// are stored in it.
+    fn instances(&self) -> &TableInstances;
+
+    /// Keeps `instance` alive as long as this table, as its functions are
+    /// stored in it.
+    fn keep_alive(&self, instance: InstanceRef) {
+        self.instances().keep_alive(instance);
+    }
+
     /// Copy `len` elements from `src_table[src_index..]` into `dst_table[dst_index..]`.
     ///
     /// # Errors
@@ -98,6 +110,13 @@ pub trait Table: fmt::Debug + Send + Sync {
             }
         }
 
+        // The copied functions are kept alive by this table too.
+        if len > 0 {
+            for instance in src_table.instances().to_vec() {
+                self.keep_alive(instance);
+            }
+        }
+
         Ok(())
     }
 }
@@ -113,6 +132,8 @@ pub struct LinearTable {
     /// Our chosen implementation style.
     style: TableStyle,
     vm_table_definition: Box<UnsafeCell<VMTableDefinition>>,
+    /// The instances whose functions are stored in this table.
+    instances: TableInstances,
 }
 
 /// This is correct because there is no thread-specific data tied to this type.
@@ -149,6 +170,7 @@ impl LinearTable {
                     base: base as _,
                     current_elements: table_minimum as _,
                 })),
+                instances: TableInstances::new(),
             }),
         }
     }
@@ -230,4 +252,9 @@ impl Table for LinearTable {
             as *const VMTableDefinition as *mut VMTableDefinition;
         unsafe { NonNull::new_unchecked(ptr) }
     }
+
+    /// Returns the instances kept alive by this table.
+    fn instances(&self) -> &TableInstances {
+        &self.instances
+    }
 }
@@ -22,19 +22,18 @@ mod utils;
 use crate::syscalls::*;
 
 pub use crate::state::{
-    Fd, WasiFile, WasiFs, WasiFsError, WasiState, WasiStateBuilder, WasiStateCreationError,
-    ALL_RIGHTS, VIRTUAL_ROOT_FD,
+    Fd, Pipe, PollEvent, PollEventBuilder, PollEventSet, WasiFile, WasiFs, WasiFsError, WasiState,
+    WasiStateBuilder, WasiStateCreationError, ALL_RIGHTS, VIRTUAL_ROOT_FD,
 };
 pub use crate::syscalls::types;
 pub use crate::utils::{get_wasi_version, is_wasi_module, WasiVersion};
 
 use thiserror::Error;
-use wasmer::{imports, Function, ImportObject, Memory, Module, Store};
+use wasmer::{
+    imports, ExportError, Function, HostEnvInitError, ImportObject, Instance, LazyInit, Memory,
+    Module, Store, WasmerEnv,
+};
 
-use std::cell::UnsafeCell;
-use std::fmt;
-use std::mem::MaybeUninit;
-use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, MutexGuard};
 
 /// This is returned in `RuntimeError`.
@@ -48,91 +47,36 @@ pub enum WasiError {
 }
 
 /// The environment provided to the WASI imports.
+///
+/// The memory is bound to the `memory` export of the instance importing
+/// the WASI functions when it is created. An instance importing its memory
+/// instead must be given it with [`WasiEnv::set_memory`].
+///
+/// The clones of a `WasiEnv` share its memory, so an environment serves a
+/// single instance: creating another instance exporting a different memory
+/// with it fails.
 #[derive(Debug, Clone)]
 pub struct WasiEnv {
     state: Arc<Mutex<WasiState>>,
-    memory: Arc<WasiMemory>,
-}
-
-/// Wrapper type around `Memory` used to delay initialization of the memory.
-///
-/// The `initialized` field is used to indicate if it's safe to read `memory` as `Memory`.
-///
-/// The `mutate_lock` is used to prevent access from multiple threads during initialization.
-struct WasiMemory {
-    initialized: AtomicBool,
-    memory: UnsafeCell<MaybeUninit<Memory>>,
-    mutate_lock: Mutex<()>,
-}
-
-impl fmt::Debug for WasiMemory {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("WasiMemory")
-            .field("initialized", &self.initialized)
-            .finish()
-    }
-}
-
-impl WasiMemory {
-    fn new() -> Self {
-        Self {
-            initialized: AtomicBool::new(false),
-            memory: UnsafeCell::new(MaybeUninit::zeroed()),
-            mutate_lock: Mutex::new(()),
-        }
-    }
-
-    /// Initialize the memory, making it safe to read from.
-    ///
-    /// Returns whether or not the set was successful. If the set failed then
-    /// the memory has already been initialized.
-    fn set_memory(&self, memory: Memory) -> bool {
-        // synchronize it
-        let _guard = self.mutate_lock.lock();
-        if self.initialized.load(Ordering::Acquire) {
-            return false;
-        }
-
-        unsafe {
-            let ptr = self.memory.get();
-            let mem_inner: &mut MaybeUninit<Memory> = &mut *ptr;
-            mem_inner.as_mut_ptr().write(memory);
-        }
-        self.initialized.store(true, Ordering::Release);
-
-        true
-    }
-
-    /// Returns `None` if the memory has not been initialized yet.
-    /// Otherwise returns the memory that was used to initialize it.
-    fn get_memory(&self) -> Option<&Memory> {
-        // Based on normal usage, `Relaxed` is fine...
-        // TODO: investigate if it's possible to use the API in a way where `Relaxed`
-        //       is not fine
-        if self.initialized.load(Ordering::Relaxed) {
-            unsafe {
-                let maybe_mem = self.memory.get();
-                Some(&*(*maybe_mem).as_ptr())
-            }
-        } else {
-            None
-        }
-    }
+    memory: LazyInit<Memory>,
 }
 
-impl Drop for WasiMemory {
-    fn drop(&mut self) {
-        if self.initialized.load(Ordering::Acquire) {
-            unsafe {
-                // We want to get the internal value in memory, so we need to consume
-                // the `UnsafeCell` and assume the `MapbeInit` is initialized, but because
-                // we only have a `&mut self` we can't do this directly, so we swap the data
-                // out so we can drop it (via `assume_init`).
-                let mut maybe_uninit = UnsafeCell::new(MaybeUninit::zeroed());
-                std::mem::swap(&mut self.memory, &mut maybe_uninit);
-                maybe_uninit.into_inner().assume_init();
+impl WasmerEnv for WasiEnv {
+    fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+        let memory = match instance.exports.get_memory("memory") {
+            Ok(memory) => memory,
+            // The memory is imported, and set with `set_memory`.
+            Err(ExportError::Missing(_)) => return Ok(()),
+            Err(ExportError::IncompatibleType) => {
+                return Err(HostEnvInitError::IncompatibleExport("memory".to_string()))
             }
+        };
+        if !self.memory.initialize(memory.clone()) && !self.memory().same(memory) {
+            return Err(HostEnvInitError::Generic(
+                "the `WasiEnv` is already bound to the memory of another instance".to_string(),
+            ));
         }
+        Ok(())
     }
 }
 
@@ -140,7 +84,7 @@ impl WasiEnv {
     pub fn new(state: WasiState) -> Self {
         Self {
             state: Arc::new(Mutex::new(state)),
-            memory: Arc::new(WasiMemory::new()),
+            memory: LazyInit::new(),
         }
     }
 
@@ -153,9 +97,16 @@ impl WasiEnv {
         ))
     }
 
-    /// Set the memory
+    /// Set the memory.
+    ///
+    /// This is only needed when the memory is not exported by the instance
+    /// importing the WASI functions, which binds it otherwise. It must be
+    /// called before the instance runs any WASI function.
+    ///
+    /// Returns `false`, leaving the memory untouched, if it was already
+    /// set.
     pub fn set_memory(&mut self, memory: Memory) -> bool {
-        self.memory.set_memory(memory)
+        self.memory.initialize(memory)
     }
 
     /// Get the WASI state
@@ -170,7 +121,7 @@ impl WasiEnv {
 
     /// Get a reference to the memory
     pub fn memory(&self) -> &Memory {
-        self.memory.get_memory().expect("The expected Memory is not attached to the `WasiEnv`. Did you forgot to call wasi_env.set_memory(...)?")
+        self.memory.get_ref().expect("The expected Memory is not attached to the `WasiEnv`. Does the instance export it as `memory`? Otherwise, call wasi_env.set_memory(...)")
     }
 
     pub(crate) fn get_memory_and_wasi_state(
@@ -1,6 +1,6 @@
 //! Builder system for configuring a [`WasiState`] and creating it.
 
-use crate::state::{WasiFile, WasiFs, WasiFsError, WasiState};
+use crate::state::{Determinism, WasiFile, WasiFs, WasiFsError, WasiState};
 use crate::syscalls::types::{__WASI_STDERR_FILENO, __WASI_STDIN_FILENO, __WASI_STDOUT_FILENO};
 use crate::WasiEnv;
 use std::path::{Path, PathBuf};
@@ -42,6 +42,7 @@ pub struct WasiStateBuilder {
     stdout_override: Option<Box<dyn WasiFile>>,
     stderr_override: Option<Box<dyn WasiFile>>,
     stdin_override: Option<Box<dyn WasiFile>>,
+    deterministic_seed: Option<u64>,
 }
 
 impl std::fmt::Debug for WasiStateBuilder {
@@ -54,6 +55,7 @@ impl std::fmt::Debug for WasiStateBuilder {
             .field("stdout_override exists", &self.stdout_override.is_some())
             .field("stderr_override exists", &self.stderr_override.is_some())
             .field("stdin_override exists", &self.stdin_override.is_some())
+            .field("deterministic_seed", &self.deterministic_seed)
             .finish()
     }
 }
@@ -309,6 +311,17 @@ impl WasiStateBuilder {
         self
     }
 
+    /// Makes the clocks and `random_get` deterministic, for reproducible
+    /// executions.
+    ///
+    /// The clocks start at 0 and advance by one microsecond every time they
+    /// are read, and the random bytes are generated from `seed`.
+    pub fn deterministic(&mut self, seed: u64) -> &mut Self {
+        self.deterministic_seed = Some(seed);
+
+        self
+    }
+
     /// Consumes the [`WasiStateBuilder`] and produces a [`WasiState`]
     ///
     /// Returns the error from `WasiFs::new` if there's an error
@@ -387,6 +400,7 @@ impl WasiStateBuilder {
             fs: wasi_fs,
             args: self.args.clone(),
             envs: self.envs.clone(),
+            determinism: self.deterministic_seed.map(Determinism::new),
         })
     }
 
@@ -404,6 +418,7 @@ impl WasiStateBuilder {
 pub struct PreopenDirBuilder {
     path: Option<PathBuf>,
     alias: Option<String>,
+    in_memory: bool,
     read: bool,
     write: bool,
     create: bool,
@@ -443,6 +458,17 @@ impl PreopenDirBuilder {
         self
     }
 
+    /// Keep this preopened directory in memory, instead of pointing it to a
+    /// host directory
+    ///
+    /// Its files are buffers, which live as long as the `WasiState`. It
+    /// appears to the WASI program as its `alias`.
+    pub fn in_memory(&mut self, toggle: bool) -> &mut Self {
+        self.in_memory = toggle;
+
+        self
+    }
+
     /// Set read permissions affecting files in the directory
     pub fn read(&mut self, toggle: bool) -> &mut Self {
         self.read = toggle;
@@ -475,16 +501,28 @@ impl PreopenDirBuilder {
             return Err(WasiStateCreationError::PreopenedDirectoryError("Preopened directories must have at least one of read, write, create permissions set".to_string()));
         }
 
-        if self.path.is_none() {
-            return Err(WasiStateCreationError::PreopenedDirectoryError(
-                "Preopened directories must point to a host directory".to_string(),
-            ));
-        }
-        let path = self.path.clone().unwrap();
+        let path = if self.in_memory {
+            if self.path.is_some() || self.alias.is_none() {
+                return Err(WasiStateCreationError::PreopenedDirectoryError(
+                    "Preopened directories in memory must have an alias, and no host directory"
+                        .to_string(),
+                ));
+            }
+            // an empty path marks the directories in memory
+            PathBuf::new()
+        } else {
+            if self.path.is_none() {
+                return Err(WasiStateCreationError::PreopenedDirectoryError(
+                    "Preopened directories must point to a host directory".to_string(),
+                ));
+            }
+            let path = self.path.clone().unwrap();
 
-        if !path.exists() {
-            return Err(WasiStateCreationError::PreopenedDirectoryNotFound(path));
-        }
+            if !path.exists() {
+                return Err(WasiStateCreationError::PreopenedDirectoryNotFound(path));
+            }
+            path
+        };
         if let Some(alias) = &self.alias {
             validate_mapped_dir_alias(alias)?;
         }
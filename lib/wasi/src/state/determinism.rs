@@ -0,0 +1,82 @@
+use crate::syscalls::types::*;
+use serde::{Deserialize, Serialize};
+
+/// The resolution of the deterministic clocks, in nanoseconds.
+pub(crate) const DETERMINISTIC_CLOCK_RESOLUTION: __wasi_timestamp_t = 1_000;
+
+/// The deterministic replacements of the clocks and of the random source,
+/// derived from a seed.
+///
+/// All the clocks start at 0, and advance by their resolution every time
+/// they are read. The random bytes are generated with SplitMix64.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Determinism {
+    time: __wasi_timestamp_t,
+    random_state: u64,
+}
+
+impl Determinism {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self {
+            time: 0,
+            random_state: seed,
+        }
+    }
+
+    /// Reads the current time, and advances it.
+    pub(crate) fn now(&mut self) -> __wasi_timestamp_t {
+        self.time += DETERMINISTIC_CLOCK_RESOLUTION;
+        self.time
+    }
+
+    /// Reads the current time, without advancing it.
+    pub(crate) fn time(&self) -> __wasi_timestamp_t {
+        self.time
+    }
+
+    /// Advances the time by `duration`, rather than waiting for it.
+    pub(crate) fn sleep(&mut self, duration: __wasi_timestamp_t) {
+        self.time = self.time.saturating_add(duration);
+    }
+
+    pub(crate) fn fill_random(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(8) {
+            let bytes = self.next_random().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.random_state = self.random_state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.random_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let (mut a, mut b) = (Determinism::new(42), Determinism::new(42));
+        let (mut x, mut y) = ([0; 13], [0; 13]);
+        a.fill_random(&mut x);
+        b.fill_random(&mut y);
+        assert_eq!(x, y);
+        assert_ne!(x, [0; 13]);
+
+        let mut z = [0; 13];
+        Determinism::new(43).fill_random(&mut z);
+        assert_ne!(x, z);
+    }
+
+    #[test]
+    fn clock_advances() {
+        let mut determinism = Determinism::new(0);
+        assert_eq!(determinism.now(), DETERMINISTIC_CLOCK_RESOLUTION);
+        assert_eq!(determinism.now(), 2 * DETERMINISTIC_CLOCK_RESOLUTION);
+    }
+}
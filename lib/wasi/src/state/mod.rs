@@ -16,9 +16,12 @@
 #![allow(clippy::cognitive_complexity, clippy::too_many_arguments)]
 
 mod builder;
+mod determinism;
+mod ops;
 mod types;
 
 pub use self::builder::*;
+pub(crate) use self::determinism::*;
 pub use self::types::*;
 use crate::syscalls::types::*;
 use generational_arena::Arena;
@@ -28,9 +31,10 @@ use std::collections::HashMap;
 use std::{
     borrow::Borrow,
     cell::Cell,
+    convert::TryFrom,
     fs,
     io::Write,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
     time::SystemTime,
 };
 use tracing::debug;
@@ -85,7 +89,8 @@ pub enum Kind {
     Dir {
         /// Parent directory
         parent: Option<Inode>,
-        /// The path on the host system where the directory is located
+        /// The path on the host system where the directory is located, or
+        /// an empty path for a directory in memory
         // TODO: wrap it like WasiFile
         path: PathBuf,
         /// The entries of a directory are lazily filled.
@@ -109,7 +114,8 @@ pub enum Kind {
         base_po_dir: __wasi_fd_t,
         /// The path to the symlink from the `base_po_dir`
         path_to_symlink: PathBuf,
-        /// the value of the symlink as a relative path
+        /// The value of the symlink, as a path relative to the directory
+        /// containing the symlink
         relative_path: PathBuf,
     },
     Buffer {
@@ -306,15 +312,13 @@ impl WasiFs {
                 &path.to_string_lossy(),
                 &alias
             );
-            let cur_dir_metadata = path.metadata().map_err(|e| {
-                format!(
-                    "Could not get metadata for file {:?}: {}",
-                    path,
-                    e.to_string()
-                )
-            })?;
+            let is_dir = is_in_memory(path)
+                || path
+                    .metadata()
+                    .map_err(|e| format!("Could not get metadata for file {:?}: {}", path, e))?
+                    .is_dir();
 
-            let kind = if cur_dir_metadata.is_dir() {
+            let kind = if is_dir {
                 Kind::Dir {
                     parent: Some(root_inode),
                     path: path.clone(),
@@ -364,7 +368,8 @@ impl WasiFs {
                         | __WASI_RIGHT_PATH_CREATE_FILE
                         | __WASI_RIGHT_PATH_LINK_TARGET
                         | __WASI_RIGHT_PATH_OPEN
-                        | __WASI_RIGHT_PATH_RENAME_TARGET;
+                        | __WASI_RIGHT_PATH_RENAME_TARGET
+                        | __WASI_RIGHT_PATH_SYMLINK;
                 }
 
                 rights
@@ -493,13 +498,15 @@ impl WasiFs {
 
     /// Internal helper function to get a standard device handle.
     /// Expects one of `__WASI_STDIN_FILENO`, `__WASI_STDOUT_FILENO`, `__WASI_STDERR_FILENO`.
-    fn std_dev_get(&self, fd: __wasi_fd_t) -> Result<&Option<Box<dyn WasiFile>>, WasiFsError> {
+    pub(crate) fn std_dev_get(
+        &self,
+        fd: __wasi_fd_t,
+    ) -> Result<&Option<Box<dyn WasiFile>>, WasiFsError> {
         if let Some(fd) = self.fd_map.get(&fd) {
-            if let Kind::File { ref handle, .. } = self.inodes[fd.inode].kind {
-                Ok(handle)
-            } else {
-                // Our public API should ensure that this is not possible
-                unreachable!("Non-file found in standard device location")
+            match self.inodes.get(fd.inode).map(|inode| &inode.kind) {
+                Some(Kind::File { ref handle, .. }) => Ok(handle),
+                // the guest can renumber any fd into a standard device location
+                _ => Err(WasiFsError::NotAFile),
             }
         } else {
             // this should only trigger if we made a mistake in this crate
@@ -513,11 +520,10 @@ impl WasiFs {
         fd: __wasi_fd_t,
     ) -> Result<&mut Option<Box<dyn WasiFile>>, WasiFsError> {
         if let Some(fd) = self.fd_map.get_mut(&fd) {
-            if let Kind::File { ref mut handle, .. } = self.inodes[fd.inode].kind {
-                Ok(handle)
-            } else {
-                // Our public API should ensure that this is not possible
-                unreachable!("Non-file found in standard device location")
+            match self.inodes.get_mut(fd.inode).map(|inode| &mut inode.kind) {
+                Some(Kind::File { ref mut handle, .. }) => Ok(handle),
+                // the guest can renumber any fd into a standard device location
+                _ => Err(WasiFsError::NotAFile),
             }
         } else {
             // this should only trigger if we made a mistake in this crate
@@ -696,17 +702,22 @@ impl WasiFs {
         &mut self,
         fd: __wasi_fd_t,
     ) -> Result<__wasi_filesize_t, __wasi_errno_t> {
-        let fd = self.fd_map.get_mut(&fd).ok_or(__WASI_EBADF)?;
-        match &mut self.inodes[fd.inode].kind {
+        let inode = self.get_inodeval_mut(fd)?;
+        match &mut inode.kind {
             Kind::File { handle, .. } => {
                 if let Some(h) = handle {
                     let new_size = h.size();
-                    self.inodes[fd.inode].stat.st_size = new_size;
+                    inode.stat.st_size = new_size;
                     Ok(new_size as __wasi_filesize_t)
                 } else {
                     Err(__WASI_EBADF)
                 }
             }
+            Kind::Buffer { buffer } => {
+                let new_size = buffer.len() as __wasi_filesize_t;
+                inode.stat.st_size = new_size;
+                Ok(new_size)
+            }
             Kind::Dir { .. } | Kind::Root { .. } => Err(__WASI_EISDIR),
             _ => Err(__WASI_EINVAL),
         }
@@ -717,276 +728,258 @@ impl WasiFs {
     /// `.` and `..`) and resolving symlinks (while preventing infinite
     /// loops/stack overflows).
     ///
-    /// TODO: expand upon exactly what the state of the returned value is,
-    /// explaining lazy-loading from the real file system and synchronizing
-    /// between them.
+    /// The path is resolved from `base_inode`, loading the inodes from the real
+    /// file system as necessary. The symlinks in the middle of the path are
+    /// always followed, the last one only if `follow_symlinks` is set.
+    /// `symlink_count` counts the symlinks followed during the whole
+    /// resolution, which fails with `__WASI_ELOOP` past `MAX_SYMLINKS`.
+    ///
+    /// The resolution only walks the inodes, so it can't escape the preopened
+    /// directories: the parent of a preopened directory is the virtual root,
+    /// whose parent is itself, and absolute paths are rejected.
     ///
     /// This is where a lot of the magic happens, be very careful when editing
     /// this code.
-    ///
-    /// TODO: write more tests for this code
     fn get_inode_at_path_inner(
         &mut self,
-        base: __wasi_fd_t,
-        path: &str,
-        mut symlink_count: u32,
+        base_inode: Inode,
+        path: &Path,
+        symlink_count: &mut u32,
         follow_symlinks: bool,
     ) -> Result<Inode, __wasi_errno_t> {
-        if symlink_count > MAX_SYMLINKS {
-            return Err(__WASI_EMLINK);
-        }
-
-        let base_dir = self.get_fd(base)?;
-        let path: &Path = Path::new(path);
-
-        let mut cur_inode = base_dir.inode;
+        let mut cur_inode = base_inode;
         let n_components = path.components().count();
         // TODO: rights checks
-        'path_iter: for (i, component) in path.components().enumerate() {
-            // used to terminate symlink resolution properly
+        for (i, component) in path.components().enumerate() {
             let last_component = i + 1 == n_components;
-            // for each component traverse file structure
-            // loading inodes as necessary
-            'symlink_resolution: while symlink_count < MAX_SYMLINKS {
-                match &mut self.inodes[cur_inode].kind {
-                    Kind::Buffer { .. } => unimplemented!("state::get_inode_at_path for buffers"),
-                    Kind::Dir {
-                        ref mut entries,
-                        ref path,
-                        ref parent,
-                        ..
-                    } => {
-                        match component.as_os_str().to_string_lossy().borrow() {
-                            ".." => {
-                                if let Some(p) = parent {
-                                    cur_inode = *p;
-                                    continue 'path_iter;
-                                } else {
-                                    return Err(__WASI_EACCES);
-                                }
-                            }
-                            "." => continue 'path_iter,
-                            _ => (),
-                        }
-                        // used for full resolution of symlinks
-                        let mut loop_for_symlink = false;
-                        if let Some(entry) =
-                            entries.get(component.as_os_str().to_string_lossy().as_ref())
-                        {
-                            cur_inode = *entry;
-                        } else {
-                            let file = {
-                                let mut cd = path.clone();
-                                cd.push(component);
-                                cd
-                            };
-                            let metadata = file.symlink_metadata().ok().ok_or(__WASI_EINVAL)?;
-                            let file_type = metadata.file_type();
-                            // we want to insert newly opened dirs and files, but not transient symlinks
-                            // TODO: explain why (think about this deeply when well rested)
-                            let mut should_insert = false;
-
-                            let kind = if file_type.is_dir() {
-                                should_insert = true;
-                                // load DIR
-                                Kind::Dir {
-                                    parent: Some(cur_inode),
-                                    path: file.clone(),
-                                    entries: Default::default(),
-                                }
-                            } else if file_type.is_file() {
-                                should_insert = true;
-                                // load file
-                                Kind::File {
-                                    handle: None,
-                                    path: file.clone(),
-                                    fd: None,
-                                }
-                            } else if file_type.is_symlink() {
-                                let link_value = file.read_link().ok().ok_or(__WASI_EIO)?;
-                                debug!("attempting to decompose path {:?}", link_value);
-
-                                let (pre_open_dir_fd, relative_path) = if link_value.is_relative() {
-                                    self.path_into_pre_open_and_relative_path(&file)?
-                                } else {
-                                    unimplemented!("Absolute symlinks are not yet supported");
-                                };
-                                loop_for_symlink = true;
-                                symlink_count += 1;
-                                Kind::Symlink {
-                                    base_po_dir: pre_open_dir_fd,
-                                    path_to_symlink: relative_path,
-                                    relative_path: link_value,
-                                }
-                            } else {
-                                #[cfg(unix)]
-                                {
-                                    use std::os::unix::fs::FileTypeExt;
-                                    let file_type: __wasi_filetype_t = if file_type.is_char_device()
-                                    {
-                                        __WASI_FILETYPE_CHARACTER_DEVICE
-                                    } else if file_type.is_block_device() {
-                                        __WASI_FILETYPE_BLOCK_DEVICE
-                                    } else if file_type.is_fifo() {
-                                        // FIFO doesn't seem to fit any other type, so unknown
-                                        __WASI_FILETYPE_UNKNOWN
-                                    } else if file_type.is_socket() {
-                                        // TODO: how do we know if it's a `__WASI_FILETYPE_SOCKET_STREAM` or
-                                        // a `__WASI_FILETYPE_SOCKET_DGRAM`?
-                                        __WASI_FILETYPE_SOCKET_STREAM
-                                    } else {
-                                        unimplemented!("state::get_inode_at_path unknown file type: not file, directory, symlink, char device, block device, fifo, or socket");
-                                    };
-
-                                    let kind = Kind::File {
-                                        handle: None,
-                                        path: file.clone(),
-                                        fd: None,
-                                    };
-                                    let new_inode = self.create_inode_with_stat(
-                                        kind,
-                                        false,
-                                        file.to_string_lossy().to_string(),
-                                        __wasi_filestat_t {
-                                            st_filetype: file_type,
-                                            ..__wasi_filestat_t::default()
-                                        },
-                                    );
-                                    if let Kind::Dir {
-                                        ref mut entries, ..
-                                    } = &mut self.inodes[cur_inode].kind
-                                    {
-                                        entries.insert(
-                                            component.as_os_str().to_string_lossy().to_string(),
-                                            new_inode,
-                                        );
-                                    } else {
-                                        unreachable!(
-                                            "Attempted to insert special device into non-directory"
-                                        );
-                                    }
-                                    // perhaps just continue with symlink resolution and return at the end
-                                    return Ok(new_inode);
-                                }
-                                #[cfg(not(unix))]
-                                unimplemented!("state::get_inode_at_path unknown file type: not file, directory, or symlink");
-                            };
-
-                            let new_inode =
-                                self.create_inode(kind, false, file.to_string_lossy().to_string())?;
-                            if should_insert {
-                                if let Kind::Dir {
-                                    ref mut entries, ..
-                                } = &mut self.inodes[cur_inode].kind
-                                {
-                                    entries.insert(
-                                        component.as_os_str().to_string_lossy().to_string(),
-                                        new_inode,
-                                    );
-                                }
-                            }
-                            cur_inode = new_inode;
-
-                            if loop_for_symlink && follow_symlinks {
-                                debug!("Following symlink to {:?}", cur_inode);
-                                continue 'symlink_resolution;
-                            }
-                        }
-                    }
-                    Kind::Root { entries } => {
-                        match component.as_os_str().to_string_lossy().borrow() {
-                            // the root's parent is the root
-                            ".." => continue 'path_iter,
-                            // the root's current directory is the root
-                            "." => continue 'path_iter,
-                            _ => (),
-                        }
-
-                        if let Some(entry) =
-                            entries.get(component.as_os_str().to_string_lossy().as_ref())
-                        {
-                            cur_inode = *entry;
-                        } else {
-                            return Err(__WASI_EINVAL);
-                        }
-                    }
-                    Kind::File { .. } => {
-                        return Err(__WASI_ENOTDIR);
-                    }
-                    Kind::Symlink {
-                        base_po_dir,
-                        path_to_symlink,
-                        relative_path,
-                    } => {
-                        let new_base_dir = *base_po_dir;
-                        // allocate to reborrow mutabily to recur
-                        let new_path = {
-                            /*if let Kind::Root { .. } = self.inodes[base_po_dir].kind {
-                                assert!(false, "symlinks should never be relative to the root");
-                            }*/
-                            let mut base = path_to_symlink.clone();
-                            // remove the symlink file itself from the path, leaving just the path from the base
-                            // to the dir containing the symlink
-                            base.pop();
-                            base.push(relative_path);
-                            base.to_string_lossy().to_string()
-                        };
-                        debug!("Following symlink recursively");
-                        let symlink_inode = self.get_inode_at_path_inner(
-                            new_base_dir,
-                            &new_path,
-                            symlink_count + 1,
-                            follow_symlinks,
-                        )?;
-                        cur_inode = symlink_inode;
-                        // if we're at the very end and we found a file, then we're done
-                        // TODO: figure out if this should also happen for directories?
-                        if let Kind::File { .. } = &self.inodes[cur_inode].kind {
-                            // check if on last step
-                            if last_component {
-                                break 'symlink_resolution;
-                            }
-                        }
-                        continue 'symlink_resolution;
-                    }
+            let dir_inode = cur_inode;
+            cur_inode = match component {
+                Component::Prefix(_) | Component::RootDir => return Err(__WASI_ENOTCAPABLE),
+                Component::CurDir => dir_inode,
+                Component::ParentDir => match &self.inodes[dir_inode].kind {
+                    Kind::Dir { parent, .. } => parent.ok_or(__WASI_EACCES)?,
+                    // the root's parent is the root
+                    Kind::Root { .. } => dir_inode,
+                    _ => return Err(__WASI_ENOTDIR),
+                },
+                Component::Normal(name) => {
+                    self.get_entry_inode(dir_inode, name.to_string_lossy().borrow())?
                 }
-                break 'symlink_resolution;
+            };
+            if !last_component || follow_symlinks {
+                cur_inode = self.follow_symlink(dir_inode, cur_inode, symlink_count)?;
             }
         }
 
         Ok(cur_inode)
     }
 
-    /// Splits a path into the first preopened directory that is a parent of it,
-    /// if such a preopened directory exists, and the rest of the path.
+    /// Follows the symlink `inode`, found in the directory `dir_inode`, to the
+    /// inode it points to. Other inodes are returned as is.
+    fn follow_symlink(
+        &mut self,
+        dir_inode: Inode,
+        inode: Inode,
+        symlink_count: &mut u32,
+    ) -> Result<Inode, __wasi_errno_t> {
+        let target = match &self.inodes[inode].kind {
+            Kind::Symlink { relative_path, .. } => relative_path.clone(),
+            _ => return Ok(inode),
+        };
+        *symlink_count += 1;
+        if *symlink_count > MAX_SYMLINKS {
+            return Err(__WASI_ELOOP);
+        }
+        if target.as_os_str().is_empty() {
+            return Err(__WASI_ENOENT);
+        }
+        debug!("Following symlink to {:?}", target);
+        self.get_inode_at_path_inner(dir_inode, &target, symlink_count, true)
+    }
+
+    /// Looks up the entry `name` of the directory `dir_inode`, loading it from
+    /// the real file system if it's not known yet.
+    fn get_entry_inode(&mut self, dir_inode: Inode, name: &str) -> Result<Inode, __wasi_errno_t> {
+        let file = match &self.inodes[dir_inode].kind {
+            Kind::Dir { entries, path, .. } => {
+                if let Some(entry) = entries.get(name) {
+                    return Ok(*entry);
+                }
+                if is_in_memory(path) {
+                    return Err(__WASI_ENOENT);
+                }
+                path.join(name)
+            }
+            Kind::Root { entries } => return entries.get(name).copied().ok_or(__WASI_EINVAL),
+            Kind::File { .. } | Kind::Buffer { .. } | Kind::Symlink { .. } => {
+                return Err(__WASI_ENOTDIR)
+            }
+        };
+
+        let metadata = file
+            .symlink_metadata()
+            .map_err(|e| WasiFsError::from(e).into_wasi_err())?;
+        let file_type = metadata.file_type();
+        let mut special_file_type = None;
+        let kind = if file_type.is_dir() {
+            // load DIR
+            Kind::Dir {
+                parent: Some(dir_inode),
+                path: file.clone(),
+                entries: Default::default(),
+            }
+        } else if file_type.is_file() {
+            // load file
+            Kind::File {
+                handle: None,
+                path: file.clone(),
+                fd: None,
+            }
+        } else if file_type.is_symlink() {
+            let link_value = file.read_link().map_err(|_| __WASI_EIO)?;
+            debug!("attempting to decompose path {:?}", link_value);
+
+            let relative_path = if link_value.is_relative() {
+                link_value
+            } else {
+                self.relative_symlink_value(dir_inode, &link_value)?
+            };
+            let (base_po_dir, path_to_symlink) =
+                self.path_into_pre_open_and_relative_path(&file)?;
+            Kind::Symlink {
+                base_po_dir,
+                path_to_symlink,
+                relative_path,
+            }
+        } else {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::FileTypeExt;
+                special_file_type = Some(if file_type.is_char_device() {
+                    __WASI_FILETYPE_CHARACTER_DEVICE
+                } else if file_type.is_block_device() {
+                    __WASI_FILETYPE_BLOCK_DEVICE
+                } else if file_type.is_socket() {
+                    // TODO: how do we know if it's a `__WASI_FILETYPE_SOCKET_STREAM` or
+                    // a `__WASI_FILETYPE_SOCKET_DGRAM`?
+                    __WASI_FILETYPE_SOCKET_STREAM
+                } else {
+                    // FIFOs don't seem to fit any other type, so unknown
+                    __WASI_FILETYPE_UNKNOWN
+                });
+            }
+            #[cfg(not(unix))]
+            {
+                special_file_type = Some(__WASI_FILETYPE_UNKNOWN);
+            }
+            Kind::File {
+                handle: None,
+                path: file.clone(),
+                fd: None,
+            }
+        };
+
+        let new_inode = if let Some(st_filetype) = special_file_type {
+            self.create_inode_with_stat(
+                kind,
+                false,
+                file.to_string_lossy().to_string(),
+                __wasi_filestat_t {
+                    st_filetype,
+                    ..__wasi_filestat_t::default()
+                },
+            )
+        } else {
+            self.create_inode(kind, false, file.to_string_lossy().to_string())?
+        };
+        if let Kind::Dir {
+            ref mut entries, ..
+        } = &mut self.inodes[dir_inode].kind
+        {
+            entries.insert(name.to_string(), new_inode);
+        }
+        Ok(new_inode)
+    }
+
+    /// Converts the value of an absolute host symlink, found in the directory
+    /// `dir_inode`, to a path relative to this directory.
     ///
-    /// NOTE: this behavior seems to be not the same as what libpreopen is
-    /// doing in WASI.
+    /// The target must be in a preopened directory, which is reached through
+    /// the virtual root if it's not the one containing the symlink.
+    fn relative_symlink_value(
+        &self,
+        dir_inode: Inode,
+        link_value: &Path,
+    ) -> Result<PathBuf, __wasi_errno_t> {
+        // find the preopened directory containing the symlink
+        let mut po_inode = dir_inode;
+        let mut relative_path = PathBuf::new();
+        while let Kind::Dir {
+            parent: Some(parent),
+            ..
+        } = &self.inodes[po_inode].kind
+        {
+            if let Kind::Root { .. } = &self.inodes[*parent].kind {
+                break;
+            }
+            po_inode = *parent;
+            relative_path.push("..");
+        }
+        if let Kind::Dir { path, .. } = &self.inodes[po_inode].kind {
+            if let Ok(rest) = link_value.strip_prefix(path) {
+                relative_path.push(rest);
+                return Ok(relative_path);
+            }
+        }
+
+        let (po_fd, rest) = self
+            .path_into_pre_open_and_relative_path(link_value)
+            .map_err(|_| __WASI_ENOTCAPABLE)?;
+        let po_name = &self.get_inodeval(po_fd)?.name;
+        // the preopened directory must be reachable as an entry of the root
+        let mut po_components = Path::new(po_name).components();
+        match (po_components.next(), po_components.next()) {
+            (Some(Component::Normal(_)), None) => (),
+            _ => return Err(__WASI_ENOTCAPABLE),
+        }
+        relative_path.push("..");
+        relative_path.push(po_name);
+        relative_path.push(rest);
+        Ok(relative_path)
+    }
+
+    /// Splits a path into the closest preopened directory that is a parent of
+    /// it, if such a preopened directory exists, and the rest of the path.
     ///
-    /// TODO: evaluate users of this function and explain why this behavior is
-    /// not the same as libpreopen or update its behavior to be the same.
+    /// The virtual root and the directories in memory are not directories of
+    /// the real file system, so they are never returned.
     fn path_into_pre_open_and_relative_path(
         &self,
         path: &Path,
     ) -> Result<(__wasi_fd_t, PathBuf), __wasi_errno_t> {
+        let mut closest: Option<(__wasi_fd_t, &Path)> = None;
         // for each preopened directory
         for po_fd in &self.preopen_fds {
-            let po_inode = self.fd_map[po_fd].inode;
-            let po_path = match &self.inodes[po_inode].kind {
-                Kind::Dir { path, .. } => &**path,
-                Kind::Root { .. } => Path::new("/"),
-                _ => unreachable!("Preopened FD that's not a directory or the root"),
-            };
-            // stem path based on it
-            if let Ok(rest) = path.strip_prefix(po_path) {
-                // if any path meets this criteria
-                // (verify that all remaining components are not symlinks except for maybe last? (or do the more complex logic of resolving intermediary symlinks))
-                // return preopened dir and the rest of the path
-
-                return Ok((*po_fd, rest.to_owned()));
+            // the guest can renumber the fds of the preopened directories
+            let po_kind = self.get_inodeval(*po_fd).map(|inode| &inode.kind);
+            if let Ok(Kind::Dir { path: po_path, .. }) = po_kind {
+                if is_in_memory(po_path) {
+                    continue;
+                }
+                // stem path based on it
+                if let Ok(rest) = path.strip_prefix(po_path) {
+                    let is_closer = closest.map_or(true, |(_, closest_rest)| {
+                        rest.components().count() < closest_rest.components().count()
+                    });
+                    if is_closer {
+                        closest = Some((*po_fd, rest));
+                    }
+                }
             }
         }
-        Err(__WASI_EINVAL) // this may not make sense
+        closest
+            .map(|(po_fd, rest)| (po_fd, rest.to_owned()))
+            .ok_or(__WASI_EINVAL)
     }
 
     // if this is still dead code and the year is 2020 or later, please delete this function
@@ -1020,55 +1013,33 @@ impl WasiFs {
         Ok(out)
     }
 
-    /// finds the number of directories between the fd and the inode if they're connected
-    /// expects inode to point to a directory
-    pub(crate) fn path_depth_from_fd(
-        &self,
-        fd: __wasi_fd_t,
-        inode: Inode,
-    ) -> Result<usize, __wasi_errno_t> {
-        let mut counter = 0;
-        let base_fd = self.get_fd(fd)?;
-        let base_inode = base_fd.inode;
-        let mut cur_inode = inode;
-
-        while cur_inode != base_inode {
-            counter += 1;
-            match &self.inodes[cur_inode].kind {
-                Kind::Dir { parent, .. } => {
-                    if let Some(p) = parent {
-                        cur_inode = *p;
-                    }
-                }
-                _ => return Err(__WASI_EINVAL),
-            }
-        }
-
-        Ok(counter)
-    }
-
     /// gets a host file from a base directory and a path
     /// this function ensures the fs remains sandboxed
-    // NOTE: follow symlinks is super weird right now
-    // even if it's false, it still follows symlinks, just not the last
-    // symlink so
-    // This will be resolved when we have tests asserting the correct behavior
+    ///
+    /// The symlinks in the middle of the path are always followed, the last
+    /// one only if `follow_symlinks` is set.
     pub(crate) fn get_inode_at_path(
         &mut self,
         base: __wasi_fd_t,
         path: &str,
         follow_symlinks: bool,
     ) -> Result<Inode, __wasi_errno_t> {
-        self.get_inode_at_path_inner(base, path, 0, follow_symlinks)
+        let base_inode = self.get_fd(base)?.inode;
+        // the base can be an unlinked file, which isn't in the arena anymore
+        if !self.inodes.contains(base_inode) {
+            return Err(__WASI_ENOTDIR);
+        }
+        self.get_inode_at_path_inner(base_inode, Path::new(path), &mut 0, follow_symlinks)
     }
 
     /// Returns the parent Dir or Root that the file at a given path is in and the file name
     /// stripped off
+    ///
+    /// The symlinks leading to the parent are always followed.
     pub(crate) fn get_parent_inode_at_path(
         &mut self,
         base: __wasi_fd_t,
         path: &Path,
-        follow_symlinks: bool,
     ) -> Result<(Inode, String), __wasi_errno_t> {
         let mut parent_dir = std::path::PathBuf::new();
         let mut components = path.components().rev();
@@ -1081,7 +1052,7 @@ impl WasiFs {
         for comp in components.rev() {
             parent_dir.push(comp);
         }
-        self.get_inode_at_path(base, &parent_dir.to_string_lossy(), follow_symlinks)
+        self.get_inode_at_path(base, &parent_dir.to_string_lossy(), true)
             .map(|v| (v, new_entity_name))
     }
 
@@ -1089,6 +1060,16 @@ impl WasiFs {
         self.fd_map.get(&fd).ok_or(__WASI_EBADF)
     }
 
+    /// gets either a normal inode or an orphaned inode
+    pub fn get_inodeval(&self, fd: __wasi_fd_t) -> Result<&InodeVal, __wasi_errno_t> {
+        let inode = self.get_fd(fd)?.inode;
+        if let Some(iv) = self.inodes.get(inode) {
+            Ok(iv)
+        } else {
+            self.orphan_fds.get(&inode).ok_or(__WASI_EBADF)
+        }
+    }
+
     /// gets either a normal inode or an orphaned inode
     pub fn get_inodeval_mut(&mut self, fd: __wasi_fd_t) -> Result<&mut InodeVal, __wasi_errno_t> {
         let inode = self.get_fd(fd)?.inode;
@@ -1100,9 +1081,7 @@ impl WasiFs {
     }
 
     pub fn filestat_fd(&self, fd: __wasi_fd_t) -> Result<__wasi_filestat_t, __wasi_errno_t> {
-        let fd = self.get_fd(fd)?;
-
-        Ok(self.inodes[fd.inode].stat)
+        Ok(self.get_inodeval(fd)?.stat)
     }
 
     pub fn fdstat(&self, fd: __wasi_fd_t) -> Result<__wasi_fdstat_t, __wasi_errno_t> {
@@ -1142,12 +1121,13 @@ impl WasiFs {
             }
             _ => (),
         }
+        let inode = self.get_inodeval(fd)?;
         let fd = self.get_fd(fd)?;
 
         debug!("fdstat: {:?}", fd);
 
         Ok(__wasi_fdstat_t {
-            fs_filetype: match self.inodes[fd.inode].kind {
+            fs_filetype: match inode.kind {
                 Kind::File { .. } => __WASI_FILETYPE_REGULAR_FILE,
                 Kind::Dir { .. } => __WASI_FILETYPE_DIRECTORY,
                 Kind::Symlink { .. } => __WASI_FILETYPE_SYMBOLIC_LINK,
@@ -1160,10 +1140,8 @@ impl WasiFs {
     }
 
     pub fn prestat_fd(&self, fd: __wasi_fd_t) -> Result<__wasi_prestat_t, __wasi_errno_t> {
-        let fd = self.fd_map.get(&fd).ok_or(__WASI_EBADF)?;
-
-        debug!("in prestat_fd {:?}", fd);
-        let inode_val = &self.inodes[fd.inode];
+        debug!("in prestat_fd {:?}", self.get_fd(fd)?);
+        let inode_val = self.get_inodeval(fd)?;
 
         if inode_val.is_preopened {
             Ok(__wasi_prestat_t {
@@ -1195,12 +1173,12 @@ impl WasiFs {
                 .and_then(|f| f.flush().ok())
                 .ok_or(__WASI_EIO)?,
             _ => {
-                let fd = self.fd_map.get(&fd).ok_or(__WASI_EBADF)?;
-                if fd.rights & __WASI_RIGHT_FD_DATASYNC == 0 {
+                let fd_entry = self.fd_map.get(&fd).ok_or(__WASI_EBADF)?;
+                if fd_entry.rights & __WASI_RIGHT_FD_DATASYNC == 0 {
                     return Err(__WASI_EACCES);
                 }
 
-                let inode = &mut self.inodes[fd.inode];
+                let inode = self.get_inodeval_mut(fd)?;
 
                 match &mut inode.kind {
                     Kind::File { handle, .. } => {
@@ -1212,7 +1190,7 @@ impl WasiFs {
                     }
                     // TODO: verify this behavior
                     Kind::Dir { .. } => return Err(__WASI_EISDIR),
-                    Kind::Symlink { .. } => unimplemented!("WasiFs::flush Kind::Symlink"),
+                    Kind::Symlink { .. } => return Err(__WASI_EBADF),
                     Kind::Buffer { .. } => (),
                     _ => return Err(__WASI_EIO),
                 }
@@ -1397,18 +1375,30 @@ impl WasiFs {
                 }
                 None => path.metadata().ok()?,
             },
+            Kind::Dir { path, .. } if is_in_memory(path) => {
+                return Some(__wasi_filestat_t {
+                    st_filetype: __WASI_FILETYPE_DIRECTORY,
+                    ..__wasi_filestat_t::default()
+                })
+            }
             Kind::Dir { path, .. } => path.metadata().ok()?,
+            Kind::Buffer { buffer } => {
+                return Some(__wasi_filestat_t {
+                    st_filetype: __WASI_FILETYPE_REGULAR_FILE,
+                    st_size: buffer.len() as u64,
+                    ..__wasi_filestat_t::default()
+                })
+            }
             Kind::Symlink {
                 base_po_dir,
                 path_to_symlink,
                 ..
             } => {
-                let base_po_inode = &self.fd_map[base_po_dir].inode;
-                let base_po_inode_v = &self.inodes[*base_po_inode];
+                // the guest may have closed the preopened directory
+                let base_po_inode = self.fd_map.get(base_po_dir)?.inode;
+                let base_po_inode_v = self.inodes.get(base_po_inode)?;
                 match &base_po_inode_v.kind {
-                    Kind::Root { .. } => {
-                        path_to_symlink.clone().symlink_metadata().ok()?
-                    }
+                    Kind::Root { .. } => path_to_symlink.clone().symlink_metadata().ok()?,
                     Kind::Dir { path, .. } => {
                         let mut real_path = path.clone();
                         // PHASE 1: ignore all possible symlinks in `relative_path`
@@ -1420,8 +1410,8 @@ impl WasiFs {
                         real_path.push(path_to_symlink);
                         real_path.symlink_metadata().ok()?
                     }
-                    // if this triggers, there's a bug in the symlink code
-                    _ => unreachable!("Symlink pointing to something that's not a directory as its base preopened directory"),
+                    // the fd of the preopened directory may have been renumbered
+                    _ => return None,
                 }
             }
             _ => return None,
@@ -1452,26 +1442,35 @@ impl WasiFs {
     }
 
     /// Closes an open FD, handling all details such as FD being preopen
-    pub(crate) fn close_fd(&mut self, fd: __wasi_fd_t) -> Result<(), __wasi_errno_t> {
+    pub fn close_fd(&mut self, fd: __wasi_fd_t) -> Result<(), __wasi_errno_t> {
+        let inode = self.get_fd(fd)?.inode;
         let inodeval_mut = self.get_inodeval_mut(fd)?;
         let is_preopened = inodeval_mut.is_preopened;
+        let name = &inodeval_mut.name;
 
         match &mut inodeval_mut.kind {
             Kind::File { ref mut handle, .. } => {
                 let mut empty_handle = None;
                 std::mem::swap(handle, &mut empty_handle);
+                self.fd_map.remove(&fd);
+            }
+            Kind::Buffer { .. } => {
+                self.fd_map.remove(&fd);
             }
             Kind::Dir { parent, path, .. } => {
                 debug!("Closing dir {:?}", &path);
-                let key = path
-                    .file_name()
-                    .ok_or(__WASI_EINVAL)?
-                    .to_string_lossy()
-                    .to_string();
+                let key = if is_in_memory(path) {
+                    name.clone()
+                } else {
+                    path.file_name()
+                        .ok_or(__WASI_EINVAL)?
+                        .to_string_lossy()
+                        .to_string()
+                };
                 if let Some(p) = *parent {
-                    match &mut self.inodes[p].kind {
-                        Kind::Dir { entries, .. } | Kind::Root { entries } => {
-                            self.fd_map.remove(&fd).unwrap();
+                    match self.inodes.get_mut(p).map(|inode| &mut inode.kind) {
+                        Some(Kind::Dir { entries, .. }) | Some(Kind::Root { entries }) => {
+                            self.fd_map.remove(&fd);
                             if is_preopened {
                                 let mut idx = None;
                                 for (i, po_fd) in self.preopen_fds.iter().enumerate() {
@@ -1489,9 +1488,10 @@ impl WasiFs {
                                 }
                             }
                         }
-                        _ => unreachable!(
-                            "Fatal internal logic error, directory's parent is not a directory"
-                        ),
+                        // the parent directory was removed while this one was open
+                        _ => {
+                            self.fd_map.remove(&fd);
+                        }
                     }
                 } else {
                     // this shouldn't be possible anymore due to Root
@@ -1500,7 +1500,13 @@ impl WasiFs {
                 }
             }
             Kind::Root { .. } => return Err(__WASI_EACCES),
-            Kind::Symlink { .. } | Kind::Buffer { .. } => return Err(__WASI_EINVAL),
+            Kind::Symlink { .. } => return Err(__WASI_EINVAL),
+        }
+
+        // an unlinked file is deleted once its last fd is closed
+        if self.orphan_fds.contains_key(&inode) && !self.fd_map.values().any(|fd| fd.inode == inode)
+        {
+            self.orphan_fds.remove(&inode);
         }
 
         Ok(())
@@ -1540,6 +1546,10 @@ pub struct WasiState {
     pub fs: WasiFs,
     pub args: Vec<Vec<u8>>,
     pub envs: Vec<Vec<u8>>,
+    /// The deterministic clocks and random source, set by
+    /// [`WasiStateBuilder::deterministic`].
+    #[serde(default)]
+    pub(crate) determinism: Option<Determinism>,
 }
 
 impl WasiState {
@@ -1573,3 +1583,19 @@ pub fn host_file_type_to_wasi_file_type(file_type: fs::FileType) -> __wasi_filet
         __WASI_FILETYPE_UNKNOWN
     }
 }
+
+/// Whether the directory at the host `path` is in memory. Its entries are
+/// then all known, and are directories in memory or buffers, so the host file
+/// system is never touched for them.
+pub(crate) fn is_in_memory(path: &Path) -> bool {
+    path.as_os_str().is_empty()
+}
+
+/// Makes room for `len` bytes in `buffer`, failing instead of aborting if the
+/// guest asks for more memory than the host can allocate.
+pub(crate) fn reserve_buffer(buffer: &mut Vec<u8>, len: u64) -> Result<(), __wasi_errno_t> {
+    let additional = usize::try_from(len)
+        .map_err(|_| __WASI_EFBIG)?
+        .saturating_sub(buffer.len());
+    buffer.try_reserve(additional).map_err(|_| __WASI_ENOMEM)
+}
@@ -0,0 +1,1189 @@
+//! The file system operations of [`WasiFs`], independent from the memory of
+//! the guest.
+//!
+//! The WASI syscalls read their arguments from the memory of the instance
+//! and delegate to these operations. They are public so other ABIs, like
+//! Emscripten, can share the same sandboxed file system.
+
+use super::{is_in_memory, reserve_buffer, Fd, HostFile, Inode, Kind, WasiFs, WasiFsError};
+use crate::syscalls::has_rights;
+use crate::syscalls::types::*;
+use std::convert::TryFrom;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use tracing::debug;
+
+impl WasiFs {
+    /// Opens the file at `path`, relative to the directory `dirfd`, and
+    /// returns its new file descriptor.
+    ///
+    /// The flags and rights are the ones of the `path_open` WASI syscall.
+    pub fn open_path(
+        &mut self,
+        dirfd: __wasi_fd_t,
+        dirflags: __wasi_lookupflags_t,
+        path: &str,
+        o_flags: __wasi_oflags_t,
+        fs_rights_base: __wasi_rights_t,
+        fs_rights_inheriting: __wasi_rights_t,
+        fs_flags: __wasi_fdflags_t,
+    ) -> Result<__wasi_fd_t, __wasi_errno_t> {
+        if dirflags & __WASI_LOOKUP_SYMLINK_FOLLOW != 0 {
+            debug!("  - will follow symlinks when opening path");
+        }
+        // o_flags:
+        // - __WASI_O_CREAT (create if it does not exist)
+        // - __WASI_O_DIRECTORY (fail if not dir)
+        // - __WASI_O_EXCL (fail if file exists)
+        // - __WASI_O_TRUNC (truncate size to 0)
+
+        let working_dir = self.get_fd(dirfd)?;
+        let working_dir_rights_inheriting = working_dir.rights_inheriting;
+
+        // ASSUMPTION: open rights apply recursively
+        if !has_rights(working_dir.rights, __WASI_RIGHT_PATH_OPEN) {
+            return Err(__WASI_EACCES);
+        }
+
+        debug!("=> fd: {}, path: {}", dirfd, &path);
+
+        let path_arg = PathBuf::from(path);
+        let maybe_inode =
+            self.get_inode_at_path(dirfd, path, dirflags & __WASI_LOOKUP_SYMLINK_FOLLOW != 0);
+
+        let mut open_flags = 0;
+        // TODO: traverse rights of dirs properly
+        // COMMENTED OUT: WASI isn't giving appropriate rights here when opening
+        //              TODO: look into this; file a bug report if this is a bug
+        let adjusted_rights = /*fs_rights_base &*/ working_dir_rights_inheriting;
+        let _ = fs_rights_base;
+        let inode = if let Ok(inode) = maybe_inode {
+            // Happy path, we found the file we're trying to open
+            match &mut self.inodes[inode].kind {
+                Kind::File {
+                    ref mut handle,
+                    path,
+                    fd,
+                } => {
+                    if let Some(special_fd) = fd {
+                        // short circuit if we're dealing with a special file
+                        if handle.is_none() {
+                            return Err(__WASI_EBADF);
+                        }
+                        return Ok(*special_fd);
+                    }
+                    if o_flags & __WASI_O_DIRECTORY != 0 {
+                        return Err(__WASI_ENOTDIR);
+                    }
+                    if o_flags & __WASI_O_EXCL != 0 && path.exists() {
+                        return Err(__WASI_EEXIST);
+                    }
+                    let mut open_options = std::fs::OpenOptions::new();
+                    let write_permission = adjusted_rights & __WASI_RIGHT_FD_WRITE != 0;
+                    // append, truncate, and create all require the permission to write
+                    let (append_permission, truncate_permission, create_permission) =
+                        if write_permission {
+                            (
+                                fs_flags & __WASI_FDFLAG_APPEND != 0,
+                                o_flags & __WASI_O_TRUNC != 0,
+                                o_flags & __WASI_O_CREAT != 0,
+                            )
+                        } else {
+                            (false, false, false)
+                        };
+                    let open_options = open_options
+                        .read(true)
+                        // TODO: ensure these rights are actually valid given parent, etc.
+                        .write(write_permission)
+                        .create(create_permission)
+                        .append(append_permission)
+                        .truncate(truncate_permission);
+                    open_flags |= Fd::READ;
+                    if adjusted_rights & __WASI_RIGHT_FD_WRITE != 0 {
+                        open_flags |= Fd::WRITE;
+                    }
+                    if o_flags & __WASI_O_CREAT != 0 {
+                        open_flags |= Fd::CREATE;
+                    }
+                    if o_flags & __WASI_O_TRUNC != 0 {
+                        open_flags |= Fd::TRUNCATE;
+                    }
+                    *handle = Some(Box::new(HostFile::new(
+                        open_options.open(&path).map_err(|_| __WASI_EIO)?,
+                        path.to_path_buf(),
+                        true,
+                        adjusted_rights & __WASI_RIGHT_FD_WRITE != 0,
+                        false,
+                    )));
+                }
+                Kind::Buffer { buffer } => {
+                    if o_flags & __WASI_O_DIRECTORY != 0 {
+                        return Err(__WASI_ENOTDIR);
+                    }
+                    if o_flags & __WASI_O_EXCL != 0 {
+                        return Err(__WASI_EEXIST);
+                    }
+                    open_flags |= Fd::READ;
+                    if adjusted_rights & __WASI_RIGHT_FD_WRITE != 0 {
+                        open_flags |= Fd::WRITE;
+                        if o_flags & __WASI_O_TRUNC != 0 {
+                            open_flags |= Fd::TRUNCATE;
+                            buffer.clear();
+                        }
+                    }
+                }
+                Kind::Dir { .. } | Kind::Root { .. } => {
+                    if o_flags & __WASI_O_EXCL != 0 {
+                        return Err(__WASI_EEXIST);
+                    }
+                }
+                // the symlink was not followed, as `__WASI_LOOKUP_SYMLINK_FOLLOW` is unset
+                Kind::Symlink { .. } => return Err(__WASI_ELOOP),
+            }
+            inode
+        } else {
+            // less-happy path, we have to try to create the file
+            debug!("Maybe creating file");
+            // only a missing file is created, not an inaccessible one
+            if o_flags & __WASI_O_CREAT != 0 && maybe_inode == Err(__WASI_ENOENT) {
+                if o_flags & __WASI_O_DIRECTORY != 0 {
+                    return Err(__WASI_ENOTDIR);
+                }
+                debug!("Creating file");
+                // strip end file name
+
+                let (parent_inode, new_entity_name) =
+                    self.get_parent_inode_at_path(dirfd, &path_arg)?;
+                let new_file_host_path = match &self.inodes[parent_inode].kind {
+                    Kind::Dir { path, .. } if is_in_memory(path) => None,
+                    Kind::Dir { path, .. } => {
+                        let mut new_path = path.clone();
+                        new_path.push(&new_entity_name);
+                        Some(new_path)
+                    }
+                    Kind::Root { .. } => return Err(__WASI_EACCES),
+                    _ => return Err(__WASI_EINVAL),
+                };
+                open_flags |= Fd::READ | Fd::WRITE | Fd::CREATE | Fd::TRUNCATE;
+                let kind = if let Some(new_file_host_path) = new_file_host_path {
+                    // once we got the data we need from the parent, we lookup the host file
+                    // todo: extra check that opening with write access is okay
+                    let mut open_options = std::fs::OpenOptions::new();
+                    let open_options = open_options
+                        .read(true)
+                        .append(fs_flags & __WASI_FDFLAG_APPEND != 0)
+                        // TODO: ensure these rights are actually valid given parent, etc.
+                        // write access is required for creating a file
+                        .write(true)
+                        .create_new(true);
+                    let handle = Box::new(HostFile::new(
+                        open_options.open(&new_file_host_path).map_err(|e| {
+                            debug!("Error opening file {}", e);
+                            __WASI_EIO
+                        })?,
+                        new_file_host_path.clone(),
+                        true,
+                        true,
+                        true,
+                    ));
+                    Kind::File {
+                        handle: Some(handle),
+                        path: new_file_host_path,
+                        fd: None,
+                    }
+                } else {
+                    // the files of the directories in memory are buffers
+                    Kind::Buffer { buffer: Vec::new() }
+                };
+                let new_inode = self.create_inode(kind, false, new_entity_name.clone())?;
+
+                if let Kind::Dir {
+                    ref mut entries, ..
+                } = &mut self.inodes[parent_inode].kind
+                {
+                    entries.insert(new_entity_name, new_inode);
+                }
+
+                new_inode
+            } else {
+                return Err(maybe_inode.unwrap_err());
+            }
+        };
+
+        debug!("inode {:?} value {:#?} found!", inode, self.inodes[inode]);
+
+        // TODO: check and reduce these
+        // TODO: ensure a mutable fd to root can never be opened
+        let out_fd = self.create_fd(
+            adjusted_rights,
+            fs_rights_inheriting,
+            fs_flags,
+            open_flags,
+            inode,
+        )?;
+        debug!("wasi::path_open returning fd {}", out_fd);
+        Ok(out_fd)
+    }
+
+    /// Reads from `fd` into `buf`, at the offset of the file descriptor,
+    /// and returns the number of bytes read.
+    ///
+    /// This is `fd_read` with a single buffer.
+    pub fn read_fd(&mut self, fd: __wasi_fd_t, buf: &mut [u8]) -> Result<usize, __wasi_errno_t> {
+        match fd {
+            __WASI_STDIN_FILENO => match self.stdin_mut().map_err(WasiFsError::into_wasi_err)? {
+                Some(stdin) => stdin.read(buf).map_err(|_| __WASI_EIO),
+                None => Err(__WASI_EBADF),
+            },
+            __WASI_STDOUT_FILENO | __WASI_STDERR_FILENO => Err(__WASI_EINVAL),
+            _ => {
+                let fd_entry = self.fd_map.get(&fd).ok_or(__WASI_EBADF)?;
+                if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_READ) {
+                    return Err(__WASI_EACCES);
+                }
+                let offset = fd_entry.offset;
+
+                let bytes_read = match &mut self.get_inodeval_mut(fd)?.kind {
+                    Kind::File {
+                        handle: Some(handle),
+                        ..
+                    } => {
+                        handle
+                            .seek(SeekFrom::Start(offset))
+                            .map_err(|_| __WASI_EIO)?;
+                        handle.read(buf).map_err(|_| __WASI_EIO)?
+                    }
+                    Kind::File { handle: None, .. } => return Err(__WASI_EINVAL),
+                    Kind::Dir { .. } | Kind::Root { .. } => return Err(__WASI_EISDIR),
+                    Kind::Symlink { .. } => return Err(__WASI_EBADF),
+                    Kind::Buffer { buffer } => {
+                        let start = std::cmp::min(offset as usize, buffer.len());
+                        (&buffer[start..]).read(buf).map_err(|_| __WASI_EIO)?
+                    }
+                };
+
+                // reborrow
+                let fd_entry = self.fd_map.get_mut(&fd).ok_or(__WASI_EBADF)?;
+                fd_entry.offset += bytes_read as u64;
+                Ok(bytes_read)
+            }
+        }
+    }
+
+    /// Writes `buf` to `fd`, at the offset of the file descriptor, and
+    /// returns the number of bytes written.
+    ///
+    /// This is `fd_write` with a single buffer.
+    pub fn write_fd(&mut self, fd: __wasi_fd_t, buf: &[u8]) -> Result<usize, __wasi_errno_t> {
+        let std_dev = match fd {
+            __WASI_STDIN_FILENO => return Err(__WASI_EINVAL),
+            __WASI_STDOUT_FILENO => Some(self.stdout_mut()),
+            __WASI_STDERR_FILENO => Some(self.stderr_mut()),
+            _ => None,
+        };
+        if let Some(std_dev) = std_dev {
+            return match std_dev.map_err(WasiFsError::into_wasi_err)? {
+                Some(std_dev) => {
+                    std_dev.write_all(buf).map_err(|_| __WASI_EIO)?;
+                    std_dev.flush().map_err(|_| __WASI_EIO)?;
+                    Ok(buf.len())
+                }
+                None => Err(__WASI_EBADF),
+            };
+        }
+
+        let fd_entry = self.fd_map.get(&fd).ok_or(__WASI_EBADF)?;
+        if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_WRITE) {
+            return Err(__WASI_EACCES);
+        }
+        let offset = fd_entry.offset;
+
+        let bytes_written = match &mut self.get_inodeval_mut(fd)?.kind {
+            Kind::File {
+                handle: Some(handle),
+                ..
+            } => {
+                handle
+                    .seek(SeekFrom::Start(offset))
+                    .map_err(|_| __WASI_EIO)?;
+                handle.write_all(buf).map_err(|_| __WASI_EIO)?;
+                handle.flush().map_err(|_| __WASI_EIO)?;
+                buf.len()
+            }
+            Kind::File { handle: None, .. } => return Err(__WASI_EINVAL),
+            Kind::Dir { .. } | Kind::Root { .. } => return Err(__WASI_EISDIR),
+            Kind::Symlink { .. } => return Err(__WASI_EBADF),
+            Kind::Buffer { buffer } => {
+                // writing past the end grows the buffer
+                reserve_buffer(buffer, offset.saturating_add(buf.len() as u64))?;
+                let mut cursor = std::io::Cursor::new(buffer);
+                cursor.set_position(offset);
+                cursor.write(buf).map_err(|_| __WASI_EIO)?
+            }
+        };
+
+        // reborrow
+        let fd_entry = self.fd_map.get_mut(&fd).ok_or(__WASI_EBADF)?;
+        fd_entry.offset += bytes_written as u64;
+        self.filestat_resync_size(fd)?;
+        Ok(bytes_written)
+    }
+
+    /// Truncates or extends the file `fd` to `st_size` bytes.
+    pub fn set_size(
+        &mut self,
+        fd: __wasi_fd_t,
+        st_size: __wasi_filesize_t,
+    ) -> Result<(), __wasi_errno_t> {
+        let fd_entry = self.get_fd(fd)?;
+        if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_FILESTAT_SET_SIZE) {
+            return Err(__WASI_EACCES);
+        }
+
+        let inode = self.get_inodeval_mut(fd)?;
+        match &mut inode.kind {
+            Kind::File { handle, .. } => {
+                if let Some(handle) = handle {
+                    handle
+                        .set_len(st_size)
+                        .map_err(WasiFsError::into_wasi_err)?;
+                } else {
+                    return Err(__WASI_EBADF);
+                }
+            }
+            Kind::Buffer { buffer } => {
+                reserve_buffer(buffer, st_size)?;
+                buffer.resize(st_size as usize, 0);
+            }
+            Kind::Symlink { .. } => return Err(__WASI_EBADF),
+            Kind::Dir { .. } | Kind::Root { .. } => return Err(__WASI_EISDIR),
+        }
+        inode.stat.st_size = st_size;
+
+        Ok(())
+    }
+
+    /// Synchronizes the data and metadata of the file `fd` to disk.
+    pub fn sync_fd(&mut self, fd: __wasi_fd_t) -> Result<(), __wasi_errno_t> {
+        let fd_entry = self.get_fd(fd)?;
+        if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_SYNC) {
+            return Err(__WASI_EACCES);
+        }
+
+        // TODO: implement this for more than files
+        match &mut self.get_inodeval_mut(fd)?.kind {
+            Kind::File { handle, .. } => {
+                if let Some(h) = handle {
+                    h.sync_to_disk().map_err(WasiFsError::into_wasi_err)
+                } else {
+                    Err(__WASI_EINVAL)
+                }
+            }
+            Kind::Root { .. } | Kind::Dir { .. } => Err(__WASI_EISDIR),
+            Kind::Buffer { .. } | Kind::Symlink { .. } => Err(__WASI_EINVAL),
+        }
+    }
+
+    /// Moves the offset of `fd`, and returns the new offset.
+    pub fn seek_fd(
+        &mut self,
+        fd: __wasi_fd_t,
+        offset: __wasi_filedelta_t,
+        whence: __wasi_whence_t,
+    ) -> Result<__wasi_filesize_t, __wasi_errno_t> {
+        let fd_entry = self.fd_map.get_mut(&fd).ok_or(__WASI_EBADF)?;
+
+        if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_SEEK) {
+            return Err(__WASI_EACCES);
+        }
+
+        // TODO: handle case if fd is a dir?
+        let base = match whence {
+            __WASI_WHENCE_CUR => fd_entry.offset,
+            __WASI_WHENCE_END => match &mut self.get_inodeval_mut(fd)?.kind {
+                Kind::File { handle, .. } => {
+                    if let Some(handle) = handle {
+                        handle.seek(SeekFrom::End(0)).map_err(|_| __WASI_EIO)?
+                    } else {
+                        return Err(__WASI_EINVAL);
+                    }
+                }
+                Kind::Symlink { .. } => return Err(__WASI_EBADF),
+                Kind::Dir { .. } | Kind::Root { .. } => {
+                    // TODO: check this
+                    return Err(__WASI_EINVAL);
+                }
+                Kind::Buffer { buffer } => buffer.len() as u64,
+            },
+            __WASI_WHENCE_SET => 0,
+            _ => return Err(__WASI_EINVAL),
+        };
+        // the resulting offset can neither be negative nor overflow
+        let new_offset = i64::try_from(base)
+            .ok()
+            .and_then(|base| base.checked_add(offset))
+            .filter(|new_offset| *new_offset >= 0)
+            .ok_or(__WASI_EINVAL)?;
+
+        // reborrow
+        let fd_entry = self.fd_map.get_mut(&fd).ok_or(__WASI_EBADF)?;
+        fd_entry.offset = new_offset as u64;
+        Ok(fd_entry.offset)
+    }
+
+    /// Returns the entries of the directory `fd` as their name, file type
+    /// and inode number, sorted by name.
+    pub fn read_dir(
+        &self,
+        fd: __wasi_fd_t,
+    ) -> Result<Vec<(String, __wasi_filetype_t, __wasi_inode_t)>, __wasi_errno_t> {
+        match &self.get_inodeval(fd)?.kind {
+            Kind::Dir { path, entries, .. } => {
+                // TODO: refactor this code
+                // we need to support multiple calls,
+                // simple and obviously correct implementation for now:
+                // maintain consistent order via lexacographic sorting
+                let in_memory = is_in_memory(path);
+                let mut entry_vec = if in_memory {
+                    Vec::new()
+                } else {
+                    let fs_info = std::fs::read_dir(path)
+                        .map_err(|_| __WASI_EIO)?
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| __WASI_EIO)?;
+                    fs_info
+                        .into_iter()
+                        .map(|entry| {
+                            Ok((
+                                entry.file_name().to_string_lossy().to_string(),
+                                super::host_file_type_to_wasi_file_type(
+                                    entry.file_type().map_err(|_| __WASI_EIO)?,
+                                ),
+                                0, // TODO: inode
+                            ))
+                        })
+                        .collect::<Result<Vec<(String, u8, u64)>, __wasi_errno_t>>()?
+                };
+                // the entries in memory are all listed, the other ones only
+                // if they are not on the host
+                entry_vec.extend(
+                    entries
+                        .iter()
+                        .filter(|(_, inode)| in_memory || self.inodes[**inode].is_preopened)
+                        .map(|(name, inode)| {
+                            let entry = &self.inodes[*inode];
+                            (name.to_string(), entry.stat.st_filetype, entry.stat.st_ino)
+                        }),
+                );
+                entry_vec.sort_by(|a, b| a.0.cmp(&b.0));
+                Ok(entry_vec)
+            }
+            Kind::Root { entries } => {
+                let sorted_entries = {
+                    let mut entry_vec: Vec<(String, Inode)> =
+                        entries.iter().map(|(a, b)| (a.clone(), *b)).collect();
+                    entry_vec.sort_by(|a, b| a.0.cmp(&b.0));
+                    entry_vec
+                };
+                Ok(sorted_entries
+                    .into_iter()
+                    .map(|(_, inode)| {
+                        let entry = &self.inodes[inode];
+                        (
+                            format!("/{}", entry.name),
+                            entry.stat.st_filetype,
+                            entry.stat.st_ino,
+                        )
+                    })
+                    .collect())
+            }
+            Kind::File { .. } | Kind::Symlink { .. } | Kind::Buffer { .. } => Err(__WASI_ENOTDIR),
+        }
+    }
+
+    /// Returns the metadata of the file at `path`, relative to the
+    /// directory `fd`.
+    pub fn path_filestat(
+        &mut self,
+        fd: __wasi_fd_t,
+        flags: __wasi_lookupflags_t,
+        path: &str,
+    ) -> Result<__wasi_filestat_t, __wasi_errno_t> {
+        let root_dir = self.get_fd(fd)?;
+
+        if !has_rights(root_dir.rights, __WASI_RIGHT_PATH_FILESTAT_GET) {
+            return Err(__WASI_EACCES);
+        }
+
+        debug!("=> base_fd: {}, path: {}", fd, &path);
+
+        let file_inode =
+            self.get_inode_at_path(fd, path, flags & __WASI_LOOKUP_SYMLINK_FOLLOW != 0)?;
+        let inode = &self.inodes[file_inode];
+        match &inode.kind {
+            // the inodes in memory keep their own metadata
+            _ if inode.is_preopened => Ok(inode.stat),
+            Kind::Dir { path, .. } if is_in_memory(path) => Ok(inode.stat),
+            Kind::Buffer { .. } => Ok(inode.stat),
+            kind => self.get_stat_for_kind(kind).ok_or(__WASI_EIO),
+        }
+    }
+
+    /// Creates the directory `path`, and its missing parents, relative to
+    /// the directory `fd`.
+    pub fn create_directory(&mut self, fd: __wasi_fd_t, path: &str) -> Result<(), __wasi_errno_t> {
+        let working_dir = self.get_fd(fd)?;
+        if !has_rights(working_dir.rights, __WASI_RIGHT_PATH_CREATE_DIRECTORY) {
+            return Err(__WASI_EACCES);
+        }
+        debug!("=> fd: {}, path: {}", fd, &path);
+
+        let path = Path::new(path);
+        if path.components().next().is_none() {
+            return Err(__WASI_EINVAL);
+        }
+
+        let mut cur_dir_inode = working_dir.inode;
+        let mut symlink_count = 0;
+        for comp in path.components() {
+            debug!("Creating dir {:?}", comp);
+            // an unlinked file is not in the arena anymore
+            let (parent, dir_path) = match self.inodes.get(cur_dir_inode).map(|inode| &inode.kind) {
+                Some(Kind::Dir { parent, path, .. }) => (*parent, path.clone()),
+                Some(Kind::Root { .. }) => return Err(__WASI_EACCES),
+                _ => return Err(__WASI_ENOTDIR),
+            };
+            let name = match comp {
+                Component::Prefix(_) | Component::RootDir => return Err(__WASI_ENOTCAPABLE),
+                Component::CurDir => continue,
+                Component::ParentDir => {
+                    cur_dir_inode = parent.ok_or(__WASI_EACCES)?;
+                    continue;
+                }
+                Component::Normal(name) => name.to_str().ok_or(__WASI_EINVAL)?,
+            };
+            cur_dir_inode = match self.get_entry_inode(cur_dir_inode, name) {
+                // the existing directories, or symlinks to them, are reused
+                Ok(inode) => self.follow_symlink(cur_dir_inode, inode, &mut symlink_count)?,
+                Err(__WASI_ENOENT) => {
+                    // the directories in memory only have subdirectories in memory
+                    let adjusted_path = if is_in_memory(&dir_path) {
+                        PathBuf::new()
+                    } else {
+                        let adjusted_path = dir_path.join(name);
+                        std::fs::create_dir(&adjusted_path).map_err(|_| __WASI_EIO)?;
+                        adjusted_path
+                    };
+                    let kind = Kind::Dir {
+                        parent: Some(cur_dir_inode),
+                        path: adjusted_path,
+                        entries: Default::default(),
+                    };
+                    let new_inode = self.create_inode(kind, false, name.to_string())?;
+                    // reborrow to insert
+                    if let Kind::Dir {
+                        ref mut entries, ..
+                    } = &mut self.inodes[cur_dir_inode].kind
+                    {
+                        entries.insert(name.to_string(), new_inode);
+                    }
+                    new_inode
+                }
+                Err(e) => return Err(e),
+            };
+        }
+
+        // the last component must be a directory too
+        match &self.inodes[cur_dir_inode].kind {
+            Kind::Dir { .. } => Ok(()),
+            Kind::Root { .. } => Err(__WASI_EACCES),
+            _ => Err(__WASI_ENOTDIR),
+        }
+    }
+
+    /// Removes the empty directory `path`, relative to the directory `fd`.
+    pub fn remove_directory(&mut self, fd: __wasi_fd_t, path: &str) -> Result<(), __wasi_errno_t> {
+        // TODO check if fd is a dir, ensure it's within sandbox, etc.
+        let base_dir = self.fd_map.get(&fd).ok_or(__WASI_EBADF)?;
+        if !has_rights(base_dir.rights, __WASI_RIGHT_PATH_REMOVE_DIRECTORY) {
+            return Err(__WASI_EACCES);
+        }
+
+        let inode = self.get_inode_at_path(fd, path, false)?;
+        let (parent_inode, childs_name) = self.get_parent_inode_at_path(fd, Path::new(path))?;
+
+        let host_path_to_remove = match &self.inodes[inode].kind {
+            Kind::Dir { entries, path, .. } => {
+                if !entries.is_empty()
+                    || (!is_in_memory(path)
+                        && std::fs::read_dir(path).map_err(|_| __WASI_EIO)?.count() != 0)
+                {
+                    return Err(__WASI_ENOTEMPTY);
+                }
+                path.clone()
+            }
+            Kind::Root { .. } => return Err(__WASI_EACCES),
+            _ => return Err(__WASI_ENOTDIR),
+        };
+
+        match &mut self.inodes[parent_inode].kind {
+            Kind::Dir {
+                ref mut entries, ..
+            } => {
+                if entries.get(&childs_name) != Some(&inode) {
+                    return Err(__WASI_EINVAL);
+                }
+                entries.remove(&childs_name);
+            }
+            Kind::Root { .. } => return Err(__WASI_EACCES),
+            Kind::File { .. } | Kind::Symlink { .. } | Kind::Buffer { .. } => {
+                return Err(__WASI_ENOTDIR)
+            }
+        }
+
+        if !is_in_memory(&host_path_to_remove) && std::fs::remove_dir(&host_path_to_remove).is_err()
+        {
+            // reinsert to prevent FS from being in bad state
+            if let Kind::Dir {
+                ref mut entries, ..
+            } = &mut self.inodes[parent_inode].kind
+            {
+                entries.insert(childs_name, inode);
+            }
+            // TODO: more intelligently return error value by inspecting returned error value
+            return Err(__WASI_EIO);
+        }
+
+        Ok(())
+    }
+
+    /// Renames the file `old_path`, relative to the directory `old_fd`, to
+    /// `new_path`, relative to the directory `new_fd`.
+    pub fn rename(
+        &mut self,
+        old_fd: __wasi_fd_t,
+        old_path: &str,
+        new_fd: __wasi_fd_t,
+        new_path: &str,
+    ) -> Result<(), __wasi_errno_t> {
+        let source_path = Path::new(old_path);
+        let target_path = Path::new(new_path);
+
+        {
+            let source_fd = self.get_fd(old_fd)?;
+            if !has_rights(source_fd.rights, __WASI_RIGHT_PATH_RENAME_SOURCE) {
+                return Err(__WASI_EACCES);
+            }
+            let target_fd = self.get_fd(new_fd)?;
+            if !has_rights(target_fd.rights, __WASI_RIGHT_PATH_RENAME_TARGET) {
+                return Err(__WASI_EACCES);
+            }
+        }
+
+        let (source_parent_inode, source_entry_name) =
+            self.get_parent_inode_at_path(old_fd, source_path)?;
+        let (target_parent_inode, target_entry_name) =
+            self.get_parent_inode_at_path(new_fd, target_path)?;
+
+        let (host_adjusted_target_path, in_memory) = match &self.inodes[target_parent_inode].kind {
+            Kind::Dir { entries, path, .. } => {
+                if entries.contains_key(&target_entry_name) {
+                    return Err(__WASI_EEXIST);
+                }
+                let mut out_path = path.clone();
+                out_path.push(&target_entry_name);
+                (out_path, is_in_memory(path))
+            }
+            Kind::Root { .. } => return Err(__WASI_ENOTCAPABLE),
+            Kind::Symlink { .. } | Kind::File { .. } | Kind::Buffer { .. } => {
+                return Err(__WASI_ENOTDIR)
+            }
+        };
+        // the files can't move between the host and the memory
+        match &self.inodes[source_parent_inode].kind {
+            Kind::Dir { path, .. } if is_in_memory(path) != in_memory => return Err(__WASI_EXDEV),
+            _ => (),
+        }
+        // load the source entry, if it was not looked up yet
+        let source_inode = self.get_inode_at_path(old_fd, old_path, false)?;
+        // a directory can't be moved into itself
+        let mut cur_inode = target_parent_inode;
+        while let Kind::Dir { parent, .. } = &self.inodes[cur_inode].kind {
+            if cur_inode == source_inode {
+                return Err(__WASI_EINVAL);
+            }
+            match parent {
+                Some(parent) => cur_inode = *parent,
+                None => break,
+            }
+        }
+        let (source_entry, host_source_path) = match &mut self.inodes[source_parent_inode].kind {
+            Kind::Dir { entries, path, .. } => (
+                entries.remove(&source_entry_name).ok_or(__WASI_EINVAL)?,
+                path.join(&source_entry_name),
+            ),
+            Kind::Root { .. } => return Err(__WASI_ENOTCAPABLE),
+            Kind::Symlink { .. } | Kind::File { .. } | Kind::Buffer { .. } => {
+                return Err(__WASI_ENOTDIR)
+            }
+        };
+
+        let result = match &mut self.inodes[source_entry].kind {
+            // nothing is on the host
+            _ if in_memory => Ok(()),
+            Kind::File { handle, path, .. } => {
+                if let Some(h) = handle {
+                    h.rename_file(&host_adjusted_target_path)
+                        .map_err(|e| e.into_wasi_err())
+                } else {
+                    std::fs::rename(path, &host_adjusted_target_path).map_err(|_| __WASI_EIO)
+                }
+            }
+            Kind::Dir { path, .. } => std::fs::rename(path, &host_adjusted_target_path)
+                .map_err(|e| WasiFsError::from(e).into_wasi_err()),
+            Kind::Buffer { .. } => Ok(()),
+            Kind::Symlink { .. } => std::fs::rename(&host_source_path, &host_adjusted_target_path)
+                .map_err(|_| __WASI_EIO),
+            Kind::Root { .. } => Err(__WASI_EACCES),
+        };
+        // if the above operation failed we have to revert the previous change and then fail
+        if let Err(e) = result {
+            if let Kind::Dir { entries, .. } = &mut self.inodes[source_parent_inode].kind {
+                entries.insert(source_entry_name, source_entry);
+            }
+            return Err(e);
+        }
+
+        if let Kind::Dir { parent, .. } = &mut self.inodes[source_entry].kind {
+            *parent = Some(target_parent_inode);
+        }
+        if !in_memory {
+            self.move_host_paths(source_entry, host_adjusted_target_path);
+        }
+        if let Kind::Dir { entries, .. } = &mut self.inodes[target_parent_inode].kind {
+            entries.insert(target_entry_name, source_entry);
+        }
+
+        Ok(())
+    }
+
+    /// Updates the host paths of `inode`, and of its descendants, after it
+    /// was moved to `new_path` on the host.
+    fn move_host_paths(&mut self, inode: Inode, new_path: PathBuf) {
+        let mut stack = vec![(inode, new_path)];
+        while let Some((inode, new_path)) = stack.pop() {
+            let pre_open = self.path_into_pre_open_and_relative_path(&new_path);
+            match &mut self.inodes[inode].kind {
+                Kind::Dir { path, entries, .. } => {
+                    stack.extend(
+                        entries
+                            .iter()
+                            .map(|(name, entry)| (*entry, new_path.join(name))),
+                    );
+                    *path = new_path;
+                }
+                Kind::File { handle, path, .. } => {
+                    if let Some(host_file) = handle
+                        .as_mut()
+                        .and_then(|handle| handle.downcast_mut::<HostFile>())
+                    {
+                        host_file.host_path = new_path.clone();
+                    }
+                    *path = new_path;
+                }
+                Kind::Symlink {
+                    base_po_dir,
+                    path_to_symlink,
+                    ..
+                } => {
+                    if let Ok((po_fd, relative_path)) = pre_open {
+                        *base_po_dir = po_fd;
+                        *path_to_symlink = relative_path;
+                    }
+                }
+                Kind::Buffer { .. } | Kind::Root { .. } => (),
+            }
+        }
+    }
+
+    /// Creates the symlink `new_path`, relative to the directory `fd`,
+    /// pointing to `old_path`.
+    ///
+    /// `old_path` is stored as is, and is resolved relative to the directory
+    /// containing the symlink when the symlink is followed.
+    pub fn create_symlink(
+        &mut self,
+        fd: __wasi_fd_t,
+        old_path: &str,
+        new_path: &str,
+    ) -> Result<(), __wasi_errno_t> {
+        let base_fd = self.get_fd(fd)?;
+        if !has_rights(base_fd.rights, __WASI_RIGHT_PATH_SYMLINK) {
+            return Err(__WASI_EACCES);
+        }
+        // absolute paths are outside of the preopened directories
+        if Path::new(old_path).has_root() {
+            return Err(__WASI_ENOTCAPABLE);
+        }
+
+        let (parent_inode, entry_name) = self.get_parent_inode_at_path(fd, Path::new(new_path))?;
+        let host_path = match &self.inodes[parent_inode].kind {
+            Kind::Dir { entries, path, .. } => {
+                if entries.contains_key(&entry_name) {
+                    return Err(__WASI_EEXIST);
+                }
+                // the symlinks are only on the host
+                if is_in_memory(path) {
+                    return Err(__WASI_ENOTSUP);
+                }
+                path.join(&entry_name)
+            }
+            Kind::Root { .. } => return Err(__WASI_ENOTCAPABLE),
+            Kind::File { .. } | Kind::Symlink { .. } | Kind::Buffer { .. } => {
+                return Err(__WASI_ENOTDIR)
+            }
+        };
+        let (base_po_dir, path_to_symlink) =
+            self.path_into_pre_open_and_relative_path(&host_path)?;
+        debug!("Symlinking {} to {}", new_path, old_path);
+
+        #[cfg(unix)]
+        let result = std::os::unix::fs::symlink(old_path, &host_path);
+        #[cfg(windows)]
+        let result = std::os::windows::fs::symlink_file(old_path, &host_path);
+        result.map_err(|e| WasiFsError::from(e).into_wasi_err())?;
+
+        let kind = Kind::Symlink {
+            base_po_dir,
+            path_to_symlink,
+            relative_path: PathBuf::from(old_path),
+        };
+        let new_inode = self.create_inode(kind, false, entry_name.clone())?;
+        if let Kind::Dir {
+            ref mut entries, ..
+        } = &mut self.inodes[parent_inode].kind
+        {
+            entries.insert(entry_name, new_inode);
+        }
+
+        Ok(())
+    }
+
+    /// Unlinks the file `path`, relative to the directory `fd`, deleting it
+    /// if it was its last link.
+    pub fn unlink_file(&mut self, fd: __wasi_fd_t, path: &str) -> Result<(), __wasi_errno_t> {
+        let base_dir = self.fd_map.get(&fd).ok_or(__WASI_EBADF)?;
+        if !has_rights(base_dir.rights, __WASI_RIGHT_PATH_UNLINK_FILE) {
+            return Err(__WASI_EACCES);
+        }
+        debug!("Requested file: {}", path);
+
+        let inode = self.get_inode_at_path(fd, path, false)?;
+        if let Kind::Dir { .. } | Kind::Root { .. } = &self.inodes[inode].kind {
+            return Err(__WASI_EISDIR);
+        }
+        let (parent_inode, childs_name) = self.get_parent_inode_at_path(fd, Path::new(path))?;
+
+        let (removed_inode, host_path) = match &mut self.inodes[parent_inode].kind {
+            Kind::Dir {
+                ref mut entries,
+                path,
+                ..
+            } => {
+                if entries.get(&childs_name) != Some(&inode) {
+                    return Err(__WASI_EINVAL);
+                }
+                entries.remove(&childs_name);
+                (inode, path.join(&childs_name))
+            }
+            Kind::Root { .. } => return Err(__WASI_EACCES),
+            Kind::File { .. } | Kind::Symlink { .. } | Kind::Buffer { .. } => {
+                return Err(__WASI_ENOTDIR)
+            }
+        };
+
+        let nlink = &mut self.inodes[removed_inode].stat.st_nlink;
+        *nlink = nlink.saturating_sub(1);
+        if self.inodes[removed_inode].stat.st_nlink == 0 {
+            match &mut self.inodes[removed_inode].kind {
+                Kind::File { handle, path, .. } => {
+                    if let Some(h) = handle {
+                        h.unlink().map_err(WasiFsError::into_wasi_err)?;
+                    } else {
+                        // File is closed
+                        // problem with the abstraction, we can't call unlink because there's no handle
+                        // TODO: replace this code
+                        std::fs::remove_file(path).map_err(|_| __WASI_EIO)?;
+                    }
+                }
+                Kind::Dir { .. } | Kind::Root { .. } => return Err(__WASI_EISDIR),
+                Kind::Symlink { .. } => {
+                    std::fs::remove_file(&host_path).map_err(|_| __WASI_EIO)?;
+                }
+                // buffers only exist in memory
+                Kind::Buffer { .. } => (),
+            }
+            // TODO: test this on Windows and actually make it portable
+            // make the file an orphan fd if the fd is still open
+            let fd_is_orphaned = match &self.inodes[removed_inode].kind {
+                Kind::File { handle, .. } => handle.is_some(),
+                Kind::Buffer { .. } => self.fd_map.values().any(|fd| fd.inode == removed_inode),
+                _ => false,
+            };
+            let removed_inode_val = unsafe { self.remove_inode(removed_inode) };
+            if let (true, Some(removed_inode_val)) = (fd_is_orphaned, removed_inode_val) {
+                self.orphan_fds.insert(removed_inode, removed_inode_val);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::WasiState;
+    use super::*;
+    use std::fs;
+
+    /// The fd of the preopened directory, after the virtual root.
+    const DIR_FD: __wasi_fd_t = 4;
+    const RIGHTS: __wasi_rights_t = __WASI_RIGHT_FD_READ | __WASI_RIGHT_FD_WRITE;
+
+    fn wasi_fs(dir: &Path) -> WasiFs {
+        WasiState::new("test_prog")
+            .map_dir("sandbox", dir)
+            .unwrap()
+            .build()
+            .unwrap()
+            .fs
+    }
+
+    fn read_to_string(fs: &mut WasiFs, fd: __wasi_fd_t) -> String {
+        let mut buf = [0; 64];
+        let read = fs.read_fd(fd, &mut buf).unwrap();
+        String::from_utf8(buf[..read].to_vec()).unwrap()
+    }
+
+    /// Adds the buffer `name` to the preopened directory.
+    fn insert_buffer(fs: &mut WasiFs, name: &str, contents: &[u8]) {
+        let inode = fs.create_inode_with_default_stat(
+            Kind::Buffer {
+                buffer: contents.to_vec(),
+            },
+            false,
+            name.to_string(),
+        );
+        let dir_inode = fs.get_fd(DIR_FD).unwrap().inode;
+        if let Kind::Dir { entries, .. } = &mut fs.inodes[dir_inode].kind {
+            entries.insert(name.to_string(), inode);
+        }
+    }
+
+    #[test]
+    fn rename_directories() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(temp_dir.path().join("a/b")).unwrap();
+        fs::write(temp_dir.path().join("a/b/file.txt"), "hello").unwrap();
+        let mut fs = wasi_fs(temp_dir.path());
+
+        let fd = fs
+            .open_path(DIR_FD, 0, "a/b/file.txt", 0, RIGHTS, 0, 0)
+            .unwrap();
+        assert_eq!(fs.rename(DIR_FD, "a", DIR_FD, "c"), Ok(()));
+        assert!(!temp_dir.path().join("a").exists());
+        assert_eq!(read_to_string(&mut fs, fd), "hello");
+        assert_eq!(
+            fs.open_path(DIR_FD, 0, "a/b/file.txt", 0, RIGHTS, 0, 0),
+            Err(__WASI_ENOENT)
+        );
+        let fd = fs
+            .open_path(DIR_FD, 0, "c/b/file.txt", 0, RIGHTS, 0, 0)
+            .unwrap();
+        assert_eq!(read_to_string(&mut fs, fd), "hello");
+
+        // a directory can't be moved into itself
+        assert_eq!(fs.rename(DIR_FD, "c", DIR_FD, "c/b/d"), Err(__WASI_EINVAL));
+        assert_eq!(fs.create_directory(DIR_FD, "e"), Ok(()));
+        assert_eq!(fs.rename(DIR_FD, "c/b", DIR_FD, "e"), Err(__WASI_EEXIST));
+        assert_eq!(fs.rename(DIR_FD, "c/b", DIR_FD, "e/b"), Ok(()));
+
+        // the open file follows its directory
+        assert_eq!(fs.unlink_file(DIR_FD, "e/b/file.txt"), Ok(()));
+        assert!(!temp_dir.path().join("e/b/file.txt").exists());
+        assert_eq!(fs.remove_directory(DIR_FD, "e/b"), Ok(()));
+    }
+
+    #[test]
+    fn buffers() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut fs = wasi_fs(temp_dir.path());
+        insert_buffer(&mut fs, "buffer", b"hello");
+
+        let fd = fs.open_path(DIR_FD, 0, "buffer", 0, RIGHTS, 0, 0).unwrap();
+        assert_eq!(read_to_string(&mut fs, fd), "hello");
+        assert_eq!(fs.write_fd(fd, b" world"), Ok(6));
+        assert_eq!(fs.seek_fd(fd, 0, __WASI_WHENCE_SET), Ok(0));
+        assert_eq!(read_to_string(&mut fs, fd), "hello world");
+        assert_eq!(fs.filestat_fd(fd).map(|stat| stat.st_size), Ok(11));
+
+        assert_eq!(
+            fs.open_path(DIR_FD, 0, "buffer", __WASI_O_DIRECTORY, RIGHTS, 0, 0),
+            Err(__WASI_ENOTDIR)
+        );
+        let fd = fs
+            .open_path(DIR_FD, 0, "buffer", __WASI_O_TRUNC, RIGHTS, 0, 0)
+            .unwrap();
+        assert_eq!(read_to_string(&mut fs, fd), "");
+
+        assert_eq!(fs.rename(DIR_FD, "buffer", DIR_FD, "renamed"), Ok(()));
+        assert_eq!(fs.unlink_file(DIR_FD, "renamed"), Ok(()));
+        assert_eq!(
+            fs.open_path(DIR_FD, 0, "renamed", 0, RIGHTS, 0, 0),
+            Err(__WASI_ENOENT)
+        );
+    }
+
+    #[test]
+    fn directories_in_memory() {
+        let mut fs = WasiState::new("test_prog")
+            .preopen(|p| {
+                p.in_memory(true)
+                    .alias("sandbox")
+                    .read(true)
+                    .write(true)
+                    .create(true)
+            })
+            .unwrap()
+            .build()
+            .unwrap()
+            .fs;
+
+        let fd = fs
+            .open_path(DIR_FD, 0, "in_memory_file", __WASI_O_CREAT, RIGHTS, 0, 0)
+            .unwrap();
+        assert_eq!(fs.write_fd(fd, b"hello"), Ok(5));
+        assert_eq!(fs.create_directory(DIR_FD, "in_memory_dir/a"), Ok(()));
+        assert_eq!(
+            fs.rename(DIR_FD, "in_memory_file", DIR_FD, "in_memory_dir/file"),
+            Ok(())
+        );
+        assert_eq!(
+            fs.path_filestat(DIR_FD, 0, "in_memory_dir/file")
+                .map(|stat| (stat.st_filetype, stat.st_size)),
+            Ok((__WASI_FILETYPE_REGULAR_FILE, 5))
+        );
+        let entries = fs.read_dir(DIR_FD).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0, "in_memory_dir");
+        assert_eq!(entries[0].1, __WASI_FILETYPE_DIRECTORY);
+        let fd = fs
+            .open_path(DIR_FD, 0, "in_memory_dir/file", 0, RIGHTS, 0, 0)
+            .unwrap();
+        assert_eq!(read_to_string(&mut fs, fd), "hello");
+
+        // nothing is looked up or created on the host
+        assert!(!Path::new("in_memory_file").exists());
+        assert!(!Path::new("in_memory_dir").exists());
+        assert_eq!(
+            fs.open_path(DIR_FD, 0, "src", 0, RIGHTS, 0, 0),
+            Err(__WASI_ENOENT)
+        );
+        assert_eq!(
+            fs.create_symlink(DIR_FD, "in_memory_dir", "link"),
+            Err(__WASI_ENOTSUP)
+        );
+
+        assert_eq!(
+            fs.remove_directory(DIR_FD, "in_memory_dir"),
+            Err(__WASI_ENOTEMPTY)
+        );
+        assert_eq!(fs.unlink_file(DIR_FD, "in_memory_dir/file"), Ok(()));
+        assert_eq!(fs.remove_directory(DIR_FD, "in_memory_dir/a"), Ok(()));
+        assert_eq!(fs.remove_directory(DIR_FD, "in_memory_dir"), Ok(()));
+        assert_eq!(fs.read_dir(DIR_FD), Ok(vec![]));
+        assert_eq!(fs.close_fd(DIR_FD), Ok(()));
+    }
+
+    #[test]
+    fn unlinked_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::write(temp_dir.path().join("file.txt"), "hello").unwrap();
+        let mut fs = wasi_fs(temp_dir.path());
+
+        let fd = fs
+            .open_path(DIR_FD, 0, "file.txt", 0, RIGHTS, 0, 0)
+            .unwrap();
+        assert_eq!(fs.unlink_file(DIR_FD, "file.txt"), Ok(()));
+        assert_eq!(read_to_string(&mut fs, fd), "hello");
+        assert_eq!(fs.filestat_fd(fd).map(|stat| stat.st_size), Ok(5));
+        assert_eq!(
+            fs.open_path(fd, 0, "file.txt", 0, RIGHTS, 0, 0),
+            Err(__WASI_ENOTDIR)
+        );
+        assert_eq!(fs.create_directory(fd, "dir"), Err(__WASI_ENOTDIR));
+
+        assert_eq!(fs.close_fd(fd), Ok(()));
+        assert!(fs.orphan_fds.is_empty());
+        assert_eq!(fs.read_fd(fd, &mut [0; 4]), Err(__WASI_EBADF));
+    }
+
+    #[test]
+    fn invalid_offsets_and_sizes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut fs = wasi_fs(temp_dir.path());
+        insert_buffer(&mut fs, "buffer", b"hello");
+
+        let fd = fs.open_path(DIR_FD, 0, "buffer", 0, RIGHTS, 0, 0).unwrap();
+        assert_eq!(fs.seek_fd(fd, -1, __WASI_WHENCE_SET), Err(__WASI_EINVAL));
+        assert_eq!(fs.seek_fd(fd, -6, __WASI_WHENCE_END), Err(__WASI_EINVAL));
+        assert_eq!(
+            fs.seek_fd(fd, i64::MAX, __WASI_WHENCE_SET),
+            Ok(i64::MAX as u64)
+        );
+        assert_eq!(fs.seek_fd(fd, 1, __WASI_WHENCE_CUR), Err(__WASI_EINVAL));
+        // the buffer can't grow as much as the guest asks for
+        assert!(fs.write_fd(fd, b"!").is_err());
+        assert!(fs.set_size(fd, u64::MAX).is_err());
+
+        assert_eq!(fs.seek_fd(fd, 0, __WASI_WHENCE_SET), Ok(0));
+        assert_eq!(read_to_string(&mut fs, fd), "hello");
+    }
+
+    #[test]
+    fn renumbered_fds() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::write(temp_dir.path().join("file.txt"), "hello").unwrap();
+        let mut fs = wasi_fs(temp_dir.path());
+
+        // any fd can be renumbered into the location of a standard device
+        let dir = fs.fd_map.remove(&DIR_FD).unwrap();
+        fs.fd_map.insert(__WASI_STDOUT_FILENO, dir);
+        assert_eq!(
+            fs.write_fd(__WASI_STDOUT_FILENO, b"hello"),
+            Err(__WASI_EINVAL)
+        );
+        let fd = fs
+            .open_path(__WASI_STDOUT_FILENO, 0, "file.txt", 0, RIGHTS, 0, 0)
+            .unwrap();
+        assert_eq!(read_to_string(&mut fs, fd), "hello");
+
+        // the symlinks are located relative to the fds of the preopened directories
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("file.txt", temp_dir.path().join("link")).unwrap();
+            assert_eq!(
+                fs.open_path(
+                    __WASI_STDOUT_FILENO,
+                    __WASI_LOOKUP_SYMLINK_FOLLOW,
+                    "link",
+                    0,
+                    RIGHTS,
+                    0,
+                    0
+                ),
+                Err(__WASI_EINVAL)
+            );
+        }
+    }
+}
@@ -6,9 +6,11 @@ use std::any::Any;
 use std::convert::TryInto;
 use std::fmt;
 use std::{
+    collections::VecDeque,
     fs,
     io::{self, Read, Seek, Write},
     path::PathBuf,
+    sync::{Arc, Mutex},
     time::SystemTime,
 };
 use thiserror::Error;
@@ -199,7 +201,7 @@ pub trait WasiFile: fmt::Debug + Send + Write + Read + Seek + 'static + Upcastab
     /// NOTE: the signature of this function will change before stabilization
     // TODO: stablizie this in 0.7.0 or 0.8.0 by removing default impl
     fn rename_file(&self, _new_name: &std::path::Path) -> Result<(), WasiFsError> {
-        panic!("Default implementation for now as this method is unstable; this default implementation or this entire method may be removed in a future release.");
+        Err(WasiFsError::UnknownError(__WASI_ENOTSUP))
     }
 
     /// Returns the number of bytes available.  This function must not block
@@ -210,6 +212,19 @@ pub trait WasiFile: fmt::Debug + Send + Write + Read + Seek + 'static + Upcastab
     fn get_raw_fd(&self) -> Option<i32> {
         None
     }
+
+    /// Returns the events of `events` the file is ready for, without blocking: `PollIn` if
+    /// reading won't block, `PollOut` if writing won't block, and `PollError` or
+    /// `PollHangUp` whatever `events` is.
+    /// Default polls the host fd returned by `get_raw_fd`, or reports the file as always ready
+    /// when there is none.  You should implement this method if reading or writing your file
+    /// can block
+    fn poll_readiness(&self, events: PollEventSet) -> Result<PollEventSet, WasiFsError> {
+        match self.get_raw_fd() {
+            Some(host_fd) => poll_host_fd(host_fd, events),
+            None => Ok(events),
+        }
+    }
 }
 
 // Implementation of `Upcastable` taken from https://users.rust-lang.org/t/why-does-downcasting-not-work-for-subtraits/33286/7 .
@@ -302,7 +317,8 @@ impl Iterator for PollEventIter {
                     return Some(r);
                 }
             }
-            unreachable!("Internal logic error in PollEventIter");
+            // the remaining bits are not events
+            None
         }
     }
 }
@@ -360,46 +376,61 @@ impl PollEventBuilder {
     }
 }
 
+/// Polls the host fd `host_fd` for `events`, without blocking.
+#[cfg(unix)]
+pub(crate) fn poll_host_fd(
+    host_fd: i32,
+    events: PollEventSet,
+) -> Result<PollEventSet, WasiFsError> {
+    let mut fd = libc::pollfd {
+        fd: host_fd,
+        events: poll_event_set_to_platform_poll_events(events),
+        revents: 0,
+    };
+    let result = unsafe { libc::poll(&mut fd, 1, 0) };
+
+    if result < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(platform_poll_events_to_pollevent_set(fd.revents))
+}
+
+/// Blocks until one of the host fds of `host_fds` is ready for its events, or until
+/// `timeout` has elapsed. It waits forever when `timeout` is `None`.
+///
+/// This may also return early, for example when interrupted by a signal.
 #[cfg(unix)]
-pub(crate) fn poll(
-    selfs: &[&dyn WasiFile],
-    events: &[PollEventSet],
-    seen_events: &mut [PollEventSet],
-) -> Result<u32, WasiFsError> {
-    if !(selfs.len() == events.len() && events.len() == seen_events.len()) {
-        return Err(WasiFsError::InvalidInput);
-    }
-    let mut fds = selfs
+pub(crate) fn wait_for_host_fds(
+    host_fds: &[(i32, PollEventSet)],
+    timeout: Option<std::time::Duration>,
+) {
+    let mut fds = host_fds
         .iter()
-        .enumerate()
-        .filter_map(|(i, s)| s.get_raw_fd().map(|rfd| (i, rfd)))
-        .map(|(i, host_fd)| libc::pollfd {
-            fd: host_fd,
-            events: poll_event_set_to_platform_poll_events(events[i]),
+        .map(|&(fd, events)| libc::pollfd {
+            fd,
+            events: poll_event_set_to_platform_poll_events(events),
             revents: 0,
         })
         .collect::<Vec<_>>();
-    let result = unsafe { libc::poll(fds.as_mut_ptr(), selfs.len() as _, 1) };
-
-    if result < 0 {
-        // TODO: check errno and return value
-        return Err(WasiFsError::IOError);
-    }
-    // convert result and write back values
-    for (i, fd) in fds.into_iter().enumerate() {
-        seen_events[i] = platform_poll_events_to_pollevent_set(fd.revents);
-    }
-    // unwrap is safe because we check for negative values above
-    Ok(result.try_into().unwrap())
+    let timeout = match timeout {
+        // Rounded up, so that the timeout has elapsed when `poll` returns.
+        Some(timeout) => {
+            let millis = timeout.as_millis() + u128::from(timeout.subsec_nanos() % 1_000_000 != 0);
+            std::cmp::min(millis, i32::MAX as u128) as i32
+        }
+        None => -1,
+    };
+    // The errors are reported by the next poll of the fds.
+    unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
 }
 
+/// Polling host fds is not supported on this target, so the files are always ready.
 #[cfg(not(unix))]
-pub(crate) fn poll(
-    _selfs: &[&dyn WasiFile],
-    _events: &[PollEventSet],
-    _seen_events: &mut [PollEventSet],
-) -> Result<(), WasiFsError> {
-    unimplemented!("HostFile::poll in WasiFile is not implemented for non-Unix-like targets yet");
+pub(crate) fn poll_host_fd(
+    _host_fd: i32,
+    events: PollEventSet,
+) -> Result<PollEventSet, WasiFsError> {
+    Ok(events)
 }
 
 pub trait WasiPath {}
@@ -523,10 +554,6 @@ impl HostFile {
             flags,
         }
     }
-
-    pub fn metadata(&self) -> fs::Metadata {
-        self.inner.metadata().unwrap()
-    }
 }
 
 impl Read for HostFile {
@@ -566,8 +593,9 @@ impl Write for HostFile {
 #[typetag::serde]
 impl WasiFile for HostFile {
     fn last_accessed(&self) -> u64 {
-        self.metadata()
-            .accessed()
+        self.inner
+            .metadata()
+            .and_then(|md| md.accessed())
             .ok()
             .and_then(|ct| ct.duration_since(SystemTime::UNIX_EPOCH).ok())
             .map(|ct| ct.as_nanos() as u64)
@@ -579,8 +607,9 @@ impl WasiFile for HostFile {
     }
 
     fn last_modified(&self) -> u64 {
-        self.metadata()
-            .modified()
+        self.inner
+            .metadata()
+            .and_then(|md| md.modified())
             .ok()
             .and_then(|ct| ct.duration_since(SystemTime::UNIX_EPOCH).ok())
             .map(|ct| ct.as_nanos() as u64)
@@ -592,8 +621,9 @@ impl WasiFile for HostFile {
     }
 
     fn created_time(&self) -> u64 {
-        self.metadata()
-            .created()
+        self.inner
+            .metadata()
+            .and_then(|md| md.created())
             .ok()
             .and_then(|ct| ct.duration_since(SystemTime::UNIX_EPOCH).ok())
             .map(|ct| ct.as_nanos() as u64)
@@ -605,7 +635,7 @@ impl WasiFile for HostFile {
     }
 
     fn size(&self) -> u64 {
-        self.metadata().len()
+        self.inner.metadata().map(|md| md.len()).unwrap_or(0)
     }
 
     fn set_len(&mut self, new_size: __wasi_filesize_t) -> Result<(), WasiFsError> {
@@ -624,8 +654,7 @@ impl WasiFile for HostFile {
     }
 
     fn bytes_available(&self) -> Result<usize, WasiFsError> {
-        // unwrap is safe because of get_raw_fd implementation
-        let host_fd = self.get_raw_fd().unwrap();
+        let host_fd = self.get_raw_fd().ok_or(WasiFsError::InvalidFd)?;
 
         host_file_bytes_available(host_fd)
     }
@@ -637,9 +666,7 @@ impl WasiFile for HostFile {
     }
     #[cfg(not(unix))]
     fn get_raw_fd(&self) -> Option<i32> {
-        unimplemented!(
-            "HostFile::get_raw_fd in WasiFile is not implemented for non-Unix-like targets yet"
-        );
+        None
     }
 }
 
@@ -687,7 +714,7 @@ fn host_file_bytes_available(host_fd: i32) -> Result<usize, WasiFsError> {
 
 #[cfg(not(unix))]
 fn host_file_bytes_available(_raw_fd: i32) -> Result<usize, WasiFsError> {
-    unimplemented!("host_file_bytes_available not yet implemented for non-Unix-like targets.  This probably means the program tried to use wasi::poll_oneoff")
+    Err(WasiFsError::UnknownError(__WASI_ENOTSUP))
 }
 
 /// A wrapper type around Stdout that implements `WasiFile` and
@@ -763,8 +790,7 @@ impl WasiFile for Stdout {
     }
 
     fn bytes_available(&self) -> Result<usize, WasiFsError> {
-        // unwrap is safe because of get_raw_fd implementation
-        let host_fd = self.get_raw_fd().unwrap();
+        let host_fd = self.get_raw_fd().ok_or(WasiFsError::InvalidFd)?;
 
         host_file_bytes_available(host_fd)
     }
@@ -777,9 +803,7 @@ impl WasiFile for Stdout {
 
     #[cfg(not(unix))]
     fn get_raw_fd(&self) -> Option<i32> {
-        unimplemented!(
-            "Stdout::get_raw_fd in WasiFile is not implemented for non-Unix-like targets yet"
-        );
+        None
     }
 }
 
@@ -856,8 +880,7 @@ impl WasiFile for Stderr {
     }
 
     fn bytes_available(&self) -> Result<usize, WasiFsError> {
-        // unwrap is safe because of get_raw_fd implementation
-        let host_fd = self.get_raw_fd().unwrap();
+        let host_fd = self.get_raw_fd().ok_or(WasiFsError::InvalidFd)?;
 
         host_file_bytes_available(host_fd)
     }
@@ -870,9 +893,7 @@ impl WasiFile for Stderr {
 
     #[cfg(not(unix))]
     fn get_raw_fd(&self) -> Option<i32> {
-        unimplemented!(
-            "Stderr::get_raw_fd in WasiFile is not implemented for non-Unix-like targets yet"
-        );
+        None
     }
 }
 
@@ -950,8 +971,7 @@ impl WasiFile for Stdin {
     }
 
     fn bytes_available(&self) -> Result<usize, WasiFsError> {
-        // unwrap is safe because of get_raw_fd implementation
-        let host_fd = self.get_raw_fd().unwrap();
+        let host_fd = self.get_raw_fd().ok_or(WasiFsError::InvalidFd)?;
 
         host_file_bytes_available(host_fd)
     }
@@ -964,9 +984,143 @@ impl WasiFile for Stdin {
 
     #[cfg(not(unix))]
     fn get_raw_fd(&self) -> Option<i32> {
-        unimplemented!(
-            "Stdin::get_raw_fd in WasiFile is not implemented for non-Unix-like targets yet"
-        );
+        None
+    }
+}
+
+/// An in-memory pipe that implements `WasiFile`: the bytes written to it are read back in
+/// order.
+///
+/// It can replace the standard streams of a module, see for example
+/// [`WasiStateBuilder::stdin`], and is ready for reading only when it holds bytes or
+/// when it's closed.
+///
+/// The clones of a pipe share its bytes, so the host can keep a clone to
+/// write the input of a module, or read its output, while it runs. The
+/// host closes the pipe with [`Pipe::close`] once it has written
+/// everything.
+///
+/// [`WasiStateBuilder::stdin`]: crate::WasiStateBuilder::stdin
+#[derive(Debug, Clone, Default)]
+pub struct Pipe {
+    buffer: Arc<Mutex<PipeBuffer>>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PipeBuffer {
+    bytes: VecDeque<u8>,
+    /// Whether the pipe was closed: nothing can be written to it anymore.
+    closed: bool,
+}
+
+impl Pipe {
+    /// Creates an empty pipe.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Closes the pipe, for all its clones.
+    ///
+    /// The bytes it holds can still be read, then reading it returns
+    /// the end of the file, and `poll_oneoff` reports it as hung up.
+    /// Writing to a closed pipe fails with a broken pipe error.
+    pub fn close(&self) {
+        self.buffer.lock().unwrap().closed = true;
+    }
+
+    /// Returns whether the pipe was closed.
+    pub fn is_closed(&self) -> bool {
+        self.buffer.lock().unwrap().closed
+    }
+}
+
+// A serialized pipe holds its bytes, and isn't shared with the clones of
+// the original pipe once deserialized.
+impl Serialize for Pipe {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.buffer.lock().unwrap().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Pipe {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let buffer = PipeBuffer::deserialize(deserializer)?;
+        Ok(Self {
+            buffer: Arc::new(Mutex::new(buffer)),
+        })
+    }
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut buffer = self.buffer.lock().unwrap();
+        let amt = std::cmp::min(buf.len(), buffer.bytes.len());
+        for (byte, read) in buf.iter_mut().zip(buffer.bytes.drain(..amt)) {
+            *byte = read;
+        }
+        Ok(amt)
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.closed {
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "can not write to a closed pipe",
+            ));
+        }
+        buffer.bytes.extend(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for Pipe {
+    fn seek(&mut self, _pos: io::SeekFrom) -> io::Result<u64> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "can not seek in a pipe",
+        ))
+    }
+}
+
+#[typetag::serde]
+impl WasiFile for Pipe {
+    fn last_accessed(&self) -> u64 {
+        0
+    }
+    fn last_modified(&self) -> u64 {
+        0
+    }
+    fn created_time(&self) -> u64 {
+        0
+    }
+    fn size(&self) -> u64 {
+        self.buffer.lock().unwrap().bytes.len() as u64
+    }
+    fn set_len(&mut self, _new_size: __wasi_filesize_t) -> Result<(), WasiFsError> {
+        Err(WasiFsError::PermissionDenied)
+    }
+    fn unlink(&mut self) -> Result<(), WasiFsError> {
+        Ok(())
+    }
+    fn bytes_available(&self) -> Result<usize, WasiFsError> {
+        Ok(self.buffer.lock().unwrap().bytes.len())
+    }
+    fn poll_readiness(&self, events: PollEventSet) -> Result<PollEventSet, WasiFsError> {
+        let buffer = self.buffer.lock().unwrap();
+        let mut readiness = events;
+        if buffer.bytes.is_empty() {
+            readiness &= !(PollEvent::PollIn as PollEventSet);
+        }
+        if buffer.closed {
+            readiness |= PollEvent::PollHangUp as PollEventSet;
+        }
+        Ok(readiness)
     }
 }
 
@@ -14,12 +14,14 @@ pub mod windows;
 pub mod legacy;
 
 use self::types::*;
+#[cfg(unix)]
+use crate::state::wait_for_host_fds;
 use crate::{
     ptr::{Array, WasmPtr},
     state::{
-        self, host_file_type_to_wasi_file_type, iterate_poll_events, poll, Fd, HostFile, Inode,
-        InodeVal, Kind, PollEvent, PollEventBuilder, WasiFile, WasiFsError, WasiState,
-        MAX_SYMLINKS,
+        self, host_file_type_to_wasi_file_type, iterate_poll_events, Determinism, Fd, HostFile,
+        Inode, InodeVal, Kind, PollEvent, PollEventBuilder, PollEventSet, WasiFile, WasiFsError,
+        WasiState, DETERMINISTIC_CLOCK_RESOLUTION, MAX_SYMLINKS,
     },
     WasiEnv, WasiError,
 };
@@ -46,6 +48,10 @@ fn write_bytes_inner<T: Write>(
     memory: &Memory,
     iovs_arr_cell: &[Cell<__wasi_ciovec_t>],
 ) -> Result<u32, __wasi_errno_t> {
+    // the number of bytes written must fit in the result
+    if total_iovs_len(iovs_arr_cell) > u64::from(u32::MAX) {
+        return Err(__WASI_EINVAL);
+    }
     let mut bytes_written = 0;
     for iov in iovs_arr_cell {
         let iov_inner = iov.get();
@@ -75,6 +81,14 @@ fn read_bytes<T: Read>(
     memory: &Memory,
     iovs_arr_cell: &[Cell<__wasi_iovec_t>],
 ) -> Result<u32, __wasi_errno_t> {
+    // the number of bytes read must fit in the result
+    let total_len: u64 = iovs_arr_cell
+        .iter()
+        .map(|iov| u64::from(iov.get().buf_len))
+        .sum();
+    if total_len > u64::from(u32::MAX) {
+        return Err(__WASI_EINVAL);
+    }
     let mut bytes_read = 0;
 
     for iov in iovs_arr_cell {
@@ -87,8 +101,16 @@ fn read_bytes<T: Read>(
     Ok(bytes_read)
 }
 
+/// The total length of the buffers of `iovs_arr_cell`.
+fn total_iovs_len(iovs_arr_cell: &[Cell<__wasi_ciovec_t>]) -> u64 {
+    iovs_arr_cell
+        .iter()
+        .map(|iov| u64::from(iov.get().buf_len))
+        .sum()
+}
+
 /// checks that `rights_check_set` is a subset of `rights_set`
-fn has_rights(rights_set: __wasi_rights_t, rights_check_set: __wasi_rights_t) -> bool {
+pub(crate) fn has_rights(rights_set: __wasi_rights_t, rights_check_set: __wasi_rights_t) -> bool {
     rights_set | rights_check_set == rights_set
 }
 
@@ -117,7 +139,12 @@ fn write_buffer_array(
     __WASI_ESUCCESS
 }
 
-fn get_current_time_in_nanos() -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+fn get_current_time_in_nanos(
+    determinism: &mut Option<Determinism>,
+) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+    if let Some(determinism) = determinism {
+        return Ok(determinism.now());
+    }
     let now = std::time::SystemTime::now();
     let duration = now
         .duration_since(std::time::SystemTime::UNIX_EPOCH)
@@ -150,11 +177,7 @@ pub fn args_get(
             .args
             .iter()
             .enumerate()
-            .map(|(i, v)| format!(
-                "{:>20}: {}",
-                i,
-                ::std::str::from_utf8(v).unwrap().to_string()
-            ))
+            .map(|(i, v)| format!("{:>20}: {}", i, String::from_utf8_lossy(v)))
             .collect::<Vec<String>>()
             .join("\n")
     );
@@ -204,10 +227,17 @@ pub fn clock_res_get(
     resolution: WasmPtr<__wasi_timestamp_t>,
 ) -> __wasi_errno_t {
     debug!("wasi::clock_res_get");
-    let memory = env.memory();
+    let (memory, state) = env.get_memory_and_wasi_state(0);
 
     let out_addr = wasi_try!(resolution.deref(memory));
-    platform_clock_res_get(clock_id, out_addr)
+    match state.determinism {
+        Some(_) if clock_id <= __WASI_CLOCK_THREAD_CPUTIME_ID => {
+            out_addr.set(DETERMINISTIC_CLOCK_RESOLUTION);
+            __WASI_ESUCCESS
+        }
+        Some(_) => __WASI_EINVAL,
+        None => platform_clock_res_get(clock_id, out_addr),
+    }
 }
 
 /// ### `clock_time_get()`
@@ -230,10 +260,17 @@ pub fn clock_time_get(
         "wasi::clock_time_get clock_id: {}, precision: {}",
         clock_id, precision
     );
-    let memory = env.memory();
+    let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
     let out_addr = wasi_try!(time.deref(memory));
-    let result = platform_clock_time_get(clock_id, precision, out_addr);
+    let result = match &mut state.determinism {
+        Some(determinism) if clock_id <= __WASI_CLOCK_THREAD_CPUTIME_ID => {
+            out_addr.set(determinism.now());
+            __WASI_ESUCCESS
+        }
+        Some(_) => __WASI_EINVAL,
+        None => platform_clock_time_get(clock_id, precision, out_addr),
+    };
     debug!(
         "time: {} => {}",
         wasi_try!(time.deref(memory)).get(),
@@ -335,14 +372,14 @@ pub fn fd_allocate(
     debug!("wasi::fd_allocate");
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let fd_entry = wasi_try!(state.fs.get_fd(fd));
-    let inode = fd_entry.inode;
 
     if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_ALLOCATE) {
         return __WASI_EACCES;
     }
     let new_size = wasi_try!(offset.checked_add(len), __WASI_EINVAL);
 
-    match &mut state.fs.inodes[inode].kind {
+    let inode = wasi_try!(state.fs.get_inodeval_mut(fd));
+    match &mut inode.kind {
         Kind::File { handle, .. } => {
             if let Some(handle) = handle {
                 wasi_try!(handle.set_len(new_size).map_err(WasiFsError::into_wasi_err));
@@ -351,12 +388,13 @@ pub fn fd_allocate(
             }
         }
         Kind::Buffer { buffer } => {
+            wasi_try!(state::reserve_buffer(buffer, new_size));
             buffer.resize(new_size as usize, 0);
         }
         Kind::Symlink { .. } => return __WASI_EBADF,
         Kind::Dir { .. } | Kind::Root { .. } => return __WASI_EISDIR,
     }
-    state.fs.inodes[inode].stat.st_size = new_size;
+    inode.stat.st_size = new_size;
     debug!("New file size: {}", new_size);
 
     __WASI_ESUCCESS
@@ -530,28 +568,8 @@ pub fn fd_filestat_set_size(
 ) -> __wasi_errno_t {
     debug!("wasi::fd_filestat_set_size");
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
-    let fd_entry = wasi_try!(state.fs.get_fd(fd));
-    let inode = fd_entry.inode;
 
-    if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_FILESTAT_SET_SIZE) {
-        return __WASI_EACCES;
-    }
-
-    match &mut state.fs.inodes[inode].kind {
-        Kind::File { handle, .. } => {
-            if let Some(handle) = handle {
-                wasi_try!(handle.set_len(st_size).map_err(WasiFsError::into_wasi_err));
-            } else {
-                return __WASI_EBADF;
-            }
-        }
-        Kind::Buffer { buffer } => {
-            buffer.resize(st_size as usize, 0);
-        }
-        Kind::Symlink { .. } => return __WASI_EBADF,
-        Kind::Dir { .. } | Kind::Root { .. } => return __WASI_EISDIR,
-    }
-    state.fs.inodes[inode].stat.st_size = st_size;
+    wasi_try!(state.fs.set_size(fd, st_size));
 
     __WASI_ESUCCESS
 }
@@ -574,6 +592,7 @@ pub fn fd_filestat_set_times(
 ) -> __wasi_errno_t {
     debug!("wasi::fd_filestat_set_times");
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
+    let state = &mut *state;
     let fd_entry = wasi_try!(state.fs.fd_map.get_mut(&fd).ok_or(__WASI_EBADF));
 
     if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_FILESTAT_SET_TIMES) {
@@ -587,14 +606,13 @@ pub fn fd_filestat_set_times(
         return __WASI_EINVAL;
     }
 
-    let inode_idx = fd_entry.inode;
-    let inode = &mut state.fs.inodes[inode_idx];
+    let inode = wasi_try!(state.fs.get_inodeval_mut(fd));
 
     if fst_flags & __WASI_FILESTAT_SET_ATIM != 0 || fst_flags & __WASI_FILESTAT_SET_ATIM_NOW != 0 {
         let time_to_set = if fst_flags & __WASI_FILESTAT_SET_ATIM != 0 {
             st_atim
         } else {
-            wasi_try!(get_current_time_in_nanos())
+            wasi_try!(get_current_time_in_nanos(&mut state.determinism))
         };
         inode.stat.st_atim = time_to_set;
         // TODO: set it for more than just files
@@ -609,7 +627,7 @@ pub fn fd_filestat_set_times(
         let time_to_set = if fst_flags & __WASI_FILESTAT_SET_MTIM != 0 {
             st_mtim
         } else {
-            wasi_try!(get_current_time_in_nanos())
+            wasi_try!(get_current_time_in_nanos(&mut state.determinism))
         };
         inode.stat.st_mtim = time_to_set;
         // TODO: set it for more than just files
@@ -666,7 +684,6 @@ pub fn fd_pread(
         __WASI_STDERR_FILENO => return __WASI_EINVAL,
         _ => {
             let fd_entry = wasi_try!(state.fs.fd_map.get_mut(&fd).ok_or(__WASI_EBADF));
-            let inode = fd_entry.inode;
 
             if !(has_rights(fd_entry.rights, __WASI_RIGHT_FD_READ)
                 && has_rights(fd_entry.rights, __WASI_RIGHT_FD_SEEK))
@@ -677,7 +694,7 @@ pub fn fd_pread(
                 );
                 return __WASI_EACCES;
             }
-            match &mut state.fs.inodes[inode].kind {
+            match &mut wasi_try!(state.fs.get_inodeval_mut(fd)).kind {
                 Kind::File { handle, .. } => {
                     if let Some(h) = handle {
                         wasi_try!(
@@ -690,10 +707,12 @@ pub fn fd_pread(
                     }
                 }
                 Kind::Dir { .. } | Kind::Root { .. } => return __WASI_EISDIR,
-                Kind::Symlink { .. } => unimplemented!("Symlinks in wasi::fd_pread"),
-                Kind::Buffer { buffer } => {
-                    wasi_try!(read_bytes(&buffer[(offset as usize)..], memory, iov_cells))
-                }
+                Kind::Symlink { .. } => return __WASI_EBADF,
+                Kind::Buffer { buffer } => wasi_try!(read_bytes(
+                    buffer.get(offset as usize..).unwrap_or_default(),
+                    memory,
+                    iov_cells
+                )),
             }
         }
     };
@@ -739,8 +758,7 @@ pub fn fd_prestat_dir_name(
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let path_chars = wasi_try!(path.deref(memory, 0, path_len));
 
-    let real_fd = wasi_try!(state.fs.fd_map.get(&fd).ok_or(__WASI_EBADF));
-    let inode_val = &state.fs.inodes[real_fd.inode];
+    let inode_val = wasi_try!(state.fs.get_inodeval(fd));
 
     // check inode-val.is_preopened?
 
@@ -748,20 +766,15 @@ pub fn fd_prestat_dir_name(
         Kind::Dir { .. } | Kind::Root { .. } => {
             // TODO: verify this: null termination, etc
             if inode_val.name.len() <= path_len as usize {
-                let mut i = 0;
-                for c in inode_val.name.bytes() {
-                    path_chars[i].set(c);
-                    i += 1
+                for (c, path_char) in inode_val.name.bytes().zip(path_chars) {
+                    path_char.set(c);
+                }
+                // the name is null terminated if there is room for it
+                if let Some(path_char) = path_chars.get(inode_val.name.len()) {
+                    path_char.set(0);
                 }
-                path_chars[i].set(0);
 
-                debug!(
-                    "=> result: \"{}\"",
-                    ::std::str::from_utf8(unsafe {
-                        &*(&path_chars[..] as *const [_] as *const [u8])
-                    })
-                    .unwrap()
-                );
+                debug!("=> result: \"{}\"", inode_val.name);
 
                 __WASI_ESUCCESS
             } else {
@@ -829,8 +842,7 @@ pub fn fd_pwrite(
                 return __WASI_EACCES;
             }
 
-            let inode_idx = fd_entry.inode;
-            let inode = &mut state.fs.inodes[inode_idx];
+            let inode = wasi_try!(state.fs.get_inodeval_mut(fd));
 
             match &mut inode.kind {
                 Kind::File { handle, .. } => {
@@ -845,12 +857,14 @@ pub fn fd_pwrite(
                     // TODO: verify
                     return __WASI_EISDIR;
                 }
-                Kind::Symlink { .. } => unimplemented!("Symlinks in wasi::fd_pwrite"),
-                Kind::Buffer { buffer } => wasi_try!(write_bytes(
-                    &mut buffer[(offset as usize)..],
-                    memory,
-                    iovs_arr_cell
-                )),
+                Kind::Symlink { .. } => return __WASI_EBADF,
+                Kind::Buffer { buffer } => {
+                    let len = offset.saturating_add(total_iovs_len(iovs_arr_cell));
+                    wasi_try!(state::reserve_buffer(buffer, len));
+                    let mut cursor = std::io::Cursor::new(buffer);
+                    cursor.set_position(offset);
+                    wasi_try!(write_bytes(&mut cursor, memory, iovs_arr_cell))
+                }
             }
         }
     };
@@ -905,8 +919,7 @@ pub fn fd_read(
             }
 
             let offset = fd_entry.offset as usize;
-            let inode_idx = fd_entry.inode;
-            let inode = &mut state.fs.inodes[inode_idx];
+            let inode = wasi_try!(state.fs.get_inodeval_mut(fd));
 
             let bytes_read = match &mut inode.kind {
                 Kind::File { handle, .. } => {
@@ -921,10 +934,12 @@ pub fn fd_read(
                     // TODO: verify
                     return __WASI_EISDIR;
                 }
-                Kind::Symlink { .. } => unimplemented!("Symlinks in wasi::fd_read"),
-                Kind::Buffer { buffer } => {
-                    wasi_try!(read_bytes(&buffer[offset..], memory, iovs_arr_cell))
-                }
+                Kind::Symlink { .. } => return __WASI_EBADF,
+                Kind::Buffer { buffer } => wasi_try!(read_bytes(
+                    buffer.get(offset..).unwrap_or_default(),
+                    memory,
+                    iovs_arr_cell
+                )),
             };
 
             // reborrow
@@ -970,64 +985,10 @@ pub fn fd_readdir(
 
     let buf_arr_cell = wasi_try!(buf.deref(memory, 0, buf_len));
     let bufused_cell = wasi_try!(bufused.deref(memory));
-    let working_dir = wasi_try!(state.fs.fd_map.get(&fd).ok_or(__WASI_EBADF));
     let mut cur_cookie = cookie;
     let mut buf_idx = 0;
 
-    let entries: Vec<(String, u8, u64)> = match &state.fs.inodes[working_dir.inode].kind {
-        Kind::Dir { path, entries, .. } => {
-            // TODO: refactor this code
-            // we need to support multiple calls,
-            // simple and obviously correct implementation for now:
-            // maintain consistent order via lexacographic sorting
-            let fs_info = wasi_try!(wasi_try!(std::fs::read_dir(path).map_err(|_| __WASI_EIO))
-                .collect::<Result<Vec<_>, _>>()
-                .map_err(|_| __WASI_EIO));
-            let mut entry_vec = wasi_try!(fs_info
-                .into_iter()
-                .map(|entry| Ok((
-                    entry.file_name().to_string_lossy().to_string(),
-                    host_file_type_to_wasi_file_type(entry.file_type().map_err(|_| __WASI_EIO)?),
-                    0, // TODO: inode
-                )))
-                .collect::<Result<Vec<(String, u8, u64)>, _>>());
-            entry_vec.extend(
-                entries
-                    .iter()
-                    .filter(|(_, inode)| state.fs.inodes[**inode].is_preopened)
-                    .map(|(name, inode)| {
-                        let entry = &state.fs.inodes[*inode];
-                        (
-                            entry.name.to_string(),
-                            entry.stat.st_filetype,
-                            entry.stat.st_ino,
-                        )
-                    }),
-            );
-            entry_vec.sort_by(|a, b| a.0.cmp(&b.0));
-            entry_vec
-        }
-        Kind::Root { entries } => {
-            let sorted_entries = {
-                let mut entry_vec: Vec<(String, Inode)> =
-                    entries.iter().map(|(a, b)| (a.clone(), *b)).collect();
-                entry_vec.sort_by(|a, b| a.0.cmp(&b.0));
-                entry_vec
-            };
-            sorted_entries
-                .into_iter()
-                .map(|(name, inode)| {
-                    let entry = &state.fs.inodes[inode];
-                    (
-                        format!("/{}", entry.name),
-                        entry.stat.st_filetype,
-                        entry.stat.st_ino,
-                    )
-                })
-                .collect()
-        }
-        Kind::File { .. } | Kind::Symlink { .. } | Kind::Buffer { .. } => return __WASI_ENOTDIR,
-    };
+    let entries = wasi_try!(state.fs.read_dir(fd));
 
     for (entry_path_str, wasi_file_type, ino) in entries.iter().skip(cookie as usize) {
         cur_cookie += 1;
@@ -1110,51 +1071,8 @@ pub fn fd_seek(
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let new_offset_cell = wasi_try!(newoffset.deref(memory));
 
-    let fd_entry = wasi_try!(state.fs.fd_map.get_mut(&fd).ok_or(__WASI_EBADF));
-
-    if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_SEEK) {
-        return __WASI_EACCES;
-    }
-
-    // TODO: handle case if fd is a dir?
-    match whence {
-        __WASI_WHENCE_CUR => fd_entry.offset = (fd_entry.offset as i64 + offset) as u64,
-        __WASI_WHENCE_END => {
-            use std::io::SeekFrom;
-            let inode_idx = fd_entry.inode;
-            match state.fs.inodes[inode_idx].kind {
-                Kind::File { ref mut handle, .. } => {
-                    if let Some(handle) = handle {
-                        let end = wasi_try!(handle.seek(SeekFrom::End(0)).ok().ok_or(__WASI_EIO));
-                        // TODO: handle case if fd_entry.offset uses 64 bits of a u64
-
-                        // reborrow
-                        let fd_entry = wasi_try!(state.fs.fd_map.get_mut(&fd).ok_or(__WASI_EBADF));
-                        fd_entry.offset = (end as i64 + offset) as u64;
-                    } else {
-                        return __WASI_EINVAL;
-                    }
-                }
-                Kind::Symlink { .. } => {
-                    unimplemented!("wasi::fd_seek not implemented for symlinks")
-                }
-                Kind::Dir { .. } | Kind::Root { .. } => {
-                    // TODO: check this
-                    return __WASI_EINVAL;
-                }
-                Kind::Buffer { .. } => {
-                    // seeking buffers probably makes sense
-                    // TODO: implement this
-                    return __WASI_EINVAL;
-                }
-            }
-        }
-        __WASI_WHENCE_SET => fd_entry.offset = offset as u64,
-        _ => return __WASI_EINVAL,
-    }
-    // reborrow
-    let fd_entry = wasi_try!(state.fs.fd_map.get_mut(&fd).ok_or(__WASI_EBADF));
-    new_offset_cell.set(fd_entry.offset);
+    let new_offset = wasi_try!(state.fs.seek_fd(fd, offset, whence));
+    new_offset_cell.set(new_offset);
 
     __WASI_ESUCCESS
 }
@@ -1172,24 +1090,8 @@ pub fn fd_sync(env: &mut WasiEnv, fd: __wasi_fd_t) -> __wasi_errno_t {
     debug!("wasi::fd_sync");
     debug!("=> fd={}", fd);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
-    let fd_entry = wasi_try!(state.fs.get_fd(fd));
-    if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_SYNC) {
-        return __WASI_EACCES;
-    }
-    let inode = fd_entry.inode;
 
-    // TODO: implement this for more than files
-    match &mut state.fs.inodes[inode].kind {
-        Kind::File { handle, .. } => {
-            if let Some(h) = handle {
-                wasi_try!(h.sync_to_disk().map_err(WasiFsError::into_wasi_err));
-            } else {
-                return __WASI_EINVAL;
-            }
-        }
-        Kind::Root { .. } | Kind::Dir { .. } => return __WASI_EISDIR,
-        Kind::Buffer { .. } | Kind::Symlink { .. } => return __WASI_EINVAL,
-    }
+    wasi_try!(state.fs.sync_fd(fd));
 
     __WASI_ESUCCESS
 }
@@ -1283,8 +1185,7 @@ pub fn fd_write(
             }
 
             let offset = fd_entry.offset as usize;
-            let inode_idx = fd_entry.inode;
-            let inode = &mut state.fs.inodes[inode_idx];
+            let inode = wasi_try!(state.fs.get_inodeval_mut(fd));
 
             let bytes_written = match &mut inode.kind {
                 Kind::File { handle, .. } => {
@@ -1299,9 +1200,13 @@ pub fn fd_write(
                     // TODO: verify
                     return __WASI_EISDIR;
                 }
-                Kind::Symlink { .. } => unimplemented!("Symlinks in wasi::fd_write"),
+                Kind::Symlink { .. } => return __WASI_EBADF,
                 Kind::Buffer { buffer } => {
-                    wasi_try!(write_bytes(&mut buffer[offset..], memory, iovs_arr_cell))
+                    let len = (offset as u64).saturating_add(total_iovs_len(iovs_arr_cell));
+                    wasi_try!(state::reserve_buffer(buffer, len));
+                    let mut cursor = std::io::Cursor::new(buffer);
+                    cursor.set_position(offset as u64);
+                    wasi_try!(write_bytes(&mut cursor, memory, iovs_arr_cell))
                 }
             };
 
@@ -1340,83 +1245,9 @@ pub fn path_create_directory(
 ) -> __wasi_errno_t {
     debug!("wasi::path_create_directory");
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
-
-    let working_dir = wasi_try!(state.fs.get_fd(fd));
-    if let Kind::Root { .. } = &state.fs.inodes[working_dir.inode].kind {
-        return __WASI_EACCES;
-    }
-    if !has_rights(working_dir.rights, __WASI_RIGHT_PATH_CREATE_DIRECTORY) {
-        return __WASI_EACCES;
-    }
     let path_string = get_input_str!(memory, path, path_len);
-    debug!("=> fd: {}, path: {}", fd, &path_string);
-
-    let path = std::path::PathBuf::from(path_string);
-    let path_vec = wasi_try!(path
-        .components()
-        .map(|comp| {
-            comp.as_os_str()
-                .to_str()
-                .map(|inner_str| inner_str.to_string())
-                .ok_or(__WASI_EINVAL)
-        })
-        .collect::<Result<Vec<String>, __wasi_errno_t>>());
-    if path_vec.is_empty() {
-        return __WASI_EINVAL;
-    }
 
-    debug!("Looking at components {:?}", &path_vec);
-
-    let mut cur_dir_inode = working_dir.inode;
-    for comp in &path_vec {
-        debug!("Creating dir {}", comp);
-        match &mut state.fs.inodes[cur_dir_inode].kind {
-            Kind::Dir {
-                ref mut entries,
-                path,
-                parent,
-            } => {
-                match comp.borrow() {
-                    ".." => {
-                        if let Some(p) = parent {
-                            cur_dir_inode = *p;
-                            continue;
-                        }
-                    }
-                    "." => continue,
-                    _ => (),
-                }
-                if let Some(child) = entries.get(comp) {
-                    cur_dir_inode = *child;
-                } else {
-                    let mut adjusted_path = path.clone();
-                    // TODO: double check this doesn't risk breaking the sandbox
-                    adjusted_path.push(comp);
-                    if adjusted_path.exists() && !adjusted_path.is_dir() {
-                        return __WASI_ENOTDIR;
-                    } else if !adjusted_path.exists() {
-                        wasi_try!(std::fs::create_dir(&adjusted_path).ok(), __WASI_EIO);
-                    }
-                    let kind = Kind::Dir {
-                        parent: Some(cur_dir_inode),
-                        path: adjusted_path,
-                        entries: Default::default(),
-                    };
-                    let new_inode = wasi_try!(state.fs.create_inode(kind, false, comp.to_string()));
-                    // reborrow to insert
-                    if let Kind::Dir {
-                        ref mut entries, ..
-                    } = &mut state.fs.inodes[cur_dir_inode].kind
-                    {
-                        entries.insert(comp.to_string(), new_inode);
-                    }
-                    cur_dir_inode = new_inode;
-                }
-            }
-            Kind::Root { .. } => return __WASI_EACCES,
-            _ => return __WASI_ENOTDIR,
-        }
-    }
+    wasi_try!(state.fs.create_directory(fd, path_string));
 
     __WASI_ESUCCESS
 }
@@ -1445,29 +1276,9 @@ pub fn path_filestat_get(
 ) -> __wasi_errno_t {
     debug!("wasi::path_filestat_get");
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
-
-    let root_dir = wasi_try!(state.fs.get_fd(fd));
-
-    if !has_rights(root_dir.rights, __WASI_RIGHT_PATH_FILESTAT_GET) {
-        return __WASI_EACCES;
-    }
     let path_string = get_input_str!(memory, path, path_len);
 
-    debug!("=> base_fd: {}, path: {}", fd, &path_string);
-
-    let file_inode = wasi_try!(state.fs.get_inode_at_path(
-        fd,
-        path_string,
-        flags & __WASI_LOOKUP_SYMLINK_FOLLOW != 0,
-    ));
-    let stat = if state.fs.inodes[file_inode].is_preopened {
-        state.fs.inodes[file_inode].stat
-    } else {
-        wasi_try!(state
-            .fs
-            .get_stat_for_kind(&state.fs.inodes[file_inode].kind)
-            .ok_or(__WASI_EIO))
-    };
+    let stat = wasi_try!(state.fs.path_filestat(fd, flags, path_string));
 
     let buf_cell = wasi_try!(buf.deref(memory));
     buf_cell.set(stat);
@@ -1504,8 +1315,8 @@ pub fn path_filestat_set_times(
 ) -> __wasi_errno_t {
     debug!("wasi::path_filestat_set_times");
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
+    let state = &mut *state;
     let fd_entry = wasi_try!(state.fs.get_fd(fd));
-    let fd_inode = fd_entry.inode;
     if !has_rights(fd_entry.rights, __WASI_RIGHT_PATH_FILESTAT_SET_TIMES) {
         return __WASI_EACCES;
     }
@@ -1529,13 +1340,13 @@ pub fn path_filestat_set_times(
         .get_stat_for_kind(&state.fs.inodes[file_inode].kind)
         .ok_or(__WASI_EIO));
 
-    let inode = &mut state.fs.inodes[fd_inode];
+    let inode = &mut state.fs.inodes[file_inode];
 
     if fst_flags & __WASI_FILESTAT_SET_ATIM != 0 || fst_flags & __WASI_FILESTAT_SET_ATIM_NOW != 0 {
         let time_to_set = if fst_flags & __WASI_FILESTAT_SET_ATIM != 0 {
             st_atim
         } else {
-            wasi_try!(get_current_time_in_nanos())
+            wasi_try!(get_current_time_in_nanos(&mut state.determinism))
         };
         inode.stat.st_atim = time_to_set;
         // TODO: set it for more than just files
@@ -1549,7 +1360,7 @@ pub fn path_filestat_set_times(
         let time_to_set = if fst_flags & __WASI_FILESTAT_SET_MTIM != 0 {
             st_mtim
         } else {
-            wasi_try!(get_current_time_in_nanos())
+            wasi_try!(get_current_time_in_nanos(&mut state.determinism))
         };
         inode.stat.st_mtim = time_to_set;
         // TODO: set it for more than just files
@@ -1617,10 +1428,12 @@ pub fn path_link(
     ));
     let target_path_arg = std::path::PathBuf::from(new_path_str);
     let (target_parent_inode, new_entry_name) =
-        wasi_try!(state
-            .fs
-            .get_parent_inode_at_path(new_fd, &target_path_arg, false));
+        wasi_try!(state.fs.get_parent_inode_at_path(new_fd, &target_path_arg));
 
+    // hard links to directories could make cycles in the file system
+    if let Kind::Dir { .. } | Kind::Root { .. } = state.fs.inodes[source_inode].kind {
+        return __WASI_EPERM;
+    }
     if state.fs.inodes[source_inode].stat.st_nlink == __wasi_linkcount_t::max_value() {
         return __WASI_EMLINK;
     }
@@ -1676,9 +1489,6 @@ pub fn path_open(
     fd: WasmPtr<__wasi_fd_t>,
 ) -> __wasi_errno_t {
     debug!("wasi::path_open");
-    if dirflags & __WASI_LOOKUP_SYMLINK_FOLLOW != 0 {
-        debug!("  - will follow symlinks when opening path");
-    }
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     /* TODO: find actual upper bound on name size (also this is a path, not a name :think-fish:) */
     if path_len > 1024 * 1024 {
@@ -1686,200 +1496,18 @@ pub fn path_open(
     }
 
     let fd_cell = wasi_try!(fd.deref(memory));
-
-    // o_flags:
-    // - __WASI_O_CREAT (create if it does not exist)
-    // - __WASI_O_DIRECTORY (fail if not dir)
-    // - __WASI_O_EXCL (fail if file exists)
-    // - __WASI_O_TRUNC (truncate size to 0)
-
-    let working_dir = wasi_try!(state.fs.get_fd(dirfd));
-    let working_dir_rights_inheriting = working_dir.rights_inheriting;
-
-    // ASSUMPTION: open rights apply recursively
-    if !has_rights(working_dir.rights, __WASI_RIGHT_PATH_OPEN) {
-        return __WASI_EACCES;
-    }
     let path_string = get_input_str!(memory, path, path_len);
 
-    debug!("=> fd: {}, path: {}", dirfd, &path_string);
-
-    let path_arg = std::path::PathBuf::from(path_string);
-    let maybe_inode = state.fs.get_inode_at_path(
+    let out_fd = wasi_try!(state.fs.open_path(
         dirfd,
+        dirflags,
         path_string,
-        dirflags & __WASI_LOOKUP_SYMLINK_FOLLOW != 0,
-    );
-
-    let mut open_flags = 0;
-    // TODO: traverse rights of dirs properly
-    // COMMENTED OUT: WASI isn't giving appropriate rights here when opening
-    //              TODO: look into this; file a bug report if this is a bug
-    let adjusted_rights = /*fs_rights_base &*/ working_dir_rights_inheriting;
-    let inode = if let Ok(inode) = maybe_inode {
-        // Happy path, we found the file we're trying to open
-        match &mut state.fs.inodes[inode].kind {
-            Kind::File {
-                ref mut handle,
-                path,
-                fd,
-            } => {
-                if let Some(special_fd) = fd {
-                    // short circuit if we're dealing with a special file
-                    assert!(handle.is_some());
-                    fd_cell.set(*special_fd);
-                    return __WASI_ESUCCESS;
-                }
-                if o_flags & __WASI_O_DIRECTORY != 0 {
-                    return __WASI_ENOTDIR;
-                }
-                if o_flags & __WASI_O_EXCL != 0 && path.exists() {
-                    return __WASI_EEXIST;
-                }
-                let mut open_options = std::fs::OpenOptions::new();
-                let write_permission = adjusted_rights & __WASI_RIGHT_FD_WRITE != 0;
-                // append, truncate, and create all require the permission to write
-                let (append_permission, truncate_permission, create_permission) =
-                    if write_permission {
-                        (
-                            fs_flags & __WASI_FDFLAG_APPEND != 0,
-                            o_flags & __WASI_O_TRUNC != 0,
-                            o_flags & __WASI_O_CREAT != 0,
-                        )
-                    } else {
-                        (false, false, false)
-                    };
-                let open_options = open_options
-                    .read(true)
-                    // TODO: ensure these rights are actually valid given parent, etc.
-                    .write(write_permission)
-                    .create(create_permission)
-                    .append(append_permission)
-                    .truncate(truncate_permission);
-                open_flags |= Fd::READ;
-                if adjusted_rights & __WASI_RIGHT_FD_WRITE != 0 {
-                    open_flags |= Fd::WRITE;
-                }
-                if o_flags & __WASI_O_CREAT != 0 {
-                    open_flags |= Fd::CREATE;
-                }
-                if o_flags & __WASI_O_TRUNC != 0 {
-                    open_flags |= Fd::TRUNCATE;
-                }
-                *handle = Some(Box::new(HostFile::new(
-                    wasi_try!(open_options.open(&path).map_err(|_| __WASI_EIO)),
-                    path.to_path_buf(),
-                    true,
-                    adjusted_rights & __WASI_RIGHT_FD_WRITE != 0,
-                    false,
-                )));
-            }
-            Kind::Buffer { .. } => unimplemented!("wasi::path_open for Buffer type files"),
-            Kind::Dir { .. } | Kind::Root { .. } => {
-                // TODO: adjust these to be correct
-                if o_flags & __WASI_O_EXCL != 0 && path_arg.exists() {
-                    return __WASI_EEXIST;
-                }
-            }
-            Kind::Symlink {
-                base_po_dir,
-                path_to_symlink,
-                relative_path,
-            } => {
-                // I think this should return an error (because symlinks should be resolved away by the path traversal)
-                // TODO: investigate this
-                unimplemented!("SYMLINKS IN PATH_OPEN");
-            }
-        }
-        inode
-    } else {
-        // less-happy path, we have to try to create the file
-        debug!("Maybe creating file");
-        if o_flags & __WASI_O_CREAT != 0 {
-            if o_flags & __WASI_O_DIRECTORY != 0 {
-                return __WASI_ENOTDIR;
-            }
-            debug!("Creating file");
-            // strip end file name
-
-            let (parent_inode, new_entity_name) = wasi_try!(state.fs.get_parent_inode_at_path(
-                dirfd,
-                &path_arg,
-                dirflags & __WASI_LOOKUP_SYMLINK_FOLLOW != 0
-            ));
-            let new_file_host_path = match &state.fs.inodes[parent_inode].kind {
-                Kind::Dir { path, .. } => {
-                    let mut new_path = path.clone();
-                    new_path.push(&new_entity_name);
-                    new_path
-                }
-                Kind::Root { .. } => return __WASI_EACCES,
-                _ => return __WASI_EINVAL,
-            };
-            // once we got the data we need from the parent, we lookup the host file
-            // todo: extra check that opening with write access is okay
-            let handle = {
-                let mut open_options = std::fs::OpenOptions::new();
-                let open_options = open_options
-                    .read(true)
-                    .append(fs_flags & __WASI_FDFLAG_APPEND != 0)
-                    // TODO: ensure these rights are actually valid given parent, etc.
-                    // write access is required for creating a file
-                    .write(true)
-                    .create_new(true);
-                open_flags |= Fd::READ | Fd::WRITE | Fd::CREATE | Fd::TRUNCATE;
-
-                Some(Box::new(HostFile::new(
-                    wasi_try!(open_options.open(&new_file_host_path).map_err(|e| {
-                        debug!("Error opening file {}", e);
-                        __WASI_EIO
-                    })),
-                    new_file_host_path.clone(),
-                    true,
-                    true,
-                    true,
-                )) as Box<dyn WasiFile>)
-            };
-
-            let new_inode = {
-                let kind = Kind::File {
-                    handle,
-                    path: new_file_host_path,
-                    fd: None,
-                };
-                wasi_try!(state.fs.create_inode(kind, false, new_entity_name.clone()))
-            };
-
-            if let Kind::Dir {
-                ref mut entries, ..
-            } = &mut state.fs.inodes[parent_inode].kind
-            {
-                entries.insert(new_entity_name, new_inode);
-            }
-
-            new_inode
-        } else {
-            return maybe_inode.unwrap_err();
-        }
-    };
-
-    debug!(
-        "inode {:?} value {:#?} found!",
-        inode, state.fs.inodes[inode]
-    );
-
-    // TODO: check and reduce these
-    // TODO: ensure a mutable fd to root can never be opened
-    let out_fd = wasi_try!(state.fs.create_fd(
-        adjusted_rights,
+        o_flags,
+        fs_rights_base,
         fs_rights_inheriting,
         fs_flags,
-        open_flags,
-        inode
     ));
-
     fd_cell.set(out_fd);
-    debug!("wasi::path_open returning fd {}", out_fd);
 
     __WASI_ESUCCESS
 }
@@ -1951,57 +1579,11 @@ pub fn path_remove_directory(
     path: WasmPtr<u8, Array>,
     path_len: u32,
 ) -> __wasi_errno_t {
-    // TODO check if fd is a dir, ensure it's within sandbox, etc.
     debug!("wasi::path_remove_directory");
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
-
-    let base_dir = wasi_try!(state.fs.fd_map.get(&fd), __WASI_EBADF);
     let path_str = get_input_str!(memory, path, path_len);
 
-    let inode = wasi_try!(state.fs.get_inode_at_path(fd, path_str, false));
-    let (parent_inode, childs_name) =
-        wasi_try!(state
-            .fs
-            .get_parent_inode_at_path(fd, std::path::Path::new(path_str), false));
-
-    let host_path_to_remove = match &state.fs.inodes[inode].kind {
-        Kind::Dir { entries, path, .. } => {
-            if !entries.is_empty()
-                || wasi_try!(std::fs::read_dir(path).ok(), __WASI_EIO).count() != 0
-            {
-                return __WASI_ENOTEMPTY;
-            }
-            path.clone()
-        }
-        Kind::Root { .. } => return __WASI_EACCES,
-        _ => return __WASI_ENOTDIR,
-    };
-
-    match &mut state.fs.inodes[parent_inode].kind {
-        Kind::Dir {
-            ref mut entries, ..
-        } => {
-            let removed_inode = wasi_try!(entries.remove(&childs_name).ok_or(__WASI_EINVAL));
-            // TODO: make this a debug assert in the future
-            assert!(inode == removed_inode);
-        }
-        Kind::Root { .. } => return __WASI_EACCES,
-        _ => unreachable!(
-            "Internal logic error in wasi::path_remove_directory, parent is not a directory"
-        ),
-    }
-
-    if std::fs::remove_dir(path_str).is_err() {
-        // reinsert to prevent FS from being in bad state
-        if let Kind::Dir {
-            ref mut entries, ..
-        } = &mut state.fs.inodes[parent_inode].kind
-        {
-            entries.insert(childs_name, inode);
-        }
-        // TODO: more intelligently return error value by inspecting returned error value
-        return __WASI_EIO;
-    }
+    wasi_try!(state.fs.remove_directory(fd, path_str));
 
     __WASI_ESUCCESS
 }
@@ -2033,86 +1615,9 @@ pub fn path_rename(
     debug!("wasi::path_rename");
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let source_str = get_input_str!(memory, old_path, old_path_len);
-    let source_path = std::path::Path::new(source_str);
     let target_str = get_input_str!(memory, new_path, new_path_len);
-    let target_path = std::path::Path::new(target_str);
-
-    {
-        let source_fd = wasi_try!(state.fs.get_fd(old_fd));
-        if !has_rights(source_fd.rights, __WASI_RIGHT_PATH_RENAME_SOURCE) {
-            return __WASI_EACCES;
-        }
-        let target_fd = wasi_try!(state.fs.get_fd(new_fd));
-        if !has_rights(target_fd.rights, __WASI_RIGHT_PATH_RENAME_TARGET) {
-            return __WASI_EACCES;
-        }
-    }
 
-    let (source_parent_inode, source_entry_name) =
-        wasi_try!(state.fs.get_parent_inode_at_path(old_fd, source_path, true));
-    let (target_parent_inode, target_entry_name) =
-        wasi_try!(state.fs.get_parent_inode_at_path(new_fd, target_path, true));
-
-    let host_adjusted_target_path = match &state.fs.inodes[target_parent_inode].kind {
-        Kind::Dir { entries, path, .. } => {
-            if entries.contains_key(&target_entry_name) {
-                return __WASI_EEXIST;
-            }
-            let mut out_path = path.clone();
-            // remove fd's own name which will be double counted
-            out_path.pop();
-            out_path.push(target_path);
-            out_path
-        }
-        Kind::Root { .. } => return __WASI_ENOTCAPABLE,
-        Kind::Symlink { .. } | Kind::File { .. } | Kind::Buffer { .. } => {
-            unreachable!("Fatal internal logic error: parent of inode is not a directory")
-        }
-    };
-    let source_entry = match &mut state.fs.inodes[source_parent_inode].kind {
-        Kind::Dir { entries, .. } => wasi_try!(entries.remove(&source_entry_name), __WASI_EINVAL),
-        Kind::Root { .. } => return __WASI_ENOTCAPABLE,
-        Kind::Symlink { .. } | Kind::File { .. } | Kind::Buffer { .. } => {
-            unreachable!("Fatal internal logic error: parent of inode is not a directory")
-        }
-    };
-
-    match &mut state.fs.inodes[source_entry].kind {
-        Kind::File {
-            handle,
-            ref mut path,
-            ..
-        } => {
-            let result = if let Some(h) = handle {
-                h.rename_file(&host_adjusted_target_path)
-                    .map_err(|e| e.into_wasi_err())
-            } else {
-                let out =
-                    std::fs::rename(&path, &host_adjusted_target_path).map_err(|_| __WASI_EIO);
-                *path = host_adjusted_target_path;
-                out
-            };
-            // if the above operation failed we have to revert the previous change and then fail
-            if let Err(e) = result {
-                if let Kind::Dir { entries, .. } = &mut state.fs.inodes[source_parent_inode].kind {
-                    entries.insert(source_entry_name, source_entry);
-                    return e;
-                }
-            }
-        }
-        Kind::Dir { path, .. } => unimplemented!("wasi::path_rename on Directories"),
-        Kind::Buffer { .. } => {}
-        Kind::Symlink { .. } => {}
-        Kind::Root { .. } => unreachable!("The root can not be moved"),
-    }
-
-    if let Kind::Dir { entries, .. } = &mut state.fs.inodes[target_parent_inode].kind {
-        let result = entries.insert(target_entry_name, source_entry);
-        assert!(
-            result.is_none(),
-            "Fatal error: race condition on filesystem detected or internal logic error"
-        );
-    }
+    wasi_try!(state.fs.rename(old_fd, source_str, new_fd, target_str));
 
     __WASI_ESUCCESS
 }
@@ -2142,60 +1647,7 @@ pub fn path_symlink(
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let old_path_str = get_input_str!(memory, old_path, old_path_len);
     let new_path_str = get_input_str!(memory, new_path, new_path_len);
-    let base_fd = wasi_try!(state.fs.get_fd(fd));
-    if !has_rights(base_fd.rights, __WASI_RIGHT_PATH_SYMLINK) {
-        return __WASI_EACCES;
-    }
-
-    // get the depth of the parent + 1 (UNDER INVESTIGATION HMMMMMMMM THINK FISH ^ THINK FISH)
-    let old_path_path = std::path::Path::new(old_path_str);
-    let (source_inode, _) = wasi_try!(state.fs.get_parent_inode_at_path(fd, old_path_path, true));
-    let depth = wasi_try!(state.fs.path_depth_from_fd(fd, source_inode)) - 1;
-
-    let new_path_path = std::path::Path::new(new_path_str);
-    let (target_parent_inode, entry_name) =
-        wasi_try!(state.fs.get_parent_inode_at_path(fd, new_path_path, true));
-
-    // short circuit if anything is wrong, before we create an inode
-    match &state.fs.inodes[target_parent_inode].kind {
-        Kind::Dir { entries, .. } => {
-            if entries.contains_key(&entry_name) {
-                return __WASI_EEXIST;
-            }
-        }
-        Kind::Root { .. } => return __WASI_ENOTCAPABLE,
-        Kind::File { .. } | Kind::Symlink { .. } | Kind::Buffer { .. } => {
-            unreachable!("get_parent_inode_at_path returned something other than a Dir or Root")
-        }
-    }
-
-    let mut source_path = std::path::Path::new(old_path_str);
-    let mut relative_path = std::path::PathBuf::new();
-    for _ in 0..depth {
-        relative_path.push("..");
-    }
-    relative_path.push(source_path);
-    debug!(
-        "Symlinking {} to {}",
-        new_path_str,
-        relative_path.to_string_lossy()
-    );
-
-    let kind = Kind::Symlink {
-        base_po_dir: fd,
-        path_to_symlink: std::path::PathBuf::from(new_path_str),
-        relative_path,
-    };
-    let new_inode = state
-        .fs
-        .create_inode_with_default_stat(kind, false, entry_name.clone());
-
-    if let Kind::Dir {
-        ref mut entries, ..
-    } = &mut state.fs.inodes[target_parent_inode].kind
-    {
-        entries.insert(entry_name, new_inode);
-    }
+    wasi_try!(state.fs.create_symlink(fd, old_path_str, new_path_str));
 
     __WASI_ESUCCESS
 }
@@ -2217,80 +1669,36 @@ pub fn path_unlink_file(
 ) -> __wasi_errno_t {
     debug!("wasi::path_unlink_file");
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
-
-    let base_dir = wasi_try!(state.fs.fd_map.get(&fd).ok_or(__WASI_EBADF));
-    if !has_rights(base_dir.rights, __WASI_RIGHT_PATH_UNLINK_FILE) {
-        return __WASI_EACCES;
-    }
     let path_str = get_input_str!(memory, path, path_len);
-    debug!("Requested file: {}", path_str);
 
-    let inode = wasi_try!(state.fs.get_inode_at_path(fd, path_str, false));
-    let (parent_inode, childs_name) =
-        wasi_try!(state
-            .fs
-            .get_parent_inode_at_path(fd, std::path::Path::new(path_str), false));
-
-    let removed_inode = match &mut state.fs.inodes[parent_inode].kind {
-        Kind::Dir {
-            ref mut entries, ..
-        } => {
-            let removed_inode = wasi_try!(entries.remove(&childs_name).ok_or(__WASI_EINVAL));
-            // TODO: make this a debug assert in the future
-            assert!(inode == removed_inode);
-            debug_assert!(state.fs.inodes[inode].stat.st_nlink > 0);
-            removed_inode
-        }
-        Kind::Root { .. } => return __WASI_EACCES,
-        _ => unreachable!(
-            "Internal logic error in wasi::path_unlink_file, parent is not a directory"
-        ),
-    };
-
-    state.fs.inodes[removed_inode].stat.st_nlink -= 1;
-    if state.fs.inodes[removed_inode].stat.st_nlink == 0 {
-        match &mut state.fs.inodes[removed_inode].kind {
-            Kind::File { handle, path, .. } => {
-                if let Some(h) = handle {
-                    wasi_try!(h.unlink().map_err(WasiFsError::into_wasi_err));
-                } else {
-                    // File is closed
-                    // problem with the abstraction, we can't call unlink because there's no handle
-                    // TODO: replace this code
-                    wasi_try!(std::fs::remove_file(path).map_err(|_| __WASI_EIO));
-                }
-            }
-            Kind::Dir { .. } | Kind::Root { .. } => return __WASI_EISDIR,
-            Kind::Symlink { .. } => {
-                // TODO: actually delete real symlinks and do nothing for virtual symlinks
-            }
-            _ => unimplemented!("wasi::path_unlink_file for Buffer"),
-        }
-        // TODO: test this on Windows and actually make it portable
-        // make the file an orphan fd if the fd is still open
-        let fd_is_orphaned = if let Kind::File { handle, .. } = &state.fs.inodes[removed_inode].kind
-        {
-            handle.is_some()
-        } else {
-            false
-        };
-        let removed_inode_val = unsafe { state.fs.remove_inode(removed_inode) };
-        assert!(
-            removed_inode_val.is_some(),
-            "Inode could not be removed because it doesn't exist"
-        );
-
-        if fd_is_orphaned {
-            state
-                .fs
-                .orphan_fds
-                .insert(removed_inode, removed_inode_val.unwrap());
-        }
-    }
+    wasi_try!(state.fs.unlink_file(fd, path_str));
 
     __WASI_ESUCCESS
 }
 
+/// The time to wait between the first two polls of the fds, in `poll_oneoff`,
+/// when they can't all be waited on with the host.
+///
+/// It doubles after each poll, up to `MAX_POLL_INTERVAL`.
+const MIN_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_micros(100);
+
+/// The longest time to wait between two polls of the fds, in `poll_oneoff`.
+const MAX_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// A subscription of `poll_oneoff`.
+enum PollSubscription {
+    /// Waits until `fd` is ready for `events`.
+    Fd {
+        fd: __wasi_fd_t,
+        events: PollEventSet,
+    },
+    /// Waits until the clock `clock_id` reaches `deadline`.
+    Clock {
+        clock_id: __wasi_clockid_t,
+        deadline: __wasi_timestamp_t,
+    },
+}
+
 /// ### `poll_oneoff()`
 /// Concurrently poll for a set of events
 /// Inputs:
@@ -2312,184 +1720,233 @@ pub fn poll_oneoff(
 ) -> __wasi_errno_t {
     debug!("wasi::poll_oneoff");
     debug!("  => nsubscriptions = {}", nsubscriptions);
-    let (memory, mut state) = env.get_memory_and_wasi_state(0);
-
+    let memory = env.memory();
     let subscription_array = wasi_try!(in_.deref(memory, 0, nsubscriptions));
-    let event_array = wasi_try!(out_.deref(memory, 0, nsubscriptions));
-    let mut events_seen = 0;
-    let out_ptr = wasi_try!(nevents.deref(memory));
-
-    let mut fds = vec![];
-    let mut clock_subs = vec![];
-    let mut in_events = vec![];
-    let mut total_ns_slept = 0;
-
-    for sub in subscription_array.iter() {
-        let s: WasiSubscription = wasi_try!(sub.get().try_into());
-        let mut peb = PollEventBuilder::new();
-        let mut ns_to_sleep = 0;
-
-        let fd = match s.event_type {
-            EventType::Read(__wasi_subscription_fs_readwrite_t { fd }) => {
-                match fd {
-                    __WASI_STDIN_FILENO | __WASI_STDOUT_FILENO | __WASI_STDERR_FILENO => (),
-                    _ => {
-                        let fd_entry = wasi_try!(state.fs.get_fd(fd));
-                        if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_READ) {
-                            return __WASI_EACCES;
+    wasi_try!(out_.deref(memory, 0, nsubscriptions));
+    wasi_try!(nevents.deref(memory));
+
+    // The subscriptions which can't be waited on are reported with the
+    // error in their event.
+    let mut subscriptions = Vec::with_capacity(subscription_array.len());
+    {
+        let state = env.state();
+        for sub in subscription_array.iter() {
+            let s: WasiSubscription = wasi_try!(sub.get().try_into());
+            let subscription = match s.event_type {
+                EventType::Read(__wasi_subscription_fs_readwrite_t { fd }) => {
+                    check_poll_rights(&state, fd, __WASI_RIGHT_FD_READ).map(|()| {
+                        PollSubscription::Fd {
+                            fd,
+                            events: PollEventBuilder::new().add(PollEvent::PollIn).build(),
                         }
-                    }
+                    })
                 }
-                in_events.push(peb.add(PollEvent::PollIn).build());
-                Some(fd)
-            }
-            EventType::Write(__wasi_subscription_fs_readwrite_t { fd }) => {
-                match fd {
-                    __WASI_STDIN_FILENO | __WASI_STDOUT_FILENO | __WASI_STDERR_FILENO => (),
-                    _ => {
-                        let fd_entry = wasi_try!(state.fs.get_fd(fd));
-
-                        if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_WRITE) {
-                            return __WASI_EACCES;
+                EventType::Write(__wasi_subscription_fs_readwrite_t { fd }) => {
+                    check_poll_rights(&state, fd, __WASI_RIGHT_FD_WRITE).map(|()| {
+                        PollSubscription::Fd {
+                            fd,
+                            events: PollEventBuilder::new().add(PollEvent::PollOut).build(),
                         }
-                    }
+                    })
                 }
-                in_events.push(peb.add(PollEvent::PollOut).build());
-                Some(fd)
-            }
-            EventType::Clock(clock_info) => {
-                if clock_info.clock_id == __WASI_CLOCK_REALTIME {
-                    // this is a hack
-                    // TODO: do this properly
-                    ns_to_sleep = clock_info.timeout;
-                    clock_subs.push(clock_info);
-                    None
-                } else {
-                    unimplemented!("Polling not implemented for clocks yet");
+                EventType::Clock(clock_info) => {
+                    let timeout = if clock_info.flags & __WASI_SUBSCRIPTION_CLOCK_ABSTIME != 0 {
+                        Ok(clock_info.timeout)
+                    } else {
+                        poll_clock_now(&state, clock_info.clock_id)
+                            .map(|now| now.saturating_add(clock_info.timeout))
+                    };
+                    // The event may fire up to `precision` nanoseconds early.
+                    timeout.map(|timeout| PollSubscription::Clock {
+                        clock_id: clock_info.clock_id,
+                        deadline: timeout.saturating_sub(clock_info.precision),
+                    })
                 }
-            }
-        };
+            };
+            subscriptions.push((s.user_data, s.event_type.raw_tag(), subscription));
+        }
+    }
 
-        if let Some(fd) = fd {
-            let wasi_file_ref: &dyn WasiFile = match fd {
-                __WASI_STDERR_FILENO => wasi_try!(
-                    wasi_try!(state.fs.stderr().map_err(WasiFsError::into_wasi_err)).as_ref(),
-                    __WASI_EBADF
-                )
-                .as_ref(),
-                __WASI_STDIN_FILENO => wasi_try!(
-                    wasi_try!(state.fs.stdin().map_err(WasiFsError::into_wasi_err)).as_ref(),
-                    __WASI_EBADF
-                )
-                .as_ref(),
-                __WASI_STDOUT_FILENO => wasi_try!(
-                    wasi_try!(state.fs.stdout().map_err(WasiFsError::into_wasi_err)).as_ref(),
-                    __WASI_EBADF
-                )
-                .as_ref(),
-                _ => {
-                    let fd_entry = wasi_try!(state.fs.get_fd(fd));
-                    let inode = fd_entry.inode;
-                    if !has_rights(fd_entry.rights, __WASI_RIGHT_POLL_FD_READWRITE) {
-                        return __WASI_EACCES;
+    let mut poll_interval = MIN_POLL_INTERVAL;
+    let events = loop {
+        let mut state = env.state();
+        let mut events = Vec::new();
+        let mut nearest_deadline: Option<__wasi_timestamp_t> = None;
+        let mut polls_fds = false;
+        // The host fds of the polled files, or `None` if one of them has none.
+        let mut host_fds = Some(Vec::new());
+        for (userdata, type_, subscription) in subscriptions.iter() {
+            let (error, fd_readwrite) = match *subscription {
+                Err(error) => (error, __wasi_event_fd_readwrite_t::default()),
+                Ok(PollSubscription::Fd { fd, events }) => {
+                    polls_fds = true;
+                    match poll_fd_readiness(&state, fd, events, &mut host_fds) {
+                        Ok(Some(fd_readwrite)) => (__WASI_ESUCCESS, fd_readwrite),
+                        Ok(None) => continue,
+                        Err(error) => (error, __wasi_event_fd_readwrite_t::default()),
                     }
-
-                    match &state.fs.inodes[inode].kind {
-                        Kind::File { handle, .. } => {
-                            if let Some(h) = handle {
-                                h.as_ref()
-                            } else {
-                                return __WASI_EBADF;
+                }
+                Ok(PollSubscription::Clock { clock_id, deadline }) => {
+                    match poll_clock_now(&state, clock_id) {
+                        Ok(now) if now < deadline => {
+                            let remaining = deadline - now;
+                            if nearest_deadline.map_or(true, |nearest| remaining < nearest) {
+                                nearest_deadline = Some(remaining);
                             }
+                            continue;
                         }
-                        Kind::Dir { .. }
-                        | Kind::Root { .. }
-                        | Kind::Buffer { .. }
-                        | Kind::Symlink { .. } => {
-                            unimplemented!("polling read on non-files not yet supported")
-                        }
+                        Ok(_) => (__WASI_ESUCCESS, __wasi_event_fd_readwrite_t::default()),
+                        Err(error) => (error, __wasi_event_fd_readwrite_t::default()),
                     }
                 }
             };
-            fds.push(wasi_file_ref);
-        } else {
-            let remaining_ns = ns_to_sleep as i64 - total_ns_slept as i64;
-            if remaining_ns > 0 {
-                debug!("Sleeping for {} nanoseconds", remaining_ns);
-                let duration = std::time::Duration::from_nanos(remaining_ns as u64);
-                std::thread::sleep(duration);
-                total_ns_slept += remaining_ns;
+            events.push(__wasi_event_t {
+                userdata: *userdata,
+                error,
+                type_: *type_,
+                u: __wasi_event_u { fd_readwrite },
+            });
+        }
+        if !events.is_empty() || subscriptions.is_empty() {
+            break events;
+        }
+
+        // Wait for the nearest deadline, or for the fds to be ready.
+        if let Some(determinism) = &mut state.determinism {
+            if let Some(remaining) = nearest_deadline {
+                determinism.sleep(remaining);
+                continue;
             }
         }
-    }
-    let mut seen_events = vec![Default::default(); in_events.len()];
-    wasi_try!(poll(
-        fds.as_slice(),
-        in_events.as_slice(),
-        seen_events.as_mut_slice()
-    )
-    .map_err(|e| e.into_wasi_err()));
-
-    for (i, seen_event) in seen_events.into_iter().enumerate() {
-        let mut flags = 0;
-        let mut error = __WASI_EAGAIN;
-        let mut bytes_available = 0;
-        let event_iter = iterate_poll_events(seen_event);
-        for event in event_iter {
-            match event {
-                PollEvent::PollError => error = __WASI_EIO,
-                PollEvent::PollHangUp => flags = __WASI_EVENT_FD_READWRITE_HANGUP,
-                PollEvent::PollInvalid => error = __WASI_EINVAL,
-                PollEvent::PollIn => {
-                    bytes_available =
-                        wasi_try!(fds[i].bytes_available().map_err(|e| e.into_wasi_err()));
-                    error = __WASI_ESUCCESS;
-                }
-                PollEvent::PollOut => {
-                    bytes_available =
-                        wasi_try!(fds[i].bytes_available().map_err(|e| e.into_wasi_err()));
-                    error = __WASI_ESUCCESS;
-                }
+        // The other WASI calls, and the host, can use the state meanwhile.
+        drop(state);
+        let timeout = nearest_deadline.map(std::time::Duration::from_nanos);
+        #[cfg(unix)]
+        {
+            if let (true, Some(host_fds)) = (polls_fds, &host_fds) {
+                debug!("Waiting on the host fds for {:?}", timeout);
+                wait_for_host_fds(host_fds, timeout);
+                continue;
             }
         }
-        let event = __wasi_event_t {
-            userdata: subscription_array[i].get().userdata,
-            error,
-            type_: subscription_array[i].get().type_,
-            u: unsafe {
-                __wasi_event_u {
-                    fd_readwrite: __wasi_event_fd_readwrite_t {
-                        nbytes: bytes_available as u64,
-                        flags,
-                    },
-                }
-            },
-        };
-        event_array[events_seen].set(event);
-        events_seen += 1;
-    }
-    for clock_info in clock_subs {
-        let event = __wasi_event_t {
-            // TOOD: review userdata value
-            userdata: 0,
-            error: __WASI_ESUCCESS,
-            type_: __WASI_EVENTTYPE_CLOCK,
-            u: unsafe {
-                __wasi_event_u {
-                    fd_readwrite: __wasi_event_fd_readwrite_t {
-                        nbytes: 0,
-                        flags: 0,
-                    },
-                }
-            },
+        // The files without a host fd are polled with a backoff.
+        let duration = match timeout {
+            Some(timeout) if polls_fds => std::cmp::min(timeout, poll_interval),
+            Some(timeout) => timeout,
+            None => poll_interval,
         };
-        event_array[events_seen].set(event);
-        events_seen += 1;
+        poll_interval = std::cmp::min(poll_interval * 2, MAX_POLL_INTERVAL);
+        debug!("Sleeping for {:?}", duration);
+        std::thread::sleep(duration);
+    };
+
+    let event_array = wasi_try!(out_.deref(memory, 0, nsubscriptions));
+    for (cell, event) in event_array.iter().zip(events.iter()) {
+        cell.set(*event);
     }
-    out_ptr.set(events_seen as u32);
+    wasi_try!(nevents.deref(memory)).set(events.len() as u32);
     __WASI_ESUCCESS
 }
 
+/// Checks that `fd` can be polled for the `rights` operation.
+fn check_poll_rights(
+    state: &WasiState,
+    fd: __wasi_fd_t,
+    rights: __wasi_rights_t,
+) -> Result<(), __wasi_errno_t> {
+    match fd {
+        __WASI_STDIN_FILENO | __WASI_STDOUT_FILENO | __WASI_STDERR_FILENO => Ok(()),
+        _ => {
+            let fd_entry = state.fs.get_fd(fd)?;
+            if !has_rights(fd_entry.rights, rights)
+                || !has_rights(fd_entry.rights, __WASI_RIGHT_POLL_FD_READWRITE)
+            {
+                return Err(__WASI_EACCES);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Polls `fd` for `events` without blocking, and returns the event to report if it's ready.
+///
+/// The host fd of the file is added to `host_fds`, which is set to `None` if it has none.
+fn poll_fd_readiness(
+    state: &WasiState,
+    fd: __wasi_fd_t,
+    events: PollEventSet,
+    host_fds: &mut Option<Vec<(i32, PollEventSet)>>,
+) -> Result<Option<__wasi_event_fd_readwrite_t>, __wasi_errno_t> {
+    let file: &dyn WasiFile = match fd {
+        __WASI_STDIN_FILENO | __WASI_STDOUT_FILENO | __WASI_STDERR_FILENO => state
+            .fs
+            .std_dev_get(fd)
+            .map_err(WasiFsError::into_wasi_err)?
+            .as_deref()
+            .ok_or(__WASI_EBADF)?,
+        _ => {
+            match &state.fs.get_inodeval(fd)?.kind {
+                Kind::File {
+                    handle: Some(handle),
+                    ..
+                } => handle.as_ref(),
+                // Reading from or writing to a buffer never blocks.
+                Kind::Buffer { .. } => return Ok(Some(__wasi_event_fd_readwrite_t::default())),
+                Kind::File { handle: None, .. }
+                | Kind::Dir { .. }
+                | Kind::Root { .. }
+                | Kind::Symlink { .. } => return Err(__WASI_EBADF),
+            }
+        }
+    };
+
+    match (file.get_raw_fd(), host_fds.as_mut()) {
+        (Some(host_fd), Some(fds)) => fds.push((host_fd, events)),
+        _ => *host_fds = None,
+    }
+    let readiness = file
+        .poll_readiness(events)
+        .map_err(WasiFsError::into_wasi_err)?;
+    let mut flags = 0;
+    for event in iterate_poll_events(readiness) {
+        match event {
+            PollEvent::PollError => return Err(__WASI_EIO),
+            PollEvent::PollInvalid => return Err(__WASI_EBADF),
+            PollEvent::PollHangUp => flags |= __WASI_EVENT_FD_READWRITE_HANGUP,
+            PollEvent::PollIn | PollEvent::PollOut => (),
+        }
+    }
+    if readiness & events == 0 && flags == 0 {
+        return Ok(None);
+    }
+    let nbytes = file.bytes_available().unwrap_or(0) as __wasi_filesize_t;
+    Ok(Some(__wasi_event_fd_readwrite_t { nbytes, flags }))
+}
+
+/// Reads the clock `clock_id` for the clock subscriptions of `poll_oneoff`.
+///
+/// Only the real-time and the monotonic clocks can be waited on.
+fn poll_clock_now(
+    state: &WasiState,
+    clock_id: __wasi_clockid_t,
+) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+    match clock_id {
+        __WASI_CLOCK_REALTIME | __WASI_CLOCK_MONOTONIC => (),
+        __WASI_CLOCK_PROCESS_CPUTIME_ID | __WASI_CLOCK_THREAD_CPUTIME_ID => {
+            return Err(__WASI_ENOTSUP)
+        }
+        _ => return Err(__WASI_EINVAL),
+    }
+    if let Some(determinism) = &state.determinism {
+        return Ok(determinism.time());
+    }
+    let now = Cell::new(0);
+    match platform_clock_time_get(clock_id, 1, &now) {
+        __WASI_ESUCCESS => Ok(now.get()),
+        error => Err(error),
+    }
+}
+
 pub fn proc_exit(env: &mut WasiEnv, code: __wasi_exitcode_t) {
     debug!("wasi::proc_exit, {}", code);
     RuntimeError::raise(Box::new(WasiError::Exit(code)));
@@ -2498,11 +1955,12 @@ pub fn proc_exit(env: &mut WasiEnv, code: __wasi_exitcode_t) {
 
 pub fn proc_raise(env: &mut WasiEnv, sig: __wasi_signal_t) -> __wasi_errno_t {
     debug!("wasi::proc_raise");
-    unimplemented!("wasi::proc_raise")
+    __WASI_ENOTSUP
 }
 
 /// ### `random_get()`
-/// Fill buffer with high-quality random data.  This function may be slow and block
+/// Fill buffer with high-quality random data.  This function may be slow and block.
+/// In deterministic mode, the data is generated from the seed of the state instead
 /// Inputs:
 /// - `void *buf`
 ///     A pointer to a buffer where the random bytes will be written
@@ -2510,15 +1968,16 @@ pub fn proc_raise(env: &mut WasiEnv, sig: __wasi_signal_t) -> __wasi_errno_t {
 ///     The number of bytes that will be written
 pub fn random_get(env: &mut WasiEnv, buf: WasmPtr<u8, Array>, buf_len: u32) -> __wasi_errno_t {
     debug!("wasi::random_get buf_len: {}", buf_len);
-    let memory = env.memory();
+    let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
     let buf = wasi_try!(buf.deref(memory, 0, buf_len));
+    let u8_buffer = unsafe { &mut *(buf as *const [_] as *mut [_] as *mut [u8]) };
 
-    let res = unsafe {
-        let u8_buffer = &mut *(buf as *const [_] as *mut [_] as *mut [u8]);
-        getrandom::getrandom(u8_buffer)
-    };
-    match res {
+    if let Some(determinism) = &mut state.determinism {
+        determinism.fill_random(u8_buffer);
+        return __WASI_ESUCCESS;
+    }
+    match getrandom::getrandom(u8_buffer) {
         Ok(()) => __WASI_ESUCCESS,
         Err(_) => __WASI_EIO,
     }
@@ -2542,7 +2001,7 @@ pub fn sock_recv(
     ro_flags: WasmPtr<__wasi_roflags_t>,
 ) -> __wasi_errno_t {
     debug!("wasi::sock_recv");
-    unimplemented!("wasi::sock_recv")
+    __WASI_ENOTSUP
 }
 pub fn sock_send(
     env: &mut WasiEnv,
@@ -2553,7 +2012,7 @@ pub fn sock_send(
     so_datalen: WasmPtr<u32>,
 ) -> __wasi_errno_t {
     debug!("wasi::sock_send");
-    unimplemented!("wasi::sock_send")
+    __WASI_ENOTSUP
 }
 pub fn sock_shutdown(
     env: &mut WasiEnv,
@@ -2561,5 +2020,5 @@ pub fn sock_shutdown(
     how: __wasi_sdflags_t,
 ) -> __wasi_errno_t {
     debug!("wasi::sock_shutdown");
-    unimplemented!("wasi::sock_shutdown")
+    __WASI_ENOTSUP
 }
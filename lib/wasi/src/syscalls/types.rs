@@ -148,7 +148,7 @@ pub const __WASI_ETXTBSY: u16 = 74;
 pub const __WASI_EXDEV: u16 = 75;
 pub const __WASI_ENOTCAPABLE: u16 = 76;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
 #[repr(C)]
 pub struct __wasi_event_fd_readwrite_t {
     pub nbytes: __wasi_filesize_t,
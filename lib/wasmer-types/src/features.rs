@@ -18,6 +18,8 @@ pub struct Features {
     pub bulk_memory: bool,
     /// Multi Value proposal should be enabled
     pub multi_value: bool,
+    /// Deterministic execution should be enforced
+    pub deterministic: bool,
 }
 
 impl Features {
@@ -30,6 +32,7 @@ impl Features {
             bulk_memory: false,
             // Multivalue should be on by default
             multi_value: true,
+            deterministic: false,
         }
     }
 
@@ -135,6 +138,29 @@ impl Features {
         self.multi_value = enable;
         self
     }
+
+    /// Configures whether the execution of WebAssembly modules must be
+    /// deterministic, giving bit-for-bit identical results with every
+    /// compiler and on every host.
+    ///
+    /// In deterministic mode:
+    ///  * every compiler canonicalizes the NaNs produced by floating-point
+    ///    operations;
+    ///  * modules using the threads proposal are rejected, even if it's
+    ///    enabled;
+    ///  * a `memory.grow` failing for a reason specific to the host, such
+    ///    as the allocation of the pages, traps rather than returning -1.
+    ///    The memories grown by the host still return these failures,
+    ///    which the host must handle deterministically.
+    ///
+    /// The host functions must be deterministic too, see for example the
+    /// deterministic mode of WASI.
+    ///
+    /// This is `false` by default.
+    pub fn deterministic(&mut self, enable: bool) -> &mut Self {
+        self.deterministic = enable;
+        self
+    }
 }
 
 impl Default for Features {
@@ -157,6 +183,7 @@ mod test_features {
                 simd: false,
                 bulk_memory: false,
                 multi_value: true,
+                deterministic: false,
             }
         );
     }
@@ -208,4 +235,11 @@ mod test_features {
         assert!(!features.bulk_memory);
         assert!(!features.reference_types);
     }
+
+    #[test]
+    fn enable_deterministic() {
+        let mut features = Features::new();
+        features.deterministic(true);
+        assert!(features.deterministic);
+    }
 }
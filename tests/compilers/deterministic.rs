@@ -0,0 +1,150 @@
+//! Testing the deterministic mode.
+//!
+//! The expected results are exact, so running these tests with each
+//! compiler cross-checks that they all agree.
+
+use crate::utils::get_deterministic_store;
+use anyhow::Result;
+use wasmer::*;
+use wasmer_compiler::{CompileError, CompilerConfig};
+use wasmer_engine_jit::JIT;
+
+/// Checks that the NaNs produced by the floating-point operations of
+/// the modules of `store` are canonical.
+fn check_nans_are_canonical(store: &Store) -> Result<()> {
+    let wat = r#"(module
+      (func (export "f32_div") (param f32 f32) (result i32)
+        (i32.reinterpret_f32 (f32.div (local.get 0) (local.get 1))))
+      (func (export "f32_add") (param f32 f32) (result i32)
+        (i32.reinterpret_f32 (f32.add (local.get 0) (local.get 1))))
+      (func (export "f64_sqrt") (param f64) (result i64)
+        (i64.reinterpret_f64 (f64.sqrt (local.get 0))))
+      (func (export "f64_mul") (param f64 f64) (result i64)
+        (i64.reinterpret_f64 (f64.mul (local.get 0) (local.get 1)))))"#;
+    let instance = Instance::new(&Module::new(store, wat)?, &imports! {})?;
+
+    let f32_div = instance
+        .exports
+        .get_native_function::<(f32, f32), i32>("f32_div")?;
+    let f32_add = instance
+        .exports
+        .get_native_function::<(f32, f32), i32>("f32_add")?;
+    let f64_sqrt = instance
+        .exports
+        .get_native_function::<f64, i64>("f64_sqrt")?;
+    let f64_mul = instance
+        .exports
+        .get_native_function::<(f64, f64), i64>("f64_mul")?;
+
+    let payload = f32::from_bits(0xffc0_1234);
+    assert_eq!(f32_div.call(0.0, 0.0)?, 0x7fc0_0000);
+    assert_eq!(f32_add.call(payload, 1.0)?, 0x7fc0_0000);
+    assert_eq!(f32_div.call(6.0, 3.0)?, 2.0f32.to_bits() as i32);
+    assert_eq!(f64_sqrt.call(-1.0)?, 0x7ff8_0000_0000_0000);
+    assert_eq!(
+        f64_mul.call(f64::from_bits(0xfff8_0000_0000_abcd), 2.0)?,
+        0x7ff8_0000_0000_0000
+    );
+    assert_eq!(f64_sqrt.call(4.0)?, 2.0f64.to_bits() as i64);
+    Ok(())
+}
+
+#[test]
+fn nans_are_canonical() -> Result<()> {
+    check_nans_are_canonical(&get_deterministic_store(|_| {}))
+}
+
+#[test]
+fn nans_are_canonical_with_every_compiler() -> Result<()> {
+    let compilers: Vec<(&str, Box<dyn CompilerConfig>)> = vec![
+        #[cfg(feature = "singlepass")]
+        (
+            "singlepass",
+            Box::new(wasmer_compiler_singlepass::Singlepass::new()),
+        ),
+        #[cfg(feature = "cranelift")]
+        (
+            "cranelift",
+            Box::new(wasmer_compiler_cranelift::Cranelift::new()),
+        ),
+        #[cfg(feature = "llvm")]
+        ("llvm", Box::new(wasmer_compiler_llvm::LLVM::new())),
+    ];
+
+    // The compilers don't canonicalize the NaNs by default.
+    for (name, compiler_config) in compilers {
+        let mut features = compiler_config.default_features_for_target(&Target::default());
+        features.deterministic(true);
+        let engine = JIT::new(&*compiler_config).features(features).engine();
+        check_nans_are_canonical(&Store::new(&engine))
+            .map_err(|error| error.context(format!("with {}", name)))?;
+    }
+    Ok(())
+}
+
+#[test]
+fn threads_are_rejected() -> Result<()> {
+    let wat = "(module (memory 1 1 shared))";
+    let store = get_deterministic_store(|features| {
+        features.threads(true);
+    });
+    let error = Module::new(&store, wat).unwrap_err();
+    assert!(
+        matches!(error, CompileError::UnsupportedFeature(feature) if feature == "threads in deterministic mode")
+    );
+    Ok(())
+}
+
+#[test]
+fn memory_grow_beyond_the_maximum_fails() -> Result<()> {
+    let wat = r#"(module
+      (memory 1 2)
+      (func (export "grow") (param i32) (result i32)
+        (memory.grow (local.get 0))))"#;
+    let store = get_deterministic_store(|_| {});
+    let instance = Instance::new(&Module::new(&store, wat)?, &imports! {})?;
+    let grow = instance.exports.get_native_function::<i32, i32>("grow")?;
+    assert_eq!(grow.call(2)?, -1);
+    assert_eq!(grow.call(1)?, 1);
+    assert_eq!(grow.call(1)?, -1);
+    Ok(())
+}
+
+#[cfg(feature = "wasi")]
+#[test]
+fn wasi_clocks_and_random_are_seeded() -> Result<()> {
+    use wasmer_wasi::WasiState;
+
+    let wat = r#"(module
+      (import "wasi_snapshot_preview1" "clock_time_get"
+        (func $clock_time_get (param i32 i64 i32) (result i32)))
+      (import "wasi_snapshot_preview1" "random_get"
+        (func $random_get (param i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (func (export "run")
+        (drop (call $clock_time_get (i32.const 0) (i64.const 0) (i32.const 0)))
+        (drop (call $clock_time_get (i32.const 1) (i64.const 0) (i32.const 8)))
+        (drop (call $random_get (i32.const 16) (i32.const 8)))))"#;
+    let store = get_deterministic_store(|_| {});
+    let module = Module::new(&store, wat)?;
+
+    let run = |seed| -> Result<[u64; 3]> {
+        let mut wasi_env = WasiState::new("deterministic")
+            .deterministic(seed)
+            .finalize()?;
+        let instance = Instance::new(&module, &wasi_env.import_object(&module)?)?;
+        instance.exports.get_function("run")?.call(&[])?;
+
+        let memory = instance.exports.get_memory("memory")?;
+        Ok([
+            memory.read_obj::<u64>(0)?,
+            memory.read_obj::<u64>(8)?,
+            memory.read_obj::<u64>(16)?,
+        ])
+    };
+    let first = run(7)?;
+    assert_eq!(first[..2], [1_000, 2_000]);
+    assert_eq!(first, run(7)?);
+    assert_ne!(first[2], run(8)?[2]);
+    Ok(())
+}
@@ -0,0 +1,132 @@
+//! Testing the lazy compilation of the functions, on their first call.
+
+#![cfg(all(target_arch = "x86_64", unix))]
+
+use crate::utils::get_store;
+use anyhow::Result;
+use wasmer::*;
+
+fn get_lazy_store() -> Store {
+    let mut store = get_store();
+    store.set_lazy_compilation(true);
+    store
+}
+
+#[test]
+#[cfg_attr(feature = "test-llvm", ignore)]
+fn functions_are_compiled_on_first_call() -> Result<()> {
+    let wat = r#"(module
+      (import "host" "double" (func $double (param i64) (result i64)))
+      (type $unary (func (param i64) (result i64)))
+      (table 2 funcref)
+      (elem (i32.const 0) $fib $double)
+      (func $fib (export "fib") (param i64) (result i64)
+        (if (result i64) (i64.lt_u (local.get 0) (i64.const 2))
+          (then (local.get 0))
+          (else
+            (i64.add
+              (call $fib (i64.sub (local.get 0) (i64.const 1)))
+              (call $fib (i64.sub (local.get 0) (i64.const 2)))))))
+      (func (export "call_indirect") (param i32 i64) (result i64)
+        (call_indirect (type $unary) (local.get 1) (local.get 0)))
+      (func (export "never_called") (result i64)
+        (call $double (i64.const 1))))"#;
+    let store = get_lazy_store();
+    let (module, stats) = Module::new_with_stats(&store, wat)?;
+    assert_eq!(stats.functions, 0);
+
+    let double = Function::new_native(&store, |x: i64| x * 2);
+    let imports = imports! { "host" => { "double" => double } };
+    let instance = Instance::new(&module, &imports)?;
+
+    let fib = instance.exports.get_native_function::<i64, i64>("fib")?;
+    assert_eq!(fib.call(20)?, 6765);
+    assert_eq!(fib.call(20)?, 6765);
+
+    let call_indirect = instance
+        .exports
+        .get_native_function::<(i32, i64), i64>("call_indirect")?;
+    assert_eq!(call_indirect.call(0, 10)?, 55);
+    assert_eq!(call_indirect.call(1, 10)?, 20);
+
+    // the functions are shared by the instances of the module
+    let instance = Instance::new(&module, &imports)?;
+    let fib = instance.exports.get_native_function::<i64, i64>("fib")?;
+    assert_eq!(fib.call(10)?, 55);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(feature = "test-llvm", ignore)]
+fn arguments_are_preserved() -> Result<()> {
+    // More arguments than registers, so some are passed on the stack.
+    let wat = r#"(module
+      (func $sum (param i32 i64 f32 f64 i32 i64 f32 f64 i64 i64 f64 f64 f64 f64 f64 f64)
+        (result f64)
+        (f64.add (f64.convert_i32_s (local.get 0))
+        (f64.add (f64.convert_i64_s (local.get 1))
+        (f64.add (f64.promote_f32 (local.get 2))
+        (f64.add (local.get 3)
+        (f64.add (f64.convert_i32_s (local.get 4))
+        (f64.add (f64.convert_i64_s (local.get 5))
+        (f64.add (f64.promote_f32 (local.get 6))
+        (f64.add (local.get 7)
+        (f64.add (f64.convert_i64_s (local.get 8))
+        (f64.add (f64.convert_i64_s (local.get 9))
+        (f64.add (local.get 10)
+        (f64.add (local.get 11)
+        (f64.add (local.get 12)
+        (f64.add (local.get 13)
+        (f64.add (local.get 14) (local.get 15)))))))))))))))))
+      (func (export "run") (result f64)
+        (call $sum
+          (i32.const 1) (i64.const 2) (f32.const 4) (f64.const 8)
+          (i32.const 16) (i64.const 32) (f32.const 64) (f64.const 128)
+          (i64.const 256) (i64.const 512) (f64.const 1024) (f64.const 2048)
+          (f64.const 4096) (f64.const 8192) (f64.const 16384) (f64.const 32768))))"#;
+    let store = get_lazy_store();
+    let instance = Instance::new(&Module::new(&store, wat)?, &imports! {})?;
+    let run = instance.exports.get_native_function::<(), f64>("run")?;
+    assert_eq!(run.call()?, 65535.0);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(any(feature = "test-singlepass", feature = "test-llvm"), ignore)]
+fn traps_of_lazily_compiled_functions() -> Result<()> {
+    let wat = r#"(module
+      (global $started (mut i32) (i32.const 0))
+      (func $start (global.set $started (i32.const 1)))
+      (func $die unreachable)
+      (func (export "run") (call $die))
+      (func (export "started") (result i32) (global.get $started))
+      (start $start))"#;
+    let store = get_lazy_store();
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &imports! {})?;
+
+    let started = instance.exports.get_native_function::<(), i32>("started")?;
+    assert_eq!(started.call()?, 1);
+
+    let run = instance.exports.get_function("run")?;
+    let e = run.call(&[]).unwrap_err();
+    assert!(
+        e.message().contains("unreachable"),
+        "wrong message: {}",
+        e.message()
+    );
+    let frames = e.trace();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].func_index(), 1);
+    assert_eq!(frames[1].func_index(), 2);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(feature = "test-llvm", ignore)]
+fn lazily_compiled_modules_are_not_serializable() -> Result<()> {
+    let store = get_lazy_store();
+    let module = Module::new(&store, "(module (func (export \"run\")))")?;
+    assert!(module.serialize().is_err());
+    Ok(())
+}
@@ -4,14 +4,23 @@
 //! implementation, such as: singlepass, cranelift or llvm depending
 //! on what's available on the target.
 
+mod deterministic;
 mod imports;
+mod lazy_compilation;
+mod memory_images;
 mod middlewares;
 mod multi_value_imports;
 mod native_functions;
+mod pooling;
 mod serialize;
+mod tiered_compilation;
 mod traps;
 mod utils;
 mod wasi;
+mod wasi_env;
+mod wasi_invalid_arguments;
+mod wasi_poll;
+mod wasi_symlink;
 mod wast;
 
 pub use crate::utils::get_compiler;
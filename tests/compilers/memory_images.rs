@@ -0,0 +1,110 @@
+//! Testing the initialization of the memories from their copy-on-write
+//! images, shared by the instances of a module.
+
+use crate::utils::get_store;
+use anyhow::Result;
+use wasmer::*;
+
+const WAT: &str = r#"(module
+  (memory (export "memory") 2 4)
+  (data (i32.const 8) "hello")
+  (data (i32.const 65540) "world")
+  (data (i32.const 10) "y")
+  (func (export "store") (param i32 i32) (i32.store8 (local.get 0) (local.get 1)))
+  (func (export "grow") (param i32) (result i32) (memory.grow (local.get 0))))"#;
+
+fn read(memory: &Memory, offset: u64, len: usize) -> Result<Vec<u8>> {
+    let mut buf = vec![0; len];
+    memory.read(offset, &mut buf)?;
+    Ok(buf)
+}
+
+#[test]
+fn the_instances_get_private_copies_of_the_data() -> Result<()> {
+    let store = get_store();
+    let module = Module::new(&store, WAT)?;
+
+    let first = Instance::new(&module, &imports! {})?;
+    let second = Instance::new(&module, &imports! {})?;
+    let memory = first.exports.get_memory("memory")?;
+    assert_eq!(read(memory, 6, 9)?, b"\0\0heylo\0\0");
+    assert_eq!(read(memory, 65540, 5)?, b"world");
+    assert_eq!(read(memory, 4096, 4)?, [0; 4]);
+
+    let store_ = first
+        .exports
+        .get_native_function::<(i32, i32), ()>("store")?;
+    store_.call(8, i32::from(b'j'))?;
+    store_.call(4096, 1)?;
+    assert_eq!(read(memory, 8, 5)?, b"jeylo");
+    assert_eq!(read(memory, 4096, 1)?, [1]);
+
+    let other_memory = second.exports.get_memory("memory")?;
+    assert_eq!(read(other_memory, 8, 5)?, b"heylo");
+    assert_eq!(read(other_memory, 4096, 1)?, [0]);
+
+    let third = Instance::new(&module, &imports! {})?;
+    assert_eq!(read(third.exports.get_memory("memory")?, 8, 5)?, b"heylo");
+    Ok(())
+}
+
+#[test]
+fn the_memories_grow_past_their_image() -> Result<()> {
+    let store = get_store();
+    let module = Module::new(&store, WAT)?;
+    let instance = Instance::new(&module, &imports! {})?;
+
+    let grow = instance.exports.get_native_function::<i32, i32>("grow")?;
+    assert_eq!(grow.call(2)?, 2);
+    let memory = instance.exports.get_memory("memory")?;
+    assert_eq!(read(memory, 65540, 5)?, b"world");
+    assert_eq!(read(memory, 3 * 65536, 4)?, [0; 4]);
+    memory.write(3 * 65536, b"more")?;
+    assert_eq!(read(memory, 3 * 65536, 4)?, b"more");
+    Ok(())
+}
+
+#[test]
+fn the_segments_based_on_globals_are_copied() -> Result<()> {
+    let store = get_store();
+    let module = Module::new(
+        &store,
+        r#"(module
+          (import "env" "offset" (global $offset i32))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "abc")
+          (data (global.get $offset) "def"))"#,
+    )?;
+
+    for &offset in &[16, 1] {
+        let imports = imports! {
+            "env" => { "offset" => Global::new(&store, Value::I32(offset)) },
+        };
+        let instance = Instance::new(&module, &imports)?;
+        let memory = instance.exports.get_memory("memory")?;
+        let expected: &[u8] = if offset == 16 { b"abc" } else { b"ade" };
+        assert_eq!(read(memory, 0, 3)?, expected);
+        assert_eq!(read(memory, offset as u64, 3)?, b"def");
+    }
+    Ok(())
+}
+
+#[test]
+fn the_segments_out_of_bounds_still_trap() -> Result<()> {
+    let store = get_store();
+    let module = Module::new(
+        &store,
+        r#"(module
+          (memory (export "memory") 1)
+          (data (i32.const 0) "abc")
+          (data (i32.const 65535) "de"))"#,
+    )?;
+
+    for _ in 0..2 {
+        assert!(matches!(
+            Instance::new(&module, &imports! {}),
+            Err(InstantiationError::Start(_))
+        ));
+    }
+    Ok(())
+}
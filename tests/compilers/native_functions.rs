@@ -105,7 +105,7 @@ fn static_host_function_with_env() -> anyhow::Result<()> {
         Ok((d * 4.0, c * 3.0, b * 2, a * 1))
     }
 
-    #[derive(Clone)]
+    #[derive(WasmerEnv, Clone)]
     struct Env(Rc<RefCell<i32>>);
 
     // Native static host function that returns a tuple.
@@ -172,7 +172,7 @@ fn dynamic_host_function_without_env() -> anyhow::Result<()> {
 fn dynamic_host_function_with_env() -> anyhow::Result<()> {
     let store = get_store();
 
-    #[derive(Clone)]
+    #[derive(WasmerEnv, Clone)]
     struct Env(Rc<RefCell<i32>>);
 
     let env = Env(Rc::new(RefCell::new(100)));
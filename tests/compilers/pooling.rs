@@ -0,0 +1,224 @@
+//! Testing the pooling allocator, reusing the slots of the dropped
+//! instances.
+
+#![cfg(unix)]
+
+use crate::utils::get_engine;
+use anyhow::Result;
+use wasmer::*;
+
+fn get_pooling_store(limits: PoolingLimits) -> (Store, PoolingAllocator) {
+    let tunables = PoolingTunables::new(limits).unwrap();
+    let allocator = tunables.allocator().clone();
+    (Store::new_with_tunables(&get_engine(), tunables), allocator)
+}
+
+const WAT: &str = r#"(module
+  (memory (export "memory") 1 4)
+  (table 2 funcref)
+  (func (export "load") (result i32) (i32.load (i32.const 0)))
+  (func (export "store") (param i32) (i32.store (i32.const 0) (local.get 0)))
+  (func (export "grow") (param i32) (result i32) (memory.grow (local.get 0))))"#;
+
+#[test]
+fn the_slots_of_dropped_instances_are_reset_and_reused() -> Result<()> {
+    let (store, allocator) = get_pooling_store(PoolingLimits {
+        max_instances: 2,
+        memory_pages: 2.into(),
+        ..Default::default()
+    });
+    let module = Module::new(&store, WAT)?;
+
+    let first = Instance::new(&module, &imports! {})?;
+    let _second = Instance::new(&module, &imports! {})?;
+    assert_eq!(allocator.available_instances(), 0);
+    assert!(Instance::new(&module, &imports! {}).is_err());
+
+    let store_ = first.exports.get_native_function::<i32, ()>("store")?;
+    store_.call(42)?;
+    let load = first.exports.get_native_function::<(), i32>("load")?;
+    assert_eq!(load.call()?, 42);
+    drop((store_, load));
+    drop(first);
+    assert_eq!(allocator.available_instances(), 1);
+
+    let third = Instance::new(&module, &imports! {})?;
+    let load = third.exports.get_native_function::<(), i32>("load")?;
+    assert_eq!(load.call()?, 0);
+
+    // The memories can't grow out of their slots.
+    let grow = third.exports.get_native_function::<i32, i32>("grow")?;
+    assert_eq!(grow.call(1)?, 1);
+    assert_eq!(grow.call(1)?, -1);
+    Ok(())
+}
+
+#[test]
+fn the_exports_keep_their_instance_alive() -> Result<()> {
+    let (store, allocator) = get_pooling_store(PoolingLimits {
+        max_instances: 1,
+        ..Default::default()
+    });
+    let module = Module::new(&store, WAT)?;
+
+    let instance = Instance::new(&module, &imports! {})?;
+    let load = instance.exports.get_function("load")?.clone();
+    drop(instance);
+    assert_eq!(allocator.available_instances(), 0);
+    assert_eq!(load.call(&[])?.to_vec(), vec![Value::I32(0)]);
+
+    drop(load);
+    assert_eq!(allocator.available_instances(), 1);
+    Ok(())
+}
+
+#[test]
+fn the_importing_instances_keep_the_exporting_ones_alive() -> Result<()> {
+    let (store, allocator) = get_pooling_store(PoolingLimits {
+        max_instances: 2,
+        ..Default::default()
+    });
+    let exporting = Instance::new(&Module::new(&store, WAT)?, &imports! {})?;
+    let importing_module = Module::new(
+        &store,
+        r#"(module
+          (import "env" "load" (func $load (result i32)))
+          (func (export "run") (result i32) (call $load)))"#,
+    )?;
+    let imports = imports! {
+        "env" => {
+            "load" => exporting.exports.get_function("load")?.clone(),
+        },
+    };
+    let importing = Instance::new(&importing_module, &imports)?;
+    drop((exporting, imports));
+    assert_eq!(allocator.available_instances(), 0);
+
+    let run = importing.exports.get_native_function::<(), i32>("run")?;
+    assert_eq!(run.call()?, 0);
+    drop(run);
+    drop(importing);
+    assert_eq!(allocator.available_instances(), 2);
+    Ok(())
+}
+
+#[test]
+fn the_tables_keep_the_instances_of_their_functions_alive() -> Result<()> {
+    let (store, allocator) = get_pooling_store(PoolingLimits {
+        max_instances: 1,
+        max_tables_per_instance: 3,
+        ..Default::default()
+    });
+    let instance = Instance::new(&Module::new(&store, WAT)?, &imports! {})?;
+    let load = instance.exports.get_function("load")?.clone();
+    let ty = TableType::new(Type::FuncRef, 1, None);
+    let table = Table::new(&store, ty, Value::FuncRef(load))?;
+    let copy = Table::new(&store, ty, table.get(0).unwrap())?;
+    Table::copy(&copy, 0, &table, 0, 1)?;
+    drop(instance);
+    drop(table);
+    assert_eq!(allocator.available_instances(), 0);
+
+    let load = match copy.get(0).unwrap() {
+        Value::FuncRef(load) => load,
+        _ => unreachable!(),
+    };
+    drop(copy);
+    assert_eq!(allocator.available_instances(), 0);
+    assert_eq!(load.call(&[])?.to_vec(), vec![Value::I32(0)]);
+
+    drop(load);
+    assert_eq!(allocator.available_instances(), 1);
+    Ok(())
+}
+
+#[test]
+fn the_instances_storing_their_functions_in_imported_tables_are_freed() -> Result<()> {
+    let (store, allocator) = get_pooling_store(PoolingLimits {
+        max_instances: 2,
+        ..Default::default()
+    });
+    // Like the Emscripten modules, storing their functions in `env.table`.
+    let importing_module = Module::new(
+        &store,
+        r#"(module
+          (import "env" "table" (table 1 funcref))
+          (func $answer (result i32) (i32.const 42))
+          (func (export "run") (result i32) (call_indirect (result i32) (i32.const 0)))
+          (elem (i32.const 0) $answer))"#,
+    )?;
+    let ty = TableType::new(Type::FuncRef, 1, None);
+    let table = Table::new(
+        &store,
+        ty,
+        Value::FuncRef(Function::new_native(&store, || 0)),
+    )?;
+    let imports = imports! {
+        "env" => {
+            "table" => table.clone(),
+        },
+    };
+    let instance = Instance::new(&importing_module, &imports)?;
+    let run = instance.exports.get_function("run")?.clone();
+    drop((instance, imports));
+    assert_eq!(allocator.available_instances(), 1);
+    assert_eq!(run.call(&[])?.to_vec(), vec![Value::I32(42)]);
+
+    // The table keeps the instance alive, and the instance its table.
+    let answer = match table.get(0).unwrap() {
+        Value::FuncRef(answer) => answer,
+        _ => unreachable!(),
+    };
+    drop((table, run));
+    assert_eq!(allocator.available_instances(), 1);
+    assert_eq!(answer.call(&[])?.to_vec(), vec![Value::I32(42)]);
+    drop(answer);
+    assert_eq!(allocator.available_instances(), 2);
+
+    // With the table exported by another instance.
+    let exporting_module = Module::new(&store, r#"(module (table (export "table") 1 funcref))"#)?;
+    let exporting = Instance::new(&exporting_module, &imports! {})?;
+    let imports = imports! {
+        "env" => {
+            "table" => exporting.exports.get_table("table")?.clone(),
+        },
+    };
+    let importing = Instance::new(&importing_module, &imports)?;
+    drop((exporting, imports));
+    let run = importing.exports.get_function("run")?.clone();
+    assert_eq!(run.call(&[])?.to_vec(), vec![Value::I32(42)]);
+    assert_eq!(allocator.available_instances(), 0);
+    drop((importing, run));
+    assert_eq!(allocator.available_instances(), 2);
+
+    // Once the store is dropped too.
+    let instance = Instance::new(
+        &importing_module,
+        &imports! {
+            "env" => {
+                "table" => Table::new(&store, ty, Value::FuncRef(Function::new_native(&store, || 0)))?,
+            },
+        },
+    )?;
+    drop((store, importing_module));
+    assert_eq!(allocator.available_instances(), 1);
+    drop(instance);
+    assert_eq!(allocator.available_instances(), 2);
+    Ok(())
+}
+
+#[test]
+fn the_modules_larger_than_the_slots_are_not_instantiated() -> Result<()> {
+    let (store, allocator) = get_pooling_store(PoolingLimits {
+        max_instances: 1,
+        memory_pages: 2.into(),
+        table_elements: 10,
+        ..Default::default()
+    });
+    let memory = Module::new(&store, "(module (memory 3))")?;
+    assert!(Instance::new(&memory, &imports! {}).is_err());
+    let table = Module::new(&store, "(module (table 11 funcref))")?;
+    assert!(Instance::new(&table, &imports! {}).is_err());
+    assert_eq!(allocator.available_instances(), 1);
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use crate::utils::{get_headless_store, get_store};
+use crate::utils::{get_compiler, get_headless_store, get_store};
 use anyhow::Result;
 use wasmer::*;
 
@@ -75,3 +75,62 @@ fn test_deserialize() -> Result<()> {
     assert_eq!(result.to_vec(), vec![Value::I64(1500)]);
     Ok(())
 }
+
+#[test]
+fn test_deserialize_unversioned() -> Result<()> {
+    let store = get_store();
+    let serialized_bytes = Module::new(&store, "(module)")?.serialize()?;
+
+    // The artifacts serialized before the format was versioned have the
+    // module right after the magic header.
+    let header = b"\0wasmer-jit";
+    assert!(serialized_bytes.starts_with(b"\0wasmer-jit-v2"));
+    let mut unversioned = header.to_vec();
+    unversioned.extend(&serialized_bytes[header.len() + 3..]);
+
+    let headless_store = get_headless_store();
+    let result = unsafe { Module::deserialize(&headless_store, &unversioned) };
+    assert!(matches!(result, Err(DeserializeError::Incompatible(_))));
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "native")]
+fn test_deserialize_native() -> Result<()> {
+    use wasmer_engine_native::Native;
+
+    let mut compiler_config = get_compiler(false);
+    let store = Store::new(&Native::new(&mut compiler_config).engine());
+    let wat = r#"
+        (module
+            (memory (export "memory") 1)
+            (data (i32.const 0) "abc")
+            (func (export "load") (result i32) (i32.load8_u (i32.const 1))))
+    "#;
+    let serialized_bytes = Module::new(&store, wat)?.serialize()?;
+
+    let headless_store = Store::new(&Native::headless().engine());
+    let check = |module: &Module| -> Result<()> {
+        let instance = Instance::new(module, &imports! {})?;
+        let load = instance.exports.get_native_function::<(), i32>("load")?;
+        assert_eq!(load.call()?, i32::from(b'b'));
+        Ok(())
+    };
+
+    // The bytes are loaded without being written to the filesystem, and
+    // kept until the module is dropped.
+    let first = unsafe { Module::deserialize(&headless_store, &serialized_bytes)? };
+    let second = unsafe { Module::deserialize(&headless_store, &serialized_bytes)? };
+    check(&first)?;
+    check(&second)?;
+    assert_eq!(first.serialize()?, serialized_bytes);
+    drop(first);
+    check(&second)?;
+
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("module.so");
+    std::fs::write(&path, &serialized_bytes)?;
+    let from_file = unsafe { Module::deserialize_from_file(&headless_store, &path)? };
+    check(&from_file)?;
+    Ok(())
+}
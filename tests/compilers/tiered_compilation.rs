@@ -0,0 +1,169 @@
+//! Testing the tiered compilation, compiling the hot functions again.
+
+#![cfg(all(target_arch = "x86_64", unix))]
+
+use crate::utils::get_compiler;
+use anyhow::Result;
+use std::thread;
+use std::time::{Duration, Instant};
+use wasmer::*;
+use wasmer_engine_jit::{JITEngine, TieringPolicy, JIT};
+
+fn get_tiered_engine(threshold: u64) -> JITEngine {
+    get_engine_with_tiers(&get_compiler(false), &get_compiler(false), threshold)
+}
+
+fn get_engine_with_tiers(
+    baseline: &dyn CompilerConfig,
+    optimizing: &dyn CompilerConfig,
+    threshold: u64,
+) -> JITEngine {
+    let mut policy = TieringPolicy::new();
+    policy
+        .threshold(threshold)
+        .interval(Duration::from_millis(1));
+    JIT::tiered(baseline, optimizing)
+        .tiering_policy(policy)
+        .engine()
+}
+
+/// Waits until `count` functions of `engine` are optimized.
+fn wait_for_optimized_functions(engine: &JITEngine, count: usize) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(10) {
+        if engine.optimized_functions() >= count {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    false
+}
+
+#[test]
+fn hot_functions_are_optimized() -> Result<()> {
+    let wat = r#"(module
+      (func $fib (export "fib") (param i64) (result i64)
+        (if (result i64) (i64.lt_u (local.get 0) (i64.const 2))
+          (then (local.get 0))
+          (else
+            (i64.add
+              (call $fib (i64.sub (local.get 0) (i64.const 1)))
+              (call $fib (i64.sub (local.get 0) (i64.const 2)))))))
+      (func (export "cold") (result i64) (i64.const 42)))"#;
+    let engine = get_tiered_engine(1000);
+    let store = Store::new(&engine);
+    let instance = Instance::new(&Module::new(&store, wat)?, &imports! {})?;
+    let fib = instance.exports.get_native_function::<i64, i64>("fib")?;
+    let cold = instance.exports.get_native_function::<(), i64>("cold")?;
+
+    assert_eq!(engine.optimized_functions(), 0);
+    assert_eq!(fib.call(15)?, 610);
+    assert!(wait_for_optimized_functions(&engine, 1));
+
+    // The optimized function is called from now on, by the host and by
+    // the recursive calls still in the baseline code.
+    for _ in 0..10 {
+        assert_eq!(fib.call(20)?, 6765);
+    }
+    assert_eq!(cold.call()?, 42);
+    assert_eq!(engine.optimized_functions(), 1);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "test-singlepass"), ignore)]
+fn functions_with_hot_loops_are_optimized() -> Result<()> {
+    let wat = r#"(module
+      (func (export "sum") (param i32) (result i64)
+        (local i64)
+        (block
+          (loop
+            (br_if 1 (i32.eqz (local.get 0)))
+            (local.set 1 (i64.add (local.get 1) (i64.extend_i32_u (local.get 0))))
+            (local.set 0 (i32.sub (local.get 0) (i32.const 1)))
+            (br 0)))
+        (local.get 1)))"#;
+    let engine = get_tiered_engine(1000);
+    let store = Store::new(&engine);
+    let instance = Instance::new(&Module::new(&store, wat)?, &imports! {})?;
+    let sum = instance.exports.get_native_function::<i32, i64>("sum")?;
+
+    // Called once, but looping enough to be hot.
+    assert_eq!(sum.call(100_000)?, 5_000_050_000);
+    assert!(wait_for_optimized_functions(&engine, 1));
+    assert_eq!(sum.call(100_000)?, 5_000_050_000);
+    Ok(())
+}
+
+#[test]
+fn modules_compiled_in_tiers_are_not_serializable() -> Result<()> {
+    let store = Store::new(&get_tiered_engine(1000));
+    let module = Module::new(&store, "(module (func (export \"run\")))")?;
+    assert!(module.serialize().is_err());
+    Ok(())
+}
+
+/// Runs a loop hot enough to be optimized with `engine`, then checks that
+/// the optimizing compiler compiled it, and that it's still correct.
+fn check_tier_up(engine: JITEngine) -> Result<()> {
+    let wat = r#"(module
+      (func $square (param i64) (result i64)
+        (i64.mul (local.get 0) (local.get 0)))
+      (func (export "sum_of_squares") (param i64) (result i64)
+        (local i64)
+        (block
+          (loop
+            (br_if 1 (i64.eqz (local.get 0)))
+            (local.set 1 (i64.add (local.get 1) (call $square (local.get 0))))
+            (local.set 0 (i64.sub (local.get 0) (i64.const 1)))
+            (br 0)))
+        (local.get 1)))"#;
+    let store = Store::new(&engine);
+    let instance = Instance::new(&Module::new(&store, wat)?, &imports! {})?;
+    let sum_of_squares = instance
+        .exports
+        .get_native_function::<i64, i64>("sum_of_squares")?;
+
+    assert_eq!(engine.optimized_functions(), 0);
+    assert_eq!(sum_of_squares.call(10_000)?, 333_383_335_000);
+    // Both the loop and the function it calls are hot.
+    assert!(wait_for_optimized_functions(&engine, 2));
+    assert_eq!(sum_of_squares.call(10_000)?, 333_383_335_000);
+    Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "singlepass", feature = "cranelift"))]
+fn singlepass_functions_are_optimized_by_cranelift() -> Result<()> {
+    let baseline = wasmer_compiler_singlepass::Singlepass::new();
+    let optimizing = wasmer_compiler_cranelift::Cranelift::new();
+    check_tier_up(get_engine_with_tiers(&baseline, &optimizing, 1000))
+}
+
+#[test]
+#[cfg(all(feature = "singlepass", feature = "llvm"))]
+fn singlepass_functions_are_optimized_by_llvm() -> Result<()> {
+    let baseline = wasmer_compiler_singlepass::Singlepass::new();
+    let optimizing = wasmer_compiler_llvm::LLVM::new();
+    check_tier_up(get_engine_with_tiers(&baseline, &optimizing, 1000))
+}
+
+#[test]
+fn functions_outliving_their_module_keep_running() -> Result<()> {
+    let engine = get_tiered_engine(1000);
+    let store = Store::new(&engine);
+    let wat = "(module (func (export \"answer\") (result i32) (i32.const 42)))";
+    let answer = {
+        let instance = Instance::new(&Module::new(&store, wat)?, &imports! {})?;
+        instance.exports.get_function("answer")?.clone()
+    };
+
+    // The function is still called through the stub of the dropped
+    // module, and compiled again once hot.
+    for _ in 0..2000 {
+        assert_eq!(answer.call(&[])?.to_vec(), vec![Value::I32(42)]);
+    }
+    assert!(wait_for_optimized_functions(&engine, 1));
+    assert_eq!(answer.call(&[])?.to_vec(), vec![Value::I32(42)]);
+    Ok(())
+}
@@ -173,10 +173,14 @@ fn trap_display_pretty() -> Result<()> {
         e.to_string(),
         "\
 RuntimeError: unreachable
-    at die (m[0]:0x23)
-    at <unnamed> (m[1]:0x27)
-    at foo (m[2]:0x2c)
-    at <unnamed> (m[3]:0x31)"
+   0: die
+             at m[0]:0x23
+   1: <unnamed>
+             at m[1]:0x27
+   2: foo
+             at m[2]:0x2c
+   3: <unnamed>
+             at m[3]:0x31"
     );
     Ok(())
 }
@@ -224,12 +228,18 @@ fn trap_display_multi_module() -> Result<()> {
         e.to_string(),
         "\
 RuntimeError: unreachable
-    at die (a[0]:0x23)
-    at <unnamed> (a[1]:0x27)
-    at foo (a[2]:0x2c)
-    at <unnamed> (a[3]:0x31)
-    at middle (b[1]:0x29)
-    at <unnamed> (b[2]:0x2e)"
+   0: die
+             at a[0]:0x23
+   1: <unnamed>
+             at a[1]:0x27
+   2: foo
+             at a[2]:0x2c
+   3: <unnamed>
+             at a[3]:0x31
+   4: middle
+             at b[1]:0x29
+   5: <unnamed>
+             at b[2]:0x2e"
     );
     Ok(())
 }
@@ -262,6 +272,7 @@ fn trap_start_function_import() -> Result<()> {
         InstantiationError::Start(err) => {
             assert_eq!(err.message(), "user trap");
         }
+        InstantiationError::HostEnvInitialization(_) => panic!("It should be a start error"),
     }
 
     Ok(())
@@ -411,7 +422,8 @@ fn call_signature_mismatch() -> Result<()> {
         format!("{}", err),
         "\
 RuntimeError: indirect call type mismatch
-    at foo (a[0]:0x30)\
+   0: foo
+             at a[0]:0x30\
 "
     );
     Ok(())
@@ -440,10 +452,14 @@ fn start_trap_pretty() -> Result<()> {
         format!("{}", err),
         "\
 RuntimeError: unreachable
-    at die (m[0]:0x1d)
-    at <unnamed> (m[1]:0x21)
-    at foo (m[2]:0x26)
-    at start (m[3]:0x2b)\
+   0: die
+             at m[0]:0x1d
+   1: <unnamed>
+             at m[1]:0x21
+   2: foo
+             at m[2]:0x26
+   3: start
+             at m[3]:0x2b\
 "
     );
     Ok(())
@@ -470,3 +486,161 @@ fn present_after_module_drop() -> Result<()> {
         // assert_eq!(t.trace()[0].func_index(), 0);
     }
 }
+
+#[test]
+#[cfg_attr(any(feature = "test-singlepass", feature = "test-llvm"), ignore)]
+fn trap_display_dwarf() -> Result<()> {
+    use gimli::write::{
+        Address, AttributeValue, DwarfUnit, EndianVec, LineProgram, LineString, Sections,
+    };
+    use gimli::{Encoding, Format, LineEncoding, LittleEndian};
+
+    let store = get_store();
+    let mut wasm = wat2wasm(
+        br#"
+        (module $m
+            (func $foo unreachable)
+            (func $run (export "run") call $foo)
+        )
+    "#,
+    )?
+    .into_owned();
+
+    // DWARF addresses are relative to the code section contents, where
+    // `$foo` spans [2, 5) and `$run` spans [6, 10).
+    let encoding = Encoding {
+        format: Format::Dwarf32,
+        version: 4,
+        address_size: 4,
+    };
+    let mut dwarf = DwarfUnit::new(encoding);
+    let mut program = LineProgram::new(
+        encoding,
+        LineEncoding::default(),
+        LineString::String(b"/code".to_vec()),
+        LineString::String(b"src/lib.rs".to_vec()),
+        None,
+    );
+    let dir = program.add_directory(LineString::String(b"src".to_vec()));
+    let file = program.add_file(LineString::String(b"lib.rs".to_vec()), dir, None);
+    program.begin_sequence(Some(Address::Constant(0)));
+    for &(address, line) in &[(2, 3), (6, 8)] {
+        let row = program.row();
+        row.address_offset = address;
+        row.file = file;
+        row.line = line;
+        row.column = 5;
+        program.generate_row();
+    }
+    program.end_sequence(10);
+    dwarf.unit.line_program = program;
+
+    let unit = &mut dwarf.unit;
+    let root = unit.root();
+    let cu = unit.get_mut(root);
+    cu.set(
+        gimli::DW_AT_name,
+        AttributeValue::String(b"src/lib.rs".to_vec()),
+    );
+    cu.set(
+        gimli::DW_AT_comp_dir,
+        AttributeValue::String(b"/code".to_vec()),
+    );
+    cu.set(
+        gimli::DW_AT_low_pc,
+        AttributeValue::Address(Address::Constant(0)),
+    );
+    cu.set(gimli::DW_AT_high_pc, AttributeValue::Udata(10));
+
+    let inner = unit.add(root, gimli::DW_TAG_subprogram);
+    let entry = unit.get_mut(inner);
+    entry.set(gimli::DW_AT_name, AttributeValue::String(b"inner".to_vec()));
+    entry.set(
+        gimli::DW_AT_inline,
+        AttributeValue::Inline(gimli::DW_INL_inlined),
+    );
+
+    for &(name, low, len) in &[("foo", 2, 3), ("run", 6, 4)] {
+        let subprogram = unit.add(root, gimli::DW_TAG_subprogram);
+        let entry = unit.get_mut(subprogram);
+        entry.set(gimli::DW_AT_name, AttributeValue::String(name.into()));
+        entry.set(
+            gimli::DW_AT_low_pc,
+            AttributeValue::Address(Address::Constant(low)),
+        );
+        entry.set(gimli::DW_AT_high_pc, AttributeValue::Udata(len));
+        if name == "foo" {
+            // `inner` is inlined at the `unreachable` instruction.
+            let inlined = unit.add(subprogram, gimli::DW_TAG_inlined_subroutine);
+            let entry = unit.get_mut(inlined);
+            entry.set(gimli::DW_AT_abstract_origin, AttributeValue::UnitRef(inner));
+            entry.set(
+                gimli::DW_AT_low_pc,
+                AttributeValue::Address(Address::Constant(3)),
+            );
+            entry.set(gimli::DW_AT_high_pc, AttributeValue::Udata(1));
+            entry.set(
+                gimli::DW_AT_call_file,
+                AttributeValue::FileIndex(Some(file)),
+            );
+            entry.set(gimli::DW_AT_call_line, AttributeValue::Udata(2));
+            entry.set(gimli::DW_AT_call_column, AttributeValue::Udata(9));
+        }
+    }
+
+    let mut sections = Sections::new(EndianVec::new(LittleEndian));
+    dwarf.write(&mut sections)?;
+    sections.for_each(|id, data| -> Result<()> {
+        if !data.slice().is_empty() {
+            append_custom_section(&mut wasm, id.name(), data.slice());
+        }
+        Ok(())
+    })?;
+
+    let module = Module::new(&store, &wasm)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let run = instance.exports.get_function("run")?;
+    let e = run.call(&[]).err().expect("error calling function");
+
+    let symbols = e.trace()[0].symbols();
+    assert_eq!(symbols.len(), 2);
+    assert_eq!(symbols[0].name(), Some("inner"));
+    assert_eq!(symbols[0].file(), Some("/code/src/lib.rs"));
+    assert_eq!(symbols[0].line(), Some(3));
+    assert_eq!(symbols[0].column(), Some(5));
+    assert_eq!(symbols[1].name(), Some("foo"));
+    assert_eq!(symbols[1].line(), Some(2));
+    assert_eq!(
+        e.to_string(),
+        "\
+RuntimeError: unreachable
+   0: inner
+             at /code/src/lib.rs:3:5
+      foo
+             at /code/src/lib.rs:2:9
+   1: run
+             at /code/src/lib.rs:8:5"
+    );
+    return Ok(());
+
+    fn append_custom_section(wasm: &mut Vec<u8>, name: &str, data: &[u8]) {
+        fn leb128(out: &mut Vec<u8>, mut value: usize) {
+            loop {
+                let byte = (value & 0x7f) as u8;
+                value >>= 7;
+                if value == 0 {
+                    out.push(byte);
+                    return;
+                }
+                out.push(byte | 0x80);
+            }
+        }
+        let mut payload = Vec::new();
+        leb128(&mut payload, name.len());
+        payload.extend_from_slice(name.as_bytes());
+        payload.extend_from_slice(data);
+        wasm.push(0);
+        leb128(wasm, payload.len());
+        wasm.extend_from_slice(&payload);
+    }
+}
@@ -1,5 +1,5 @@
 use std::sync::Arc;
-use wasmer::{FunctionMiddlewareGenerator, Store};
+use wasmer::{Features, FunctionMiddlewareGenerator, Store, Target};
 use wasmer_compiler::CompilerConfig;
 use wasmer_engine::Engine;
 use wasmer_engine_jit::JIT;
@@ -52,6 +52,15 @@ pub fn get_store_with_middlewares<I: Iterator<Item = Arc<dyn FunctionMiddlewareG
     Store::new(&engine)
 }
 
+pub fn get_deterministic_store(configure: impl FnOnce(&mut Features)) -> Store {
+    let compiler_config = get_compiler(false);
+    let mut features = compiler_config.default_features_for_target(&Target::default());
+    features.deterministic(true);
+    configure(&mut features);
+    let engine = JIT::new(&compiler_config).features(features).engine();
+    Store::new(&engine)
+}
+
 pub fn get_headless_store() -> Store {
     Store::new(&JIT::headless().engine())
 }
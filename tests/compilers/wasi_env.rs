@@ -0,0 +1,66 @@
+//! Testing the binding of the memory of the `WasiEnv` to the instances.
+
+#![cfg(feature = "wasi")]
+
+use crate::utils::get_store;
+use anyhow::Result;
+use wasmer::*;
+use wasmer_wasi::WasiState;
+
+/// Writes the size of the arguments at the offset it is given.
+fn args_sizes_module(memory: &str) -> String {
+    format!(
+        r#"(module
+          (import "wasi_snapshot_preview1" "args_sizes_get"
+            (func $args_sizes_get (param i32 i32) (result i32)))
+          {}
+          (func (export "args_sizes") (param i32) (result i32)
+            (call $args_sizes_get (local.get 0) (i32.add (local.get 0) (i32.const 4)))))"#,
+        memory
+    )
+}
+
+#[test]
+fn imported_memories_are_set_by_the_host() -> Result<()> {
+    let store = get_store();
+    let module = Module::new(
+        &store,
+        args_sizes_module(r#"(import "env" "memory" (memory 1))"#),
+    )?;
+    let memory = Memory::new(&store, MemoryType::new(1, None, false))?;
+
+    let mut wasi_env = WasiState::new("program").arg("arg").finalize()?;
+    let mut import_object = wasi_env.import_object(&module)?;
+    let mut env = Exports::new();
+    env.insert("memory", memory.clone());
+    import_object.register("env", env);
+    let instance = Instance::new(&module, &import_object)?;
+    assert!(wasi_env.set_memory(memory.clone()));
+
+    let args_sizes = instance
+        .exports
+        .get_native_function::<i32, i32>("args_sizes")?;
+    assert_eq!(args_sizes.call(16)?, 0);
+    assert_eq!(memory.read_obj::<u32>(16)?, 2);
+    Ok(())
+}
+
+#[test]
+fn exported_memories_are_bound_on_instantiation() -> Result<()> {
+    let store = get_store();
+    let module = Module::new(&store, args_sizes_module(r#"(memory (export "memory") 1)"#))?;
+
+    let mut wasi_env = WasiState::new("program").finalize()?;
+    let instance = Instance::new(&module, &wasi_env.import_object(&module)?)?;
+    let memory = instance.exports.get_memory("memory")?;
+    assert!(wasi_env.memory().same(memory));
+    assert!(!wasi_env.set_memory(memory.clone()));
+
+    // The environment can't serve another instance.
+    let import_object = wasi_env.import_object(&module)?;
+    assert!(matches!(
+        Instance::new(&module, &import_object),
+        Err(InstantiationError::HostEnvInitialization(_))
+    ));
+    Ok(())
+}
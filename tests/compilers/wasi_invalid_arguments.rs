@@ -0,0 +1,68 @@
+//! Testing that the WASI syscalls return an error, instead of panicking,
+//! when the guest passes invalid arguments.
+
+#![cfg(feature = "wasi")]
+
+use crate::utils::get_store;
+use anyhow::Result;
+use wasmer::*;
+use wasmer_wasi::types::*;
+use wasmer_wasi::WasiState;
+
+const SYSCALLS: &str = r#"(module
+  (import "wasi_snapshot_preview1" "fd_seek"
+    (func $fd_seek (param i32 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_prestat_dir_name"
+    (func $fd_prestat_dir_name (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_renumber"
+    (func $fd_renumber (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (export "fd_seek" (func $fd_seek))
+  (export "fd_write" (func $fd_write))
+  (export "fd_prestat_dir_name" (func $fd_prestat_dir_name))
+  (export "fd_renumber" (func $fd_renumber)))"#;
+
+fn call(instance: &Instance, name: &str, params: &[Val]) -> Result<__wasi_errno_t> {
+    let results = instance.exports.get_function(name)?.call(params)?;
+    Ok(results[0].unwrap_i32() as __wasi_errno_t)
+}
+
+#[test]
+fn invalid_arguments() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let module = Module::new(&get_store(), SYSCALLS)?;
+    let mut wasi_env = WasiState::new("invalid_arguments")
+        .map_dir("sandbox", temp_dir.path())?
+        .finalize()?;
+    let instance = Instance::new(&module, &wasi_env.import_object(&module)?)?;
+    let memory = instance.exports.get_memory("memory")?;
+
+    // the integers are reinterpreted as the unsigned types of the syscalls
+    let params = [Val::I32(-1), Val::I64(0), Val::I32(0), Val::I32(0)];
+    assert_eq!(call(&instance, "fd_seek", &params)?, __WASI_EBADF);
+    let params = [Val::I32(4), Val::I64(-1), Val::I32(0x100), Val::I32(0)];
+    assert_eq!(call(&instance, "fd_seek", &params)?, __WASI_EINVAL);
+
+    // there is no room for the null terminator of the name
+    let params = [Val::I32(4), Val::I32(0), Val::I32(7)];
+    assert_eq!(
+        call(&instance, "fd_prestat_dir_name", &params)?,
+        __WASI_ESUCCESS
+    );
+    let mut name = [0; 7];
+    memory.read(0, &mut name)?;
+    assert_eq!(&name, b"sandbox");
+
+    let end = memory.size().bytes().0 as i32;
+    let params = [Val::I32(1), Val::I32(end - 4), Val::I32(1), Val::I32(0)];
+    assert_eq!(call(&instance, "fd_write", &params)?, __WASI_EFAULT);
+
+    // the standard output is replaced by a directory
+    let params = [Val::I32(4), Val::I32(1)];
+    assert_eq!(call(&instance, "fd_renumber", &params)?, __WASI_ESUCCESS);
+    let params = [Val::I32(1), Val::I32(0), Val::I32(0), Val::I32(0)];
+    assert_eq!(call(&instance, "fd_write", &params)?, __WASI_EINVAL);
+    Ok(())
+}
@@ -0,0 +1,308 @@
+//! Testing `poll_oneoff` with clocks and readiness of the standard input.
+
+#![cfg(feature = "wasi")]
+
+use crate::utils::{get_deterministic_store, get_store};
+use anyhow::Result;
+use std::io::{Read, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use wasmer::*;
+use wasmer_wasi::types::*;
+use wasmer_wasi::{Pipe, WasiEnv, WasiState, WasiStateBuilder};
+
+const POLL: &str = r#"(module
+  (import "wasi_snapshot_preview1" "poll_oneoff"
+    (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "clock_time_get"
+    (func $clock_time_get (param i32 i64 i32) (result i32)))
+  (memory (export "memory") 1)
+  ;; Polls the subscriptions at 0, writing the events at 1024 and their
+  ;; number at 2048.
+  (func (export "poll") (param i32) (result i32)
+    (call $poll_oneoff (i32.const 0) (i32.const 1024) (local.get 0) (i32.const 2048)))
+  (func (export "now") (result i64)
+    (drop (call $clock_time_get (i32.const 1) (i64.const 0) (i32.const 2056)))
+    (i64.load (i32.const 2056))))"#;
+
+const SUBSCRIPTION_SIZE: usize = 48;
+const EVENT_SIZE: u64 = 32;
+
+fn clock_subscription(userdata: u64, timeout: u64, flags: u16) -> Vec<u8> {
+    clock_subscription_with(userdata, __WASI_CLOCK_MONOTONIC, timeout, flags)
+}
+
+fn clock_subscription_with(
+    userdata: u64,
+    clock_id: __wasi_clockid_t,
+    timeout: u64,
+    flags: u16,
+) -> Vec<u8> {
+    let mut subscription = vec![0; SUBSCRIPTION_SIZE];
+    subscription[0..8].copy_from_slice(&userdata.to_le_bytes());
+    subscription[8] = __WASI_EVENTTYPE_CLOCK;
+    subscription[16..20].copy_from_slice(&clock_id.to_le_bytes());
+    subscription[24..32].copy_from_slice(&timeout.to_le_bytes());
+    subscription[40..42].copy_from_slice(&flags.to_le_bytes());
+    subscription
+}
+
+fn fd_read_subscription(userdata: u64, fd: __wasi_fd_t) -> Vec<u8> {
+    let mut subscription = vec![0; SUBSCRIPTION_SIZE];
+    subscription[0..8].copy_from_slice(&userdata.to_le_bytes());
+    subscription[8] = __WASI_EVENTTYPE_FD_READ;
+    subscription[16..20].copy_from_slice(&fd.to_le_bytes());
+    subscription
+}
+
+#[derive(Debug, PartialEq)]
+struct Event {
+    userdata: u64,
+    error: u16,
+    type_: u8,
+    nbytes: u64,
+    flags: u16,
+}
+
+struct Poller {
+    instance: Instance,
+    wasi_env: WasiEnv,
+}
+
+impl Poller {
+    fn new(store: &Store, state: &mut WasiStateBuilder) -> Result<Self> {
+        let module = Module::new(store, POLL)?;
+        let mut wasi_env = state.finalize()?;
+        let instance = Instance::new(&module, &wasi_env.import_object(&module)?)?;
+        Ok(Self { instance, wasi_env })
+    }
+
+    fn poll(&self, subscriptions: &[Vec<u8>]) -> Result<Vec<Event>> {
+        let memory = self.instance.exports.get_memory("memory")?;
+        memory.write(0, &subscriptions.concat())?;
+        let poll = self
+            .instance
+            .exports
+            .get_native_function::<i32, i32>("poll")?;
+        assert_eq!(poll.call(subscriptions.len() as i32)?, 0);
+
+        let nevents = memory.read_obj::<u32>(2048)?;
+        (0..nevents as u64)
+            .map(|index| {
+                let offset = 1024 + index * EVENT_SIZE;
+                Ok(Event {
+                    userdata: memory.read_obj(offset)?,
+                    error: memory.read_obj(offset + 8)?,
+                    type_: memory.read_obj(offset + 10)?,
+                    nbytes: memory.read_obj(offset + 16)?,
+                    flags: memory.read_obj(offset + 24)?,
+                })
+            })
+            .collect()
+    }
+
+    fn now(&self) -> Result<u64> {
+        let now = self
+            .instance
+            .exports
+            .get_native_function::<(), i64>("now")?;
+        Ok(now.call()? as u64)
+    }
+}
+
+#[test]
+fn sleeps_until_the_timeout() -> Result<()> {
+    let poller = Poller::new(&get_store(), &mut WasiState::new("poll"))?;
+
+    let start = Instant::now();
+    let events = poller.poll(&[clock_subscription(7, 20_000_000, 0)])?;
+    assert!(start.elapsed() >= Duration::from_millis(20));
+    assert_eq!(
+        events,
+        [Event {
+            userdata: 7,
+            error: __WASI_ESUCCESS,
+            type_: __WASI_EVENTTYPE_CLOCK,
+            nbytes: 0,
+            flags: 0,
+        }]
+    );
+
+    // An absolute timeout in the past fires immediately.
+    let start = Instant::now();
+    let events = poller.poll(&[clock_subscription(
+        8,
+        poller.now()?,
+        __WASI_SUBSCRIPTION_CLOCK_ABSTIME,
+    )])?;
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(events[0].userdata, 8);
+    Ok(())
+}
+
+#[test]
+fn reports_the_readiness_of_a_pipe() -> Result<()> {
+    let mut stdin = Pipe::new();
+    stdin.write_all(b"hello")?;
+    let poller = Poller::new(&get_store(), WasiState::new("poll").stdin(Box::new(stdin)))?;
+    let events = poller.poll(&[
+        fd_read_subscription(1, __WASI_STDIN_FILENO),
+        clock_subscription(2, 10_000_000_000, 0),
+    ])?;
+    assert_eq!(
+        events,
+        [Event {
+            userdata: 1,
+            error: __WASI_ESUCCESS,
+            type_: __WASI_EVENTTYPE_FD_READ,
+            nbytes: 5,
+            flags: 0,
+        }]
+    );
+
+    // An empty pipe is not ready, so the timeout fires.
+    let poller = Poller::new(
+        &get_store(),
+        WasiState::new("poll").stdin(Box::new(Pipe::new())),
+    )?;
+    let events = poller.poll(&[
+        fd_read_subscription(1, __WASI_STDIN_FILENO),
+        clock_subscription(2, 10_000_000, 0),
+    ])?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].userdata, 2);
+    Ok(())
+}
+
+#[test]
+fn waits_for_the_host_to_write_to_a_pipe() -> Result<()> {
+    let mut stdin = Pipe::new();
+    let poller = Poller::new(
+        &get_store(),
+        WasiState::new("poll").stdin(Box::new(stdin.clone())),
+    )?;
+
+    let wasi_env = poller.wasi_env.clone();
+    let host = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        // The state isn't locked while the poll waits.
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            drop(wasi_env.state());
+            sender.send(()).unwrap();
+        });
+        let unlocked = receiver.recv_timeout(Duration::from_secs(5)).is_ok();
+        stdin.write_all(b"hello").unwrap();
+        unlocked
+    });
+    let events = poller.poll(&[fd_read_subscription(1, __WASI_STDIN_FILENO)])?;
+    assert!(host.join().unwrap(), "the WASI state is locked by the poll");
+    assert_eq!(
+        events,
+        [Event {
+            userdata: 1,
+            error: __WASI_ESUCCESS,
+            type_: __WASI_EVENTTYPE_FD_READ,
+            nbytes: 5,
+            flags: 0,
+        }]
+    );
+    Ok(())
+}
+
+#[test]
+fn reports_the_hang_up_of_a_closed_pipe() -> Result<()> {
+    let mut stdin = Pipe::new();
+    stdin.write_all(b"hello")?;
+    stdin.close();
+    assert!(stdin.write_all(b"world").is_err());
+    let poller = Poller::new(
+        &get_store(),
+        WasiState::new("poll").stdin(Box::new(stdin.clone())),
+    )?;
+    let events = poller.poll(&[fd_read_subscription(1, __WASI_STDIN_FILENO)])?;
+    assert_eq!(
+        events,
+        [Event {
+            userdata: 1,
+            error: __WASI_ESUCCESS,
+            type_: __WASI_EVENTTYPE_FD_READ,
+            nbytes: 5,
+            flags: __WASI_EVENT_FD_READWRITE_HANGUP,
+        }]
+    );
+
+    // Once read, a closed pipe is still reported, without bytes.
+    let mut buffer = Vec::new();
+    stdin.read_to_end(&mut buffer)?;
+    assert_eq!(buffer, b"hello");
+    let events = poller.poll(&[fd_read_subscription(1, __WASI_STDIN_FILENO)])?;
+    assert_eq!(events[0].nbytes, 0);
+    assert_eq!(events[0].flags, __WASI_EVENT_FD_READWRITE_HANGUP);
+    Ok(())
+}
+
+#[test]
+fn waits_for_the_host_to_close_a_pipe() -> Result<()> {
+    let stdin = Pipe::new();
+    let poller = Poller::new(
+        &get_store(),
+        WasiState::new("poll").stdin(Box::new(stdin.clone())),
+    )?;
+    let host = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        stdin.close();
+    });
+    let events = poller.poll(&[fd_read_subscription(1, __WASI_STDIN_FILENO)])?;
+    host.join().unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].flags, __WASI_EVENT_FD_READWRITE_HANGUP);
+    Ok(())
+}
+
+#[test]
+fn reports_the_errors_of_the_subscriptions_in_their_events() -> Result<()> {
+    let poller = Poller::new(&get_store(), &mut WasiState::new("poll"))?;
+    let start = Instant::now();
+    let events = poller.poll(&[
+        clock_subscription_with(1, __WASI_CLOCK_PROCESS_CPUTIME_ID, 0, 0),
+        fd_read_subscription(2, 1234),
+        clock_subscription(3, 10_000_000_000, 0),
+    ])?;
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(
+        events,
+        [
+            Event {
+                userdata: 1,
+                error: __WASI_ENOTSUP,
+                type_: __WASI_EVENTTYPE_CLOCK,
+                nbytes: 0,
+                flags: 0,
+            },
+            Event {
+                userdata: 2,
+                error: __WASI_EBADF,
+                type_: __WASI_EVENTTYPE_FD_READ,
+                nbytes: 0,
+                flags: 0,
+            }
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn sleeps_instantly_in_deterministic_mode() -> Result<()> {
+    let poller = Poller::new(
+        &get_deterministic_store(|_| {}),
+        WasiState::new("poll").deterministic(0),
+    )?;
+    let before = poller.now()?;
+    let start = Instant::now();
+    poller.poll(&[clock_subscription(1, 3_600_000_000_000, 0)])?;
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert!(poller.now()? > before + 3_600_000_000_000);
+    Ok(())
+}
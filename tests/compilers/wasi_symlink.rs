@@ -0,0 +1,209 @@
+//! Testing the resolution of the symlinks of the preopened directories.
+
+#![cfg(feature = "wasi")]
+
+use crate::utils::get_store;
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+use wasmer::*;
+use wasmer_wasi::types::*;
+use wasmer_wasi::WasiState;
+
+const FS: &str = r#"(module
+  (import "wasi_snapshot_preview1" "path_open"
+    (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read"
+    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_symlink"
+    (func $path_symlink (param i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_readlink"
+    (func $path_readlink (param i32 i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_unlink_file"
+    (func $path_unlink_file (param i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  ;; The paths are at 0 and 512, their lengths are the parameters. The
+  ;; results are written at 2048, their length at 1032.
+  (func (export "read") (param i32 i32) (result i32)
+    (local $errno i32)
+    (local.set $errno
+      (call $path_open (i32.const 4) (local.get 0) (i32.const 0) (local.get 1)
+        (i32.const 0) (i64.const 2) (i64.const 0) (i32.const 0) (i32.const 1024)))
+    (if (local.get $errno) (return (local.get $errno)))
+    (i32.store (i32.const 1040) (i32.const 2048))
+    (i32.store (i32.const 1044) (i32.const 64))
+    (call $fd_read (i32.load (i32.const 1024)) (i32.const 1040) (i32.const 1) (i32.const 1032)))
+  (func (export "symlink") (param i32 i32) (result i32)
+    (call $path_symlink (i32.const 0) (local.get 0) (i32.const 4) (i32.const 512) (local.get 1)))
+  (func (export "readlink") (param i32) (result i32)
+    (call $path_readlink (i32.const 4) (i32.const 0) (local.get 0)
+      (i32.const 2048) (i32.const 64) (i32.const 1032)))
+  (func (export "unlink") (param i32) (result i32)
+    (call $path_unlink_file (i32.const 4) (i32.const 0) (local.get 0))))"#;
+
+struct Guest {
+    instance: Instance,
+}
+
+impl Guest {
+    /// Instantiates the guest with `dir` preopened as `sandbox`.
+    fn new(dir: &Path) -> Result<Self> {
+        let module = Module::new(&get_store(), FS)?;
+        let mut wasi_env = WasiState::new("symlinks")
+            .map_dir("sandbox", dir)?
+            .finalize()?;
+        let instance = Instance::new(&module, &wasi_env.import_object(&module)?)?;
+        Ok(Self { instance })
+    }
+
+    fn memory(&self) -> &Memory {
+        self.instance.exports.get_memory("memory").unwrap()
+    }
+
+    fn call(&self, name: &str, params: &[i32]) -> Result<(__wasi_errno_t, String)> {
+        let params = params
+            .iter()
+            .map(|param| Val::I32(*param))
+            .collect::<Vec<_>>();
+        let results = self.instance.exports.get_function(name)?.call(&params)?;
+        let errno = results[0].unwrap_i32() as __wasi_errno_t;
+        let mut output = vec![0; self.memory().read_obj::<u32>(1032)? as usize];
+        self.memory().read(2048, &mut output)?;
+        Ok((errno, String::from_utf8(output)?))
+    }
+
+    /// Reads the file at `path`, returning the error code if it fails.
+    fn read(&self, path: &str, follow_symlinks: bool) -> Result<Result<String, __wasi_errno_t>> {
+        self.memory().write(1032, &[0; 4])?;
+        self.memory().write(0, path.as_bytes())?;
+        let lookup_flags = if follow_symlinks {
+            __WASI_LOOKUP_SYMLINK_FOLLOW
+        } else {
+            0
+        };
+        let (errno, contents) = self.call("read", &[lookup_flags as i32, path.len() as i32])?;
+        Ok(if errno == __WASI_ESUCCESS {
+            Ok(contents)
+        } else {
+            Err(errno)
+        })
+    }
+
+    fn symlink(&self, old_path: &str, new_path: &str) -> Result<__wasi_errno_t> {
+        self.memory().write(0, old_path.as_bytes())?;
+        self.memory().write(512, new_path.as_bytes())?;
+        let params = [old_path.len() as i32, new_path.len() as i32];
+        Ok(self.call("symlink", &params)?.0)
+    }
+
+    fn readlink(&self, path: &str) -> Result<Result<String, __wasi_errno_t>> {
+        self.memory().write(1032, &[0; 4])?;
+        self.memory().write(0, path.as_bytes())?;
+        let (errno, value) = self.call("readlink", &[path.len() as i32])?;
+        Ok(if errno == __WASI_ESUCCESS {
+            Ok(value)
+        } else {
+            Err(errno)
+        })
+    }
+
+    fn unlink(&self, path: &str) -> Result<__wasi_errno_t> {
+        self.memory().write(0, path.as_bytes())?;
+        Ok(self.call("unlink", &[path.len() as i32])?.0)
+    }
+}
+
+/// Creates a sandbox with `data/file.txt`, next to a file `secret.txt`
+/// outside of it.
+fn sandbox() -> Result<tempfile::TempDir> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::write(temp_dir.path().join("secret.txt"), "secret")?;
+    fs::create_dir_all(temp_dir.path().join("sandbox/data"))?;
+    fs::write(temp_dir.path().join("sandbox/data/file.txt"), "hello")?;
+    Ok(temp_dir)
+}
+
+#[cfg(unix)]
+#[test]
+fn host_symlinks_are_followed() -> Result<()> {
+    use std::os::unix::fs::symlink;
+
+    let temp_dir = sandbox()?;
+    let dir = temp_dir.path().join("sandbox");
+    symlink("data/file.txt", dir.join("link"))?;
+    symlink("data", dir.join("dir_link"))?;
+    symlink(dir.join("data/file.txt"), dir.join("absolute_link"))?;
+    symlink("link", dir.join("link_to_link"))?;
+    let guest = Guest::new(&dir)?;
+
+    assert_eq!(guest.read("link", true)?, Ok("hello".to_string()));
+    assert_eq!(guest.read("link_to_link", true)?, Ok("hello".to_string()));
+    assert_eq!(guest.read("absolute_link", true)?, Ok("hello".to_string()));
+    // the symlinks in the middle of a path are always followed
+    assert_eq!(
+        guest.read("dir_link/file.txt", false)?,
+        Ok("hello".to_string())
+    );
+    assert_eq!(guest.read("link", false)?, Err(__WASI_ELOOP));
+
+    assert_eq!(guest.readlink("link")?, Ok("data/file.txt".to_string()));
+    assert_eq!(
+        guest.readlink("absolute_link")?,
+        Ok("data/file.txt".to_string())
+    );
+    assert_eq!(guest.readlink("data/file.txt")?, Err(__WASI_EINVAL));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_cant_escape_the_preopened_directories() -> Result<()> {
+    use std::os::unix::fs::symlink;
+
+    let temp_dir = sandbox()?;
+    let dir = temp_dir.path().join("sandbox");
+    symlink("../secret.txt", dir.join("escape"))?;
+    symlink("../../../secret.txt", dir.join("data/escape"))?;
+    symlink(
+        temp_dir.path().join("secret.txt"),
+        dir.join("absolute_escape"),
+    )?;
+    symlink("loop", dir.join("loop"))?;
+    let guest = Guest::new(&dir)?;
+
+    // `..` of a preopened directory is the virtual root
+    assert_eq!(guest.read("escape", true)?, Err(__WASI_EINVAL));
+    assert_eq!(guest.read("data/escape", true)?, Err(__WASI_EINVAL));
+    assert_eq!(
+        guest.read("absolute_escape", true)?,
+        Err(__WASI_ENOTCAPABLE)
+    );
+    assert_eq!(guest.read("loop", true)?, Err(__WASI_ELOOP));
+    assert_eq!(
+        guest.symlink("/secret.txt", "absolute")?,
+        __WASI_ENOTCAPABLE
+    );
+    Ok(())
+}
+
+#[test]
+fn guest_symlinks() -> Result<()> {
+    let temp_dir = sandbox()?;
+    let dir = temp_dir.path().join("sandbox");
+    let guest = Guest::new(&dir)?;
+
+    assert_eq!(guest.symlink("file.txt", "data/link")?, __WASI_ESUCCESS);
+    assert_eq!(guest.symlink("file.txt", "data/link")?, __WASI_EEXIST);
+    assert_eq!(guest.readlink("data/link")?, Ok("file.txt".to_string()));
+    assert_eq!(guest.read("data/link", true)?, Ok("hello".to_string()));
+    assert_eq!(fs::read_link(dir.join("data/link"))?, Path::new("file.txt"));
+
+    // the symlink is resolved relative to its directory
+    assert_eq!(guest.symlink("data/missing", "dangling")?, __WASI_ESUCCESS);
+    assert_eq!(guest.read("dangling", true)?, Err(__WASI_ENOENT));
+
+    assert_eq!(guest.unlink("data/link")?, __WASI_ESUCCESS);
+    assert!(fs::symlink_metadata(dir.join("data/link")).is_err());
+    assert_eq!(guest.read("data/file.txt", true)?, Ok("hello".to_string()));
+    Ok(())
+}